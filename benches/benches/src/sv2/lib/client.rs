@@ -176,6 +176,9 @@ impl IsMiningUpstream<(), NullDownstreamMiningSelector> for Device {
     fn add_hash_rate(&mut self, _to_add: u64) {
         todo!()
     }
+    fn remove_hash_rate(&mut self, _to_remove: u64) {
+        todo!()
+    }
     fn get_opened_channels(
         &mut self,
     ) -> &mut Vec<roles_logic_sv2::common_properties::UpstreamChannel> {
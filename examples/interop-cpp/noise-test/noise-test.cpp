@@ -0,0 +1,128 @@
+#include <sv2.h>
+
+#include <iostream>
+
+#include <string.h>
+
+using namespace std;
+
+// Authority key pair of the roles config examples
+#define PUBLIC_KEY "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72"
+#define SECRET_KEY "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n"
+// Authority public key that did not sign the responder certificate
+#define OTHER_PUBLIC_KEY "9bETSCePTP78FSzHkRDjnqAh1rd3ZDKa9w39aU35hzrcLDvVKLS"
+
+#define ERROR_CODE "unsupported-feature-flags"
+
+int fail(const char *reason) {
+  cout << "Failure!!! " << reason << "\n";
+  return 1;
+}
+
+// Runs the handshake in memory, returns false if it fails
+bool handshake(const uint8_t *pinned_key, const uint8_t *public_key,
+               const uint8_t *secret_key, NoiseCodecWrapper **initiator_codec,
+               NoiseCodecWrapper **responder_codec) {
+  uint8_t step_0[RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
+  uint8_t step_1[INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
+
+  InitiatorWrapper *initiator = new_initiator(pinned_key);
+  ResponderWrapper *responder = new_responder(public_key, secret_key, 3600);
+  if (initiator == NULL || responder == NULL) {
+    return false;
+  }
+  if (!initiator_step_0(initiator, step_0)) {
+    return false;
+  }
+  *responder_codec = responder_step_1(responder, step_0, step_1);
+  if (*responder_codec == NULL) {
+    return false;
+  }
+  *initiator_codec = initiator_step_2(initiator, step_1);
+  return *initiator_codec != NULL;
+}
+
+int main() {
+  uint8_t public_key[32];
+  uint8_t secret_key[32];
+  if (!parse_authority_public_key(PUBLIC_KEY, public_key) ||
+      !parse_authority_secret_key(SECRET_KEY, secret_key)) {
+    return fail("can not parse the authority keys");
+  }
+
+  NoiseCodecWrapper *initiator = NULL;
+  NoiseCodecWrapper *responder = NULL;
+  if (!handshake(public_key, public_key, secret_key, &initiator, &responder)) {
+    return fail("handshake failed");
+  }
+  cout << "Handshake completed\n";
+
+  // Encrypt a SetupConnectionError on the initiator side
+  CSetupConnectionError message;
+  message.flags = 1;
+  message.error_code = cvec_from_buffer((const uint8_t *) ERROR_CODE, strlen(ERROR_CODE));
+  CSv2Message to_send;
+  to_send.tag = CSv2Message::Tag::SetupConnectionError;
+  to_send.setup_connection_error._0 = message;
+
+  CResult<CVec, Sv2Error> encoded = noise_encode(&to_send, initiator);
+  if (encoded.tag != CResult<CVec, Sv2Error>::Tag::Ok) {
+    return fail("can not encode the message");
+  }
+  uint8_t *frame = (uint8_t *) malloc(encoded.ok._0.len);
+  size_t frame_len = encoded.ok._0.len;
+  memcpy(frame, encoded.ok._0.data, frame_len);
+  drop_sv2_message(to_send);
+  flush_noise_encoder(initiator);
+
+  // Decrypt it on the responder side, feeding the decoder the bytes it asks for
+  size_t read = 0;
+  CResult<CSv2Message, Sv2Error> decoded;
+  while (true) {
+    CVec writable = noise_get_writable(responder);
+    if (read + writable.len > frame_len) {
+      return fail("decoder asked for too many bytes");
+    }
+    memcpy(writable.data, frame + read, writable.len);
+    read += writable.len;
+    decoded = noise_next_frame(responder);
+    if (decoded.tag == CResult<CSv2Message, Sv2Error>::Tag::Ok) {
+      break;
+    }
+    if (decoded.err._0.tag != Sv2Error::Tag::MissingBytes) {
+      return fail("can not decode the message");
+    }
+  }
+  free(frame);
+
+  CSv2Message received = decoded.ok._0;
+  if (received.tag != CSv2Message::Tag::SetupConnectionError ||
+      received.setup_connection_error._0.flags != 1 ||
+      received.setup_connection_error._0.error_code.len != strlen(ERROR_CODE) ||
+      memcmp(received.setup_connection_error._0.error_code.data, ERROR_CODE,
+             strlen(ERROR_CODE)) != 0) {
+    return fail("decoded message is not the encoded one");
+  }
+  cout << "Decoded SetupConnectionError\n";
+  drop_sv2_message(received);
+  free_noise_codec(initiator);
+  free_noise_codec(responder);
+
+  // An initiator that pins another key must reject the responder certificate
+  uint8_t other_key[32];
+  if (!parse_authority_public_key(OTHER_PUBLIC_KEY, other_key)) {
+    return fail("can not parse the other authority key");
+  }
+  initiator = NULL;
+  responder = NULL;
+  if (handshake(other_key, public_key, secret_key, &initiator, &responder)) {
+    return fail("handshake succeeded with the wrong authority key");
+  }
+  if (responder != NULL) {
+    free_noise_codec(responder);
+  }
+  cout << "Handshake rejected with the wrong authority key\n";
+
+  cout << "Success!\n";
+  return 0;
+}
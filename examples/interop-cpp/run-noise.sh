@@ -0,0 +1,18 @@
+#! /bin/sh
+
+# CLEAN
+rm -f libsv2_ffi.a
+rm -f noise-test.out
+rm -f sv2.h
+
+cargo build \
+    --manifest-path=../../protocols/Cargo.toml \
+    --release \
+    -p sv2_ffi && \
+    cp ../../protocols/target/release/libsv2_ffi.a ./
+
+../../scripts/build_header.sh ../../protocols && mv ../../scripts/sv2.h .
+
+g++ -I ./ ./noise-test/noise-test.cpp libsv2_ffi.a -lpthread -ldl -o noise-test.out
+
+./noise-test.out
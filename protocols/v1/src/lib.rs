@@ -49,7 +49,7 @@ use tracing::debug;
 use error::Error;
 pub use json_rpc::Message;
 pub use methods::{client_to_server, server_to_client, Method, MethodError, ParsingMethodError};
-use utils::{Extranonce, HexU32Be};
+use utils::{Extranonce, HexBytes, HexU32Be};
 
 /// json_rpc Response are not handled cause stratum v1 does not have any request from a server to a
 /// client
@@ -86,7 +86,14 @@ pub trait IsServer<'a> {
         Self: std::marker::Sized,
     {
         match request {
-            methods::Client2Server::SuggestDifficulty() => Ok(None),
+            methods::Client2Server::SuggestDifficulty(suggest_difficulty) => {
+                let accepted = self.handle_suggest_difficulty(&suggest_difficulty);
+                Ok(Some(suggest_difficulty.respond(accepted)))
+            }
+            methods::Client2Server::GetTransactions(get_transactions) => {
+                let transactions = self.handle_get_transactions(&get_transactions);
+                Ok(Some(get_transactions.respond(transactions)))
+            }
             methods::Client2Server::Authorize(authorize) => {
                 let authorized = self.handle_authorize(&authorize);
                 if authorized {
@@ -182,6 +189,29 @@ pub trait IsServer<'a> {
     /// Indicates to the server that the client supports the mining.set_extranonce method.
     fn handle_extranonce_subscribe(&self);
 
+    /// The client suggests the share difficulty it would like to work with, the returned value is
+    /// the result sent back to the client. A server taking the suggestion into account should then
+    /// send a [mining.set_difficulty][a] with [`IsServer::handle_set_difficulty`].
+    ///
+    /// [a]: crate::methods::server_to_client::SetDifficulty
+    ///
+    /// The suggestion is refused by default.
+    fn handle_suggest_difficulty(
+        &mut self,
+        _request: &client_to_server::SuggestDifficulty,
+    ) -> bool {
+        false
+    }
+
+    /// Return the hex-encoded transactions of the requested job, the coinbase excluded. A server
+    /// that does not keep track of the transactions returns an empty list, which is the default.
+    fn handle_get_transactions(
+        &self,
+        _request: &client_to_server::GetTransactions,
+    ) -> Vec<HexBytes> {
+        vec![]
+    }
+
     fn is_authorized(&self, name: &str) -> bool;
 
     fn authorize(&mut self, name: &str);
@@ -221,6 +251,27 @@ pub trait IsServer<'a> {
 
     fn notify(&mut self) -> Result<json_rpc::Message, Error>;
 
+    /// Ask the client to reconnect, to `host` and `port` if provided, after `wait_time` seconds
+    fn reconnect(
+        &mut self,
+        host: Option<String>,
+        port: Option<u16>,
+        wait_time: Option<u64>,
+    ) -> json_rpc::Message {
+        server_to_client::Reconnect {
+            id: None,
+            host,
+            port,
+            wait_time,
+        }
+        .into()
+    }
+
+    /// Send a human-readable message to be displayed to the operator of the client
+    fn show_message(&mut self, message: String) -> json_rpc::Message {
+        server_to_client::ShowMessage { id: None, message }.into()
+    }
+
     fn handle_set_difficulty(&mut self, value: f64) -> Result<json_rpc::Message, Error> {
         let set_difficulty = server_to_client::SetDifficulty { value };
         Ok(set_difficulty.into())
@@ -304,6 +355,14 @@ pub trait IsClient<'a> {
                 self.handle_set_version_mask(&mut set_version_mask)?;
                 Ok(None)
             }
+            methods::Server2Client::Reconnect(reconnect) => {
+                self.handle_reconnect(reconnect)?;
+                Ok(None)
+            }
+            methods::Server2Client::ShowMessage(show_message) => {
+                self.handle_show_message(show_message)?;
+                Ok(None)
+            }
         }
     }
 
@@ -351,6 +410,10 @@ pub trait IsClient<'a> {
             // impossible state
             methods::Server2ClientResponse::GeneralResponse(_) => panic!(),
             methods::Server2ClientResponse::SetDifficulty(_) => Ok(None),
+            methods::Server2ClientResponse::GetTransactions(get_transactions) => {
+                self.handle_get_transactions(get_transactions)?;
+                Ok(None)
+            }
         }
     }
 
@@ -391,6 +454,33 @@ pub trait IsClient<'a> {
         subscribe: &server_to_client::Subscribe<'a>,
     ) -> Result<(), Error<'a>>;
 
+    /// The server asks the client to reconnect, see [`server_to_client::Reconnect`]. Ignored by
+    /// default.
+    fn handle_reconnect(
+        &mut self,
+        _reconnect: server_to_client::Reconnect,
+    ) -> Result<(), Error<'a>> {
+        Ok(())
+    }
+
+    /// The server sends a message for the operator of the client. Ignored by default.
+    fn handle_show_message(
+        &mut self,
+        _show_message: server_to_client::ShowMessage,
+    ) -> Result<(), Error<'a>> {
+        Ok(())
+    }
+
+    /// The server answers a [mining.get_transactions][a]. Ignored by default.
+    ///
+    /// [a]: crate::methods::client_to_server::GetTransactions
+    fn handle_get_transactions(
+        &mut self,
+        _transactions: server_to_client::GetTransactions,
+    ) -> Result<(), Error<'a>> {
+        Ok(())
+    }
+
     fn set_extranonce1(&mut self, extranonce1: Extranonce<'a>);
 
     fn extranonce1(&self) -> Extranonce<'a>;
@@ -463,6 +553,14 @@ pub trait IsClient<'a> {
         }
     }
 
+    fn suggest_difficulty(&mut self, id: u64, value: f64) -> json_rpc::Message {
+        client_to_server::SuggestDifficulty { id, value }.into()
+    }
+
+    fn get_transactions(&mut self, id: u64, job_id: String) -> json_rpc::Message {
+        client_to_server::GetTransactions { id, job_id }.into()
+    }
+
     fn submit(
         &mut self,
         id: u64,
@@ -9,7 +9,7 @@ use crate::{
     error::Error,
     json_rpc::{Message, Response, StandardRequest},
     methods::ParsingMethodError,
-    utils::{Extranonce, HexU32Be},
+    utils::{Extranonce, HexBytes, HexU32Be},
 };
 
 #[cfg(test)]
@@ -99,7 +99,56 @@ fn from_to_json_rpc(auth: Authorize) -> bool {
 #[derive(Debug, Clone, Copy)]
 pub struct ExtranonceSubscribe();
 
-// mining.get_transactions
+/// _mining.get_transactions("job id")_
+///
+/// The client asks for the transactions of the job, so that it can verify the block it is working
+/// on. The server responds with the list of the hex-encoded transactions, the coinbase excluded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetTransactions {
+    pub id: u64,
+    pub job_id: String,
+}
+
+impl GetTransactions {
+    pub fn respond(self, transactions: Vec<HexBytes>) -> Response {
+        let response = crate::server_to_client::GetTransactions {
+            id: self.id,
+            transactions,
+        };
+        match Message::from(response) {
+            Message::OkResponse(r) => r,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<GetTransactions> for Message {
+    fn from(gt: GetTransactions) -> Self {
+        Message::StandardRequest(StandardRequest {
+            id: gt.id,
+            method: "mining.get_transactions".into(),
+            params: (&[gt.job_id][..]).into(),
+        })
+    }
+}
+
+impl TryFrom<StandardRequest> for GetTransactions {
+    type Error = ParsingMethodError;
+
+    fn try_from(msg: StandardRequest) -> Result<Self, Self::Error> {
+        match msg.params.as_array() {
+            Some(params) => {
+                let job_id = match &params[..] {
+                    [JString(a)] => a.into(),
+                    _ => return Err(ParsingMethodError::wrong_args_from_value(msg.params)),
+                };
+                let id = msg.id;
+                Ok(Self { id, job_id })
+            }
+            None => Err(ParsingMethodError::not_array_from_value(msg.params)),
+        }
+    }
+}
 
 /// _mining.submit("username", "job id", "ExtraNonce2", "nTime", "nOnce")_
 ///
@@ -654,7 +703,60 @@ impl From<InfoParams> for serde_json::Map<String, Value> {
     }
 }
 
-// mining.suggest_difficulty
+/// _mining.suggest_difficulty(difficulty)_
+///
+/// The client suggests the share difficulty it would like to work with. The server is free to
+/// ignore it, and if it takes it into account it answers with a [mining.set_difficulty][a].
+///
+/// [a]: crate::methods::server_to_client::SetDifficulty
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuggestDifficulty {
+    pub id: u64,
+    pub value: f64,
+}
+
+impl SuggestDifficulty {
+    pub fn respond(self, is_ok: bool) -> Response {
+        // infallible
+        let result = serde_json::to_value(is_ok).unwrap();
+        Response {
+            id: self.id,
+            result,
+            error: None,
+        }
+    }
+}
+
+impl From<SuggestDifficulty> for Message {
+    fn from(sd: SuggestDifficulty) -> Self {
+        let value: Value = sd.value.into();
+        Message::StandardRequest(StandardRequest {
+            id: sd.id,
+            method: "mining.suggest_difficulty".into(),
+            params: (&[value][..]).into(),
+        })
+    }
+}
+
+impl TryFrom<StandardRequest> for SuggestDifficulty {
+    type Error = ParsingMethodError;
+
+    fn try_from(msg: StandardRequest) -> Result<Self, Self::Error> {
+        match msg.params.as_array() {
+            Some(params) => {
+                let value = match &params[..] {
+                    [a] => a
+                        .as_f64()
+                        .ok_or_else(|| ParsingMethodError::not_float_from_value(a.clone()))?,
+                    _ => return Err(ParsingMethodError::wrong_args_from_value(msg.params)),
+                };
+                let id = msg.id;
+                Ok(Self { id, value })
+            }
+            None => Err(ParsingMethodError::not_array_from_value(msg.params)),
+        }
+    }
+}
 
 // mining.suggest_target
 
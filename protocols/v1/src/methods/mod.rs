@@ -62,6 +62,8 @@ pub enum ParsingMethodError {
     ValueNotAnInt(Box<serde_json::value::Number>),
     UnexpectedValue(Box<serde_json::Value>),
     ImpossibleToParseResultField(Box<Response>),
+    /// The response result can be parsed as more than one method
+    AmbiguousResponse(Box<Response>),
     ImpossibleToParseAsU64(Box<serde_json::Number>),
     UnexpectedArrayParams(Vec<serde_json::Value>),
     UnexpectedObjectParams(serde_json::Map<String, serde_json::Value>),
@@ -125,12 +127,13 @@ pub enum Method<'a> {
 
 #[derive(Debug, Clone)]
 pub enum Client2Server<'a> {
-    SuggestDifficulty(),
+    SuggestDifficulty(client_to_server::SuggestDifficulty),
     Subscribe(client_to_server::Subscribe<'a>),
     Authorize(client_to_server::Authorize),
     ExtranonceSubscribe(client_to_server::ExtranonceSubscribe),
     Submit(client_to_server::Submit<'a>),
     Configure(client_to_server::Configure),
+    GetTransactions(client_to_server::GetTransactions),
 }
 
 impl<'a> From<Client2Server<'a>> for Method<'a> {
@@ -159,6 +162,8 @@ pub enum Server2Client<'a> {
     SetDifficulty(server_to_client::SetDifficulty),
     SetExtranonce(server_to_client::SetExtranonce<'a>),
     SetVersionMask(server_to_client::SetVersionMask),
+    Reconnect(server_to_client::Reconnect),
+    ShowMessage(server_to_client::ShowMessage),
 }
 
 impl<'a> From<Server2Client<'a>> for Method<'a> {
@@ -189,6 +194,7 @@ pub enum Server2ClientResponse<'a> {
     Authorize(server_to_client::Authorize),
     Submit(server_to_client::Submit),
     SetDifficulty(server_to_client::SetDifficulty),
+    GetTransactions(server_to_client::GetTransactions),
 }
 
 impl<'a> From<Server2ClientResponse<'a>> for Method<'a> {
@@ -218,7 +224,13 @@ impl<'a> TryFrom<Message> for Method<'a> {
         match &msg {
             Message::StandardRequest(request) => match &request.method[..] {
                 "mining.suggest_difficulty" => {
-                    Ok(Method::Client2Server(Client2Server::SuggestDifficulty()))
+                    let method = request
+                        .clone()
+                        .try_into()
+                        .map_err(|e: ParsingMethodError| e.as_method_error(msg))?;
+                    Ok(Method::Client2Server(Client2Server::SuggestDifficulty(
+                        method,
+                    )))
                 }
                 "mining.subscribe" => {
                     let method = request
@@ -251,6 +263,30 @@ impl<'a> TryFrom<Message> for Method<'a> {
                         .map_err(|e: ParsingMethodError| e.as_method_error(msg))?;
                     Ok(Method::Client2Server(Client2Server::Configure(method)))
                 }
+                "mining.get_transactions" => {
+                    let method = request
+                        .clone()
+                        .try_into()
+                        .map_err(|e: ParsingMethodError| e.as_method_error(msg))?;
+                    Ok(Method::Client2Server(Client2Server::GetTransactions(
+                        method,
+                    )))
+                }
+                // Some servers send these as requests rather than notifications
+                "client.reconnect" => {
+                    let method = request
+                        .clone()
+                        .try_into()
+                        .map_err(|e: ParsingMethodError| e.as_method_error(msg))?;
+                    Ok(Method::Server2Client(Server2Client::Reconnect(method)))
+                }
+                "client.show_message" => {
+                    let method = request
+                        .clone()
+                        .try_into()
+                        .map_err(|e: ParsingMethodError| e.as_method_error(msg))?;
+                    Ok(Method::Server2Client(Server2Client::ShowMessage(method)))
+                }
                 _ => Err(MethodError::MethodNotFound(request.clone().method)),
             },
             Message::Notification(notification) => match &notification.method[..] {
@@ -282,6 +318,20 @@ impl<'a> TryFrom<Message> for Method<'a> {
                         .map_err(|e: ParsingMethodError| e.as_method_error(msg))?;
                     Ok(Method::Server2Client(Server2Client::SetExtranonce(method)))
                 }
+                "client.reconnect" => {
+                    let method = notification
+                        .clone()
+                        .try_into()
+                        .map_err(|e: ParsingMethodError| e.as_method_error(msg))?;
+                    Ok(Method::Server2Client(Server2Client::Reconnect(method)))
+                }
+                "client.show_message" => {
+                    let method = notification
+                        .clone()
+                        .try_into()
+                        .map_err(|e: ParsingMethodError| e.as_method_error(msg))?;
+                    Ok(Method::Server2Client(Server2Client::ShowMessage(method)))
+                }
                 _ => Err(MethodError::MethodNotFound(notification.clone().method)),
             },
             Message::OkResponse(response) => response
@@ -302,13 +352,18 @@ impl TryFrom<crate::json_rpc::Response> for Server2ClientResponse<'_> {
         let configure: Result<server_to_client::Configure, ParsingMethodError> = (&msg).try_into();
         let general_response: Result<server_to_client::GeneralResponse, ParsingMethodError> =
             (&msg).try_into();
-        match (subscribe, configure, general_response) {
-            (Ok(a), Err(_), Err(_)) => Ok(Server2ClientResponse::Subscribe(a)),
-            (Err(_), Ok(a), Err(_)) => Ok(Server2ClientResponse::Configure(a)),
-            (Err(_), Err(_), Ok(a)) => Ok(Server2ClientResponse::GeneralResponse(a)),
-            (Err(e), Err(ee), Err(eee)) => Err(ParsingMethodError::MultipleError(vec![e, ee, eee])),
-            // Impossible state a message can not be more than one method
-            _ => panic!(),
+        let get_transactions: Result<server_to_client::GetTransactions, ParsingMethodError> =
+            (&msg).try_into();
+        match (subscribe, configure, general_response, get_transactions) {
+            (Ok(a), Err(_), Err(_), Err(_)) => Ok(Server2ClientResponse::Subscribe(a)),
+            (Err(_), Ok(a), Err(_), Err(_)) => Ok(Server2ClientResponse::Configure(a)),
+            (Err(_), Err(_), Ok(a), Err(_)) => Ok(Server2ClientResponse::GeneralResponse(a)),
+            (Err(_), Err(_), Err(_), Ok(a)) => Ok(Server2ClientResponse::GetTransactions(a)),
+            (Err(e), Err(ee), Err(eee), Err(eeee)) => {
+                Err(ParsingMethodError::MultipleError(vec![e, ee, eee, eeee]))
+            }
+            // A message can not be more than one method
+            _ => Err(ParsingMethodError::AmbiguousResponse(Box::new(msg))),
         }
     }
 }
@@ -6,16 +6,165 @@ use std::convert::{TryFrom, TryInto};
 
 use crate::{
     error::Error,
-    json_rpc::{Message, Notification, Response},
+    json_rpc::{Message, Notification, Response, StandardRequest},
     methods::ParsingMethodError,
     utils::{Extranonce, HexBytes, HexU32Be, MerkleNode, PrevHash},
 };
 
 // client.get_version()
 
-// client.reconnect
+/// _client.reconnect("hostname", port, waittime)_
+///
+/// The server asks the client to reconnect, to the given host and port if provided or to the same
+/// endpoint otherwise, after waiting `wait_time` seconds. All the parameters are optional, but one
+/// can be provided only if the ones before it are provided too.
+///
+/// Some servers send this method as a request, with an id, others as a notification, both are
+/// accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reconnect {
+    /// Id of the request, `None` when sent as a notification.
+    pub id: Option<u64>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub wait_time: Option<u64>,
+}
+
+impl From<Reconnect> for Message {
+    fn from(reconnect: Reconnect) -> Self {
+        let mut params: Vec<Value> = vec![];
+        if let Some(host) = reconnect.host {
+            params.push(host.into());
+            if let Some(port) = reconnect.port {
+                params.push(port.into());
+                if let Some(wait_time) = reconnect.wait_time {
+                    params.push(wait_time.into());
+                }
+            }
+        }
+        server_message(reconnect.id, "client.reconnect", params.into())
+    }
+}
+
+impl TryFrom<Notification> for Reconnect {
+    type Error = ParsingMethodError;
+
+    fn try_from(msg: Notification) -> Result<Self, Self::Error> {
+        Reconnect::from_params(None, msg.params)
+    }
+}
+
+impl TryFrom<StandardRequest> for Reconnect {
+    type Error = ParsingMethodError;
+
+    fn try_from(msg: StandardRequest) -> Result<Self, Self::Error> {
+        Reconnect::from_params(Some(msg.id), msg.params)
+    }
+}
+
+impl Reconnect {
+    fn from_params(id: Option<u64>, params: Value) -> Result<Self, ParsingMethodError> {
+        let params = params
+            .as_array()
+            .ok_or_else(|| ParsingMethodError::not_array_from_value(params.clone()))?;
+        let (host, port, wait_time) = match &params[..] {
+            [] => (None, None, None),
+            [JString(a)] => (Some(a.clone()), None, None),
+            [JString(a), b] => (Some(a.clone()), Some(Reconnect::parse_port(b)?), None),
+            [JString(a), b, JNumber(c)] => (
+                Some(a.clone()),
+                Some(Reconnect::parse_port(b)?),
+                Some(
+                    c.as_u64()
+                        .ok_or_else(|| ParsingMethodError::not_unsigned_from_value(c.clone()))?,
+                ),
+            ),
+            _ => {
+                return Err(ParsingMethodError::wrong_args_from_value(
+                    params.clone().into(),
+                ))
+            }
+        };
+        Ok(Reconnect {
+            id,
+            host,
+            port,
+            wait_time,
+        })
+    }
+
+    // Ports are sent either as numbers or as strings
+    fn parse_port(port: &Value) -> Result<u16, ParsingMethodError> {
+        let parsed = match port {
+            JNumber(p) => p.as_u64().and_then(|p| u16::try_from(p).ok()),
+            JString(p) => p.parse().ok(),
+            _ => None,
+        };
+        parsed.ok_or_else(|| ParsingMethodError::unexpected_value_from_value(port.clone()))
+    }
+}
+
+/// _client.show_message("human-readable message")_
+///
+/// The client should display the message to its operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShowMessage {
+    /// Id of the request, `None` when sent as a notification.
+    pub id: Option<u64>,
+    pub message: String,
+}
+
+impl From<ShowMessage> for Message {
+    fn from(show_message: ShowMessage) -> Self {
+        server_message(
+            show_message.id,
+            "client.show_message",
+            (&[show_message.message][..]).into(),
+        )
+    }
+}
+
+impl TryFrom<Notification> for ShowMessage {
+    type Error = ParsingMethodError;
+
+    fn try_from(msg: Notification) -> Result<Self, Self::Error> {
+        ShowMessage::from_params(None, msg.params)
+    }
+}
+
+impl TryFrom<StandardRequest> for ShowMessage {
+    type Error = ParsingMethodError;
+
+    fn try_from(msg: StandardRequest) -> Result<Self, Self::Error> {
+        ShowMessage::from_params(Some(msg.id), msg.params)
+    }
+}
+
+impl ShowMessage {
+    fn from_params(id: Option<u64>, params: Value) -> Result<Self, ParsingMethodError> {
+        let message = match params.as_array().map(|params| &params[..]) {
+            Some([JString(a)]) => a.clone(),
+            Some(_) => return Err(ParsingMethodError::wrong_args_from_value(params)),
+            None => return Err(ParsingMethodError::not_array_from_value(params)),
+        };
+        Ok(ShowMessage { id, message })
+    }
+}
 
-// client.show_message
+// Methods sent by the server with an id are requests, the others are notifications
+fn server_message(id: Option<u64>, method: &str, params: Value) -> Message {
+    match id {
+        Some(id) => Message::StandardRequest(StandardRequest {
+            id,
+            method: method.to_string(),
+            params,
+        }),
+        None => Message::Notification(Notification {
+            method: method.to_string(),
+            params,
+        }),
+    }
+}
 
 /// Fields in order:
 ///
@@ -238,6 +387,13 @@ pub struct SetVersionMask {
     version_mask: HexU32Be,
 }
 
+impl SetVersionMask {
+    /// The new version rolling mask
+    pub fn version_mask(&self) -> HexU32Be {
+        self.version_mask.clone()
+    }
+}
+
 impl From<SetVersionMask> for Message {
     fn from(sv: SetVersionMask) -> Self {
         let version_mask: Value = sv.version_mask.into();
@@ -330,6 +486,47 @@ impl Submit {
     }
 }
 
+/// Response to [mining.get_transactions][a]: the hex-encoded transactions of the job, the coinbase
+/// excluded.
+///
+/// [a]: crate::methods::client_to_server::GetTransactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetTransactions {
+    pub id: u64,
+    pub transactions: Vec<HexBytes>,
+}
+
+impl From<GetTransactions> for Message {
+    fn from(gt: GetTransactions) -> Self {
+        let transactions: Vec<Value> = gt.transactions.into_iter().map(Into::into).collect();
+        Message::OkResponse(Response {
+            id: gt.id,
+            error: None,
+            result: transactions.into(),
+        })
+    }
+}
+
+impl TryFrom<&Response> for GetTransactions {
+    type Error = ParsingMethodError;
+
+    fn try_from(msg: &Response) -> Result<Self, Self::Error> {
+        let id = msg.id;
+        let result = msg.result.as_array().ok_or_else(|| {
+            ParsingMethodError::ImpossibleToParseResultField(Box::new(msg.clone()))
+        })?;
+        let mut transactions = vec![];
+        for transaction in result {
+            let transaction: HexBytes = transaction
+                .as_str()
+                .ok_or_else(|| ParsingMethodError::UnexpectedArrayParams(result.clone()))?
+                .try_into()?;
+            transactions.push(transaction);
+        }
+        Ok(GetTransactions { id, transactions })
+    }
+}
+
 /// mining.subscribe
 /// mining.subscribe("user agent/version", "extranonce1")
 /// The optional second parameter specifies a mining.notify subscription id the client wishes to
@@ -631,3 +828,66 @@ impl From<VersionRollingParams> for serde_json::Map<String, Value> {
         params
     }
 }
+
+#[test]
+fn reconnect_parsing() {
+    let notification =
+        r#"{"id":null,"method":"client.reconnect","params":["pool.example.com","3334",5]}"#;
+    let notification: Message = serde_json::from_str(notification).unwrap();
+    let reconnect = match crate::Method::try_from(notification).unwrap() {
+        crate::Method::Server2Client(crate::methods::Server2Client::Reconnect(r)) => r,
+        m => panic!("unexpected method {:?}", m),
+    };
+    assert_eq!(
+        reconnect,
+        Reconnect {
+            id: None,
+            host: Some("pool.example.com".to_string()),
+            port: Some(3334),
+            wait_time: Some(5),
+        }
+    );
+
+    // Sent as a request, without parameters
+    let request = r#"{"id":7,"method":"client.reconnect","params":[]}"#;
+    let request: Message = serde_json::from_str(request).unwrap();
+    match crate::Method::try_from(request).unwrap() {
+        crate::Method::Server2Client(crate::methods::Server2Client::Reconnect(r)) => {
+            assert_eq!(r.id, Some(7));
+            assert_eq!(r.host, None);
+            // The id is kept when the message is sent back
+            match Message::from(r) {
+                Message::StandardRequest(request) => assert_eq!(request.id, 7),
+                m => panic!("unexpected message {:?}", m),
+            }
+        }
+        m => panic!("unexpected method {:?}", m),
+    };
+
+    let message: Message = Reconnect {
+        id: None,
+        host: Some("pool.example.com".to_string()),
+        port: Some(3334),
+        wait_time: None,
+    }
+    .into();
+    match message {
+        Message::Notification(n) => {
+            assert_eq!(n.params, serde_json::json!(["pool.example.com", 3334]))
+        }
+        _ => panic!(),
+    };
+}
+
+#[test]
+fn get_transactions_response_parsing() {
+    let response = r#"{"id":3,"error":null,"result":["0100","0200"]}"#;
+    let response: Response = serde_json::from_str(response).unwrap();
+    match crate::methods::Server2ClientResponse::try_from(response).unwrap() {
+        crate::methods::Server2ClientResponse::GetTransactions(gt) => {
+            assert_eq!(gt.id, 3);
+            assert_eq!(gt.transactions.len(), 2);
+        }
+        r => panic!("unexpected response {:?}", r),
+    };
+}
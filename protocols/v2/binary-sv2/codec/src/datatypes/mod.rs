@@ -19,6 +19,7 @@
 //   as `U24` (24-bit unsigned integer).
 // - **`non_copy_data_types`**: Manages dynamically-sized types, like sequences, public keys, and
 //   strings, requiring size handling logic for SV2 compatibility.
+// - **`serde_impls`**: With the `serde` feature, implements the `serde` traits for the data types.
 //
 // ### Re-exports
 // Re-exports common data types used in SV2 serialization, such as `PubKey`, `Signature`, `Seq0255`,
@@ -35,6 +36,8 @@ use crate::{
 mod non_copy_data_types;
 
 mod copy_data_types;
+#[cfg(feature = "serde")]
+pub mod serde_impls;
 use crate::codec::decodable::FieldMarker;
 pub use copy_data_types::U24;
 pub use non_copy_data_types::{
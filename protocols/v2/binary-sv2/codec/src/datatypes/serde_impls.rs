@@ -0,0 +1,307 @@
+// Implements `serde::Serialize` and `serde::Deserialize` for the Sv2 data types, so that Sv2
+// messages can be written in human-readable formats like JSON.
+//
+// ## Representation
+// - Byte arrays and hashes (`U256`, `B032`, `B0255`, `B064K`, `B016M`, `Signature`, ...) are
+//   lowercase hex strings, in the same byte order used on the wire. Formats that are not human
+//   readable get the raw bytes.
+// - `U24` is an integer.
+// - `Seq0255` and `Seq064K` are sequences, `Sv2Option` is an option.
+//
+// `Str0255` and `U32AsRef` are aliases of byte arrays, so they can not have their own
+// representation: fields of those types use the `serde_fields` modules with
+// `#[serde(with = "...")]` to be written as a string (hex for non UTF-8 `Str0255`) and as an
+// integer.
+
+use super::{Inner, Seq0255, Seq064K, Sv2Option, U24};
+use alloc::{string::String, vec::Vec};
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
+use serde::{
+    de::{Error as _, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        hex.push(HEX_CHARS[(b >> 4) as usize] as char);
+        hex.push(HEX_CHARS[(b & 0x0f) as usize] as char);
+    }
+    hex
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    fn nibble(c: u8) -> Option<u8> {
+        match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            b'A'..=b'F' => Some(c - b'A' + 10),
+            _ => None,
+        }
+    }
+    let pairs = hex.as_bytes().chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| Some(nibble(pair[0])? << 4 | nibble(pair[1])?))
+        .collect()
+}
+
+impl<const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
+    Serialize for Inner<'_, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&to_hex(self.as_ref()))
+        } else {
+            serializer.serialize_bytes(self.as_ref())
+        }
+    }
+}
+
+// Accepts a hex string or raw bytes and checks the size of the resulting `Inner`.
+struct InnerVisitor<
+    const ISFIXED: bool,
+    const SIZE: usize,
+    const HEADERSIZE: usize,
+    const MAXSIZE: usize,
+>;
+
+impl<const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
+    InnerVisitor<ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    fn inner<'a, E: serde::de::Error>(
+        bytes: Vec<u8>,
+    ) -> Result<Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>, E> {
+        let len = bytes.len();
+        bytes.try_into().map_err(|_| E::invalid_length(len, &Self))
+    }
+}
+
+impl<
+        'de,
+        const ISFIXED: bool,
+        const SIZE: usize,
+        const HEADERSIZE: usize,
+        const MAXSIZE: usize,
+    > Visitor<'de> for InnerVisitor<ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if ISFIXED {
+            write!(f, "{} bytes as a hex string", SIZE)
+        } else {
+            write!(f, "up to {} bytes as a hex string", MAXSIZE)
+        }
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        from_hex(v).ok_or_else(|| E::invalid_value(serde::de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+}
+
+impl<
+        'de,
+        const ISFIXED: bool,
+        const SIZE: usize,
+        const HEADERSIZE: usize,
+        const MAXSIZE: usize,
+    > Deserialize<'de> for Inner<'_, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = if deserializer.is_human_readable() {
+            deserializer.deserialize_str(InnerVisitor::<ISFIXED, SIZE, HEADERSIZE, MAXSIZE>)?
+        } else {
+            deserializer.deserialize_byte_buf(InnerVisitor::<ISFIXED, SIZE, HEADERSIZE, MAXSIZE>)?
+        };
+        InnerVisitor::<ISFIXED, SIZE, HEADERSIZE, MAXSIZE>::inner(bytes)
+    }
+}
+
+impl Serialize for U24 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for U24 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u32::deserialize(deserializer)?;
+        U24::try_from(value).map_err(|_| D::Error::custom("value exceeds 24 bits"))
+    }
+}
+
+impl<T: Serialize> Serialize for Seq0255<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Seq0255<'_, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let inner = Vec::<T>::deserialize(deserializer)?;
+        let len = inner.len();
+        Seq0255::new(inner).map_err(|_| D::Error::invalid_length(len, &"up to 255 elements"))
+    }
+}
+
+impl<T: Serialize> Serialize for Seq064K<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Seq064K<'_, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let inner = Vec::<T>::deserialize(deserializer)?;
+        let len = inner.len();
+        Seq064K::new(inner).map_err(|_| D::Error::invalid_length(len, &"up to 65535 elements"))
+    }
+}
+
+impl<T: Serialize> Serialize for Sv2Option<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.first().serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Sv2Option<'_, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Sv2Option::new(Option::<T>::deserialize(deserializer)?))
+    }
+}
+
+/// Modules to use with `#[serde(with = "...")]` on the fields whose Sv2 type is an alias of a
+/// byte array but that have a more natural representation.
+pub mod serde_fields {
+    use super::*;
+
+    /// Writes a [`Str0255`](crate::Str0255) as a string when it is valid UTF-8, and as
+    /// `{"hex": "..."}` otherwise, so that any `Str0255` received on the wire can be written and
+    /// read back unchanged. Both forms are accepted when reading. Formats that are not human
+    /// readable get the raw bytes.
+    pub mod str0255 {
+        use super::*;
+        use crate::Str0255;
+        use serde::{de::MapAccess, ser::SerializeMap};
+
+        const HEX_KEY: &str = "hex";
+
+        pub fn serialize<S: Serializer>(
+            value: &Str0255<'_>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            if !serializer.is_human_readable() {
+                return serializer.serialize_bytes(value.as_ref());
+            }
+            match core::str::from_utf8(value.as_ref()) {
+                Ok(value) => serializer.serialize_str(value),
+                Err(_) => {
+                    let mut map = serializer.serialize_map(Some(1))?;
+                    map.serialize_entry(HEX_KEY, &to_hex(value.as_ref()))?;
+                    map.end()
+                }
+            }
+        }
+
+        struct Str0255Visitor;
+
+        impl<'de> Visitor<'de> for Str0255Visitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or a map with a hex string under \"hex\"")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(v.as_bytes().to_vec())
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(v.to_vec())
+            }
+
+            fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(v)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut bytes = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    if key != HEX_KEY || bytes.is_some() {
+                        return Err(A::Error::unknown_field(&key, &[HEX_KEY]));
+                    }
+                    let value = map.next_value::<String>()?;
+                    bytes = Some(from_hex(&value).ok_or_else(|| {
+                        A::Error::invalid_value(serde::de::Unexpected::Str(&value), &"a hex string")
+                    })?);
+                }
+                bytes.ok_or_else(|| A::Error::missing_field(HEX_KEY))
+            }
+        }
+
+        pub fn deserialize<'de, 'a, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Str0255<'a>, D::Error> {
+            let value = if deserializer.is_human_readable() {
+                deserializer.deserialize_any(Str0255Visitor)?
+            } else {
+                deserializer.deserialize_byte_buf(Str0255Visitor)?
+            };
+            let len = value.len();
+            Str0255::try_from(value).map_err(|_| D::Error::invalid_length(len, &"up to 255 bytes"))
+        }
+    }
+
+    /// Writes a `Vec<u8>` as a hex string, like the Sv2 byte arrays.
+    pub mod hex {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&to_hex(value))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<u8>, D::Error> {
+            let value = String::deserialize(deserializer)?;
+            from_hex(&value).ok_or_else(|| {
+                D::Error::invalid_value(serde::de::Unexpected::Str(&value), &"a hex string")
+            })
+        }
+    }
+
+    /// Writes a [`U32AsRef`](crate::U32AsRef) as an integer.
+    pub mod u32_as_ref {
+        use super::*;
+        use crate::U32AsRef;
+
+        pub fn serialize<S: Serializer>(
+            value: &U32AsRef<'_>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u32(value.as_u32())
+        }
+
+        pub fn deserialize<'de, 'a, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<U32AsRef<'a>, D::Error> {
+            Ok(u32::deserialize(deserializer)?.into())
+        }
+    }
+}
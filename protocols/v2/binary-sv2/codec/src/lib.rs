@@ -59,6 +59,11 @@
 //! Supports optional features like `no_std` for environments without standard library support.
 //! Error types are conditionally compiled to work with or without `std`.
 //!
+//! With the `serde` feature the data types implement `serde::Serialize` and `serde::Deserialize`:
+//! byte arrays are hex strings in wire byte order, sequences are sequences and options are
+//! options. [`serde_fields`] has the helpers for the `Str0255` (string, or hex when not
+//! UTF-8) and `U32AsRef` (integer) fields.
+//!
 //! ## Conditional Compilation
 //! - With the `no_std` feature enabled, I/O-related errors use a simplified `IoError`
 //!   representation.
@@ -81,6 +86,8 @@ use std::io::{Error as E, ErrorKind};
 
 mod codec;
 mod datatypes;
+#[cfg(feature = "serde")]
+pub use datatypes::serde_impls::serde_fields;
 pub use datatypes::{
     PubKey, Seq0255, Seq064K, ShortTxId, Signature, Str0255, Sv2DataType, Sv2Option, U32AsRef,
     B016M, B0255, B032, B064K, U24, U256,
@@ -11,6 +11,8 @@
 //! - **prop_test**: Adds support for property testing for protocol types.
 //! - **with_buffer_pool**: Enables support for buffer pooling to optimize memory usage during
 //!   serialization and deserialization.
+//! - **serde**: Implements `serde::Serialize` and `serde::Deserialize` for the Sv2 data types, see
+//!   [`serde_fields`] for the `Str0255` and `U32AsRef` fields.
 #![no_std]
 
 #[macro_use]
@@ -679,4 +681,82 @@ mod test {
             assert_eq!(bytes, bytes_2);
         }
     }
+
+    #[cfg(feature = "serde")]
+    mod test_serde {
+        use super::*;
+        use alloc::string::ToString;
+        use core::convert::TryInto;
+        use serde_json::json;
+
+        #[test]
+        fn test_bytes_as_hex() {
+            let mut u256 = [0_u8; 32];
+            u256[0] = 0xab;
+            let u256: U256 = u256.into();
+            let value = serde_json::to_value(&u256).unwrap();
+            let mut expected = "ab".to_string();
+            expected.push_str(&"00".repeat(31));
+            assert_eq!(value, json!(expected));
+            let deserialized: U256 = serde_json::from_value(value).unwrap();
+            assert_eq!(deserialized, u256);
+
+            let b0255: B0255 = vec![1, 2, 255].try_into().unwrap();
+            assert_eq!(serde_json::to_value(&b0255).unwrap(), json!("0102ff"));
+            let deserialized: B0255 = serde_json::from_value(json!("0102FF")).unwrap();
+            assert_eq!(deserialized, b0255);
+        }
+
+        #[test]
+        fn test_invalid_bytes() {
+            assert!(serde_json::from_value::<U256>(json!("00")).is_err());
+            assert!(serde_json::from_value::<B032>(json!("zz")).is_err());
+            assert!(serde_json::from_value::<B032>(json!("00".repeat(33))).is_err());
+        }
+
+        #[test]
+        fn test_sequences_and_options() {
+            let seq: Seq0255<U24> = Seq0255::new(vec![1_u32.try_into().unwrap()]).unwrap();
+            let value = serde_json::to_value(&seq).unwrap();
+            assert_eq!(value, json!([1]));
+            let deserialized: Seq0255<U24> = serde_json::from_value(value).unwrap();
+            assert_eq!(deserialized, seq);
+
+            let seq: Seq064K<B064K> =
+                Seq064K::new(vec![vec![0xca, 0xfe].try_into().unwrap()]).unwrap();
+            let value = serde_json::to_value(&seq).unwrap();
+            assert_eq!(value, json!(["cafe"]));
+            let deserialized: Seq064K<B064K> = serde_json::from_value(value).unwrap();
+            assert_eq!(deserialized, seq);
+
+            let none: Sv2Option<u32> = Sv2Option::new(None);
+            assert_eq!(serde_json::to_value(&none).unwrap(), json!(null));
+            let some: Sv2Option<u32> = serde_json::from_value(json!(7)).unwrap();
+            assert_eq!(some.into_inner(), Some(7));
+        }
+
+        #[test]
+        fn test_fields() {
+            let s: Str0255 = "hello".to_string().try_into().unwrap();
+            let value =
+                serde_fields::str0255::serialize(&s, serde_json::value::Serializer).unwrap();
+            assert_eq!(value, json!("hello"));
+            assert_eq!(serde_fields::str0255::deserialize(value).unwrap(), s);
+            assert!(serde_fields::str0255::deserialize(json!("a".repeat(256))).is_err());
+
+            let s: Str0255 = vec![0x66, 0x6f, 0xff].try_into().unwrap();
+            let value =
+                serde_fields::str0255::serialize(&s, serde_json::value::Serializer).unwrap();
+            assert_eq!(value, json!({"hex": "666fff"}));
+            assert_eq!(serde_fields::str0255::deserialize(value).unwrap(), s);
+            assert!(serde_fields::str0255::deserialize(json!({"hex": "zz"})).is_err());
+            assert!(serde_fields::str0255::deserialize(json!({"utf8": "a"})).is_err());
+
+            let n = U32AsRef::from(42);
+            let value =
+                serde_fields::u32_as_ref::serialize(&n, serde_json::value::Serializer).unwrap();
+            assert_eq!(value, json!(42));
+            assert_eq!(serde_fields::u32_as_ref::deserialize(value).unwrap(), n);
+        }
+    }
 }
@@ -53,6 +53,10 @@ pub enum Error {
     #[cfg(feature = "noise_sv2")]
     NotInHandShakeState,
 
+    /// Noise protocol is not in transport mode.
+    #[cfg(feature = "noise_sv2")]
+    NotInTransportState,
+
     /// Unexpected state in the Noise protocol.
     UnexpectedNoiseState,
 }
@@ -84,6 +88,11 @@ impl fmt::Display for Error {
                 f,
                 "This operation can be executed only during the noise handshake"
             ),
+            #[cfg(feature = "noise_sv2")]
+            NotInTransportState => write!(
+                f,
+                "This operation can be executed only after the noise handshake"
+            ),
             UnexpectedNoiseState => {
                 write!(f, "Noise state is incorrect")
             }
@@ -151,6 +160,9 @@ pub enum CError {
     /// Noise protocol is not in the expected handshake state.
     NotInHandShakeState,
 
+    /// Noise protocol is not in transport mode.
+    NotInTransportState,
+
     /// Unexpected state in the Noise protocol.
     UnexpectedNoiseState,
 }
@@ -182,6 +194,8 @@ impl From<Error> for CError {
             Error::NoiseSv2Error(_) => CError::NoiseSv2Error,
             #[cfg(feature = "noise_sv2")]
             Error::NotInHandShakeState => CError::NotInHandShakeState,
+            #[cfg(feature = "noise_sv2")]
+            Error::NotInTransportState => CError::NotInTransportState,
             Error::UnexpectedNoiseState => CError::UnexpectedNoiseState,
         }
     }
@@ -199,6 +213,7 @@ impl Drop for CError {
             CError::MissingBytes(_) => (),
             CError::NoiseSv2Error => (),
             CError::NotInHandShakeState => (),
+            CError::NotInTransportState => (),
             CError::UnexpectedNoiseState => (),
         };
     }
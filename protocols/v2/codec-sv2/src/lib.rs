@@ -63,7 +63,7 @@ pub use framing_sv2::framing::HandShakeFrame;
 pub use framing_sv2::framing::Sv2Frame;
 
 #[cfg(feature = "noise_sv2")]
-pub use noise_sv2::{self, Initiator, NoiseCodec, Responder};
+pub use noise_sv2::{self, Initiator, NoiseCodec, RekeyPolicy, Responder};
 
 pub use buffer_sv2;
 
@@ -288,6 +288,20 @@ impl State {
     pub fn with_transport_mode(tm: NoiseCodec) -> Self {
         Self::Transport(tm)
     }
+
+    /// Sets when the keys of the [`NoiseCodec`] are rekeyed, see [`RekeyPolicy`].
+    ///
+    /// Both peers of a connection must use the same policy. Errors if the codec is not in
+    /// [`State::Transport`] mode.
+    pub fn set_rekey_policy(&mut self, rekey_policy: RekeyPolicy) -> Result<()> {
+        match self {
+            Self::Transport(noise_codec) => {
+                noise_codec.set_rekey_policy(rekey_policy);
+                Ok(())
+            }
+            _ => Err(Error::NotInTransportState),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -303,6 +317,16 @@ mod tests {
         assert_eq!(actual, expect);
     }
 
+    #[test]
+    fn rekey_policy_requires_transport_mode() {
+        let mut state = State::NotInitialized(32);
+        let actual = state
+            .set_rekey_policy(RekeyPolicy::after_messages(1))
+            .unwrap_err();
+        let expect = Error::NotInTransportState;
+        assert_eq!(actual, expect);
+    }
+
     #[test]
     fn handshake_step_fails_if_state_is_in_transport_mode() {
         let mut state = State::NotInitialized(32);
@@ -42,6 +42,18 @@
 /// extensions.
 pub const EXTENSION_TYPE_NO_EXTENSION: u16 = 0;
 
+/// Identifier for the extension_type field in the SV2 frame, indicating the extensions
+/// negotiation extension (`RequestExtensions` and its responses).
+pub const EXTENSION_TYPE_EXTENSIONS_NEGOTIATION: u16 = 0x0001;
+
+/// Identifier of the worker-specific hashrate tracking extension. It does not define new messages,
+/// it appends a `user_identity` TLV field to `SubmitSharesExtended`.
+pub const EXTENSION_TYPE_WORKER_HASHRATE_TRACKING: u16 = 0x0002;
+
+/// TLV field type of the `user_identity` field of the worker-specific hashrate tracking
+/// extension.
+pub const TLV_FIELD_TYPE_USER_IDENTITY: u8 = 0x01;
+
 /// Size of the SV2 frame header in bytes.
 pub const SV2_FRAME_HEADER_SIZE: usize = 6;
 
@@ -142,6 +154,11 @@ pub const MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS: u8 = 0x1;
 pub const MESSAGE_TYPE_SETUP_CONNECTION_ERROR: u8 = 0x2;
 pub const MESSAGE_TYPE_CHANNEL_ENDPOINT_CHANGED: u8 = 0x3;
 
+// Extensions negotiation message types, only valid with `EXTENSION_TYPE_EXTENSIONS_NEGOTIATION`.
+pub const MESSAGE_TYPE_REQUEST_EXTENSIONS: u8 = 0x0;
+pub const MESSAGE_TYPE_REQUEST_EXTENSIONS_SUCCESS: u8 = 0x1;
+pub const MESSAGE_TYPE_REQUEST_EXTENSIONS_ERROR: u8 = 0x2;
+
 // Mining Protocol message types.
 pub const MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL: u8 = 0x10;
 pub const MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS: u8 = 0x11;
@@ -203,6 +220,10 @@ pub const CHANNEL_BIT_SETUP_CONNECTION_SUCCESS: bool = false;
 pub const CHANNEL_BIT_SETUP_CONNECTION_ERROR: bool = false;
 pub const CHANNEL_BIT_CHANNEL_ENDPOINT_CHANGED: bool = true;
 
+pub const CHANNEL_BIT_REQUEST_EXTENSIONS: bool = false;
+pub const CHANNEL_BIT_REQUEST_EXTENSIONS_SUCCESS: bool = false;
+pub const CHANNEL_BIT_REQUEST_EXTENSIONS_ERROR: bool = false;
+
 // For the Template Distribution protocol, the channel bit is always unset.
 pub const CHANNEL_BIT_COINBASE_OUTPUT_CONSTRAINTS: bool = false;
 pub const CHANNEL_BIT_NEW_TEMPLATE: bool = false;
@@ -95,6 +95,32 @@ where
         res
     }
 
+    // Replaces the encryption key with a new key derived from it, as defined by the `REKEY`
+    // function of the Noise specification: the first 32 bytes of `ENCRYPT(k, maxnonce, zerolen,
+    // zeros)`, where `maxnonce` is `2^64 - 1` and `zeros` are 32 zero bytes.
+    //
+    // As required by the specification the nonce is not reset. The derived key is only stored if
+    // the current key is.
+    fn rekey(&mut self) -> Result<(), aes_gcm::Error> {
+        let mut max_nonce = [0u8; 12];
+        max_nonce[4..].copy_from_slice(&u64::MAX.to_le_bytes());
+        let mut zeros = vec![0u8; 32];
+        match self.get_cipher() {
+            Some(c) => c.encrypt(&max_nonce, &[], &mut zeros)?,
+            None => return Ok(()),
+        };
+        let mut k = [0u8; 32];
+        k.copy_from_slice(&zeros[..32]);
+        *self.get_cipher() = Some(Cipher_::from_key(k));
+        if self.get_k().is_some() {
+            self.set_k(Some(k));
+        }
+        for b in zeros.iter_mut().chain(k.iter_mut()) {
+            unsafe { ptr::write_volatile(b, 0) };
+        }
+        Ok(())
+    }
+
     #[allow(dead_code)]
     fn into_aesg(mut self) -> Option<Cipher<Aes256Gcm>> {
         #[allow(clippy::clone_on_copy)]
@@ -115,12 +141,17 @@ where
     //
     // Performs authenticated encryption on the provided `data` buffer, modifying it in place to
     // contain the ciphertext. The encryption is performed using the current nonce and the AAD.
-    // The nonce is incremented after each successful encryption.
+    // The nonce is incremented after each successful encryption, once it reaches `u64::MAX` the
+    // encryption fails.
     fn encrypt_with_ad<T: Buffer>(
         &mut self,
         ad: &[u8],
         data: &mut T,
     ) -> Result<(), aes_gcm::Error> {
+        // `u64::MAX` is reserved for the rekey, a cipher that reached it can not be used anymore
+        if self.get_n() == u64::MAX {
+            return Err(aes_gcm::Error);
+        }
         let n = self.nonce_to_bytes();
         self.set_n(self.get_n() + 1);
         if let Some(c) = self.get_cipher() {
@@ -141,12 +172,17 @@ where
     //
     // Performs authenticated decryption on the provided `data` buffer, modifying it in place to
     // contain the plaintext. The decryption is performed using the current nonce and the provided
-    // AAD. The nonce is incremented after each successful decryption.
+    // AAD. The nonce is incremented after each successful decryption, once it reaches `u64::MAX`
+    // the decryption fails.
     fn decrypt_with_ad<T: Buffer>(
         &mut self,
         ad: &[u8],
         data: &mut T,
     ) -> Result<(), aes_gcm::Error> {
+        // `u64::MAX` is reserved for the rekey, a cipher that reached it can not be used anymore
+        if self.get_n() == u64::MAX {
+            return Err(aes_gcm::Error);
+        }
         let n = self.nonce_to_bytes();
         self.set_n(self.get_n() + 1);
         if let Some(c) = self.get_cipher() {
@@ -216,6 +252,23 @@ impl GenericCipher {
         }
     }
 
+    // Returns the nonce that will be used for the next encryption or decryption.
+    #[cfg(test)]
+    pub fn nonce(&self) -> u64 {
+        match self {
+            GenericCipher::ChaCha20Poly1305(c) => c.get_n(),
+            GenericCipher::Aes256Gcm(c) => c.get_n(),
+        }
+    }
+
+    // Rekeys the underlying cipher, see [`CipherState::rekey`].
+    pub fn rekey(&mut self) -> Result<(), aes_gcm::Error> {
+        match self {
+            GenericCipher::ChaCha20Poly1305(c) => c.rekey(),
+            GenericCipher::Aes256Gcm(c) => c.rekey(),
+        }
+    }
+
     // Securely erases the encryption key (`k`) from memory.
     //
     // Overwrites the encryption key stored within the [`GenericCipher`] with zeros and sets it to
@@ -15,6 +15,9 @@ pub enum Error {
     /// Error on an empty cipher list is provided where one is required.
     CipherListMustBeNonEmpty,
 
+    /// Error on an empty authority public key list is provided where one is required.
+    AuthorityKeyListMustBeNonEmpty,
+
     /// Error on unsupported ciphers.
     UnsupportedCiphers(Vec<u8>),
 
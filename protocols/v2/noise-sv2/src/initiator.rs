@@ -37,6 +37,7 @@
 use alloc::{
     boxed::Box,
     string::{String, ToString},
+    vec::Vec,
 };
 use core::{convert::TryInto, ptr};
 
@@ -85,10 +86,9 @@ pub struct Initiator {
     // Ephemeral key pair generated by the initiator for this session, used for generating the
     // shared secret with the responder.
     e: Keypair,
-    // Optional public key of the responder, used to authenticate the responder during the
-    // handshake.
-    #[allow(unused)]
-    responder_authority_pk: Option<XOnlyPublicKey>,
+    // Authority public keys accepted to authenticate the responder during the handshake. If
+    // empty, the responder is not authenticated.
+    responder_authority_pks: Vec<XOnlyPublicKey>,
     // First [`CipherState`] used for encrypting messages from the initiator to the responder
     // after the handshake is complete.
     c1: Option<GenericCipher>,
@@ -193,7 +193,7 @@ impl Initiator {
             ck: [0; 32],
             h: [0; 32],
             e: Self::generate_key_with_rng(rng),
-            responder_authority_pk: pk,
+            responder_authority_pks: pk.into_iter().collect(),
             c1: None,
             c2: None,
         };
@@ -231,6 +231,42 @@ impl Initiator {
         Ok(Self::new_with_rng(Some(pk), rng))
     }
 
+    /// Creates a new [`Initiator`] instance that accepts a responder certificate signed by any
+    /// of the provided raw 32-byte authority public keys.
+    ///
+    /// Used to pin several authority keys while the responder rotates its authority key, so that
+    /// the connection succeeds before and after the rotation. Errors if `keys` is empty or if any
+    /// of them is not a valid [`XOnlyPublicKey`].
+    #[cfg(feature = "std")]
+    pub fn from_raw_ks(keys: &[[u8; 32]]) -> Result<Box<Self>, Error> {
+        Self::from_raw_ks_with_rng(keys, &mut rand::thread_rng())
+    }
+
+    /// Creates a new [`Initiator`] instance that accepts several raw 32-byte authority public keys,
+    /// using a custom random number generator.
+    ///
+    /// See [`Self::from_raw_ks`] for more details.
+    ///
+    /// The custom random number generator should be provided in order to not implicitely rely on
+    /// `std` and allow `no_std` environments to provide a hardware random number generator for
+    /// example.
+    #[inline]
+    pub fn from_raw_ks_with_rng<R: rand::Rng + ?Sized>(
+        keys: &[[u8; 32]],
+        rng: &mut R,
+    ) -> Result<Box<Self>, Error> {
+        if keys.is_empty() {
+            return Err(Error::AuthorityKeyListMustBeNonEmpty);
+        }
+        let pks = keys
+            .iter()
+            .map(|key| XOnlyPublicKey::from_slice(key).map_err(|_| Error::InvalidRawPublicKey))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut self_ = Self::new_with_rng(None, rng);
+        self_.responder_authority_pks = pks;
+        Ok(self_)
+    }
+
     /// Creates a new [`Initiator`] without requiring the responder's authority public key.
     /// This function initializes the [`Initiator`] with a default empty state and is intended
     /// for use when both the initiator and responder are within the same network. In this case,
@@ -374,13 +410,20 @@ impl Initiator {
 
         self.decrypt_and_hash(&mut to_decrypt)?;
         let plaintext: [u8; SIGNATURE_NOISE_MESSAGE_SIZE] = to_decrypt.try_into().unwrap();
-        let signature_message: SignatureNoiseMessage = plaintext.into();
         let rs_pub_key = PublicKey::from_ellswift(elligatorswift_theirs_static)
             .x_only_public_key()
             .0
             .serialize();
         let rs_pk_xonly = XOnlyPublicKey::from_slice(&rs_pub_key).unwrap();
-        if signature_message.verify_with_now(&rs_pk_xonly, &self.responder_authority_pk, now) {
+        let verified = self.responder_authority_pks.is_empty()
+            || self.responder_authority_pks.iter().any(|authority_pk| {
+                SignatureNoiseMessage::from(plaintext).verify_with_now(
+                    &rs_pk_xonly,
+                    &Some(*authority_pk),
+                    now,
+                )
+            });
+        if verified {
             let (temp_k1, temp_k2) = Self::hkdf_2(self.get_ck(), &[]);
             let c1 = ChaCha20Poly1305::new(&temp_k1.into());
             let c2 = ChaCha20Poly1305::new(&temp_k2.into());
@@ -392,10 +435,7 @@ impl Initiator {
             let mut decryptor = GenericCipher::ChaCha20Poly1305(c2);
             encryptor.erase_k();
             decryptor.erase_k();
-            let codec = crate::NoiseCodec {
-                encryptor,
-                decryptor,
-            };
+            let codec = crate::NoiseCodec::new(encryptor, decryptor);
             Ok(codec)
         } else {
             Err(Error::InvalidCertificate(plaintext))
@@ -58,34 +58,140 @@ pub use const_sv2::{NOISE_HASHED_PROTOCOL_NAME_CHACHA, NOISE_SUPPORTED_CIPHERS_M
 // In this case, `Parity::Even` is used.
 const PARITY: secp256k1::Parity = secp256k1::Parity::Even;
 
+/// Limits after which the transport keys of a [`NoiseCodec`] are rekeyed.
+///
+/// Each direction of the connection is rekeyed independently, once the number of messages or
+/// plaintext bytes handled with the current key reaches one of the limits. Rekeying does not
+/// require any message exchange, so both peers must be configured with the same policy. A limit
+/// set to `None` is never reached.
+///
+/// As defined by the Noise specification rekeying does not reset the nonces, so a direction of the
+/// connection can not carry more than `2^64 - 1` messages, after that encryption and decryption
+/// fail.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RekeyPolicy {
+    /// Maximum number of messages encrypted (or decrypted) with the same key.
+    pub max_messages: Option<u64>,
+    /// Maximum number of plaintext bytes encrypted (or decrypted) with the same key.
+    pub max_bytes: Option<u64>,
+}
+
+impl RekeyPolicy {
+    /// Rekeys after `max_messages` messages.
+    pub fn after_messages(max_messages: u64) -> Self {
+        Self {
+            max_messages: Some(max_messages),
+            max_bytes: None,
+        }
+    }
+
+    /// Rekeys after `max_bytes` plaintext bytes.
+    pub fn after_bytes(max_bytes: u64) -> Self {
+        Self {
+            max_messages: None,
+            max_bytes: Some(max_bytes),
+        }
+    }
+
+    // Returns `true` if the traffic handled with the current key reached one of the limits.
+    fn is_reached(&self, traffic: &Traffic) -> bool {
+        self.max_messages.is_some_and(|max| traffic.messages >= max)
+            || self.max_bytes.is_some_and(|max| traffic.bytes >= max)
+    }
+}
+
+// Messages and plaintext bytes handled by a cipher since its last rekey.
+#[derive(Debug, Default)]
+struct Traffic {
+    messages: u64,
+    bytes: u64,
+}
+
+impl Traffic {
+    fn record(&mut self, bytes: usize) {
+        self.messages = self.messages.saturating_add(1);
+        self.bytes = self.bytes.saturating_add(bytes as u64);
+    }
+}
+
 /// A codec for managing encrypted communication in the Noise protocol.
 ///
 /// Manages the encryption and decryption of messages between two parties, the [`Initiator`] and
 /// [`Responder`], using the Noise protocol. A symmetric cipher is used for both encrypting
 /// outgoing messages and decrypting incoming messages.
+///
+/// The ciphers are rekeyed as defined by the Noise specification according to the codec
+/// [`RekeyPolicy`], by default never.
 pub struct NoiseCodec {
     // Cipher to encrypt outgoing messages.
     encryptor: GenericCipher,
 
     // Cipher to decrypt incoming messages.
     decryptor: GenericCipher,
+
+    // When the ciphers are rekeyed.
+    rekey_policy: RekeyPolicy,
+
+    // Traffic encrypted with the current `encryptor` key.
+    encrypted: Traffic,
+
+    // Traffic decrypted with the current `decryptor` key.
+    decrypted: Traffic,
 }
 
 impl core::fmt::Debug for NoiseCodec {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("NoiseCodec").finish()
+        f.debug_struct("NoiseCodec")
+            .field("rekey_policy", &self.rekey_policy)
+            .finish()
     }
 }
 
 impl NoiseCodec {
+    // Creates a codec from the session ciphers derived at the end of the handshake.
+    pub(crate) fn new(encryptor: GenericCipher, decryptor: GenericCipher) -> Self {
+        Self {
+            encryptor,
+            decryptor,
+            rekey_policy: RekeyPolicy::default(),
+            encrypted: Traffic::default(),
+            decrypted: Traffic::default(),
+        }
+    }
+
+    /// Sets when the ciphers are rekeyed. The peer must use the same [`RekeyPolicy`].
+    ///
+    /// Traffic already handled with the current keys counts toward the new limits.
+    pub fn set_rekey_policy(&mut self, rekey_policy: RekeyPolicy) {
+        self.rekey_policy = rekey_policy;
+    }
+
+    /// Returns the current [`RekeyPolicy`].
+    pub fn rekey_policy(&self) -> RekeyPolicy {
+        self.rekey_policy
+    }
+
     /// Encrypts a message (`msg`) in place using the stored cipher.
     pub fn encrypt<T: Buffer>(&mut self, msg: &mut T) -> Result<(), aes_gcm::Error> {
-        self.encryptor.encrypt(msg)
+        let len = msg.len();
+        self.encryptor.encrypt(msg)?;
+        self.encrypted.record(len);
+        if self.rekey_policy.is_reached(&self.encrypted) {
+            self.encryptor.rekey()?;
+            self.encrypted = Traffic::default();
+        }
+        Ok(())
     }
 
     /// Decrypts a message (`msg`) in place using the stored cipher.
     pub fn decrypt<T: Buffer>(&mut self, msg: &mut T) -> Result<(), aes_gcm::Error> {
-        self.decryptor.decrypt(msg)
+        self.decryptor.decrypt(msg)?;
+        self.decrypted.record(msg.len());
+        if self.rekey_policy.is_reached(&self.decrypted) {
+            self.decryptor.rekey()?;
+            self.decrypted = Traffic::default();
+        }
+        Ok(())
     }
 }
 
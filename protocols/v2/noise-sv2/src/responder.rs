@@ -384,10 +384,7 @@ impl Responder {
         let mut decryptor = GenericCipher::ChaCha20Poly1305(c1);
         encryptor.erase_k();
         decryptor.erase_k();
-        let codec = crate::NoiseCodec {
-            encryptor,
-            decryptor,
-        };
+        let codec = crate::NoiseCodec::new(encryptor, decryptor);
         Ok((to_send, codec))
     }
 
@@ -1,4 +1,10 @@
-use crate::{handshake::HandshakeOp, initiator::Initiator, responder::Responder};
+use crate::{
+    cipher_state::{CipherState, GenericCipher},
+    handshake::HandshakeOp,
+    initiator::Initiator,
+    responder::Responder,
+    RekeyPolicy,
+};
 
 #[test]
 #[cfg(feature = "std")]
@@ -44,3 +50,133 @@ fn test_1_with_rng() {
 
     assert!(message == "ciao".as_bytes().to_vec());
 }
+
+fn codecs() -> (crate::NoiseCodec, crate::NoiseCodec) {
+    let key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let mut initiator =
+        Initiator::new_with_rng(Some(key_pair.public_key().into()), &mut rand::thread_rng());
+    let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+    let first_message = initiator.step_0().unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let (second_message, codec_responder) = responder
+        .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+        .unwrap();
+    let codec_initiator = initiator.step_2_with_now(second_message, now).unwrap();
+    (codec_initiator, codec_responder)
+}
+
+#[test]
+fn rekey_after_messages() {
+    let (mut codec_initiator, mut codec_responder) = codecs();
+    codec_initiator.set_rekey_policy(RekeyPolicy::after_messages(2));
+    codec_responder.set_rekey_policy(RekeyPolicy::after_messages(2));
+    for i in 0..7u8 {
+        let mut message = vec![i; 10];
+        codec_initiator.encrypt(&mut message).unwrap();
+        codec_responder.decrypt(&mut message).unwrap();
+        assert_eq!(message, vec![i; 10]);
+
+        let mut message = vec![i; 10];
+        codec_responder.encrypt(&mut message).unwrap();
+        codec_initiator.decrypt(&mut message).unwrap();
+        assert_eq!(message, vec![i; 10]);
+    }
+}
+
+#[test]
+fn rekey_after_bytes() {
+    let (mut codec_initiator, mut codec_responder) = codecs();
+    codec_initiator.set_rekey_policy(RekeyPolicy::after_bytes(25));
+    codec_responder.set_rekey_policy(RekeyPolicy::after_bytes(25));
+    for i in 0..10u8 {
+        let mut message = vec![i; i as usize];
+        codec_initiator.encrypt(&mut message).unwrap();
+        codec_responder.decrypt(&mut message).unwrap();
+        assert_eq!(message, vec![i; i as usize]);
+    }
+}
+
+#[test]
+fn rekey_changes_the_key() {
+    let (mut codec_initiator, mut codec_responder) = codecs();
+    codec_initiator.set_rekey_policy(RekeyPolicy::after_messages(1));
+    let mut message = "ciao".as_bytes().to_vec();
+    codec_initiator.encrypt(&mut message).unwrap();
+    codec_responder.decrypt(&mut message).unwrap();
+
+    let mut message = "ciao".as_bytes().to_vec();
+    codec_initiator.encrypt(&mut message).unwrap();
+    assert!(codec_responder.decrypt(&mut message).is_err());
+}
+
+#[test]
+fn rekey_does_not_reset_the_nonce() {
+    let (mut codec_initiator, mut codec_responder) = codecs();
+    codec_initiator.set_rekey_policy(RekeyPolicy::after_messages(1));
+    codec_responder.set_rekey_policy(RekeyPolicy::after_messages(1));
+    for _ in 0..3 {
+        let mut message = "ciao".as_bytes().to_vec();
+        codec_initiator.encrypt(&mut message).unwrap();
+        codec_responder.decrypt(&mut message).unwrap();
+        assert_eq!(message, "ciao".as_bytes().to_vec());
+    }
+    assert_eq!(codec_initiator.encryptor.nonce(), 3);
+    assert_eq!(codec_responder.decryptor.nonce(), 3);
+}
+
+#[test]
+fn exhausted_nonce_is_an_error() {
+    let (mut codec_initiator, mut codec_responder) = codecs();
+    for cipher in [
+        &mut codec_initiator.encryptor,
+        &mut codec_responder.decryptor,
+    ] {
+        match cipher {
+            GenericCipher::ChaCha20Poly1305(c) => c.set_n(u64::MAX - 2),
+            GenericCipher::Aes256Gcm(c) => c.set_n(u64::MAX - 2),
+        }
+    }
+    for _ in 0..2 {
+        let mut message = "ciao".as_bytes().to_vec();
+        codec_initiator.encrypt(&mut message).unwrap();
+        codec_responder.decrypt(&mut message).unwrap();
+        assert_eq!(message, "ciao".as_bytes().to_vec());
+    }
+    // `u64::MAX` is reserved for the rekey
+    let mut message = "ciao".as_bytes().to_vec();
+    assert!(codec_initiator.encrypt(&mut message).is_err());
+    assert!(codec_responder.decrypt(&mut message).is_err());
+}
+
+#[test]
+fn initiator_accepts_any_pinned_authority_key() {
+    let old_key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let new_key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let other_key_pair = Responder::generate_key_with_rng(&mut rand::thread_rng());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    let pinned = [
+        old_key_pair.x_only_public_key().0.serialize(),
+        new_key_pair.x_only_public_key().0.serialize(),
+    ];
+    for (key_pair, accepted) in [(new_key_pair, true), (other_key_pair, false)] {
+        let mut initiator =
+            Initiator::from_raw_ks_with_rng(&pinned, &mut rand::thread_rng()).unwrap();
+        let mut responder = Responder::new_with_rng(key_pair, 31449600, &mut rand::thread_rng());
+        let first_message = initiator.step_0().unwrap();
+        let (second_message, _) = responder
+            .step_1_with_now_rng(first_message, now, &mut rand::thread_rng())
+            .unwrap();
+        let codec_initiator = initiator.step_2_with_now(second_message, now);
+        assert_eq!(codec_initiator.is_ok(), accepted);
+    }
+    assert_eq!(
+        Initiator::from_raw_ks_with_rng(&[], &mut rand::thread_rng()).unwrap_err(),
+        crate::Error::AuthorityKeyListMustBeNonEmpty
+    );
+}
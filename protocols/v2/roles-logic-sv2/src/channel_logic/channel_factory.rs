@@ -228,6 +228,12 @@ struct ChannelFactory {
     job_ids: Id,
     channel_to_group_id: HashMap<u32, u32, BuildNoHashHasher<u32>>,
     future_templates: HashMap<u32, NewTemplate<'static>, BuildNoHashHasher<u32>>,
+    // Extranonces of the standard channels. They are taken under a range_1 prefix of their own,
+    // so that they do not fall in the search space of an extended channel
+    standard_extranonces: Option<ExtendedExtranonce>,
+    // Extranonces of the closed channels, given to the next channels opened
+    free_extended_prefixes: Vec<binary_sv2::B032<'static>>,
+    free_standard_extranonces: Vec<mining_sv2::Extranonce>,
 }
 
 impl ChannelFactory {
@@ -287,11 +293,14 @@ impl ChannelFactory {
                     return Err(e);
                 }
             };
-            let extranonce_prefix = self
-                .extranonces
-                .next_prefix_extended(max_extranonce_size as usize)
-                .unwrap()
-                .into_b032();
+            let extranonce_prefix = match self.free_extended_prefixes.pop() {
+                Some(extranonce_prefix) => extranonce_prefix,
+                None => self
+                    .extranonces
+                    .next_prefix_extended(max_extranonce_size as usize)
+                    .map_err(|_| Error::ExtranonceSpaceEnded)?
+                    .into_b032(),
+            };
             let success = OpenExtendedMiningChannelSuccess {
                 request_id,
                 channel_id,
@@ -374,10 +383,7 @@ impl ChannelFactory {
                 return Err(e);
             }
         };
-        let extranonce = self
-            .extranonces
-            .next_prefix_standard()
-            .map_err(|_| Error::ExtranonceSpaceEnded)?;
+        let extranonce = self.next_standard_extranonce()?;
         let standard_channel = StandardChannel {
             channel_id,
             group_id: hom_group_id,
@@ -429,10 +435,7 @@ impl ChannelFactory {
                 return Err(e);
             }
         };
-        let extranonce = self
-            .extranonces
-            .next_prefix_standard()
-            .map_err(|_| Error::ExtranonceSpaceEnded)?;
+        let extranonce = self.next_standard_extranonce()?;
         let standard_channel = StandardChannel {
             channel_id,
             group_id,
@@ -457,6 +460,44 @@ impl ChannelFactory {
         Ok(result)
     }
 
+    fn next_standard_extranonce(&mut self) -> Result<mining_sv2::Extranonce, Error> {
+        if let Some(extranonce) = self.free_standard_extranonces.pop() {
+            return Ok(extranonce);
+        }
+        let extranonces = &mut self.extranonces;
+        let standard_extranonces = self.standard_extranonces.get_or_insert_with(|| {
+            // Fails without range_1, then there are no extended channels to keep apart from
+            let _ = extranonces.next_prefix_extended(extranonces.get_range2_len());
+            extranonces.clone()
+        });
+        standard_extranonces
+            .next_prefix_standard()
+            .map_err(|_| Error::ExtranonceSpaceEnded)
+    }
+
+    /// Forgets a channel closed by the downstream, its extranonce is given to the next channel
+    /// opened. Returns `false` if the channel is unknown.
+    fn remove_channel(&mut self, channel_id: u32) -> bool {
+        let group_id = match self.channel_to_group_id.remove(&channel_id) {
+            Some(group_id) => group_id,
+            None => return false,
+        };
+        let complete_id = GroupId::into_complete_id(group_id, channel_id);
+        if let Some(channel) = self.extended_channels.remove(&channel_id) {
+            self.free_extended_prefixes.push(channel.extranonce_prefix);
+        } else if let Some(channel) = self
+            .standard_channels_for_hom_downstreams
+            .remove(&channel_id)
+            .or_else(|| {
+                self.standard_channels_for_non_hom_downstreams
+                    .remove(&complete_id)
+            })
+        {
+            self.free_standard_extranonces.push(channel.extranonce);
+        }
+        true
+    }
+
     // When a hom downstream opens a channel, we use this function to prepare all the standard jobs
     // (future and not) that we need to be sent downstream
     fn prepare_standard_jobs_and_p_hash(
@@ -975,10 +1016,17 @@ impl ChannelFactory {
             },
         }
     }
-    /// Updates the downstream target for the given channel_id
+    /// Updates the downstream target for the given channel_id, that can be either an extended
+    /// channel or a standard channel opened by an header only downstream.
     fn update_target_for_channel(&mut self, channel_id: u32, new_target: Target) -> Option<bool> {
-        let channel = self.extended_channels.get_mut(&channel_id)?;
-        channel.target = new_target.into();
+        if let Some(channel) = self.extended_channels.get_mut(&channel_id) {
+            channel.target = new_target.into();
+            return Some(true);
+        }
+        let channel = self
+            .standard_channels_for_hom_downstreams
+            .get_mut(&channel_id)?;
+        channel.target = new_target;
         Some(true)
     }
 }
@@ -1023,6 +1071,9 @@ impl PoolChannelFactory {
             job_ids: Id::new(),
             channel_to_group_id: HashMap::with_hasher(BuildNoHashHasher::default()),
             future_templates: HashMap::with_hasher(BuildNoHashHasher::default()),
+            standard_extranonces: None,
+            free_extended_prefixes: Vec::new(),
+            free_standard_extranonces: Vec::new(),
         };
 
         Self {
@@ -1372,6 +1423,9 @@ impl ProxyExtendedChannelFactory {
             job_ids: Id::new(),
             channel_to_group_id: HashMap::with_hasher(BuildNoHashHasher::default()),
             future_templates: HashMap::with_hasher(BuildNoHashHasher::default()),
+            standard_extranonces: None,
+            free_extended_prefixes: Vec::new(),
+            free_standard_extranonces: Vec::new(),
         };
         ProxyExtendedChannelFactory {
             inner,
@@ -1404,6 +1458,11 @@ impl ProxyExtendedChannelFactory {
             .new_extended_channel(request_id, hash_rate, min_extranonce_size)
     }
 
+    /// Calls [`ChannelFactory::remove_channel`]
+    pub fn remove_channel(&mut self, channel_id: u32) -> bool {
+        self.inner.remove_channel(channel_id)
+    }
+
     /// Called only when a new prev hash is received by a Template Provider when job declaration is
     /// used. It matches the message with a `job_id`, creates a new custom job, and calls
     /// [`ChannelFactory::on_new_prev_hash`]
@@ -1789,6 +1848,15 @@ impl ProxyExtendedChannelFactory {
     ) -> Option<bool> {
         self.inner.update_target_for_channel(channel_id, new_target)
     }
+
+    /// Returns the target of the upstream channel
+    pub fn get_upstream_target(&self) -> Option<Target> {
+        match &self.inner.kind {
+            ExtendedChannelKind::Proxy { upstream_target }
+            | ExtendedChannelKind::ProxyJd { upstream_target } => Some(upstream_target.clone()),
+            ExtendedChannelKind::Pool => None,
+        }
+    }
 }
 
 /// Used by proxies for tracking upstream targets.
@@ -2035,4 +2103,76 @@ mod test {
             OnNewShare::ShareMeetDownstreamTarget => panic!(),
         };
     }
+
+    fn proxy_factory() -> ProxyExtendedChannelFactory {
+        // 4 bytes of extranonce1, 1 byte to tell the channels apart and 4 bytes rolled by each
+        // extended channel
+        let extranonce1: mining_sv2::Extranonce = vec![1, 2, 3, 4].try_into().unwrap();
+        let extranonces =
+            ExtendedExtranonce::from_upstream_extranonce(extranonce1, 0..4, 4..5, 5..9).unwrap();
+        ProxyExtendedChannelFactory::new(
+            Arc::new(Mutex::new(GroupId::new())),
+            extranonces,
+            None,
+            1.0,
+            ExtendedChannelKind::Proxy {
+                upstream_target: mining_sv2::Target::from([255; 32]),
+            },
+            None,
+            0,
+        )
+    }
+
+    fn open_extended(factory: &mut ProxyExtendedChannelFactory) -> (u32, Vec<u8>) {
+        match &factory.new_extended_channel(1, 1_000.0, 4).unwrap()[0] {
+            Mining::OpenExtendedMiningChannelSuccess(success) => (
+                success.channel_id,
+                success.extranonce_prefix.inner_as_ref().to_vec(),
+            ),
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    fn open_standard(factory: &mut ProxyExtendedChannelFactory, id: u32) -> Vec<u8> {
+        match &factory.add_standard_channel(2, 1_000.0, true, id).unwrap()[0] {
+            Mining::OpenStandardMiningChannelSuccess(success) => {
+                success.extranonce_prefix.inner_as_ref().to_vec()
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn standard_channels_do_not_overlap_extended_channels() {
+        let mut factory = proxy_factory();
+        let (_, prefix) = open_extended(&mut factory);
+        assert_eq!(prefix, vec![1, 2, 3, 4, 1]);
+        let extranonce = open_standard(&mut factory, 100);
+        assert_eq!(extranonce, vec![1, 2, 3, 4, 2, 0, 0, 0, 1]);
+        let (_, prefix) = open_extended(&mut factory);
+        assert_eq!(prefix, vec![1, 2, 3, 4, 3]);
+        let extranonce = open_standard(&mut factory, 101);
+        assert_eq!(extranonce, vec![1, 2, 3, 4, 2, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn removed_channels_extranonces_are_reused() {
+        let mut factory = proxy_factory();
+        let (first, first_prefix) = open_extended(&mut factory);
+        let (_, second_prefix) = open_extended(&mut factory);
+        assert!(factory.remove_channel(first));
+        assert!(!factory.remove_channel(first));
+        let (third, third_prefix) = open_extended(&mut factory);
+        assert_ne!(third, first);
+        assert_eq!(third_prefix, first_prefix);
+        assert_ne!(third_prefix, second_prefix);
+
+        let extranonce = open_standard(&mut factory, 100);
+        assert!(factory.remove_channel(100));
+        assert_eq!(open_standard(&mut factory, 101), extranonce);
+        assert_eq!(
+            open_standard(&mut factory, 102),
+            vec![1, 2, 3, 4, 3, 0, 0, 0, 2]
+        );
+    }
 }
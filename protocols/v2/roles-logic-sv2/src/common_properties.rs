@@ -168,6 +168,9 @@ pub trait IsMiningUpstream<Down: IsMiningDownstream>: IsUpstream<Down> {
     /// Adds hash rate to the upstream node.
     fn add_hash_rate(&mut self, to_add: u64);
 
+    /// Removes hash rate from the upstream node, e.g. when a downstream disconnects.
+    fn remove_hash_rate(&mut self, to_remove: u64);
+
     /// Returns the list of open channels on the upstream node.
     fn get_opened_channels(&mut self) -> &mut Vec<UpstreamChannel>;
 
@@ -235,6 +238,10 @@ impl<Down: IsMiningDownstream + D> IsMiningUpstream<Down> for () {
     fn add_hash_rate(&mut self, _to_add: u64) {
         unreachable!("Null selector can not add hash rate");
     }
+
+    fn remove_hash_rate(&mut self, _to_remove: u64) {
+        unreachable!("Null selector can not remove hash rate");
+    }
     fn get_opened_channels(&mut self) -> &mut Vec<UpstreamChannel> {
         unreachable!("Null selector can not open channels");
     }
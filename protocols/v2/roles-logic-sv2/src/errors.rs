@@ -27,6 +27,9 @@ pub enum Error {
     NoGroupsFound,
     /// Unexpected message received.
     UnexpectedMessage(u8),
+    /// Message of an extension that has not been registered. Parameters are: (`extension_type`,
+    /// `message_type`)
+    UnexpectedExtensionMessage(u16, u8),
     /// Extended channels do not have group IDs
     NoGroupIdOnExtendedChannel,
     /// No pairable upstream. Parameters are: (`min_v`, `max_v`, all flags supported)
@@ -152,6 +155,7 @@ impl Display for Error {
                 "A channel was attempted to be added to an Upstream, but no groups are specified"
             ),
             UnexpectedMessage(type_) => write!(f, "Error: Unexpected message received. Recv m type: {:x}", type_),
+            UnexpectedExtensionMessage(ext_type, type_) => write!(f, "Error: Unexpected extension message received. Recv ext type: {:x}, m type: {:x}", ext_type, type_),
             NoGroupIdOnExtendedChannel => write!(f, "Extended channels do not have group IDs"),
             NoPairableUpstream(a) => {
                 write!(f, "No pairable upstream node: {:?}", a)
@@ -0,0 +1,178 @@
+//! # Extensions Handlers
+//!
+//! This module defines traits for handling Stratum V2 protocol extensions: the extensions
+//! negotiation messages (`RequestExtensions`, `RequestExtensionsSuccess` and
+//! `RequestExtensionsError`) and the messages of custom extensions.
+//!
+//! ## Message Handling
+//!
+//! Extension messages are identified by the frame `extension_type` together with the message type,
+//! so every handler takes both. Messages of custom extensions are only accepted when they have been
+//! registered in the [`ExtensionRegistry`] of the role, they are passed to the handler as opaque
+//! [`ExtensionMessage`]s that can be relayed to a remote or deserialized by the role.
+//!
+//! ## Return Type
+//!
+//! Functions return `Result<SendTo, Error>`, where `SendTo` specifies the next action for the
+//! message: whether to forward it, respond to it, or ignore it.
+
+use super::SendTo_;
+use crate::{
+    errors::Error,
+    parsers::{AnyMessage, ExtensionMessage, ExtensionRegistry, ExtensionsNegotiation},
+    utils::Mutex,
+};
+use common_messages_sv2::{RequestExtensions, RequestExtensionsError, RequestExtensionsSuccess};
+use const_sv2::*;
+use core::convert::TryInto;
+use std::sync::Arc;
+
+/// see [`SendTo_`]
+pub type SendTo = SendTo_<ExtensionsNegotiation<'static>, ()>;
+
+/// see [`SendTo_`]
+pub type SendToExtension<Remote> = SendTo_<ExtensionMessage, Remote>;
+
+/// A trait that is implemented by the upstream node, and is used to handle the extensions
+/// negotiation messages sent by the downstream
+pub trait ParseExtensionsNegotiationFromDownstream
+where
+    Self: Sized,
+{
+    /// Takes a message type and a payload of a frame with extension type
+    /// `EXTENSION_TYPE_EXTENSIONS_NEGOTIATION` and calls the appropriate handler function
+    fn handle_message_extensions_negotiation(
+        self_: Arc<Mutex<Self>>,
+        message_type: u8,
+        payload: &mut [u8],
+    ) -> Result<SendTo, Error> {
+        Self::handle_message_extensions_negotiation_deserialized(
+            self_,
+            (message_type, payload).try_into(),
+        )
+    }
+
+    /// Takes a message and it calls the appropriate handler function
+    fn handle_message_extensions_negotiation_deserialized(
+        self_: Arc<Mutex<Self>>,
+        message: Result<ExtensionsNegotiation<'_>, Error>,
+    ) -> Result<SendTo, Error> {
+        match message {
+            Ok(ExtensionsNegotiation::RequestExtensions(m)) => {
+                self_.safe_lock(|x| x.handle_request_extensions(m))?
+            }
+            Ok(ExtensionsNegotiation::RequestExtensionsSuccess(_)) => {
+                Err(Error::UnexpectedExtensionMessage(
+                    EXTENSION_TYPE_EXTENSIONS_NEGOTIATION,
+                    MESSAGE_TYPE_REQUEST_EXTENSIONS_SUCCESS,
+                ))
+            }
+            Ok(ExtensionsNegotiation::RequestExtensionsError(_)) => {
+                Err(Error::UnexpectedExtensionMessage(
+                    EXTENSION_TYPE_EXTENSIONS_NEGOTIATION,
+                    MESSAGE_TYPE_REQUEST_EXTENSIONS_ERROR,
+                ))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Handles a `RequestExtensions` message.
+    ///
+    /// The response can be built with [`ExtensionRegistry::negotiate`].
+    fn handle_request_extensions(&mut self, m: RequestExtensions) -> Result<SendTo, Error>;
+}
+
+/// A trait that is implemented by the downstream node, and is used to handle the extensions
+/// negotiation messages sent by the upstream
+pub trait ParseExtensionsNegotiationFromUpstream
+where
+    Self: Sized,
+{
+    /// Takes a message type and a payload of a frame with extension type
+    /// `EXTENSION_TYPE_EXTENSIONS_NEGOTIATION` and calls the appropriate handler function
+    fn handle_message_extensions_negotiation(
+        self_: Arc<Mutex<Self>>,
+        message_type: u8,
+        payload: &mut [u8],
+    ) -> Result<SendTo, Error> {
+        Self::handle_message_extensions_negotiation_deserialized(
+            self_,
+            (message_type, payload).try_into(),
+        )
+    }
+
+    /// Takes a message and it calls the appropriate handler function
+    fn handle_message_extensions_negotiation_deserialized(
+        self_: Arc<Mutex<Self>>,
+        message: Result<ExtensionsNegotiation<'_>, Error>,
+    ) -> Result<SendTo, Error> {
+        match message {
+            Ok(ExtensionsNegotiation::RequestExtensionsSuccess(m)) => {
+                self_.safe_lock(|x| x.handle_request_extensions_success(m))?
+            }
+            Ok(ExtensionsNegotiation::RequestExtensionsError(m)) => {
+                self_.safe_lock(|x| x.handle_request_extensions_error(m))?
+            }
+            Ok(ExtensionsNegotiation::RequestExtensions(_)) => {
+                Err(Error::UnexpectedExtensionMessage(
+                    EXTENSION_TYPE_EXTENSIONS_NEGOTIATION,
+                    MESSAGE_TYPE_REQUEST_EXTENSIONS,
+                ))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Handles a `RequestExtensionsSuccess` message.
+    fn handle_request_extensions_success(
+        &mut self,
+        m: RequestExtensionsSuccess,
+    ) -> Result<SendTo, Error>;
+
+    /// Handles a `RequestExtensionsError` message.
+    fn handle_request_extensions_error(
+        &mut self,
+        m: RequestExtensionsError,
+    ) -> Result<SendTo, Error>;
+}
+
+/// A trait that is implemented by the nodes that handle messages of custom extensions, either to
+/// process them or to route them to another remote.
+pub trait ParseExtensionMessages<Remote>
+where
+    Self: Sized,
+{
+    /// Message types of the custom extensions that the node can handle.
+    fn extension_registry(&self) -> &ExtensionRegistry;
+
+    /// Takes the extension type, the message type and the payload of a frame, if the message has
+    /// been registered in [`ParseExtensionMessages::extension_registry`] it calls
+    /// [`ParseExtensionMessages::handle_extension_message`], otherwise it returns
+    /// [`Error::UnexpectedExtensionMessage`]
+    fn handle_message_extension(
+        self_: Arc<Mutex<Self>>,
+        extension_type: u16,
+        message_type: u8,
+        payload: &mut [u8],
+    ) -> Result<SendToExtension<Remote>, Error> {
+        let message = self_.safe_lock(|x| {
+            x.extension_registry()
+                .parse(extension_type, message_type, payload)
+        })??;
+        match message {
+            AnyMessage::Extension(m) => self_.safe_lock(|x| x.handle_extension_message(m))?,
+            // Sv2 subprotocols and extensions negotiation messages have their own handlers
+            _ => Err(Error::UnexpectedExtensionMessage(
+                extension_type,
+                message_type,
+            )),
+        }
+    }
+
+    /// Handles a message of a registered extension.
+    fn handle_extension_message(
+        &mut self,
+        m: ExtensionMessage,
+    ) -> Result<SendToExtension<Remote>, Error>;
+}
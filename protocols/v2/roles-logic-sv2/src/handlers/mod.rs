@@ -17,6 +17,7 @@
 //!
 //! Supported subprotocols include:
 //! - `common`: Shared messages across all Sv2 roles.
+//! - `extensions`: Extensions negotiation and messages of custom extensions.
 //! - `job_declaration`: Manages custom mining job declarations, transactions, and solutions.
 //! - `mining`: Manages standard mining communication (e.g., job dispatch, shares submission).
 //! - `template_distribution`: Handles block templates updates and transaction data.
@@ -28,6 +29,7 @@
 //! - `SendTo_` specifies the action (relay, respond, or no action).
 //! - `Error` indicates processing issues.
 pub mod common;
+pub mod extensions;
 pub mod job_declaration;
 pub mod mining;
 pub mod template_distribution;
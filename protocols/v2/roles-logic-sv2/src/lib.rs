@@ -17,6 +17,8 @@
 //! This crate can be built with the following features:
 //!
 //! - `prop_test`: Enables support for property testing in [`template_distribution_sv2`] crate.
+//! - `serde`: Implements `serde::Serialize` and `serde::Deserialize` for the Sv2 messages and
+//!   [`parsers::AnyMessage`].
 pub mod channel_logic;
 pub mod common_properties;
 pub mod errors;
@@ -25,6 +27,7 @@ pub mod job_creator;
 pub mod job_dispatcher;
 pub mod parsers;
 pub mod utils;
+pub mod vardiff;
 pub use common_messages_sv2;
 pub use errors::Error;
 pub use job_declaration_sv2;
@@ -15,15 +15,28 @@
 //!
 //! ## Supported Subprotocols
 //! - **Common Messages**: Shared across all Sv2 roles.
+//! - **Extensions Negotiation**: Lets a downstream ask which protocol extensions can be used on a
+//!   connection.
 //! - **Template Distribution**: Handles block templates updates and transaction data.
 //! - **Job Declaration**: Manages custom mining job declarations, transactions, and solutions.
 //! - **Mining Protocol**: Manages standard mining communication (e.g., job dispatch, shares
 //!   submission).
+//!
+//! ## Serde
+//! With the `serde` feature the messages, the subprotocol enums and [`AnyMessage`] implement
+//! `serde::Serialize` and `serde::Deserialize` with serde's default enum representation, e.g.
+//! `{"Mining": {"SetTarget": {"channel_id": 1, "maximum_target": "ffff..."}}}`. Byte arrays and
+//! hashes are hex strings in wire byte order and strings are UTF-8, see [`binary_sv2`].
+//!
+//! ## Protocol Extensions
+//! Messages of the Sv2 subprotocols and of the extensions negotiation are parsed by [`AnyMessage`].
+//! Messages of any other extension are parsed as an opaque [`ExtensionMessage`], but only if their
+//! extension type and message type have been registered in an [`ExtensionRegistry`].
 
 use crate::Error;
 use binary_sv2::{
     decodable::{DecodableField, FieldMarker},
-    encodable::EncodableField,
+    encodable::{EncodableField, EncodablePrimitive},
     from_bytes, Deserialize, GetSize,
 };
 use const_sv2::{
@@ -37,7 +50,8 @@ use const_sv2::{
     CHANNEL_BIT_OPEN_EXTENDED_MINING_CHANNEL_SUCCES, CHANNEL_BIT_OPEN_MINING_CHANNEL_ERROR,
     CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL, CHANNEL_BIT_OPEN_STANDARD_MINING_CHANNEL_SUCCESS,
     CHANNEL_BIT_PROVIDE_MISSING_TRANSACTIONS, CHANNEL_BIT_PROVIDE_MISSING_TRANSACTIONS_SUCCESS,
-    CHANNEL_BIT_RECONNECT, CHANNEL_BIT_REQUEST_TRANSACTION_DATA,
+    CHANNEL_BIT_RECONNECT, CHANNEL_BIT_REQUEST_EXTENSIONS, CHANNEL_BIT_REQUEST_EXTENSIONS_ERROR,
+    CHANNEL_BIT_REQUEST_EXTENSIONS_SUCCESS, CHANNEL_BIT_REQUEST_TRANSACTION_DATA,
     CHANNEL_BIT_REQUEST_TRANSACTION_DATA_ERROR, CHANNEL_BIT_REQUEST_TRANSACTION_DATA_SUCCESS,
     CHANNEL_BIT_SETUP_CONNECTION, CHANNEL_BIT_SETUP_CONNECTION_ERROR,
     CHANNEL_BIT_SETUP_CONNECTION_SUCCESS, CHANNEL_BIT_SET_CUSTOM_MINING_JOB,
@@ -47,6 +61,7 @@ use const_sv2::{
     CHANNEL_BIT_SUBMIT_SHARES_EXTENDED, CHANNEL_BIT_SUBMIT_SHARES_STANDARD,
     CHANNEL_BIT_SUBMIT_SHARES_SUCCESS, CHANNEL_BIT_SUBMIT_SOLUTION, CHANNEL_BIT_SUBMIT_SOLUTION_JD,
     CHANNEL_BIT_UPDATE_CHANNEL, CHANNEL_BIT_UPDATE_CHANNEL_ERROR,
+    EXTENSION_TYPE_EXTENSIONS_NEGOTIATION, EXTENSION_TYPE_NO_EXTENSION,
     MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN, MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN_SUCCESS,
     MESSAGE_TYPE_CHANNEL_ENDPOINT_CHANGED, MESSAGE_TYPE_CLOSE_CHANNEL,
     MESSAGE_TYPE_COINBASE_OUTPUT_CONSTRAINTS, MESSAGE_TYPE_DECLARE_MINING_JOB,
@@ -58,23 +73,25 @@ use const_sv2::{
     MESSAGE_TYPE_OPEN_MINING_CHANNEL_ERROR, MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL,
     MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS, MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS,
     MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTIONS_SUCCESS, MESSAGE_TYPE_RECONNECT,
-    MESSAGE_TYPE_REQUEST_TRANSACTION_DATA, MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_ERROR,
-    MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_SUCCESS, MESSAGE_TYPE_SETUP_CONNECTION,
-    MESSAGE_TYPE_SETUP_CONNECTION_ERROR, MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS,
-    MESSAGE_TYPE_SET_CUSTOM_MINING_JOB, MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_ERROR,
-    MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_SUCCESS, MESSAGE_TYPE_SET_EXTRANONCE_PREFIX,
-    MESSAGE_TYPE_SET_GROUP_CHANNEL, MESSAGE_TYPE_SET_NEW_PREV_HASH, MESSAGE_TYPE_SET_TARGET,
-    MESSAGE_TYPE_SUBMIT_SHARES_ERROR, MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
-    MESSAGE_TYPE_SUBMIT_SHARES_STANDARD, MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS,
-    MESSAGE_TYPE_SUBMIT_SOLUTION, MESSAGE_TYPE_SUBMIT_SOLUTION_JD, MESSAGE_TYPE_UPDATE_CHANNEL,
-    MESSAGE_TYPE_UPDATE_CHANNEL_ERROR,
+    MESSAGE_TYPE_REQUEST_EXTENSIONS, MESSAGE_TYPE_REQUEST_EXTENSIONS_ERROR,
+    MESSAGE_TYPE_REQUEST_EXTENSIONS_SUCCESS, MESSAGE_TYPE_REQUEST_TRANSACTION_DATA,
+    MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_ERROR, MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_SUCCESS,
+    MESSAGE_TYPE_SETUP_CONNECTION, MESSAGE_TYPE_SETUP_CONNECTION_ERROR,
+    MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS, MESSAGE_TYPE_SET_CUSTOM_MINING_JOB,
+    MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_ERROR, MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_SUCCESS,
+    MESSAGE_TYPE_SET_EXTRANONCE_PREFIX, MESSAGE_TYPE_SET_GROUP_CHANNEL,
+    MESSAGE_TYPE_SET_NEW_PREV_HASH, MESSAGE_TYPE_SET_TARGET, MESSAGE_TYPE_SUBMIT_SHARES_ERROR,
+    MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED, MESSAGE_TYPE_SUBMIT_SHARES_STANDARD,
+    MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS, MESSAGE_TYPE_SUBMIT_SOLUTION,
+    MESSAGE_TYPE_SUBMIT_SOLUTION_JD, MESSAGE_TYPE_UPDATE_CHANNEL,
+    MESSAGE_TYPE_UPDATE_CHANNEL_ERROR, SV2_FRAME_HEADER_LEN_OFFSET, SV2_FRAME_HEADER_SIZE,
 };
 use core::convert::{TryFrom, TryInto};
 use framing_sv2::framing::Sv2Frame;
 
 use common_messages_sv2::{
-    ChannelEndpointChanged, Reconnect, SetupConnection, SetupConnectionError,
-    SetupConnectionSuccess,
+    ChannelEndpointChanged, Reconnect, RequestExtensions, RequestExtensionsError,
+    RequestExtensionsSuccess, SetupConnection, SetupConnectionError, SetupConnectionSuccess,
 };
 use job_declaration_sv2::{
     AllocateMiningJobToken, AllocateMiningJobTokenSuccess, DeclareMiningJob, DeclareMiningJobError,
@@ -87,7 +104,7 @@ use mining_sv2::{
     OpenStandardMiningChannelSuccess, SetCustomMiningJob, SetCustomMiningJobError,
     SetCustomMiningJobSuccess, SetExtranoncePrefix, SetGroupChannel,
     SetNewPrevHash as MiningSetNewPrevHash, SetTarget, SubmitSharesError, SubmitSharesExtended,
-    SubmitSharesStandard, SubmitSharesSuccess, UpdateChannel, UpdateChannelError,
+    SubmitSharesStandard, SubmitSharesSuccess, Tlv, UpdateChannel, UpdateChannelError,
 };
 use template_distribution_sv2::{
     CoinbaseOutputConstraints, NewTemplate, RequestTransactionData, RequestTransactionDataError,
@@ -160,6 +177,7 @@ pub fn message_type_to_name(msg_type: u8) -> &'static str {
 /// A parser of messages that are common to all Sv2 subprotocols, to be used for parsing raw
 /// messages
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CommonMessages<'a> {
     /// Notifies about changes in channel endpoint configuration.
     ChannelEndpointChanged(ChannelEndpointChanged),
@@ -173,8 +191,60 @@ pub enum CommonMessages<'a> {
     SetupConnectionSuccess(SetupConnectionSuccess),
 }
 
+/// A parser of messages of the extensions negotiation extension
+/// ([`EXTENSION_TYPE_EXTENSIONS_NEGOTIATION`]).
+///
+/// The message types of this extension overlap with the [`CommonMessages`] ones, so they can only
+/// be parsed together with the frame `extension_type`, see [`AnyMessage`] and
+/// [`ExtensionRegistry::parse`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExtensionsNegotiation<'a> {
+    /// Asks the upstream for a set of extensions.
+    RequestExtensions(RequestExtensions<'a>),
+    /// Rejects a `RequestExtensions`.
+    RequestExtensionsError(RequestExtensionsError<'a>),
+    /// Accepts a `RequestExtensions`.
+    RequestExtensionsSuccess(RequestExtensionsSuccess<'a>),
+}
+
+impl ExtensionsNegotiation<'_> {
+    /// converter into static lifetime
+    pub fn into_static(self) -> ExtensionsNegotiation<'static> {
+        match self {
+            ExtensionsNegotiation::RequestExtensions(m) => {
+                ExtensionsNegotiation::RequestExtensions(m.into_static())
+            }
+            ExtensionsNegotiation::RequestExtensionsError(m) => {
+                ExtensionsNegotiation::RequestExtensionsError(m.into_static())
+            }
+            ExtensionsNegotiation::RequestExtensionsSuccess(m) => {
+                ExtensionsNegotiation::RequestExtensionsSuccess(m.into_static())
+            }
+        }
+    }
+}
+
+/// A message of a protocol extension registered in an [`ExtensionRegistry`].
+///
+/// The payload is not deserialized, it is up to the handler of the extension to do it. This let
+/// custom messages travel next to the Sv2 subprotocols messages without adding variants to
+/// [`AnyMessage`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtensionMessage {
+    /// Extension type, without the channel bit.
+    pub extension_type: u16,
+    pub message_type: u8,
+    pub channel_bit: bool,
+    /// Serialized message.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::hex"))]
+    pub payload: Vec<u8>,
+}
+
 /// A parser of messages of Template Distribution subprotocol, to be used for parsing raw messages
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TemplateDistribution<'a> {
     CoinbaseOutputConstraints(CoinbaseOutputConstraints),
     NewTemplate(NewTemplate<'a>),
@@ -187,6 +257,7 @@ pub enum TemplateDistribution<'a> {
 
 /// A parser of messages of Job Declaration subprotocol, to be used for parsing raw messages
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum JobDeclaration<'a> {
     AllocateMiningJobToken(AllocateMiningJobToken<'a>),
     AllocateMiningJobTokenSuccess(AllocateMiningJobTokenSuccess<'a>),
@@ -224,6 +295,7 @@ pub enum JobDeclaration<'a> {
 ///     unified interface for handling mining-related communication. This reduces complexity and
 ///     ensures consistency across roles.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mining<'a> {
     CloseChannel(CloseChannel<'a>),
     NewExtendedMiningJob(NewExtendedMiningJob<'a>),
@@ -294,6 +366,46 @@ pub trait IsSv2Message {
     fn message_type(&self) -> u8;
     /// get channel bit
     fn channel_bit(&self) -> bool;
+    /// get extension type, [`EXTENSION_TYPE_NO_EXTENSION`] for the Sv2 subprotocols messages
+    fn extension_type(&self) -> u16 {
+        EXTENSION_TYPE_NO_EXTENSION
+    }
+}
+
+impl IsSv2Message for ExtensionsNegotiation<'_> {
+    fn message_type(&self) -> u8 {
+        match self {
+            Self::RequestExtensions(_) => MESSAGE_TYPE_REQUEST_EXTENSIONS,
+            Self::RequestExtensionsError(_) => MESSAGE_TYPE_REQUEST_EXTENSIONS_ERROR,
+            Self::RequestExtensionsSuccess(_) => MESSAGE_TYPE_REQUEST_EXTENSIONS_SUCCESS,
+        }
+    }
+
+    fn channel_bit(&self) -> bool {
+        match self {
+            Self::RequestExtensions(_) => CHANNEL_BIT_REQUEST_EXTENSIONS,
+            Self::RequestExtensionsError(_) => CHANNEL_BIT_REQUEST_EXTENSIONS_ERROR,
+            Self::RequestExtensionsSuccess(_) => CHANNEL_BIT_REQUEST_EXTENSIONS_SUCCESS,
+        }
+    }
+
+    fn extension_type(&self) -> u16 {
+        EXTENSION_TYPE_EXTENSIONS_NEGOTIATION
+    }
+}
+
+impl IsSv2Message for ExtensionMessage {
+    fn message_type(&self) -> u8 {
+        self.message_type
+    }
+
+    fn channel_bit(&self) -> bool {
+        self.channel_bit
+    }
+
+    fn extension_type(&self) -> u16 {
+        self.extension_type
+    }
 }
 
 impl IsSv2Message for CommonMessages<'_> {
@@ -451,6 +563,25 @@ impl<'decoder> From<CommonMessages<'decoder>> for EncodableField<'decoder> {
         }
     }
 }
+impl<'decoder> From<ExtensionsNegotiation<'decoder>> for EncodableField<'decoder> {
+    fn from(m: ExtensionsNegotiation<'decoder>) -> Self {
+        match m {
+            ExtensionsNegotiation::RequestExtensions(a) => a.into(),
+            ExtensionsNegotiation::RequestExtensionsError(a) => a.into(),
+            ExtensionsNegotiation::RequestExtensionsSuccess(a) => a.into(),
+        }
+    }
+}
+impl<'decoder> From<ExtensionMessage> for EncodableField<'decoder> {
+    fn from(m: ExtensionMessage) -> Self {
+        EncodableField::Struct(
+            m.payload
+                .into_iter()
+                .map(|b| EncodableField::Primitive(EncodablePrimitive::U8(b)))
+                .collect(),
+        )
+    }
+}
 impl<'decoder> From<TemplateDistribution<'decoder>> for EncodableField<'decoder> {
     fn from(m: TemplateDistribution<'decoder>) -> Self {
         match m {
@@ -520,6 +651,20 @@ impl GetSize for CommonMessages<'_> {
         }
     }
 }
+impl GetSize for ExtensionsNegotiation<'_> {
+    fn get_size(&self) -> usize {
+        match self {
+            ExtensionsNegotiation::RequestExtensions(a) => a.get_size(),
+            ExtensionsNegotiation::RequestExtensionsError(a) => a.get_size(),
+            ExtensionsNegotiation::RequestExtensionsSuccess(a) => a.get_size(),
+        }
+    }
+}
+impl GetSize for ExtensionMessage {
+    fn get_size(&self) -> usize {
+        self.payload.len()
+    }
+}
 impl GetSize for TemplateDistribution<'_> {
     fn get_size(&self) -> usize {
         match self {
@@ -697,6 +842,58 @@ impl<'a> TryFrom<(u8, &'a mut [u8])> for CommonMessages<'a> {
     }
 }
 
+/// A list of 8-bit message type variants of the extensions negotiation extension
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+#[allow(clippy::enum_variant_names)]
+pub enum ExtensionsNegotiationTypes {
+    RequestExtensions = MESSAGE_TYPE_REQUEST_EXTENSIONS,
+    RequestExtensionsSuccess = MESSAGE_TYPE_REQUEST_EXTENSIONS_SUCCESS,
+    RequestExtensionsError = MESSAGE_TYPE_REQUEST_EXTENSIONS_ERROR,
+}
+
+impl TryFrom<u8> for ExtensionsNegotiationTypes {
+    type Error = Error;
+
+    fn try_from(v: u8) -> Result<ExtensionsNegotiationTypes, Error> {
+        match v {
+            MESSAGE_TYPE_REQUEST_EXTENSIONS => Ok(ExtensionsNegotiationTypes::RequestExtensions),
+            MESSAGE_TYPE_REQUEST_EXTENSIONS_SUCCESS => {
+                Ok(ExtensionsNegotiationTypes::RequestExtensionsSuccess)
+            }
+            MESSAGE_TYPE_REQUEST_EXTENSIONS_ERROR => {
+                Ok(ExtensionsNegotiationTypes::RequestExtensionsError)
+            }
+            _ => Err(Error::UnexpectedExtensionMessage(
+                EXTENSION_TYPE_EXTENSIONS_NEGOTIATION,
+                v,
+            )),
+        }
+    }
+}
+
+impl<'a> TryFrom<(u8, &'a mut [u8])> for ExtensionsNegotiation<'a> {
+    type Error = Error;
+
+    fn try_from(v: (u8, &'a mut [u8])) -> Result<Self, Self::Error> {
+        let msg_type: ExtensionsNegotiationTypes = v.0.try_into()?;
+        match msg_type {
+            ExtensionsNegotiationTypes::RequestExtensions => {
+                let message: RequestExtensions<'a> = from_bytes(v.1)?;
+                Ok(ExtensionsNegotiation::RequestExtensions(message))
+            }
+            ExtensionsNegotiationTypes::RequestExtensionsSuccess => {
+                let message: RequestExtensionsSuccess<'a> = from_bytes(v.1)?;
+                Ok(ExtensionsNegotiation::RequestExtensionsSuccess(message))
+            }
+            ExtensionsNegotiationTypes::RequestExtensionsError => {
+                let message: RequestExtensionsError<'a> = from_bytes(v.1)?;
+                Ok(ExtensionsNegotiation::RequestExtensionsError(message))
+            }
+        }
+    }
+}
+
 /// A list of 8-bit message type variants under Template Distribution subprotocol
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
@@ -1035,6 +1232,7 @@ impl<'a> TryFrom<(u8, &'a mut [u8])> for Mining<'a> {
 
 /// A parser of messages that a Mining Device could send
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MiningDeviceMessages<'a> {
     Common(CommonMessages<'a>),
     Mining(Mining<'a>),
@@ -1073,11 +1271,15 @@ impl<'a> TryFrom<(u8, &'a mut [u8])> for MiningDeviceMessages<'a> {
 
 /// A parser of all possible SV2 messages
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AnyMessage<'a> {
     Common(CommonMessages<'a>),
     Mining(Mining<'a>),
     JobDeclaration(JobDeclaration<'a>),
     TemplateDistribution(TemplateDistribution<'a>),
+    ExtensionsNegotiation(ExtensionsNegotiation<'a>),
+    /// Message of a registered extension, see [`ExtensionRegistry`]
+    Extension(ExtensionMessage),
 }
 
 impl<'a> TryFrom<MiningDeviceMessages<'a>> for AnyMessage<'a> {
@@ -1098,6 +1300,8 @@ impl<'decoder> From<AnyMessage<'decoder>> for EncodableField<'decoder> {
             AnyMessage::Mining(a) => a.into(),
             AnyMessage::JobDeclaration(a) => a.into(),
             AnyMessage::TemplateDistribution(a) => a.into(),
+            AnyMessage::ExtensionsNegotiation(a) => a.into(),
+            AnyMessage::Extension(a) => a.into(),
         }
     }
 }
@@ -1108,6 +1312,8 @@ impl GetSize for AnyMessage<'_> {
             AnyMessage::Mining(a) => a.get_size(),
             AnyMessage::JobDeclaration(a) => a.get_size(),
             AnyMessage::TemplateDistribution(a) => a.get_size(),
+            AnyMessage::ExtensionsNegotiation(a) => a.get_size(),
+            AnyMessage::Extension(a) => a.get_size(),
         }
     }
 }
@@ -1119,6 +1325,8 @@ impl IsSv2Message for AnyMessage<'_> {
             AnyMessage::Mining(a) => a.message_type(),
             AnyMessage::JobDeclaration(a) => a.message_type(),
             AnyMessage::TemplateDistribution(a) => a.message_type(),
+            AnyMessage::ExtensionsNegotiation(a) => a.message_type(),
+            AnyMessage::Extension(a) => a.message_type(),
         }
     }
 
@@ -1128,6 +1336,16 @@ impl IsSv2Message for AnyMessage<'_> {
             AnyMessage::Mining(a) => a.channel_bit(),
             AnyMessage::JobDeclaration(a) => a.channel_bit(),
             AnyMessage::TemplateDistribution(a) => a.channel_bit(),
+            AnyMessage::ExtensionsNegotiation(a) => a.channel_bit(),
+            AnyMessage::Extension(a) => a.channel_bit(),
+        }
+    }
+
+    fn extension_type(&self) -> u16 {
+        match self {
+            AnyMessage::ExtensionsNegotiation(a) => a.extension_type(),
+            AnyMessage::Extension(a) => a.extension_type(),
+            _ => EXTENSION_TYPE_NO_EXTENSION,
         }
     }
 }
@@ -1173,6 +1391,164 @@ impl<'a> TryFrom<(u8, &'a mut [u8])> for AnyMessage<'a> {
     }
 }
 
+/// Parses a message given the `extension_type` and the `message_type` of its frame. Only the Sv2
+/// subprotocols and the extensions negotiation messages are known, the messages of any other
+/// extension are parsed with [`ExtensionRegistry::parse`].
+impl<'a> TryFrom<(u16, u8, &'a mut [u8])> for AnyMessage<'a> {
+    type Error = Error;
+
+    fn try_from(v: (u16, u8, &'a mut [u8])) -> Result<Self, Self::Error> {
+        let (extension_type, message_type, payload) = v;
+        match extension_type & EXTENSION_TYPE_MASK {
+            EXTENSION_TYPE_NO_EXTENSION => (message_type, payload).try_into(),
+            EXTENSION_TYPE_EXTENSIONS_NEGOTIATION => Ok(AnyMessage::ExtensionsNegotiation(
+                (message_type, payload).try_into()?,
+            )),
+            extension_type => Err(Error::UnexpectedExtensionMessage(
+                extension_type,
+                message_type,
+            )),
+        }
+    }
+}
+
+// The most significant bit of the frame `extension_type` is the channel bit, it is not part of
+// the extension identifier.
+const EXTENSION_TYPE_MASK: u16 = u16::MAX >> 1;
+
+/// Extension messages that a role is able to handle.
+///
+/// Every role that wants to receive messages of a custom extension registers here the message
+/// types of the extension. The registered messages are then parsed as [`ExtensionMessage`] and
+/// can be relayed or handled like any other message, everything else is rejected with
+/// [`Error::UnexpectedExtensionMessage`].
+///
+/// The registry is also used to answer to a `RequestExtensions`, see
+/// [`ExtensionRegistry::negotiate`].
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionRegistry {
+    messages: Vec<(u16, u8)>,
+    // Supported extensions that do not define messages, like the ones that only add TLV fields
+    extensions: Vec<u16>,
+    required_extensions: Vec<u16>,
+}
+
+impl ExtensionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a message type of an extension.
+    pub fn register(&mut self, extension_type: u16, message_type: u8) {
+        let extension_type = extension_type & EXTENSION_TYPE_MASK;
+        if !self.is_registered(extension_type, message_type) {
+            self.messages.push((extension_type, message_type));
+        }
+    }
+
+    /// Marks as supported an extension that does not define messages, like the worker-specific
+    /// hashrate tracking extension that only appends TLV fields (see [`mining_sv2::Tlv`]).
+    pub fn support(&mut self, extension_type: u16) {
+        let extension_type = extension_type & EXTENSION_TYPE_MASK;
+        if !self.extensions.contains(&extension_type) {
+            self.extensions.push(extension_type);
+        }
+    }
+
+    /// Marks an extension as required, a `RequestExtensions` that does not ask for it is
+    /// rejected.
+    pub fn require(&mut self, extension_type: u16) {
+        let extension_type = extension_type & EXTENSION_TYPE_MASK;
+        if !self.required_extensions.contains(&extension_type) {
+            self.required_extensions.push(extension_type);
+        }
+    }
+
+    pub fn is_registered(&self, extension_type: u16, message_type: u8) -> bool {
+        let extension_type = extension_type & EXTENSION_TYPE_MASK;
+        self.messages.contains(&(extension_type, message_type))
+    }
+
+    /// Returns true if the extension has been marked as supported or if at least a message type
+    /// of the extension has been registered.
+    pub fn supports(&self, extension_type: u16) -> bool {
+        let extension_type = extension_type & EXTENSION_TYPE_MASK;
+        self.extensions.contains(&extension_type)
+            || self.messages.iter().any(|(e, _)| *e == extension_type)
+    }
+
+    /// Extensions marked as supported or with at least a registered message type, without
+    /// duplicates.
+    pub fn supported_extensions(&self) -> Vec<u16> {
+        let mut extensions: Vec<u16> = self.extensions.clone();
+        for (extension_type, _) in &self.messages {
+            if !extensions.contains(extension_type) {
+                extensions.push(*extension_type);
+            }
+        }
+        extensions
+    }
+
+    /// Parses a message given the `extension_type` and the `message_type` of its frame. Messages
+    /// without extension and extensions negotiation messages are always parsed, messages of other
+    /// extensions only if registered.
+    pub fn parse<'a>(
+        &self,
+        extension_type: u16,
+        message_type: u8,
+        payload: &'a mut [u8],
+    ) -> Result<AnyMessage<'a>, Error> {
+        let channel_bit = extension_type & !EXTENSION_TYPE_MASK != 0;
+        let extension_type = extension_type & EXTENSION_TYPE_MASK;
+        match extension_type {
+            EXTENSION_TYPE_NO_EXTENSION | EXTENSION_TYPE_EXTENSIONS_NEGOTIATION => {
+                (extension_type, message_type, payload).try_into()
+            }
+            _ if self.is_registered(extension_type, message_type) => {
+                Ok(AnyMessage::Extension(ExtensionMessage {
+                    extension_type,
+                    message_type,
+                    channel_bit,
+                    payload: payload.to_vec(),
+                }))
+            }
+            _ => Err(Error::UnexpectedExtensionMessage(
+                extension_type,
+                message_type,
+            )),
+        }
+    }
+
+    /// Builds the response to a `RequestExtensions`. The request is rejected if it does not ask
+    /// for all the required extensions, otherwise it is accepted with the requested extensions
+    /// that are supported.
+    pub fn negotiate(&self, request: &RequestExtensions) -> ExtensionsNegotiation<'static> {
+        let requested = request.requested_extensions.clone().into_inner();
+        let (supported, unsupported): (Vec<u16>, Vec<u16>) = requested
+            .iter()
+            .copied()
+            .partition(|extension_type| self.supports(*extension_type));
+        let missing: Vec<u16> = self
+            .required_extensions
+            .iter()
+            .filter(|extension_type| !requested.contains(*extension_type))
+            .copied()
+            .collect();
+        if missing.is_empty() {
+            ExtensionsNegotiation::RequestExtensionsSuccess(RequestExtensionsSuccess {
+                request_id: request.request_id,
+                supported_extensions: supported.into(),
+            })
+        } else {
+            ExtensionsNegotiation::RequestExtensionsError(RequestExtensionsError {
+                request_id: request.request_id,
+                unsupported_extensions: unsupported.into(),
+                required_extensions: missing.into(),
+            })
+        }
+    }
+}
+
 impl<'a> From<SetupConnection<'a>> for CommonMessages<'a> {
     fn from(v: SetupConnection<'a>) -> Self {
         CommonMessages::SetupConnection(v)
@@ -1225,7 +1601,7 @@ impl<'decoder, B: AsMut<[u8]> + AsRef<[u8]>> TryFrom<AnyMessage<'decoder>>
     type Error = Error;
 
     fn try_from(v: AnyMessage<'decoder>) -> Result<Self, Error> {
-        let extension_type = 0;
+        let extension_type = v.extension_type();
         let channel_bit = v.channel_bit();
         let message_type = v.message_type();
         Sv2Frame::from_message(v, message_type, extension_type, channel_bit)
@@ -1233,6 +1609,38 @@ impl<'decoder, B: AsMut<[u8]> + AsRef<[u8]>> TryFrom<AnyMessage<'decoder>>
     }
 }
 
+/// Builds the frame of `message` with `tlvs` appended to its payload.
+///
+/// TLV fields are defined by protocol extensions, they must only be appended once the extension
+/// has been negotiated with the remote. The returned frame is already serialized.
+pub fn frame_with_tlvs<'decoder, B>(
+    message: AnyMessage<'decoder>,
+    tlvs: &[Tlv],
+) -> Result<Sv2Frame<AnyMessage<'decoder>, B>, Error>
+where
+    B: AsMut<[u8]> + AsRef<[u8]> + From<Vec<u8>>,
+{
+    let frame: Sv2Frame<AnyMessage<'decoder>, B> = message.try_into()?;
+    if tlvs.is_empty() {
+        return Ok(frame);
+    }
+    let mut bytes = vec![0; frame.encoded_length()];
+    frame
+        .serialize(&mut bytes)
+        .map_err(|_| Error::BadPayloadSize)?;
+    for tlv in tlvs {
+        tlv.write(&mut bytes);
+    }
+    // The payload length is an U24 at the end of the header
+    let len = (bytes.len() - SV2_FRAME_HEADER_SIZE) as u32;
+    if len >= 1 << 24 {
+        return Err(Error::BadPayloadSize);
+    }
+    bytes[SV2_FRAME_HEADER_LEN_OFFSET..SV2_FRAME_HEADER_SIZE]
+        .copy_from_slice(&len.to_le_bytes()[..3]);
+    Ok(Sv2Frame::from_bytes_unchecked(bytes.into()))
+}
+
 impl<'decoder, B: AsMut<[u8]> + AsRef<[u8]>> TryFrom<MiningDeviceMessages<'decoder>>
     for Sv2Frame<MiningDeviceMessages<'decoder>, B>
 {
@@ -1270,6 +1678,8 @@ impl<'a> TryFrom<AnyMessage<'a>> for MiningDeviceMessages<'a> {
             AnyMessage::Mining(message) => Ok(Self::Mining(message)),
             AnyMessage::JobDeclaration(_) => Err(Error::UnexpectedPoolMessage),
             AnyMessage::TemplateDistribution(_) => Err(Error::UnexpectedPoolMessage),
+            AnyMessage::ExtensionsNegotiation(_) => Err(Error::UnexpectedPoolMessage),
+            AnyMessage::Extension(_) => Err(Error::UnexpectedPoolMessage),
         }
     }
 }
@@ -1277,11 +1687,16 @@ impl<'a> TryFrom<AnyMessage<'a>> for MiningDeviceMessages<'a> {
 #[cfg(test)]
 mod test {
     use crate::{
-        mining_sv2::NewMiningJob,
-        parsers::{AnyMessage, Mining},
+        common_messages_sv2::RequestExtensions,
+        mining_sv2::{user_identity as user_identity_of, NewMiningJob, SubmitSharesExtended, Tlv},
+        parsers::{
+            frame_with_tlvs, AnyMessage, ExtensionMessage, ExtensionRegistry,
+            ExtensionsNegotiation, Mining,
+        },
     };
-    use binary_sv2::{Sv2Option, U256};
+    use binary_sv2::{GetSize, Sv2Option, U256};
     use codec_sv2::StandardSv2Frame;
+    use const_sv2::EXTENSION_TYPE_WORKER_HASHRATE_TRACKING;
     use std::convert::{TryFrom, TryInto};
 
     pub type Message = AnyMessage<'static>;
@@ -1304,6 +1719,130 @@ mod test {
         message_serialization_check(mining_message, CORRECTLY_SERIALIZED_MSG);
     }
 
+    #[test]
+    fn request_extensions_serialization() {
+        const CORRECTLY_SERIALIZED_MSG: &[u8] = &[1, 0, 0, 8, 0, 0, 1, 0, 2, 0, 2, 0, 16, 0];
+        let message = AnyMessage::ExtensionsNegotiation(ExtensionsNegotiation::RequestExtensions(
+            RequestExtensions {
+                request_id: 1,
+                requested_extensions: vec![2, 16].into(),
+            },
+        ));
+        message_serialization_check(message, CORRECTLY_SERIALIZED_MSG);
+    }
+
+    #[test]
+    fn extension_messages_are_parsed_only_if_registered() {
+        let message = ExtensionMessage {
+            extension_type: 0x0100,
+            message_type: 5,
+            channel_bit: true,
+            payload: vec![1, 2, 3],
+        };
+        let frame = StdFrame::try_from(AnyMessage::Extension(message.clone())).unwrap();
+        let encoded_frame_length = frame.encoded_length();
+        let mut buffer = [0; 0xffff];
+        frame.serialize(&mut buffer).unwrap();
+        assert!(is_channel_msg(&buffer));
+        let extension_type = u16::from_le_bytes([buffer[0], buffer[1]]);
+        let message_type = extract_message_type(&buffer);
+        let payload = &mut buffer[6..encoded_frame_length];
+
+        let mut registry = ExtensionRegistry::new();
+        assert!(registry
+            .parse(extension_type, message_type, payload)
+            .is_err());
+        registry.register(0x0100, 5);
+        match registry.parse(extension_type, message_type, payload) {
+            Ok(AnyMessage::Extension(parsed)) => assert_eq!(parsed, message),
+            other => panic!("Unexpected parsing result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn extensions_negotiation() {
+        let mut registry = ExtensionRegistry::new();
+        registry.register(2, 0);
+        registry.register(3, 0);
+        let request = RequestExtensions {
+            request_id: 7,
+            requested_extensions: vec![2, 4].into(),
+        };
+        match registry.negotiate(&request) {
+            ExtensionsNegotiation::RequestExtensionsSuccess(m) => {
+                assert_eq!(m.request_id, 7);
+                assert_eq!(m.supported_extensions.into_inner(), vec![2]);
+            }
+            other => panic!("Unexpected response: {:?}", other),
+        }
+        registry.require(3);
+        match registry.negotiate(&request) {
+            ExtensionsNegotiation::RequestExtensionsError(m) => {
+                assert_eq!(m.unsupported_extensions.into_inner(), vec![4]);
+                assert_eq!(m.required_extensions.into_inner(), vec![3]);
+            }
+            other => panic!("Unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn extensions_without_messages_can_be_negotiated() {
+        let mut registry = ExtensionRegistry::new();
+        registry.support(EXTENSION_TYPE_WORKER_HASHRATE_TRACKING);
+        assert!(registry.supports(EXTENSION_TYPE_WORKER_HASHRATE_TRACKING));
+        assert_eq!(
+            registry.supported_extensions(),
+            vec![EXTENSION_TYPE_WORKER_HASHRATE_TRACKING]
+        );
+        let request = RequestExtensions {
+            request_id: 1,
+            requested_extensions: vec![EXTENSION_TYPE_WORKER_HASHRATE_TRACKING].into(),
+        };
+        match registry.negotiate(&request) {
+            ExtensionsNegotiation::RequestExtensionsSuccess(m) => assert_eq!(
+                m.supported_extensions.into_inner(),
+                vec![EXTENSION_TYPE_WORKER_HASHRATE_TRACKING]
+            ),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn submit_shares_extended_with_user_identity() {
+        let share = SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 2,
+            job_id: 3,
+            nonce: 4,
+            ntime: 5,
+            version: 6,
+            extranonce: vec![7; 16].try_into().unwrap(),
+        };
+        let user_identity = Tlv::user_identity("worker.1").unwrap();
+        let frame: StdFrame = frame_with_tlvs(
+            AnyMessage::Mining(Mining::SubmitSharesExtended(share.clone())),
+            core::slice::from_ref(&user_identity),
+        )
+        .unwrap();
+        let encoded_frame_length = frame.encoded_length();
+        assert_eq!(
+            encoded_frame_length,
+            6 + share.get_size() + user_identity.encoded_length()
+        );
+
+        // The frame is already serialized so the buffer must have its exact size
+        let mut buffer = vec![0; encoded_frame_length];
+        frame.serialize(&mut buffer).unwrap();
+        check_length_consistency(&buffer);
+        assert_eq!(extract_extension_type(&buffer), 0);
+        let payload = buffer[6..].to_vec();
+        let mut bytes = payload.clone();
+        let parsed: SubmitSharesExtended = binary_sv2::from_bytes(&mut bytes).unwrap();
+        assert_eq!(parsed.job_id, share.job_id);
+        let tlvs = parsed.tlvs(&payload).unwrap();
+        assert_eq!(user_identity_of(&tlvs), Some("worker.1"));
+    }
+
     fn message_serialization_check(message: AnyMessage<'static>, expected_result: &[u8]) {
         let frame = StdFrame::try_from(message).unwrap();
         let encoded_frame_length = frame.encoded_length();
@@ -1376,4 +1915,120 @@ mod test {
             message_length, payload_length,
         );
     }
+
+    #[cfg(feature = "serde")]
+    mod json {
+        use super::*;
+        use crate::{
+            common_messages_sv2::{Protocol, SetupConnection},
+            mining_sv2::OpenExtendedMiningChannelSuccess,
+            parsers::CommonMessages,
+        };
+        use binary_sv2::Seq064K;
+        use serde_json::json;
+
+        fn json_round_trip(message: Message) -> serde_json::Value {
+            let value = serde_json::to_value(&message).unwrap();
+            let deserialized: Message = serde_json::from_value(value.clone()).unwrap();
+            assert_eq!(
+                binary_sv2::to_bytes(deserialized).unwrap(),
+                binary_sv2::to_bytes(message).unwrap()
+            );
+            value
+        }
+
+        #[test]
+        fn setup_connection_to_json() {
+            let message = AnyMessage::Common(CommonMessages::SetupConnection(SetupConnection {
+                protocol: Protocol::MiningProtocol,
+                min_version: 2,
+                max_version: 2,
+                flags: 1,
+                endpoint_host: "0.0.0.0".to_string().try_into().unwrap(),
+                endpoint_port: 3333,
+                vendor: "vendor".to_string().try_into().unwrap(),
+                hardware_version: "hw".to_string().try_into().unwrap(),
+                firmware: "fw".to_string().try_into().unwrap(),
+                device_id: "".to_string().try_into().unwrap(),
+            }));
+            assert_eq!(
+                json_round_trip(message),
+                json!({"Common": {"SetupConnection": {
+                    "protocol": "MiningProtocol",
+                    "min_version": 2,
+                    "max_version": 2,
+                    "flags": 1,
+                    "endpoint_host": "0.0.0.0",
+                    "endpoint_port": 3333,
+                    "vendor": "vendor",
+                    "hardware_version": "hw",
+                    "firmware": "fw",
+                    "device_id": "",
+                }}})
+            );
+        }
+
+        #[test]
+        fn mining_messages_to_json() {
+            let message = AnyMessage::Mining(Mining::NewMiningJob(NewMiningJob {
+                channel_id: 1,
+                job_id: 2,
+                min_ntime: Sv2Option::new(None),
+                version: 3,
+                merkle_root: U256::from([0xab; 32]),
+            }));
+            let value = json_round_trip(message);
+            assert_eq!(value["Mining"]["NewMiningJob"]["min_ntime"], json!(null));
+            assert_eq!(
+                value["Mining"]["NewMiningJob"]["merkle_root"],
+                json!("ab".repeat(32))
+            );
+
+            let message = AnyMessage::Mining(Mining::OpenExtendedMiningChannelSuccess(
+                OpenExtendedMiningChannelSuccess {
+                    request_id: 7,
+                    channel_id: 1,
+                    target: U256::from([0xff; 32]),
+                    extranonce_size: 16,
+                    extranonce_prefix: vec![0, 1].try_into().unwrap(),
+                },
+            ));
+            let value = json_round_trip(message);
+            assert_eq!(
+                value["Mining"]["OpenExtendedMiningChannelSuccess"]["extranonce_prefix"],
+                json!("0001")
+            );
+        }
+
+        #[test]
+        fn extensions_to_json() {
+            let message = AnyMessage::ExtensionsNegotiation(
+                ExtensionsNegotiation::RequestExtensions(RequestExtensions {
+                    request_id: 1,
+                    requested_extensions: Seq064K::new(vec![2, 16]).unwrap(),
+                }),
+            );
+            json_round_trip(message);
+
+            let message = AnyMessage::Extension(ExtensionMessage {
+                extension_type: 0x0100,
+                message_type: 5,
+                channel_bit: true,
+                payload: vec![1, 2, 3],
+            });
+            assert_eq!(
+                json_round_trip(message)["Extension"]["payload"],
+                json!("010203")
+            );
+        }
+
+        #[test]
+        fn invalid_json_is_rejected() {
+            let too_long = json!({"Mining": {"SetExtranoncePrefix": {
+                "channel_id": 1,
+                "extranonce_prefix": "00".repeat(33),
+            }}});
+            assert!(serde_json::from_value::<Message>(too_long).is_err());
+        }
+    }
 }
@@ -0,0 +1,384 @@
+//! # Variable Difficulty
+//!
+//! Provides [`Vardiff`], a reusable difficulty adjustment engine that every role setting targets
+//! for downstream channels (pool, job declarator client, proxies, translator) can use.
+//!
+//! [`Vardiff`] is driven by two kind of events:
+//! - share arrivals ([`Vardiff::on_share`]), every valid share received on the channel;
+//! - timers ([`Vardiff::on_timer`]), needed to lower the difficulty of a channel that is not
+//!   sending shares anymore.
+//!
+//! Every event returns the new target for the channel if it must be changed. The engine never
+//! reads the clock, the caller always pass the current [`Instant`], so it can be driven
+//! deterministically (e.g. in simulations).
+//!
+//! On every retarget the hashrate of the channel is estimated from the shares received since the
+//! last retarget and the current target. The estimation is then smoothed with
+//! [`VardiffConfig::damping`] and converted in a target that produce
+//! [`VardiffConfig::shares_per_minute`] shares. The new target is clamped between
+//! [`VardiffConfig::min_target`] and [`VardiffConfig::max_target`], in that case the hashrate of
+//! the channel is recomputed from the clamped target.
+
+use crate::{
+    utils::{hash_rate_from_target, hash_rate_to_target},
+    Error,
+};
+use mining_sv2::Target;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// [`Vardiff`] settings.
+#[derive(Debug, Clone)]
+pub struct VardiffConfig {
+    /// Share rate that the channel should have.
+    pub shares_per_minute: f32,
+    /// Minimum time between two retargets.
+    pub retarget_interval: Duration,
+    /// Hardest target that can be set, `None` for no limit. A proxy would use the upstream target
+    /// here, so that shares valid for the upstream are never dropped by the downstream.
+    pub min_target: Option<Target>,
+    /// Easiest target that can be set, `None` for no limit.
+    pub max_target: Option<Target>,
+    /// Fraction, between 0 and 1, of the estimated hashrate change that is applied on each
+    /// retarget. `1.0` jump straight to the estimated hashrate, smaller values smooth the
+    /// estimation noise at the cost of a slower convergence.
+    pub damping: f32,
+}
+
+impl VardiffConfig {
+    pub fn new(shares_per_minute: f32) -> Self {
+        Self {
+            shares_per_minute,
+            retarget_interval: Duration::from_secs(15),
+            min_target: None,
+            max_target: None,
+            damping: 1.0,
+        }
+    }
+}
+
+/// Difficulty adjustment for a single channel. See the [module level docs](self).
+#[derive(Debug, Clone)]
+pub struct Vardiff {
+    config: VardiffConfig,
+    hashrate: f32,
+    target: Target,
+    shares_since_last_update: u32,
+    last_update: Instant,
+}
+
+impl Vardiff {
+    /// Creates a [`Vardiff`] for a channel with the given nominal `hashrate`, the initial target is
+    /// computed from it.
+    pub fn new(config: VardiffConfig, hashrate: f32, now: Instant) -> Result<Self, Error> {
+        let target: Target =
+            hash_rate_to_target(hashrate as f64, config.shares_per_minute as f64)?.into();
+        let mut self_ = Self::with_target(config, hashrate, target.clone(), now);
+        self_.set_target(target, hashrate);
+        Ok(self_)
+    }
+
+    /// Creates a [`Vardiff`] for a channel with an already assigned `target`.
+    pub fn with_target(config: VardiffConfig, hashrate: f32, target: Target, now: Instant) -> Self {
+        Self {
+            config,
+            hashrate,
+            target,
+            shares_since_last_update: 0,
+            last_update: now,
+        }
+    }
+
+    /// Current target of the channel.
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    /// Current estimated hashrate of the channel.
+    pub fn hashrate(&self) -> f32 {
+        self.hashrate
+    }
+
+    /// Configuration of the channel, with the target limits currently applied.
+    pub fn config(&self) -> &VardiffConfig {
+        &self.config
+    }
+
+    /// Updates the hardest target allowed. The new limit is applied at the next retarget.
+    pub fn set_min_target(&mut self, min_target: Option<Target>) {
+        self.config.min_target = min_target;
+    }
+
+    /// Updates the easiest target allowed. The new limit is applied at the next retarget.
+    pub fn set_max_target(&mut self, max_target: Option<Target>) {
+        self.config.max_target = max_target;
+    }
+
+    /// Counts a valid share and returns the new target if the channel must be retargeted.
+    pub fn on_share(&mut self, now: Instant) -> Option<Target> {
+        self.shares_since_last_update += 1;
+        self.try_retarget(now)
+    }
+
+    /// Returns the new target if the channel must be retargeted. Must be called periodically, so
+    /// that channels that stop sending shares are retargeted too.
+    pub fn on_timer(&mut self, now: Instant) -> Option<Target> {
+        self.try_retarget(now)
+    }
+
+    fn clamp(&self, mut target: Target) -> Target {
+        if let Some(min_target) = &self.config.min_target {
+            if &target < min_target {
+                target = min_target.clone();
+            }
+        }
+        if let Some(max_target) = &self.config.max_target {
+            if &target > max_target {
+                target = max_target.clone();
+            }
+        }
+        target
+    }
+
+    // Sets the target computed for `hashrate`, clamped between the configured limits. If the
+    // target is clamped the hashrate is recomputed from it, so that the next estimations are
+    // compared with the hashrate expected with the target actually set.
+    fn set_target(&mut self, target: Target, hashrate: f32) {
+        let clamped = self.clamp(target.clone());
+        self.hashrate = if clamped == target {
+            hashrate
+        } else {
+            hash_rate_from_target(clamped.clone().into(), self.config.shares_per_minute as f64)
+                .map(|hashrate| hashrate as f32)
+                .unwrap_or(hashrate)
+        };
+        self.target = clamped;
+    }
+
+    // Estimates the hashrate of the channel since the last retarget.
+    fn estimate_hashrate(&self, delta_time: Duration) -> f32 {
+        let delta_secs = delta_time.as_secs();
+        if self.shares_since_last_update == 0 {
+            // Nothing received, the target is likely too hard for the channel
+            return match delta_secs {
+                dt if dt <= 30 => self.hashrate / 1.5,
+                dt if dt < 60 => self.hashrate / 2.0,
+                _ => self.hashrate / 3.0,
+            };
+        }
+        let realized_share_per_min =
+            self.shares_since_last_update as f64 / (delta_time.as_secs_f64() / 60.0);
+        match hash_rate_from_target(self.target.clone().into(), realized_share_per_min) {
+            Ok(hashrate) => hashrate as f32,
+            Err(_) => self.hashrate * realized_share_per_min as f32 / self.config.shares_per_minute,
+        }
+    }
+
+    fn try_retarget(&mut self, now: Instant) -> Option<Target> {
+        let delta_time = now.saturating_duration_since(self.last_update);
+        if delta_time < self.config.retarget_interval {
+            return None;
+        }
+        let delta_secs = delta_time.as_secs();
+        let estimated_hashrate = self.estimate_hashrate(delta_time);
+        let delta_percentage = ((estimated_hashrate - self.hashrate).abs() / self.hashrate) * 100.0;
+        debug!(
+            "Vardiff: shares since last update {}, estimated hashrate {}, delta {}%",
+            self.shares_since_last_update, estimated_hashrate, delta_percentage
+        );
+        // The longer the window the more precise is the estimation, so smaller drifts are enough
+        // to retarget.
+        let should_retarget = delta_percentage >= 100.0
+            || (delta_percentage >= 60.0 && delta_secs >= 60)
+            || (delta_percentage >= 50.0 && delta_secs >= 120)
+            || (delta_percentage >= 45.0 && delta_secs >= 180)
+            || (delta_percentage >= 30.0 && delta_secs >= 240)
+            || (delta_percentage >= 15.0 && delta_secs >= 300);
+        if !should_retarget {
+            return None;
+        }
+        let damping = self.config.damping.clamp(0.0, 1.0);
+        let new_hashrate = self.hashrate + (estimated_hashrate - self.hashrate) * damping;
+        let new_target =
+            match hash_rate_to_target(new_hashrate as f64, self.config.shares_per_minute as f64) {
+                Ok(target) => target.into(),
+                Err(e) => {
+                    debug!("Vardiff: impossible to calculate new target: {:?}", e);
+                    return None;
+                }
+            };
+        self.shares_since_last_update = 0;
+        self.last_update = now;
+        let old_target = self.target.clone();
+        self.set_target(new_target, new_hashrate);
+        (self.target != old_target).then(|| self.target.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use binary_sv2::U256;
+
+    const SHARES_PER_MINUTE: f32 = 10.0;
+    const TICK: Duration = Duration::from_secs(15);
+
+    fn target_for(hashrate: f32) -> Target {
+        hash_rate_to_target(hashrate as f64, SHARES_PER_MINUTE as f64)
+            .unwrap()
+            .into()
+    }
+
+    // Deterministic miner: every tick it produces exactly the expected number of shares for its
+    // hashrate and the current target, the fractional part is carried to the next tick.
+    struct SimulatedMiner {
+        hashrate: f64,
+        carry: f64,
+    }
+
+    impl SimulatedMiner {
+        fn new(hashrate: f64) -> Self {
+            Self {
+                hashrate,
+                carry: 0.0,
+            }
+        }
+
+        fn shares_in(&mut self, target: &Target, time: Duration) -> u32 {
+            // expected hashes for a share are 2^256 / (target + 1)
+            let target: U256<'static> = target.clone().into();
+            let target = target
+                .inner_as_ref()
+                .iter()
+                .rev()
+                .fold(0.0, |acc, byte| acc * 256.0 + *byte as f64);
+            let hashes_per_share = 2_f64.powi(256) / (target + 1.0);
+            let shares = self.hashrate * time.as_secs_f64() / hashes_per_share + self.carry;
+            self.carry = shares.fract();
+            shares.trunc() as u32
+        }
+    }
+
+    // Runs the simulation for `ticks` ticks, returns the number of retargets and the time at the
+    // end of the simulation.
+    fn simulate(
+        vardiff: &mut Vardiff,
+        miner: &mut SimulatedMiner,
+        start: Instant,
+        ticks: u32,
+    ) -> (u32, Instant) {
+        let mut now = start;
+        let mut retargets = 0;
+        for _ in 0..ticks {
+            let shares = miner.shares_in(vardiff.target(), TICK);
+            // Shares are evenly spread over the tick
+            for i in 1..=shares {
+                if vardiff.on_share(now + TICK * i / shares).is_some() {
+                    retargets += 1;
+                }
+            }
+            now += TICK;
+            if vardiff.on_timer(now).is_some() {
+                retargets += 1;
+            }
+        }
+        (retargets, now)
+    }
+
+    fn relative_error(actual: f32, expected: f64) -> f64 {
+        ((actual as f64 - expected) / expected).abs()
+    }
+
+    #[test]
+    fn converge_to_real_hashrate_when_underestimated() {
+        let start = Instant::now();
+        let mut vardiff =
+            Vardiff::new(VardiffConfig::new(SHARES_PER_MINUTE), 1_000.0, start).unwrap();
+        let mut miner = SimulatedMiner::new(1_000_000.0);
+        simulate(&mut vardiff, &mut miner, start, 4 * 30);
+        assert!(relative_error(vardiff.hashrate(), miner.hashrate) < 0.2);
+    }
+
+    #[test]
+    fn converge_to_real_hashrate_when_overestimated() {
+        let start = Instant::now();
+        let mut vardiff =
+            Vardiff::new(VardiffConfig::new(SHARES_PER_MINUTE), 1_000_000.0, start).unwrap();
+        let mut miner = SimulatedMiner::new(1_000.0);
+        simulate(&mut vardiff, &mut miner, start, 4 * 30);
+        assert!(relative_error(vardiff.hashrate(), miner.hashrate) < 0.2);
+    }
+
+    #[test]
+    fn stable_hashrate_do_not_retarget() {
+        let start = Instant::now();
+        let mut vardiff =
+            Vardiff::new(VardiffConfig::new(SHARES_PER_MINUTE), 1_000_000.0, start).unwrap();
+        let mut miner = SimulatedMiner::new(1_000_000.0);
+        let (retargets, _) = simulate(&mut vardiff, &mut miner, start, 4 * 30);
+        assert_eq!(retargets, 0);
+    }
+
+    #[test]
+    fn respect_retarget_interval() {
+        let start = Instant::now();
+        let mut config = VardiffConfig::new(SHARES_PER_MINUTE);
+        config.retarget_interval = Duration::from_secs(60);
+        let mut vardiff = Vardiff::new(config, 1_000.0, start).unwrap();
+        let mut miner = SimulatedMiner::new(1_000_000.0);
+        let (retargets, _) = simulate(&mut vardiff, &mut miner, start, 3);
+        assert_eq!(retargets, 0);
+        let (retargets, _) = simulate(&mut vardiff, &mut miner, start + TICK * 3, 1);
+        assert_eq!(retargets, 1);
+    }
+
+    #[test]
+    fn damping_slow_down_convergence() {
+        let start = Instant::now();
+        let mut config = VardiffConfig::new(SHARES_PER_MINUTE);
+        let mut undamped = Vardiff::new(config.clone(), 1_000.0, start).unwrap();
+        config.damping = 0.5;
+        let mut damped = Vardiff::new(config, 1_000.0, start).unwrap();
+        let real_hashrate = 1_000_000.0;
+        simulate(
+            &mut undamped,
+            &mut SimulatedMiner::new(real_hashrate),
+            start,
+            1,
+        );
+        simulate(
+            &mut damped,
+            &mut SimulatedMiner::new(real_hashrate),
+            start,
+            1,
+        );
+        assert!(damped.hashrate() < undamped.hashrate());
+        assert!(damped.hashrate() > 1_000.0);
+    }
+
+    #[test]
+    fn target_is_clamped() {
+        let start = Instant::now();
+        let min_target = target_for(10_000.0);
+        let max_target = target_for(100.0);
+        let mut config = VardiffConfig::new(SHARES_PER_MINUTE);
+        config.min_target = Some(min_target.clone());
+        config.max_target = Some(max_target.clone());
+
+        let mut vardiff = Vardiff::new(config.clone(), 1_000.0, start).unwrap();
+        simulate(
+            &mut vardiff,
+            &mut SimulatedMiner::new(1_000_000.0),
+            start,
+            4 * 10,
+        );
+        assert_eq!(vardiff.target(), &min_target);
+        // The hashrate follows the clamped target
+        assert!((vardiff.hashrate() - 10_000.0).abs() < 100.0);
+
+        let mut vardiff = Vardiff::new(config, 1_000.0, start).unwrap();
+        simulate(&mut vardiff, &mut SimulatedMiner::new(1.0), start, 4 * 10);
+        assert_eq!(vardiff.target(), &max_target);
+        assert!((vardiff.hashrate() - 100.0).abs() < 1.0);
+    }
+}
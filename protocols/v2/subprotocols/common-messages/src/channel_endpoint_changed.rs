@@ -12,6 +12,7 @@ use core::convert::TryInto;
 /// support) must be reset and renegotiated.
 #[repr(C)]
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChannelEndpointChanged {
     /// Unique identifier of the channel that has changed its endpoint.
     pub channel_id: u32,
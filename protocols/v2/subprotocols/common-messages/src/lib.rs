@@ -6,6 +6,7 @@
 //! This crate can be built with the following features:
 //! - `std`: Enables support for standard library features.
 //! - `quickcheck`: Enables support for property-based testing using QuickCheck.
+//! - `serde`: Implements `serde::Serialize` and `serde::Deserialize` for the messages.
 //!
 //!
 //! For further information about the messages, please refer to [Stratum V2
@@ -16,6 +17,7 @@
 extern crate alloc;
 mod channel_endpoint_changed;
 mod reconnect;
+mod request_extensions;
 mod setup_connection;
 
 #[cfg(feature = "prop_test")]
@@ -27,6 +29,7 @@ use quickcheck::{Arbitrary, Gen};
 
 pub use channel_endpoint_changed::ChannelEndpointChanged;
 pub use reconnect::Reconnect;
+pub use request_extensions::{RequestExtensions, RequestExtensionsError, RequestExtensionsSuccess};
 pub use setup_connection::{
     has_requires_std_job, has_version_rolling, has_work_selection, Protocol, SetupConnection,
     SetupConnectionError, SetupConnectionSuccess,
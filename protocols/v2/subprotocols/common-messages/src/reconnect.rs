@@ -13,8 +13,10 @@ use core::convert::TryInto;
 /// not be able to redirect hashrate to an arbitrary server in case the pool server get compromised
 /// and instructed to send reconnects to a new location.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Reconnect<'decoder> {
     /// When empty, downstream node should attempt to reconnect to current pool host.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub new_host: Str0255<'decoder>,
     /// When 0, downstream node should attempt to reconnect to current pool host.
     pub new_port: u16,
@@ -0,0 +1,41 @@
+use alloc::vec::Vec;
+use binary_sv2::{binary_codec_sv2, Deserialize, Seq064K, Serialize};
+use core::convert::TryInto;
+
+/// Message used by a downstream to request the support of a set of protocol extensions.
+///
+/// It is sent right after a successful `SetupConnection`, with the frame `extension_type` set to
+/// `EXTENSION_TYPE_EXTENSIONS_NEGOTIATION`. Until the upstream replies with
+/// [`RequestExtensionsSuccess`], the downstream must not send messages of the requested
+/// extensions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RequestExtensions<'decoder> {
+    /// Identifier of the request, echoed in the response.
+    pub request_id: u16,
+    /// Extension types that the downstream wants to use.
+    pub requested_extensions: Seq064K<'decoder, u16>,
+}
+
+/// Message used by an upstream to accept a [`RequestExtensions`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RequestExtensionsSuccess<'decoder> {
+    /// Identifier of the [`RequestExtensions`] this message reply to.
+    pub request_id: u16,
+    /// Requested extension types that the upstream supports, only these can be used on the
+    /// connection.
+    pub supported_extensions: Seq064K<'decoder, u16>,
+}
+
+/// Message used by an upstream to reject a [`RequestExtensions`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RequestExtensionsError<'decoder> {
+    /// Identifier of the [`RequestExtensions`] this message reply to.
+    pub request_id: u16,
+    /// Requested extension types that the upstream does not support.
+    pub unsupported_extensions: Seq064K<'decoder, u16>,
+    /// Extension types that the upstream requires but that have not been requested.
+    pub required_extensions: Seq064K<'decoder, u16>,
+}
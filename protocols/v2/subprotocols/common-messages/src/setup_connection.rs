@@ -25,6 +25,7 @@ use core::convert::{TryFrom, TryInto};
 /// A valid response to this message from the upstream role can either be [`SetupConnectionSuccess`]
 /// or [`SetupConnectionError`] message.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetupConnection<'decoder> {
     /// Protocol to be used for the connection.
     pub protocol: Protocol,
@@ -41,16 +42,21 @@ pub struct SetupConnection<'decoder> {
     /// Each [`SetupConnection::protocol`] value has it's own flags.
     pub flags: u32,
     /// ASCII representation of the connection hostname or IP address.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub endpoint_host: Str0255<'decoder>,
     /// Connection port value.
     pub endpoint_port: u16,
     /// Device vendor name.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub vendor: Str0255<'decoder>,
     /// Device hardware version.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub hardware_version: Str0255<'decoder>,
     /// Device firmware version.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub firmware: Str0255<'decoder>,
     /// Device identifier.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub device_id: Str0255<'decoder>,
 }
 
@@ -267,6 +273,7 @@ impl From<SetupConnection<'_>> for CSetupConnection {
 ///
 /// This message is sent in response to a [`SetupConnection`] message.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct SetupConnectionSuccess {
     /// Selected version based on the [`SetupConnection::min_version`] and
@@ -296,6 +303,7 @@ pub struct SetupConnectionSuccess {
 /// [`SetupConnectionError`] message and must consistently support the same set of flags across all
 /// servers on the same hostname and port number.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetupConnectionError<'decoder> {
     /// Unsupported feature flags.
     ///
@@ -308,6 +316,7 @@ pub struct SetupConnectionError<'decoder> {
     /// - unsupported-feature-flags
     /// - unsupported-protocol
     /// - protocol-version-mismatch
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub error_code: Str0255<'decoder>,
 }
 
@@ -354,6 +363,7 @@ impl<'a> From<SetupConnectionError<'a>> for CSetupConnectionError {
 
 /// This enum has a list of the different Stratum V2 subprotocols.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 #[allow(clippy::enum_variant_names)]
 pub enum Protocol {
@@ -4,11 +4,13 @@ use core::convert::TryInto;
 
 /// Message used by JDC to request an identifier for a future mining job from JDS.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct AllocateMiningJobToken<'decoder> {
     /// Unconstrained sequence of bytes. Whatever is needed by the JDS to
     /// identify/authenticate the client. Additional restrictions can be imposed by the
     /// JDS. It is highly recommended that UTF-8 encoding is used.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub user_identifier: Str0255<'decoder>,
     /// A unique identifier for pairing the response/request.
     pub request_id: u32,
@@ -16,6 +18,7 @@ pub struct AllocateMiningJobToken<'decoder> {
 
 /// Message used by JDS to accept [`AllocateMiningJobToken`] message.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct AllocateMiningJobTokenSuccess<'decoder> {
     /// A unique identifier for pairing the response/request.
@@ -7,6 +7,7 @@ use core::convert::TryInto;
 /// Message used by JDC to proposes a selected set of transactions to JDS they wish to
 /// mine on.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct DeclareMiningJob<'decoder> {
     /// A unique identifier for this request.
@@ -48,6 +49,7 @@ pub struct DeclareMiningJob<'decoder> {
 
 /// Messaged used by JDS to accept [`DeclareMiningJob`] message.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct DeclareMiningJobSuccess<'decoder> {
     /// A unique identifier for this request.
@@ -66,6 +68,7 @@ pub struct DeclareMiningJobSuccess<'decoder> {
 /// Downstream should consider this as a trigger to fallback into some other Pool/JDS or solo
 /// mining.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct DeclareMiningJobError<'decoder> {
     /// The unique identifier of the request.
@@ -76,6 +79,7 @@ pub struct DeclareMiningJobError<'decoder> {
     ///
     /// - invalid-mining-job-token
     /// - invalid-job-param-value-{DeclareMiningJob::field}
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub error_code: Str0255<'decoder>,
     /// Optional details about the error.
     pub error_details: B064K<'decoder>,
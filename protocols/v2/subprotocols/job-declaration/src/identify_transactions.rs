@@ -6,6 +6,7 @@ use core::convert::TryInto;
 /// detected a collision in the [`crate::DeclareMiningJob::tx_short_hash_list`], or was unable to
 /// reconstruct the [`crate::DeclareMiningJob::tx_hash_list_hash`].
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct IdentifyTransactions {
     /// Mining job unique identifier.
@@ -17,6 +18,7 @@ pub struct IdentifyTransactions {
 /// Messaged used by JDC to accept [`IdentifyTransactions`] message and provide the full set
 /// of transaction data hashes.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct IdentifyTransactionsSuccess<'decoder> {
     /// Unique identifier.
@@ -7,6 +7,7 @@
 //! ## Build Options
 //! This crate can be built with the following features:
 //! - `std`: Enables support for standard library features.
+//! - `serde`: Implements `serde::Serialize` and `serde::Deserialize` for the messages.
 //!
 //! For further information about the messages, please refer to [Stratum V2 documentation - Job
 //! Declaration](https://stratumprotocol.org/specification/06-Job-Declaration-Protocol/).
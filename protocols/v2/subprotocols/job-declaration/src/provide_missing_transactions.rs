@@ -12,6 +12,7 @@ use core::convert::TryInto;
 /// for them. They are specified by their position in the original DeclareMiningJob message,
 /// 0-indexed not including the coinbase transaction transaction.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct ProvideMissingTransactions<'decoder> {
     /// Unique Identifier.
@@ -27,6 +28,7 @@ pub struct ProvideMissingTransactions<'decoder> {
 /// Message used by JDC to accept [`ProvideMissingTransactions`] message and provide the full
 /// list of transactions in the order they were requested by [`ProvideMissingTransactions`].
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct ProvideMissingTransactionsSuccess<'decoder> {
     /// Unique Identifier.
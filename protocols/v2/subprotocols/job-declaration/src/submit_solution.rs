@@ -10,6 +10,7 @@ use core::convert::TryInto;
 /// Note that JDC is also expected to share the new block data through `SubmitSolution`
 /// message under the Template Distribution Protocol.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct SubmitSolutionJd<'decoder> {
     /// Full extranonce that forms a valid submission.
@@ -9,9 +9,11 @@ use core::convert::TryInto;
 ///
 /// Upon receiving this message, upstream **must** stop sending messages for the channel.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CloseChannel<'decoder> {
     /// Channel id of the channel to be closed.
     pub channel_id: u32,
     /// Reason for closing the channel.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub reason_code: Str0255<'decoder>,
 }
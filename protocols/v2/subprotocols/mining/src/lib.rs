@@ -11,6 +11,7 @@
 //! ## Build Options
 //!
 //! This crate can be built with the following features:
+//! - `serde`: Implements `serde::Serialize` and `serde::Deserialize` for the messages.
 //!
 //! ## Usage
 //!
@@ -42,6 +43,7 @@ mod set_group_channel;
 mod set_new_prev_hash;
 mod set_target;
 mod submit_shares;
+mod tlv;
 mod update_channel;
 
 pub use close_channel::CloseChannel;
@@ -61,6 +63,7 @@ pub use set_target::SetTarget;
 pub use submit_shares::{
     SubmitSharesError, SubmitSharesExtended, SubmitSharesStandard, SubmitSharesSuccess,
 };
+pub use tlv::{user_identity, Tlv, MAX_USER_IDENTITY_LEN};
 pub use update_channel::{UpdateChannel, UpdateChannelError};
 const MAX_EXTRANONCE_LEN: usize = 32;
 
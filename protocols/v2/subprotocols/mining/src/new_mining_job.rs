@@ -10,6 +10,7 @@ use core::convert::TryInto;
 /// and the only rollable bits are `version`, `nonce`, and `nTime` fields of the block header.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NewMiningJob<'decoder> {
     /// Channel identifier for the channel that this job is valid for.
     ///
@@ -65,6 +66,7 @@ impl NewMiningJob<'_> {
 /// that they can implement various advanced use cases such as: translation between Stratum V1 and
 /// V2 protocols, difficulty aggregation and search space splitting.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NewExtendedMiningJob<'decoder> {
     /// Identifier of the Extended Mining Channel that this job is valid for.
     ///
@@ -7,12 +7,17 @@ use core::convert::TryInto;
 /// connection within a reasonable period, otherwise the upstream should close the connection for
 /// inactivity.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OpenStandardMiningChannel<'decoder> {
     /// Specified by downstream role.
     ///
     /// Used for matching responses from upstream.
     ///
     /// The value must be connection-wide unique and is not interpreted by the upstream.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "binary_sv2::serde_fields::u32_as_ref")
+    )]
     pub request_id: U32AsRef<'decoder>,
     /// Unconstrained sequence of bytes.
     ///
@@ -21,6 +26,7 @@ pub struct OpenStandardMiningChannel<'decoder> {
     ///
     /// Additional restrictions can be imposed by the upstream role (e.g. a pool). It is highly
     /// recommended to use UTF-8 encoding.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub user_identity: Str0255<'decoder>,
     /// Expected hash rate of the device (or cumulative hashrate on the channel if multiple devices
     /// are connected downstream) in h/s.
@@ -54,11 +60,16 @@ impl OpenStandardMiningChannel<'_> {
 /// Message used by upstream to accept [`OpenStandardMiningChannel`] request from downstream.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OpenStandardMiningChannelSuccess<'decoder> {
     /// Used for matching requests/responses.
     ///
     /// Specified by downstream role and should be extracted from the corresponding
     /// [`OpenStandardMiningChannel`] message.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "binary_sv2::serde_fields::u32_as_ref")
+    )]
     pub request_id: U32AsRef<'decoder>,
     /// Newly assigned identifier of the channel, stable for the whole lifetime of the connection.
     ///
@@ -97,6 +108,7 @@ impl OpenStandardMiningChannelSuccess<'_> {
 /// by the upstream role based on the [`OpenExtendedMiningChannel::min_extranonce_size`] requested
 /// by the downstream.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OpenExtendedMiningChannel<'decoder> {
     /// Specified by downstream role.
     ///
@@ -111,6 +123,7 @@ pub struct OpenExtendedMiningChannel<'decoder> {
     ///
     /// Additional restrictions can be imposed by the upstream role (e.g. a pool). It is highly
     /// recommended to use UTF-8 encoding.
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub user_identity: Str0255<'decoder>,
     /// Expected hash rate of the device (or cumulative hashrate on the channel if multiple devices
     /// are connected downstream) in h/s.
@@ -136,6 +149,7 @@ impl OpenExtendedMiningChannel<'_> {
 
 /// Message used by upstream to accept [`OpenExtendedMiningChannel` request from downstream.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OpenExtendedMiningChannelSuccess<'decoder> {
     /// Used for matching requests/responses.
     ///
@@ -157,6 +171,7 @@ pub struct OpenExtendedMiningChannelSuccess<'decoder> {
 /// Message used by upstream to reject [`OpenExtendedMiningChannel`] or
 /// [`OpenStandardMiningchannel`] request from downstream.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OpenMiningChannelError<'decoder> {
     /// Used for matching requests/responses.
     ///
@@ -169,6 +184,7 @@ pub struct OpenMiningChannelError<'decoder> {
     ///
     /// - ‘unknown-user’
     /// - ‘max-target-out-of-range’
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub error_code: Str0255<'decoder>,
 }
 
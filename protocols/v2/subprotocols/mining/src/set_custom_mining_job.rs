@@ -13,6 +13,7 @@ use core::convert::TryInto;
 /// Previously exchanged `SetupConnection::flags` must contain `REQUIRES_WORK_SELECTION` flag i.e.,
 /// work selection feature was successfully negotiated.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetCustomMiningJob<'decoder> {
     /// Extended mining channel identifier.
     pub channel_id: u32,
@@ -62,6 +63,7 @@ pub struct SetCustomMiningJob<'decoder> {
 /// Upon receiving this message, downstream can start submitting shares for this job immediately (by
 /// using the [`SetCustomMiningJobSuccess::job_id`] provided within this response).
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetCustomMiningJobSuccess {
     /// Extended mining channel identifier.
     pub channel_id: u32,
@@ -73,6 +75,7 @@ pub struct SetCustomMiningJobSuccess {
 
 /// Message used by upstream to reject [`SetCustomMiningJob`] request.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetCustomMiningJobError<'decoder> {
     /// Extended mining channel identifier.
     pub channel_id: u32,
@@ -84,5 +87,6 @@ pub struct SetCustomMiningJobError<'decoder> {
     /// - invalid-channel-id
     /// - invalid-mining-job-token
     /// - invalid-job-param-value-{field_name}
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub error_code: Str0255<'decoder>,
 }
@@ -12,6 +12,7 @@ use core::convert::TryInto;
 /// Note that this message is applicable only for opened Standard or Extended Channels, not Group
 /// Channels.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetExtranoncePrefix<'decoder> {
     /// Extended or Standard Channel identifier.
     pub channel_id: u32,
@@ -18,6 +18,7 @@ use core::convert::TryInto;
 /// This message can be sent only to connections that didnt set `REQUIRES_STANDARD_JOBS` flag in
 /// `SetupConnection` message.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetGroupChannel<'decoder> {
     /// Identifier of the group where the standard channel belongs.
     pub group_channel_id: u32,
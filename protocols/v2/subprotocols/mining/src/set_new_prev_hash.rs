@@ -11,6 +11,7 @@ use core::convert::TryInto;
 /// When a downstream receives this message, only the job referenced by [`SetNewPrevHash::job_id`]
 /// is valid. Remaining jobs have to be dropped.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetNewPrevHash<'decoder> {
     /// Group channel or channel that this prevhash is valid for.
     pub channel_id: u32,
@@ -15,6 +15,7 @@ use core::convert::TryInto;
 /// When this message is sent to a group channel, the maximum target is applicable to all channels
 /// in the group.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetTarget<'decoder> {
     /// Channel identifier.
     pub channel_id: u32,
@@ -4,6 +4,7 @@ use core::convert::TryInto;
 
 /// Message used by downstream to send result of its hashing work to an upstream.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubmitSharesStandard {
     /// Channel identification.
     pub channel_id: u32,
@@ -33,6 +34,7 @@ pub struct SubmitSharesStandard {
 ///
 /// Only relevant for Extended Channels.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubmitSharesExtended<'decoder> {
     /// Channel identification.
     pub channel_id: u32,
@@ -71,6 +73,7 @@ pub struct SubmitSharesExtended<'decoder> {
 /// actually increasing. It can use the last one received when sending a response. It is the
 /// downstream’s responsibility to keep the sequence numbers correct/useful.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubmitSharesSuccess {
     /// Channel identifier.
     pub channel_id: u32,
@@ -88,6 +91,7 @@ pub struct SubmitSharesSuccess {
 /// soon as the result is known. This delayed validation can occur when a miner gets faster
 /// updates about a new `prevhash` than the upstream does.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubmitSharesError<'decoder> {
     /// Channel identification.
     pub channel_id: u32,
@@ -101,6 +105,7 @@ pub struct SubmitSharesError<'decoder> {
     /// - stale-share
     /// - difficulty-too-low
     /// - invalid-job-id
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub error_code: Str0255<'decoder>,
 }
 
@@ -0,0 +1,174 @@
+use alloc::vec::Vec;
+use binary_sv2::GetSize;
+use const_sv2::{EXTENSION_TYPE_WORKER_HASHRATE_TRACKING, TLV_FIELD_TYPE_USER_IDENTITY};
+
+use crate::SubmitSharesExtended;
+
+/// Maximum length in bytes of the `user_identity` TLV field.
+pub const MAX_USER_IDENTITY_LEN: usize = 32;
+
+/// Size of the type (`extension_type` + `field_type`) and length of a [`Tlv`].
+const TLV_HEADER_SIZE: usize = 5;
+
+/// Type-Length-Value field appended by a protocol extension to a message.
+///
+/// TLV fields are serialized after the last field of the message: `extension_type` (`U16`),
+/// `field_type` (`U8`), length of the value (`U16`) and the value. Parsers that do not know about
+/// the extension ignore them, so they must only be sent once the extension has been negotiated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tlv {
+    /// Extension that defines the field.
+    pub extension_type: u16,
+    /// Type of the field within the extension.
+    pub field_type: u8,
+    /// Value of the field.
+    pub value: Vec<u8>,
+}
+
+impl Tlv {
+    /// Builds a new [`Tlv`], returns `None` if `value` is longer than `u16::MAX` bytes.
+    pub fn new(extension_type: u16, field_type: u8, value: Vec<u8>) -> Option<Self> {
+        if value.len() > u16::MAX as usize {
+            return None;
+        }
+        Some(Self {
+            extension_type,
+            field_type,
+            value,
+        })
+    }
+
+    /// Builds the `user_identity` field of the worker-specific hashrate tracking extension,
+    /// returns `None` if `user_identity` is longer than [`MAX_USER_IDENTITY_LEN`] bytes.
+    pub fn user_identity(user_identity: &str) -> Option<Self> {
+        if user_identity.len() > MAX_USER_IDENTITY_LEN {
+            return None;
+        }
+        Self::new(
+            EXTENSION_TYPE_WORKER_HASHRATE_TRACKING,
+            TLV_FIELD_TYPE_USER_IDENTITY,
+            user_identity.as_bytes().to_vec(),
+        )
+    }
+
+    /// Returns the value of the field if it is a valid `user_identity` field.
+    pub fn as_user_identity(&self) -> Option<&str> {
+        if self.extension_type != EXTENSION_TYPE_WORKER_HASHRATE_TRACKING
+            || self.field_type != TLV_FIELD_TYPE_USER_IDENTITY
+            || self.value.len() > MAX_USER_IDENTITY_LEN
+        {
+            return None;
+        }
+        core::str::from_utf8(&self.value).ok()
+    }
+
+    /// Size of the serialized field.
+    pub fn encoded_length(&self) -> usize {
+        TLV_HEADER_SIZE + self.value.len()
+    }
+
+    /// Appends the serialized field to `dst`.
+    pub fn write(&self, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(&self.extension_type.to_le_bytes());
+        dst.push(self.field_type);
+        dst.extend_from_slice(&(self.value.len() as u16).to_le_bytes());
+        dst.extend_from_slice(&self.value);
+    }
+
+    /// Parses a sequence of serialized fields, returns `None` if `bytes` do not contain only
+    /// complete fields.
+    pub fn decode_all(mut bytes: &[u8]) -> Option<Vec<Self>> {
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            if bytes.len() < TLV_HEADER_SIZE {
+                return None;
+            }
+            let extension_type = u16::from_le_bytes([bytes[0], bytes[1]]);
+            let field_type = bytes[2];
+            let len = u16::from_le_bytes([bytes[3], bytes[4]]) as usize;
+            let value = bytes.get(TLV_HEADER_SIZE..TLV_HEADER_SIZE + len)?.to_vec();
+            fields.push(Self {
+                extension_type,
+                field_type,
+                value,
+            });
+            bytes = &bytes[TLV_HEADER_SIZE + len..];
+        }
+        Some(fields)
+    }
+}
+
+/// Returns the first valid `user_identity` in `fields`.
+pub fn user_identity(fields: &[Tlv]) -> Option<&str> {
+    fields.iter().find_map(|field| field.as_user_identity())
+}
+
+impl SubmitSharesExtended<'_> {
+    /// Parses the TLV fields that follow this message in `payload`, the serialized message it has
+    /// been decoded from. Returns `None` if the trailing bytes are not valid TLV fields.
+    pub fn tlvs(&self, payload: &[u8]) -> Option<Vec<Tlv>> {
+        Tlv::decode_all(payload.get(self.get_size()..)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binary_sv2::{from_bytes, to_bytes};
+    use core::convert::TryInto;
+
+    fn share() -> SubmitSharesExtended<'static> {
+        SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 2,
+            job_id: 3,
+            nonce: 4,
+            ntime: 5,
+            version: 6,
+            extranonce: vec![7; 8].try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn user_identity_is_limited_to_32_bytes() {
+        assert!(Tlv::user_identity(&"a".repeat(MAX_USER_IDENTITY_LEN)).is_some());
+        assert!(Tlv::user_identity(&"a".repeat(MAX_USER_IDENTITY_LEN + 1)).is_none());
+    }
+
+    #[test]
+    fn tlvs_appended_to_submit_shares_extended() {
+        let mut payload = to_bytes(share()).unwrap();
+        let field = Tlv::user_identity("worker.1").unwrap();
+        field.write(&mut payload);
+        assert_eq!(payload.len(), share().get_size() + field.encoded_length());
+
+        let mut bytes = payload.clone();
+        let decoded: SubmitSharesExtended = from_bytes(&mut bytes).unwrap();
+        assert_eq!(decoded.nonce, 4);
+        let fields = decoded.tlvs(&payload).unwrap();
+        assert_eq!(fields, vec![field]);
+        assert_eq!(user_identity(&fields), Some("worker.1"));
+    }
+
+    #[test]
+    fn submit_shares_extended_without_tlvs() {
+        let payload = to_bytes(share()).unwrap();
+        assert_eq!(share().tlvs(&payload), Some(vec![]));
+    }
+
+    #[test]
+    fn truncated_tlvs_are_rejected() {
+        let mut bytes = Vec::new();
+        Tlv::user_identity("worker.1").unwrap().write(&mut bytes);
+        bytes.pop();
+        assert!(Tlv::decode_all(&bytes).is_none());
+        assert!(Tlv::decode_all(&bytes[..3]).is_none());
+    }
+
+    #[test]
+    fn unknown_fields_are_not_user_identities() {
+        let field = Tlv::new(0x4242, TLV_FIELD_TYPE_USER_IDENTITY, b"worker".to_vec()).unwrap();
+        assert_eq!(field.as_user_identity(), None);
+        assert_eq!(user_identity(&[field]), None);
+    }
+}
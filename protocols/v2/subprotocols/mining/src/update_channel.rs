@@ -9,6 +9,7 @@ use core::convert::TryInto;
 ///
 /// Only relevant for Extended Channels.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UpdateChannel<'decoder> {
     /// Channel identification.
     pub channel_id: u32,
@@ -36,6 +37,7 @@ pub struct UpdateChannel<'decoder> {
 
 /// Message used by upstream to notify downstream about an error in the [`UpdateChannel`] message.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UpdateChannelError<'decoder> {
     /// Channel identification.
     pub channel_id: u32,
@@ -44,5 +46,6 @@ pub struct UpdateChannelError<'decoder> {
     /// Possible error codes:
     /// - max-target-out-of-range
     /// - invalid-channel-id
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub error_code: Str0255<'decoder>,
 }
@@ -23,6 +23,7 @@ use core::convert::TryInto;
 ///
 /// [`NewTemplate`]: crate::NewTemplate
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct CoinbaseOutputConstraints {
     /// Additional serialized bytes needed in coinbase transaction outputs.
@@ -9,6 +9,7 @@
 //! This crate can be built with the following features:
 //! - `std`: Enables support for standard library features.
 //! - `prop_test`: Enables support for property testing.
+//! - `serde`: Implements `serde::Serialize` and `serde::Deserialize` for the messages.
 //!
 //! For further information about the messages, please refer to [Stratum V2 documentation - Job
 //! Distribution](https://stratumprotocol.org/specification/07-Template-Distribution-Protocol/).
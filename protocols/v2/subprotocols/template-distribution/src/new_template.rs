@@ -8,6 +8,7 @@ use core::convert::TryInto;
 /// Message used by an upstream(Template Provider) to provide a new template for downstream to mine
 /// on.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NewTemplate<'decoder> {
     /// Upstream’s identification of the template.
     ///
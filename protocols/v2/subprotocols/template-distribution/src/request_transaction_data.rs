@@ -11,6 +11,7 @@ use core::convert::TryInto;
 ///
 /// Note that the coinbase transaction is excluded from this data.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct RequestTransactionData {
     /// Identifier of the template that the downstream node is requesting transaction data for.
@@ -53,6 +54,7 @@ pub struct RequestTransactionData {
 /// code-release to activation and there being in protocol(Template Declaration) signaling of
 /// support for the new fork (e.g. for soft-forks activated using [BIP 9]).
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RequestTransactionDataSuccess<'decoder> {
     /// The template_id corresponding to a NewTemplate/RequestTransactionData message.
     pub template_id: u64,
@@ -115,6 +117,7 @@ impl<'a> From<RequestTransactionDataSuccess<'a>> for CRequestTransactionDataSucc
 /// Message used by an upstream(Template Provider) to respond with an error to a
 /// [`RequestTransactionData`] message.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RequestTransactionDataError<'decoder> {
     /// Identifier of the template that the downstream node is requesting transaction data for.
     pub template_id: u64,
@@ -122,6 +125,7 @@ pub struct RequestTransactionDataError<'decoder> {
     ///
     /// Possible error codes:
     /// - template-id-not-found
+    #[cfg_attr(feature = "serde", serde(with = "binary_sv2::serde_fields::str0255"))]
     pub error_code: Str0255<'decoder>,
 }
 
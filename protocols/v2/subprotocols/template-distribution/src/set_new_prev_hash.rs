@@ -14,6 +14,7 @@ use core::convert::TryInto;
 /// [`crate::NewTemplate::future_template`] flag set, the [`SetNewPrevHash::template_id`] field
 /// **should** be set to the [`crate::NewTemplate::template_id`].
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetNewPrevHash<'decoder> {
     /// Identifier of the template to mine on.
     ///
@@ -14,6 +14,7 @@ use core::convert::TryInto;
 /// Upon receiving this message, upstream(Template Provider) **must** immediately construct the
 /// corresponding full block and attempt to propagate it to the Bitcoin network.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubmitSolution<'decoder> {
     /// Identifies the template to which this solution corresponds.
     ///
@@ -3,6 +3,11 @@ use std::{
     fmt::{Display, Formatter},
 };
 
+#[cfg(feature = "noise")]
+mod noise;
+#[cfg(feature = "noise")]
+pub use noise::*;
+
 use codec_sv2::{Encoder, StandardDecoder, StandardSv2Frame};
 use common_messages_sv2::{
     CSetupConnection, CSetupConnectionError, ChannelEndpointChanged, SetupConnection,
@@ -392,14 +397,13 @@ pub extern "C" fn flush_encoder(encoder: *mut EncoderWrapper) {
     let _ = Box::into_raw(encoder);
 }
 
-fn encode_(
+fn encode_frame(
     message: &'static mut CSv2Message,
-    encoder: &mut EncoderWrapper,
-) -> Result<CVec, Sv2Error> {
+) -> Result<StandardSv2Frame<Sv2Message<'static>>, Sv2Error> {
     let message: Sv2Message = message.to_rust_rep_mut()?;
     let m_type = message.message_type();
     let c_bit = message.channel_bit();
-    let frame = StandardSv2Frame::<Sv2Message<'static>>::from_message(
+    StandardSv2Frame::<Sv2Message<'static>>::from_message(
         message.clone(),
         m_type,
         EXTENSION_TYPE_NO_EXTENSION,
@@ -407,7 +411,14 @@ fn encode_(
     )
     .ok_or(Sv2Error::PayloadTooBig(
         format!("{}", message).as_bytes().into(),
-    ))?;
+    ))
+}
+
+fn encode_(
+    message: &'static mut CSv2Message,
+    encoder: &mut EncoderWrapper,
+) -> Result<CVec, Sv2Error> {
+    let frame = encode_frame(message)?;
     encoder
         .encoder
         .encode(frame)
@@ -465,20 +476,8 @@ pub extern "C" fn next_frame(decoder: *mut DecoderWrapper) -> CResult<CSv2Messag
 
     match decoder.0.next_frame() {
         Ok(mut f) => {
-            let msg_type = match f.get_header() {
-                Some(header) => header.msg_type(),
-                None => return CResult::Err(Sv2Error::InvalidSv2Frame),
-            };
-            let payload = f.payload();
-            let len = payload.len();
-            let ptr = payload.as_mut_ptr();
-            let payload = unsafe { std::slice::from_raw_parts_mut(ptr, len) };
             let _ = Box::into_raw(decoder);
-            (msg_type, payload)
-                .try_into()
-                .map(|x: Sv2Message| x.into())
-                .map_err(|_| Sv2Error::Unknown)
-                .into()
+            to_c_message(&mut f)
         }
         Err(_) => {
             let _ = Box::into_raw(decoder);
@@ -486,6 +485,21 @@ pub extern "C" fn next_frame(decoder: *mut DecoderWrapper) -> CResult<CSv2Messag
         }
     }
 }
+
+fn to_c_message(
+    frame: &mut StandardSv2Frame<Sv2Message<'static>>,
+) -> CResult<CSv2Message, Sv2Error> {
+    let msg_type = match frame.get_header() {
+        Some(header) => header.msg_type(),
+        None => return CResult::Err(Sv2Error::InvalidSv2Frame),
+    };
+    (msg_type, frame.payload())
+        .try_into()
+        .map(|x: Sv2Message| x.into())
+        .map_err(|_| Sv2Error::Unknown)
+        .into()
+}
+
 #[cfg(test)]
 #[cfg(feature = "prop_test")]
 mod tests {
@@ -0,0 +1,393 @@
+//! C ABI of the Noise handshake and of the encrypted Sv2 codec.
+//!
+//! The handshake messages are raw bytes, with no Sv2 header: the initiator writes
+//! `RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE` bytes with [`initiator_step_0`], the responder
+//! reads them and writes `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` bytes with
+//! [`responder_step_1`], and the initiator completes the handshake with [`initiator_step_2`].
+//! Both sides then own a [`NoiseCodecWrapper`] used to encrypt and decrypt Sv2 messages.
+
+use crate::{encode_frame, to_c_message, CResult, CSv2Message, Sv2Error, Sv2Message};
+use binary_sv2::binary_codec_sv2::CVec;
+use codec_sv2::{Frame, Initiator, NoiseEncoder, Responder, StandardNoiseDecoder, State};
+use const_sv2::{
+    INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE, RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use std::{convert::TryInto, ffi::CStr, os::raw::c_char, time::Duration};
+
+pub struct InitiatorWrapper(Box<Initiator>);
+
+pub struct ResponderWrapper(Box<Responder>);
+
+pub struct NoiseCodecWrapper {
+    state: State,
+    encoder: NoiseEncoder<Sv2Message<'static>>,
+    decoder: StandardNoiseDecoder<Sv2Message<'static>>,
+    encoded: Vec<u8>,
+    free: bool,
+}
+
+impl NoiseCodecWrapper {
+    fn new(state: State) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            state,
+            encoder: NoiseEncoder::new(),
+            decoder: StandardNoiseDecoder::new(),
+            encoded: Vec::new(),
+            free: true,
+        }))
+    }
+}
+
+/// Parses a base58check encoded authority public key, as found in the roles config files, and
+/// writes its 32 bytes in `out`. Returns false if the key is not valid.
+///
+/// # Safety
+/// `key` must be a null terminated string and `out` must point to 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn parse_authority_public_key(key: *const c_char, out: *mut u8) -> bool {
+    match CStr::from_ptr(key)
+        .to_str()
+        .map(str::parse::<Secp256k1PublicKey>)
+    {
+        Ok(Ok(key)) => {
+            std::slice::from_raw_parts_mut(out, 32).copy_from_slice(&key.into_bytes());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Parses a base58check encoded authority secret key, as found in the roles config files, and
+/// writes its 32 bytes in `out`. Returns false if the key is not valid.
+///
+/// # Safety
+/// `key` must be a null terminated string and `out` must point to 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn parse_authority_secret_key(key: *const c_char, out: *mut u8) -> bool {
+    match CStr::from_ptr(key)
+        .to_str()
+        .map(str::parse::<Secp256k1SecretKey>)
+    {
+        Ok(Ok(key)) => {
+            std::slice::from_raw_parts_mut(out, 32).copy_from_slice(&key.into_bytes());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Creates the initiator (downstream) side of a handshake. The responder certificate is verified
+/// against the 32 bytes `authority_public_key`, or not verified at all if it is null. Returns null
+/// if the key is not valid.
+///
+/// # Safety
+/// `authority_public_key` must be null or point to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn new_initiator(authority_public_key: *const u8) -> *mut InitiatorWrapper {
+    let initiator = if authority_public_key.is_null() {
+        Initiator::without_pk()
+    } else {
+        let mut key = [0; 32];
+        key.copy_from_slice(std::slice::from_raw_parts(authority_public_key, 32));
+        Initiator::from_raw_k(key)
+    };
+    match initiator {
+        Ok(initiator) => Box::into_raw(Box::new(InitiatorWrapper(initiator))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn free_initiator(initiator: *mut InitiatorWrapper) {
+    let initiator = unsafe { Box::from_raw(initiator) };
+    drop(initiator);
+}
+
+/// Writes the first handshake message, `RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE` bytes, in
+/// `out`. Returns false on failure.
+///
+/// # Safety
+/// `out` must point to `RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn initiator_step_0(initiator: *mut InitiatorWrapper, out: *mut u8) -> bool {
+    let mut initiator = Box::from_raw(initiator);
+    let result = initiator.0.step_0();
+    let _ = Box::into_raw(initiator);
+    match result {
+        Ok(message) => {
+            std::slice::from_raw_parts_mut(out, RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE)
+                .copy_from_slice(&message);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Completes the handshake with the responder message, `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE`
+/// bytes. Consumes `initiator` and returns the encrypted codec, or null if the handshake failed
+/// (e.g. the responder certificate is not valid).
+///
+/// # Safety
+/// `message` must point to `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn initiator_step_2(
+    initiator: *mut InitiatorWrapper,
+    message: *const u8,
+) -> *mut NoiseCodecWrapper {
+    let mut initiator = Box::from_raw(initiator);
+    let message: [u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE] =
+        match std::slice::from_raw_parts(message, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE)
+            .try_into()
+        {
+            Ok(message) => message,
+            Err(_) => return std::ptr::null_mut(),
+        };
+    match initiator.0.step_2(message) {
+        Ok(codec) => NoiseCodecWrapper::new(State::with_transport_mode(codec)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Creates the responder (upstream) side of a handshake, that signs certificates valid for
+/// `cert_validity_sec` seconds with the 32 bytes authority key pair. Returns null if the keys do
+/// not match.
+///
+/// # Safety
+/// `authority_public_key` and `authority_secret_key` must point to 32 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn new_responder(
+    authority_public_key: *const u8,
+    authority_secret_key: *const u8,
+    cert_validity_sec: u64,
+) -> *mut ResponderWrapper {
+    let mut public = [0; 32];
+    public.copy_from_slice(std::slice::from_raw_parts(authority_public_key, 32));
+    let mut secret = [0; 32];
+    secret.copy_from_slice(std::slice::from_raw_parts(authority_secret_key, 32));
+    match Responder::from_authority_kp(&public, &secret, Duration::from_secs(cert_validity_sec)) {
+        Ok(responder) => Box::into_raw(Box::new(ResponderWrapper(responder))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn free_responder(responder: *mut ResponderWrapper) {
+    let responder = unsafe { Box::from_raw(responder) };
+    drop(responder);
+}
+
+/// Reads the initiator message, `RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE` bytes, and writes the
+/// reply, `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` bytes, in `out`. Consumes `responder` and
+/// returns the encrypted codec, or null if the handshake failed.
+///
+/// # Safety
+/// `message` must point to `RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE` readable bytes and `out`
+/// to `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn responder_step_1(
+    responder: *mut ResponderWrapper,
+    message: *const u8,
+    out: *mut u8,
+) -> *mut NoiseCodecWrapper {
+    let mut responder = Box::from_raw(responder);
+    let message: [u8; RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE] =
+        match std::slice::from_raw_parts(message, RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE)
+            .try_into()
+        {
+            Ok(message) => message,
+            Err(_) => return std::ptr::null_mut(),
+        };
+    match responder.0.step_1(message) {
+        Ok((reply, codec)) => {
+            std::slice::from_raw_parts_mut(out, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE)
+                .copy_from_slice(&reply);
+            NoiseCodecWrapper::new(State::with_transport_mode(codec))
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn free_noise_codec(codec: *mut NoiseCodecWrapper) {
+    let codec = unsafe { Box::from_raw(codec) };
+    drop(codec);
+}
+
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn flush_noise_encoder(codec: *mut NoiseCodecWrapper) {
+    let mut codec = unsafe { Box::from_raw(codec) };
+    codec.free = true;
+    let _ = Box::into_raw(codec);
+}
+
+/// Encodes and encrypts `message`. The returned buffer is owned by the codec and is valid until
+/// `flush_noise_encoder` is called, `EncoderBusy` is returned until then.
+///
+/// # Safety
+#[no_mangle]
+pub unsafe extern "C" fn noise_encode(
+    message: &'static mut CSv2Message,
+    codec: *mut NoiseCodecWrapper,
+) -> CResult<CVec, Sv2Error> {
+    let mut codec = Box::from_raw(codec);
+    if !codec.free {
+        let _ = Box::into_raw(codec);
+        return CResult::Err(Sv2Error::EncoderBusy);
+    }
+    let codec_ = &mut *codec;
+    let result = encode_frame(message).and_then(|frame| {
+        codec_
+            .encoder
+            .encode(Frame::Sv2(frame), &mut codec_.state)
+            .map_err(|e| Sv2Error::CodecError(e.into()))
+            .map(|encoded| {
+                let encoded: &[u8] = encoded.as_ref();
+                codec_.encoded = encoded.to_vec();
+                CVec::as_shared_buffer(&mut codec_.encoded)
+            })
+    });
+    codec.free = result.is_err();
+    let _ = Box::into_raw(codec);
+    result.into()
+}
+
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn noise_get_writable(codec: *mut NoiseCodecWrapper) -> CVec {
+    let mut codec = unsafe { Box::from_raw(codec) };
+    let writable = codec.decoder.writable();
+    let res = CVec::as_shared_buffer(writable);
+    let _ = Box::into_raw(codec);
+    res
+}
+
+/// Decrypts and decodes the next frame. Returns `MissingBytes` until the bytes requested by
+/// `noise_get_writable` have been written, and `CodecError` if the frame can not be decrypted.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn noise_next_frame(
+    codec: *mut NoiseCodecWrapper,
+) -> CResult<CSv2Message, Sv2Error> {
+    let mut codec = unsafe { Box::from_raw(codec) };
+    let codec_ = &mut *codec;
+    let result = match codec_.decoder.next_frame(&mut codec_.state) {
+        Ok(Frame::Sv2(mut f)) => to_c_message(&mut f),
+        Ok(Frame::HandShake(_)) => CResult::Err(Sv2Error::InvalidSv2Frame),
+        Err(codec_sv2::Error::MissingBytes(_)) => CResult::Err(Sv2Error::MissingBytes),
+        Err(e) => CResult::Err(Sv2Error::CodecError(e.into())),
+    };
+    let _ = Box::into_raw(codec);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common_messages_sv2::SetupConnectionError;
+    use std::ffi::CString;
+
+    const PUBLIC_KEY: &str = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72";
+    const SECRET_KEY: &str = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n";
+
+    fn keys() -> ([u8; 32], [u8; 32]) {
+        let (mut public, mut secret) = ([0; 32], [0; 32]);
+        let public_key = CString::new(PUBLIC_KEY).unwrap();
+        let secret_key = CString::new(SECRET_KEY).unwrap();
+        unsafe {
+            assert!(parse_authority_public_key(
+                public_key.as_ptr(),
+                public.as_mut_ptr()
+            ));
+            assert!(parse_authority_secret_key(
+                secret_key.as_ptr(),
+                secret.as_mut_ptr()
+            ));
+        }
+        (public, secret)
+    }
+
+    unsafe fn handshake(pinned_key: &[u8; 32]) -> (*mut NoiseCodecWrapper, *mut NoiseCodecWrapper) {
+        let (public, secret) = keys();
+        let initiator = new_initiator(pinned_key.as_ptr());
+        let responder = new_responder(public.as_ptr(), secret.as_ptr(), 3600);
+        let mut step_0 = [0; RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
+        let mut step_1 = [0; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
+        assert!(initiator_step_0(initiator, step_0.as_mut_ptr()));
+        let responder = responder_step_1(responder, step_0.as_ptr(), step_1.as_mut_ptr());
+        (initiator_step_2(initiator, step_1.as_ptr()), responder)
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        unsafe {
+            let (initiator, responder) = handshake(&keys().0);
+            assert!(!initiator.is_null() && !responder.is_null());
+
+            let message: CSv2Message = Sv2Message::SetupConnectionError(SetupConnectionError {
+                flags: 1,
+                error_code: "unsupported-feature-flags"
+                    .to_string()
+                    .into_bytes()
+                    .try_into()
+                    .unwrap(),
+            })
+            .into();
+            let encoded = match noise_encode(Box::leak(Box::new(message)), initiator) {
+                CResult::Ok(mut encoded) => encoded.as_mut_slice().to_vec(),
+                CResult::Err(e) => panic!("{}", e),
+            };
+            let message: CSv2Message = Sv2Message::SetupConnectionError(SetupConnectionError {
+                flags: 1,
+                error_code: "busy".to_string().into_bytes().try_into().unwrap(),
+            })
+            .into();
+            assert!(matches!(
+                noise_encode(Box::leak(Box::new(message)), initiator),
+                CResult::Err(Sv2Error::EncoderBusy)
+            ));
+            flush_noise_encoder(initiator);
+
+            let mut encoded = &encoded[..];
+            let decoded = loop {
+                let mut writable = noise_get_writable(responder);
+                let writable = writable.as_mut_slice();
+                writable.copy_from_slice(&encoded[..writable.len()]);
+                encoded = &encoded[writable.len()..];
+                match noise_next_frame(responder) {
+                    CResult::Ok(decoded) => break decoded,
+                    CResult::Err(Sv2Error::MissingBytes) => continue,
+                    CResult::Err(e) => panic!("{}", e),
+                }
+            };
+            assert!(encoded.is_empty());
+            match decoded {
+                CSv2Message::SetupConnectionError(mut m) => {
+                    let m = m.to_rust_rep_mut().unwrap();
+                    assert_eq!(m.flags, 1);
+                    assert_eq!(m.error_code.to_vec(), b"unsupported-feature-flags".to_vec());
+                }
+                _ => panic!("unexpected message"),
+            }
+            free_noise_codec(initiator);
+            free_noise_codec(responder);
+        }
+    }
+
+    #[test]
+    fn handshake_fails_with_wrong_authority_key() {
+        let secret_key: Secp256k1SecretKey = "8WwpJCixn9cKe3jAyXvxNeo5JrBFKj43ULkUeTfeLMqLiZPjj"
+            .parse()
+            .unwrap();
+        let other_key: Secp256k1PublicKey = secret_key.into();
+        unsafe {
+            let (initiator, responder) = handshake(&other_key.into_bytes());
+            assert!(initiator.is_null());
+            free_noise_codec(responder);
+        }
+    }
+}
@@ -535,6 +535,8 @@ struct CError {
     NoiseSv2Error,
     /// Noise protocol is not in the expected handshake state.
     NotInHandShakeState,
+    /// Noise protocol is not in transport mode.
+    NotInTransportState,
     /// Unexpected state in the Noise protocol.
     UnexpectedNoiseState,
   };
@@ -569,6 +571,12 @@ struct DecoderWrapper;
 
 struct EncoderWrapper;
 
+struct InitiatorWrapper;
+
+struct NoiseCodecWrapper;
+
+struct ResponderWrapper;
+
 struct CSv2Message {
   enum class Tag {
     CoinbaseOutputConstraints,
@@ -723,4 +731,82 @@ CVec get_writable(DecoderWrapper *decoder);
 
 CResult<CSv2Message, Sv2Error> next_frame(DecoderWrapper *decoder);
 
+/// Parses a base58check encoded authority public key, as found in the roles config files, and
+/// writes its 32 bytes in `out`. Returns false if the key is not valid.
+///
+/// # Safety
+/// `key` must be a null terminated string and `out` must point to 32 writable bytes.
+bool parse_authority_public_key(const char *key, uint8_t *out);
+
+/// Parses a base58check encoded authority secret key, as found in the roles config files, and
+/// writes its 32 bytes in `out`. Returns false if the key is not valid.
+///
+/// # Safety
+/// `key` must be a null terminated string and `out` must point to 32 writable bytes.
+bool parse_authority_secret_key(const char *key, uint8_t *out);
+
+/// Creates the initiator (downstream) side of a handshake. The responder certificate is verified
+/// against the 32 bytes `authority_public_key`, or not verified at all if it is null. Returns null
+/// if the key is not valid.
+///
+/// # Safety
+/// `authority_public_key` must be null or point to 32 readable bytes.
+InitiatorWrapper *new_initiator(const uint8_t *authority_public_key);
+
+void free_initiator(InitiatorWrapper *initiator);
+
+/// Writes the first handshake message, `RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE` bytes, in
+/// `out`. Returns false on failure.
+///
+/// # Safety
+/// `out` must point to `RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE` writable bytes.
+bool initiator_step_0(InitiatorWrapper *initiator, uint8_t *out);
+
+/// Completes the handshake with the responder message, `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE`
+/// bytes. Consumes `initiator` and returns the encrypted codec, or null if the handshake failed
+/// (e.g. the responder certificate is not valid).
+///
+/// # Safety
+/// `message` must point to `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` readable bytes.
+NoiseCodecWrapper *initiator_step_2(InitiatorWrapper *initiator, const uint8_t *message);
+
+/// Creates the responder (upstream) side of a handshake, that signs certificates valid for
+/// `cert_validity_sec` seconds with the 32 bytes authority key pair. Returns null if the keys do
+/// not match.
+///
+/// # Safety
+/// `authority_public_key` and `authority_secret_key` must point to 32 readable bytes.
+ResponderWrapper *new_responder(const uint8_t *authority_public_key,
+                                const uint8_t *authority_secret_key,
+                                uint64_t cert_validity_sec);
+
+void free_responder(ResponderWrapper *responder);
+
+/// Reads the initiator message, `RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE` bytes, and writes the
+/// reply, `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` bytes, in `out`. Consumes `responder` and
+/// returns the encrypted codec, or null if the handshake failed.
+///
+/// # Safety
+/// `message` must point to `RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE` readable bytes and `out`
+/// to `INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE` writable bytes.
+NoiseCodecWrapper *responder_step_1(ResponderWrapper *responder,
+                                    const uint8_t *message,
+                                    uint8_t *out);
+
+void free_noise_codec(NoiseCodecWrapper *codec);
+
+void flush_noise_encoder(NoiseCodecWrapper *codec);
+
+/// Encodes and encrypts `message`. The returned buffer is owned by the codec and is valid until
+/// `flush_noise_encoder` is called, `EncoderBusy` is returned until then.
+///
+/// # Safety
+CResult<CVec, Sv2Error> noise_encode(CSv2Message *message, NoiseCodecWrapper *codec);
+
+CVec noise_get_writable(NoiseCodecWrapper *codec);
+
+/// Decrypts and decodes the next frame. Returns `MissingBytes` until the bytes requested by
+/// `noise_get_writable` have been written, and `CodecError` if the frame can not be decrypted.
+CResult<CSv2Message, Sv2Error> noise_next_frame(NoiseCodecWrapper *codec);
+
 } // extern "C"
@@ -0,0 +1,332 @@
+//! Sv2 framing, with and without Noise encryption.
+
+use crate::{
+    messages::{from_dict, parse, to_dict},
+    Sv2Error,
+};
+use codec_sv2::{
+    Frame, NoiseEncoder, StandardDecoder, StandardNoiseDecoder, StandardSv2Frame, State,
+};
+use const_sv2::{
+    ENCRYPTED_SV2_FRAME_HEADER_SIZE, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+    RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE, SV2_FRAME_HEADER_SIZE,
+};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+use roles_logic_sv2::parsers::{AnyMessage, IsSv2Message};
+use std::{convert::TryInto, time::Duration};
+
+type Message = AnyMessage<'static>;
+
+fn frame(message: &Bound<'_, PyDict>) -> PyResult<StandardSv2Frame<Message>> {
+    let message = from_dict(message)?;
+    let (extension_type, message_type, channel_bit) = (
+        message.extension_type(),
+        message.message_type(),
+        message.channel_bit(),
+    );
+    StandardSv2Frame::from_message(message, message_type, extension_type, channel_bit)
+        .ok_or_else(|| PyValueError::new_err("message is too big"))
+}
+
+fn frame_to_dict<'py>(
+    py: Python<'py>,
+    frame: &mut StandardSv2Frame<Message>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let header = frame
+        .get_header()
+        .ok_or_else(|| Sv2Error::new_err("frame without header"))?;
+    let message = parse(header.ext_type(), header.msg_type(), frame.payload())?;
+    to_dict(py, &message)
+}
+
+// The decoders ask for the exact number of bytes they miss and `writable` grows their buffer
+// each time it is called, so `feed` buffers the incoming data and only asks for a writable slice
+// once it can be filled.
+enum Decoded {
+    Frame(Box<StandardSv2Frame<Message>>),
+    MissingBytes(usize),
+}
+
+trait FrameDecoder {
+    /// Bytes needed to start decoding a frame.
+    const HEADER_SIZE: usize;
+
+    fn writable(&mut self) -> &mut [u8];
+    fn next_frame(&mut self) -> PyResult<Decoded>;
+}
+
+fn feed<'py, D: FrameDecoder>(
+    py: Python<'py>,
+    decoder: &mut D,
+    pending: &mut Vec<u8>,
+    missing: &mut usize,
+    data: &[u8],
+) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    pending.extend_from_slice(data);
+    let mut messages = Vec::new();
+    while *missing <= pending.len() {
+        decoder.writable().copy_from_slice(&pending[..*missing]);
+        pending.drain(..*missing);
+        match decoder.next_frame()? {
+            Decoded::Frame(mut frame) => {
+                *missing = D::HEADER_SIZE;
+                messages.push(frame_to_dict(py, &mut frame)?);
+            }
+            Decoded::MissingBytes(n) => *missing = n,
+        }
+    }
+    Ok(messages)
+}
+
+/// Encodes messages as plain Sv2 frames.
+#[pyclass(unsendable)]
+pub struct Encoder(codec_sv2::Encoder<Message>);
+
+#[pymethods]
+impl Encoder {
+    #[new]
+    fn new() -> Self {
+        Self(codec_sv2::Encoder::new())
+    }
+
+    /// Encodes a message dict as a Sv2 frame.
+    fn encode<'py>(
+        &mut self,
+        py: Python<'py>,
+        message: &Bound<'_, PyDict>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let encoded = self
+            .0
+            .encode(frame(message)?)
+            .map_err(|e| Sv2Error::new_err(format!("{:?}", e)))?;
+        Ok(PyBytes::new_bound(py, encoded))
+    }
+}
+
+/// Decodes plain Sv2 frames.
+#[pyclass(unsendable)]
+pub struct Decoder {
+    decoder: StandardDecoder<Message>,
+    pending: Vec<u8>,
+    missing: usize,
+}
+
+impl FrameDecoder for StandardDecoder<Message> {
+    const HEADER_SIZE: usize = SV2_FRAME_HEADER_SIZE;
+
+    fn writable(&mut self) -> &mut [u8] {
+        StandardDecoder::writable(self)
+    }
+
+    fn next_frame(&mut self) -> PyResult<Decoded> {
+        match StandardDecoder::next_frame(self) {
+            Ok(frame) => Ok(Decoded::Frame(Box::new(frame))),
+            Err(codec_sv2::Error::MissingBytes(n)) => Ok(Decoded::MissingBytes(n)),
+            Err(e) => Err(Sv2Error::new_err(format!("{:?}", e))),
+        }
+    }
+}
+
+#[pymethods]
+impl Decoder {
+    #[new]
+    fn new() -> Self {
+        Self {
+            decoder: StandardDecoder::new(),
+            pending: Vec::new(),
+            missing: SV2_FRAME_HEADER_SIZE,
+        }
+    }
+
+    /// Feeds bytes read from the connection, returns the messages of the completed frames.
+    fn feed<'py>(&mut self, py: Python<'py>, data: &[u8]) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        feed(
+            py,
+            &mut self.decoder,
+            &mut self.pending,
+            &mut self.missing,
+            data,
+        )
+    }
+}
+
+/// Initiator (downstream) side of a Noise handshake.
+#[pyclass(unsendable)]
+pub struct Initiator(Option<Box<codec_sv2::Initiator>>);
+
+#[pymethods]
+impl Initiator {
+    /// The responder certificate is verified against the base58check encoded authority public
+    /// key, or not verified at all if it is `None`.
+    #[new]
+    #[pyo3(signature = (authority_public_key=None))]
+    fn new(authority_public_key: Option<&str>) -> PyResult<Self> {
+        let initiator = match authority_public_key {
+            Some(key) => {
+                let key: Secp256k1PublicKey = key
+                    .parse()
+                    .map_err(|e| PyValueError::new_err(format!("{:?}", e)))?;
+                codec_sv2::Initiator::from_raw_k(key.into_bytes())
+            }
+            None => codec_sv2::Initiator::without_pk(),
+        }
+        .map_err(|e| Sv2Error::new_err(format!("{:?}", e)))?;
+        Ok(Self(Some(initiator)))
+    }
+
+    /// Returns the first handshake message, to send to the responder.
+    fn step_0<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let message = self
+            .initiator()?
+            .step_0()
+            .map_err(|e| Sv2Error::new_err(format!("{:?}", e)))?;
+        Ok(PyBytes::new_bound(py, &message))
+    }
+
+    /// Completes the handshake with the responder message and returns the encrypted codec.
+    fn step_2(&mut self, message: &[u8]) -> PyResult<NoiseCodec> {
+        let message: [u8; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE] = message
+            .try_into()
+            .map_err(|_| PyValueError::new_err("invalid handshake message length"))?;
+        let codec = self
+            .initiator()?
+            .step_2(message)
+            .map_err(|e| Sv2Error::new_err(format!("{:?}", e)))?;
+        self.0 = None;
+        Ok(NoiseCodec::new(State::with_transport_mode(codec)))
+    }
+}
+
+impl Initiator {
+    fn initiator(&mut self) -> PyResult<&mut codec_sv2::Initiator> {
+        self.0
+            .as_deref_mut()
+            .ok_or_else(|| Sv2Error::new_err("handshake already completed"))
+    }
+}
+
+/// Responder (upstream) side of a Noise handshake.
+#[pyclass(unsendable)]
+pub struct Responder(Box<codec_sv2::Responder>);
+
+#[pymethods]
+impl Responder {
+    /// Signs certificates valid for `cert_validity` seconds with the base58check encoded
+    /// authority key pair.
+    #[new]
+    #[pyo3(signature = (authority_public_key, authority_secret_key, cert_validity=3600))]
+    fn new(
+        authority_public_key: &str,
+        authority_secret_key: &str,
+        cert_validity: u64,
+    ) -> PyResult<Self> {
+        let public_key: Secp256k1PublicKey = authority_public_key
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("{:?}", e)))?;
+        let secret_key: Secp256k1SecretKey = authority_secret_key
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("{:?}", e)))?;
+        codec_sv2::Responder::from_authority_kp(
+            &public_key.into_bytes(),
+            &secret_key.into_bytes(),
+            Duration::from_secs(cert_validity),
+        )
+        .map(Self)
+        .map_err(|e| Sv2Error::new_err(format!("{:?}", e)))
+    }
+
+    /// Reads the initiator message, returns the reply to send back and the encrypted codec.
+    fn step_1<'py>(
+        &mut self,
+        py: Python<'py>,
+        message: &[u8],
+    ) -> PyResult<(Bound<'py, PyBytes>, NoiseCodec)> {
+        let message: [u8; RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE] = message
+            .try_into()
+            .map_err(|_| PyValueError::new_err("invalid handshake message length"))?;
+        let (reply, codec) = self
+            .0
+            .step_1(message)
+            .map_err(|e| Sv2Error::new_err(format!("{:?}", e)))?;
+        Ok((
+            PyBytes::new_bound(py, &reply),
+            NoiseCodec::new(State::with_transport_mode(codec)),
+        ))
+    }
+}
+
+/// Encrypts and decrypts the Sv2 frames of a connection once the handshake is completed.
+#[pyclass(unsendable)]
+pub struct NoiseCodec {
+    state: State,
+    encoder: NoiseEncoder<Message>,
+    decoder: StandardNoiseDecoder<Message>,
+    pending: Vec<u8>,
+    missing: usize,
+}
+
+// The encoder and the decoder share the `State` that holds the session keys.
+struct NoiseDecoder<'a> {
+    state: &'a mut State,
+    decoder: &'a mut StandardNoiseDecoder<Message>,
+}
+
+impl FrameDecoder for NoiseDecoder<'_> {
+    const HEADER_SIZE: usize = ENCRYPTED_SV2_FRAME_HEADER_SIZE;
+
+    fn writable(&mut self) -> &mut [u8] {
+        self.decoder.writable()
+    }
+
+    fn next_frame(&mut self) -> PyResult<Decoded> {
+        match self.decoder.next_frame(self.state) {
+            Ok(Frame::Sv2(frame)) => Ok(Decoded::Frame(Box::new(frame))),
+            Ok(Frame::HandShake(_)) => Err(Sv2Error::new_err("unexpected handshake frame")),
+            Err(codec_sv2::Error::MissingBytes(n)) => Ok(Decoded::MissingBytes(n)),
+            Err(e) => Err(Sv2Error::new_err(format!("{:?}", e))),
+        }
+    }
+}
+
+impl NoiseCodec {
+    fn new(state: State) -> Self {
+        Self {
+            state,
+            encoder: NoiseEncoder::new(),
+            decoder: StandardNoiseDecoder::new(),
+            pending: Vec::new(),
+            // The decoder starts asking for bytes after a first call to `next_frame`
+            missing: 0,
+        }
+    }
+}
+
+#[pymethods]
+impl NoiseCodec {
+    /// Encodes and encrypts a message dict.
+    fn encode<'py>(
+        &mut self,
+        py: Python<'py>,
+        message: &Bound<'_, PyDict>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let encoded = self
+            .encoder
+            .encode(Frame::Sv2(frame(message)?), &mut self.state)
+            .map_err(|e| Sv2Error::new_err(format!("{:?}", e)))?;
+        Ok(PyBytes::new_bound(py, &encoded))
+    }
+
+    /// Feeds bytes read from the connection, returns the messages of the decrypted frames.
+    fn feed<'py>(&mut self, py: Python<'py>, data: &[u8]) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let mut decoder = NoiseDecoder {
+            state: &mut self.state,
+            decoder: &mut self.decoder,
+        };
+        feed(py, &mut decoder, &mut self.pending, &mut self.missing, data)
+    }
+}
@@ -0,0 +1,125 @@
+//! # Python bindings for Stratum V2
+//!
+//! Exposes the Sv2 messages of [`roles_logic_sv2::parsers::AnyMessage`], the Sv2 framing with and
+//! without Noise encryption and a few helpers of [`roles_logic_sv2::utils`] to Python, so that
+//! tools like fuzzers, sniffers and mock miners can be written in Python against the same code used
+//! by the roles.
+//!
+//! Messages are Python dicts, see [`messages`] for their format. The module is built with
+//! [maturin](https://www.maturin.rs), see the README.
+
+// False positive on the `PyResult` returned by the `#[pymethods]`
+#![allow(clippy::useless_conversion)]
+
+mod codec;
+mod messages;
+
+use codec::{Decoder, Encoder, Initiator, NoiseCodec, Responder};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+use roles_logic_sv2::utils;
+use std::convert::TryInto;
+
+create_exception!(
+    sv2,
+    Sv2Error,
+    PyException,
+    "Error returned by the Sv2 codec."
+);
+
+/// Parses the payload of a frame with the given message type and extension type.
+#[pyfunction]
+#[pyo3(signature = (message_type, payload, extension_type=0))]
+fn decode_message<'py>(
+    py: Python<'py>,
+    message_type: u8,
+    payload: &[u8],
+    extension_type: u16,
+) -> PyResult<Bound<'py, PyDict>> {
+    let mut payload = payload.to_vec();
+    let message = messages::parse(extension_type, message_type, &mut payload)?;
+    messages::to_dict(py, &message)
+}
+
+/// Serializes a message dict, without the frame header.
+#[pyfunction]
+fn encode_message<'py>(
+    py: Python<'py>,
+    message: &Bound<'_, PyDict>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let payload = messages::serialize(messages::from_dict(message)?)?;
+    Ok(PyBytes::new_bound(py, &payload))
+}
+
+/// Returns the target (little endian) for a device with the given hashrate (H/s) to produce on
+/// average `shares_per_minute` shares.
+#[pyfunction]
+fn hash_rate_to_target<'py>(
+    py: Python<'py>,
+    hashrate: f64,
+    shares_per_minute: f64,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let target = utils::hash_rate_to_target(hashrate, shares_per_minute)
+        .map_err(|e| PyValueError::new_err(format!("{:?}", e)))?;
+    Ok(PyBytes::new_bound(py, target.inner_as_ref()))
+}
+
+/// Returns the hashrate (H/s) of a device that produces on average `shares_per_minute` shares
+/// with the given target (little endian).
+#[pyfunction]
+fn hash_rate_from_target(target: &[u8], shares_per_minute: f64) -> PyResult<f64> {
+    let target: [u8; 32] = target
+        .try_into()
+        .map_err(|_| PyValueError::new_err("target must be 32 bytes"))?;
+    utils::hash_rate_from_target(target.into(), shares_per_minute)
+        .map_err(|e| PyValueError::new_err(format!("{:?}", e)))
+}
+
+/// Computes the merkle root of a job from the coinbase parts, the extranonce and the merkle path.
+/// Returns `None` if the coinbase transaction is not valid.
+#[pyfunction]
+fn merkle_root_from_path<'py>(
+    py: Python<'py>,
+    coinbase_tx_prefix: &[u8],
+    coinbase_tx_suffix: &[u8],
+    extranonce: &[u8],
+    path: Vec<Vec<u8>>,
+) -> Option<Bound<'py, PyBytes>> {
+    utils::merkle_root_from_path(coinbase_tx_prefix, coinbase_tx_suffix, extranonce, &path)
+        .map(|root| PyBytes::new_bound(py, &root))
+}
+
+/// Computes the merkle root from the coinbase transaction id and the merkle path.
+#[pyfunction]
+fn merkle_root_from_coinbase_id<'py>(
+    py: Python<'py>,
+    coinbase_id: &[u8],
+    path: Vec<Vec<u8>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let coinbase_id: [u8; 32] = coinbase_id
+        .try_into()
+        .map_err(|_| PyValueError::new_err("coinbase id must be 32 bytes"))?;
+    let root = utils::merkle_root_from_path_(coinbase_id, &path);
+    Ok(PyBytes::new_bound(py, &root))
+}
+
+#[pymodule]
+fn sv2(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("Sv2Error", m.py().get_type_bound::<Sv2Error>())?;
+    m.add_function(wrap_pyfunction!(decode_message, m)?)?;
+    m.add_function(wrap_pyfunction!(encode_message, m)?)?;
+    m.add_function(wrap_pyfunction!(hash_rate_to_target, m)?)?;
+    m.add_function(wrap_pyfunction!(hash_rate_from_target, m)?)?;
+    m.add_function(wrap_pyfunction!(merkle_root_from_path, m)?)?;
+    m.add_function(wrap_pyfunction!(merkle_root_from_coinbase_id, m)?)?;
+    m.add_class::<Encoder>()?;
+    m.add_class::<Decoder>()?;
+    m.add_class::<Initiator>()?;
+    m.add_class::<Responder>()?;
+    m.add_class::<NoiseCodec>()?;
+    Ok(())
+}
@@ -0,0 +1,394 @@
+//! Conversion of [`AnyMessage`] to and from Python dicts.
+//!
+//! A message is a dict with the name of the subprotocol (the [`AnyMessage`] variant), the name of
+//! the message and one item per field, e.g.
+//! `{"subprotocol": "Mining", "message": "SetTarget", "channel_id": 1, "maximum_target": b"..."}`.
+//! Integers and booleans are Python `int` and `bool`, byte arrays and hashes are `bytes` (in the
+//! same byte order as on the wire), sequences are `list` and optional fields are `None` when
+//! missing. Strings are `bytes` too, as nothing guarantees that they are valid UTF-8, but a `str`
+//! is accepted when building a message.
+
+use binary_sv2::{
+    to_bytes, Seq0255, Seq064K, ShortTxId, Signature, Sv2Option, U32AsRef, B016M, B0255, B032,
+    B064K, U256,
+};
+use const_sv2::{EXTENSION_TYPE_EXTENSIONS_NEGOTIATION, EXTENSION_TYPE_NO_EXTENSION};
+use pyo3::{
+    exceptions::{PyKeyError, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyDict, PyList},
+};
+use roles_logic_sv2::{
+    common_messages_sv2::{
+        ChannelEndpointChanged, Protocol, Reconnect, RequestExtensions, RequestExtensionsError,
+        RequestExtensionsSuccess, SetupConnection, SetupConnectionError, SetupConnectionSuccess,
+    },
+    job_declaration_sv2::{
+        AllocateMiningJobToken, AllocateMiningJobTokenSuccess, DeclareMiningJob,
+        DeclareMiningJobError, DeclareMiningJobSuccess, IdentifyTransactions,
+        IdentifyTransactionsSuccess, ProvideMissingTransactions, ProvideMissingTransactionsSuccess,
+        SubmitSolutionJd,
+    },
+    mining_sv2::{
+        CloseChannel, NewExtendedMiningJob, NewMiningJob, OpenExtendedMiningChannel,
+        OpenExtendedMiningChannelSuccess, OpenMiningChannelError, OpenStandardMiningChannel,
+        OpenStandardMiningChannelSuccess, SetCustomMiningJob, SetCustomMiningJobError,
+        SetCustomMiningJobSuccess, SetExtranoncePrefix, SetGroupChannel,
+        SetNewPrevHash as MiningSetNewPrevHash, SetTarget, SubmitSharesError, SubmitSharesExtended,
+        SubmitSharesStandard, SubmitSharesSuccess, UpdateChannel, UpdateChannelError,
+    },
+    parsers::{
+        AnyMessage, CommonMessages, ExtensionMessage, ExtensionsNegotiation, JobDeclaration,
+        Mining, TemplateDistribution,
+    },
+    template_distribution_sv2::{
+        CoinbaseOutputConstraints, NewTemplate, RequestTransactionData,
+        RequestTransactionDataError, RequestTransactionDataSuccess, SetNewPrevHash, SubmitSolution,
+    },
+};
+use std::convert::{TryFrom, TryInto};
+
+// The most significant bit of the frame `extension_type` is the channel bit.
+const CHANNEL_BIT_MASK: u16 = 0x8000;
+
+/// A message field that can be converted to and from a Python object.
+trait Field: Sized {
+    fn to_py(&self, py: Python<'_>) -> PyObject;
+    fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Self>;
+}
+
+macro_rules! impl_field_for_primitive {
+    ($($t:ty),*) => {$(
+        impl Field for $t {
+            fn to_py(&self, py: Python<'_>) -> PyObject {
+                self.into_py(py)
+            }
+
+            fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+                value.extract()
+            }
+        }
+    )*};
+}
+
+impl_field_for_primitive!(bool, u8, u16, u32, u64, f32);
+
+macro_rules! impl_field_for_bytes {
+    ($($t:ty),*) => {$(
+        impl Field for $t {
+            fn to_py(&self, py: Python<'_>) -> PyObject {
+                PyBytes::new_bound(py, self.as_ref()).into_py(py)
+            }
+
+            fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+                let value: Vec<u8> = value.extract()?;
+                value
+                    .try_into()
+                    .map_err(|e| PyValueError::new_err(format!("{:?}", e)))
+            }
+        }
+    )*};
+}
+
+// `PubKey` is the same type as `U256`, `Str0255` is the same type as `B0255`.
+impl_field_for_bytes!(
+    U256<'_>,
+    ShortTxId<'_>,
+    Signature<'_>,
+    B032<'_>,
+    B0255<'_>,
+    B064K<'_>,
+    B016M<'_>
+);
+
+impl Field for U32AsRef<'_> {
+    fn to_py(&self, py: Python<'_>) -> PyObject {
+        self.as_u32().into_py(py)
+    }
+
+    fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+        Ok(value.extract::<u32>()?.into())
+    }
+}
+
+impl Field for Protocol {
+    fn to_py(&self, py: Python<'_>) -> PyObject {
+        (*self as u8).into_py(py)
+    }
+
+    fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+        Protocol::try_from(value.extract::<u8>()?)
+            .map_err(|e| PyValueError::new_err(format!("{:?}", e)))
+    }
+}
+
+impl<T: Field + Clone> Field for Seq0255<'_, T> {
+    fn to_py(&self, py: Python<'_>) -> PyObject {
+        let items: Vec<PyObject> = self.0.iter().map(|item| item.to_py(py)).collect();
+        PyList::new_bound(py, items).into_py(py)
+    }
+
+    fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let items = items_from_py(value)?;
+        Seq0255::new(items).map_err(|e| PyValueError::new_err(format!("{:?}", e)))
+    }
+}
+
+impl<T: Field + Clone> Field for Seq064K<'_, T> {
+    fn to_py(&self, py: Python<'_>) -> PyObject {
+        let items: Vec<PyObject> = self
+            .clone()
+            .into_inner()
+            .iter()
+            .map(|item| item.to_py(py))
+            .collect();
+        PyList::new_bound(py, items).into_py(py)
+    }
+
+    fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let items = items_from_py(value)?;
+        Seq064K::new(items).map_err(|e| PyValueError::new_err(format!("{:?}", e)))
+    }
+}
+
+impl<T: Field + Clone> Field for Sv2Option<'_, T> {
+    fn to_py(&self, py: Python<'_>) -> PyObject {
+        match self.clone().into_inner() {
+            Some(value) => value.to_py(py),
+            None => py.None(),
+        }
+    }
+
+    fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if value.is_none() {
+            Ok(Sv2Option::new(None))
+        } else {
+            Ok(Sv2Option::new(Some(T::from_py(value)?)))
+        }
+    }
+}
+
+fn items_from_py<T: Field>(value: &Bound<'_, PyAny>) -> PyResult<Vec<T>> {
+    value.iter()?.map(|item| T::from_py(&item?)).collect()
+}
+
+// `Str0255` and `B0255` are the same type, string fields are marked as `str` in `messages!` so
+// that they can also be built from a `str`. They are always converted to `bytes`, so that strings
+// that are not valid UTF-8 round trip.
+fn str_from_py<T: TryFrom<Vec<u8>> + Field>(value: &Bound<'_, PyAny>) -> PyResult<T>
+where
+    T::Error: std::fmt::Debug,
+{
+    match value.extract::<String>() {
+        Ok(value) => value
+            .into_bytes()
+            .try_into()
+            .map_err(|e| PyValueError::new_err(format!("{:?}", e))),
+        Err(_) => T::from_py(value),
+    }
+}
+
+fn item<'py>(dict: &Bound<'py, PyDict>, key: &str) -> PyResult<Bound<'py, PyAny>> {
+    dict.get_item(key)?
+        .ok_or_else(|| PyKeyError::new_err(key.to_string()))
+}
+
+macro_rules! field_from_py {
+    ($value:expr) => {
+        Field::from_py($value)
+    };
+    ($value:expr, str) => {
+        str_from_py($value)
+    };
+}
+
+// Generates `to_dict` and `from_dict` for every message of `AnyMessage` but the extension ones.
+macro_rules! messages {
+    ($(
+        $subprotocol:ident($parser:ident) {$(
+            $message:ident($type:ident) { $($field:ident $(: $kind:ident)?),* $(,)? }
+        ),* $(,)?}
+    )*) => {
+        /// Converts a message to a dict.
+        pub fn to_dict<'py>(py: Python<'py>, message: &AnyMessage<'_>) -> PyResult<Bound<'py, PyDict>> {
+            let dict = PyDict::new_bound(py);
+            match message {
+                $($(AnyMessage::$subprotocol($parser::$message(m)) => {
+                    dict.set_item("subprotocol", stringify!($subprotocol))?;
+                    dict.set_item("message", stringify!($message))?;
+                    $(dict.set_item(stringify!($field), Field::to_py(&m.$field, py))?;)*
+                })*)*
+                AnyMessage::Extension(m) => {
+                    dict.set_item("subprotocol", "Extension")?;
+                    dict.set_item("extension_type", m.extension_type)?;
+                    dict.set_item("message_type", m.message_type)?;
+                    dict.set_item("channel_bit", m.channel_bit)?;
+                    dict.set_item("payload", PyBytes::new_bound(py, &m.payload))?;
+                }
+            }
+            Ok(dict)
+        }
+
+        /// Builds a message from a dict.
+        pub fn from_dict(dict: &Bound<'_, PyDict>) -> PyResult<AnyMessage<'static>> {
+            let subprotocol: String = item(dict, "subprotocol")?.extract()?;
+            if subprotocol == "Extension" {
+                return Ok(AnyMessage::Extension(ExtensionMessage {
+                    extension_type: item(dict, "extension_type")?.extract()?,
+                    message_type: item(dict, "message_type")?.extract()?,
+                    channel_bit: item(dict, "channel_bit")?.extract()?,
+                    payload: item(dict, "payload")?.extract()?,
+                }));
+            }
+            let message: String = item(dict, "message")?.extract()?;
+            $($(
+                if subprotocol == stringify!($subprotocol) && message == stringify!($message) {
+                    return Ok(AnyMessage::$subprotocol($parser::$message($type {
+                        $($field: field_from_py!(&item(dict, stringify!($field))? $(, $kind)?)?,)*
+                    })));
+                }
+            )*)*
+            Err(PyValueError::new_err(format!(
+                "unknown message {}.{}",
+                subprotocol, message
+            )))
+        }
+    };
+}
+
+messages! {
+    Common(CommonMessages) {
+        ChannelEndpointChanged(ChannelEndpointChanged) { channel_id },
+        Reconnect(Reconnect) { new_host: str, new_port },
+        SetupConnection(SetupConnection) {
+            protocol, min_version, max_version, flags, endpoint_host: str, endpoint_port,
+            vendor: str, hardware_version: str, firmware: str, device_id: str,
+        },
+        SetupConnectionError(SetupConnectionError) { flags, error_code: str },
+        SetupConnectionSuccess(SetupConnectionSuccess) { used_version, flags },
+    }
+    ExtensionsNegotiation(ExtensionsNegotiation) {
+        RequestExtensions(RequestExtensions) { request_id, requested_extensions },
+        RequestExtensionsError(RequestExtensionsError) {
+            request_id, unsupported_extensions, required_extensions,
+        },
+        RequestExtensionsSuccess(RequestExtensionsSuccess) { request_id, supported_extensions },
+    }
+    TemplateDistribution(TemplateDistribution) {
+        CoinbaseOutputConstraints(CoinbaseOutputConstraints) {
+            coinbase_output_max_additional_size, coinbase_output_max_additional_sigops,
+        },
+        NewTemplate(NewTemplate) {
+            template_id, future_template, version, coinbase_tx_version, coinbase_prefix,
+            coinbase_tx_input_sequence, coinbase_tx_value_remaining, coinbase_tx_outputs_count,
+            coinbase_tx_outputs, coinbase_tx_locktime, merkle_path,
+        },
+        RequestTransactionData(RequestTransactionData) { template_id },
+        RequestTransactionDataError(RequestTransactionDataError) { template_id, error_code: str },
+        RequestTransactionDataSuccess(RequestTransactionDataSuccess) {
+            template_id, excess_data, transaction_list,
+        },
+        SetNewPrevHash(SetNewPrevHash) { template_id, prev_hash, header_timestamp, n_bits, target },
+        SubmitSolution(SubmitSolution) {
+            template_id, version, header_timestamp, header_nonce, coinbase_tx,
+        },
+    }
+    JobDeclaration(JobDeclaration) {
+        AllocateMiningJobToken(AllocateMiningJobToken) { user_identifier: str, request_id },
+        AllocateMiningJobTokenSuccess(AllocateMiningJobTokenSuccess) {
+            request_id, mining_job_token, coinbase_output_max_additional_size,
+            coinbase_output_max_additional_sigops, coinbase_output, async_mining_allowed,
+        },
+        DeclareMiningJob(DeclareMiningJob) {
+            request_id, mining_job_token, version, coinbase_prefix, coinbase_suffix,
+            tx_short_hash_nonce, tx_short_hash_list, tx_hash_list_hash, excess_data,
+        },
+        DeclareMiningJobError(DeclareMiningJobError) { request_id, error_code: str, error_details },
+        DeclareMiningJobSuccess(DeclareMiningJobSuccess) { request_id, new_mining_job_token },
+        IdentifyTransactions(IdentifyTransactions) { request_id },
+        IdentifyTransactionsSuccess(IdentifyTransactionsSuccess) { request_id, tx_data_hashes },
+        ProvideMissingTransactions(ProvideMissingTransactions) {
+            request_id, unknown_tx_position_list,
+        },
+        ProvideMissingTransactionsSuccess(ProvideMissingTransactionsSuccess) {
+            request_id, transaction_list,
+        },
+        SubmitSolution(SubmitSolutionJd) { extranonce, prev_hash, ntime, nonce, nbits, version },
+    }
+    Mining(Mining) {
+        CloseChannel(CloseChannel) { channel_id, reason_code: str },
+        NewExtendedMiningJob(NewExtendedMiningJob) {
+            channel_id, job_id, min_ntime, version, version_rolling_allowed, merkle_path,
+            coinbase_tx_prefix, coinbase_tx_suffix,
+        },
+        NewMiningJob(NewMiningJob) { channel_id, job_id, min_ntime, version, merkle_root },
+        OpenExtendedMiningChannel(OpenExtendedMiningChannel) {
+            request_id, user_identity: str, nominal_hash_rate, max_target, min_extranonce_size,
+        },
+        OpenExtendedMiningChannelSuccess(OpenExtendedMiningChannelSuccess) {
+            request_id, channel_id, target, extranonce_size, extranonce_prefix,
+        },
+        OpenMiningChannelError(OpenMiningChannelError) { request_id, error_code: str },
+        OpenStandardMiningChannel(OpenStandardMiningChannel) {
+            request_id, user_identity: str, nominal_hash_rate, max_target,
+        },
+        OpenStandardMiningChannelSuccess(OpenStandardMiningChannelSuccess) {
+            request_id, channel_id, target, extranonce_prefix, group_channel_id,
+        },
+        SetCustomMiningJob(SetCustomMiningJob) {
+            channel_id, request_id, token, version, prev_hash, min_ntime, nbits,
+            coinbase_tx_version, coinbase_prefix, coinbase_tx_input_n_sequence,
+            coinbase_tx_value_remaining, coinbase_tx_outputs, coinbase_tx_locktime, merkle_path,
+            extranonce_size,
+        },
+        SetCustomMiningJobError(SetCustomMiningJobError) {
+            channel_id, request_id, error_code: str,
+        },
+        SetCustomMiningJobSuccess(SetCustomMiningJobSuccess) { channel_id, request_id, job_id },
+        SetExtranoncePrefix(SetExtranoncePrefix) { channel_id, extranonce_prefix },
+        SetGroupChannel(SetGroupChannel) { group_channel_id, channel_ids },
+        SetNewPrevHash(MiningSetNewPrevHash) { channel_id, job_id, prev_hash, min_ntime, nbits },
+        SetTarget(SetTarget) { channel_id, maximum_target },
+        SubmitSharesError(SubmitSharesError) { channel_id, sequence_number, error_code: str },
+        SubmitSharesExtended(SubmitSharesExtended) {
+            channel_id, sequence_number, job_id, nonce, ntime, version, extranonce,
+        },
+        SubmitSharesStandard(SubmitSharesStandard) {
+            channel_id, sequence_number, job_id, nonce, ntime, version,
+        },
+        SubmitSharesSuccess(SubmitSharesSuccess) {
+            channel_id, last_sequence_number, new_submits_accepted_count, new_shares_sum,
+        },
+        UpdateChannel(UpdateChannel) { channel_id, nominal_hash_rate, maximum_target },
+        UpdateChannelError(UpdateChannelError) { channel_id, error_code: str },
+    }
+}
+
+/// Parses the payload of a frame. The messages of extensions other than the extensions
+/// negotiation are returned as opaque `Extension` messages.
+pub fn parse(
+    extension_type: u16,
+    message_type: u8,
+    payload: &mut [u8],
+) -> PyResult<AnyMessage<'_>> {
+    match extension_type & !CHANNEL_BIT_MASK {
+        EXTENSION_TYPE_NO_EXTENSION | EXTENSION_TYPE_EXTENSIONS_NEGOTIATION => {
+            AnyMessage::try_from((extension_type, message_type, payload))
+                .map_err(|e| PyValueError::new_err(format!("{:?}", e)))
+        }
+        extension => Ok(AnyMessage::Extension(ExtensionMessage {
+            extension_type: extension,
+            message_type,
+            channel_bit: extension_type & CHANNEL_BIT_MASK != 0,
+            payload: payload.to_vec(),
+        })),
+    }
+}
+
+/// Serializes the payload of a message.
+pub fn serialize(message: AnyMessage<'static>) -> PyResult<Vec<u8>> {
+    match message {
+        AnyMessage::Extension(m) => Ok(m.payload),
+        message => to_bytes(message).map_err(|e| PyValueError::new_err(format!("{:?}", e))),
+    }
+}
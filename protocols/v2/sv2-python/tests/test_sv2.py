@@ -0,0 +1,136 @@
+import pytest
+
+import sv2
+
+AUTHORITY_PUBLIC_KEY = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72"
+AUTHORITY_SECRET_KEY = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n"
+
+SETUP_CONNECTION = {
+    "subprotocol": "Common",
+    "message": "SetupConnection",
+    "protocol": 0,
+    "min_version": 2,
+    "max_version": 2,
+    "flags": 0,
+    "endpoint_host": b"0.0.0.0",
+    "endpoint_port": 8545,
+    "vendor": b"Bitmain",
+    "hardware_version": b"901",
+    "firmware": b"abcX",
+    "device_id": b"89a1-e7bb",
+}
+
+OPEN_STANDARD_MINING_CHANNEL = {
+    "subprotocol": "Mining",
+    "message": "OpenStandardMiningChannel",
+    "request_id": 7,
+    "user_identity": b"user",
+    "nominal_hash_rate": 5.0,
+    "max_target": bytes([0xFF] * 32),
+}
+
+SUBMIT_SHARES_EXTENDED = {
+    "subprotocol": "Mining",
+    "message": "SubmitSharesExtended",
+    "channel_id": 1,
+    "sequence_number": 2,
+    "job_id": 3,
+    "nonce": 4,
+    "ntime": 5,
+    "version": 6,
+    "extranonce": bytes(range(16)),
+}
+
+
+@pytest.mark.parametrize(
+    "message", [SETUP_CONNECTION, OPEN_STANDARD_MINING_CHANNEL, SUBMIT_SHARES_EXTENDED]
+)
+def test_message_round_trip(message):
+    payload = sv2.encode_message(message)
+    message_types = {
+        "SetupConnection": 0x00,
+        "OpenStandardMiningChannel": 0x10,
+        "SubmitSharesExtended": 0x1B,
+    }
+    decoded = sv2.decode_message(message_types[message["message"]], payload)
+    assert decoded == message
+
+
+def test_strings_are_bytes():
+    message = dict(OPEN_STANDARD_MINING_CHANNEL, user_identity=b"\xff\xfe")
+    assert sv2.decode_message(0x10, sv2.encode_message(message)) == message
+    message = dict(OPEN_STANDARD_MINING_CHANNEL, user_identity="user")
+    assert sv2.encode_message(message) == sv2.encode_message(OPEN_STANDARD_MINING_CHANNEL)
+
+
+def test_extension_message_is_opaque():
+    decoded = sv2.decode_message(0x01, b"\x01\x02\x03", extension_type=0x4242)
+    assert decoded == {
+        "subprotocol": "Extension",
+        "extension_type": 0x4242,
+        "message_type": 0x01,
+        "channel_bit": False,
+        "payload": b"\x01\x02\x03",
+    }
+    assert sv2.encode_message(decoded) == b"\x01\x02\x03"
+
+
+def test_invalid_messages():
+    with pytest.raises(KeyError):
+        sv2.encode_message({"subprotocol": "Mining", "message": "SetTarget"})
+    with pytest.raises(ValueError):
+        sv2.encode_message({"subprotocol": "Mining", "message": "Unknown"})
+    with pytest.raises(ValueError):
+        sv2.decode_message(0x7F, b"")
+
+
+def test_frame_round_trip_in_chunks():
+    encoder = sv2.Encoder()
+    data = encoder.encode(SETUP_CONNECTION) + encoder.encode(OPEN_STANDARD_MINING_CHANNEL)
+    decoder = sv2.Decoder()
+    messages = []
+    for i in range(0, len(data), 5):
+        messages += decoder.feed(data[i : i + 5])
+    assert messages == [SETUP_CONNECTION, OPEN_STANDARD_MINING_CHANNEL]
+
+
+def handshake(authority_public_key):
+    initiator = sv2.Initiator(authority_public_key)
+    responder = sv2.Responder(AUTHORITY_PUBLIC_KEY, AUTHORITY_SECRET_KEY)
+    reply, responder_codec = responder.step_1(initiator.step_0())
+    return initiator.step_2(reply), responder_codec
+
+
+def test_noise_round_trip():
+    initiator_codec, responder_codec = handshake(AUTHORITY_PUBLIC_KEY)
+    data = initiator_codec.encode(SETUP_CONNECTION)
+    assert responder_codec.feed(data[:10]) == []
+    assert responder_codec.feed(data[10:]) == [SETUP_CONNECTION]
+    data = responder_codec.encode(SUBMIT_SHARES_EXTENDED) + responder_codec.encode(
+        OPEN_STANDARD_MINING_CHANNEL
+    )
+    assert initiator_codec.feed(data) == [SUBMIT_SHARES_EXTENDED, OPEN_STANDARD_MINING_CHANNEL]
+
+
+def test_noise_without_authority_key():
+    initiator_codec, responder_codec = handshake(None)
+    assert responder_codec.feed(initiator_codec.encode(SETUP_CONNECTION)) == [SETUP_CONNECTION]
+
+
+def test_noise_wrong_authority_key():
+    with pytest.raises(sv2.Sv2Error):
+        handshake("9bETSCePTP78FSzHkRDjnqAh1rd3ZDKa9w39aU35hzrcLDvVKLS")
+
+
+def test_hash_rate_target_round_trip():
+    target = sv2.hash_rate_to_target(1_000_000_000.0, 6.0)
+    assert len(target) == 32
+    hashrate = sv2.hash_rate_from_target(target, 6.0)
+    assert hashrate == pytest.approx(1_000_000_000.0, rel=1e-3)
+
+
+def test_merkle_root():
+    coinbase_id = bytes(range(32))
+    assert sv2.merkle_root_from_coinbase_id(coinbase_id, []) == coinbase_id
+    root = sv2.merkle_root_from_coinbase_id(coinbase_id, [bytes(32)])
+    assert len(root) == 32 and root != coinbase_id
@@ -1,9 +1,10 @@
 #![allow(dead_code)]
 use config_helpers::CoinbaseOutput;
 use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use network_helpers_sv2::RekeyConfig;
 use roles_logic_sv2::utils::CoinbaseOutput as CoinbaseOutput_;
 use serde::Deserialize;
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 use stratum_common::bitcoin::{Amount, TxOut};
 
 /// Represents the configuration of a Job Declarator Client(JDC).
@@ -25,6 +26,8 @@ pub struct JobDeclaratorClientConfig {
     authority_public_key: Secp256k1PublicKey,
     authority_secret_key: Secp256k1SecretKey,
     cert_validity_sec: u64,
+    #[serde(default)]
+    authority_keys_file: Option<PathBuf>,
     tp_address: String,
     tp_authority_public_key: Option<Secp256k1PublicKey>,
     upstreams: Vec<Upstream>,
@@ -32,6 +35,10 @@ pub struct JobDeclaratorClientConfig {
     timeout: Duration,
     coinbase_outputs: Vec<CoinbaseOutput>,
     jdc_signature: String,
+    #[serde(default)]
+    worker_hashrate_tracking: bool,
+    #[serde(default)]
+    rekey_policy: RekeyConfig,
 }
 
 impl JobDeclaratorClientConfig {
@@ -55,12 +62,15 @@ impl JobDeclaratorClientConfig {
             authority_public_key: pool_config.authority_public_key,
             authority_secret_key: pool_config.authority_secret_key,
             cert_validity_sec: tp_config.cert_validity_sec,
+            authority_keys_file: None,
             tp_address: tp_config.tp_address,
             tp_authority_public_key: tp_config.tp_authority_public_key,
             upstreams,
             timeout,
             coinbase_outputs: protocol_config.coinbase_outputs,
             jdc_signature,
+            worker_hashrate_tracking: false,
+            rekey_policy: RekeyConfig::default(),
         }
     }
 
@@ -101,6 +111,18 @@ impl JobDeclaratorClientConfig {
         self.cert_validity_sec
     }
 
+    /// Returns the file the authority keys are loaded from, if any.
+    ///
+    /// When set, it replaces the authority key pair of the config and is reloaded on `SIGHUP`.
+    pub fn authority_keys_file(&self) -> Option<&PathBuf> {
+        self.authority_keys_file.as_ref()
+    }
+
+    /// Sets the file the authority keys are loaded from.
+    pub fn set_authority_keys_file(&mut self, authority_keys_file: Option<PathBuf>) {
+        self.authority_keys_file = authority_keys_file;
+    }
+
     /// Returns Template Provider address.
     pub fn tp_address(&self) -> &str {
         &self.tp_address
@@ -126,6 +148,17 @@ impl JobDeclaratorClientConfig {
         &self.jdc_signature
     }
 
+    /// Returns true if the worker-specific hashrate tracking extension has to be requested to the
+    /// upstream.
+    pub fn worker_hashrate_tracking(&self) -> bool {
+        self.worker_hashrate_tracking
+    }
+
+    /// Returns when the upstream and downstream mining connections are rekeyed.
+    pub fn rekey_policy(&self) -> RekeyConfig {
+        self.rekey_policy
+    }
+
     pub fn get_txout(&self) -> Result<Vec<TxOut>, roles_logic_sv2::Error> {
         let mut result = Vec::new();
         for coinbase_output_pool in &self.coinbase_outputs {
@@ -202,19 +235,25 @@ impl ProtocolConfig {
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Upstream {
-    pub authority_pubkey: Secp256k1PublicKey,
+    /// Authority public keys accepted for the pool and JDS certificates. Several keys can be
+    /// pinned while the upstream rotates its authority key.
+    #[serde(
+        alias = "authority_pubkey",
+        deserialize_with = "config_helpers::one_or_many"
+    )]
+    pub authority_pubkeys: Vec<Secp256k1PublicKey>,
     pub pool_address: String,
     pub jd_address: String,
 }
 
 impl Upstream {
     pub fn new(
-        authority_pubkey: Secp256k1PublicKey,
+        authority_pubkeys: Vec<Secp256k1PublicKey>,
         pool_address: String,
         jd_address: String,
     ) -> Self {
         Self {
-            authority_pubkey,
+            authority_pubkeys,
             pool_address,
             jd_address,
         }
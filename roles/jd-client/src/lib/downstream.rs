@@ -17,15 +17,14 @@ use roles_logic_sv2::{
     },
     job_creator::JobsCreators,
     mining_sv2::*,
-    parsers::{AnyMessage, Mining, MiningDeviceMessages},
+    parsers::{frame_with_tlvs, AnyMessage, Mining, MiningDeviceMessages},
     template_distribution_sv2::{NewTemplate, SubmitSolution},
     utils::Mutex,
 };
 use tokio::sync::Notify;
 use tracing::{debug, error, info, warn};
 
-use codec_sv2::{HandshakeRole, Responder, StandardEitherFrame, StandardSv2Frame};
-use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use codec_sv2::{HandshakeRole, StandardEitherFrame, StandardSv2Frame};
 
 use stratum_common::bitcoin::{consensus::Decodable, TxOut};
 
@@ -53,6 +52,9 @@ pub struct DownstreamMiningNode {
     last_template_id: u64,
     pub jd: Option<Arc<Mutex<JobDeclarator>>>,
     jdc_signature: String,
+    // `user_identity` of the channel opened by the downstream, sent with every share when the
+    // upstream supports worker-specific hashrate tracking
+    user_identity: Option<String>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -185,6 +187,7 @@ impl DownstreamMiningNode {
             last_template_id: 0,
             jd,
             jdc_signature,
+            user_identity: None,
         }
     }
 
@@ -257,6 +260,21 @@ impl DownstreamMiningNode {
         }
     }
 
+    // Builds the `user_identity` TLV field of the worker-specific hashrate tracking extension
+    fn user_identity_tlv(&self) -> Vec<Tlv> {
+        match self.user_identity.as_deref().map(Tlv::user_identity) {
+            Some(Some(tlv)) => vec![tlv],
+            Some(None) => {
+                warn!(
+                    "Downstream user identity {:?} is longer than 32 bytes, the share is sent without it",
+                    self.user_identity
+                );
+                vec![]
+            }
+            None => vec![],
+        }
+    }
+
     /// Parse the received message and relay it to the right upstream
     pub async fn next(self_mutex: &Arc<Mutex<Self>>, mut incoming: StdFrame) {
         let message_type = incoming.get_header().unwrap().msg_type();
@@ -308,8 +326,15 @@ impl DownstreamMiningNode {
                 );
                 let message = Mining::SubmitSharesExtended(share);
                 let message: AnyMessage = AnyMessage::Mining(message);
+                let worker_hashrate_tracking = upstream_mutex
+                    .safe_lock(|u| u.worker_hashrate_tracking_accepted())
+                    .unwrap();
+                let tlvs = match worker_hashrate_tracking {
+                    true => self_mutex.safe_lock(|s| s.user_identity_tlv()).unwrap(),
+                    false => vec![],
+                };
                 let sv2_frame: codec_sv2::Sv2Frame<AnyMessage, buffer_sv2::Slice> =
-                    message.try_into().unwrap();
+                    frame_with_tlvs(message, &tlvs).unwrap();
                 UpstreamMiningNode::send(&upstream_mutex, sv2_frame)
                     .await
                     .unwrap();
@@ -470,6 +495,9 @@ impl ParseMiningMessagesFromDownstream<UpstreamMiningNode> for DownstreamMiningN
             m.get_request_id_as_u32()
         );
         debug!("OpenExtendedMiningChannel: {:?}", m);
+        self.user_identity = std::str::from_utf8(m.user_identity.as_ref())
+            .ok()
+            .map(|identity| identity.to_string());
         if !self.status.is_solo_miner() {
             // Safe unwrap alreay checked if it cointains upstream with is_solo_miner
             Ok(SendTo::RelaySameMessageToRemote(
@@ -687,7 +715,9 @@ impl ParseCommonMessagesFromDownstream for DownstreamMiningNode {
 }
 
 use binary_sv2::Str0255;
-use network_helpers_sv2::noise_connection::Connection;
+use network_helpers_sv2::{
+    certificate_provider::CertificateProvider, noise_connection::Connection,
+};
 use std::net::SocketAddr;
 use tokio::{
     net::TcpListener,
@@ -701,9 +731,7 @@ pub async fn listen_for_downstream_mining(
     address: SocketAddr,
     upstream: Option<Arc<Mutex<UpstreamMiningNode>>>,
     withhold: bool,
-    authority_public_key: Secp256k1PublicKey,
-    authority_secret_key: Secp256k1SecretKey,
-    cert_validity_sec: u64,
+    certificate_provider: Arc<dyn CertificateProvider>,
     task_collector: Arc<Mutex<Vec<AbortHandle>>>,
     tx_status: async_channel::Sender<status::Status<'static>>,
     miner_coinbase_output: Vec<TxOut>,
@@ -738,16 +766,14 @@ pub async fn listen_for_downstream_mining(
 
                 let (send_solution, recv_solution) = bounded(10);
 
-                let responder = Responder::from_authority_kp(
-                    &authority_public_key.into_bytes(),
-                    &authority_secret_key.into_bytes(),
-                    std::time::Duration::from_secs(cert_validity_sec),
+                let responder = certificate_provider.responder().unwrap();
+                let (receiver, sender) = Connection::new_with_rekey_policy(
+                    stream,
+                    HandshakeRole::Responder(responder),
+                    config.rekey_policy().into(),
                 )
-                .unwrap();
-                let (receiver, sender) =
-                    Connection::new(stream, HandshakeRole::Responder(responder))
-                        .await
-                        .expect("impossible to connect");
+                .await
+                .expect("impossible to connect");
 
                 let tx_status_downstream = status::Sender::Downstream(tx_status.clone());
                 let node = DownstreamMiningNode::new(
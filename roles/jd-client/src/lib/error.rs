@@ -43,6 +43,8 @@ pub enum Error<'a> {
     Io(std::io::Error),
     /// Errors on bad `String` to `int` conversion.
     ParseInt(std::num::ParseIntError),
+    /// Errors from `network_helpers_sv2` crate.
+    NetworkHelpers(network_helpers_sv2::Error),
     /// Errors from `roles_logic_sv2` crate.
     RolesSv2Logic(roles_logic_sv2::errors::Error),
     UpstreamIncoming(roles_logic_sv2::errors::Error),
@@ -69,6 +71,7 @@ impl fmt::Display for Error<'_> {
             FramingSv2(ref e) => write!(f, "Framing SV2 error: `{:?}`", e),
             Io(ref e) => write!(f, "I/O error: `{:?}", e),
             ParseInt(ref e) => write!(f, "Bad convert from `String` to `int`: `{:?}`", e),
+            NetworkHelpers(ref e) => write!(f, "Network helpers error: `{:?}`", e),
             RolesSv2Logic(ref e) => write!(f, "Roles SV2 Logic Error: `{:?}`", e),
             SubprotocolMining(ref e) => write!(f, "Subprotocol Mining Error: `{:?}`", e),
             UpstreamIncoming(ref e) => write!(f, "Upstream parse incoming error: `{:?}`", e),
@@ -112,6 +115,12 @@ impl From<std::num::ParseIntError> for Error<'_> {
     }
 }
 
+impl From<network_helpers_sv2::Error> for Error<'_> {
+    fn from(e: network_helpers_sv2::Error) -> Self {
+        Error::NetworkHelpers(e)
+    }
+}
+
 impl From<roles_logic_sv2::errors::Error> for Error<'_> {
     fn from(e: roles_logic_sv2::errors::Error) -> Self {
         Error::RolesSv2Logic(e)
@@ -75,13 +75,13 @@ pub struct JobDeclarator {
 impl JobDeclarator {
     pub async fn new(
         address: SocketAddr,
-        authority_public_key: [u8; 32],
+        authority_public_keys: Vec<[u8; 32]>,
         config: JobDeclaratorClientConfig,
         up: Arc<Mutex<Upstream>>,
         task_collector: Arc<Mutex<Vec<AbortHandle>>>,
     ) -> Result<Arc<Mutex<Self>>, Error<'static>> {
         let stream = tokio::net::TcpStream::connect(address).await?;
-        let initiator = Initiator::from_raw_k(authority_public_key)?;
+        let initiator = Initiator::from_raw_ks(&authority_public_keys)?;
         let (mut receiver, mut sender) =
             Connection::new(stream, HandshakeRole::Initiator(initiator))
                 .await
@@ -12,6 +12,7 @@ use async_channel::unbounded;
 use config::JobDeclaratorClientConfig;
 use futures::{select, FutureExt};
 use job_declarator::JobDeclarator;
+use network_helpers_sv2::certificate_provider::{certificate_provider, CertificateProvider};
 use roles_logic_sv2::utils::Mutex;
 use std::{
     net::{IpAddr, SocketAddr},
@@ -88,8 +89,21 @@ impl JobDeclaratorClient {
         });
 
         let config = self.config;
+        let certificate_provider = match certificate_provider(
+            *config.authority_public_key(),
+            *config.authority_secret_key(),
+            Duration::from_secs(config.cert_validity_sec()),
+            config.authority_keys_file().map(|path| path.as_path()),
+        ) {
+            Ok(certificate_provider) => certificate_provider,
+            Err(e) => {
+                error!("Invalid authority keys: {:?}", e);
+                return;
+            }
+        };
         'outer: loop {
             let task_collector = task_collector.clone();
+            let certificate_provider = certificate_provider.clone();
             let tx_status = tx_status.clone();
             let config = config.clone();
             let shutdown = self.shutdown.clone();
@@ -99,8 +113,15 @@ impl JobDeclaratorClient {
                 let task_collector = task_collector.clone();
                 let upstream = upstream.clone();
                 root_handler = tokio::spawn(async move {
-                    Self::initialize_jd(config, tx_status, task_collector, upstream, shutdown)
-                        .await;
+                    Self::initialize_jd(
+                        config,
+                        tx_status,
+                        task_collector,
+                        upstream,
+                        shutdown,
+                        certificate_provider,
+                    )
+                    .await;
                 });
             } else {
                 let tx_status = tx_status.clone();
@@ -111,6 +132,7 @@ impl JobDeclaratorClient {
                         tx_status.clone(),
                         task_collector.clone(),
                         shutdown,
+                        certificate_provider,
                     )
                     .await;
                 });
@@ -201,6 +223,7 @@ impl JobDeclaratorClient {
         tx_status: async_channel::Sender<status::Status<'static>>,
         task_collector: Arc<Mutex<Vec<AbortHandle>>>,
         shutdown: Arc<Notify>,
+        certificate_provider: Arc<dyn CertificateProvider>,
     ) {
         let miner_tx_out = config.get_txout().expect("Failed to get txout");
 
@@ -209,9 +232,7 @@ impl JobDeclaratorClient {
             *config.listening_address(),
             None,
             config.withhold(),
-            *config.authority_public_key(),
-            *config.authority_secret_key(),
-            config.cert_validity_sec(),
+            certificate_provider,
             task_collector.clone(),
             tx_status.clone(),
             miner_tx_out.clone(),
@@ -231,6 +252,7 @@ impl JobDeclaratorClient {
         task_collector: Arc<Mutex<Vec<AbortHandle>>>,
         upstream_config: config::Upstream,
         shutdown: Arc<Notify>,
+        certificate_provider: Arc<dyn CertificateProvider>,
     ) {
         let timeout = config.timeout();
 
@@ -253,11 +275,13 @@ impl JobDeclaratorClient {
         // Instantiate a new `Upstream` (SV2 Pool)
         let upstream = match upstream_sv2::Upstream::new(
             upstream_addr,
-            upstream_config.authority_pubkey,
+            upstream_config.authority_pubkeys.clone(),
             status::Sender::Upstream(tx_status.clone()),
             task_collector.clone(),
             Arc::new(Mutex::new(PoolChangerTrigger::new(timeout))),
             config.jdc_signature().to_string(),
+            config.worker_hashrate_tracking(),
+            config.rekey_policy().into(),
         )
         .await
         {
@@ -293,7 +317,11 @@ impl JobDeclaratorClient {
         let port_jd = parts.next().unwrap().parse::<u16>().unwrap();
         let jd = match JobDeclarator::new(
             SocketAddr::new(IpAddr::from_str(ip_jd.as_str()).unwrap(), port_jd),
-            upstream_config.authority_pubkey.into_bytes(),
+            upstream_config
+                .authority_pubkeys
+                .iter()
+                .map(|key| key.into_bytes())
+                .collect(),
             config.clone(),
             upstream.clone(),
             task_collector.clone(),
@@ -316,9 +344,7 @@ impl JobDeclaratorClient {
             *config.listening_address(),
             Some(upstream),
             config.withhold(),
-            *config.authority_public_key(),
-            *config.authority_secret_key(),
-            config.cert_validity_sec(),
+            certificate_provider,
             task_collector.clone(),
             tx_status.clone(),
             vec![],
@@ -108,6 +108,10 @@ pub async fn handle_error(
         Error::Io(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
         // Errors on bad `String` to `int` conversion.
         Error::ParseInt(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
+        // Errors from `network_helpers_sv2` crate.
+        Error::NetworkHelpers(_) => {
+            send_status(sender, e, error_handling::ErrorBranch::Break).await
+        }
         // Errors from `roles_logic_sv2` crate.
         Error::RolesSv2Logic(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
         Error::UpstreamIncoming(_) => {
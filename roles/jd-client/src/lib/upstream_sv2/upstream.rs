@@ -11,16 +11,20 @@ use super::super::{
 };
 use async_channel::{Receiver, Sender};
 use binary_sv2::{Seq0255, U256};
-use codec_sv2::{HandshakeRole, Initiator};
+use codec_sv2::{HandshakeRole, Initiator, RekeyPolicy};
+use const_sv2::EXTENSION_TYPE_WORKER_HASHRATE_TRACKING;
 use error_handling::handle_result;
 use key_utils::Secp256k1PublicKey;
-use network_helpers_sv2::noise_connection::Connection;
+use network_helpers_sv2::{extensions::negotiate_extensions, noise_connection::Connection};
 use roles_logic_sv2::{
     channel_logic::channel_factory::PoolChannelFactory,
-    common_messages_sv2::{Protocol, Reconnect, SetupConnection},
+    common_messages_sv2::{
+        Protocol, Reconnect, RequestExtensionsError, RequestExtensionsSuccess, SetupConnection,
+    },
     common_properties::{IsMiningUpstream, IsUpstream},
     handlers::{
         common::{ParseCommonMessagesFromUpstream, SendTo as SendToCommon},
+        extensions::{ParseExtensionsNegotiationFromUpstream, SendTo as SendToExtensions},
         mining::{ParseMiningMessagesFromUpstream, SendTo, SupportedChannelTypes},
     },
     job_declaration_sv2::DeclareMiningJob,
@@ -125,6 +129,12 @@ pub struct Upstream {
     template_to_job_id: TemplateToJobId,
     req_ids: Id,
     jdc_signature: String,
+    /// Set from the `jdc-config.toml`, true if the worker-specific hashrate tracking extension
+    /// has to be requested on every connection.
+    worker_hashrate_tracking: bool,
+    /// True if the SV2 Upstream accepted the worker-specific hashrate tracking extension, in which
+    /// case every share carries the downstream user identity.
+    worker_hashrate_tracking_accepted: bool,
 }
 
 impl Upstream {
@@ -148,11 +158,13 @@ impl Upstream {
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
         address: SocketAddr,
-        authority_public_key: Secp256k1PublicKey,
+        authority_public_keys: Vec<Secp256k1PublicKey>,
         tx_status: status::Sender,
         task_collector: Arc<Mutex<Vec<AbortHandle>>>,
         pool_chaneger_trigger: Arc<Mutex<PoolChangerTrigger>>,
         jdc_signature: String,
+        worker_hashrate_tracking: bool,
+        rekey_policy: RekeyPolicy,
     ) -> ProxyResult<'static, Arc<Mutex<Self>>> {
         // Connect to the SV2 Upstream role retry connection every 5 seconds.
         let socket = loop {
@@ -169,8 +181,11 @@ impl Upstream {
             }
         };
 
-        let pub_key: Secp256k1PublicKey = authority_public_key;
-        let initiator = Initiator::from_raw_k(pub_key.into_bytes())?;
+        let pub_keys: Vec<[u8; 32]> = authority_public_keys
+            .iter()
+            .map(|key| key.into_bytes())
+            .collect();
+        let initiator = Initiator::from_raw_ks(&pub_keys)?;
 
         info!(
             "PROXY SERVER - ACCEPTING FROM UPSTREAM: {}",
@@ -178,9 +193,13 @@ impl Upstream {
         );
 
         // Channel to send and receive messages to the SV2 Upstream role
-        let (receiver, sender) = Connection::new(socket, HandshakeRole::Initiator(initiator))
-            .await
-            .expect("Failed to create connection");
+        let (receiver, sender) = Connection::new_with_rekey_policy(
+            socket,
+            HandshakeRole::Initiator(initiator),
+            rekey_policy,
+        )
+        .await
+        .expect("Failed to create connection");
 
         Ok(Arc::new(Mutex::new(Self {
             channel_id: None,
@@ -196,6 +215,8 @@ impl Upstream {
             template_to_job_id: TemplateToJobId::new(),
             req_ids: Id::new(),
             jdc_signature,
+            worker_hashrate_tracking,
+            worker_hashrate_tracking_accepted: false,
         })))
     }
 
@@ -246,9 +267,42 @@ impl Upstream {
             message_type,
             payload,
         )?;
+
+        Self::negotiate_extensions(self_).await
+    }
+
+    /// Requests the worker-specific hashrate tracking extension to the SV2 Upstream role, if
+    /// enabled in the config, and waits for either a `RequestExtensionsSuccess` or a
+    /// `RequestExtensionsError`.
+    async fn negotiate_extensions(self_: Arc<Mutex<Self>>) -> ProxyResult<'static, ()> {
+        let (worker_hashrate_tracking, sender, receiver) = self_
+            .safe_lock(|s| {
+                s.worker_hashrate_tracking_accepted = false;
+                (
+                    s.worker_hashrate_tracking,
+                    s.sender.clone(),
+                    s.receiver.clone(),
+                )
+            })
+            .map_err(|_| PoisonLock)?;
+        if !worker_hashrate_tracking {
+            return Ok(());
+        }
+        negotiate_extensions(
+            self_,
+            &sender,
+            &receiver,
+            vec![EXTENSION_TYPE_WORKER_HASHRATE_TRACKING],
+        )
+        .await?;
         Ok(())
     }
 
+    /// Returns true if the SV2 Upstream accepted the worker-specific hashrate tracking extension.
+    pub fn worker_hashrate_tracking_accepted(&self) -> bool {
+        self.worker_hashrate_tracking_accepted
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn set_custom_jobs(
         self_: &Arc<Mutex<Self>>,
@@ -475,6 +529,10 @@ impl IsMiningUpstream<Downstream> for Upstream {
         todo!()
     }
 
+    fn remove_hash_rate(&mut self, _to_remove: u64) {
+        todo!()
+    }
+
     fn get_opened_channels(
         &mut self,
     ) -> &mut Vec<roles_logic_sv2::common_properties::UpstreamChannel> {
@@ -517,6 +575,37 @@ impl ParseCommonMessagesFromUpstream for Upstream {
     }
 }
 
+impl ParseExtensionsNegotiationFromUpstream for Upstream {
+    fn handle_request_extensions_success(
+        &mut self,
+        m: RequestExtensionsSuccess,
+    ) -> Result<SendToExtensions, RolesLogicError> {
+        let supported_extensions = m.supported_extensions.into_inner();
+        info!(
+            "Received `RequestExtensionsSuccess` from Pool: supported_extensions={:?}",
+            supported_extensions
+        );
+        self.worker_hashrate_tracking_accepted =
+            supported_extensions.contains(&EXTENSION_TYPE_WORKER_HASHRATE_TRACKING);
+        if !self.worker_hashrate_tracking_accepted {
+            warn!("Pool does not support worker-specific hashrate tracking");
+        }
+        Ok(SendToExtensions::None(None))
+    }
+
+    fn handle_request_extensions_error(
+        &mut self,
+        m: RequestExtensionsError,
+    ) -> Result<SendToExtensions, RolesLogicError> {
+        warn!(
+            "Received `RequestExtensionsError` from Pool: unsupported_extensions={:?}, required_extensions={:?}",
+            m.unsupported_extensions, m.required_extensions
+        );
+        self.worker_hashrate_tracking_accepted = false;
+        Ok(SendToExtensions::None(None))
+    }
+}
+
 /// Connection-wide SV2 Upstream role messages parser implemented by a downstream ("downstream"
 /// here is relative to the SV2 Upstream role and is represented by this `Upstream` struct).
 impl ParseMiningMessagesFromUpstream<Downstream> for Upstream {
@@ -1,8 +1,9 @@
 use config_helpers::CoinbaseOutput;
 use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use network_helpers_sv2::{admission::AdmissionConfig, ConnectionLimitsConfig, RekeyConfig};
 use roles_logic_sv2::utils::CoinbaseOutput as CoinbaseOutput_;
 use serde::Deserialize;
-use std::{convert::TryInto, time::Duration};
+use std::{convert::TryInto, path::PathBuf, time::Duration};
 use stratum_common::bitcoin::{Amount, TxOut};
 
 /// Represents the configuration of a Job Declarator Server.
@@ -17,6 +18,8 @@ pub struct JobDeclaratorServerConfig {
     authority_public_key: Secp256k1PublicKey,
     authority_secret_key: Secp256k1SecretKey,
     cert_validity_sec: u64,
+    #[serde(default)]
+    authority_keys_file: Option<PathBuf>,
     coinbase_outputs: Vec<CoinbaseOutput>,
     core_rpc_url: String,
     core_rpc_port: u16,
@@ -24,6 +27,12 @@ pub struct JobDeclaratorServerConfig {
     core_rpc_pass: String,
     #[serde(deserialize_with = "config_helpers::duration_from_toml")]
     mempool_update_interval: Duration,
+    #[serde(default)]
+    admission: AdmissionConfig,
+    #[serde(default)]
+    rekey_policy: RekeyConfig,
+    #[serde(default)]
+    connection_limits: ConnectionLimitsConfig,
 }
 
 impl JobDeclaratorServerConfig {
@@ -43,12 +52,16 @@ impl JobDeclaratorServerConfig {
             authority_public_key,
             authority_secret_key,
             cert_validity_sec,
+            authority_keys_file: None,
             coinbase_outputs,
             core_rpc_url: core_rpc.url,
             core_rpc_port: core_rpc.port,
             core_rpc_user: core_rpc.user,
             core_rpc_pass: core_rpc.pass,
             mempool_update_interval,
+            admission: AdmissionConfig::default(),
+            rekey_policy: RekeyConfig::default(),
+            connection_limits: ConnectionLimitsConfig::default(),
         }
     }
 
@@ -97,6 +110,18 @@ impl JobDeclaratorServerConfig {
         self.cert_validity_sec
     }
 
+    /// Returns the file the authority keys are loaded from, if any.
+    ///
+    /// When set, it replaces the authority key pair of the config and is reloaded on `SIGHUP`.
+    pub fn authority_keys_file(&self) -> Option<&PathBuf> {
+        self.authority_keys_file.as_ref()
+    }
+
+    /// Sets the file the authority keys are loaded from.
+    pub fn set_authority_keys_file(&mut self, authority_keys_file: Option<PathBuf>) {
+        self.authority_keys_file = authority_keys_file;
+    }
+
     /// Returns whether async mining is allowed.
     pub fn async_mining_allowed(&self) -> bool {
         self.async_mining_allowed
@@ -107,6 +132,26 @@ impl JobDeclaratorServerConfig {
         self.mempool_update_interval
     }
 
+    /// Returns the limits applied to the Job Declarator Client connections.
+    pub fn admission(&self) -> &AdmissionConfig {
+        &self.admission
+    }
+
+    /// Sets the limits applied to the Job Declarator Client connections.
+    pub fn set_admission(&mut self, admission: AdmissionConfig) {
+        self.admission = admission;
+    }
+
+    /// Returns when the Job Declarator Client connections are rekeyed.
+    pub fn rekey_policy(&self) -> RekeyConfig {
+        self.rekey_policy
+    }
+
+    /// Returns the timeouts, keepalive and frame size limit of the Job Declarator Client connections.
+    pub fn connection_limits(&self) -> ConnectionLimitsConfig {
+        self.connection_limits
+    }
+
     /// Sets the listening address of Bitcoin core RPC.
     pub fn set_core_rpc_url(&mut self, url: String) {
         self.core_rpc_url = url;
@@ -4,11 +4,14 @@ use super::{
 };
 use async_channel::{Receiver, Sender};
 use binary_sv2::{B0255, U256};
-use codec_sv2::{HandshakeRole, Responder};
+use codec_sv2::HandshakeRole;
 use core::panic;
 use error_handling::handle_result;
 use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey, SignatureService};
-use network_helpers_sv2::noise_connection::Connection;
+use network_helpers_sv2::{
+    admission::AdmissionControl, certificate_provider::certificate_provider,
+    noise_connection::Connection,
+};
 use nohash_hasher::BuildNoHashHasher;
 use roles_logic_sv2::{
     common_messages_sv2::{
@@ -21,7 +24,7 @@ use roles_logic_sv2::{
 };
 use std::{collections::HashMap, convert::TryInto, sync::Arc};
 use tokio::{net::TcpListener, time::Duration};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use stratum_common::bitcoin::{
     consensus::{encode::serialize, Encodable},
@@ -462,98 +465,129 @@ impl JobDeclarator {
         new_block_sender: Sender<String>,
         sender_add_txs_to_mempool: Sender<AddTrasactionsToMempoolInner>,
     ) {
+        let certificate_provider = match certificate_provider(
+            *config.authority_public_key(),
+            *config.authority_secret_key(),
+            Duration::from_secs(config.cert_validity_sec()),
+            config.authority_keys_file().map(|path| path.as_path()),
+        ) {
+            Ok(certificate_provider) => certificate_provider,
+            Err(e) => {
+                error!("Invalid authority keys: {:?}", e);
+                return;
+            }
+        };
+        let admission = AdmissionControl::new(config.admission().clone());
         let listener = TcpListener::bind(config.listen_jd_address()).await.unwrap();
 
-        while let Ok((stream, _)) = listener.accept().await {
-            let responder = Responder::from_authority_kp(
-                &config.authority_public_key().into_bytes(),
-                &config.authority_secret_key().into_bytes(),
-                std::time::Duration::from_secs(config.cert_validity_sec()),
-            )
-            .unwrap();
+        while let Ok((stream, address)) = listener.accept().await {
+            let permit = match admission.admit(address.ip()) {
+                Ok(permit) => permit,
+                Err(e) => {
+                    warn!("Refusing connection from {}: {}", address, e);
+                    continue;
+                }
+            };
+            let responder = certificate_provider.responder().unwrap();
 
             let addr = stream.peer_addr();
 
-            if let Ok((receiver, sender)) =
-                Connection::new(stream, HandshakeRole::Responder(responder)).await
-            {
-                match receiver.recv().await {
-                    Ok(EitherFrame::Sv2(mut sv2_message)) => {
-                        debug!("Received SV2 message: {:?}", sv2_message);
-                        let payload = sv2_message.payload();
-
-                        if let Ok(setup_connection) =
-                            binary_sv2::from_bytes::<SetupConnection>(payload)
-                        {
-                            let flag = setup_connection.flags;
-                            let is_valid = SetupConnection::check_flags(
-                                Protocol::JobDeclarationProtocol,
-                                config.async_mining_allowed() as u32,
-                                flag,
-                            );
+            let connection = Connection::new_with_config(
+                stream,
+                HandshakeRole::Responder(responder),
+                config.rekey_policy().into(),
+                permit.into_connection_config(config.connection_limits().into()),
+            )
+            .await;
+            let (receiver, sender) = match connection {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Noise handshake with {} failed: {:?}", address, e);
+                    if let Err(e) = status_tx
+                        .send(status::Status {
+                            state: status::State::Healthy(format!(
+                                "Noise handshake with {} failed: {:?}",
+                                address, e
+                            )),
+                        })
+                        .await
+                    {
+                        error!("Encountered Error but status channel is down: {}", e);
+                    }
+                    continue;
+                }
+            };
+            match receiver.recv().await {
+                Ok(EitherFrame::Sv2(mut sv2_message)) => {
+                    debug!("Received SV2 message: {:?}", sv2_message);
+                    let payload = sv2_message.payload();
 
-                            if is_valid {
-                                let success_message = SetupConnectionSuccess {
-                                    used_version: 2,
-                                    flags: (setup_connection.flags & 1u32),
-                                };
-                                info!("Sending success message for proxy");
-                                let sv2_frame: StdFrame = JdsMessages::Common(success_message.into())
-        .try_into()
-        .expect("Failed to convert setup connection response message to standard frame");
+                    if let Ok(setup_connection) = binary_sv2::from_bytes::<SetupConnection>(payload)
+                    {
+                        let flag = setup_connection.flags;
+                        let is_valid = SetupConnection::check_flags(
+                            Protocol::JobDeclarationProtocol,
+                            config.async_mining_allowed() as u32,
+                            flag,
+                        );
 
-                                sender.send(sv2_frame.into()).await.unwrap();
+                        if is_valid {
+                            let success_message = SetupConnectionSuccess {
+                                used_version: 2,
+                                flags: (setup_connection.flags & 1u32),
+                            };
+                            info!("Sending success message for proxy");
+                            let sv2_frame: StdFrame = JdsMessages::Common(success_message.into())
+    .try_into()
+    .expect("Failed to convert setup connection response message to standard frame");
 
-                                let jddownstream = Arc::new(Mutex::new(
-                                    JobDeclaratorDownstream::new(
-                                        (setup_connection.flags & 1u32) != 0u32, /* this takes a
-                                                                                  * bool instead
-                                                                                  * of u32 */
-                                        receiver.clone(),
-                                        sender.clone(),
-                                        &config,
-                                        mempool.clone(),
-                                        sender_add_txs_to_mempool.clone(), /* each downstream has its own sender (multi producer single consumer) */
-                                    ),
-                                ));
+                            sender.send(sv2_frame.into()).await.unwrap();
 
-                                JobDeclaratorDownstream::start(
-                                    jddownstream,
-                                    status_tx.clone(),
-                                    new_block_sender.clone(),
-                                );
-                            } else {
-                                let error_message = SetupConnectionError {
-                                    flags: flag,
-                                    error_code: "unsupported-feature-flags"
-                                        .to_string()
-                                        .into_bytes()
-                                        .try_into()
-                                        .unwrap(),
-                                };
-                                info!("Sending error message for proxy");
-                                let sv2_frame: StdFrame = JdsMessages::Common(error_message.into())
-        .try_into()
-        .expect("Failed to convert setup connection response message to standard frame");
+                            let jddownstream = Arc::new(Mutex::new(JobDeclaratorDownstream::new(
+                                (setup_connection.flags & 1u32) != 0u32, /* this takes a
+                                                                          * bool instead
+                                                                          * of u32 */
+                                receiver.clone(),
+                                sender.clone(),
+                                &config,
+                                mempool.clone(),
+                                sender_add_txs_to_mempool.clone(), /* each downstream has its own sender (multi producer single consumer) */
+                            )));
 
-                                sender.send(sv2_frame.into()).await.unwrap();
-                            }
+                            JobDeclaratorDownstream::start(
+                                jddownstream,
+                                status_tx.clone(),
+                                new_block_sender.clone(),
+                            );
                         } else {
-                            error!("Error parsing SetupConnection message");
+                            let error_message = SetupConnectionError {
+                                flags: flag,
+                                error_code: "unsupported-feature-flags"
+                                    .to_string()
+                                    .into_bytes()
+                                    .try_into()
+                                    .unwrap(),
+                            };
+                            info!("Sending error message for proxy");
+                            let sv2_frame: StdFrame = JdsMessages::Common(error_message.into())
+    .try_into()
+    .expect("Failed to convert setup connection response message to standard frame");
+
+                            sender.send(sv2_frame.into()).await.unwrap();
                         }
-                    }
-                    Ok(EitherFrame::HandShake(handshake_message)) => {
-                        error!(
-                            "Unexpected handshake message from upstream: {:?} at {:?}",
-                            handshake_message, addr
-                        );
-                    }
-                    Err(e) => {
-                        error!("Error receiving message: {:?}", e);
+                    } else {
+                        error!("Error parsing SetupConnection message");
                     }
                 }
-            } else {
-                error!("Cannot connect to {:?}", addr);
+                Ok(EitherFrame::HandShake(handshake_message)) => {
+                    error!(
+                        "Unexpected handshake message from upstream: {:?} at {:?}",
+                        handshake_message, addr
+                    );
+                }
+                Err(e) => {
+                    error!("Error receiving message: {:?}", e);
+                }
             }
         }
     }
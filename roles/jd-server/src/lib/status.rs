@@ -12,6 +12,16 @@ pub enum Sender {
     Upstream(async_channel::Sender<Status>),
 }
 
+impl Sender {
+    pub async fn send(&self, status: Status) -> Result<(), async_channel::SendError<Status>> {
+        match self {
+            Self::Downstream(inner) => inner.send(status).await,
+            Self::DownstreamListener(inner) => inner.send(status).await,
+            Self::Upstream(inner) => inner.send(status).await,
+        }
+    }
+}
+
 impl Clone for Sender {
     fn clone(&self) -> Self {
         match self {
@@ -1,28 +1,40 @@
-use std::{convert::TryInto, sync::Arc};
+use std::{
+    convert::TryInto,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
 
 use async_channel::{Receiver, SendError, Sender};
-use tokio::{net::TcpListener, sync::oneshot::Receiver as TokioReceiver};
-use tracing::{debug, info, trace, warn};
+use tokio::{net::TcpListener, sync::watch};
+use tracing::{debug, error, info, trace, warn};
 
 use super::{
+    get_common_routing_logic, get_routing_logic,
     routing_logic::{CommonRouter, CommonRoutingLogic, MiningRouter, MiningRoutingLogic},
+    status,
     upstream_mining::{StdFrame as UpstreamFrame, UpstreamMiningNode},
+    RLogic,
 };
 use codec_sv2::{StandardEitherFrame, StandardSv2Frame};
 use network_helpers_sv2::plain_connection::PlainConnection;
 use roles_logic_sv2::{
     common_messages_sv2::{SetupConnection, SetupConnectionSuccess},
-    common_properties::{CommonDownstreamData, IsDownstream, IsMiningDownstream},
+    common_properties::{CommonDownstreamData, IsDownstream, IsMiningDownstream, IsMiningUpstream},
     errors::Error,
     handlers::{
         common::{ParseCommonMessagesFromDownstream, SendTo as SendToCommon},
         mining::{ParseMiningMessagesFromDownstream, SendTo, SupportedChannelTypes},
     },
     mining_sv2::*,
-    parsers::{AnyMessage, Mining, MiningDeviceMessages},
+    parsers::{AnyMessage, IsSv2Message, Mining, MiningDeviceMessages},
     utils::Mutex,
+    vardiff::{Vardiff, VardiffConfig},
 };
 
+/// How often the vardiff of the downstream channel is checked, the engine enforce its own minimum
+/// retarget interval.
+const VARDIFF_TIMER_INTERVAL: Duration = Duration::from_secs(15);
+
 pub type Message = MiningDeviceMessages<'static>;
 pub type StdFrame = StandardSv2Frame<Message>;
 pub type EitherFrame = StandardEitherFrame<Message>;
@@ -38,6 +50,16 @@ pub struct DownstreamMiningNode {
     sender: Sender<EitherFrame>,
     pub status: DownstreamMiningNodeStatus,
     upstream: Option<Arc<Mutex<UpstreamMiningNode>>>,
+    routing_logic: Weak<Mutex<RLogic>>,
+    // The mutex this downstream is in, it is registered with the routing logic and the upstream
+    // when a channel is opened, so that it can be removed from them when the downstream exits.
+    self_mutex: Weak<Mutex<Self>>,
+    // Hash rate added to the upstream when the channel is opened, removed when the downstream
+    // exits.
+    hash_rate: u64,
+    // Only for channels opened on extended upstream channels, where the proxy set the downstream
+    // target. With group upstream channels targets are set by the upstream.
+    vardiff: Option<Vardiff>,
 }
 
 #[derive(Debug, Clone)]
@@ -59,6 +81,21 @@ pub enum Channel {
         data: CommonDownstreamData,
         channel_id: u32,
     },
+    DownstreamNonHomUpstreamExtended {
+        data: CommonDownstreamData,
+        channel_id: u32,
+    },
+}
+
+impl Channel {
+    /// Id of the channel opened by the downstream
+    pub fn channel_id(&self) -> u32 {
+        match self {
+            Channel::DownstreamHomUpstreamGroup { channel_id, .. } => *channel_id,
+            Channel::DownstreamHomUpstreamExtended { channel_id, .. } => *channel_id,
+            Channel::DownstreamNonHomUpstreamExtended { channel_id, .. } => *channel_id,
+        }
+    }
 }
 
 impl DownstreamMiningNodeStatus {
@@ -122,6 +159,21 @@ impl DownstreamMiningNodeStatus {
             DownstreamMiningNodeStatus::ChannelOpened(..) => panic!("Channel already opened"),
         }
     }
+
+    fn open_channel_for_down_non_hom_up_extended(&mut self, channel_id: u32) {
+        match self {
+            DownstreamMiningNodeStatus::Initializing => panic!(),
+            DownstreamMiningNodeStatus::Paired(data) => {
+                let channel = Channel::DownstreamNonHomUpstreamExtended {
+                    data: *data,
+                    channel_id,
+                };
+                let self_ = Self::ChannelOpened(channel);
+                let _ = std::mem::replace(self, self_);
+            }
+            DownstreamMiningNodeStatus::ChannelOpened(..) => panic!("Channel already opened"),
+        }
+    }
 }
 
 impl PartialEq for DownstreamMiningNode {
@@ -144,22 +196,123 @@ impl DownstreamMiningNode {
         self.status
             .open_channel_for_down_hom_up_extended(channel_id, group_id);
     }
+    pub fn open_channel_for_down_non_hom_up_extended(&mut self, channel_id: u32) {
+        self.status
+            .open_channel_for_down_non_hom_up_extended(channel_id);
+    }
 
-    pub fn new(receiver: Receiver<EitherFrame>, sender: Sender<EitherFrame>, id: u32) -> Self {
-        Self {
-            receiver,
-            sender,
-            status: DownstreamMiningNodeStatus::Initializing,
-            upstream: None,
-            id,
+    /// Creates a new [`DownstreamMiningNode`] wrapped in an `Arc<Mutex>`, the one registered with
+    /// the upstream when the downstream opens a channel.
+    pub fn new_as_mutex(
+        receiver: Receiver<EitherFrame>,
+        sender: Sender<EitherFrame>,
+        id: u32,
+        routing_logic: Weak<Mutex<RLogic>>,
+    ) -> Arc<Mutex<Self>> {
+        Arc::new_cyclic(|self_mutex| {
+            Mutex::new(Self {
+                receiver,
+                sender,
+                status: DownstreamMiningNodeStatus::Initializing,
+                upstream: None,
+                id,
+                routing_logic,
+                self_mutex: self_mutex.clone(),
+                hash_rate: 0,
+                vardiff: None,
+            })
+        })
+    }
+
+    /// Return the hash rate that this downstream added to its upstream, only the first time it is
+    /// called, so that the upstream removes it once.
+    pub fn take_hash_rate(&mut self) -> u64 {
+        std::mem::take(&mut self.hash_rate)
+    }
+
+    // The handlers are called with the downstream locked, so its mutex is alive
+    fn self_mutex(&self) -> Arc<Mutex<Self>> {
+        self.self_mutex
+            .upgrade()
+            .expect("Downstream not created with new_as_mutex")
+    }
+
+    // Count the share for the vardiff if it has been accepted, if the channel must be retargeted
+    // update the upstream channel factory and return the `SetTarget` for the downstream
+    fn on_share_response(&mut self, response: &Mining<'static>) -> Option<Mining<'static>> {
+        let channel_id = match (response, &self.status) {
+            (
+                Mining::SubmitSharesSuccess(_),
+                DownstreamMiningNodeStatus::ChannelOpened(channel),
+            ) => channel.channel_id(),
+            _ => return None,
+        };
+        let new_target = self.vardiff.as_mut()?.on_share(Instant::now())?;
+        self.upstream
+            .as_ref()?
+            .safe_lock(|u| u.update_downstream_target(channel_id, new_target.clone()))
+            .ok()?;
+        debug!("Vardiff: new target for channel {}", channel_id);
+        Some(Mining::SetTarget(SetTarget {
+            channel_id,
+            maximum_target: new_target.into(),
+        }))
+    }
+
+    // Respond to a share with the upstream response and, if the channel has been retargeted, with
+    // a `SetTarget`
+    fn respond_to_share(&mut self, res: Mining<'static>) -> SendTo<UpstreamMiningNode> {
+        match self.on_share_response(&res) {
+            Some(set_target) => {
+                SendTo::Multiple(vec![SendTo::Respond(res), SendTo::Respond(set_target)])
+            }
+            None => SendTo::Respond(res),
+        }
+    }
+
+    /// Periodically check if the downstream hashrate changed, if so update the channel target and
+    /// send `SetTarget` to the downstream. Return an error if the downstream is not reachable.
+    async fn vardiff_loop(self_mutex: Arc<Mutex<Self>>) -> Result<(), super::error::Error> {
+        let mut interval = tokio::time::interval(VARDIFF_TIMER_INTERVAL);
+        loop {
+            interval.tick().await;
+            let (upstream, channel_id) =
+                match self_mutex.safe_lock(|s| match (&s.vardiff, &s.upstream, &s.status) {
+                    (Some(_), Some(up), DownstreamMiningNodeStatus::ChannelOpened(channel)) => {
+                        Some((up.clone(), channel.channel_id()))
+                    }
+                    _ => None,
+                })? {
+                    Some(v) => v,
+                    None => continue,
+                };
+            let upstream_target = upstream.safe_lock(|u| u.upstream_target())?;
+            let new_target = self_mutex.safe_lock(|s| {
+                s.vardiff.as_mut().and_then(|v| {
+                    v.set_min_target(upstream_target);
+                    v.on_timer(Instant::now())
+                })
+            })?;
+            if let Some(new_target) = new_target {
+                upstream
+                    .safe_lock(|u| u.update_downstream_target(channel_id, new_target.clone()))?;
+                debug!("Vardiff: new target for channel {}", channel_id);
+                let set_target = Mining::SetTarget(SetTarget {
+                    channel_id,
+                    maximum_target: new_target.into(),
+                });
+                let frame: StdFrame = MiningDeviceMessages::Mining(set_target).try_into()?;
+                Self::send(self_mutex.clone(), frame).await?;
+            }
         }
     }
 
     /// Send SetupConnectionSuccess to donwstream and start processing new messages coming from
-    /// downstream
+    /// downstream, until the downstream disconnect or the proxy is shut down
     pub async fn start(
         self_mutex: Arc<Mutex<Self>>,
         setup_connection_success: SetupConnectionSuccess,
+        mut shutdown_rx: watch::Receiver<()>,
     ) {
         if self_mutex
             .safe_lock(|self_| self_.status.is_paired())
@@ -167,31 +320,67 @@ impl DownstreamMiningNode {
         {
             let setup_connection_success: MiningDeviceMessages = setup_connection_success.into();
 
+            if DownstreamMiningNode::send(
+                self_mutex.clone(),
+                setup_connection_success.try_into().unwrap(),
+            )
+            .await
+            .is_err()
             {
-                DownstreamMiningNode::send(
-                    self_mutex.clone(),
-                    setup_connection_success.try_into().unwrap(),
-                )
-                .await
-                .unwrap();
+                Self::exit(self_mutex);
+                return;
             }
             let receiver = self_mutex
                 .safe_lock(|self_| self_.receiver.clone())
                 .unwrap();
 
-            while let Ok(message) = receiver.recv().await {
-                let incoming: StdFrame = message.try_into().unwrap();
-                Self::next(self_mutex.clone(), incoming).await;
+            let vardiff = tokio::spawn({
+                let self_mutex = self_mutex.clone();
+                async move {
+                    if let Err(e) = Self::vardiff_loop(self_mutex.clone()).await {
+                        let id = self_mutex.safe_lock(|s| s.id).unwrap_or_default();
+                        warn!("Vardiff stopped for downstream {}: {:?}", id, e);
+                    }
+                }
+            });
+            loop {
+                let message = tokio::select! {
+                    message = receiver.recv() => match message {
+                        Ok(message) => message,
+                        Err(_) => break,
+                    },
+                    _ = shutdown_rx.changed() => break,
+                };
+                let incoming: StdFrame = match message.try_into() {
+                    Ok(frame) => frame,
+                    Err(_) => {
+                        warn!("Received an invalid frame from downstream");
+                        break;
+                    }
+                };
+                if let Err(e) = Self::next(self_mutex.clone(), incoming).await {
+                    let id = self_mutex.safe_lock(|s| s.id).unwrap();
+                    error!("Closing downstream {}: {:?}", id, e);
+                    break;
+                }
             }
+            vardiff.abort();
             Self::exit(self_mutex);
         } else {
             panic!()
         }
     }
 
-    /// Parse the received message and relay it to the right upstream
-    pub async fn next(self_mutex: Arc<Mutex<Self>>, mut incoming: StdFrame) {
-        let message_type = incoming.get_header().unwrap().msg_type();
+    /// Parse the received message and relay it to the right upstream. An error means that the
+    /// downstream can not be served anymore and must be closed.
+    pub async fn next(
+        self_mutex: Arc<Mutex<Self>>,
+        mut incoming: StdFrame,
+    ) -> Result<(), super::error::Error> {
+        let message_type = incoming
+            .get_header()
+            .ok_or(Error::UnexpectedMessage(0))?
+            .msg_type();
         let payload = incoming.payload();
 
         let next_message_to_send = ParseMiningMessagesFromDownstream::handle_message_mining(
@@ -200,87 +389,81 @@ impl DownstreamMiningNode {
             payload,
         );
 
-        match next_message_to_send {
-            Ok(SendTo::RelaySameMessageToRemote(upstream_mutex)) => {
+        match next_message_to_send? {
+            SendTo::RelaySameMessageToRemote(upstream_mutex) => {
                 let sv2_frame: codec_sv2::Sv2Frame<AnyMessage, buffer_sv2::Slice> =
                     incoming.map(|payload| payload.try_into().unwrap());
-                UpstreamMiningNode::send(upstream_mutex.clone(), sv2_frame)
-                    .await
-                    .unwrap();
+                UpstreamMiningNode::send(upstream_mutex.clone(), sv2_frame).await?;
             }
-            Ok(SendTo::RelayNewMessageToRemote(upstream_mutex, message)) => {
+            SendTo::RelayNewMessageToRemote(upstream_mutex, message) => {
                 let message = AnyMessage::Mining(message);
                 let frame: UpstreamFrame = message.try_into().unwrap();
-                UpstreamMiningNode::send(upstream_mutex.clone(), frame)
-                    .await
-                    .unwrap();
+                UpstreamMiningNode::send(upstream_mutex.clone(), frame).await?;
             }
-            Ok(SendTo::Respond(message)) => {
+            SendTo::Respond(message) => {
                 let message = MiningDeviceMessages::Mining(message);
                 let frame: StdFrame = message.try_into().unwrap();
-                DownstreamMiningNode::send(self_mutex.clone(), frame)
-                    .await
-                    .unwrap();
+                DownstreamMiningNode::send(self_mutex.clone(), frame).await?;
             }
-            Ok(SendTo::Multiple(sends_to)) => {
+            SendTo::Multiple(sends_to) => {
                 for message in sends_to {
                     match message {
-                        roles_logic_sv2::handlers::SendTo_::Respond(m) => match m {
-                            Mining::NewMiningJob(_) => {
+                        SendTo::Respond(m) => match m {
+                            Mining::NewMiningJob(_)
+                            | Mining::NewExtendedMiningJob(_)
+                            | Mining::OpenStandardMiningChannelSuccess(_)
+                            | Mining::OpenExtendedMiningChannelSuccess(_)
+                            | Mining::OpenMiningChannelError(_)
+                            | Mining::SetNewPrevHash(_)
+                            | Mining::SubmitSharesSuccess(_)
+                            | Mining::SubmitSharesError(_)
+                            | Mining::SetTarget(_) => {
                                 let message = MiningDeviceMessages::Mining(m);
                                 let frame: StdFrame = message.try_into().unwrap();
-                                DownstreamMiningNode::send(self_mutex.clone(), frame)
-                                    .await
-                                    .unwrap();
+                                DownstreamMiningNode::send(self_mutex.clone(), frame).await?;
                             }
-                            Mining::OpenStandardMiningChannelSuccess(_) => {
-                                let message = MiningDeviceMessages::Mining(m);
-                                let frame: StdFrame = message.try_into().unwrap();
-                                DownstreamMiningNode::send(self_mutex.clone(), frame)
-                                    .await
-                                    .unwrap();
-                            }
-                            Mining::SetNewPrevHash(_) => {
-                                let message = MiningDeviceMessages::Mining(m);
-                                let frame: StdFrame = message.try_into().unwrap();
-                                DownstreamMiningNode::send(self_mutex.clone(), frame)
-                                    .await
-                                    .unwrap();
-                            }
-                            m => panic!("{:?}", m),
+                            m => return Err(Error::UnexpectedMessage(m.message_type()).into()),
                         },
-                        m => panic!("{:?}", m),
+                        SendTo::None(_) => (),
+                        m => {
+                            return Err(super::error::Error::Custom(format!(
+                                "Unexpected SendTo for downstream: {:?}",
+                                m
+                            )))
+                        }
                     }
                 }
             }
-            Ok(SendTo::None(_)) => (),
-            Ok(_) => panic!(),
-            Err(_) => todo!(),
+            SendTo::None(_) => (),
+            m => {
+                return Err(super::error::Error::Custom(format!(
+                    "Unexpected SendTo for downstream: {:?}",
+                    m
+                )))
+            }
         }
+        Ok(())
     }
 
     /// Send a message downstream
     pub async fn send(
         self_mutex: Arc<Mutex<Self>>,
         sv2_frame: StdFrame,
-    ) -> Result<(), SendError<StdFrame>> {
+    ) -> Result<(), SendError<EitherFrame>> {
         let either_frame = sv2_frame.into();
         let sender = self_mutex.safe_lock(|self_| self_.sender.clone()).unwrap();
-        match sender.send(either_frame).await {
-            Ok(_) => Ok(()),
-            Err(_) => {
-                todo!()
-            }
-        }
+        sender.send(either_frame).await
     }
 
     pub fn exit(self_: Arc<Mutex<Self>>) {
         if let Some(up) = self_.safe_lock(|s| s.upstream.clone()).unwrap() {
             UpstreamMiningNode::remove_dowstream(up, &self_);
         };
+        // Closing the sender shut down the connection
         self_
             .safe_lock(|s| {
                 s.receiver.close();
+                s.sender.close();
             })
             .unwrap();
     }
@@ -289,7 +472,7 @@ impl DownstreamMiningNode {
 /// It impl UpstreamMining cause the proxy act as an upstream node for the DownstreamMiningNode
 impl ParseMiningMessagesFromDownstream<UpstreamMiningNode> for DownstreamMiningNode {
     fn get_channel_type(&self) -> SupportedChannelTypes {
-        SupportedChannelTypes::Group
+        SupportedChannelTypes::GroupAndExtended
     }
 
     fn is_work_selection_enabled(&self) -> bool {
@@ -314,7 +497,7 @@ impl ParseMiningMessagesFromDownstream<UpstreamMiningNode> for DownstreamMiningN
         );
         debug!("OpenStandardMiningChannel: {:?}", req);
         let downstream_mining_data = self.get_downstream_mining_data();
-        let routing_logic = super::get_routing_logic();
+        let routing_logic = get_routing_logic(&self.routing_logic);
 
         let upstream = match routing_logic {
             MiningRoutingLogic::Proxy(r_logic) => {
@@ -322,23 +505,28 @@ impl ParseMiningMessagesFromDownstream<UpstreamMiningNode> for DownstreamMiningN
                 let up = r_logic
                     .safe_lock(|r_logic| {
                         r_logic.on_open_standard_channel(
-                            Arc::new(Mutex::new(self.clone())),
+                            self.self_mutex(),
                             &mut req.clone(),
                             &downstream_mining_data,
                         )
                     })?;
                 trace!("On OpenStandardMiningChannel best candidate is: {:?}", up);
-                Some(up?)
+                let up = up?;
+                up.safe_lock(|up| up.add_hash_rate(req.nominal_hash_rate as u64))?;
+                self.hash_rate += req.nominal_hash_rate as u64;
+                self.upstream = Some(up.clone());
+                Some(up)
             }
             // Variant just used for phantom data is ok to panic
             MiningRoutingLogic::_P(_) => panic!("Must use either MiningRoutingLogic::None or MiningRoutingLogic::Proxy for `routing_logic` param"),
-            _ => unreachable!()
+            // The proxy has been dropped
+            MiningRoutingLogic::None => return Err(Error::NoUpstreamsConnected),
         };
 
         let channel_id = upstream
             .as_ref()
             .expect("No upstream initialized")
-            .safe_lock(|s| s.channel_ids.safe_lock(|r| r.next()).unwrap())
+            .safe_lock(|s| s.new_downstream_channel_id())
             .unwrap();
         let cloned = upstream.as_ref().expect("No upstream initialized").clone();
 
@@ -359,6 +547,15 @@ impl ParseMiningMessagesFromDownstream<UpstreamMiningNode> for DownstreamMiningN
                                 m.channel_id,
                                 m.group_channel_id,
                             );
+                            self.vardiff = Some(Vardiff::with_target(
+                                VardiffConfig {
+                                    min_target: up.upstream_target(),
+                                    ..VardiffConfig::new(up.downstream_share_per_minute())
+                                },
+                                req.nominal_hash_rate,
+                                m.target.clone().into(),
+                                Instant::now(),
+                            ));
                         }
                     }
                     let messages = messages.into_iter().map(SendTo::Respond).collect();
@@ -372,9 +569,65 @@ impl ParseMiningMessagesFromDownstream<UpstreamMiningNode> for DownstreamMiningN
 
     fn handle_open_extended_mining_channel(
         &mut self,
-        _: OpenExtendedMiningChannel,
+        req: OpenExtendedMiningChannel,
     ) -> Result<SendTo<UpstreamMiningNode>, Error> {
-        todo!()
+        info!(
+            "Received OpenExtendedMiningChannel from: {} with id: {}",
+            std::str::from_utf8(req.user_identity.as_ref()).unwrap_or("Unknown identity"),
+            req.get_request_id_as_u32()
+        );
+        debug!("OpenExtendedMiningChannel: {:?}", req);
+        let downstream_mining_data = self.get_downstream_mining_data();
+        let routing_logic = get_routing_logic(&self.routing_logic);
+
+        let upstream = match routing_logic {
+            MiningRoutingLogic::Proxy(r_logic) => {
+                trace!("On OpenExtendedMiningChannel r_logic is: {:?}", r_logic);
+                let up = r_logic.safe_lock(|r_logic| {
+                    r_logic.on_open_extended_channel(
+                        self.self_mutex(),
+                        &mut req.clone(),
+                        &downstream_mining_data,
+                    )
+                })?;
+                trace!("On OpenExtendedMiningChannel best candidate is: {:?}", up);
+                let up = up?;
+                up.safe_lock(|up| up.add_hash_rate(req.nominal_hash_rate as u64))?;
+                self.hash_rate += req.nominal_hash_rate as u64;
+                self.upstream = Some(up.clone());
+                up
+            }
+            // Variant just used for phantom data is ok to panic
+            MiningRoutingLogic::_P(_) => panic!("Must use either MiningRoutingLogic::None or MiningRoutingLogic::Proxy for `routing_logic` param"),
+            // The proxy has been dropped
+            MiningRoutingLogic::None => return Err(Error::NoUpstreamsConnected),
+        };
+
+        let messages = upstream.safe_lock(|up| {
+            up.open_extended_channel_down(
+                req.request_id,
+                req.nominal_hash_rate,
+                req.min_extranonce_size,
+            )
+        })??;
+        let (share_per_minute, upstream_target) =
+            upstream.safe_lock(|up| (up.downstream_share_per_minute(), up.upstream_target()))?;
+        for m in &messages {
+            if let Mining::OpenExtendedMiningChannelSuccess(m) = m {
+                self.open_channel_for_down_non_hom_up_extended(m.channel_id);
+                self.vardiff = Some(Vardiff::with_target(
+                    VardiffConfig {
+                        min_target: upstream_target.clone(),
+                        ..VardiffConfig::new(share_per_minute)
+                    },
+                    req.nominal_hash_rate,
+                    m.target.clone().into(),
+                    Instant::now(),
+                ));
+            }
+        }
+        let messages = messages.into_iter().map(SendTo::Respond).collect();
+        Ok(SendTo::Multiple(messages))
     }
 
     fn handle_update_channel(
@@ -394,8 +647,11 @@ impl ParseMiningMessagesFromDownstream<UpstreamMiningNode> for DownstreamMiningN
         // sending them upstream If that is the case it should be
         // done by GroupChannel not here
         match &self.status {
-            DownstreamMiningNodeStatus::Initializing => todo!(),
-            DownstreamMiningNodeStatus::Paired(_) => todo!(),
+            DownstreamMiningNodeStatus::Initializing | DownstreamMiningNodeStatus::Paired(_) => {
+                Err(Error::UnexpectedMessage(
+                    const_sv2::MESSAGE_TYPE_SUBMIT_SHARES_STANDARD,
+                ))
+            }
             DownstreamMiningNodeStatus::ChannelOpened(Channel::DownstreamHomUpstreamGroup {
                 ..
             }) => {
@@ -409,17 +665,37 @@ impl ParseMiningMessagesFromDownstream<UpstreamMiningNode> for DownstreamMiningN
                 // Safe unwrap is channel have been opened it means that the dowsntream is paired
                 // with an upstream
                 let remote = self.upstream.as_ref().unwrap();
-                let res = UpstreamMiningNode::handle_std_shr(remote.clone(), m).unwrap();
-                Ok(SendTo::Respond(res))
+                let res = UpstreamMiningNode::handle_std_shr(remote.clone(), m)?;
+                Ok(self.respond_to_share(res))
             }
+            DownstreamMiningNodeStatus::ChannelOpened(
+                Channel::DownstreamNonHomUpstreamExtended { .. },
+            ) => Err(Error::UnexpectedMessage(
+                const_sv2::MESSAGE_TYPE_SUBMIT_SHARES_STANDARD,
+            )),
         }
     }
 
     fn handle_submit_shares_extended(
         &mut self,
-        _: SubmitSharesExtended,
+        m: SubmitSharesExtended,
     ) -> Result<SendTo<UpstreamMiningNode>, Error> {
-        todo!()
+        info!("Received SubmitSharesExtended");
+        debug!("SubmitSharesExtended {:?}", m);
+        match &self.status {
+            DownstreamMiningNodeStatus::ChannelOpened(
+                Channel::DownstreamNonHomUpstreamExtended { .. },
+            ) => {
+                // Safe unwrap is channel have been opened it means that the dowsntream is paired
+                // with an upstream
+                let remote = self.upstream.as_ref().unwrap();
+                let res = UpstreamMiningNode::handle_ext_shr(remote.clone(), m.into_static())?;
+                Ok(self.respond_to_share(res))
+            }
+            _ => Err(Error::UnexpectedMessage(
+                const_sv2::MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
+            )),
+        }
     }
 
     fn handle_set_custom_mining_job(
@@ -439,67 +715,54 @@ impl ParseCommonMessagesFromDownstream for DownstreamMiningNode {
             "Received `SetupConnection`: version={}, flags={:b}",
             m.min_version, m.flags
         );
-        let routing_logic = super::get_common_routing_logic();
+        let routing_logic = get_common_routing_logic(&self.routing_logic);
         match routing_logic {
             CommonRoutingLogic::Proxy(r_logic) => {
                 trace!("On SetupConnection r_logic is {:?}", r_logic);
                 let result = r_logic.safe_lock(|r_logic| r_logic.on_setup_connection(&m))?;
                 let (data, message) = result?;
-                let upstream = match super::get_routing_logic() {
-                    MiningRoutingLogic::Proxy(proxy_routing) => proxy_routing
-                        .safe_lock(|r| r.downstream_to_upstream_map.get(&data).unwrap()[0].clone())
-                        .unwrap(),
-                    _ => unreachable!(),
-                };
-                self.upstream = Some(upstream);
-
+                // The upstream is selected when a channel is opened
                 self.status.pair(data);
                 Ok(SendToCommon::RelayNewMessageToRemote(
                     Arc::new(Mutex::new(())),
                     message.into(),
                 ))
             }
-            _ => unreachable!(),
+            // The proxy has been dropped
+            _ => Err(Error::NoUpstreamsConnected),
         }
     }
 }
 
 pub async fn listen_for_downstream_mining(
     listener: TcpListener,
-    mut shutdown_rx: TokioReceiver<()>,
+    routing_logic: Arc<Mutex<RLogic>>,
+    status_tx: status::Sender,
+    mut shutdown_rx: watch::Receiver<()>,
 ) {
     let mut ids = roles_logic_sv2::utils::Id::new();
     loop {
         tokio::select! {
             accept_result = listener.accept() => {
-                let (stream, _) = accept_result.expect("failed to accept downstream connection");
+                let stream = match accept_result {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        error!("Failed to accept downstream connection: {:?}", e);
+                        status::send_status(&status_tx, e.into()).await;
+                        return;
+                    }
+                };
                 let (receiver, sender): (Receiver<EitherFrame>, Sender<EitherFrame>) =
                     PlainConnection::new(stream).await;
-                let node = DownstreamMiningNode::new(receiver, sender, ids.next());
-
-                let mut incoming: StdFrame =
-                    node.receiver.recv().await.unwrap().try_into().unwrap();
-                let message_type = incoming.get_header().unwrap().msg_type();
-                let payload = incoming.payload();
-                let node = Arc::new(Mutex::new(node));
-
-                // Call handle_setup_connection or fail
-                let common_msg = DownstreamMiningNode::handle_message_common(
-                    node.clone(),
-                    message_type,
-                    payload,
-                ).expect("failed to process downstream message");
-
-
-                if let SendToCommon::RelayNewMessageToRemote(_, relay_msg) = common_msg {
-                    if let roles_logic_sv2::parsers::CommonMessages::SetupConnectionSuccess(setup_msg) = relay_msg {
-                        DownstreamMiningNode::start(node, setup_msg).await;
-                    }
-                } else {
-                    warn!("Received unexpected message from downstream");
-                }
+                let node = DownstreamMiningNode::new_as_mutex(
+                    receiver,
+                    sender,
+                    ids.next(),
+                    Arc::downgrade(&routing_logic),
+                );
+                tokio::spawn(setup_downstream(node, shutdown_rx.clone()));
             }
-            _ = &mut shutdown_rx => {
+            _ = shutdown_rx.changed() => {
                 info!("Closing listener");
                 return;
             }
@@ -507,6 +770,60 @@ pub async fn listen_for_downstream_mining(
     }
 }
 
+// Wait for the downstream SetupConnection and, if the downstream can be paired with an upstream,
+// start serving it
+async fn setup_downstream(
+    node: Arc<Mutex<DownstreamMiningNode>>,
+    mut shutdown_rx: watch::Receiver<()>,
+) {
+    let receiver = node.safe_lock(|node| node.receiver.clone()).unwrap();
+    let received = tokio::select! {
+        received = receiver.recv() => received,
+        _ = shutdown_rx.changed() => return,
+    };
+    let mut incoming: StdFrame = match received {
+        Ok(frame) => match frame.try_into() {
+            Ok(frame) => frame,
+            Err(_) => {
+                warn!("Received an invalid frame from downstream");
+                return;
+            }
+        },
+        Err(_) => {
+            warn!("Downstream disconnected before SetupConnection");
+            return;
+        }
+    };
+    let message_type = match incoming.get_header() {
+        Some(header) => header.msg_type(),
+        None => {
+            warn!("Received a frame without header from downstream");
+            return;
+        }
+    };
+    let payload = incoming.payload();
+
+    // Call handle_setup_connection or fail
+    let common_msg =
+        match DownstreamMiningNode::handle_message_common(node.clone(), message_type, payload) {
+            Ok(msg) => msg,
+            Err(e) => {
+                warn!("Failed to process downstream message: {:?}", e);
+                return;
+            }
+        };
+
+    if let SendToCommon::RelayNewMessageToRemote(_, relay_msg) = common_msg {
+        if let roles_logic_sv2::parsers::CommonMessages::SetupConnectionSuccess(setup_msg) =
+            relay_msg
+        {
+            DownstreamMiningNode::start(node, setup_msg, shutdown_rx).await;
+        }
+    } else {
+        warn!("Received unexpected message from downstream");
+    }
+}
+
 impl IsDownstream for DownstreamMiningNode {
     fn get_downstream_mining_data(&self) -> CommonDownstreamData {
         match self.status {
@@ -520,7 +837,402 @@ impl IsDownstream for DownstreamMiningNode {
                 data,
                 ..
             }) => data,
+            DownstreamMiningNodeStatus::ChannelOpened(
+                Channel::DownstreamNonHomUpstreamExtended { data, .. },
+            ) => data,
         }
     }
 }
 impl IsMiningDownstream for DownstreamMiningNode {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        routing_logic::MiningProxyRoutingLogic,
+        selectors::{DownstreamMiningSelector, GeneralMiningSelector},
+        upstream_mining::HasDownstreamSelector,
+        upstream_selection::UpstreamSelection,
+    };
+    use super::*;
+    use binary_sv2::{Seq0255, Sv2Option, U256};
+    use roles_logic_sv2::{
+        handlers::mining::ParseMiningMessagesFromUpstream,
+        utils::{GroupId, Id},
+    };
+    use std::{
+        collections::HashMap,
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+    };
+
+    const UPSTREAM_CHANNEL_ID: u32 = 1;
+    const UPSTREAM_EXTRANONCE_PREFIX_LEN: usize = 4;
+    const UPSTREAM_EXTRANONCE_SIZE: u16 = 16;
+    const JOB_ID: u32 = 1;
+
+    // Legacy coinbase with a script sig made only of the extranonce and a single empty output
+    fn coinbase_prefix_and_suffix() -> (Vec<u8>, Vec<u8>) {
+        let extranonce_len = UPSTREAM_EXTRANONCE_PREFIX_LEN + UPSTREAM_EXTRANONCE_SIZE as usize;
+        let mut prefix = vec![1, 0, 0, 0, 1];
+        prefix.extend_from_slice(&[0; 32]);
+        prefix.extend_from_slice(&[0xff; 4]);
+        prefix.push(extranonce_len as u8);
+        let mut suffix = vec![0xff; 4];
+        suffix.push(1);
+        suffix.extend_from_slice(&[0; 8]);
+        suffix.push(0);
+        suffix.extend_from_slice(&[0; 4]);
+        (prefix, suffix)
+    }
+
+    // Upstream with an opened extended channel, an active job and an upstream target that no
+    // share can meet, so that valid shares are never relayed upstream.
+    fn upstream() -> Arc<Mutex<UpstreamMiningNode>> {
+        let upstream = Arc::new(Mutex::new(UpstreamMiningNode::new(
+            0,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 34254),
+            [0; 32],
+            super::super::ChannelKind::Extended,
+            Arc::new(Mutex::new(GroupId::new())),
+            Arc::new(Mutex::new(Id::new())),
+            10.0,
+            None,
+            None,
+            100_000.0,
+            false,
+            codec_sv2::RekeyPolicy::default(),
+            Weak::new(),
+            None,
+        )));
+        let (coinbase_tx_prefix, coinbase_tx_suffix) = coinbase_prefix_and_suffix();
+        upstream
+            .safe_lock(|u| {
+                u.handle_open_extended_mining_channel_success(OpenExtendedMiningChannelSuccess {
+                    request_id: 0,
+                    channel_id: UPSTREAM_CHANNEL_ID,
+                    target: U256::from([0; 32]),
+                    extranonce_size: UPSTREAM_EXTRANONCE_SIZE,
+                    extranonce_prefix: vec![0; UPSTREAM_EXTRANONCE_PREFIX_LEN].try_into().unwrap(),
+                })
+                .unwrap();
+                u.handle_new_extended_mining_job(NewExtendedMiningJob {
+                    channel_id: UPSTREAM_CHANNEL_ID,
+                    job_id: JOB_ID,
+                    min_ntime: Sv2Option::new(None),
+                    version: 0x2000_0000,
+                    version_rolling_allowed: true,
+                    merkle_path: Seq0255::new(vec![]).unwrap(),
+                    coinbase_tx_prefix: coinbase_tx_prefix.try_into().unwrap(),
+                    coinbase_tx_suffix: coinbase_tx_suffix.try_into().unwrap(),
+                })
+                .unwrap();
+                u.handle_set_new_prev_hash(SetNewPrevHash {
+                    channel_id: UPSTREAM_CHANNEL_ID,
+                    job_id: JOB_ID,
+                    prev_hash: U256::from([0; 32]),
+                    min_ntime: 0,
+                    nbits: 0x1d00_ffff,
+                })
+                .unwrap();
+            })
+            .unwrap();
+        upstream
+    }
+
+    // Downstream with an extended channel opened on `upstream`, every share meet its target and
+    // its vardiff retarget on the first share. The vardiff can not start from the maximum target
+    // as the hashrate estimation would overflow. Return the downstream, the sender of the messages
+    // coming from it, the receiver of the messages sent to it and its channel id.
+    #[allow(clippy::type_complexity)]
+    fn downstream(
+        upstream: Arc<Mutex<UpstreamMiningNode>>,
+    ) -> (
+        Arc<Mutex<DownstreamMiningNode>>,
+        Sender<EitherFrame>,
+        Receiver<EitherFrame>,
+        u32,
+    ) {
+        let (from_downstream, receiver) = async_channel::bounded(10);
+        let (sender, to_downstream) = async_channel::bounded(10);
+        let downstream = DownstreamMiningNode::new_as_mutex(receiver, sender, 1, Weak::new());
+        downstream
+            .safe_lock(|d| d.status.pair(downstream_proxy_data()))
+            .unwrap();
+
+        let request_id = 7;
+        let messages = upstream
+            .safe_lock(|u| {
+                u.get_remote_selector()
+                    .on_open_extended_channel_request(request_id, downstream.clone());
+                u.open_extended_channel_down(request_id, 1_000.0, 8)
+            })
+            .unwrap()
+            .unwrap();
+        let channel_id = match &messages[0] {
+            Mining::OpenExtendedMiningChannelSuccess(m) => m.channel_id,
+            m => panic!(
+                "Expected OpenExtendedMiningChannelSuccess, received {:?}",
+                m
+            ),
+        };
+        upstream
+            .safe_lock(|u| u.update_downstream_target(channel_id, [0xff; 32].into()))
+            .unwrap();
+        downstream
+            .safe_lock(|d| {
+                d.upstream = Some(upstream.clone());
+                d.open_channel_for_down_non_hom_up_extended(channel_id);
+                d.vardiff = Some(Vardiff::with_target(
+                    VardiffConfig {
+                        retarget_interval: Duration::ZERO,
+                        ..VardiffConfig::new(10.0)
+                    },
+                    1_000.0,
+                    easy_target(),
+                    Instant::now() - Duration::from_secs(60),
+                ));
+            })
+            .unwrap();
+        (downstream, from_downstream, to_downstream, channel_id)
+    }
+
+    fn downstream_proxy_data() -> CommonDownstreamData {
+        CommonDownstreamData {
+            header_only: false,
+            work_selection: false,
+            version_rolling: true,
+        }
+    }
+
+    // Routing logic with `upstream` as the only upstream of the downstream proxies
+    fn routing_logic(upstream: Arc<Mutex<UpstreamMiningNode>>) -> Arc<Mutex<RLogic>> {
+        upstream.safe_lock(|u| u.set_connected()).unwrap();
+        let mut downstream_to_upstream_map = HashMap::new();
+        downstream_to_upstream_map.insert(downstream_proxy_data(), vec![upstream.clone()]);
+        Arc::new(Mutex::new(MiningProxyRoutingLogic {
+            upstream_selector: GeneralMiningSelector::new(vec![upstream]),
+            downstream_id_generator: Id::new(),
+            downstream_to_upstream_map,
+            upstream_selection: UpstreamSelection::default().build(&[]),
+        }))
+    }
+
+    // Downstream proxy that completed the SetupConnection, return it with the receiver of the
+    // messages sent to it
+    fn paired_downstream(
+        routing_logic: &Arc<Mutex<RLogic>>,
+        id: u32,
+    ) -> (Arc<Mutex<DownstreamMiningNode>>, Receiver<EitherFrame>) {
+        let (_, receiver) = async_channel::bounded(10);
+        let (sender, to_downstream) = async_channel::bounded(10);
+        let downstream =
+            DownstreamMiningNode::new_as_mutex(receiver, sender, id, Arc::downgrade(routing_logic));
+        downstream
+            .safe_lock(|d| d.status.pair(downstream_proxy_data()))
+            .unwrap();
+        (downstream, to_downstream)
+    }
+
+    // Open an extended channel, return its id and extranonce prefix
+    async fn open_extended_channel(
+        downstream: Arc<Mutex<DownstreamMiningNode>>,
+        to_downstream: &Receiver<EitherFrame>,
+    ) -> (u32, Vec<u8>) {
+        let request = serialized(Mining::OpenExtendedMiningChannel(
+            OpenExtendedMiningChannel {
+                request_id: 1,
+                user_identity: "proxy".to_string().try_into().unwrap(),
+                nominal_hash_rate: 1_000.0,
+                max_target: [0xff; 32].into(),
+                min_extranonce_size: 8,
+            },
+        ));
+        DownstreamMiningNode::next(downstream, request)
+            .await
+            .unwrap();
+        let frame: StdFrame = to_downstream.recv().await.unwrap().try_into().unwrap();
+        let mut bytes = vec![0; frame.encoded_length()];
+        frame.serialize(&mut bytes).unwrap();
+        let mut frame = StdFrame::from_bytes(bytes.into()).unwrap();
+        let message_type = frame.get_header().unwrap().msg_type();
+        let message: Mining = (message_type, frame.payload()).try_into().unwrap();
+        match message {
+            Mining::OpenExtendedMiningChannelSuccess(m) => {
+                (m.channel_id, m.extranonce_prefix.inner_as_ref().to_vec())
+            }
+            m => panic!(
+                "Expected OpenExtendedMiningChannelSuccess, received {:?}",
+                m
+            ),
+        }
+    }
+
+    fn easy_target() -> Target {
+        let mut target = [0xff; 32];
+        target[31] = 0x7f;
+        target.into()
+    }
+
+    // Frames received from the connection are serialized
+    fn serialized(message: Mining<'static>) -> StdFrame {
+        let frame: StdFrame = MiningDeviceMessages::Mining(message).try_into().unwrap();
+        let mut bytes = vec![0; frame.encoded_length()];
+        frame.serialize(&mut bytes).unwrap();
+        StdFrame::from_bytes(bytes.into()).unwrap()
+    }
+
+    fn submit_shares_extended(channel_id: u32, job_id: u32) -> StdFrame {
+        serialized(Mining::SubmitSharesExtended(SubmitSharesExtended {
+            channel_id,
+            sequence_number: 0,
+            job_id,
+            nonce: 0,
+            ntime: 0,
+            version: 0x2000_0000,
+            extranonce: vec![0; 12].try_into().unwrap(),
+        }))
+    }
+
+    fn setup_connection_success() -> SetupConnectionSuccess {
+        SetupConnectionSuccess {
+            used_version: 2,
+            flags: 0,
+        }
+    }
+
+    async fn next_message_type(receiver: &Receiver<EitherFrame>) -> u8 {
+        let frame: StdFrame = receiver.recv().await.unwrap().try_into().unwrap();
+        frame.get_header().unwrap().msg_type()
+    }
+
+    #[tokio::test]
+    async fn share_that_trigger_a_retarget_is_answered_with_set_target() {
+        let upstream = upstream();
+        let (downstream, _, to_downstream, channel_id) = downstream(upstream);
+
+        DownstreamMiningNode::next(downstream, submit_shares_extended(channel_id, JOB_ID))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            next_message_type(&to_downstream).await,
+            const_sv2::MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS
+        );
+        assert_eq!(
+            next_message_type(&to_downstream).await,
+            const_sv2::MESSAGE_TYPE_SET_TARGET
+        );
+        assert!(to_downstream.is_empty());
+    }
+
+    #[tokio::test]
+    async fn extended_share_for_an_unknown_job_is_rejected() {
+        let upstream = upstream();
+        let (downstream, _, to_downstream, channel_id) = downstream(upstream);
+
+        DownstreamMiningNode::next(downstream, submit_shares_extended(channel_id, JOB_ID + 1))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            next_message_type(&to_downstream).await,
+            const_sv2::MESSAGE_TYPE_SUBMIT_SHARES_ERROR
+        );
+        assert!(to_downstream.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unexpected_share_close_the_downstream() {
+        let upstream = upstream();
+        let (downstream, from_downstream, to_downstream, channel_id) = downstream(upstream.clone());
+        let (_shutdown_tx, shutdown_rx) = watch::channel(());
+        let task = tokio::spawn(DownstreamMiningNode::start(
+            downstream.clone(),
+            setup_connection_success(),
+            shutdown_rx,
+        ));
+
+        // Standard shares are not expected on an extended channel
+        let share = serialized(Mining::SubmitSharesStandard(SubmitSharesStandard {
+            channel_id,
+            sequence_number: 0,
+            job_id: JOB_ID,
+            nonce: 0,
+            ntime: 0,
+            version: 0x2000_0000,
+        }));
+        from_downstream.send(share.into()).await.unwrap();
+        task.await.unwrap();
+
+        assert_eq!(
+            next_message_type(&to_downstream).await,
+            const_sv2::MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS
+        );
+        assert!(to_downstream.recv().await.is_err());
+        assert!(from_downstream.is_closed());
+        assert!(upstream
+            .safe_lock(|u| u.get_remote_selector().get_all_downstreams().is_empty())
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn downstream_proxy_reconnects() {
+        let upstream = upstream();
+        let routing_logic = routing_logic(upstream.clone());
+
+        let (first, to_first) = paired_downstream(&routing_logic, 1);
+        let (first_channel, first_prefix) = open_extended_channel(first.clone(), &to_first).await;
+        let registered = upstream
+            .safe_lock(|u| {
+                u.get_remote_selector()
+                    .downstream_from_channel_id(first_channel)
+            })
+            .unwrap()
+            .unwrap();
+        assert!(Arc::ptr_eq(&registered, &first));
+        assert_eq!(upstream.safe_lock(|u| u.total_hash_rate()).unwrap(), 1_000);
+
+        DownstreamMiningNode::exit(first.clone());
+        assert!(upstream
+            .safe_lock(|u| u.get_remote_selector().get_all_downstreams().is_empty())
+            .unwrap());
+        assert_eq!(upstream.safe_lock(|u| u.total_hash_rate()).unwrap(), 0);
+        // The downstream exits again when its task stops, nothing must be removed twice
+        DownstreamMiningNode::exit(first);
+        assert_eq!(upstream.safe_lock(|u| u.total_hash_rate()).unwrap(), 0);
+
+        let (second, to_second) = paired_downstream(&routing_logic, 2);
+        let (second_channel, second_prefix) =
+            open_extended_channel(second.clone(), &to_second).await;
+        assert_ne!(second_channel, first_channel);
+        // The extranonce prefix of the closed channel is given to the new one
+        assert_eq!(second_prefix, first_prefix);
+        let downstreams = upstream
+            .safe_lock(|u| u.get_remote_selector().get_all_downstreams())
+            .unwrap();
+        assert_eq!(downstreams.len(), 1);
+        assert!(Arc::ptr_eq(&downstreams[0], &second));
+        assert_eq!(upstream.safe_lock(|u| u.total_hash_rate()).unwrap(), 1_000);
+    }
+
+    #[tokio::test]
+    async fn shutdown_close_the_downstream() {
+        let upstream = upstream();
+        let (downstream, from_downstream, to_downstream, _) = downstream(upstream);
+        let (shutdown_tx, shutdown_rx) = watch::channel(());
+        let task = tokio::spawn(DownstreamMiningNode::start(
+            downstream,
+            setup_connection_success(),
+            shutdown_rx,
+        ));
+        assert_eq!(
+            next_message_type(&to_downstream).await,
+            const_sv2::MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS
+        );
+
+        shutdown_tx.send(()).unwrap();
+        task.await.unwrap();
+
+        assert!(to_downstream.recv().await.is_err());
+        assert!(from_downstream.is_closed());
+    }
+}
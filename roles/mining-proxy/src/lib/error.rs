@@ -1,10 +1,14 @@
 use async_channel::SendError;
 use codec_sv2::StandardEitherFrame;
-use roles_logic_sv2::parsers::AnyMessage;
-use std::net::SocketAddr;
+use roles_logic_sv2::parsers::{AnyMessage, MiningDeviceMessages};
+use std::{
+    net::SocketAddr,
+    sync::{MutexGuard, PoisonError},
+};
 
 pub type Message = AnyMessage<'static>;
 pub type EitherFrame = StandardEitherFrame<Message>;
+pub type DownstreamEitherFrame = StandardEitherFrame<MiningDeviceMessages<'static>>;
 
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
@@ -12,8 +16,15 @@ pub type EitherFrame = StandardEitherFrame<Message>;
 #[allow(dead_code)]
 pub enum Error {
     SendError(SendError<EitherFrame>),
+    DownstreamSendError(SendError<DownstreamEitherFrame>),
+    RolesLogic(roles_logic_sv2::Error),
     UpstreamNotAvailabe(SocketAddr),
     SetupConnectionError(String),
+    Io(std::io::Error),
+    PoisonLock(String),
+    /// No more upstreams are available
+    NoUpstreamsAvailable,
+    Custom(String),
 }
 
 impl From<SendError<EitherFrame>> for Error {
@@ -21,3 +32,27 @@ impl From<SendError<EitherFrame>> for Error {
         Error::SendError(error)
     }
 }
+
+impl From<SendError<DownstreamEitherFrame>> for Error {
+    fn from(error: SendError<DownstreamEitherFrame>) -> Self {
+        Error::DownstreamSendError(error)
+    }
+}
+
+impl From<roles_logic_sv2::Error> for Error {
+    fn from(error: roles_logic_sv2::Error) -> Self {
+        Error::RolesLogic(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl<T> From<PoisonError<MutexGuard<'_, T>>> for Error {
+    fn from(e: PoisonError<MutexGuard<T>>) -> Self {
+        Error::PoisonLock(e.to_string())
+    }
+}
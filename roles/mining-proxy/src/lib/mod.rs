@@ -2,86 +2,92 @@ pub mod downstream_mining;
 pub mod error;
 pub mod routing_logic;
 pub mod selectors;
+pub mod status;
 pub mod upstream_mining;
+pub mod upstream_selection;
 
-use once_cell::sync::OnceCell;
+use async_channel::unbounded;
+use error::Error;
+use network_helpers_sv2::RekeyConfig;
 use roles_logic_sv2::utils::{GroupId, Id, Mutex};
 use routing_logic::{CommonRoutingLogic, MiningProxyRoutingLogic, MiningRoutingLogic};
 use selectors::GeneralMiningSelector;
 use serde::Deserialize;
-use std::{net::SocketAddr, sync::Arc};
-use tokio::{net::TcpListener, sync::oneshot};
-use tracing::info;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Weak},
+};
+use tokio::{net::TcpListener, select, sync::watch};
+use tracing::{error, info, warn};
 use upstream_mining::UpstreamMiningNode;
+use upstream_selection::UpstreamSelection;
 
-type RLogic = MiningProxyRoutingLogic<
+pub type RLogic = MiningProxyRoutingLogic<
     downstream_mining::DownstreamMiningNode,
     upstream_mining::UpstreamMiningNode,
     upstream_mining::ProxyRemoteSelector,
 >;
 
-/// Panic whene we are looking one of this 2 global mutex would force the proxy to go down as every
-/// part of the program depend on them.
-/// SAFTEY note: we use global mutable memory instead of a dedicated struct that use a dedicated
-/// task to change the mutable state and communicate with the other parts of the program via
-/// messages cause it is impossible for a task to panic while is using one of the two below Mutex.
-/// So it make sense to use shared mutable memory to lower the complexity of the codebase and to
-/// have some performance gain.
-pub static ROUTING_LOGIC: OnceCell<Mutex<RLogic>> = OnceCell::new();
 static MIN_EXTRANONCE_SIZE: u16 = 6;
 static EXTRANONCE_RANGE_1_LENGTH: usize = 4;
 
-pub async fn initialize_upstreams(min_version: u16, max_version: u16) {
-    let upstreams = ROUTING_LOGIC
-        .get()
-        .expect("BUG: ROUTING_LOGIC has not been set yet")
+/// Connect to all the configured upstreams and keep in the routing logic only the ones that are
+/// available.
+pub async fn initialize_upstreams(
+    routing_logic: &Arc<Mutex<RLogic>>,
+    min_version: u16,
+    max_version: u16,
+) {
+    let upstreams = routing_logic
         .safe_lock(|r_logic| r_logic.upstream_selector.upstreams.clone())
         .unwrap();
     let available_upstreams = upstream_mining::scan(upstreams, min_version, max_version).await;
-    ROUTING_LOGIC
-        .get()
-        .unwrap()
+    routing_logic
         .safe_lock(|rl| rl.upstream_selector.update_upstreams(available_upstreams))
         .unwrap();
 }
 
-fn remove_upstream(id: u32) {
-    let upstreams = ROUTING_LOGIC
-        .get()
-        .expect("BUG: ROUTING_LOGIC has not been set yet")
-        .safe_lock(|r_logic| r_logic.upstream_selector.upstreams.clone())
-        .unwrap();
-    let mut updated_upstreams = vec![];
-    for upstream in upstreams {
-        if upstream.safe_lock(|s| s.get_id()).unwrap() != id {
-            updated_upstreams.push(upstream)
-        }
-    }
-    ROUTING_LOGIC
-        .get()
+/// Remove an upstream from the routing logic, return the number of the remaining upstreams.
+fn remove_upstream(routing_logic: &Arc<Mutex<RLogic>>, id: u32) -> usize {
+    routing_logic
+        .safe_lock(|rl| {
+            let updated_upstreams: Vec<_> = rl
+                .upstream_selector
+                .upstreams
+                .iter()
+                .filter(|upstream| upstream.safe_lock(|s| s.get_id()).unwrap() != id)
+                .cloned()
+                .collect();
+            let remaining = updated_upstreams.len();
+            rl.upstream_selector.update_upstreams(updated_upstreams);
+            for candidates in rl.downstream_to_upstream_map.values_mut() {
+                candidates.retain(|upstream| upstream.safe_lock(|s| s.get_id()).unwrap() != id);
+            }
+            remaining
+        })
         .unwrap()
-        .safe_lock(|rl| rl.upstream_selector.update_upstreams(updated_upstreams))
-        .unwrap();
 }
 
-pub fn get_routing_logic() -> MiningRoutingLogic<
+/// Routing logic used by downstreams and upstreams, that only keep a weak reference to it.
+/// `MiningRoutingLogic::None` is returned if the proxy has been dropped.
+pub fn get_routing_logic(
+    routing_logic: &Weak<Mutex<RLogic>>,
+) -> MiningRoutingLogic<
     downstream_mining::DownstreamMiningNode,
     upstream_mining::UpstreamMiningNode,
     upstream_mining::ProxyRemoteSelector,
     RLogic,
 > {
-    MiningRoutingLogic::Proxy(
-        ROUTING_LOGIC
-            .get()
-            .expect("BUG: ROUTING_LOGIC was not set yet"),
-    )
+    match routing_logic.upgrade() {
+        Some(routing_logic) => MiningRoutingLogic::Proxy(routing_logic),
+        None => MiningRoutingLogic::None,
+    }
 }
-pub fn get_common_routing_logic() -> CommonRoutingLogic<RLogic> {
-    CommonRoutingLogic::Proxy(
-        ROUTING_LOGIC
-            .get()
-            .expect("BUG: ROUTING_LOGIC was not set yet"),
-    )
+pub fn get_common_routing_logic(routing_logic: &Weak<Mutex<RLogic>>) -> CommonRoutingLogic<RLogic> {
+    match routing_logic.upgrade() {
+        Some(routing_logic) => CommonRoutingLogic::Proxy(routing_logic),
+        None => CommonRoutingLogic::None,
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -90,6 +96,16 @@ pub struct UpstreamMiningValues {
     pub port: u16,
     pub pub_key: key_utils::Secp256k1PublicKey,
     pub channel_kind: ChannelKind,
+    /// Percentage of the hashrate sent to this upstream, used by the `Weighted` strategy
+    #[serde(default)]
+    pub weight: Option<f32>,
+    /// Lower is preferred, used by the `Priority` strategy
+    #[serde(default)]
+    pub priority: Option<u32>,
+    /// Downstreams with a `user_identity` that start with one of these prefixes are sent to this
+    /// upstream, used by the `UserIdentityPrefix` strategy
+    #[serde(default)]
+    pub user_identity_prefixes: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, Copy)]
@@ -108,84 +124,273 @@ pub struct Configuration {
     pub downstream_share_per_minute: f32,
     pub expected_total_downstream_hr: f32,
     pub reconnect: bool,
+    #[serde(default)]
+    pub upstream_selection_strategy: UpstreamSelection,
+    /// When the upstream connections are rekeyed, never by default.
+    #[serde(default)]
+    pub rekey_policy: RekeyConfig,
 }
-pub async fn initialize_r_logic(
+pub fn initialize_r_logic(
     upstreams: &[UpstreamMiningValues],
     group_id: Arc<Mutex<GroupId>>,
     config: Configuration,
-) -> RLogic {
-    let channel_ids = Arc::new(Mutex::new(Id::new()));
-    let mut upstream_mining_nodes = Vec::with_capacity(upstreams.len());
-    for (index, upstream_) in upstreams.iter().enumerate() {
-        let socket = SocketAddr::new(upstream_.address.parse().unwrap(), upstream_.port);
-
-        let upstream = Arc::new(Mutex::new(UpstreamMiningNode::new(
-            index as u32,
-            socket,
-            upstream_.pub_key.into_bytes(),
-            upstream_.channel_kind,
-            group_id.clone(),
-            channel_ids.clone(),
-            config.downstream_share_per_minute,
-            None,
-            None,
-            config.expected_total_downstream_hr,
-            config.reconnect,
-        )));
-
-        match upstream_.channel_kind {
-            ChannelKind::Group => (),
-            ChannelKind::Extended => (),
+    status_tx: status::Sender,
+) -> Arc<Mutex<RLogic>> {
+    // Upstreams keep a weak reference to the routing logic that own them, so that dropping the
+    // proxy drop the whole routing state.
+    Arc::new_cyclic(|routing_logic: &Weak<Mutex<RLogic>>| {
+        let channel_ids = Arc::new(Mutex::new(Id::new()));
+        let mut upstream_mining_nodes = Vec::with_capacity(upstreams.len());
+        for (index, upstream_) in upstreams.iter().enumerate() {
+            let socket = SocketAddr::new(upstream_.address.parse().unwrap(), upstream_.port);
+
+            let upstream = Arc::new(Mutex::new(UpstreamMiningNode::new(
+                index as u32,
+                socket,
+                upstream_.pub_key.into_bytes(),
+                upstream_.channel_kind,
+                group_id.clone(),
+                channel_ids.clone(),
+                config.downstream_share_per_minute,
+                None,
+                None,
+                config.expected_total_downstream_hr,
+                config.reconnect,
+                config.rekey_policy.into(),
+                routing_logic.clone(),
+                Some(status_tx.clone()),
+            )));
+
+            upstream_mining_nodes.push(upstream);
+        }
+        let upstream_selector = GeneralMiningSelector::new(upstream_mining_nodes);
+        Mutex::new(MiningProxyRoutingLogic {
+            upstream_selector,
+            downstream_id_generator: Id::new(),
+            downstream_to_upstream_map: std::collections::HashMap::new(),
+            upstream_selection: config.upstream_selection_strategy.build(upstreams),
+        })
+    })
+}
+
+/// A mining proxy instance. Every instance own its routing state, so more proxies can run in the
+/// same process and a proxy can be stopped and started again.
+#[derive(Debug, Clone)]
+pub struct MiningProxy {
+    config: Configuration,
+    status_tx: Arc<std::sync::Mutex<Option<async_channel::Sender<status::Status>>>>,
+}
+
+impl MiningProxy {
+    pub fn new(config: Configuration) -> MiningProxy {
+        MiningProxy {
+            config,
+            status_tx: Arc::new(std::sync::Mutex::new(None)),
+        }
+    }
+
+    /// Connect to the upstreams, start to listen for downstream connections and spawn the status
+    /// loop. Return as soon as the proxy is listening.
+    pub async fn start(&self) -> Result<(), Error> {
+        let config = self.config.clone();
+        let (status_tx, status_rx) = unbounded();
+
+        if let Ok(mut s_tx) = self.status_tx.lock() {
+            *s_tx = Some(status_tx.clone());
+        } else {
+            error!("Failed to access MiningProxy status lock");
+            return Err(Error::Custom(
+                "Failed to access MiningProxy status lock".to_string(),
+            ));
         }
 
-        upstream_mining_nodes.push(upstream);
+        let group_id = Arc::new(Mutex::new(GroupId::new()));
+        let routing_logic = initialize_r_logic(
+            &config.upstreams,
+            group_id,
+            config.clone(),
+            status::Sender::Upstream(status_tx.clone()),
+        );
+
+        info!("Initializing upstream scanner");
+        initialize_upstreams(
+            &routing_logic,
+            config.min_supported_version,
+            config.max_supported_version,
+        )
+        .await;
+        info!("Initializing downstream listener");
+
+        let socket = SocketAddr::new(
+            config
+                .listen_address
+                .parse()
+                .map_err(|_| Error::Custom(format!("Invalid address {}", config.listen_address)))?,
+            config.listen_mining_port,
+        );
+        let listener = TcpListener::bind(socket).await?;
+
+        info!("Listening for downstream mining connections on {}", socket);
+
+        let (send_stop_signal, recv_stop_signal) = watch::channel(());
+
+        tokio::spawn(downstream_mining::listen_for_downstream_mining(
+            listener,
+            routing_logic.clone(),
+            status::Sender::DownstreamListener(status_tx),
+            recv_stop_signal,
+        ));
+
+        // Start the status loop, see `./status.rs`
+        tokio::spawn(async move {
+            loop {
+                let task_status = select! {
+                    task_status = status_rx.recv() => task_status,
+                    interrupt_signal = tokio::signal::ctrl_c() => {
+                        match interrupt_signal {
+                            Ok(()) => {
+                                info!("Interrupt received");
+                            },
+                            Err(err) => {
+                                error!("Unable to listen for interrupt signal: {}", err);
+                                // we also shut down in case of error
+                            },
+                        }
+                        break;
+                    }
+                };
+                let task_status: status::Status = match task_status {
+                    Ok(task_status) => task_status,
+                    Err(_) => break,
+                };
+
+                match task_status.state {
+                    status::State::Shutdown => {
+                        info!("Received shutdown signal");
+                        break;
+                    }
+                    status::State::DownstreamShutdown(err) => {
+                        error!("SHUTDOWN from Downstream listener: {:?}", err);
+                        break;
+                    }
+                    status::State::UpstreamShutdown(err) => {
+                        error!("SHUTDOWN from Upstream: {:?}", err);
+                        break;
+                    }
+                    status::State::Healthy(msg) => {
+                        info!("HEALTHY message: {}", msg);
+                    }
+                }
+            }
+            let _ = send_stop_signal.send(());
+            let upstreams = routing_logic
+                .safe_lock(|r| r.upstream_selector.upstreams.clone())
+                .unwrap_or_default();
+            for upstream in upstreams {
+                UpstreamMiningNode::shutdown(upstream);
+            }
+            info!("Shutdown done");
+        });
+        Ok(())
     }
-    let upstream_selector = GeneralMiningSelector::new(upstream_mining_nodes);
-    MiningProxyRoutingLogic {
-        upstream_selector,
-        downstream_id_generator: Id::new(),
-        downstream_to_upstream_map: std::collections::HashMap::new(),
+
+    pub fn shutdown(&self) {
+        info!("Attempting to shutdown mining proxy");
+        if let Ok(status_tx) = &self.status_tx.lock() {
+            if let Some(status_tx) = status_tx.as_ref().cloned() {
+                info!("Mining proxy is running, sending shutdown signal");
+                tokio::spawn(async move {
+                    if let Err(e) = status_tx
+                        .send(status::Status {
+                            state: status::State::Shutdown,
+                        })
+                        .await
+                    {
+                        error!("Failed to send shutdown signal to status loop: {:?}", e);
+                    } else {
+                        info!("Sent shutdown signal to mining proxy");
+                    }
+                });
+            } else {
+                warn!("Mining proxy is not running.");
+            }
+        } else {
+            error!("Failed to access MiningProxy status lock");
+        }
     }
 }
 
-pub async fn start_mining_proxy(config: Configuration) {
-    let group_id = Arc::new(Mutex::new(GroupId::new()));
-    ROUTING_LOGIC
-        .set(Mutex::new(
-            initialize_r_logic(&config.upstreams, group_id, config.clone()).await,
-        ))
-        .expect("BUG: Failed to set ROUTING_LOGIC");
-
-    info!("Initializing upstream scanner");
-    initialize_upstreams(config.min_supported_version, config.max_supported_version).await;
-    info!("Initializing downstream listener");
-
-    let socket = SocketAddr::new(
-        config.listen_address.parse().unwrap(),
-        config.listen_mining_port,
-    );
-    let listener = TcpListener::bind(socket).await.unwrap();
-
-    info!("Listening for downstream mining connections on {}", socket);
-
-    let (shutdown_tx, shutdown_rx) = oneshot::channel();
-
-    let (_, res) = tokio::join!(
-        // Wait for downstream connection
-        downstream_mining::listen_for_downstream_mining(listener, shutdown_rx),
-        // handle SIGTERM/QUIT / ctrl+c
-        tokio::spawn(async {
-            tokio::signal::ctrl_c()
-                .await
-                .expect("Failed to listen to signals");
-            let _ = shutdown_tx.send(());
-            info!("Interrupt received");
-        })
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{str::FromStr, time::Duration};
+    use tokio::net::TcpStream;
 
-    if let Err(e) = res {
-        panic!("Failed to wait for clean exit: {:?}", e);
+    fn available_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
     }
 
-    info!("Shutdown done");
+    // Nothing listen on the upstream address, the proxy start anyway and wait for downstreams
+    fn config(listen_mining_port: u16) -> Configuration {
+        Configuration {
+            upstreams: vec![UpstreamMiningValues {
+                address: "127.0.0.1".to_string(),
+                port: available_port(),
+                pub_key: key_utils::Secp256k1PublicKey::from_str(
+                    "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72",
+                )
+                .unwrap(),
+                channel_kind: ChannelKind::Extended,
+                weight: None,
+                priority: None,
+                user_identity_prefixes: vec![],
+            }],
+            listen_address: "127.0.0.1".to_string(),
+            listen_mining_port,
+            max_supported_version: 2,
+            min_supported_version: 2,
+            downstream_share_per_minute: 1.0,
+            expected_total_downstream_hr: 10_000.0,
+            reconnect: false,
+            upstream_selection_strategy: Default::default(),
+            rekey_policy: Default::default(),
+        }
+    }
+
+    async fn wait_until_closed(port: u16) {
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Mining proxy still listening on port {}", port);
+    }
+
+    #[tokio::test]
+    async fn two_proxies_in_the_same_process_and_restart() {
+        let (port_a, port_b) = (available_port(), available_port());
+        let proxy_a = MiningProxy::new(config(port_a));
+        let proxy_b = MiningProxy::new(config(port_b));
+        proxy_a.start().await.unwrap();
+        proxy_b.start().await.unwrap();
+        assert!(TcpStream::connect(("127.0.0.1", port_a)).await.is_ok());
+        assert!(TcpStream::connect(("127.0.0.1", port_b)).await.is_ok());
+
+        proxy_a.shutdown();
+        wait_until_closed(port_a).await;
+        assert!(TcpStream::connect(("127.0.0.1", port_b)).await.is_ok());
+
+        proxy_a.start().await.unwrap();
+        assert!(TcpStream::connect(("127.0.0.1", port_a)).await.is_ok());
+
+        proxy_a.shutdown();
+        proxy_b.shutdown();
+        wait_until_closed(port_a).await;
+        wait_until_closed(port_b).await;
+    }
 }
@@ -20,7 +20,6 @@
 //! ## Future Work
 //!
 //! - Consider hiding all traits from the public API and exporting only marker traits.
-//! - Improve upstream selection logic to be configurable by the caller.
 
 use super::{
     downstream_mining::DownstreamMiningNode,
@@ -29,16 +28,19 @@ use super::{
         UpstreamMiningSelctor,
     },
     upstream_mining::HasDownstreamSelector,
+    upstream_selection::{connected, IsConnected, UpstreamSelectionStrategy},
 };
 use roles_logic_sv2::{
     common_messages_sv2::{
-        has_requires_std_job, Protocol, SetupConnection, SetupConnectionSuccess,
+        has_requires_std_job, has_version_rolling, Protocol, SetupConnection,
+        SetupConnectionSuccess,
     },
     common_properties::{
-        CommonDownstreamData, IsDownstream, IsMiningDownstream, IsMiningUpstream, IsUpstream,
-        PairSettings,
+        CommonDownstreamData, IsMiningDownstream, IsMiningUpstream, IsUpstream, PairSettings,
+    },
+    mining_sv2::{
+        OpenExtendedMiningChannel, OpenStandardMiningChannel, OpenStandardMiningChannelSuccess,
     },
-    mining_sv2::{OpenStandardMiningChannel, OpenStandardMiningChannelSuccess},
     utils::{Id, Mutex},
     Error,
 };
@@ -85,6 +87,14 @@ pub trait MiningRouter<
         upstream: &mut Up,
         request: &mut OpenStandardMiningChannelSuccess,
     ) -> Result<Arc<Mutex<Down>>, Error>;
+
+    /// Handles an `OpenExtendedMiningChannel` message from a downstream.
+    fn on_open_extended_channel(
+        &mut self,
+        downstream: Arc<Mutex<Down>>,
+        request: &mut OpenExtendedMiningChannel,
+        downstream_mining_data: &CommonDownstreamData,
+    ) -> Result<Arc<Mutex<Up>>, Error>;
 }
 
 /// A no-operation router for scenarios where no routing logic is needed.
@@ -121,13 +131,22 @@ impl<Down: IsMiningDownstream + D, Up: IsMiningUpstream<Down> + D + HasDownstrea
     ) -> Result<Arc<Mutex<Down>>, Error> {
         unreachable!()
     }
+
+    fn on_open_extended_channel(
+        &mut self,
+        _downstream: Arc<Mutex<Down>>,
+        _request: &mut OpenExtendedMiningChannel,
+        _downstream_mining_data: &CommonDownstreamData,
+    ) -> Result<Arc<Mutex<Up>>, Error> {
+        unreachable!()
+    }
 }
 
 /// Routing logic options for the common protocol.
 #[derive(Debug)]
 pub enum CommonRoutingLogic<Router: 'static + CommonRouter> {
     /// Proxy routing logic for the common protocol.
-    Proxy(&'static Mutex<Router>),
+    Proxy(Arc<Mutex<Router>>),
     /// No routing logic.
     None,
 }
@@ -141,7 +160,7 @@ pub enum MiningRoutingLogic<
     Router: 'static + MiningRouter<Down, Up, Sel>,
 > {
     /// Proxy routing logic for the mining protocol.
-    Proxy(&'static Mutex<Router>),
+    Proxy(Arc<Mutex<Router>>),
     /// No routing logic.
     None,
     /// Marker for the generic parameters.
@@ -152,7 +171,7 @@ impl<Router: CommonRouter> Clone for CommonRoutingLogic<Router> {
     fn clone(&self) -> Self {
         match self {
             Self::None => Self::None,
-            Self::Proxy(x) => Self::Proxy(x),
+            Self::Proxy(x) => Self::Proxy(x.clone()),
         }
     }
 }
@@ -167,7 +186,7 @@ impl<
     fn clone(&self) -> Self {
         match self {
             Self::None => Self::None,
-            Self::Proxy(x) => Self::Proxy(x),
+            Self::Proxy(x) => Self::Proxy(x.clone()),
             // Variant used only for PhantomData safe to panic here
             Self::_P(_) => panic!(),
         }
@@ -185,8 +204,10 @@ pub struct MiningProxyRoutingLogic<
     pub upstream_selector: GeneralMiningSelector<Sel, Down, Up>,
     /// ID generator for downstream entities.
     pub downstream_id_generator: Id,
-    /// Mapping from downstream to upstream entities.
+    /// Mapping from downstream to the upstream entities compatible with it.
     pub downstream_to_upstream_map: HashMap<CommonDownstreamData, Vec<Arc<Mutex<Up>>>>,
+    /// Strategy used to select the upstream when a downstream opens a channel.
+    pub upstream_selection: Box<dyn UpstreamSelectionStrategy<Down, Up>>,
 }
 
 impl<
@@ -218,6 +239,9 @@ impl<
             (Protocol::MiningProtocol, true) => {
                 self.on_setup_connection_mining_header_only(&pair_settings)
             }
+            (Protocol::MiningProtocol, false) => {
+                self.on_setup_connection_mining_non_header_only(&pair_settings)
+            }
             // TODO: Add handler for other protocols.
             _ => Err(Error::UnimplementedProtocol),
         }
@@ -225,7 +249,7 @@ impl<
 }
 
 impl<
-        Up: IsMiningUpstream<DownstreamMiningNode> + D + HasDownstreamSelector,
+        Up: IsMiningUpstream<DownstreamMiningNode> + D + HasDownstreamSelector + IsConnected,
         Sel: DownstreamMiningSelector<DownstreamMiningNode> + D,
     > MiningRouter<DownstreamMiningNode, Up, Sel>
     for MiningProxyRoutingLogic<DownstreamMiningNode, Up, Sel>
@@ -241,18 +265,12 @@ impl<
         request: &mut OpenStandardMiningChannel,
         downstream_mining_data: &CommonDownstreamData,
     ) -> Result<Arc<Mutex<Up>>, Error> {
-        let upstreams = self
-            .downstream_to_upstream_map
-            .get(downstream_mining_data)
-            .ok_or(Error::NoCompatibleUpstream(*downstream_mining_data))?;
-        // If we are here, a list of possible upstreams has already been selected.
-        // TODO: The upstream selection logic should be specified by the caller.
         let upstream =
-            Self::select_upstreams(&mut upstreams.to_vec()).ok_or(Error::NoUpstreamsConnected)?;
+            self.select_upstream(downstream_mining_data, request.user_identity.as_ref())?;
         let old_id = request.get_request_id_as_u32();
         let new_req_id = upstream.safe_lock(|u| u.get_mapper().unwrap().on_open_channel(old_id))?;
         request.update_id(new_req_id);
-        self.on_open_standard_channel_request_header_only(downstream, request)
+        self.on_open_standard_channel_request_header_only(downstream, request, upstream)
     }
 
     // Handles the `OpenStandardMiningChannelSuccess` message.
@@ -284,69 +302,50 @@ impl<
             request.channel_id,
         )
     }
-}
-
-// Selects the upstream with the lowest total hash rate.
-// # Panics
-// This function panics if the slice is empty, as it is internally guaranteed that this function
-// will only be called with non-empty vectors.
-fn minor_total_hr_upstream<Down, Up>(ups: &mut [Arc<Mutex<Up>>]) -> Arc<Mutex<Up>>
-where
-    Down: IsMiningDownstream + D,
-    Up: IsMiningUpstream<Down> + D,
-{
-    ups.iter_mut()
-        .reduce(|acc, item| {
-            // Safely locks and compares the total hash rate of each upstream.
-            if acc.safe_lock(|x| x.total_hash_rate()).unwrap()
-                < item.safe_lock(|x| x.total_hash_rate()).unwrap()
-            {
-                acc
-            } else {
-                item
-            }
-        })
-        .unwrap()
-        .clone() // Unwrap is safe because the function only operates on non-empty vectors.
-}
 
-// Filters upstream entities that are not configured for header-only mining.
-fn filter_header_only<Down, Up>(ups: &mut [Arc<Mutex<Up>>]) -> Vec<Arc<Mutex<Up>>>
-where
-    Down: IsMiningDownstream + D,
-    Up: IsMiningUpstream<Down> + D,
-{
-    ups.iter()
-        .filter(|up_mutex| {
-            up_mutex
-                .safe_lock(|up| !up.is_header_only())
-                .unwrap_or_default()
-        })
-        .cloned()
-        .collect()
+    // Handles the `OpenExtendedMiningChannel` message.
+    //
+    // Extended channels opened by downstream proxies are never relayed upstream: the proxy carves
+    // a sub-extranonce range out of its own upstream extended channel. Here we only select the
+    // upstream that will own the channel and record the request so that the upstream selector can
+    // pair the new channel id with the downstream.
+    fn on_open_extended_channel(
+        &mut self,
+        downstream: Arc<Mutex<DownstreamMiningNode>>,
+        request: &mut OpenExtendedMiningChannel,
+        downstream_mining_data: &CommonDownstreamData,
+    ) -> Result<Arc<Mutex<Up>>, Error> {
+        let upstream =
+            self.select_upstream(downstream_mining_data, request.user_identity.as_ref())?;
+        upstream.safe_lock(|u| {
+            let selector = u.get_remote_selector();
+            selector.on_open_extended_channel_request(request.request_id, downstream)
+        })?;
+        Ok(upstream)
+    }
 }
 
-// Selects the most appropriate upstream entity based on specific criteria.
-//
-// # Criteria
-// - If only one upstream is available, it is selected.
-// - If multiple upstreams exist, preference is given to those not configured as header-only.
-// - Among the remaining upstreams, the one with the lowest total hash rate is selected.
-fn select_upstream<Down, Up>(ups: &mut [Arc<Mutex<Up>>]) -> Option<Arc<Mutex<Up>>>
-where
-    Down: IsMiningDownstream + D,
-    Up: IsMiningUpstream<Down> + D,
+impl<
+        Down: IsMiningDownstream + D,
+        Up: IsMiningUpstream<Down> + D + HasDownstreamSelector + IsConnected,
+        Sel: DownstreamMiningSelector<Down> + D,
+    > MiningProxyRoutingLogic<Down, Up, Sel>
 {
-    if ups.is_empty() {
-        None
-    } else if ups.len() == 1 {
-        Some(ups[0].clone())
-    } else if !filter_header_only::<Down, Up>(ups).is_empty() {
-        Some(minor_total_hr_upstream::<Down, Up>(
-            &mut filter_header_only::<Down, Up>(ups),
-        ))
-    } else {
-        Some(minor_total_hr_upstream::<Down, Up>(ups))
+    // Selects an upstream, among the connected ones compatible with the downstream, with the
+    // configured strategy.
+    fn select_upstream(
+        &mut self,
+        downstream_mining_data: &CommonDownstreamData,
+        user_identity: &[u8],
+    ) -> Result<Arc<Mutex<Up>>, Error> {
+        let candidates = connected(
+            self.downstream_to_upstream_map
+                .get(downstream_mining_data)
+                .ok_or(Error::NoCompatibleUpstream(*downstream_mining_data))?,
+        );
+        self.upstream_selection
+            .select(&candidates, std::str::from_utf8(user_identity).ok())
+            .ok_or(Error::NoUpstreamsConnected)
     }
 }
 
@@ -356,22 +355,15 @@ impl<
         Sel: DownstreamMiningSelector<Down> + D,
     > MiningProxyRoutingLogic<Down, Up, Sel>
 {
-    // Selects an upstream entity from a list of available upstreams.
-    fn select_upstreams(ups: &mut [Arc<Mutex<Up>>]) -> Option<Arc<Mutex<Up>>> {
-        select_upstream::<Down, Up>(ups)
-    }
-
     /// Handles the `SetupConnection` process for header-only mining downstream's.
     ///
     /// This method selects compatible upstreams, assigns connection flags, and maps the
-    /// downstream to the selected upstreams.
+    /// downstream to the compatible upstreams.
     pub fn on_setup_connection_mining_header_only(
         &mut self,
         pair_settings: &PairSettings,
     ) -> Result<(CommonDownstreamData, SetupConnectionSuccess), Error> {
-        let mut upstreams = self.upstream_selector.on_setup_connection(pair_settings)?;
-        let upstream =
-            Self::select_upstreams(&mut upstreams.0).ok_or(Error::NoUpstreamsConnected)?;
+        let (upstreams, flags) = self.upstream_selector.on_setup_connection(pair_settings)?;
         let downstream_data = CommonDownstreamData {
             header_only: true,
             work_selection: false,
@@ -379,10 +371,36 @@ impl<
         };
         let message = SetupConnectionSuccess {
             used_version: 2,
-            flags: upstream.safe_lock(|u| u.get_flags())?,
+            flags,
+        };
+        // The upstream is selected when the downstream opens a channel
+        self.downstream_to_upstream_map
+            .insert(downstream_data, upstreams);
+        Ok((downstream_data, message))
+    }
+
+    /// Handles the `SetupConnection` process for non header-only mining downstream's.
+    ///
+    /// Non header-only downstreams (usually other proxies) open extended channels, the upstream
+    /// selected when the channel is opened must then be able to carve extranonce ranges for them
+    /// out of its own extended channel.
+    pub fn on_setup_connection_mining_non_header_only(
+        &mut self,
+        pair_settings: &PairSettings,
+    ) -> Result<(CommonDownstreamData, SetupConnectionSuccess), Error> {
+        let (upstreams, flags) = self.upstream_selector.on_setup_connection(pair_settings)?;
+        let downstream_data = CommonDownstreamData {
+            header_only: false,
+            work_selection: false,
+            version_rolling: has_version_rolling(pair_settings.flags),
+        };
+        let message = SetupConnectionSuccess {
+            used_version: 2,
+            flags,
         };
+        // The upstream is selected when the downstream opens a channel
         self.downstream_to_upstream_map
-            .insert(downstream_data, vec![upstream]);
+            .insert(downstream_data, upstreams);
         Ok((downstream_data, message))
     }
 
@@ -391,13 +409,8 @@ impl<
         &mut self,
         downstream: Arc<Mutex<DownstreamMiningNode>>,
         request: &OpenStandardMiningChannel,
+        upstream: Arc<Mutex<Up>>,
     ) -> Result<Arc<Mutex<Up>>, Error> {
-        let downstream_mining_data = downstream.safe_lock(|d| d.get_downstream_mining_data())?;
-        let upstream = self
-            .downstream_to_upstream_map
-            .get(&downstream_mining_data)
-            .ok_or(Error::NoCompatibleUpstream(downstream_mining_data))?[0]
-            .clone();
         upstream.safe_lock(|u| {
             let selector = u.get_remote_selector();
             selector.on_open_standard_channel_request(request.request_id.as_u32(), downstream)
@@ -87,6 +87,26 @@ impl<Down: IsMiningDownstream> DownstreamMiningSelector<Down>
         Ok(downstream)
     }
 
+    /// Records a request to open an extended channel with an associated downstream node.
+    fn on_open_extended_channel_request(&mut self, request_id: u32, downstream: Arc<Mutex<Down>>) {
+        self.request_id_to_remotes.insert(request_id, downstream);
+    }
+
+    fn on_open_extended_channel_success(
+        &mut self,
+        request_id: u32,
+        channel_id: u32,
+    ) -> Result<Arc<Mutex<Down>>, Error> {
+        let downstream = self
+            .request_id_to_remotes
+            .remove(&request_id)
+            .ok_or(Error::UnknownRequestId(request_id))?;
+        // Extended channels are not part of any group, they are only addressable by channel id
+        self.channel_id_to_downstream
+            .insert(channel_id, downstream.clone());
+        Ok(downstream)
+    }
+
     // Retrieves all downstream nodes associated with a standard/group channel ID.
     fn get_downstreams_in_channel(&self, channel_id: u32) -> Option<&Vec<Arc<Mutex<Down>>>> {
         self.channel_id_to_downstreams.get(&channel_id)
@@ -145,6 +165,21 @@ pub trait DownstreamMiningSelector<Downstream: IsMiningDownstream>:
         channel_id: u32,
     ) -> Result<Arc<Mutex<Downstream>>, Error>;
 
+    /// Handles a downstream node's request to open an extended channel.
+    fn on_open_extended_channel_request(
+        &mut self,
+        request_id: u32,
+        downstream: Arc<Mutex<Downstream>>,
+    );
+
+    /// Handles the successful opening of an extended channel with a downstream node. Returns an
+    /// error if the request ID is unknown.
+    fn on_open_extended_channel_success(
+        &mut self,
+        request_id: u32,
+        channel_id: u32,
+    ) -> Result<Arc<Mutex<Downstream>>, Error>;
+
     /// Retrieves all downstream nodes associated with a channel ID.
     fn get_downstreams_in_channel(&self, channel_id: u32) -> Option<&Vec<Arc<Mutex<Downstream>>>>;
 
@@ -209,6 +244,24 @@ impl<Down: IsMiningDownstream + D> DownstreamMiningSelector<Down> for NullDownst
         unreachable!("on_open_standard_channel_success")
     }
 
+    /// [`unreachable`] in this no-op implementation.
+    fn on_open_extended_channel_request(
+        &mut self,
+        _request_id: u32,
+        _downstream: Arc<Mutex<Down>>,
+    ) {
+        unreachable!("on_open_extended_channel_request")
+    }
+
+    /// [`unreachable`] in this no-op implementation.
+    fn on_open_extended_channel_success(
+        &mut self,
+        _request_id: u32,
+        _channel_id: u32,
+    ) -> Result<Arc<Mutex<Down>>, Error> {
+        unreachable!("on_open_extended_channel_success")
+    }
+
     /// [`unreachable`] in this no-op implementation.
     fn get_downstreams_in_channel(&self, _channel_id: u32) -> Option<&Vec<Arc<Mutex<Down>>>> {
         unreachable!("get_downstreams_in_channel")
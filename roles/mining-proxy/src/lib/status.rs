@@ -0,0 +1,52 @@
+use super::error::Error;
+
+/// Each sending side of the status channel
+/// should be wrapped with this enum to allow
+/// the main thread to know which component sent the message
+#[derive(Debug)]
+pub enum Sender {
+    DownstreamListener(async_channel::Sender<Status>),
+    Upstream(async_channel::Sender<Status>),
+}
+
+impl Sender {
+    pub async fn send(&self, status: Status) -> Result<(), async_channel::SendError<Status>> {
+        match self {
+            Self::DownstreamListener(inner) => inner.send(status).await,
+            Self::Upstream(inner) => inner.send(status).await,
+        }
+    }
+}
+
+impl Clone for Sender {
+    fn clone(&self) -> Self {
+        match self {
+            Self::DownstreamListener(inner) => Self::DownstreamListener(inner.clone()),
+            Self::Upstream(inner) => Self::Upstream(inner.clone()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum State {
+    DownstreamShutdown(Error),
+    UpstreamShutdown(Error),
+    Healthy(String),
+    Shutdown,
+}
+
+/// message to be sent to the status loop on the main thread
+#[derive(Debug)]
+pub struct Status {
+    pub state: State,
+}
+
+/// Wrap the error in the right `State` variant, based on the component that experienced it, and
+/// send it to the status loop.
+pub async fn send_status(sender: &Sender, e: Error) {
+    let state = match sender {
+        Sender::DownstreamListener(_) => State::DownstreamShutdown(e),
+        Sender::Upstream(_) => State::UpstreamShutdown(e),
+    };
+    sender.send(Status { state }).await.unwrap_or(());
+}
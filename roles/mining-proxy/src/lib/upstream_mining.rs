@@ -1,21 +1,30 @@
 #![allow(dead_code)]
 
 use core::convert::TryInto;
-use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
 use async_channel::{Receiver, SendError, Sender};
 use async_recursion::async_recursion;
 use nohash_hasher::BuildNoHashHasher;
 use tokio::{net::TcpStream, task};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use super::{
-    downstream_mining::{Channel, DownstreamMiningNode, StdFrame as DownstreamFrame},
+    downstream_mining::{
+        Channel, DownstreamMiningNode, DownstreamMiningNodeStatus, StdFrame as DownstreamFrame,
+    },
     routing_logic::{MiningRouter, MiningRoutingLogic},
     selectors::{DownstreamMiningSelector, ProxyDownstreamMiningSelector as Prs},
-    EXTRANONCE_RANGE_1_LENGTH,
+    status,
+    upstream_selection::IsConnected,
+    RLogic, EXTRANONCE_RANGE_1_LENGTH,
 };
-use codec_sv2::{HandshakeRole, Initiator, StandardEitherFrame, StandardSv2Frame};
+use codec_sv2::{HandshakeRole, Initiator, RekeyPolicy, StandardEitherFrame, StandardSv2Frame};
 use network_helpers_sv2::noise_connection::Connection;
 use roles_logic_sv2::{
     channel_logic::{
@@ -187,6 +196,16 @@ pub struct UpstreamMiningNode {
         HashMap<u32, Vec<(Arc<Mutex<DownstreamMiningNode>>, u32)>, BuildNoHashHasher<u32>>,
     downstream_hash_rate: f32,
     reconnect: bool,
+    // When the connection to the upstream is rekeyed.
+    rekey_policy: RekeyPolicy,
+    // Extended channels opened by non HOM downstreams, channel_id -> downstream. These
+    // downstreams receive the upstream extended jobs so they need to be told apart from the HOM
+    // ones when a prev hash is relayed.
+    extended_downstreams: HashMap<u32, Arc<Mutex<DownstreamMiningNode>>, BuildNoHashHasher<u32>>,
+    // The routing logic is owned by the proxy and the upstreams are owned by the routing logic,
+    // so only a weak reference is kept here.
+    routing_logic: Weak<Mutex<RLogic>>,
+    status_tx: Option<status::Sender>,
 }
 
 /// It assume that endpoint NEVER change flags and version!
@@ -205,6 +224,9 @@ impl UpstreamMiningNode {
         recv_coinbase_out: Option<Receiver<(Vec<TxOut>, Vec<u8>)>>,
         downstream_hash_rate: f32,
         reconnect: bool,
+        rekey_policy: RekeyPolicy,
+        routing_logic: Weak<Mutex<RLogic>>,
+        status_tx: Option<status::Sender>,
     ) -> Self {
         let request_id_mapper = RequestIdMapper::new();
         let downstream_selector = ProxyRemoteSelector::new();
@@ -228,6 +250,10 @@ impl UpstreamMiningNode {
             job_up_to_down_ids: HashMap::with_hasher(BuildNoHashHasher::default()),
             downstream_hash_rate,
             reconnect,
+            rekey_policy,
+            extended_downstreams: HashMap::with_hasher(BuildNoHashHasher::default()),
+            routing_logic,
+            status_tx,
         }
     }
     fn on_p_hash(
@@ -239,6 +265,9 @@ impl UpstreamMiningNode {
                 let mut res = vec![];
                 for (downstream, job_id) in downstreams {
                     m.job_id = *job_id;
+                    if let Some(channel_id) = self.extended_downstream_channel_id(downstream) {
+                        m.channel_id = channel_id;
+                    }
                     let message = Mining::SetNewPrevHash(m.clone().into_static());
                     res.push(SendTo::RelayNewMessageToRemote(
                         downstream.clone(),
@@ -251,10 +280,22 @@ impl UpstreamMiningNode {
             None => {
                 let downstrems = self.downstream_selector.get_all_downstreams();
                 let mut res = vec![];
-                m.job_id = 0;
-                let message = Mining::SetNewPrevHash(m.into_static());
+                let upstream_job_id = m.job_id;
                 for downstream in downstrems {
-                    res.push(SendTo::RelayNewMessageToRemote(downstream, message.clone()));
+                    // Extended downstreams receive the upstream jobs as they are, so the prev hash
+                    // must keep the upstream job id
+                    let message = match self.extended_downstream_channel_id(&downstream) {
+                        Some(channel_id) => {
+                            m.channel_id = channel_id;
+                            m.job_id = upstream_job_id;
+                            Mining::SetNewPrevHash(m.clone().into_static())
+                        }
+                        None => {
+                            m.job_id = 0;
+                            Mining::SetNewPrevHash(m.clone().into_static())
+                        }
+                    };
+                    res.push(SendTo::RelayNewMessageToRemote(downstream, message));
                 }
                 self.job_up_to_down_ids = HashMap::with_hasher(BuildNoHashHasher::default());
                 Ok(SendTo::Multiple(res))
@@ -262,6 +303,32 @@ impl UpstreamMiningNode {
         }
     }
 
+    // Return the channel id if the downstream opened an extended channel with the proxy
+    fn extended_downstream_channel_id(
+        &self,
+        downstream: &Arc<Mutex<DownstreamMiningNode>>,
+    ) -> Option<u32> {
+        self.extended_downstreams
+            .iter()
+            .find(|(_, d)| Arc::ptr_eq(d, downstream))
+            .map(|(channel_id, _)| *channel_id)
+    }
+
+    /// Return a new id for a channel opened by a downstream with this upstream.
+    ///
+    /// When the upstream is extended, the channel factory assigns ids to the extended channels
+    /// opened by non HOM downstreams using `group_id`, so standard channels must draw their ids
+    /// from the same source in order to never collide.
+    pub fn new_downstream_channel_id(&mut self) -> u32 {
+        match self.channel_kind {
+            ChannelKind::Extended(_) => self
+                .group_id
+                .safe_lock(|ids| ids.new_channel_id(0))
+                .unwrap(),
+            ChannelKind::Group(_) => self.channel_ids.safe_lock(|ids| ids.next()).unwrap(),
+        }
+    }
+
     /// Try send a message to the upstream node.
     /// If the node is connected and there are no error return Ok(())
     /// If the node is connected and there is an error the message is not sent and an error is
@@ -357,8 +424,14 @@ impl UpstreamMiningNode {
         match has_connection {
             true => Ok(()),
             false => {
-                let (address, authority_public_key) = self_mutex
-                    .safe_lock(|self_| (self_.address, self_.authority_public_key))
+                let (address, authority_public_key, rekey_policy) = self_mutex
+                    .safe_lock(|self_| {
+                        (
+                            self_.address,
+                            self_.authority_public_key,
+                            self_.rekey_policy,
+                        )
+                    })
                     .unwrap();
                 let socket = TcpStream::connect(address).await.map_err(|_| {
                     error!("Upstream node {} is not available", address);
@@ -370,10 +443,13 @@ impl UpstreamMiningNode {
                 );
 
                 let initiator = Initiator::from_raw_k(authority_public_key).unwrap();
-                let (receiver, sender) =
-                    Connection::new(socket, HandshakeRole::Initiator(initiator))
-                        .await
-                        .expect("impossible to conenct");
+                let (receiver, sender) = Connection::new_with_rekey_policy(
+                    socket,
+                    HandshakeRole::Initiator(initiator),
+                    rekey_policy,
+                )
+                .await
+                .expect("impossible to conenct");
                 let connection = UpstreamMiningConnection { receiver, sender };
                 self_mutex
                     .safe_lock(|self_| {
@@ -446,15 +522,102 @@ impl UpstreamMiningNode {
         self.id
     }
 
+    pub fn downstream_share_per_minute(&self) -> f32 {
+        self.downstream_share_per_minute
+    }
+
+    /// Target of the upstream extended channel, `None` for group channels or if the extended
+    /// channel is not opened yet
+    pub fn upstream_target(&self) -> Option<Target> {
+        match &self.channel_kind {
+            ChannelKind::Extended(Some(factory)) => factory.get_upstream_target(),
+            _ => None,
+        }
+    }
+
+    /// Update the target of a downstream channel opened on the upstream extended channel
+    pub fn update_downstream_target(&mut self, channel_id: u32, new_target: Target) {
+        if let ChannelKind::Extended(Some(factory)) = &mut self.channel_kind {
+            if factory
+                .update_target_for_channel(channel_id, new_target)
+                .is_none()
+            {
+                error!("Impossible to update target for channel {}", channel_id);
+            }
+        }
+    }
+
+    /// Forget a downstream that exited, the channel it opened is removed from the channel factory
+    /// so that its extranonce can be given to the next channel opened, and its hash rate is no
+    /// longer counted when selecting the upstream of new channels.
     pub fn remove_dowstream(self_: Arc<Mutex<Self>>, down: &Arc<Mutex<DownstreamMiningNode>>) {
+        let (channel_id, hash_rate) = down
+            .safe_lock(|d| {
+                let channel_id = match &d.status {
+                    DownstreamMiningNodeStatus::ChannelOpened(channel) => {
+                        Some(channel.channel_id())
+                    }
+                    _ => None,
+                };
+                (channel_id, d.take_hash_rate())
+            })
+            .unwrap();
+        self_
+            .safe_lock(|s| {
+                s.remove_hash_rate(hash_rate);
+                s.downstream_selector.remove_downstream(down);
+                s.extended_downstreams.retain(|_, d| !Arc::ptr_eq(d, down));
+                if let (Some(channel_id), ChannelKind::Extended(Some(factory))) =
+                    (channel_id, &mut s.channel_kind)
+                {
+                    factory.remove_channel(channel_id);
+                }
+            })
+            .unwrap();
+    }
+
+    // Act as if the upstream accepted the SetupConnection, so that it is selected for new channels
+    #[cfg(test)]
+    pub(crate) fn set_connected(&mut self) {
+        self.sv2_connection = Some(Sv2MiningConnection {
+            version: 2,
+            setup_connection_flags: 0,
+            setup_connection_success_flags: 0,
+        });
+    }
+
+    /// Stop the upstream: closing the connection make the task that relay the incoming messages
+    /// call `exit` and no reconnection is attempted.
+    pub fn shutdown(self_: Arc<Mutex<Self>>) {
         self_
-            .safe_lock(|s| s.downstream_selector.remove_downstream(down))
+            .safe_lock(|s| {
+                s.reconnect = false;
+                if let Some(connection) = &s.connection {
+                    connection.receiver.close();
+                    connection.sender.close();
+                }
+            })
             .unwrap();
     }
 
     fn exit(self_: Arc<Mutex<Self>>) {
         if !self_.safe_lock(|s| s.reconnect).unwrap() {
-            super::remove_upstream(self_.safe_lock(|s| s.id).unwrap());
+            let (id, routing_logic, status_tx) = self_
+                .safe_lock(|s| (s.id, s.routing_logic.upgrade(), s.status_tx.clone()))
+                .unwrap();
+            if let Some(routing_logic) = routing_logic {
+                if super::remove_upstream(&routing_logic, id) == 0 {
+                    if let Some(status_tx) = status_tx {
+                        task::spawn(async move {
+                            status::send_status(
+                                &status_tx,
+                                super::error::Error::NoUpstreamsAvailable,
+                            )
+                            .await;
+                        });
+                    }
+                }
+            }
         }
         let downstreams = self_
             .safe_lock(|s| s.downstream_selector.get_all_downstreams())
@@ -467,12 +630,7 @@ impl UpstreamMiningNode {
                     super::downstream_mining::DownstreamMiningNodeStatus::Paired(_) => None,
                     super::downstream_mining::DownstreamMiningNodeStatus::ChannelOpened(
                         channel,
-                    ) => match channel {
-                        Channel::DownstreamHomUpstreamGroup { channel_id, .. } => Some(*channel_id),
-                        Channel::DownstreamHomUpstreamExtended { channel_id, .. } => {
-                            Some(*channel_id)
-                        }
-                    },
+                    ) => Some(channel.channel_id()),
                 })
                 .unwrap()
             {
@@ -493,14 +651,20 @@ impl UpstreamMiningNode {
         }
         if self_.safe_lock(|s| s.reconnect).unwrap() {
             self_.safe_lock(|s| s.connection = None).unwrap();
+            // Without `sv2_connection` the upstream is not selected for new channels until it
+            // reconnects
             let flags = self_
-                .safe_lock(|s| s.sv2_connection.unwrap().setup_connection_flags)
+                .safe_lock(|s| s.sv2_connection.take().map(|c| c.setup_connection_flags))
+                .unwrap();
+            self_
+                .safe_lock(|s| {
+                    s.channel_kind.reset();
+                    s.extended_downstreams.clear();
+                })
                 .unwrap();
-            self_.safe_lock(|s| s.sv2_connection = None).unwrap();
-            self_.safe_lock(|s| s.channel_kind.reset()).unwrap();
             tokio::task::spawn(async move {
                 tokio::time::sleep(std::time::Duration::from_secs(10)).await;
-                Self::setup_flag_and_version(self_, Some(flags), 2, 2)
+                Self::setup_flag_and_version(self_, flags, 2, 2)
                     .await
                     .unwrap();
             });
@@ -513,62 +677,59 @@ impl UpstreamMiningNode {
         incoming: StdFrame,
     ) {
         match to_send {
-            Ok(SendTo::RelaySameMessageToRemote(downstream)) => {
+            Ok(SendTo::Multiple(sends_to)) => {
+                for send_to in sends_to {
+                    match send_to {
+                        SendTo::Multiple(_) => {
+                            error!("Nested SendTo::Multiple not supported")
+                        }
+                        send_to => {
+                            Self::send_to(self_mutex.clone(), send_to, incoming.clone()).await
+                        }
+                    }
+                }
+            }
+            Ok(send_to) => Self::send_to(self_mutex, send_to, incoming).await,
+            Err(Error::NoDownstreamsConnected) => (),
+            Err(e) => error!("Failed to handle upstream message: {:?}", e),
+        }
+    }
+
+    // A failed send to a downstream means that the downstream is gone, its own task close it so
+    // here the error is only logged
+    async fn send_to(
+        self_mutex: Arc<Mutex<Self>>,
+        send_to: SendTo<DownstreamMiningNode>,
+        incoming: StdFrame,
+    ) {
+        let result = match send_to {
+            SendTo::RelaySameMessageToRemote(downstream) => {
                 let sv2_frame: codec_sv2::Sv2Frame<MiningDeviceMessages, buffer_sv2::Slice> =
                     incoming.map(|payload| payload.try_into().unwrap());
-
-                DownstreamMiningNode::send(downstream.clone(), sv2_frame)
+                DownstreamMiningNode::send(downstream, sv2_frame)
                     .await
-                    .unwrap();
+                    .map_err(|e| e.into())
             }
-            Ok(SendTo::RelayNewMessageToRemote(downstream_mutex, message)) => {
+            SendTo::RelayNewMessageToRemote(downstream, message) => {
                 let message = MiningDeviceMessages::Mining(message);
                 let frame: DownstreamFrame = message.try_into().unwrap();
-                DownstreamMiningNode::send(downstream_mutex, frame)
+                DownstreamMiningNode::send(downstream, frame)
                     .await
-                    .unwrap();
+                    .map_err(|e| e.into())
             }
-            Ok(SendTo::Respond(message)) => {
+            SendTo::Respond(message) => {
                 let message = AnyMessage::Mining(message);
                 let frame: StdFrame = message.try_into().unwrap();
-                UpstreamMiningNode::send(self_mutex, frame).await.unwrap();
+                UpstreamMiningNode::send(self_mutex, frame).await
             }
-            Ok(SendTo::Multiple(sends_to)) => {
-                for send_to in sends_to {
-                    match send_to {
-                        SendTo::RelayNewMessageToRemote(downstream_mutex, message) => {
-                            let message = MiningDeviceMessages::Mining(message);
-                            let frame: DownstreamFrame = message.try_into().unwrap();
-                            DownstreamMiningNode::send(downstream_mutex, frame)
-                                .await
-                                .unwrap();
-                        }
-                        SendTo::RelaySameMessageToRemote(downstream_mutex) => {
-                            let frame: codec_sv2::Sv2Frame<
-                                MiningDeviceMessages,
-                                buffer_sv2::Slice,
-                            > = incoming.clone().map(|payload| payload.try_into().unwrap());
-                            DownstreamMiningNode::send(downstream_mutex, frame)
-                                .await
-                                .unwrap();
-                        }
-                        SendTo::Respond(message) => {
-                            let message = AnyMessage::Mining(message);
-                            let frame: StdFrame = message.try_into().unwrap();
-                            UpstreamMiningNode::send(self_mutex.clone(), frame)
-                                .await
-                                .unwrap();
-                        }
-                        SendTo::None(_) => (),
-                        SendTo::Multiple(_) => panic!("Nested SendTo::Multiple not supported"),
-                        _ => panic!(),
-                    }
-                }
+            SendTo::None(_) => Ok(()),
+            send_to => {
+                error!("Unexpected SendTo for upstream message: {:?}", send_to);
+                Ok(())
             }
-            Ok(SendTo::None(_)) => (),
-            Ok(_) => panic!(),
-            Err(Error::NoDownstreamsConnected) => (),
-            Err(e) => panic!("{:?}", e),
+        };
+        if let Err(e) = result {
+            error!("Failed to relay upstream message: {:?}", e);
         }
     }
 
@@ -743,6 +904,112 @@ impl UpstreamMiningNode {
         }
     }
 
+    /// Open an extended channel for a non HOM downstream (usually another proxy). The channel
+    /// get a sub-range of the extranonce space of the extended channel opened with this upstream,
+    /// the proxy then validate the shares against the downstream target and forward upstream the
+    /// ones that meet the upstream target.
+    pub fn open_extended_channel_down(
+        &mut self,
+        request_id: u32,
+        downstream_hash_rate: f32,
+        min_extranonce_size: u16,
+    ) -> Result<Vec<Mining<'static>>, Error> {
+        match &mut self.channel_kind {
+            // Group upstreams only relay standard channels, they have no extranonce space to
+            // carve out for downstream extended channels
+            ChannelKind::Group(_) => Ok(vec![Mining::OpenMiningChannelError(
+                OpenMiningChannelError::unsupported_extranonce_size(request_id),
+            )]),
+            ChannelKind::Extended(Some(factory)) => {
+                let messages = factory.new_extended_channel(
+                    request_id,
+                    downstream_hash_rate,
+                    min_extranonce_size,
+                )?;
+                let channel_id = messages.iter().find_map(|m| match m {
+                    Mining::OpenExtendedMiningChannelSuccess(m) => Some(m.channel_id),
+                    _ => None,
+                });
+                let channel_id = match channel_id {
+                    Some(channel_id) => {
+                        let downstream = self
+                            .downstream_selector
+                            .on_open_extended_channel_success(request_id, channel_id)?;
+                        self.extended_downstreams.insert(channel_id, downstream);
+                        channel_id
+                    }
+                    None => return Ok(messages.into_iter().map(|x| x.into_static()).collect()),
+                };
+                // The factory replay the last jobs as they were received from upstream, they must
+                // be addressed to the new channel
+                Ok(messages
+                    .into_iter()
+                    .map(|m| match m {
+                        Mining::NewExtendedMiningJob(mut job) => {
+                            job.channel_id = channel_id;
+                            Mining::NewExtendedMiningJob(job.into_static())
+                        }
+                        m => m.into_static(),
+                    })
+                    .collect())
+            }
+            ChannelKind::Extended(None) => Err(Error::NoUpstreamsConnected),
+        }
+    }
+
+    /// Handle a share submitted on an extended channel opened by a non HOM downstream: the share
+    /// is checked against the downstream target and, if it meets the upstream target, forwarded
+    /// upstream on the proxy extended channel.
+    pub fn handle_ext_shr(
+        self_: Arc<Mutex<Self>>,
+        share_: SubmitSharesExtended<'static>,
+    ) -> Result<Mining<'static>, Error> {
+        let (share, upstream_channel_id) = self_.safe_lock(|s| match &mut s.channel_kind {
+            ChannelKind::Extended(Some(factory)) => Ok((
+                factory.on_submit_shares_extended(share_.clone()),
+                factory.get_this_channel_id(),
+            )),
+            _ => Err(Error::NoUpstreamsConnected),
+        })??;
+        let success = SubmitSharesSuccess {
+            channel_id: share_.channel_id,
+            last_sequence_number: share_.sequence_number,
+            new_submits_accepted_count: 1,
+            new_shares_sum: 1,
+        };
+        match share? {
+            OnNewShare::SendErrorDownstream(e) => {
+                tracing::error!("Received invalid share");
+                Ok(Mining::SubmitSharesError(e))
+            }
+            // The proxy do not have JD capabilities so the bitcoin target is never known, a share
+            // that meet it is just a share that meet the upstream target
+            OnNewShare::SendSubmitShareUpstream((Share::Extended(mut s), _))
+            | OnNewShare::ShareMeetBitcoinTarget((Share::Extended(mut s), ..)) => {
+                s.channel_id = upstream_channel_id;
+                let message = AnyMessage::Mining(Mining::SubmitSharesExtended(s));
+                let frame: StdFrame = message.try_into().unwrap();
+                tokio::task::spawn(async move {
+                    if let Err(e) = UpstreamMiningNode::send(self_.clone(), frame).await {
+                        error!("Failed to send share upstream: {:?}", e);
+                    }
+                });
+                Ok(Mining::SubmitSharesSuccess(success))
+            }
+            // We are in an extended channel so shares are extended and never relayed to a group
+            // channel
+            OnNewShare::SendSubmitShareUpstream((Share::Standard(_), _))
+            | OnNewShare::ShareMeetBitcoinTarget((Share::Standard(_), ..))
+            | OnNewShare::RelaySubmitShareUpstream => {
+                error!("Invalid result for an extended share: {:?}", share_);
+                Err(Error::UnexpectedMessage(
+                    const_sv2::MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
+                ))
+            }
+            OnNewShare::ShareMeetDownstreamTarget => Ok(Mining::SubmitSharesSuccess(success)),
+        }
+    }
+
     pub fn handle_std_shr(
         self_: Arc<Mutex<Self>>,
         share_: SubmitSharesStandard,
@@ -879,6 +1146,12 @@ impl HasDownstreamSelector for UpstreamMiningNode {
     }
 }
 
+impl IsConnected for UpstreamMiningNode {
+    fn is_connected(&self) -> bool {
+        self.sv2_connection.is_some()
+    }
+}
+
 impl ParseMiningMessagesFromUpstream<DownstreamMiningNode> for UpstreamMiningNode {
     fn get_channel_type(&self) -> SupportedChannelTypes {
         SupportedChannelTypes::GroupAndExtended
@@ -892,7 +1165,7 @@ impl ParseMiningMessagesFromUpstream<DownstreamMiningNode> for UpstreamMiningNod
         &mut self,
         m: OpenStandardMiningChannelSuccess,
     ) -> Result<SendTo<DownstreamMiningNode>, Error> {
-        let routing_logic = super::get_routing_logic();
+        let routing_logic = super::get_routing_logic(&self.routing_logic);
         let remote = match routing_logic {
             MiningRoutingLogic::None => None,
             MiningRoutingLogic::Proxy(r_logic) => {
@@ -1098,15 +1371,40 @@ impl ParseMiningMessagesFromUpstream<DownstreamMiningNode> for UpstreamMiningNod
                     };
                     for (id, message) in messages {
                         match &message {
-                            Mining::NewExtendedMiningJob(_) => {
-                                // TODO implement it if support for non HOM downstream is needed
-                                todo!()
+                            Mining::NewExtendedMiningJob(m) => {
+                                // A channel without downstream must not stop the jobs of the others
+                                let downstream = match self
+                                    .downstream_selector
+                                    .downstream_from_channel_id(id)
+                                {
+                                    Some(downstream) => downstream,
+                                    None => {
+                                        warn!("No downstream for channel {}, job not relayed", id);
+                                        continue;
+                                    }
+                                };
+                                if is_future {
+                                    let ids =
+                                        self.job_up_to_down_ids.get_mut(&original_job_id).unwrap();
+                                    ids.push((downstream.clone(), m.job_id));
+                                };
+                                res.push(SendTo::RelayNewMessageToRemote(
+                                    downstream,
+                                    Mining::NewExtendedMiningJob(m.clone()),
+                                ));
                             }
                             Mining::NewMiningJob(m) => {
-                                let downstream = self
+                                // A channel without downstream must not stop the jobs of the others
+                                let downstream = match self
                                     .downstream_selector
                                     .downstream_from_channel_id(id)
-                                    .ok_or(Error::NoDownstreamsConnected)?;
+                                {
+                                    Some(downstream) => downstream,
+                                    None => {
+                                        warn!("No downstream for channel {}, job not relayed", id);
+                                        continue;
+                                    }
+                                };
                                 if is_future {
                                     let ids =
                                         self.job_up_to_down_ids.get_mut(&original_job_id).unwrap();
@@ -1247,12 +1545,13 @@ pub async fn scan(
 }
 
 impl IsUpstream<DownstreamMiningNode> for UpstreamMiningNode {
+    // A disconnected upstream has version 0 so that it is never pairable
     fn get_version(&self) -> u16 {
-        self.sv2_connection.unwrap().version
+        self.sv2_connection.map_or(0, |c| c.version)
     }
 
     fn get_flags(&self) -> u32 {
-        self.sv2_connection.unwrap().setup_connection_flags
+        self.sv2_connection.map_or(0, |c| c.setup_connection_flags)
     }
 
     fn get_supported_protocols(&self) -> Vec<Protocol> {
@@ -1274,6 +1573,9 @@ impl IsMiningUpstream<DownstreamMiningNode> for UpstreamMiningNode {
     fn add_hash_rate(&mut self, to_add: u64) {
         self.total_hash_rate += to_add;
     }
+    fn remove_hash_rate(&mut self, to_remove: u64) {
+        self.total_hash_rate = self.total_hash_rate.saturating_sub(to_remove);
+    }
     fn get_opened_channels(&mut self) -> &mut Vec<UpstreamChannel> {
         todo!()
     }
@@ -1310,6 +1612,9 @@ mod tests {
             None,
             100_000.0,
             false,
+            RekeyPolicy::default(),
+            Weak::new(),
+            None,
         );
 
         assert_eq!(actual.id, id);
@@ -1332,4 +1637,42 @@ mod tests {
         assert!(actual.channel_id_to_job_dispatcher.is_empty());
         assert_eq!(actual.request_id_mapper, RequestIdMapper::new());
     }
+
+    #[tokio::test]
+    async fn exit_mark_the_upstream_as_disconnected() {
+        let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+        let mut upstream = UpstreamMiningNode::new(
+            0,
+            address,
+            [0; 32],
+            super::super::ChannelKind::Group,
+            Arc::new(Mutex::new(GroupId::new())),
+            Arc::new(Mutex::new(Id::new())),
+            10.0,
+            None,
+            None,
+            100_000.0,
+            true,
+            RekeyPolicy::default(),
+            Weak::new(),
+            None,
+        );
+        upstream.sv2_connection = Some(Sv2MiningConnection {
+            version: 2,
+            setup_connection_flags: 0b0110,
+            setup_connection_success_flags: 0,
+        });
+        let upstream = Arc::new(Mutex::new(upstream));
+        assert!(upstream.safe_lock(|u| u.is_connected()).unwrap());
+
+        UpstreamMiningNode::exit(upstream.clone());
+
+        upstream
+            .safe_lock(|u| {
+                assert!(!u.is_connected());
+                assert_eq!(u.get_version(), 0);
+                assert_eq!(u.get_flags(), 0);
+            })
+            .unwrap();
+    }
 }
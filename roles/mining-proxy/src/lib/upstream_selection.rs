@@ -0,0 +1,545 @@
+//! # Upstream Selection
+//!
+//! Strategies used by the routing logic to pick the upstream that will serve a downstream.
+//!
+//! The routing logic first filters the upstreams that are pairable with the downstream (same
+//! protocol, compatible versions and flags), then asks the configured
+//! [`UpstreamSelectionStrategy`] to pick one of them. The selection is done every time a
+//! downstream opens a channel, when the `user_identity` of the downstream is known. The pairable
+//! upstreams are found when the downstream connects, so the ones that disconnected since then are
+//! filtered out before each selection.
+//!
+//! Built-in strategies:
+//! - **`LowestHashrate`**: select the upstream with the lowest total hashrate, preferring non
+//!   header-only upstreams (default).
+//! - **`Weighted`**: split the hashrate between the upstreams by a configured percentage.
+//! - **`Priority`**: always select the available upstream with the highest priority, the other
+//!   upstreams are only used as failover.
+//! - **`RoundRobin`**: select the upstreams one after the other.
+//! - **`UserIdentityPrefix`**: select the upstream by matching a prefix of the downstream
+//!   `user_identity`.
+
+use super::UpstreamMiningValues;
+use roles_logic_sv2::{
+    common_properties::{IsMiningDownstream, IsMiningUpstream},
+    utils::Mutex,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt::Debug as D, sync::Arc};
+
+/// Pick an upstream among a list of candidates.
+pub trait UpstreamSelectionStrategy<Down: IsMiningDownstream, Up: IsMiningUpstream<Down>>:
+    D + Send
+{
+    /// Select an upstream from `candidates`. `user_identity` is the one sent by the downstream in
+    /// the open channel message. Return `None` only if `candidates` is empty.
+    fn select(
+        &mut self,
+        candidates: &[Arc<Mutex<Up>>],
+        user_identity: Option<&str>,
+    ) -> Option<Arc<Mutex<Up>>>;
+}
+
+/// Upstreams that can disconnect while the proxy is running.
+pub trait IsConnected {
+    fn is_connected(&self) -> bool;
+}
+
+/// Return the candidates that are currently connected.
+pub fn connected<Up: IsConnected>(candidates: &[Arc<Mutex<Up>>]) -> Vec<Arc<Mutex<Up>>> {
+    candidates
+        .iter()
+        .filter(|up| up.safe_lock(|u| u.is_connected()).unwrap_or_default())
+        .cloned()
+        .collect()
+}
+
+/// Strategy used by the proxy to select upstreams, set in the config file with
+/// `upstream_selection_strategy`.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpstreamSelection {
+    #[default]
+    LowestHashrate,
+    Weighted,
+    Priority,
+    RoundRobin,
+    UserIdentityPrefix,
+}
+
+impl UpstreamSelection {
+    /// Build the selected strategy, the id of each upstream is its index in `upstreams`.
+    pub fn build<Down, Up>(
+        self,
+        upstreams: &[UpstreamMiningValues],
+    ) -> Box<dyn UpstreamSelectionStrategy<Down, Up>>
+    where
+        Down: IsMiningDownstream + 'static,
+        Up: IsMiningUpstream<Down> + 'static,
+    {
+        match self {
+            UpstreamSelection::LowestHashrate => Box::new(LowestHashrate),
+            UpstreamSelection::Weighted => Box::new(Weighted::new(
+                upstreams
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(id, up)| up.weight.map(|weight| (id as u32, weight)))
+                    .collect(),
+            )),
+            UpstreamSelection::Priority => Box::new(Priority::new(
+                upstreams
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(id, up)| up.priority.map(|priority| (id as u32, priority)))
+                    .collect(),
+            )),
+            UpstreamSelection::RoundRobin => Box::new(RoundRobin::default()),
+            UpstreamSelection::UserIdentityPrefix => {
+                let prefixes = upstreams
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(id, up)| {
+                        up.user_identity_prefixes
+                            .iter()
+                            .map(move |prefix| (prefix.clone(), id as u32))
+                    })
+                    .collect();
+                Box::new(UserIdentityPrefix::new(prefixes))
+            }
+        }
+    }
+}
+
+fn get_id<Down: IsMiningDownstream, Up: IsMiningUpstream<Down>>(up: &Arc<Mutex<Up>>) -> u32 {
+    up.safe_lock(|u| u.get_id()).unwrap()
+}
+
+fn total_hash_rate<Down: IsMiningDownstream, Up: IsMiningUpstream<Down>>(
+    up: &Arc<Mutex<Up>>,
+) -> u64 {
+    up.safe_lock(|u| u.total_hash_rate()).unwrap()
+}
+
+// Selects the upstream with the lowest total hash rate.
+// # Panics
+// This function panics if the slice is empty, as it is internally guaranteed that this function
+// will only be called with non-empty vectors.
+fn minor_total_hr_upstream<Down, Up>(ups: &[Arc<Mutex<Up>>]) -> Arc<Mutex<Up>>
+where
+    Down: IsMiningDownstream,
+    Up: IsMiningUpstream<Down>,
+{
+    ups.iter()
+        .reduce(|acc, item| {
+            // Safely locks and compares the total hash rate of each upstream.
+            if total_hash_rate::<Down, Up>(acc) < total_hash_rate::<Down, Up>(item) {
+                acc
+            } else {
+                item
+            }
+        })
+        .unwrap()
+        .clone() // Unwrap is safe because the function only operates on non-empty vectors.
+}
+
+// Filters upstream entities that are not configured for header-only mining.
+fn filter_header_only<Down, Up>(ups: &[Arc<Mutex<Up>>]) -> Vec<Arc<Mutex<Up>>>
+where
+    Down: IsMiningDownstream,
+    Up: IsMiningUpstream<Down>,
+{
+    ups.iter()
+        .filter(|up_mutex| {
+            up_mutex
+                .safe_lock(|up| !up.is_header_only())
+                .unwrap_or_default()
+        })
+        .cloned()
+        .collect()
+}
+
+// Selects the most appropriate upstream entity based on specific criteria.
+//
+// # Criteria
+// - If only one upstream is available, it is selected.
+// - If multiple upstreams exist, preference is given to those not configured as header-only.
+// - Among the remaining upstreams, the one with the lowest total hash rate is selected.
+fn select_lowest_hashrate<Down, Up>(ups: &[Arc<Mutex<Up>>]) -> Option<Arc<Mutex<Up>>>
+where
+    Down: IsMiningDownstream,
+    Up: IsMiningUpstream<Down>,
+{
+    if ups.is_empty() {
+        None
+    } else if ups.len() == 1 {
+        Some(ups[0].clone())
+    } else {
+        let not_header_only = filter_header_only::<Down, Up>(ups);
+        if !not_header_only.is_empty() {
+            Some(minor_total_hr_upstream::<Down, Up>(&not_header_only))
+        } else {
+            Some(minor_total_hr_upstream::<Down, Up>(ups))
+        }
+    }
+}
+
+/// Select the upstream with the lowest total hashrate, preferring the non header-only ones.
+#[derive(Debug, Default)]
+pub struct LowestHashrate;
+
+impl<Down: IsMiningDownstream, Up: IsMiningUpstream<Down>> UpstreamSelectionStrategy<Down, Up>
+    for LowestHashrate
+{
+    fn select(
+        &mut self,
+        candidates: &[Arc<Mutex<Up>>],
+        _user_identity: Option<&str>,
+    ) -> Option<Arc<Mutex<Up>>> {
+        select_lowest_hashrate::<Down, Up>(candidates)
+    }
+}
+
+/// Split the hashrate between the upstreams by the configured percentages.
+///
+/// The selected upstream is the one with the biggest difference between its configured share and
+/// the share of the total hashrate that it is actually receiving. Upstreams without a configured
+/// weight are only selected if no other candidate is available.
+#[derive(Debug)]
+pub struct Weighted {
+    weights: HashMap<u32, f32>,
+}
+
+impl Weighted {
+    pub fn new(weights: HashMap<u32, f32>) -> Self {
+        Self { weights }
+    }
+}
+
+impl<Down: IsMiningDownstream, Up: IsMiningUpstream<Down>> UpstreamSelectionStrategy<Down, Up>
+    for Weighted
+{
+    fn select(
+        &mut self,
+        candidates: &[Arc<Mutex<Up>>],
+        _user_identity: Option<&str>,
+    ) -> Option<Arc<Mutex<Up>>> {
+        let weighted: Vec<(Arc<Mutex<Up>>, f32, u64)> = candidates
+            .iter()
+            .filter_map(|up| {
+                let weight = *self.weights.get(&get_id::<Down, Up>(up))?;
+                (weight > 0.0).then(|| (up.clone(), weight, total_hash_rate::<Down, Up>(up)))
+            })
+            .collect();
+        if weighted.is_empty() {
+            return select_lowest_hashrate::<Down, Up>(candidates);
+        }
+        let total_weight: f32 = weighted.iter().map(|(_, w, _)| w).sum();
+        let total_hash_rate: u64 = weighted.iter().map(|(_, _, hr)| hr).sum();
+        let deficit = |weight: f32, hash_rate: u64| {
+            let actual = if total_hash_rate == 0 {
+                0.0
+            } else {
+                hash_rate as f32 / total_hash_rate as f32
+            };
+            weight / total_weight - actual
+        };
+        weighted
+            .into_iter()
+            .reduce(|acc, item| {
+                if deficit(item.1, item.2) > deficit(acc.1, acc.2) {
+                    item
+                } else {
+                    acc
+                }
+            })
+            .map(|(up, _, _)| up)
+    }
+}
+
+/// Always select the candidate with the highest priority (lowest value), the other upstreams are
+/// used only when the preferred ones are not available. Upstreams without a configured priority
+/// have the lowest priority.
+#[derive(Debug)]
+pub struct Priority {
+    priorities: HashMap<u32, u32>,
+}
+
+impl Priority {
+    pub fn new(priorities: HashMap<u32, u32>) -> Self {
+        Self { priorities }
+    }
+}
+
+impl<Down: IsMiningDownstream, Up: IsMiningUpstream<Down>> UpstreamSelectionStrategy<Down, Up>
+    for Priority
+{
+    fn select(
+        &mut self,
+        candidates: &[Arc<Mutex<Up>>],
+        _user_identity: Option<&str>,
+    ) -> Option<Arc<Mutex<Up>>> {
+        candidates
+            .iter()
+            .min_by_key(|up| {
+                let id = get_id::<Down, Up>(up);
+                (*self.priorities.get(&id).unwrap_or(&u32::MAX), id)
+            })
+            .cloned()
+    }
+}
+
+/// Select the candidates one after the other.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: usize,
+}
+
+impl<Down: IsMiningDownstream, Up: IsMiningUpstream<Down>> UpstreamSelectionStrategy<Down, Up>
+    for RoundRobin
+{
+    fn select(
+        &mut self,
+        candidates: &[Arc<Mutex<Up>>],
+        _user_identity: Option<&str>,
+    ) -> Option<Arc<Mutex<Up>>> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let selected = candidates[self.next % candidates.len()].clone();
+        self.next = self.next.wrapping_add(1);
+        Some(selected)
+    }
+}
+
+/// Select the upstream by the downstream `user_identity`: the upstream that has the longest
+/// configured prefix matching the `user_identity` is selected. If no prefix match, the upstreams
+/// without configured prefixes are used as default, selecting the one with the lowest hashrate.
+#[derive(Debug)]
+pub struct UserIdentityPrefix {
+    // (prefix, upstream id)
+    prefixes: Vec<(String, u32)>,
+}
+
+impl UserIdentityPrefix {
+    pub fn new(mut prefixes: Vec<(String, u32)>) -> Self {
+        // Longest prefixes first so that the first match is the most specific one
+        prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Self { prefixes }
+    }
+}
+
+impl<Down: IsMiningDownstream, Up: IsMiningUpstream<Down>> UpstreamSelectionStrategy<Down, Up>
+    for UserIdentityPrefix
+{
+    fn select(
+        &mut self,
+        candidates: &[Arc<Mutex<Up>>],
+        user_identity: Option<&str>,
+    ) -> Option<Arc<Mutex<Up>>> {
+        if let Some(user_identity) = user_identity {
+            for (prefix, id) in &self.prefixes {
+                if user_identity.starts_with(prefix.as_str()) {
+                    if let Some(up) = candidates.iter().find(|up| get_id::<Down, Up>(up) == *id) {
+                        return Some(up.clone());
+                    }
+                }
+            }
+        }
+        let defaults: Vec<_> = candidates
+            .iter()
+            .filter(|up| {
+                let id = get_id::<Down, Up>(up);
+                !self.prefixes.iter().any(|(_, id_)| *id_ == id)
+            })
+            .cloned()
+            .collect();
+        if defaults.is_empty() {
+            select_lowest_hashrate::<Down, Up>(candidates)
+        } else {
+            select_lowest_hashrate::<Down, Up>(&defaults)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::downstream_mining::DownstreamMiningNode;
+    use super::*;
+    use roles_logic_sv2::{
+        common_messages_sv2::Protocol,
+        common_properties::{IsUpstream, RequestIdMapper, UpstreamChannel},
+    };
+
+    #[derive(Debug)]
+    struct TestUpstream {
+        id: u32,
+        hash_rate: u64,
+        channels: Vec<UpstreamChannel>,
+        connected: bool,
+    }
+
+    impl IsConnected for TestUpstream {
+        fn is_connected(&self) -> bool {
+            self.connected
+        }
+    }
+
+    impl IsUpstream<DownstreamMiningNode> for TestUpstream {
+        fn get_version(&self) -> u16 {
+            2
+        }
+        fn get_flags(&self) -> u32 {
+            0
+        }
+        fn get_supported_protocols(&self) -> Vec<Protocol> {
+            vec![Protocol::MiningProtocol]
+        }
+        fn get_id(&self) -> u32 {
+            self.id
+        }
+        fn get_mapper(&mut self) -> Option<&mut RequestIdMapper> {
+            None
+        }
+    }
+
+    impl IsMiningUpstream<DownstreamMiningNode> for TestUpstream {
+        fn total_hash_rate(&self) -> u64 {
+            self.hash_rate
+        }
+        fn add_hash_rate(&mut self, to_add: u64) {
+            self.hash_rate += to_add;
+        }
+        fn remove_hash_rate(&mut self, to_remove: u64) {
+            self.hash_rate -= to_remove;
+        }
+        fn get_opened_channels(&mut self) -> &mut Vec<UpstreamChannel> {
+            &mut self.channels
+        }
+        fn update_channels(&mut self, c: UpstreamChannel) {
+            self.channels.push(c);
+        }
+    }
+
+    type Strategy = Box<dyn UpstreamSelectionStrategy<DownstreamMiningNode, TestUpstream>>;
+
+    fn upstreams(n: u32) -> Vec<Arc<Mutex<TestUpstream>>> {
+        (0..n)
+            .map(|id| {
+                Arc::new(Mutex::new(TestUpstream {
+                    id,
+                    hash_rate: 0,
+                    channels: vec![],
+                    connected: true,
+                }))
+            })
+            .collect()
+    }
+
+    // Select an upstream and add `hash_rate` to it, like the proxy does when a channel is opened
+    fn select_and_add(
+        strategy: &mut Strategy,
+        ups: &[Arc<Mutex<TestUpstream>>],
+        user_identity: Option<&str>,
+        hash_rate: u64,
+    ) -> u32 {
+        let up = strategy.select(ups, user_identity).unwrap();
+        up.safe_lock(|u| {
+            u.add_hash_rate(hash_rate);
+            u.id
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn no_candidates() {
+        let mut strategy: Strategy = Box::<RoundRobin>::default();
+        assert!(strategy.select(&[], None).is_none());
+        let mut strategy: Strategy = Box::new(Weighted::new(HashMap::new()));
+        assert!(strategy.select(&[], None).is_none());
+    }
+
+    #[test]
+    fn weighted_split_hash_rate_by_percentage() {
+        let ups = upstreams(2);
+        let weights = vec![(0, 70.0), (1, 30.0)].into_iter().collect();
+        let mut strategy: Strategy = Box::new(Weighted::new(weights));
+        for _ in 0..100 {
+            select_and_add(&mut strategy, &ups, None, 10);
+        }
+        let hash_rates: Vec<u64> = ups
+            .iter()
+            .map(|up| up.safe_lock(|u| u.hash_rate).unwrap())
+            .collect();
+        assert_eq!(hash_rates, vec![700, 300]);
+    }
+
+    #[test]
+    fn selection_after_churn() {
+        let ups = upstreams(2);
+        let mut strategy: Strategy = Box::<LowestHashrate>::default();
+        let selected: Vec<u32> = (0..4)
+            .map(|_| select_and_add(&mut strategy, &ups, None, 10))
+            .collect();
+        assert_eq!(selected, vec![1, 0, 1, 0]);
+        // Both downstreams of upstream 1 disconnect
+        ups[1].safe_lock(|u| u.remove_hash_rate(20)).unwrap();
+        assert_eq!(select_and_add(&mut strategy, &ups, None, 10), 1);
+        assert_eq!(select_and_add(&mut strategy, &ups, None, 10), 1);
+
+        let weights = vec![(0, 50.0), (1, 50.0)].into_iter().collect();
+        let mut strategy: Strategy = Box::new(Weighted::new(weights));
+        // Upstream 0 loses all its downstreams, it must get the next ones until it is back to its
+        // share
+        ups[0].safe_lock(|u| u.remove_hash_rate(20)).unwrap();
+        let selected: Vec<u32> = (0..2)
+            .map(|_| select_and_add(&mut strategy, &ups, None, 10))
+            .collect();
+        assert_eq!(selected, vec![0, 0]);
+        let hash_rates: Vec<u64> = ups
+            .iter()
+            .map(|up| up.safe_lock(|u| u.hash_rate).unwrap())
+            .collect();
+        assert_eq!(hash_rates, vec![20, 20]);
+    }
+
+    #[test]
+    fn priority_fail_over() {
+        let ups = upstreams(3);
+        let priorities = vec![(0, 2), (1, 1)].into_iter().collect();
+        let mut strategy: Strategy = Box::new(Priority::new(priorities));
+        assert_eq!(select_and_add(&mut strategy, &connected(&ups), None, 0), 1);
+        // upstream 1 disconnects, the candidates of the downstream do not change
+        ups[1].safe_lock(|u| u.connected = false).unwrap();
+        assert_eq!(select_and_add(&mut strategy, &connected(&ups), None, 0), 0);
+        // upstream 1 reconnects
+        ups[1].safe_lock(|u| u.connected = true).unwrap();
+        assert_eq!(select_and_add(&mut strategy, &connected(&ups), None, 0), 1);
+    }
+
+    #[test]
+    fn round_robin() {
+        let ups = upstreams(3);
+        let mut strategy: Strategy = Box::<RoundRobin>::default();
+        let selected: Vec<u32> = (0..6)
+            .map(|_| select_and_add(&mut strategy, &ups, None, 0))
+            .collect();
+        assert_eq!(selected, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn user_identity_prefix() {
+        let ups = upstreams(3);
+        let prefixes = vec![("pool_a.".to_string(), 0), ("pool_a.farm2".to_string(), 1)];
+        let mut strategy: Strategy = Box::new(UserIdentityPrefix::new(prefixes));
+        assert_eq!(
+            select_and_add(&mut strategy, &ups, Some("pool_a.farm1.worker"), 0),
+            0
+        );
+        assert_eq!(
+            select_and_add(&mut strategy, &ups, Some("pool_a.farm2.worker"), 0),
+            1
+        );
+        // Not matching identities go to the upstream without prefixes
+        assert_eq!(select_and_add(&mut strategy, &ups, Some("other"), 0), 2);
+        assert_eq!(select_and_add(&mut strategy, &ups, None, 0), 2);
+    }
+}
@@ -17,10 +17,10 @@
 //! A Downstream that signal the capacity to handle group channels can open more than one channel.
 //! A Downstream that signal the incapacity to handle group channels can open only one channel.
 #![allow(special_module_name)]
-use tracing::error;
+use tracing::{error, info};
 
 use ext_config::{Config, File, FileFormat};
-use lib::Configuration;
+use lib::{Configuration, MiningProxy};
 
 pub mod lib;
 
@@ -127,5 +127,17 @@ async fn main() {
         }
     };
 
-    lib::start_mining_proxy(config).await;
+    let mining_proxy = MiningProxy::new(config);
+    if let Err(e) = mining_proxy.start().await {
+        error!("Failed to start mining proxy: {:?}", e);
+        return;
+    }
+    match tokio::signal::ctrl_c().await {
+        Ok(()) => info!("Mining proxy(bin): Caught interrupt signal. Shutting down..."),
+        Err(err) => error!(
+            "Mining proxy(bin): Unable to listen for interrupt signal: {}",
+            err
+        ),
+    }
+    mining_proxy.shutdown();
 }
@@ -1,6 +1,7 @@
 use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use network_helpers_sv2::{admission::AdmissionConfig, ConnectionLimitsConfig, RekeyConfig};
 use roles_logic_sv2::utils::CoinbaseOutput as CoinbaseOutput_;
-use std::convert::TryFrom;
+use std::{convert::TryFrom, path::PathBuf};
 
 /// Represents the configuration of a Pool.
 ///
@@ -14,9 +15,17 @@ pub struct PoolConfig {
     authority_public_key: Secp256k1PublicKey,
     authority_secret_key: Secp256k1SecretKey,
     cert_validity_sec: u64,
+    #[serde(default)]
+    authority_keys_file: Option<PathBuf>,
     coinbase_outputs: Vec<CoinbaseOutput>,
     pool_signature: String,
     shares_per_minute: f32,
+    #[serde(default)]
+    admission: AdmissionConfig,
+    #[serde(default)]
+    rekey_policy: RekeyConfig,
+    #[serde(default)]
+    connection_limits: ConnectionLimitsConfig,
 }
 
 impl PoolConfig {
@@ -35,9 +44,13 @@ impl PoolConfig {
             authority_public_key: authority_config.public_key,
             authority_secret_key: authority_config.secret_key,
             cert_validity_sec: pool_connection.cert_validity_sec,
+            authority_keys_file: None,
             coinbase_outputs,
             pool_signature: pool_connection.signature,
             shares_per_minute,
+            admission: AdmissionConfig::default(),
+            rekey_policy: RekeyConfig::default(),
+            connection_limits: ConnectionLimitsConfig::default(),
         }
     }
 
@@ -66,6 +79,18 @@ impl PoolConfig {
         self.cert_validity_sec
     }
 
+    /// Returns the file the authority keys are loaded from, if any.
+    ///
+    /// When set, it replaces the authority key pair of the config and is reloaded on `SIGHUP`.
+    pub fn authority_keys_file(&self) -> Option<&PathBuf> {
+        self.authority_keys_file.as_ref()
+    }
+
+    /// Sets the file the authority keys are loaded from.
+    pub fn set_authority_keys_file(&mut self, authority_keys_file: Option<PathBuf>) {
+        self.authority_keys_file = authority_keys_file;
+    }
+
     /// Returns the Pool signature.
     pub fn pool_signature(&self) -> &String {
         &self.pool_signature
@@ -91,6 +116,26 @@ impl PoolConfig {
         self.shares_per_minute
     }
 
+    /// Returns the limits applied to the downstream connections.
+    pub fn admission(&self) -> &AdmissionConfig {
+        &self.admission
+    }
+
+    /// Sets the limits applied to the downstream connections.
+    pub fn set_admission(&mut self, admission: AdmissionConfig) {
+        self.admission = admission;
+    }
+
+    /// Returns when the downstream connections are rekeyed.
+    pub fn rekey_policy(&self) -> RekeyConfig {
+        self.rekey_policy
+    }
+
+    /// Returns the timeouts, keepalive and frame size limit of the downstream connections.
+    pub fn connection_limits(&self) -> ConnectionLimitsConfig {
+        self.connection_limits
+    }
+
     /// Change TP address.
     pub fn set_tp_address(&mut self, tp_address: String) {
         self.tp_address = tp_address;
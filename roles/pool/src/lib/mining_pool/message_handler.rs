@@ -1,10 +1,14 @@
 use super::super::mining_pool::Downstream;
 use binary_sv2::Str0255;
 use roles_logic_sv2::{
+    common_messages_sv2::RequestExtensions,
     errors::Error,
-    handlers::mining::{ParseMiningMessagesFromDownstream, SendTo, SupportedChannelTypes},
+    handlers::{
+        extensions::{ParseExtensionsNegotiationFromDownstream, SendTo as SendToExtensions},
+        mining::{ParseMiningMessagesFromDownstream, SendTo, SupportedChannelTypes},
+    },
     mining_sv2::*,
-    parsers::Mining,
+    parsers::{ExtensionsNegotiation, Mining},
     template_distribution_sv2::SubmitSolution,
     utils::Mutex,
 };
@@ -192,6 +196,7 @@ impl ParseMiningMessagesFromDownstream<()> for Downstream {
                         // TODO we can block everything with the below (looks like this will infinite loop??)
                         while self.solution_sender.try_send(solution.clone()).is_err() {};
                     }
+                    self.log_worker_share(m.channel_id);
                     let success = SubmitSharesSuccess {
                         channel_id: m.channel_id,
                         last_sequence_number: m.sequence_number,
@@ -203,6 +208,7 @@ impl ParseMiningMessagesFromDownstream<()> for Downstream {
 
                 },
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::ShareMeetDownstreamTarget => {
+                    self.log_worker_share(m.channel_id);
                 let success = SubmitSharesSuccess {
                         channel_id: m.channel_id,
                         last_sequence_number: m.sequence_number,
@@ -236,3 +242,20 @@ impl ParseMiningMessagesFromDownstream<()> for Downstream {
         Ok(SendTo::Respond(Mining::SetCustomMiningJobSuccess(m)))
     }
 }
+
+impl ParseExtensionsNegotiationFromDownstream for Downstream {
+    fn handle_request_extensions(
+        &mut self,
+        m: RequestExtensions,
+    ) -> Result<SendToExtensions, Error> {
+        info!(
+            "Received RequestExtensions with id: {}, extensions: {:?}",
+            m.request_id, m.requested_extensions
+        );
+        let response = self.extension_registry.negotiate(&m);
+        if let ExtensionsNegotiation::RequestExtensionsSuccess(success) = &response {
+            self.negotiated_extensions = success.supported_extensions.clone().into_inner();
+        }
+        Ok(SendToExtensions::Respond(response))
+    }
+}
@@ -6,19 +6,31 @@ use super::{
 };
 use async_channel::{Receiver, Sender};
 use binary_sv2::U256;
-use codec_sv2::{HandshakeRole, Responder, StandardEitherFrame, StandardSv2Frame};
+use codec_sv2::{HandshakeRole, StandardEitherFrame, StandardSv2Frame};
+use const_sv2::{
+    EXTENSION_TYPE_EXTENSIONS_NEGOTIATION, EXTENSION_TYPE_WORKER_HASHRATE_TRACKING,
+    MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
+};
 use error_handling::handle_result;
 use key_utils::SignatureService;
-use network_helpers_sv2::noise_connection::Connection;
+use network_helpers_sv2::{
+    admission::AdmissionControl, certificate_provider::certificate_provider,
+    noise_connection::Connection,
+};
 use nohash_hasher::BuildNoHashHasher;
 use roles_logic_sv2::{
     channel_logic::channel_factory::PoolChannelFactory,
     common_properties::{CommonDownstreamData, IsDownstream, IsMiningDownstream},
     errors::Error,
-    handlers::mining::{ParseMiningMessagesFromDownstream, SendTo},
+    handlers::{
+        extensions::{ParseExtensionsNegotiationFromDownstream, SendTo as SendToExtensions},
+        mining::{ParseMiningMessagesFromDownstream, SendTo},
+    },
     job_creator::JobsCreators,
-    mining_sv2::{ExtendedExtranonce, SetNewPrevHash as SetNPH},
-    parsers::{AnyMessage, Mining},
+    mining_sv2::{
+        user_identity, ExtendedExtranonce, SetNewPrevHash as SetNPH, SubmitSharesExtended,
+    },
+    parsers::{AnyMessage, ExtensionRegistry, Mining},
     template_distribution_sv2::{NewTemplate, SetNewPrevHash, SubmitSolution},
     utils::{CoinbaseOutput as CoinbaseOutput_, Mutex},
 };
@@ -64,6 +76,12 @@ pub struct Downstream {
     downstream_data: CommonDownstreamData,
     solution_sender: Sender<SubmitSolution<'static>>,
     channel_factory: Arc<Mutex<PoolChannelFactory>>,
+    // Extensions that the pool can negotiate with the downstream
+    extension_registry: ExtensionRegistry,
+    // Extensions accepted in the last `RequestExtensions`
+    negotiated_extensions: Vec<u16>,
+    // `user_identity` TLV of the `SubmitSharesExtended` being handled, if any
+    share_user_identity: Option<String>,
 }
 
 /// Accept downstream connection
@@ -77,12 +95,10 @@ pub struct Pool {
 }
 
 impl Downstream {
-    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         mut receiver: Receiver<EitherFrame>,
         mut sender: Sender<EitherFrame>,
         solution_sender: Sender<SubmitSolution<'static>>,
-        pool: Arc<Mutex<Pool>>,
         channel_factory: Arc<Mutex<PoolChannelFactory>>,
         status_tx: status::Sender,
         address: SocketAddr,
@@ -97,6 +113,9 @@ impl Downstream {
             true => channel_factory.safe_lock(|c| c.new_standard_id_for_hom())?,
         };
 
+        let mut extension_registry = ExtensionRegistry::new();
+        extension_registry.support(EXTENSION_TYPE_WORKER_HASHRATE_TRACKING);
+
         let self_ = Arc::new(Mutex::new(Downstream {
             id,
             receiver,
@@ -104,6 +123,9 @@ impl Downstream {
             downstream_data,
             solution_sender,
             channel_factory,
+            extension_registry,
+            negotiated_extensions: Vec::new(),
+            share_user_identity: None,
         }));
 
         let cloned = self_.clone();
@@ -144,11 +166,15 @@ impl Downstream {
                         );
                     }
                     _ => {
-                        let res = pool
-                            .safe_lock(|p| p.downstreams.remove(&id))
-                            .map_err(|e| PoolError::PoisonLock(e.to_string()));
-                        handle_result!(status_tx, res);
                         error!("Downstream {} disconnected", id);
+                        if let Err(e) = status_tx
+                            .send(status::Status {
+                                state: status::State::DownstreamInstanceDropped(id),
+                            })
+                            .await
+                        {
+                            error!("Encountered Error but status channel is down: {}", e);
+                        }
                         break;
                     }
                 }
@@ -159,15 +185,40 @@ impl Downstream {
     }
 
     pub async fn next(self_mutex: Arc<Mutex<Self>>, mut incoming: StdFrame) -> PoolResult<()> {
-        let message_type = incoming
+        let header = incoming
             .get_header()
-            .ok_or_else(|| PoolError::Custom(String::from("No header set")))?
-            .msg_type();
+            .ok_or_else(|| PoolError::Custom(String::from("No header set")))?;
+        let message_type = header.msg_type();
         let payload = incoming.payload();
         debug!(
             "Received downstream message type: {:?}, payload: {:?}",
             message_type, payload
         );
+        if header.ext_type() == EXTENSION_TYPE_EXTENSIONS_NEGOTIATION {
+            let response =
+                ParseExtensionsNegotiationFromDownstream::handle_message_extensions_negotiation(
+                    self_mutex.clone(),
+                    message_type,
+                    payload,
+                )?;
+            if let SendToExtensions::Respond(message) = response {
+                let sv2_frame: StdFrame = AnyMessage::ExtensionsNegotiation(message).try_into()?;
+                let sender = self_mutex.safe_lock(|self_| self_.sender.clone())?;
+                sender.send(sv2_frame.into()).await?;
+            }
+            return Ok(());
+        }
+        if message_type == MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED {
+            let worker_hashrate_tracking = self_mutex.safe_lock(|d| {
+                d.negotiated_extensions
+                    .contains(&EXTENSION_TYPE_WORKER_HASHRATE_TRACKING)
+            })?;
+            let share_user_identity = match worker_hashrate_tracking {
+                true => Self::share_user_identity(payload),
+                false => None,
+            };
+            self_mutex.safe_lock(|d| d.share_user_identity = share_user_identity)?;
+        }
         let next_message_to_send = ParseMiningMessagesFromDownstream::handle_message_mining(
             self_mutex.clone(),
             message_type,
@@ -176,6 +227,25 @@ impl Downstream {
         Self::match_send_to(self_mutex, next_message_to_send).await
     }
 
+    // Parses the `user_identity` TLV appended to a serialized `SubmitSharesExtended`
+    fn share_user_identity(payload: &[u8]) -> Option<String> {
+        let mut bytes = payload.to_vec();
+        let share: SubmitSharesExtended = binary_sv2::from_bytes(&mut bytes).ok()?;
+        let tlvs = share.tlvs(payload)?;
+        user_identity(&tlvs).map(|identity| identity.to_string())
+    }
+
+    /// Logs the worker that submitted an accepted share, if the downstream sent its
+    /// `user_identity`
+    pub(crate) fn log_worker_share(&mut self, channel_id: u32) {
+        if let Some(identity) = self.share_user_identity.take() {
+            debug!(
+                "Share accepted on channel {} for worker {}",
+                channel_id, identity
+            );
+        }
+    }
+
     #[async_recursion::async_recursion]
     async fn match_send_to(
         self_: Arc<Mutex<Self>>,
@@ -262,6 +332,29 @@ impl IsDownstream for Downstream {
 
 impl IsMiningDownstream for Downstream {}
 
+// The pool signature and 8 bytes telling the channels apart are the extranonce prefix, the rest is
+// left to the downstreams
+fn extranonces(pool_signature: &str, extranonce_len: usize) -> ExtendedExtranonce {
+    let range_0 = std::ops::Range { start: 0, end: 0 };
+
+    let range_1_end = pool_signature.len() + 8;
+    let range_1 = std::ops::Range {
+        start: 0,
+        end: range_1_end,
+    };
+    let range_2 = std::ops::Range {
+        start: range_1_end,
+        end: extranonce_len,
+    };
+    ExtendedExtranonce::new(
+        range_0,
+        range_1,
+        range_2,
+        Some(pool_signature.as_bytes().to_vec()),
+    )
+    .expect("Failed to create ExtendedExtranonce with valid ranges")
+}
+
 impl Pool {
     async fn accept_incoming_connection(
         self_: Arc<Mutex<Pool>>,
@@ -269,6 +362,16 @@ impl Pool {
         mut recv_stop_signal: tokio::sync::watch::Receiver<()>,
     ) -> PoolResult<()> {
         let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
+        let certificate_provider = certificate_provider(
+            *config.authority_public_key(),
+            *config.authority_secret_key(),
+            std::time::Duration::from_secs(config.cert_validity_sec()),
+            config.authority_keys_file().map(|path| path.as_path()),
+        )
+        .map_err(|e| PoolError::Custom(format!("Invalid authority keys: {:?}", e)))?;
+        let admission = AdmissionControl::new(config.admission().clone());
+        let rekey_policy = config.rekey_policy();
+        let connection_limits = config.connection_limits();
         let listener = TcpListener::bind(&config.listen_address()).await?;
         info!("Pool is running on: {}", config.listen_address());
         // Run the listener in the background
@@ -285,24 +388,44 @@ impl Pool {
                             Ok((stream, _)) => {
                                 let address = stream.peer_addr().unwrap();
                                 info!("New connection from {:?}", stream.peer_addr().map_err(PoolError::Io));
-                                let responder = Responder::from_authority_kp(
-                                    &config.authority_public_key().into_bytes(),
-                                    &config.authority_secret_key().into_bytes(),
-                                    std::time::Duration::from_secs(config.cert_validity_sec()),
-                                );
+                                let permit = match admission.admit(address.ip()) {
+                                    Ok(permit) => permit,
+                                    Err(e) => {
+                                        warn!("Refusing connection from {}: {}", address, e);
+                                        continue;
+                                    }
+                                };
+                                let responder = certificate_provider.responder();
 
                                 match responder {
                                     Ok(resp) => {
-                                        if let Ok((receiver, sender)) = Connection::new(stream, HandshakeRole::Responder(resp)).await {
-                                            handle_result!(
-                                                status_tx,
-                                                Self::accept_incoming_connection_(
-                                                    self_.clone(),
-                                                    receiver,
-                                                    sender,
-                                                    address
-                                                ).await
-                                            );
+                                        let connection_config = permit.into_connection_config(connection_limits.into());
+                                        match Connection::new_with_config(stream, HandshakeRole::Responder(resp), rekey_policy.into(), connection_config).await {
+                                            Ok((receiver, sender)) => {
+                                                handle_result!(
+                                                    status_tx,
+                                                    Self::accept_incoming_connection_(
+                                                        self_.clone(),
+                                                        receiver,
+                                                        sender,
+                                                        address
+                                                    ).await
+                                                );
+                                            }
+                                            Err(e) => {
+                                                warn!("Noise handshake with {} failed: {:?}", address, e);
+                                                if let Err(e) = status_tx
+                                                    .send(status::Status {
+                                                        state: status::State::Healthy(format!(
+                                                            "Noise handshake with {} failed: {:?}",
+                                                            address, e
+                                                        )),
+                                                    })
+                                                    .await
+                                                {
+                                                    error!("Encountered Error but status channel is down: {}", e);
+                                                }
+                                            }
                                         }
                                     }
                                     Err(_) => {
@@ -335,7 +458,6 @@ impl Pool {
             receiver,
             sender,
             solution_sender,
-            self_.clone(),
             channel_factory,
             // convert Listener variant to Downstream variant
             status_tx.listener_to_connection(),
@@ -463,29 +585,10 @@ impl Pool {
         recv_stop_signal: tokio::sync::watch::Receiver<()>,
     ) -> Result<Arc<Mutex<Self>>, PoolError> {
         let extranonce_len = 32;
-        let range_0 = std::ops::Range { start: 0, end: 0 };
-
-        let pool_signature_len = config.pool_signature().len();
-        let range_1_end = pool_signature_len + 8;
-        let range_1 = std::ops::Range {
-            start: 0,
-            end: range_1_end,
-        };
-        let range_2 = std::ops::Range {
-            start: range_1_end,
-            end: extranonce_len,
-        };
-
         let ids = Arc::new(Mutex::new(roles_logic_sv2::utils::GroupId::new()));
         let pool_coinbase_outputs = get_coinbase_output(&config);
         info!("PUB KEY: {:?}", pool_coinbase_outputs);
-        let extranonces = ExtendedExtranonce::new(
-            range_0,
-            range_1,
-            range_2,
-            Some(config.pool_signature().as_bytes().to_vec()),
-        )
-        .expect("Failed to create ExtendedExtranonce with valid ranges");
+        let extranonces = extranonces(config.pool_signature(), extranonce_len);
         let creator = JobsCreators::new(extranonce_len as u8);
         let kind = roles_logic_sv2::channel_logic::channel_factory::ExtendedChannelKind::Pool;
         let channel_factory = Arc::new(Mutex::new(PoolChannelFactory::new(
@@ -581,7 +684,13 @@ impl Pool {
 mod test {
     use binary_sv2::{B0255, B064K};
     use ext_config::{Config, File, FileFormat};
-    use std::convert::TryInto;
+    use roles_logic_sv2::{
+        channel_logic::channel_factory::{ExtendedChannelKind, PoolChannelFactory},
+        job_creator::JobsCreators,
+        parsers::Mining,
+        utils::{GroupId, Mutex},
+    };
+    use std::{convert::TryInto, sync::Arc};
     use tracing::error;
 
     use stratum_common::{
@@ -684,6 +793,55 @@ mod test {
         );
     }
 
+    fn open_extended(factory: &mut PoolChannelFactory) -> Vec<u8> {
+        match &factory.new_extended_channel(1, 1_000.0, 4).unwrap()[0] {
+            Mining::OpenExtendedMiningChannelSuccess(success) => {
+                success.extranonce_prefix.inner_as_ref().to_vec()
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    fn open_standard(factory: &mut PoolChannelFactory, id: u32) -> Vec<u8> {
+        match &factory.add_standard_channel(2, 1_000.0, true, id).unwrap()[0] {
+            Mining::OpenStandardMiningChannelSuccess(success) => {
+                success.extranonce_prefix.inner_as_ref().to_vec()
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn standard_and_extended_channels_get_disjoint_extranonces() {
+        let mut factory = PoolChannelFactory::new(
+            Arc::new(Mutex::new(GroupId::new())),
+            super::extranonces("Stratum V2 SRI Pool", 32),
+            JobsCreators::new(32),
+            10.0,
+            ExtendedChannelKind::Pool,
+            vec![],
+        );
+        let mut prefixes = vec![];
+        let mut extranonces = vec![];
+        for id in 100..104 {
+            prefixes.push(open_extended(&mut factory));
+            extranonces.push(open_standard(&mut factory, id));
+        }
+        for extranonce in &extranonces {
+            assert_eq!(extranonce.len(), 32);
+            assert!(extranonce.starts_with(b"Stratum V2 SRI Pool"));
+            assert!(prefixes
+                .iter()
+                .all(|prefix| !extranonce.starts_with(prefix)));
+        }
+        extranonces.sort();
+        extranonces.dedup();
+        assert_eq!(extranonces.len(), 4);
+        prefixes.sort();
+        prefixes.dedup();
+        assert_eq!(prefixes.len(), 4);
+    }
+
     // copied from roles-logic-sv2::job_creator
     fn coinbase_tx_prefix(coinbase: &Transaction, script_prefix_len: usize) -> B064K<'static> {
         let encoded = consensus::serialize(coinbase);
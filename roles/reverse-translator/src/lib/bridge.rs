@@ -0,0 +1,557 @@
+//! Maps the SV2 channels of the downstreams on the SV1 session with the pool.
+//!
+//! Every downstream channel gets a slice of the extranonce2 of the SV1 session from a
+//! [`ProxyExtendedChannelFactory`]: standard channels get a fixed extranonce, extended channels
+//! get a prefix and roll the rest of the extranonce2. The factory validates the shares against the
+//! channel target, the shares that also meet the pool target are sent to the [`Upstream`].
+//!
+//! When the pool session is replaced by one with another extranonce space, e.g. after a
+//! reconnection, the channels are closed and the downstreams have to open new ones.
+//!
+//! [`Upstream`]: crate::upstream::Upstream
+
+use crate::{
+    config::ReverseTranslatorConfig,
+    downstream::{EitherFrame, StdFrame},
+    error::{Error, ReverseTranslatorResult},
+    upstream::UpstreamEvent,
+};
+use async_channel::{Receiver, Sender};
+use nohash_hasher::BuildNoHashHasher;
+use roles_logic_sv2::{
+    channel_logic::channel_factory::{
+        ExtendedChannelKind, OnNewShare, ProxyExtendedChannelFactory, Share,
+    },
+    mining_sv2::{
+        CloseChannel, ExtendedExtranonce, Extranonce, SetNewPrevHash, SetTarget, SubmitSharesError,
+        SubmitSharesExtended, SubmitSharesStandard, SubmitSharesSuccess, Target,
+    },
+    parsers::{AnyMessage, Mining},
+    utils::{hash_rate_to_target, GroupId, Mutex},
+    vardiff::{Vardiff, VardiffConfig},
+};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use sv1_server::job::target_from_difficulty;
+use tracing::{debug, error, info, warn};
+
+/// Interval at which the difficulty of the channels that stopped sending shares is lowered.
+const VARDIFF_TIMER_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Messages to send, with the sender of the downstream they are addressed to.
+pub type Outgoing = Vec<(Sender<EitherFrame>, Mining<'static>)>;
+
+#[derive(Debug)]
+struct Channel {
+    downstream_id: u32,
+    sender: Sender<EitherFrame>,
+    vardiff: Vardiff,
+}
+
+/// Bridge between the SV2 channels and the SV1 session, see the [module level docs](self).
+#[derive(Debug)]
+pub struct Bridge {
+    factory: ProxyExtendedChannelFactory,
+    ids: Arc<Mutex<GroupId>>,
+    // Extranonce1 and extranonce2 size of the pool session
+    extranonce1: Vec<u8>,
+    extranonce2_size: usize,
+    extranonce_prefix_size: usize,
+    channels: HashMap<u32, Channel, BuildNoHashHasher<u32>>,
+    // Extended job of every standard job sent since the last prev hash, by standard job id
+    standard_jobs: HashMap<u32, u32, BuildNoHashHasher<u32>>,
+    current_job: Option<u32>,
+    shares_per_minute: f32,
+    upstream_target: Target,
+    submits: Sender<SubmitSharesExtended<'static>>,
+}
+
+impl Bridge {
+    /// Creates the bridge for a SV1 session subscribed with `extranonce1` and `extranonce2_size`.
+    /// The shares that meet the pool target are sent to `submits`.
+    pub fn new(
+        config: &ReverseTranslatorConfig,
+        extranonce1: Vec<u8>,
+        extranonce2_size: usize,
+        submits: Sender<SubmitSharesExtended<'static>>,
+    ) -> ReverseTranslatorResult<Self> {
+        let ids = Arc::new(Mutex::new(GroupId::new()));
+        // SV1 sessions start at difficulty 1 until the pool sends `mining.set_difficulty`
+        let upstream_target = target_from_difficulty(1.0);
+        let factory = Self::new_factory(
+            ids.clone(),
+            &extranonce1,
+            extranonce2_size,
+            config.extranonce_prefix_size,
+            config.shares_per_minute,
+            upstream_target.clone(),
+        )?;
+        Ok(Self {
+            factory,
+            ids,
+            extranonce1,
+            extranonce2_size,
+            extranonce_prefix_size: config.extranonce_prefix_size,
+            channels: HashMap::with_hasher(BuildNoHashHasher::default()),
+            standard_jobs: HashMap::with_hasher(BuildNoHashHasher::default()),
+            current_job: None,
+            shares_per_minute: config.shares_per_minute,
+            upstream_target,
+            submits,
+        })
+    }
+
+    fn new_factory(
+        ids: Arc<Mutex<GroupId>>,
+        extranonce1: &[u8],
+        extranonce2_size: usize,
+        extranonce_prefix_size: usize,
+        shares_per_minute: f32,
+        upstream_target: Target,
+    ) -> ReverseTranslatorResult<ProxyExtendedChannelFactory> {
+        if extranonce_prefix_size >= extranonce2_size {
+            return Err(Error::InvalidConfig(format!(
+                "extranonce_prefix_size must be smaller than the extranonce2 size of the pool ({})",
+                extranonce2_size
+            )));
+        }
+        let range_0 = 0..extranonce1.len();
+        let range_1 = range_0.end..(range_0.end + extranonce_prefix_size);
+        let range_2 = range_1.end..(range_0.end + extranonce2_size);
+        let extranonce1: Extranonce = extranonce1
+            .to_vec()
+            .try_into()
+            .map_err(|_| Error::InvalidUpstreamMessage("extranonce1 too long".to_string()))?;
+        let extranonces =
+            ExtendedExtranonce::from_upstream_extranonce(extranonce1, range_0, range_1, range_2)
+                .map_err(|e| Error::InvalidUpstreamMessage(format!("{:?}", e)))?;
+        Ok(ProxyExtendedChannelFactory::new(
+            ids,
+            extranonces,
+            None,
+            shares_per_minute,
+            ExtendedChannelKind::Proxy { upstream_target },
+            None,
+            0,
+        ))
+    }
+
+    /// Forwards the events of the [`Upstream`](crate::upstream::Upstream) to the downstreams and
+    /// retargets the channels that stopped sending shares.
+    pub fn start(self_: Arc<Mutex<Self>>, events: Receiver<UpstreamEvent>) {
+        let cloned = self_.clone();
+        tokio::task::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                let result = cloned
+                    .safe_lock(|bridge| bridge.on_upstream_event(event))
+                    .map_err(|e| e.to_string());
+                match result {
+                    Ok(Ok(outgoing)) => dispatch(outgoing).await,
+                    Ok(Err(e)) => error!("Can not forward SV1 pool message: {}", e),
+                    Err(e) => {
+                        error!("Bridge mutex poisoned: {}", e);
+                        break;
+                    }
+                }
+            }
+            info!("SV1 pool connection closed, bridge stopped");
+        });
+        tokio::task::spawn(async move {
+            loop {
+                tokio::time::sleep(VARDIFF_TIMER_INTERVAL).await;
+                let outgoing = match self_.safe_lock(|bridge| bridge.on_vardiff_timer()) {
+                    Ok(outgoing) => outgoing,
+                    Err(_) => break,
+                };
+                dispatch(outgoing).await;
+            }
+        });
+    }
+
+    fn on_upstream_event(&mut self, event: UpstreamEvent) -> ReverseTranslatorResult<Outgoing> {
+        match event {
+            UpstreamEvent::Subscribed {
+                extranonce1,
+                extranonce2_size,
+            } => self.on_subscribed(extranonce1, extranonce2_size),
+            UpstreamEvent::SetTarget(target) => {
+                self.factory.set_target(&mut target.clone());
+                for channel in self.channels.values_mut() {
+                    channel.vardiff.set_min_target(Some(target.clone()));
+                }
+                self.upstream_target = target;
+                Ok(vec![])
+            }
+            UpstreamEvent::NewJob(job, set_new_prev_hash) => {
+                self.on_new_job(job, set_new_prev_hash)
+            }
+        }
+    }
+
+    // A new pool session with the same extranonce space keeps the channels, otherwise they are
+    // closed
+    fn on_subscribed(
+        &mut self,
+        extranonce1: Vec<u8>,
+        extranonce2_size: usize,
+    ) -> ReverseTranslatorResult<Outgoing> {
+        if extranonce1 == self.extranonce1 && extranonce2_size == self.extranonce2_size {
+            info!("SV1 pool session restored, the downstream channels are kept");
+            return Ok(vec![]);
+        }
+        warn!("SV1 pool session changed the extranonce space, closing the downstream channels");
+        let upstream_target = target_from_difficulty(1.0);
+        self.factory = Self::new_factory(
+            self.ids.clone(),
+            &extranonce1,
+            extranonce2_size,
+            self.extranonce_prefix_size,
+            self.shares_per_minute,
+            upstream_target.clone(),
+        )?;
+        self.extranonce1 = extranonce1;
+        self.extranonce2_size = extranonce2_size;
+        self.upstream_target = upstream_target;
+        self.standard_jobs.clear();
+        self.current_job = None;
+        Ok(self
+            .channels
+            .drain()
+            .map(|(channel_id, channel)| {
+                let close_channel = CloseChannel {
+                    channel_id,
+                    // Infallible unwrap the reason is a short static string
+                    reason_code: "pool-session-changed".to_string().try_into().unwrap(),
+                };
+                (channel.sender, Mining::CloseChannel(close_channel))
+            })
+            .collect())
+    }
+
+    fn on_new_job(
+        &mut self,
+        job: roles_logic_sv2::mining_sv2::NewExtendedMiningJob<'static>,
+        set_new_prev_hash: Option<SetNewPrevHash<'static>>,
+    ) -> ReverseTranslatorResult<Outgoing> {
+        let job_id = job.job_id;
+        let jobs = self.factory.on_new_extended_mining_job(job)?;
+        if let Some(set_new_prev_hash) = &set_new_prev_hash {
+            self.factory.on_new_prev_hash(set_new_prev_hash.clone())?;
+            self.standard_jobs.clear();
+        }
+        self.current_job = Some(job_id);
+        let mut outgoing = vec![];
+        for (channel_id, message) in jobs {
+            let channel = match self.channels.get(&channel_id) {
+                Some(channel) => channel,
+                None => continue,
+            };
+            // Standard channels receive a job with their own id, the prev hash must refer to it
+            let channel_job_id = match &message {
+                Mining::NewMiningJob(standard_job) => {
+                    self.standard_jobs.insert(standard_job.job_id, job_id);
+                    standard_job.job_id
+                }
+                _ => job_id,
+            };
+            outgoing.push((channel.sender.clone(), message));
+            if let Some(set_new_prev_hash) = &set_new_prev_hash {
+                let set_new_prev_hash = SetNewPrevHash {
+                    channel_id,
+                    job_id: channel_job_id,
+                    ..set_new_prev_hash.clone()
+                };
+                outgoing.push((
+                    channel.sender.clone(),
+                    Mining::SetNewPrevHash(set_new_prev_hash),
+                ));
+            }
+        }
+        Ok(outgoing)
+    }
+
+    /// Opens a standard channel for the downstream `downstream_id`, returns the messages to send
+    /// back: the channel and, if already known, the current job and prev hash.
+    pub fn open_standard_channel(
+        &mut self,
+        downstream_id: u32,
+        sender: Sender<EitherFrame>,
+        request_id: u32,
+        nominal_hash_rate: f32,
+    ) -> ReverseTranslatorResult<Vec<Mining<'static>>> {
+        let channel_id = self.ids.safe_lock(|ids| ids.new_channel_id(0))?;
+        let messages: Vec<Mining<'static>> = self
+            .factory
+            .add_standard_channel(request_id, nominal_hash_rate, true, channel_id)?
+            .into_iter()
+            .map(|message| message.into_static())
+            .collect();
+        for message in &messages {
+            match message {
+                Mining::OpenStandardMiningChannelSuccess(success) => {
+                    self.add_channel(
+                        success.channel_id,
+                        downstream_id,
+                        sender.clone(),
+                        nominal_hash_rate,
+                        success.target.clone().into(),
+                    );
+                }
+                Mining::NewMiningJob(job) => {
+                    if let Some(current_job) = self.current_job {
+                        self.standard_jobs.insert(job.job_id, current_job);
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Opens an extended channel for the downstream `downstream_id`, returns the messages to send
+    /// back: the channel and, if already known, the current job and prev hash.
+    pub fn open_extended_channel(
+        &mut self,
+        downstream_id: u32,
+        sender: Sender<EitherFrame>,
+        request_id: u32,
+        nominal_hash_rate: f32,
+        min_extranonce_size: u16,
+    ) -> ReverseTranslatorResult<Vec<Mining<'static>>> {
+        let messages: Vec<Mining<'static>> = self
+            .factory
+            .new_extended_channel(request_id, nominal_hash_rate, min_extranonce_size)?
+            .into_iter()
+            .map(|message| message.into_static())
+            .collect();
+        let success = messages.iter().find_map(|message| match message {
+            Mining::OpenExtendedMiningChannelSuccess(success) => {
+                Some((success.channel_id, success.target.clone()))
+            }
+            _ => None,
+        });
+        let Some((channel_id, target)) = success else {
+            return Ok(messages);
+        };
+        self.add_channel(
+            channel_id,
+            downstream_id,
+            sender,
+            nominal_hash_rate,
+            target.into(),
+        );
+        // The factory replays the jobs as they were received, they must be addressed to the new
+        // channel
+        Ok(messages
+            .into_iter()
+            .map(|message| match message {
+                Mining::NewExtendedMiningJob(mut job) => {
+                    job.channel_id = channel_id;
+                    Mining::NewExtendedMiningJob(job)
+                }
+                message => message,
+            })
+            .collect())
+    }
+
+    fn add_channel(
+        &mut self,
+        channel_id: u32,
+        downstream_id: u32,
+        sender: Sender<EitherFrame>,
+        nominal_hash_rate: f32,
+        target: Target,
+    ) {
+        let vardiff_config = VardiffConfig {
+            min_target: Some(self.upstream_target.clone()),
+            ..VardiffConfig::new(self.shares_per_minute)
+        };
+        let vardiff =
+            Vardiff::with_target(vardiff_config, nominal_hash_rate, target, Instant::now());
+        self.channels.insert(
+            channel_id,
+            Channel {
+                downstream_id,
+                sender,
+                vardiff,
+            },
+        );
+    }
+
+    /// Sets the target of a channel from the hashrate announced with `UpdateChannel`.
+    pub fn update_channel(
+        &mut self,
+        channel_id: u32,
+        nominal_hash_rate: f32,
+    ) -> ReverseTranslatorResult<Mining<'static>> {
+        let maximum_target =
+            hash_rate_to_target(nominal_hash_rate.into(), self.shares_per_minute.into())?;
+        let target: Target = maximum_target.clone().into();
+        let channel = self
+            .channels
+            .get_mut(&channel_id)
+            .ok_or(roles_logic_sv2::Error::NotFoundChannelId)?;
+        channel.vardiff = Vardiff::with_target(
+            channel.vardiff.config().clone(),
+            nominal_hash_rate,
+            target.clone(),
+            Instant::now(),
+        );
+        self.factory.update_target_for_channel(channel_id, target);
+        Ok(Mining::SetTarget(SetTarget {
+            channel_id,
+            maximum_target,
+        }))
+    }
+
+    /// Validates a share received on a standard channel.
+    pub fn on_submit_shares_standard(
+        &mut self,
+        share: SubmitSharesStandard,
+    ) -> ReverseTranslatorResult<Vec<Mining<'static>>> {
+        let (channel_id, sequence_number) = (share.channel_id, share.sequence_number);
+        let job_id = match self.standard_jobs.get(&share.job_id) {
+            Some(job_id) => *job_id,
+            None => {
+                return Ok(vec![share_error(
+                    channel_id,
+                    sequence_number,
+                    SubmitSharesError::invalid_job_id_error_code(),
+                )])
+            }
+        };
+        let result = self.factory.on_submit_shares_standard(share)?;
+        self.on_share_result(channel_id, sequence_number, result, Some(job_id))
+    }
+
+    /// Validates a share received on an extended channel.
+    pub fn on_submit_shares_extended(
+        &mut self,
+        share: SubmitSharesExtended<'static>,
+    ) -> ReverseTranslatorResult<Vec<Mining<'static>>> {
+        let (channel_id, sequence_number) = (share.channel_id, share.sequence_number);
+        let result = self.factory.on_submit_shares_extended(share)?;
+        self.on_share_result(channel_id, sequence_number, result, None)
+    }
+
+    // Sends upstream the shares that meet the pool target and answers the downstream. `job_id` is
+    // the extended job of a share received on a standard channel.
+    fn on_share_result(
+        &mut self,
+        channel_id: u32,
+        sequence_number: u32,
+        result: OnNewShare,
+        job_id: Option<u32>,
+    ) -> ReverseTranslatorResult<Vec<Mining<'static>>> {
+        match result {
+            OnNewShare::SendErrorDownstream(error) => {
+                debug!("Invalid share on channel {}: {:?}", channel_id, error);
+                return Ok(vec![Mining::SubmitSharesError(error)]);
+            }
+            OnNewShare::SendSubmitShareUpstream((Share::Extended(mut share), _))
+            | OnNewShare::ShareMeetBitcoinTarget((Share::Extended(mut share), _, _, _)) => {
+                if let Some(job_id) = job_id {
+                    share.job_id = job_id;
+                }
+                self.submits.try_send(share.into_static()).map_err(|e| {
+                    Error::ChannelSend(Box::new(format!("share not submitted: {}", e)))
+                })?;
+            }
+            OnNewShare::ShareMeetDownstreamTarget => (),
+            result => {
+                error!("Unexpected share validation result: {:?}", result);
+                return Ok(vec![share_error(
+                    channel_id,
+                    sequence_number,
+                    SubmitSharesError::invalid_channel_error_code(),
+                )]);
+            }
+        }
+        let mut responses = vec![Mining::SubmitSharesSuccess(SubmitSharesSuccess {
+            channel_id,
+            last_sequence_number: sequence_number,
+            new_submits_accepted_count: 1,
+            new_shares_sum: 1,
+        })];
+        let new_target = self
+            .channels
+            .get_mut(&channel_id)
+            .and_then(|channel| channel.vardiff.on_share(Instant::now()));
+        if let Some(target) = new_target {
+            responses.push(self.set_target(channel_id, target));
+        }
+        Ok(responses)
+    }
+
+    fn on_vardiff_timer(&mut self) -> Outgoing {
+        let now = Instant::now();
+        let retargets: Vec<(u32, Target)> = self
+            .channels
+            .iter_mut()
+            .filter_map(|(channel_id, channel)| {
+                channel
+                    .vardiff
+                    .on_timer(now)
+                    .map(|target| (*channel_id, target))
+            })
+            .collect();
+        let mut outgoing = vec![];
+        for (channel_id, target) in retargets {
+            let message = self.set_target(channel_id, target);
+            if let Some(channel) = self.channels.get(&channel_id) {
+                outgoing.push((channel.sender.clone(), message));
+            }
+        }
+        outgoing
+    }
+
+    fn set_target(&mut self, channel_id: u32, target: Target) -> Mining<'static> {
+        self.factory
+            .update_target_for_channel(channel_id, target.clone());
+        Mining::SetTarget(SetTarget {
+            channel_id,
+            maximum_target: target.into(),
+        })
+    }
+
+    /// Forgets the channels of a disconnected downstream, their extranonces are given to the next
+    /// channels opened.
+    pub fn remove_downstream(&mut self, downstream_id: u32) {
+        let factory = &mut self.factory;
+        self.channels.retain(|channel_id, channel| {
+            if channel.downstream_id != downstream_id {
+                return true;
+            }
+            factory.remove_channel(*channel_id);
+            false
+        });
+    }
+}
+
+fn share_error(channel_id: u32, sequence_number: u32, error_code: &str) -> Mining<'static> {
+    Mining::SubmitSharesError(SubmitSharesError {
+        channel_id,
+        sequence_number,
+        // Infallible unwrap the error codes are short static strings
+        error_code: error_code.to_string().try_into().unwrap(),
+    })
+}
+
+/// Sends the messages to the downstreams, the downstreams that are gone are skipped.
+pub async fn dispatch(outgoing: Outgoing) {
+    for (sender, message) in outgoing {
+        let frame: Result<StdFrame, _> = AnyMessage::Mining(message).try_into();
+        match frame {
+            Ok(frame) => {
+                if sender.send(frame.into()).await.is_err() {
+                    debug!("Downstream disconnected, message dropped");
+                }
+            }
+            Err(e) => error!("Can not encode message for downstream: {:?}", e),
+        }
+    }
+}
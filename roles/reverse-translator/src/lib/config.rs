@@ -0,0 +1,52 @@
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use network_helpers_sv2::{admission::AdmissionConfig, ConnectionLimitsConfig, RekeyConfig};
+use serde::Deserialize;
+
+/// Configuration of the reverse translator.
+///
+/// The reverse translator connects to the SV1 pool at
+/// [`ReverseTranslatorConfig::upstream_address`] and accepts SV2 mining devices and proxies on
+/// [`ReverseTranslatorConfig::listen_address`].
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReverseTranslatorConfig {
+    /// `host:port` of the SV1 pool.
+    pub upstream_address: String,
+    /// User name sent upstream with `mining.authorize`, every share found by the downstreams is
+    /// submitted with it.
+    pub upstream_user: String,
+    /// Password sent upstream with `mining.authorize`.
+    #[serde(default)]
+    pub upstream_password: String,
+    /// Version rolling mask requested upstream with `mining.configure`, `None` to not request
+    /// version rolling.
+    #[serde(default = "default_version_rolling_mask")]
+    pub version_rolling_mask: Option<u32>,
+    /// Bytes of the upstream extranonce2 reserved to tell the downstream extended channels apart,
+    /// what is left of the extranonce2 is the search space of each extended channel.
+    #[serde(default = "default_extranonce_prefix_size")]
+    pub extranonce_prefix_size: usize,
+    /// Address where the SV2 downstreams connect.
+    pub listen_address: String,
+    pub authority_public_key: Secp256k1PublicKey,
+    pub authority_secret_key: Secp256k1SecretKey,
+    pub cert_validity_sec: u64,
+    /// Share rate of the downstream channels.
+    pub shares_per_minute: f32,
+    /// Limits applied to the SV2 downstream connections.
+    #[serde(default)]
+    pub admission: AdmissionConfig,
+    /// When the SV2 downstream connections are rekeyed, never by default.
+    #[serde(default)]
+    pub rekey_policy: RekeyConfig,
+    /// Timeouts, keepalive and frame size limit of the SV2 downstream connections.
+    #[serde(default)]
+    pub connection_limits: ConnectionLimitsConfig,
+}
+
+fn default_version_rolling_mask() -> Option<u32> {
+    Some(0x1FFFE000)
+}
+
+fn default_extranonce_prefix_size() -> usize {
+    1
+}
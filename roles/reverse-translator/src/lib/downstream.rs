@@ -0,0 +1,384 @@
+//! SV2 downstreams, mining devices or proxies, connected to the reverse translator.
+
+use crate::{
+    bridge::{dispatch, Bridge},
+    config::ReverseTranslatorConfig,
+    error::{Error, ReverseTranslatorResult},
+};
+use async_channel::{Receiver, Sender};
+use binary_sv2::Str0255;
+use codec_sv2::{HandshakeRole, RekeyPolicy, StandardEitherFrame, StandardSv2Frame};
+use network_helpers_sv2::{
+    admission::AdmissionControl, certificate_provider::certificate_provider,
+    noise_connection::Connection,
+};
+use roles_logic_sv2::{
+    common_messages_sv2::{
+        has_requires_std_job, has_version_rolling, SetupConnection, SetupConnectionError,
+        SetupConnectionSuccess,
+    },
+    common_properties::{CommonDownstreamData, IsDownstream, IsMiningDownstream},
+    handlers::{
+        common::{ParseCommonMessagesFromDownstream, SendTo as SendToCommon},
+        mining::{ParseMiningMessagesFromDownstream, SendTo, SupportedChannelTypes},
+    },
+    mining_sv2::{
+        OpenExtendedMiningChannel, OpenStandardMiningChannel, SetCustomMiningJob,
+        SubmitSharesExtended, SubmitSharesStandard, UpdateChannel,
+    },
+    parsers::{AnyMessage, CommonMessages, IsSv2Message, Mining},
+    utils::{Id, Mutex},
+};
+use std::{convert::TryInto, net::SocketAddr, sync::Arc};
+use tokio::{net::TcpListener, task};
+use tracing::{debug, error, info, warn};
+
+pub type Message = AnyMessage<'static>;
+pub type StdFrame = StandardSv2Frame<Message>;
+pub type EitherFrame = StandardEitherFrame<Message>;
+
+/// Accepts the SV2 downstreams on [`ReverseTranslatorConfig::listen_address`].
+pub async fn listen_for_downstreams(
+    config: ReverseTranslatorConfig,
+    bridge: Arc<Mutex<Bridge>>,
+) -> ReverseTranslatorResult<()> {
+    let certificate_provider = certificate_provider(
+        config.authority_public_key,
+        config.authority_secret_key,
+        std::time::Duration::from_secs(config.cert_validity_sec),
+        None,
+    )
+    .map_err(|e| Error::InvalidConfig(format!("Invalid authority keys: {:?}", e)))?;
+    let admission = AdmissionControl::new(config.admission.clone());
+    let rekey_policy: RekeyPolicy = config.rekey_policy.into();
+    let connection_limits = config.connection_limits;
+    let listener = TcpListener::bind(&config.listen_address).await?;
+    info!("Listening for SV2 downstreams on {}", config.listen_address);
+    let mut downstream_ids = Id::new();
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("Error accepting connection: {:?}", e);
+                continue;
+            }
+        };
+        info!("New connection from {}", address);
+        let permit = match admission.admit(address.ip()) {
+            Ok(permit) => permit,
+            Err(e) => {
+                warn!("Refusing connection from {}: {}", address, e);
+                continue;
+            }
+        };
+        let responder = match certificate_provider.responder() {
+            Ok(responder) => responder,
+            Err(e) => {
+                error!("Can not sign the certificate: {:?}", e);
+                continue;
+            }
+        };
+        let id = downstream_ids.next();
+        let bridge = bridge.clone();
+        task::spawn(async move {
+            let connection = Connection::new_with_config(
+                stream,
+                HandshakeRole::Responder(responder),
+                rekey_policy,
+                permit.into_connection_config(connection_limits.into()),
+            )
+            .await;
+            match connection {
+                Ok((receiver, sender)) => {
+                    if let Err(e) = Downstream::run(id, receiver, sender, bridge, address).await {
+                        warn!("Downstream {} at {} dropped: {}", id, address, e);
+                    }
+                }
+                Err(e) => warn!("Noise handshake with {} failed: {:?}", address, e),
+            }
+        });
+    }
+}
+
+/// A SV2 downstream connection.
+#[derive(Debug)]
+pub struct Downstream {
+    id: u32,
+    sender: Sender<EitherFrame>,
+    downstream_data: CommonDownstreamData,
+    bridge: Arc<Mutex<Bridge>>,
+}
+
+impl Downstream {
+    async fn run(
+        id: u32,
+        mut receiver: Receiver<EitherFrame>,
+        mut sender: Sender<EitherFrame>,
+        bridge: Arc<Mutex<Bridge>>,
+        address: SocketAddr,
+    ) -> ReverseTranslatorResult<()> {
+        let setup_connection = Arc::new(Mutex::new(SetupConnectionHandler { flags: 0 }));
+        let downstream_data =
+            SetupConnectionHandler::setup(setup_connection, &mut receiver, &mut sender).await?;
+        info!("Downstream {} at {} set up", id, address);
+        let self_ = Arc::new(Mutex::new(Downstream {
+            id,
+            sender,
+            downstream_data,
+            bridge: bridge.clone(),
+        }));
+        let result = Self::receive(self_, receiver).await;
+        bridge.safe_lock(|bridge| bridge.remove_downstream(id))?;
+        info!("Downstream {} at {} disconnected", id, address);
+        result
+    }
+
+    async fn receive(
+        self_: Arc<Mutex<Self>>,
+        receiver: Receiver<EitherFrame>,
+    ) -> ReverseTranslatorResult<()> {
+        while let Ok(frame) = receiver.recv().await {
+            let mut frame: StdFrame = frame.try_into()?;
+            let message_type = frame
+                .get_header()
+                .ok_or_else(|| Error::InvalidDownstreamMessage("No header set".to_string()))?
+                .msg_type();
+            let payload = frame.payload();
+            let send_to = ParseMiningMessagesFromDownstream::handle_message_mining(
+                self_.clone(),
+                message_type,
+                payload,
+            );
+            let messages = match send_to {
+                Ok(SendTo::Respond(message)) => vec![message],
+                Ok(SendTo::Multiple(send_to)) => send_to
+                    .into_iter()
+                    .filter_map(|send_to| match send_to {
+                        SendTo::Respond(message) => Some(message),
+                        _ => None,
+                    })
+                    .collect(),
+                Ok(SendTo::None(_)) => vec![],
+                Ok(send_to) => {
+                    error!("Unexpected SendTo: {:?}", send_to);
+                    vec![]
+                }
+                Err(e) => {
+                    warn!(
+                        "Can not handle downstream message {}: {:?}",
+                        message_type, e
+                    );
+                    vec![]
+                }
+            };
+            let sender = self_.safe_lock(|s| s.sender.clone())?;
+            dispatch(
+                messages
+                    .into_iter()
+                    .map(|message| (sender.clone(), message))
+                    .collect(),
+            )
+            .await;
+        }
+        Ok(())
+    }
+}
+
+impl IsDownstream for Downstream {
+    fn get_downstream_mining_data(&self) -> CommonDownstreamData {
+        self.downstream_data
+    }
+}
+
+impl IsMiningDownstream for Downstream {}
+
+// Converts the errors of the bridge in the errors of the message handlers
+fn into_handler_error(e: Error) -> roles_logic_sv2::Error {
+    match e {
+        Error::RolesLogic(e) => e,
+        e => roles_logic_sv2::Error::PoisonLock(e.to_string()),
+    }
+}
+
+fn respond(messages: Vec<Mining<'static>>) -> SendTo<()> {
+    SendTo::Multiple(messages.into_iter().map(SendTo::Respond).collect())
+}
+
+impl ParseMiningMessagesFromDownstream<()> for Downstream {
+    fn get_channel_type(&self) -> SupportedChannelTypes {
+        SupportedChannelTypes::GroupAndExtended
+    }
+
+    fn is_work_selection_enabled(&self) -> bool {
+        false
+    }
+
+    fn is_downstream_authorized(
+        _self_mutex: Arc<Mutex<Self>>,
+        _user_identity: &Str0255,
+    ) -> Result<bool, roles_logic_sv2::Error> {
+        Ok(true)
+    }
+
+    fn handle_open_standard_mining_channel(
+        &mut self,
+        m: OpenStandardMiningChannel,
+    ) -> Result<SendTo<()>, roles_logic_sv2::Error> {
+        info!(
+            "Received OpenStandardMiningChannel from: {} with id: {}",
+            std::str::from_utf8(m.user_identity.as_ref()).unwrap_or("Unknown identity"),
+            m.get_request_id_as_u32()
+        );
+        let (id, sender) = (self.id, self.sender.clone());
+        let messages = self.bridge.safe_lock(|bridge| {
+            bridge.open_standard_channel(id, sender, m.get_request_id_as_u32(), m.nominal_hash_rate)
+        })?;
+        Ok(respond(messages.map_err(into_handler_error)?))
+    }
+
+    fn handle_open_extended_mining_channel(
+        &mut self,
+        m: OpenExtendedMiningChannel,
+    ) -> Result<SendTo<()>, roles_logic_sv2::Error> {
+        info!(
+            "Received OpenExtendedMiningChannel from: {} with id: {}",
+            std::str::from_utf8(m.user_identity.as_ref()).unwrap_or("Unknown identity"),
+            m.get_request_id_as_u32()
+        );
+        let (id, sender) = (self.id, self.sender.clone());
+        let messages = self.bridge.safe_lock(|bridge| {
+            bridge.open_extended_channel(
+                id,
+                sender,
+                m.request_id,
+                m.nominal_hash_rate,
+                m.min_extranonce_size,
+            )
+        })?;
+        Ok(respond(messages.map_err(into_handler_error)?))
+    }
+
+    fn handle_update_channel(
+        &mut self,
+        m: UpdateChannel,
+    ) -> Result<SendTo<()>, roles_logic_sv2::Error> {
+        debug!("UpdateChannel: {:?}", m);
+        let message = self
+            .bridge
+            .safe_lock(|bridge| bridge.update_channel(m.channel_id, m.nominal_hash_rate))?;
+        Ok(SendTo::Respond(message.map_err(into_handler_error)?))
+    }
+
+    fn handle_submit_shares_standard(
+        &mut self,
+        m: SubmitSharesStandard,
+    ) -> Result<SendTo<()>, roles_logic_sv2::Error> {
+        debug!("SubmitSharesStandard {:?}", m);
+        let messages = self
+            .bridge
+            .safe_lock(|bridge| bridge.on_submit_shares_standard(m))?;
+        Ok(respond(messages.map_err(into_handler_error)?))
+    }
+
+    fn handle_submit_shares_extended(
+        &mut self,
+        m: SubmitSharesExtended,
+    ) -> Result<SendTo<()>, roles_logic_sv2::Error> {
+        debug!("SubmitSharesExtended {:?}", m);
+        let messages = self
+            .bridge
+            .safe_lock(|bridge| bridge.on_submit_shares_extended(m.into_static()))?;
+        Ok(respond(messages.map_err(into_handler_error)?))
+    }
+
+    fn handle_set_custom_mining_job(
+        &mut self,
+        _m: SetCustomMiningJob,
+    ) -> Result<SendTo<()>, roles_logic_sv2::Error> {
+        // The jobs come from the SV1 pool, work selection is never negotiated
+        Err(roles_logic_sv2::Error::UnexpectedMessage(
+            const_sv2::MESSAGE_TYPE_SET_CUSTOM_MINING_JOB,
+        ))
+    }
+}
+
+/// Handles the `SetupConnection` of a downstream.
+struct SetupConnectionHandler {
+    // Flags of the downstream `SetupConnection`
+    flags: u32,
+}
+
+impl SetupConnectionHandler {
+    async fn setup(
+        self_: Arc<Mutex<Self>>,
+        receiver: &mut Receiver<EitherFrame>,
+        sender: &mut Sender<EitherFrame>,
+    ) -> ReverseTranslatorResult<CommonDownstreamData> {
+        let mut incoming: StdFrame = receiver.recv().await?.try_into()?;
+        let message_type = incoming
+            .get_header()
+            .ok_or_else(|| Error::InvalidDownstreamMessage("No header set".to_string()))?
+            .msg_type();
+        let payload = incoming.payload();
+        let response = ParseCommonMessagesFromDownstream::handle_message_common(
+            self_.clone(),
+            message_type,
+            payload,
+        )?;
+        let message = response
+            .into_message()
+            .ok_or(roles_logic_sv2::Error::NoDownstreamsConnected)?;
+        let frame: StdFrame = AnyMessage::Common(message.clone()).try_into()?;
+        sender.send(frame.into()).await?;
+        match message {
+            CommonMessages::SetupConnectionSuccess(_) => {
+                let flags = self_.safe_lock(|handler| handler.flags)?;
+                Ok(CommonDownstreamData {
+                    header_only: has_requires_std_job(flags),
+                    work_selection: false,
+                    version_rolling: has_version_rolling(flags),
+                })
+            }
+            message => Err(Error::RolesLogic(
+                roles_logic_sv2::Error::UnexpectedMessage(message.message_type()),
+            )),
+        }
+    }
+}
+
+impl ParseCommonMessagesFromDownstream for SetupConnectionHandler {
+    fn handle_setup_connection(
+        &mut self,
+        m: SetupConnection,
+    ) -> Result<SendToCommon, roles_logic_sv2::Error> {
+        info!(
+            "Received `SetupConnection`: version={}, flags={:b}",
+            m.min_version, m.flags
+        );
+        // The jobs come from the SV1 pool, work selection can not be negotiated. The
+        // REQUIRES_WORK_SELECTION bit is checked here as `has_work_selection` reads the
+        // REQUIRES_VERSION_ROLLING bit
+        if m.flags & 0b0000_0000_0000_0010 != 0 {
+            return Ok(SendToCommon::RelayNewMessageToRemote(
+                Arc::new(Mutex::new(())),
+                CommonMessages::SetupConnectionError(SetupConnectionError {
+                    // REQUIRES_WORK_SELECTION
+                    flags: 0b0000_0000_0000_0010,
+                    error_code: "unsupported-feature-flags"
+                        .to_string()
+                        .into_bytes()
+                        .try_into()?,
+                }),
+            ));
+        }
+        self.flags = m.flags;
+        // Neither a fixed version nor extended channels are required
+        Ok(SendToCommon::RelayNewMessageToRemote(
+            Arc::new(Mutex::new(())),
+            CommonMessages::SetupConnectionSuccess(SetupConnectionSuccess {
+                flags: 0,
+                used_version: 2,
+            }),
+        ))
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    fmt::Debug,
+    sync::{MutexGuard, PoisonError},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    ChannelSend(Box<dyn std::marker::Send + Debug>),
+    ChannelRecv(async_channel::RecvError),
+    BinarySv2(binary_sv2::Error),
+    Codec(codec_sv2::Error),
+    Framing(codec_sv2::framing_sv2::Error),
+    RolesLogic(roles_logic_sv2::Error),
+    V1Protocol(String),
+    Json(serde_json::Error),
+    /// The SV1 pool closed the connection
+    UpstreamDisconnected,
+    /// The SV1 pool sent something that can not be translated in SV2
+    InvalidUpstreamMessage(String),
+    /// A downstream sent something that can not be handled
+    InvalidDownstreamMessage(String),
+    InvalidConfig(String),
+    PoisonLock(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Error::*;
+        match self {
+            Io(ref e) => write!(f, "I/O error: `{:?}", e),
+            ChannelSend(ref e) => write!(f, "Channel send failed: `{:?}`", e),
+            ChannelRecv(ref e) => write!(f, "Channel recv failed: `{:?}`", e),
+            BinarySv2(ref e) => write!(f, "Binary SV2 error: `{:?}`", e),
+            Codec(ref e) => write!(f, "Codec SV2 error: `{:?}", e),
+            Framing(ref e) => write!(f, "Framing SV2 error: `{:?}`", e),
+            RolesLogic(ref e) => write!(f, "Roles Logic SV2 error: `{:?}`", e),
+            V1Protocol(ref e) => write!(f, "SV1 protocol error: `{}`", e),
+            Json(ref e) => write!(f, "Invalid JSON: `{}`", e),
+            UpstreamDisconnected => write!(f, "SV1 pool disconnected"),
+            InvalidUpstreamMessage(ref e) => write!(f, "Invalid message from SV1 pool: {}", e),
+            InvalidDownstreamMessage(ref e) => write!(f, "Invalid message from downstream: {}", e),
+            InvalidConfig(ref e) => write!(f, "Invalid configuration: {}", e),
+            PoisonLock(ref e) => write!(f, "Poison lock: {:?}", e),
+        }
+    }
+}
+
+pub type ReverseTranslatorResult<T> = Result<T, Error>;
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<async_channel::RecvError> for Error {
+    fn from(e: async_channel::RecvError) -> Error {
+        Error::ChannelRecv(e)
+    }
+}
+
+impl<T: 'static + std::marker::Send + Debug> From<async_channel::SendError<T>> for Error {
+    fn from(e: async_channel::SendError<T>) -> Error {
+        Error::ChannelSend(Box::new(e))
+    }
+}
+
+impl From<binary_sv2::Error> for Error {
+    fn from(e: binary_sv2::Error) -> Error {
+        Error::BinarySv2(e)
+    }
+}
+
+impl From<codec_sv2::Error> for Error {
+    fn from(e: codec_sv2::Error) -> Error {
+        Error::Codec(e)
+    }
+}
+
+impl From<codec_sv2::framing_sv2::Error> for Error {
+    fn from(e: codec_sv2::framing_sv2::Error) -> Error {
+        Error::Framing(e)
+    }
+}
+
+impl From<roles_logic_sv2::Error> for Error {
+    fn from(e: roles_logic_sv2::Error) -> Error {
+        Error::RolesLogic(e)
+    }
+}
+
+impl From<v1::error::Error<'_>> for Error {
+    fn from(e: v1::error::Error<'_>) -> Error {
+        Error::V1Protocol(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl<T> From<PoisonError<MutexGuard<'_, T>>> for Error {
+    fn from(e: PoisonError<MutexGuard<T>>) -> Error {
+        Error::PoisonLock(e.to_string())
+    }
+}
@@ -0,0 +1,88 @@
+//! Bridges SV2 mining devices and proxies to a SV1 pool.
+//!
+//! The [`upstream::Upstream`] keeps a single SV1 session with the pool and translates its jobs in
+//! SV2, the [`bridge::Bridge`] shares that session between the channels opened by the SV2
+//! downstreams and sends upstream the shares that meet the pool target.
+pub mod bridge;
+pub mod config;
+pub mod downstream;
+pub mod error;
+pub mod translation;
+pub mod upstream;
+
+use async_channel::unbounded;
+use bridge::Bridge;
+use config::ReverseTranslatorConfig;
+use error::{Error, ReverseTranslatorResult};
+use roles_logic_sv2::utils::Mutex;
+use std::sync::Arc;
+use tracing::{error, info};
+use upstream::{Upstream, UpstreamEvent};
+
+#[derive(Debug, Clone)]
+pub struct ReverseTranslatorSv2 {
+    config: ReverseTranslatorConfig,
+}
+
+impl ReverseTranslatorSv2 {
+    pub fn new(config: ReverseTranslatorConfig) -> ReverseTranslatorSv2 {
+        ReverseTranslatorSv2 { config }
+    }
+
+    /// Connects to the SV1 pool and, once the session is subscribed, starts accepting SV2
+    /// downstreams. Returns when the connection with the pool is closed.
+    pub async fn start(&self) -> ReverseTranslatorResult<()> {
+        let config = self.config.clone();
+        let (events_tx, events_rx) = unbounded();
+        let (submits_tx, submits_rx) = unbounded();
+
+        let upstream = Upstream::new(&config, events_tx);
+        let upstream_address = config.upstream_address.clone();
+        let mut upstream_task =
+            tokio::task::spawn(async move { upstream.run(&upstream_address, submits_rx).await });
+
+        // The extranonce of the session is needed to assign the downstream extranonces
+        let (extranonce1, extranonce2_size) = tokio::select! {
+            event = events_rx.recv() => match event? {
+                UpstreamEvent::Subscribed {
+                    extranonce1,
+                    extranonce2_size,
+                } => (extranonce1, extranonce2_size),
+                _ => {
+                    return Err(Error::InvalidUpstreamMessage(
+                        "pool sent a message before the subscribe response".to_string(),
+                    ))
+                }
+            },
+            result = &mut upstream_task => {
+                return flatten(result);
+            }
+        };
+        info!(
+            "Subscribed to SV1 pool, extranonce1: {:?}, extranonce2 size: {}",
+            extranonce1, extranonce2_size
+        );
+
+        let bridge = Arc::new(Mutex::new(Bridge::new(
+            &config,
+            extranonce1,
+            extranonce2_size,
+            submits_tx,
+        )?));
+        Bridge::start(bridge.clone(), events_rx);
+
+        let listener = tokio::task::spawn(downstream::listen_for_downstreams(config, bridge));
+        let result = flatten(upstream_task.await);
+        listener.abort();
+        if let Err(e) = &result {
+            error!("SV1 pool connection terminated: {}", e);
+        }
+        result
+    }
+}
+
+fn flatten(
+    result: Result<ReverseTranslatorResult<()>, tokio::task::JoinError>,
+) -> ReverseTranslatorResult<()> {
+    result.map_err(|e| Error::Io(std::io::Error::other(e)))?
+}
@@ -0,0 +1,234 @@
+//! Translation between the SV1 messages exchanged with the pool and the SV2 messages exchanged
+//! with the downstreams.
+//!
+//! A `mining.notify` becomes a `NewExtendedMiningJob`. When the notify changes the prev hash, or
+//! asks the miners to drop the previous jobs, the job is sent as a future job and activated by a
+//! `SetNewPrevHash`, otherwise it can be mined at once on the current prev hash. Shares that meet
+//! the upstream target go the other way and become a `mining.submit` for the SV1 job they refer
+//! to.
+
+use crate::error::{Error, ReverseTranslatorResult};
+use binary_sv2::{Seq0255, Sv2Option, B064K, U256};
+use roles_logic_sv2::{
+    mining_sv2::{NewExtendedMiningJob, SetNewPrevHash, SubmitSharesExtended},
+    utils::Id,
+};
+use std::{collections::HashMap, convert::TryInto};
+use v1::{
+    client_to_server, server_to_client,
+    utils::{Extranonce, HexU32Be},
+};
+
+/// Keeps track of the SV2 jobs created from the `mining.notify` of the pool.
+#[derive(Debug, Default)]
+pub struct JobTranslator {
+    job_ids: Id,
+    // SV1 job id of every SV2 job created since the last prev hash
+    jobs: HashMap<u32, String>,
+    prev_hash: Option<U256<'static>>,
+    version_rolling_allowed: bool,
+}
+
+impl JobTranslator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the downstreams can roll the version, that is whether the pool accepted the
+    /// version rolling requested with `mining.configure`.
+    pub fn set_version_rolling_allowed(&mut self, version_rolling_allowed: bool) {
+        self.version_rolling_allowed = version_rolling_allowed;
+    }
+
+    /// Translates a `mining.notify` in a `NewExtendedMiningJob`, followed by the
+    /// `SetNewPrevHash` that activates it when the job starts a new prev hash.
+    ///
+    /// The `channel_id` of the returned messages is 0, it is set for every downstream channel
+    /// when the messages are dispatched.
+    pub fn on_notify(
+        &mut self,
+        notify: server_to_client::Notify<'static>,
+    ) -> ReverseTranslatorResult<(
+        NewExtendedMiningJob<'static>,
+        Option<SetNewPrevHash<'static>>,
+    )> {
+        let prev_hash = notify.prev_hash.0.clone();
+        let new_prev_hash = notify.clean_jobs || self.prev_hash.as_ref() != Some(&prev_hash);
+        if new_prev_hash {
+            self.jobs.clear();
+            self.prev_hash = Some(prev_hash.clone());
+        }
+        let job_id = self.job_ids.next();
+        self.jobs.insert(job_id, notify.job_id.clone());
+
+        let merkle_path: Vec<U256<'static>> = notify
+            .merkle_branch
+            .into_iter()
+            .map(|node| node.0)
+            .collect();
+        let merkle_path = Seq0255::new(merkle_path).map_err(|_| {
+            Error::InvalidUpstreamMessage(format!(
+                "too many merkle branches in job {}",
+                notify.job_id
+            ))
+        })?;
+        let coinbase_tx_prefix: Vec<u8> = notify.coin_base1.into();
+        let coinbase_tx_prefix: B064K<'static> = coinbase_tx_prefix.try_into()?;
+        let coinbase_tx_suffix: Vec<u8> = notify.coin_base2.into();
+        let coinbase_tx_suffix: B064K<'static> = coinbase_tx_suffix.try_into()?;
+
+        let min_ntime = match new_prev_hash {
+            true => None,
+            false => Some(notify.time.0),
+        };
+        let job = NewExtendedMiningJob {
+            channel_id: 0,
+            job_id,
+            min_ntime: Sv2Option::new(min_ntime),
+            version: notify.version.0,
+            version_rolling_allowed: self.version_rolling_allowed,
+            merkle_path,
+            coinbase_tx_prefix,
+            coinbase_tx_suffix,
+        };
+        let set_new_prev_hash = new_prev_hash.then_some(SetNewPrevHash {
+            channel_id: 0,
+            job_id,
+            prev_hash,
+            min_ntime: notify.time.0,
+            nbits: notify.bits.0,
+        });
+        Ok((job, set_new_prev_hash))
+    }
+
+    /// SV1 job id of the SV2 job `job_id`, `None` if the job is unknown or stale.
+    pub fn sv1_job_id(&self, job_id: u32) -> Option<&str> {
+        self.jobs.get(&job_id).map(|job_id| job_id.as_str())
+    }
+}
+
+/// Builds the `mining.submit` of a share that meets the upstream target.
+///
+/// `share.extranonce` must be the extranonce2 of the share, as returned by the channel factory,
+/// and `job_id` the SV1 job id of the share job. When version rolling has been negotiated the
+/// rolled bits of the share version are sent as `version_bits`.
+pub fn share_to_submit(
+    share: &SubmitSharesExtended,
+    id: u64,
+    user_name: String,
+    job_id: String,
+    version_rolling_mask: Option<u32>,
+) -> ReverseTranslatorResult<client_to_server::Submit<'static>> {
+    let extra_nonce2: Extranonce<'static> = share.extranonce.to_vec().try_into()?;
+    Ok(client_to_server::Submit {
+        user_name,
+        job_id,
+        extra_nonce2,
+        time: HexU32Be(share.ntime),
+        nonce: HexU32Be(share.nonce),
+        version_bits: version_rolling_mask.map(|mask| HexU32Be(share.version & mask)),
+        id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use v1::json_rpc;
+
+    const PREV_HASH: &str = "4d16b6f85af6e2198f44ae2a6de67f78487ae5611b77c6c0440b921e00000000";
+
+    fn notify(
+        job_id: &str,
+        prev_hash: &str,
+        clean_jobs: bool,
+    ) -> server_to_client::Notify<'static> {
+        let message = format!(
+            r#"{{"id":null,"method":"mining.notify","params":["{}","{}","01000000010000","072f736c7573682f00000000",{},"20000000","1d00ffff","504e86ed",{}]}}"#,
+            job_id,
+            prev_hash,
+            r#"["aa00000000000000000000000000000000000000000000000000000000000000"]"#,
+            clean_jobs
+        );
+        match serde_json::from_str(&message).unwrap() {
+            json_rpc::Message::Notification(notification) => {
+                server_to_client::Notify::try_from(notification).unwrap()
+            }
+            _ => panic!("not a notification"),
+        }
+    }
+
+    #[test]
+    fn translates_notify() {
+        let mut translator = JobTranslator::new();
+        translator.set_version_rolling_allowed(true);
+
+        let notify_ = notify("bf", PREV_HASH, true);
+        let expected_prev_hash = notify_.prev_hash.0.clone();
+        let (job, set_new_prev_hash) = translator.on_notify(notify_).unwrap();
+        assert!(job.is_future());
+        assert!(job.version_rolling_allowed);
+        assert_eq!(job.version, 0x20000000);
+        assert_eq!(job.coinbase_tx_prefix.to_vec(), vec![1, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(job.merkle_path.to_vec().len(), 1);
+        assert_eq!(job.merkle_path.to_vec()[0][0], 0xaa);
+        let set_new_prev_hash = set_new_prev_hash.unwrap();
+        assert_eq!(set_new_prev_hash.job_id, job.job_id);
+        assert_eq!(set_new_prev_hash.nbits, 0x1d00ffff);
+        assert_eq!(set_new_prev_hash.min_ntime, 0x504e86ed);
+        assert_eq!(set_new_prev_hash.prev_hash, expected_prev_hash);
+        assert_eq!(translator.sv1_job_id(job.job_id), Some("bf"));
+
+        // same prev hash and no clean jobs: the job can be mined at once
+        let (second, set_new_prev_hash) = translator
+            .on_notify(notify("c0", PREV_HASH, false))
+            .unwrap();
+        assert!(set_new_prev_hash.is_none());
+        assert_eq!(second.min_ntime.into_inner(), Some(0x504e86ed));
+        assert_ne!(second.job_id, job.job_id);
+        assert_eq!(translator.sv1_job_id(job.job_id), Some("bf"));
+
+        // a new prev hash makes the previous jobs stale
+        let other_prev_hash = PREV_HASH.replace("4d16", "4d17");
+        let (third, set_new_prev_hash) = translator
+            .on_notify(notify("c1", &other_prev_hash, false))
+            .unwrap();
+        assert!(third.is_future());
+        assert!(set_new_prev_hash.is_some());
+        assert_eq!(translator.sv1_job_id(job.job_id), None);
+        assert_eq!(translator.sv1_job_id(second.job_id), None);
+        assert_eq!(translator.sv1_job_id(third.job_id), Some("c1"));
+    }
+
+    #[test]
+    fn translates_share() {
+        let share = SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 7,
+            job_id: 1,
+            nonce: 0xdeadbeef,
+            ntime: 0x504e86ed,
+            version: 0x2000e000,
+            extranonce: vec![1, 2, 3, 4].try_into().unwrap(),
+        };
+        let submit = share_to_submit(
+            &share,
+            4,
+            "worker".to_string(),
+            "bf".to_string(),
+            Some(0x1fffe000),
+        )
+        .unwrap();
+        assert_eq!(submit.id, 4);
+        assert_eq!(submit.job_id, "bf");
+        assert_eq!(Vec::<u8>::from(submit.extra_nonce2), vec![1, 2, 3, 4]);
+        assert_eq!(submit.nonce, HexU32Be(0xdeadbeef));
+        assert_eq!(submit.time, HexU32Be(0x504e86ed));
+        assert_eq!(submit.version_bits, Some(HexU32Be(0xe000)));
+
+        let submit =
+            share_to_submit(&share, 5, "worker".to_string(), "bf".to_string(), None).unwrap();
+        assert_eq!(submit.version_bits, None);
+    }
+}
@@ -0,0 +1,568 @@
+//! SV1 connection with the pool.
+//!
+//! [`Upstream`] configures, subscribes and authorizes the connection, then translates the jobs and
+//! the difficulty sent by the pool in [`UpstreamEvent`]s for the [`Bridge`](crate::bridge::Bridge)
+//! and submits the shares received from it.
+//!
+//! When the connection is lost, or the pool sends `client.reconnect`, a new session is opened.
+//! Failed attempts are retried with an exponential backoff.
+
+use crate::{
+    config::ReverseTranslatorConfig,
+    error::{Error, ReverseTranslatorResult},
+    translation::{share_to_submit, JobTranslator},
+};
+use async_channel::{Receiver, Sender};
+use roles_logic_sv2::mining_sv2::{
+    NewExtendedMiningJob, SetNewPrevHash, SubmitSharesExtended, Target,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+use sv1_server::job::target_from_difficulty;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{tcp::OwnedWriteHalf, TcpStream},
+};
+use tracing::{debug, error, info, warn};
+use v1::{
+    error::Error as V1Error,
+    json_rpc, server_to_client,
+    utils::{Extranonce, HexU32Be},
+    ClientStatus, IsClient,
+};
+
+/// Delay before reconnecting after a failure, doubled after every failed attempt.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// What the pool sent, translated for the SV2 side.
+#[derive(Debug, Clone)]
+pub enum UpstreamEvent {
+    /// The pool answered `mining.subscribe`, the extranonce space of the downstreams is known.
+    Subscribed {
+        extranonce1: Vec<u8>,
+        extranonce2_size: usize,
+    },
+    /// The pool changed the difficulty of the shares.
+    SetTarget(Target),
+    /// A new job, with the prev hash that activates it if the job starts a new prev hash.
+    NewJob(
+        NewExtendedMiningJob<'static>,
+        Option<SetNewPrevHash<'static>>,
+    ),
+}
+
+/// SV1 client connected to the pool.
+pub struct Upstream {
+    user_name: String,
+    password: String,
+    // Version rolling mask of the configuration, requested at every new session
+    requested_version_rolling_mask: Option<HexU32Be>,
+    // Set when the pool sends `client.reconnect`
+    reconnect: Option<server_to_client::Reconnect>,
+    next_id: u64,
+    status: ClientStatus,
+    configure_id: Option<u64>,
+    authorize_sent: bool,
+    // Pending `mining.authorize` (id, user name)
+    authorize_requests: HashMap<u64, String>,
+    authorized: Vec<String>,
+    // Ids of the pending `mining.submit`
+    submits: HashSet<u64>,
+    extranonce1: Option<Extranonce<'static>>,
+    extranonce2_size: usize,
+    version_rolling_mask: Option<HexU32Be>,
+    version_rolling_min_bit: Option<HexU32Be>,
+    last_notify: Option<server_to_client::Notify<'static>>,
+    jobs: JobTranslator,
+    events: Sender<UpstreamEvent>,
+    accepted_shares: u64,
+    rejected_shares: u64,
+}
+
+impl Upstream {
+    pub fn new(config: &ReverseTranslatorConfig, events: Sender<UpstreamEvent>) -> Self {
+        Self {
+            user_name: config.upstream_user.clone(),
+            password: config.upstream_password.clone(),
+            requested_version_rolling_mask: config.version_rolling_mask.map(HexU32Be),
+            reconnect: None,
+            next_id: 0,
+            status: ClientStatus::Init,
+            configure_id: None,
+            authorize_sent: false,
+            authorize_requests: HashMap::new(),
+            authorized: Vec::new(),
+            submits: HashSet::new(),
+            extranonce1: None,
+            extranonce2_size: 0,
+            version_rolling_mask: config.version_rolling_mask.map(HexU32Be),
+            version_rolling_min_bit: None,
+            last_notify: None,
+            jobs: JobTranslator::new(),
+            events,
+            accepted_shares: 0,
+            rejected_shares: 0,
+        }
+    }
+
+    /// Connects to the pool and keeps a session open with it, reconnecting when the connection is
+    /// lost. `submits` are the shares to send upstream, as returned by the channel factory.
+    /// Returns only when the bridge is gone.
+    pub async fn run(
+        mut self,
+        address: &str,
+        submits: Receiver<SubmitSharesExtended<'static>>,
+    ) -> ReverseTranslatorResult<()> {
+        let mut current_address = address.to_string();
+        let mut delay = RECONNECT_DELAY;
+        loop {
+            let result = self.session(&current_address, &submits).await;
+            if self.status == ClientStatus::Subscribed {
+                delay = RECONNECT_DELAY;
+            }
+            let wait = match result {
+                Ok(reconnect) => {
+                    let wait = Duration::from_secs(reconnect.wait_time.unwrap_or(0));
+                    current_address = reconnect_address(&current_address, &reconnect);
+                    info!(
+                        "SV1 pool asked to reconnect to {} in {:?}",
+                        current_address, wait
+                    );
+                    wait
+                }
+                Err(Error::ChannelRecv(e)) => return Err(Error::ChannelRecv(e)),
+                Err(e) => {
+                    warn!(
+                        "SV1 pool connection to {} lost: {}, reconnecting in {:?}",
+                        current_address, e, delay
+                    );
+                    // A pool that redirected the session and can not be reached is abandoned
+                    current_address = address.to_string();
+                    let wait = delay;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    wait
+                }
+            };
+            tokio::time::sleep(wait).await;
+            // The shares of the previous session can not be submitted on the new one
+            while submits.try_recv().is_ok() {}
+            self.reset_session();
+        }
+    }
+
+    // Runs a session with the pool until the connection is lost, or the pool asks to reconnect
+    async fn session(
+        &mut self,
+        address: &str,
+        submits: &Receiver<SubmitSharesExtended<'static>>,
+    ) -> ReverseTranslatorResult<server_to_client::Reconnect> {
+        let stream = TcpStream::connect(address).await?;
+        info!("Connected to SV1 pool at {}", address);
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let id = self.new_id();
+        self.configure_id = Some(id);
+        let configure = self.configure(id);
+        Self::send(&mut writer, configure).await?;
+
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let line = line?.ok_or(Error::UpstreamDisconnected)?;
+                    debug!("Received from SV1 pool: {}", line);
+                    for message in self.on_line(&line)? {
+                        Self::send(&mut writer, message).await?;
+                    }
+                    if let Some(reconnect) = self.reconnect.take() {
+                        return Ok(reconnect);
+                    }
+                }
+                share = submits.recv() => {
+                    if let Some(submit) = self.on_share(share?)? {
+                        Self::send(&mut writer, submit).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    // Forgets the state of the previous session, the jobs ids keep growing so that the shares of
+    // the old jobs are not mistaken for the new ones
+    fn reset_session(&mut self) {
+        self.status = ClientStatus::Init;
+        self.reconnect = None;
+        self.configure_id = None;
+        self.authorize_sent = false;
+        self.authorize_requests.clear();
+        self.authorized.clear();
+        self.submits.clear();
+        self.extranonce1 = None;
+        self.extranonce2_size = 0;
+        let mask = self.requested_version_rolling_mask.clone();
+        self.set_version_rolling_mask(mask);
+        self.version_rolling_min_bit = None;
+        self.last_notify = None;
+    }
+
+    // Handles a message of the pool and returns the messages to send back
+    fn on_line(&mut self, line: &str) -> ReverseTranslatorResult<Vec<json_rpc::Message>> {
+        let message: json_rpc::Message = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Skipping invalid line from SV1 pool: {}: {}", e, line);
+                return Ok(vec![]);
+            }
+        };
+        let mut responses = vec![];
+        let response_id = match &message {
+            json_rpc::Message::OkResponse(response)
+            | json_rpc::Message::ErrorResponse(response) => Some(response.id),
+            _ => None,
+        };
+        if let json_rpc::Message::OkResponse(response) = &message {
+            if self.submits.remove(&response.id) {
+                match response.result.as_bool() {
+                    Some(true) => self.on_submit_result(response.id, true),
+                    _ => self.on_submit_result(response.id, false),
+                }
+                return Ok(responses);
+            }
+        }
+        match self.handle_message(message) {
+            Ok(Some(response)) => responses.push(response),
+            Ok(None) => (),
+            Err(e) => warn!("Can not handle message from SV1 pool: {}: {}", e, line),
+        }
+        // Pools that do not support `mining.configure` answer with an error, in that case the
+        // connection is subscribed without version rolling
+        if self.status == ClientStatus::Init
+            && response_id.is_some()
+            && response_id == self.configure_id
+        {
+            warn!("SV1 pool does not support mining.configure, version rolling disabled");
+            self.set_version_rolling_mask(None);
+            self.status = ClientStatus::Configured;
+            let id = self.new_id();
+            responses.push(self.subscribe(id, None)?);
+        }
+        if self.status == ClientStatus::Subscribed && !self.authorize_sent {
+            self.authorize_sent = true;
+            let id = self.new_id();
+            let (user_name, password) = (self.user_name.clone(), self.password.clone());
+            responses.push(self.authorize(id, user_name, password)?);
+        }
+        Ok(responses)
+    }
+
+    // Translates a share in a `mining.submit`, `None` if the share job is stale
+    fn on_share(
+        &mut self,
+        share: SubmitSharesExtended<'static>,
+    ) -> ReverseTranslatorResult<Option<json_rpc::Message>> {
+        let job_id = match self.jobs.sv1_job_id(share.job_id) {
+            Some(job_id) => job_id.to_string(),
+            None => {
+                warn!("Dropping share for stale job {}", share.job_id);
+                return Ok(None);
+            }
+        };
+        let id = self.new_id();
+        let mask = self.version_rolling_mask.as_ref().map(|mask| mask.0);
+        let submit = share_to_submit(&share, id, self.user_name.clone(), job_id, mask)?;
+        self.submits.insert(id);
+        Ok(Some(submit.into()))
+    }
+
+    fn on_submit_result(&mut self, id: u64, accepted: bool) {
+        match accepted {
+            true => self.accepted_shares += 1,
+            false => {
+                self.rejected_shares += 1;
+                warn!("SV1 pool rejected share {}", id);
+            }
+        }
+        debug!(
+            "SV1 pool shares: {} accepted, {} rejected",
+            self.accepted_shares, self.rejected_shares
+        );
+    }
+
+    fn new_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn emit(&self, event: UpstreamEvent) {
+        // The channel is unbounded, it fails only if the bridge is gone
+        if self.events.try_send(event).is_err() {
+            error!("Bridge is down, dropping SV1 pool message");
+        }
+    }
+
+    async fn send(
+        writer: &mut OwnedWriteHalf,
+        message: json_rpc::Message,
+    ) -> ReverseTranslatorResult<()> {
+        let message = format!("{}\n", serde_json::to_string(&message)?);
+        debug!("Sending to SV1 pool: {}", message.trim_end());
+        writer.write_all(message.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+impl IsClient<'static> for Upstream {
+    fn handle_error_message(
+        &mut self,
+        message: json_rpc::Message,
+    ) -> Result<Option<json_rpc::Message>, V1Error<'static>> {
+        if let json_rpc::Message::ErrorResponse(response) = &message {
+            if self.submits.remove(&response.id) {
+                self.on_submit_result(response.id, false);
+                return Ok(None);
+            }
+            if self.authorize_requests.remove(&response.id).is_some() {
+                error!("SV1 pool refused mining.authorize: {:?}", response.error);
+                return Ok(None);
+            }
+        }
+        warn!("Error from SV1 pool: {:?}", message);
+        Ok(None)
+    }
+
+    fn id_is_authorize(&mut self, id: &u64) -> Option<String> {
+        self.authorize_requests.remove(id)
+    }
+
+    fn id_is_submit(&mut self, id: &u64) -> bool {
+        self.submits.contains(id)
+    }
+
+    fn handle_notify(
+        &mut self,
+        notify: server_to_client::Notify<'static>,
+    ) -> Result<(), V1Error<'static>> {
+        self.last_notify = Some(notify.clone());
+        match self.jobs.on_notify(notify) {
+            Ok((job, set_new_prev_hash)) => {
+                self.emit(UpstreamEvent::NewJob(job, set_new_prev_hash))
+            }
+            Err(e) => error!("Can not translate mining.notify: {}", e),
+        }
+        Ok(())
+    }
+
+    fn handle_configure(
+        &mut self,
+        _conf: &mut server_to_client::Configure,
+    ) -> Result<(), V1Error<'static>> {
+        Ok(())
+    }
+
+    fn handle_set_difficulty(
+        &mut self,
+        m: &mut server_to_client::SetDifficulty,
+    ) -> Result<(), V1Error<'static>> {
+        info!("SV1 pool set difficulty {}", m.value);
+        self.emit(UpstreamEvent::SetTarget(target_from_difficulty(m.value)));
+        Ok(())
+    }
+
+    fn handle_set_extranonce(
+        &mut self,
+        _m: &mut server_to_client::SetExtranonce,
+    ) -> Result<(), V1Error<'static>> {
+        // The extranonce1 is split among the downstream channels when they are opened, it can not
+        // change afterwards. `mining.extranonce.subscribe` is never sent, so the pool should not
+        // send it.
+        warn!("Ignoring mining.set_extranonce from SV1 pool");
+        Ok(())
+    }
+
+    fn handle_set_version_mask(
+        &mut self,
+        m: &mut server_to_client::SetVersionMask,
+    ) -> Result<(), V1Error<'static>> {
+        if self.version_rolling_mask.is_some() {
+            info!("SV1 pool set version rolling mask {:?}", m.version_mask());
+            self.version_rolling_mask = Some(m.version_mask());
+        }
+        Ok(())
+    }
+
+    fn handle_subscribe(
+        &mut self,
+        subscribe: &server_to_client::Subscribe<'static>,
+    ) -> Result<(), V1Error<'static>> {
+        info!(
+            "Subscribed to SV1 pool, extranonce2 size: {}",
+            subscribe.extra_nonce2_size
+        );
+        self.emit(UpstreamEvent::Subscribed {
+            extranonce1: subscribe.extra_nonce1.clone().into(),
+            extranonce2_size: subscribe.extra_nonce2_size,
+        });
+        Ok(())
+    }
+
+    fn handle_reconnect(
+        &mut self,
+        reconnect: server_to_client::Reconnect,
+    ) -> Result<(), V1Error<'static>> {
+        self.reconnect = Some(reconnect);
+        Ok(())
+    }
+
+    fn handle_show_message(
+        &mut self,
+        show_message: server_to_client::ShowMessage,
+    ) -> Result<(), V1Error<'static>> {
+        info!("Message from SV1 pool: {}", show_message.message);
+        Ok(())
+    }
+
+    fn set_extranonce1(&mut self, extranonce1: Extranonce<'static>) {
+        self.extranonce1 = Some(extranonce1);
+    }
+
+    fn extranonce1(&self) -> Extranonce<'static> {
+        self.extranonce1
+            .clone()
+            .unwrap_or_else(|| Vec::new().try_into().expect("empty extranonce is valid"))
+    }
+
+    fn set_extranonce2_size(&mut self, extra_nonce2_size: usize) {
+        self.extranonce2_size = extra_nonce2_size;
+    }
+
+    fn extranonce2_size(&self) -> usize {
+        self.extranonce2_size
+    }
+
+    fn version_rolling_mask(&self) -> Option<HexU32Be> {
+        self.version_rolling_mask.clone()
+    }
+
+    fn set_version_rolling_mask(&mut self, mask: Option<HexU32Be>) {
+        self.jobs.set_version_rolling_allowed(mask.is_some());
+        self.version_rolling_mask = mask;
+    }
+
+    fn set_version_rolling_min_bit(&mut self, min: Option<HexU32Be>) {
+        self.version_rolling_min_bit = min;
+    }
+
+    fn version_rolling_min_bit(&mut self) -> Option<HexU32Be> {
+        self.version_rolling_min_bit.clone()
+    }
+
+    fn set_status(&mut self, status: ClientStatus) {
+        self.status = status;
+    }
+
+    fn signature(&self) -> String {
+        format!("reverse_translator_sv2/{}", env!("CARGO_PKG_VERSION"))
+    }
+
+    fn status(&self) -> ClientStatus {
+        self.status
+    }
+
+    fn last_notify(&self) -> Option<server_to_client::Notify<'_>> {
+        self.last_notify.clone()
+    }
+
+    fn is_authorized(&self, name: &String) -> bool {
+        self.authorized.contains(name)
+    }
+
+    fn authorize_user_name(&mut self, name: String) {
+        info!("Authorized on SV1 pool as {}", name);
+        self.authorized.push(name)
+    }
+
+    fn authorize(
+        &mut self,
+        id: u64,
+        name: String,
+        password: String,
+    ) -> Result<json_rpc::Message, V1Error<'_>> {
+        match self.status() {
+            ClientStatus::Init => Err(V1Error::IncorrectClientStatus(
+                "mining.authorize".to_string(),
+            )),
+            _ => {
+                self.authorize_requests.insert(id, name.clone());
+                Ok(v1::client_to_server::Authorize { id, name, password }.into())
+            }
+        }
+    }
+}
+
+// Address of a `client.reconnect`, the host and the port that are not given are the current ones
+fn reconnect_address(current: &str, reconnect: &server_to_client::Reconnect) -> String {
+    let (current_host, current_port) = current.rsplit_once(':').unwrap_or((current, ""));
+    let host = reconnect.host.as_deref().unwrap_or(current_host);
+    match reconnect.port {
+        Some(port) => format!("{}:{}", host, port),
+        None => format!("{}:{}", host, current_port),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ext_config::{Config, File, FileFormat};
+
+    fn upstream() -> Upstream {
+        let config: ReverseTranslatorConfig = Config::builder()
+            .add_source(File::from_str(
+                include_str!("../../config-examples/reverse-translator-config-example.toml"),
+                FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        Upstream::new(&config, async_channel::unbounded().0)
+    }
+
+    fn reconnect(host: Option<&str>, port: Option<u16>) -> server_to_client::Reconnect {
+        server_to_client::Reconnect {
+            id: None,
+            host: host.map(str::to_string),
+            port,
+            wait_time: None,
+        }
+    }
+
+    #[test]
+    fn reconnect_address_defaults_to_the_current_one() {
+        let current = "127.0.0.1:3333";
+        assert_eq!(reconnect_address(current, &reconnect(None, None)), current);
+        assert_eq!(
+            reconnect_address(current, &reconnect(None, Some(3334))),
+            "127.0.0.1:3334"
+        );
+        assert_eq!(
+            reconnect_address(current, &reconnect(Some("pool.example.com"), None)),
+            "pool.example.com:3333"
+        );
+    }
+
+    #[test]
+    fn invalid_lines_are_skipped() {
+        let mut upstream = upstream();
+        assert!(upstream.on_line("not json").unwrap().is_empty());
+        assert!(upstream.reconnect.is_none());
+
+        let line = r#"{"id":7,"method":"client.reconnect","params":["pool.example.com",3334,5]}"#;
+        upstream.on_line(line).unwrap();
+        let reconnect = upstream.reconnect.take().unwrap();
+        assert_eq!(reconnect.id, Some(7));
+        assert_eq!(reconnect.wait_time, Some(5));
+    }
+}
@@ -0,0 +1,121 @@
+#![allow(special_module_name)]
+
+mod lib;
+use ext_config::{Config, File, FileFormat};
+pub use lib::{bridge, config, downstream, error, translation, upstream, ReverseTranslatorSv2};
+use tokio::select;
+use tracing::{error, info};
+
+mod args {
+    use std::path::PathBuf;
+
+    #[derive(Debug)]
+    pub struct Args {
+        pub config_path: PathBuf,
+    }
+
+    enum ArgsState {
+        Next,
+        ExpectPath,
+        Done,
+    }
+
+    enum ArgsResult {
+        Config(PathBuf),
+        None,
+        Help(String),
+    }
+
+    impl Args {
+        const DEFAULT_CONFIG_PATH: &'static str = "reverse-translator-config.toml";
+        const HELP_MSG: &'static str =
+            "Usage: -h/--help, -c/--config <path|default reverse-translator-config.toml>";
+
+        pub fn from_args() -> Result<Self, String> {
+            let cli_args = std::env::args();
+
+            if cli_args.len() == 1 {
+                println!("Using default config path: {}", Self::DEFAULT_CONFIG_PATH);
+                println!("{}\n", Self::HELP_MSG);
+            }
+
+            let config_path = cli_args
+                .scan(ArgsState::Next, |state, item| {
+                    match std::mem::replace(state, ArgsState::Done) {
+                        ArgsState::Next => match item.as_str() {
+                            "-c" | "--config" => {
+                                *state = ArgsState::ExpectPath;
+                                Some(ArgsResult::None)
+                            }
+                            "-h" | "--help" => Some(ArgsResult::Help(Self::HELP_MSG.to_string())),
+                            _ => {
+                                *state = ArgsState::Next;
+
+                                Some(ArgsResult::None)
+                            }
+                        },
+                        ArgsState::ExpectPath => Some(ArgsResult::Config(PathBuf::from(item))),
+                        ArgsState::Done => None,
+                    }
+                })
+                .last();
+            let config_path = match config_path {
+                Some(ArgsResult::Config(p)) => p,
+                Some(ArgsResult::Help(h)) => return Err(h),
+                _ => PathBuf::from(Self::DEFAULT_CONFIG_PATH),
+            };
+            Ok(Self { config_path })
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+
+    let args = match args::Args::from_args() {
+        Ok(cfg) => cfg,
+        Err(help) => {
+            error!("{}", help);
+            return;
+        }
+    };
+
+    let config_path = args.config_path.to_str().expect("Invalid config path");
+
+    // Load config
+    let config: config::ReverseTranslatorConfig = match Config::builder()
+        .add_source(File::new(config_path, FileFormat::Toml))
+        .build()
+    {
+        Ok(settings) => match settings.try_deserialize::<config::ReverseTranslatorConfig>() {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Failed to deserialize config: {}", e);
+                return;
+            }
+        },
+        Err(e) => {
+            error!("Failed to build config: {}", e);
+            return;
+        }
+    };
+    let reverse_translator = ReverseTranslatorSv2::new(config);
+    select! {
+        result = reverse_translator.start() => {
+            if let Err(e) = result {
+                error!("ReverseTranslator(bin): Stopped: {}", e);
+            }
+        }
+        interrupt_signal = tokio::signal::ctrl_c() => {
+            match interrupt_signal {
+                Ok(()) => {
+                    info!("ReverseTranslator(bin): Caught interrupt signal. Shutting down...");
+                },
+                Err(err) => {
+                    error!("ReverseTranslator(bin): Unable to listen for interrupt signal: {}", err);
+                },
+            }
+        }
+    };
+}
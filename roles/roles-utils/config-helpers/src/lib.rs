@@ -2,4 +2,4 @@ mod coinbase_output;
 pub use coinbase_output::CoinbaseOutput;
 
 mod toml;
-pub use toml::duration_from_toml;
+pub use toml::{duration_from_toml, one_or_many};
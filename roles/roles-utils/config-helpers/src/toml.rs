@@ -31,3 +31,24 @@ where
         _ => Err(serde::de::Error::custom("Unsupported duration unit")),
     }
 }
+
+/// Deserialize a list from a TOML array, or from a single value for a list of one element.
+pub fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    use serde::Deserialize;
+
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Helper<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    match Helper::deserialize(deserializer)? {
+        Helper::One(value) => Ok(vec![value]),
+        Helper::Many(values) => Ok(values),
+    }
+}
@@ -0,0 +1,166 @@
+//! Compares the throughput of the channel based connections with the `tokio_util` codecs, sending
+//! frames over a localhost TCP socket.
+//!
+//! Run with `cargo bench --features tokio_codec`.
+
+use binary_sv2::{binary_codec_sv2, Deserialize, Serialize, B064K};
+use codec_sv2::{RekeyPolicy, StandardEitherFrame, StandardSv2Frame};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::{SinkExt, StreamExt};
+use network_helpers_sv2::{
+    codec::{noise_framed, Sv2Codec},
+    noise_connection::Connection,
+    plain_connection::PlainConnection,
+};
+use std::convert::TryInto;
+use test_utils::{roles, tcp_pair};
+use tokio::runtime::Runtime;
+use tokio_util::codec::Framed;
+
+#[allow(dead_code)]
+#[path = "../src/test_utils.rs"]
+mod test_utils;
+
+const FRAMES: usize = 1_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Ping<'decoder> {
+    nonce: u32,
+    padding: B064K<'decoder>,
+}
+
+fn frame(size: usize) -> StandardSv2Frame<Ping<'static>> {
+    let message = Ping {
+        nonce: 0,
+        padding: vec![0; size].try_into().unwrap(),
+    };
+    StandardSv2Frame::from_message(message, 0xf0, 0, false).unwrap()
+}
+
+fn bench_connections(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("connection");
+    group.throughput(Throughput::Elements(FRAMES as u64));
+
+    for size in [100, 10_000] {
+        let (client, server) = runtime.block_on(async {
+            let (client, server) = tcp_pair().await;
+            let (initiator, responder) = roles();
+            let (client, server) = tokio::join!(
+                Connection::new::<Ping>(client, initiator),
+                Connection::new::<Ping>(server, responder)
+            );
+            (client.unwrap(), server.unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("noise_channels", size),
+            &size,
+            |b, size| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let sending = async {
+                            for _ in 0..FRAMES {
+                                let frame: StandardEitherFrame<Ping<'static>> = frame(*size).into();
+                                client.1.send(frame).await.unwrap();
+                            }
+                        };
+                        let receiving = async {
+                            for _ in 0..FRAMES {
+                                server.0.recv().await.unwrap();
+                            }
+                        };
+                        tokio::join!(sending, receiving);
+                    })
+                })
+            },
+        );
+
+        let (mut client, mut server) = runtime.block_on(async {
+            let (client, server) = tcp_pair().await;
+            let (initiator, responder) = roles();
+            let (client, server) = tokio::join!(
+                noise_framed::<_, Ping>(client, initiator, RekeyPolicy::default()),
+                noise_framed::<_, Ping>(server, responder, RekeyPolicy::default())
+            );
+            (client.unwrap(), server.unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("noise_codec", size), &size, |b, size| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let sending = async {
+                        for _ in 0..FRAMES {
+                            client.feed(frame(*size)).await.unwrap();
+                        }
+                        client.flush().await.unwrap();
+                    };
+                    let receiving = async {
+                        for _ in 0..FRAMES {
+                            server.next().await.unwrap().unwrap();
+                        }
+                    };
+                    tokio::join!(sending, receiving);
+                })
+            })
+        });
+
+        let (client, server) = runtime.block_on(async {
+            let (client, server) = tcp_pair().await;
+            (
+                PlainConnection::new::<Ping>(client).await,
+                PlainConnection::new::<Ping>(server).await,
+            )
+        });
+        group.bench_with_input(
+            BenchmarkId::new("plain_channels", size),
+            &size,
+            |b, size| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let sending = async {
+                            for _ in 0..FRAMES {
+                                let frame: StandardEitherFrame<Ping<'static>> = frame(*size).into();
+                                client.1.send(frame).await.unwrap();
+                            }
+                        };
+                        let receiving = async {
+                            for _ in 0..FRAMES {
+                                server.0.recv().await.unwrap();
+                            }
+                        };
+                        tokio::join!(sending, receiving);
+                    })
+                })
+            },
+        );
+
+        let (mut client, mut server) = runtime.block_on(async {
+            let (client, server) = tcp_pair().await;
+            (
+                Framed::new(client, Sv2Codec::<Ping>::new()),
+                Framed::new(server, Sv2Codec::<Ping>::new()),
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("plain_codec", size), &size, |b, size| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let sending = async {
+                        for _ in 0..FRAMES {
+                            client.feed(frame(*size)).await.unwrap();
+                        }
+                        client.flush().await.unwrap();
+                    };
+                    let receiving = async {
+                        for _ in 0..FRAMES {
+                            server.next().await.unwrap().unwrap();
+                        }
+                    };
+                    tokio::join!(sending, receiving);
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_connections);
+criterion_main!(benches);
@@ -0,0 +1,428 @@
+//! Admission control for public listeners.
+//!
+//! An [`AdmissionControl`] is shared by all the connections accepted on a listener. Every new
+//! connection asks for a [`Permit`] with [`AdmissionControl::admit`] before doing any work, like
+//! the Noise handshake, and keeps it until it is closed. Connections are refused when:
+//! - the peer IP is banned,
+//! - the listener or the peer IP already has too many open connections,
+//! - too many handshakes were started recently.
+//!
+//! Protocol errors reported through the [`Permit`] (failed handshakes, oversized frames, message
+//! floods) count against the peer IP, which is banned for a while once it reports too many.
+//!
+//! The per-IP limits, the error counts and the bans apply to the whole /64 of IPv6 peers, as a
+//! single host usually gets at least a /64.
+//!
+//! The default [`AdmissionConfig`] has no limit, so that roles keep accepting everything unless
+//! configured otherwise.
+
+use crate::ConnectionConfig;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, Ipv6Addr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Limits applied by an [`AdmissionControl`]. `None` disables the corresponding limit.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct AdmissionConfig {
+    /// Maximum number of open connections.
+    pub max_connections: Option<usize>,
+    /// Maximum number of open connections from a single IP, or IPv6 /64.
+    pub max_connections_per_ip: Option<usize>,
+    /// Maximum number of new connections (and so of Noise handshakes) per second, with bursts
+    /// of the same size.
+    pub max_handshakes_per_second: Option<u32>,
+    /// Maximum number of messages per second on a single connection, with bursts of the same
+    /// size. Connections going over it are closed.
+    pub max_messages_per_second: Option<u32>,
+    /// Number of protocol errors after which an IP is banned. Errors older than
+    /// `ban_duration_secs` are forgotten.
+    pub max_protocol_errors: Option<u32>,
+    /// How long an IP stays banned, in seconds.
+    pub ban_duration_secs: u64,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            max_connections_per_ip: None,
+            max_handshakes_per_second: None,
+            max_messages_per_second: None,
+            max_protocol_errors: None,
+            ban_duration_secs: 600,
+        }
+    }
+}
+
+/// Why [`AdmissionControl::admit`] refused a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    Banned,
+    TooManyConnections,
+    TooManyConnectionsFromIp,
+    HandshakeRateLimited,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Banned => write!(f, "IP is banned"),
+            Rejection::TooManyConnections => write!(f, "too many open connections"),
+            Rejection::TooManyConnectionsFromIp => {
+                write!(f, "too many open connections from the same IP")
+            }
+            Rejection::HandshakeRateLimited => write!(f, "too many new connections"),
+        }
+    }
+}
+
+/// Token bucket allowing `rate` events per second, with bursts of `rate` events.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// Takes a token, returns `false` if there are none left.
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// How often the expired errors and bans are forgotten
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+// The key the per-IP state is stored under: the address itself for IPv4, its /64 for IPv6.
+fn ip_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => {
+                let prefix = u128::from(v6) & !((1_u128 << 64) - 1);
+                IpAddr::V6(Ipv6Addr::from(prefix))
+            }
+        },
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    connections: usize,
+    connections_per_ip: HashMap<IpAddr, usize>,
+    handshakes: Option<RateLimiter>,
+    // Number of errors of an IP and time of the first one
+    errors: HashMap<IpAddr, (u32, Instant)>,
+    bans: HashMap<IpAddr, Instant>,
+    last_prune: Option<Instant>,
+}
+
+impl State {
+    // Forgets the errors older than `ban_duration` and the expired bans, at most once per
+    // `PRUNE_INTERVAL`, so that a listener seeing many IPs does not keep them all forever.
+    fn prune(&mut self, now: Instant, ban_duration: Duration) {
+        if matches!(self.last_prune, Some(last) if now.saturating_duration_since(last) < PRUNE_INTERVAL)
+        {
+            return;
+        }
+        self.last_prune = Some(now);
+        self.errors
+            .retain(|_, (_, first)| now.saturating_duration_since(*first) <= ban_duration);
+        self.bans.retain(|_, until| *until > now);
+    }
+}
+
+/// Connection and rate limits shared by the connections of a listener.
+#[derive(Debug)]
+pub struct AdmissionControl {
+    config: AdmissionConfig,
+    state: Mutex<State>,
+}
+
+impl AdmissionControl {
+    pub fn new(config: AdmissionConfig) -> Arc<Self> {
+        let state = State {
+            handshakes: config.max_handshakes_per_second.map(RateLimiter::new),
+            ..Default::default()
+        };
+        Arc::new(Self {
+            config,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Admits a new connection from `ip`, or tells why it must be refused.
+    pub fn admit(self: &Arc<Self>, ip: IpAddr) -> Result<Permit, Rejection> {
+        let now = Instant::now();
+        let key = ip_key(ip);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.prune(now, Duration::from_secs(self.config.ban_duration_secs));
+        match state.bans.get(&key) {
+            Some(until) if *until > now => return Err(Rejection::Banned),
+            Some(_) => {
+                state.bans.remove(&key);
+            }
+            None => (),
+        }
+        if matches!(self.config.max_connections, Some(max) if state.connections >= max) {
+            return Err(Rejection::TooManyConnections);
+        }
+        let from_ip = state.connections_per_ip.get(&key).copied().unwrap_or(0);
+        if matches!(self.config.max_connections_per_ip, Some(max) if from_ip >= max) {
+            return Err(Rejection::TooManyConnectionsFromIp);
+        }
+        if let Some(handshakes) = state.handshakes.as_mut() {
+            if !handshakes.try_acquire_at(now) {
+                return Err(Rejection::HandshakeRateLimited);
+            }
+        }
+        state.connections += 1;
+        *state.connections_per_ip.entry(key).or_insert(0) += 1;
+        Ok(Permit {
+            control: self.clone(),
+            ip,
+        })
+    }
+
+    /// Counts a protocol error against `ip`, and bans it if it made too many.
+    pub fn report_protocol_error(&self, ip: IpAddr) {
+        let max_errors = match self.config.max_protocol_errors {
+            Some(max_errors) => max_errors,
+            None => return,
+        };
+        let now = Instant::now();
+        let ban_duration = Duration::from_secs(self.config.ban_duration_secs);
+        let key = ip_key(ip);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.prune(now, ban_duration);
+        let errors = state.errors.entry(key).or_insert((0, now));
+        if now.saturating_duration_since(errors.1) > ban_duration {
+            *errors = (0, now);
+        }
+        errors.0 += 1;
+        if errors.0 >= max_errors {
+            warn!(
+                "Banning {} for {}s after {} protocol errors",
+                ip,
+                ban_duration.as_secs(),
+                errors.0
+            );
+            state.errors.remove(&key);
+            state.bans.insert(key, now + ban_duration);
+        }
+    }
+
+    /// Returns `true` if new connections from `ip` are refused.
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        matches!(state.bans.get(&ip_key(ip)), Some(until) if *until > Instant::now())
+    }
+
+    /// Number of open connections.
+    pub fn connections(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .connections
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.connections -= 1;
+        let key = ip_key(ip);
+        if let Some(from_ip) = state.connections_per_ip.get_mut(&key) {
+            *from_ip -= 1;
+            if *from_ip == 0 {
+                state.connections_per_ip.remove(&key);
+            }
+        }
+    }
+}
+
+/// An admitted connection. The connection slot is released when the permit is dropped.
+#[derive(Debug)]
+pub struct Permit {
+    control: Arc<AdmissionControl>,
+    ip: IpAddr,
+}
+
+impl Permit {
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// Counts a protocol error against the IP of the connection.
+    pub fn report_protocol_error(&self) {
+        self.control.report_protocol_error(self.ip)
+    }
+
+    /// Returns the message rate limiter of the connection, if any.
+    pub fn message_rate_limiter(&self) -> Option<RateLimiter> {
+        self.control
+            .config
+            .max_messages_per_second
+            .map(RateLimiter::new)
+    }
+
+    /// Returns `config` with the message rate limit of the admission control. The permit is
+    /// moved into the config, so that it is released when the connection built with it is
+    /// closed and that the connection reports its protocol errors.
+    pub fn into_connection_config(self, config: ConnectionConfig) -> ConnectionConfig {
+        ConnectionConfig {
+            max_messages_per_second: self.control.config.max_messages_per_second,
+            permit: Some(Arc::new(self)),
+            ..config
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.control.release(self.ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+    const OTHER_IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 2));
+
+    #[test]
+    fn connection_caps() {
+        let control = AdmissionControl::new(AdmissionConfig {
+            max_connections: Some(3),
+            max_connections_per_ip: Some(2),
+            ..Default::default()
+        });
+        let first = control.admit(IP).unwrap();
+        let _second = control.admit(IP).unwrap();
+        assert_eq!(
+            control.admit(IP).unwrap_err(),
+            Rejection::TooManyConnectionsFromIp
+        );
+        let _third = control.admit(OTHER_IP).unwrap();
+        assert_eq!(
+            control.admit(OTHER_IP).unwrap_err(),
+            Rejection::TooManyConnections
+        );
+        drop(first);
+        assert_eq!(control.connections(), 2);
+        assert!(control.admit(IP).is_ok());
+    }
+
+    #[test]
+    fn handshake_rate_limit() {
+        let control = AdmissionControl::new(AdmissionConfig {
+            max_handshakes_per_second: Some(2),
+            ..Default::default()
+        });
+        assert!(control.admit(IP).is_ok());
+        assert!(control.admit(OTHER_IP).is_ok());
+        assert_eq!(
+            control.admit(IP).unwrap_err(),
+            Rejection::HandshakeRateLimited
+        );
+    }
+
+    #[test]
+    fn rate_limiter_refills() {
+        let mut limiter = RateLimiter::new(10);
+        let start = limiter.last;
+        for _ in 0..10 {
+            assert!(limiter.try_acquire_at(start));
+        }
+        assert!(!limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start + Duration::from_millis(100)));
+        assert!(!limiter.try_acquire_at(start + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn repeated_errors_ban_the_ip() {
+        let control = AdmissionControl::new(AdmissionConfig {
+            max_protocol_errors: Some(2),
+            ..Default::default()
+        });
+        let permit = control.admit(IP).unwrap();
+        permit.report_protocol_error();
+        assert!(!control.is_banned(IP));
+        permit.report_protocol_error();
+        assert!(control.is_banned(IP));
+        assert_eq!(control.admit(IP).unwrap_err(), Rejection::Banned);
+        assert!(control.admit(OTHER_IP).is_ok());
+    }
+
+    #[test]
+    fn ipv6_limits_apply_to_the_64() {
+        let control = AdmissionControl::new(AdmissionConfig {
+            max_connections_per_ip: Some(1),
+            max_protocol_errors: Some(1),
+            ..Default::default()
+        });
+        let ip: IpAddr = "2001:db8:0:1::1".parse().unwrap();
+        let same_64: IpAddr = "2001:db8:0:1:ffff::2".parse().unwrap();
+        let other_64: IpAddr = "2001:db8:0:2::1".parse().unwrap();
+        let permit = control.admit(ip).unwrap();
+        assert_eq!(
+            control.admit(same_64).unwrap_err(),
+            Rejection::TooManyConnectionsFromIp
+        );
+        let _other = control.admit(other_64).unwrap();
+        permit.report_protocol_error();
+        drop(permit);
+        assert_eq!(control.admit(same_64).unwrap_err(), Rejection::Banned);
+        assert!(!control.is_banned(other_64));
+        let mapped: IpAddr = "::ffff:10.0.0.1".parse().unwrap();
+        assert_eq!(ip_key(mapped), IP);
+    }
+
+    #[test]
+    fn expired_errors_and_bans_are_pruned() {
+        let control = AdmissionControl::new(AdmissionConfig {
+            max_protocol_errors: Some(2),
+            ban_duration_secs: 10,
+            ..Default::default()
+        });
+        control.report_protocol_error(IP);
+        control.report_protocol_error(OTHER_IP);
+        control.report_protocol_error(OTHER_IP);
+        let mut state = control.state.lock().unwrap();
+        assert_eq!((state.errors.len(), state.bans.len()), (1, 1));
+        let now = Instant::now();
+        // Pruned at most once per interval
+        state.prune(now, Duration::from_secs(10));
+        assert_eq!((state.errors.len(), state.bans.len()), (1, 1));
+        state.prune(now + PRUNE_INTERVAL, Duration::from_secs(10));
+        assert_eq!((state.errors.len(), state.bans.len()), (0, 0));
+    }
+}
@@ -0,0 +1,328 @@
+use crate::Error;
+use codec_sv2::{noise_sv2::Error as NoiseError, Responder};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{error, info};
+
+/// Provides the [`Responder`] used to perform the Noise handshake of each incoming connection.
+///
+/// Implementors decide which authority key signs the certificate of a new connection, so that the
+/// authority key can be rotated without restarting the role or dropping open connections.
+pub trait CertificateProvider: Send + Sync {
+    /// Builds the [`Responder`] for a new incoming connection.
+    fn responder(&self) -> Result<Box<Responder>, NoiseError>;
+}
+
+/// An authority key pair, used to sign certificates from `valid_from` (unix timestamp) onward.
+#[derive(Debug, Clone)]
+pub struct AuthorityKey {
+    pub public_key: Secp256k1PublicKey,
+    pub secret_key: Secp256k1SecretKey,
+    pub valid_from: u64,
+}
+
+impl AuthorityKey {
+    /// Creates an [`AuthorityKey`] that can be used right away.
+    pub fn new(public_key: Secp256k1PublicKey, secret_key: Secp256k1SecretKey) -> Self {
+        Self {
+            public_key,
+            secret_key,
+            valid_from: 0,
+        }
+    }
+
+    // Parses a line of an authority keys file: `<public key> <secret key> [valid from]`.
+    fn from_line(line: &str) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidAuthorityKeys(format!("{}: `{}`", reason, line));
+        let mut fields = line.split_whitespace();
+        let public_key = fields
+            .next()
+            .ok_or_else(|| invalid("missing public key"))?
+            .parse()
+            .map_err(|_| invalid("invalid public key"))?;
+        let secret_key = fields
+            .next()
+            .ok_or_else(|| invalid("missing secret key"))?
+            .parse()
+            .map_err(|_| invalid("invalid secret key"))?;
+        let valid_from = match fields.next() {
+            Some(valid_from) => valid_from
+                .parse()
+                .map_err(|_| invalid("invalid timestamp"))?,
+            None => 0,
+        };
+        if fields.next().is_some() {
+            return Err(invalid("unexpected field"));
+        }
+        Ok(Self {
+            public_key,
+            secret_key,
+            valid_from,
+        })
+    }
+}
+
+/// A set of authority keys, of which the most recent valid one signs the certificates.
+///
+/// During a rotation window the set contains both the current key and the next one, with a
+/// `valid_from` in the future: certificates are signed with the current key until the next one
+/// becomes valid. Initiators should pin both keys for the duration of the window.
+#[derive(Debug, Clone)]
+pub struct AuthorityKeys {
+    keys: Vec<AuthorityKey>,
+    cert_validity: Duration,
+}
+
+impl AuthorityKeys {
+    /// Creates a new [`AuthorityKeys`]. Errors if `keys` contains a mismatched key pair or if
+    /// none of the keys is valid yet.
+    pub fn new(keys: Vec<AuthorityKey>, cert_validity: Duration) -> Result<Self, Error> {
+        for key in &keys {
+            let public_key: Secp256k1PublicKey = key.secret_key.into();
+            if public_key.into_bytes() != key.public_key.into_bytes() {
+                return Err(Error::InvalidAuthorityKeys(format!(
+                    "secret key does not match public key `{}`",
+                    key.public_key
+                )));
+            }
+        }
+        let self_ = Self {
+            keys,
+            cert_validity,
+        };
+        if self_.signing_key(now()).is_none() {
+            return Err(Error::InvalidAuthorityKeys(
+                "no authority key is valid yet".to_string(),
+            ));
+        }
+        Ok(self_)
+    }
+
+    /// Parses the authority keys file at `path`.
+    ///
+    /// The file contains one key pair per line, as `<public key> <secret key> [valid from]` where
+    /// `valid from` is an optional unix timestamp. Empty lines and lines starting with `#` are
+    /// ignored.
+    pub fn from_file(path: &Path, cert_validity: Duration) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            Error::InvalidAuthorityKeys(format!("can not read `{}`: {}", path.display(), e))
+        })?;
+        let keys = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(AuthorityKey::from_line)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(keys, cert_validity)
+    }
+
+    /// Returns the keys in the set.
+    pub fn keys(&self) -> &[AuthorityKey] {
+        &self.keys
+    }
+
+    // Returns the valid key with the most recent `valid_from`, the last one in case of ties.
+    fn signing_key(&self, now: u64) -> Option<&AuthorityKey> {
+        self.keys
+            .iter()
+            .filter(|key| key.valid_from <= now)
+            .max_by_key(|key| key.valid_from)
+    }
+}
+
+impl CertificateProvider for AuthorityKeys {
+    fn responder(&self) -> Result<Box<Responder>, NoiseError> {
+        // `new` ensures that a key is valid, and keys never stop being valid
+        let key = self
+            .signing_key(now())
+            .ok_or(NoiseError::InvalidRawPrivateKey)?;
+        Responder::from_authority_kp(
+            &key.public_key.into_bytes(),
+            &key.secret_key.into_bytes(),
+            self.cert_validity,
+        )
+    }
+}
+
+/// A [`CertificateProvider`] that reads the authority keys from a file and can reload it while
+/// the role is running.
+///
+/// See [`AuthorityKeys::from_file`] for the file format.
+#[derive(Debug)]
+pub struct FileCertificateProvider {
+    path: PathBuf,
+    keys: RwLock<AuthorityKeys>,
+}
+
+impl FileCertificateProvider {
+    /// Creates a new [`FileCertificateProvider`], errors if the file is not valid.
+    pub fn new(path: PathBuf, cert_validity: Duration) -> Result<Self, Error> {
+        let keys = AuthorityKeys::from_file(&path, cert_validity)?;
+        Ok(Self {
+            path,
+            keys: RwLock::new(keys),
+        })
+    }
+
+    /// Returns the keys currently in use.
+    pub fn keys(&self) -> AuthorityKeys {
+        self.keys.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Reloads the keys from the file. If the file is not valid the current keys are kept.
+    pub fn reload(&self) -> Result<(), Error> {
+        let cert_validity = self.keys().cert_validity;
+        let keys = AuthorityKeys::from_file(&self.path, cert_validity)?;
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = keys;
+        Ok(())
+    }
+
+    /// Spawns a task that reloads the keys every time the process receives `SIGHUP`.
+    #[cfg(unix)]
+    pub fn reload_on_sighup(self: Arc<Self>) -> Result<(), Error> {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangup = signal(SignalKind::hangup()).map_err(|e| {
+            Error::InvalidAuthorityKeys(format!("can not listen for SIGHUP: {}", e))
+        })?;
+        tokio::task::spawn(async move {
+            while hangup.recv().await.is_some() {
+                match self.reload() {
+                    Ok(()) => info!("Reloaded authority keys from {}", self.path.display()),
+                    Err(e) => error!(
+                        "Failed to reload authority keys, keeping the old ones: {:?}",
+                        e
+                    ),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+impl CertificateProvider for FileCertificateProvider {
+    fn responder(&self) -> Result<Box<Responder>, NoiseError> {
+        self.keys().responder()
+    }
+}
+
+/// Builds the [`CertificateProvider`] of a role: the key pair from the role config, or the keys
+/// of `authority_keys_file` if set, reloaded on `SIGHUP`.
+pub fn certificate_provider(
+    authority_public_key: Secp256k1PublicKey,
+    authority_secret_key: Secp256k1SecretKey,
+    cert_validity: Duration,
+    authority_keys_file: Option<&Path>,
+) -> Result<Arc<dyn CertificateProvider>, Error> {
+    match authority_keys_file {
+        Some(path) => {
+            let provider = Arc::new(FileCertificateProvider::new(
+                path.to_path_buf(),
+                cert_validity,
+            )?);
+            #[cfg(unix)]
+            provider.clone().reload_on_sighup()?;
+            Ok(provider)
+        }
+        None => Ok(Arc::new(AuthorityKeys::new(
+            vec![AuthorityKey::new(
+                authority_public_key,
+                authority_secret_key,
+            )],
+            cert_validity,
+        )?)),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{PUBLIC_KEY, SECRET_KEY};
+    use std::io::Write;
+
+    const NEXT_SECRET_KEY: &str = "8WwpJCixn9cKe3jAyXvxNeo5JrBFKj43ULkUeTfeLMqLiZPjj";
+
+    fn key_pair(secret_key: &str) -> (Secp256k1PublicKey, Secp256k1SecretKey) {
+        let secret_key: Secp256k1SecretKey = secret_key.parse().unwrap();
+        (secret_key.into(), secret_key)
+    }
+
+    #[test]
+    fn most_recent_valid_key_signs() {
+        let (public_key, secret_key) = key_pair(SECRET_KEY);
+        let (next_public_key, next_secret_key) = key_pair(NEXT_SECRET_KEY);
+        let keys = AuthorityKeys::new(
+            vec![
+                AuthorityKey::new(public_key, secret_key),
+                AuthorityKey {
+                    public_key: next_public_key,
+                    secret_key: next_secret_key,
+                    valid_from: 1_000,
+                },
+            ],
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+        let signing_key = |now| keys.signing_key(now).unwrap().public_key.into_bytes();
+        assert_eq!(signing_key(999), public_key.into_bytes());
+        assert_eq!(signing_key(1_000), next_public_key.into_bytes());
+        assert!(keys.responder().is_ok());
+    }
+
+    #[test]
+    fn mismatched_and_future_keys_are_rejected() {
+        let (public_key, _) = key_pair(SECRET_KEY);
+        let (_, secret_key) = key_pair(NEXT_SECRET_KEY);
+        let keys = vec![AuthorityKey::new(public_key, secret_key)];
+        assert!(AuthorityKeys::new(keys, Duration::from_secs(3600)).is_err());
+
+        let (public_key, secret_key) = key_pair(SECRET_KEY);
+        let keys = vec![AuthorityKey {
+            public_key,
+            secret_key,
+            valid_from: u64::MAX,
+        }];
+        assert!(AuthorityKeys::new(keys, Duration::from_secs(3600)).is_err());
+    }
+
+    #[test]
+    fn file_provider_reloads_keys() {
+        let path = std::env::temp_dir().join(format!("authority-keys-{}", std::process::id()));
+        let write = |content: &str| {
+            let mut file = std::fs::File::create(&path).unwrap();
+            file.write_all(content.as_bytes()).unwrap();
+        };
+        write(&format!("# current key\n{} {}\n", PUBLIC_KEY, SECRET_KEY));
+        let provider =
+            FileCertificateProvider::new(path.clone(), Duration::from_secs(3600)).unwrap();
+        assert!(provider.responder().is_ok());
+
+        let (next_public_key, next_secret_key) = key_pair(NEXT_SECRET_KEY);
+        write(&format!(
+            "{} {}\n{} {} 1000\n",
+            PUBLIC_KEY, SECRET_KEY, next_public_key, next_secret_key
+        ));
+        provider.reload().unwrap();
+        let keys = provider.keys();
+        assert_eq!(keys.keys().len(), 2);
+        assert_eq!(
+            keys.signing_key(now()).unwrap().public_key.into_bytes(),
+            next_public_key.into_bytes()
+        );
+
+        write("not a key\n");
+        assert!(provider.reload().is_err());
+        assert_eq!(provider.keys().keys().len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
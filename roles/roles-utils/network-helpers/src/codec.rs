@@ -0,0 +1,360 @@
+//! [`tokio_util::codec`] adapters for Sv2 frames.
+//!
+//! [`Sv2Codec`] and [`NoiseSv2Codec`] implement [`Decoder`] and [`Encoder`] on top of
+//! [`StandardDecoder`]/[`codec_sv2::Encoder`] and [`StandardNoiseDecoder`]/[`NoiseEncoder`], so a
+//! socket can be turned into a [`Stream`](futures::Stream) and [`Sink`](futures::Sink) of
+//! [`StandardSv2Frame`] with [`Framed`].
+//!
+//! Unlike [`crate::noise_connection::Connection`] and [`crate::plain_connection::PlainConnection`]
+//! no task and no channel is involved: frames are decoded from the read buffer of [`Framed`] and
+//! encoded straight into its write buffer, and the caller drives the connection. Incoming bytes are
+//! copied once into the decoder buffer, that then hands the frame out without further copies.
+//!
+//! ```ignore
+//! let stream = TcpStream::connect(address).await?;
+//! let mut framed = noise_framed::<_, Message>(stream, role, RekeyPolicy::default()).await?;
+//! framed.send(frame).await?;
+//! let incoming = framed.next().await;
+//! ```
+
+use crate::{config::check_frame_size, Error};
+use binary_sv2::{Deserialize, GetSize, Serialize};
+use codec_sv2::{
+    HandshakeRole, NoiseEncoder, RekeyPolicy, StandardDecoder, StandardNoiseDecoder,
+    StandardSv2Frame, State,
+};
+use const_sv2::{
+    ENCRYPTED_SV2_FRAME_HEADER_SIZE, INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
+    RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE, SV2_FRAME_HEADER_SIZE,
+};
+use std::convert::TryInto;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::{
+    bytes::{Buf, BytesMut},
+    codec::{Decoder, Encoder, Framed},
+};
+
+/// Codec for plain (unencrypted) Sv2 frames.
+pub struct Sv2Codec<Message: Serialize + GetSize> {
+    decoder: StandardDecoder<Message>,
+    encoder: codec_sv2::Encoder<Message>,
+    // Bytes the decoder needs before `next_frame` can make progress. `writable` grows the
+    // decoder buffer by this amount, so it must be called only once they are available.
+    missing: usize,
+    max_frame_size: Option<usize>,
+}
+
+impl<Message: Serialize + GetSize> Sv2Codec<Message> {
+    pub fn new() -> Self {
+        Self {
+            decoder: StandardDecoder::new(),
+            encoder: codec_sv2::Encoder::new(),
+            missing: SV2_FRAME_HEADER_SIZE,
+            max_frame_size: None,
+        }
+    }
+
+    /// Rejects the frames with a payload larger than `max_frame_size` with
+    /// [`Error::FrameTooLarge`], before buffering them.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = Some(max_frame_size);
+        self
+    }
+}
+
+impl<Message: Serialize + GetSize> Default for Sv2Codec<Message> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Message: Serialize + GetSize> Decoder for Sv2Codec<Message> {
+    type Item = StandardSv2Frame<Message>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Error> {
+        loop {
+            if src.len() < self.missing {
+                src.reserve(self.missing - src.len());
+                return Ok(None);
+            }
+            self.decoder
+                .writable()
+                .copy_from_slice(&src[..self.missing]);
+            src.advance(self.missing);
+            match self.decoder.next_frame() {
+                Ok(frame) => {
+                    self.missing = SV2_FRAME_HEADER_SIZE;
+                    return Ok(Some(frame));
+                }
+                Err(codec_sv2::Error::MissingBytes(missing)) => {
+                    check_frame_size(missing, self.max_frame_size)?;
+                    self.missing = missing;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl<Message: Serialize + GetSize> Encoder<StandardSv2Frame<Message>> for Sv2Codec<Message> {
+    type Error = Error;
+
+    fn encode(&mut self, item: StandardSv2Frame<Message>, dst: &mut BytesMut) -> Result<(), Error> {
+        dst.extend_from_slice(self.encoder.encode(item)?);
+        Ok(())
+    }
+}
+
+/// Codec for Sv2 frames encrypted with Noise.
+///
+/// The codec only handles the transport phase: build it with [`NoiseSv2Codec::new`] from the state
+/// returned by [`handshake`], or use [`noise_framed`] to do both.
+pub struct NoiseSv2Codec<Message: Serialize + GetSize> {
+    state: State,
+    decoder: StandardNoiseDecoder<Message>,
+    encoder: NoiseEncoder<Message>,
+    // See `Sv2Codec::missing`. The decoder asks for the first header only after a first call to
+    // `next_frame`, so it starts at 0.
+    missing: usize,
+    max_frame_size: Option<usize>,
+}
+
+impl<Message: Serialize + GetSize> NoiseSv2Codec<Message> {
+    /// Creates a codec from a [`State`] in transport mode.
+    pub fn new(state: State) -> Result<Self, Error> {
+        if !matches!(state, State::Transport(_)) {
+            return Err(Error::CodecError(codec_sv2::Error::NotInTransportState));
+        }
+        Ok(Self {
+            state,
+            decoder: StandardNoiseDecoder::new(),
+            encoder: NoiseEncoder::new(),
+            missing: 0,
+            max_frame_size: None,
+        })
+    }
+
+    /// Rejects the frames whose encrypted body (payload and MACs) is larger than
+    /// `max_frame_size` with [`Error::FrameTooLarge`], before buffering them.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = Some(max_frame_size);
+        self
+    }
+}
+
+// The decoder needs `Deserialize` to tell handshake frames apart, the messages carried by the
+// connections are `'static`.
+impl<Message: Serialize + Deserialize<'static> + GetSize> Decoder for NoiseSv2Codec<Message> {
+    type Item = StandardSv2Frame<Message>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Error> {
+        loop {
+            if src.len() < self.missing {
+                src.reserve(self.missing - src.len());
+                return Ok(None);
+            }
+            self.decoder
+                .writable()
+                .copy_from_slice(&src[..self.missing]);
+            src.advance(self.missing);
+            match self.decoder.next_frame(&mut self.state) {
+                Ok(frame) => {
+                    self.missing = ENCRYPTED_SV2_FRAME_HEADER_SIZE;
+                    return frame
+                        .try_into()
+                        .map(Some)
+                        .map_err(|_| Error::HandshakeRemoteInvalidMessage);
+                }
+                Err(codec_sv2::Error::MissingBytes(missing)) => {
+                    check_frame_size(missing, self.max_frame_size)?;
+                    self.missing = missing;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl<Message: Serialize + GetSize> Encoder<StandardSv2Frame<Message>> for NoiseSv2Codec<Message> {
+    type Error = Error;
+
+    fn encode(&mut self, item: StandardSv2Frame<Message>, dst: &mut BytesMut) -> Result<(), Error> {
+        let encrypted = self.encoder.encode(item.into(), &mut self.state)?;
+        dst.extend_from_slice(encrypted.as_ref());
+        Ok(())
+    }
+}
+
+/// Does the Noise handshake over `stream` and returns the [`State`] in transport mode.
+pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    role: HandshakeRole,
+    rekey_policy: RekeyPolicy,
+) -> Result<State, Error> {
+    let is_initiator = matches!(role, HandshakeRole::Initiator(_));
+    let mut state = State::initialized(role);
+    let mut transport_mode = if is_initiator {
+        let first_message = state.step_0()?.get_payload_when_handshaking();
+        stream.write_all(&first_message).await?;
+        let mut second_message = [0; INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
+        stream.read_exact(&mut second_message).await?;
+        state.step_2(second_message)?
+    } else {
+        let mut first_message = [0; RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE];
+        stream.read_exact(&mut first_message).await?;
+        let (second_message, transport_mode) = state.step_1(first_message)?;
+        stream
+            .write_all(&second_message.get_payload_when_handshaking())
+            .await?;
+        transport_mode
+    };
+    transport_mode.set_rekey_policy(rekey_policy)?;
+    Ok(transport_mode)
+}
+
+/// Does the Noise handshake over `stream` and wraps it in a [`Framed`] with a [`NoiseSv2Codec`].
+pub async fn noise_framed<S: AsyncRead + AsyncWrite + Unpin, Message: Serialize + GetSize>(
+    mut stream: S,
+    role: HandshakeRole,
+    rekey_policy: RekeyPolicy,
+) -> Result<Framed<S, NoiseSv2Codec<Message>>, Error> {
+    let state = handshake(&mut stream, role, rekey_policy).await?;
+    Ok(Framed::new(stream, NoiseSv2Codec::new(state)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::roles;
+    use binary_sv2::{binary_codec_sv2, B064K};
+    use codec_sv2::{Initiator, StandardEitherFrame};
+    use futures::{SinkExt, StreamExt};
+    use key_utils::Secp256k1PublicKey;
+    use tokio::net::{TcpListener, TcpStream};
+
+    const MESSAGE_TYPE: u8 = 0xf0;
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    struct Ping<'decoder> {
+        nonce: u32,
+        padding: B064K<'decoder>,
+    }
+
+    fn frame(nonce: u32, size: usize) -> StandardSv2Frame<Ping<'static>> {
+        let message = Ping {
+            nonce,
+            padding: vec![nonce as u8; size].try_into().unwrap(),
+        };
+        StandardSv2Frame::from_message(message, MESSAGE_TYPE, 0, false).unwrap()
+    }
+
+    fn nonce(mut frame: StandardSv2Frame<Ping<'static>>) -> u32 {
+        assert_eq!(frame.get_header().unwrap().msg_type(), MESSAGE_TYPE);
+        let ping: Ping<'_> = binary_sv2::from_bytes(frame.payload()).unwrap();
+        ping.nonce
+    }
+
+    // Sends frames of growing size, so that some of them span several reads.
+    async fn exchange<S, T>(mut sender: S, mut receiver: T)
+    where
+        S: SinkExt<StandardSv2Frame<Ping<'static>>, Error = Error> + Unpin,
+        T: StreamExt<Item = Result<StandardSv2Frame<Ping<'static>>, Error>> + Unpin,
+    {
+        let sizes = [0, 1, 100, 10_000, 65_000];
+        let sending = async {
+            for (i, size) in sizes.iter().enumerate() {
+                sender.send(frame(i as u32, *size)).await.unwrap();
+            }
+        };
+        let receiving = async {
+            for i in 0..sizes.len() {
+                assert_eq!(nonce(receiver.next().await.unwrap().unwrap()), i as u32);
+            }
+        };
+        tokio::join!(sending, receiving);
+    }
+
+    #[tokio::test]
+    async fn plain_round_trip() {
+        let (a, b) = tokio::io::duplex(1024);
+        let a = Framed::new(a, Sv2Codec::<Ping>::new());
+        let (b_sink, b_stream) = Framed::new(b, Sv2Codec::<Ping>::new()).split();
+        let (a_sink, a_stream) = a.split();
+        exchange(a_sink, b_stream).await;
+        exchange(b_sink, a_stream).await;
+    }
+
+    #[tokio::test]
+    async fn large_frame_is_rejected() {
+        let (a, b) = tokio::io::duplex(4096);
+        let mut a = Framed::new(a, Sv2Codec::<Ping>::new());
+        let mut b = Framed::new(b, Sv2Codec::<Ping>::new().with_max_frame_size(100));
+        a.send(frame(0, 10)).await.unwrap();
+        a.send(frame(1, 1000)).await.unwrap();
+        assert_eq!(nonce(b.next().await.unwrap().unwrap()), 0);
+        assert!(matches!(
+            b.next().await.unwrap(),
+            Err(Error::FrameTooLarge(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn noise_round_trip() {
+        let (a, b) = tokio::io::duplex(1024);
+        let (initiator, responder) = roles();
+        let (a, b) = tokio::join!(
+            noise_framed::<_, Ping>(a, initiator, RekeyPolicy::default()),
+            noise_framed::<_, Ping>(b, responder, RekeyPolicy::default())
+        );
+        let (a_sink, a_stream) = a.unwrap().split();
+        let (b_sink, b_stream) = b.unwrap().split();
+        exchange(a_sink, b_stream).await;
+        exchange(b_sink, a_stream).await;
+    }
+
+    #[tokio::test]
+    async fn noise_codec_talks_to_noise_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (initiator, responder) = roles();
+        let (framed, connection) = tokio::join!(
+            async {
+                let stream = TcpStream::connect(address).await.unwrap();
+                noise_framed::<_, Ping>(stream, initiator, RekeyPolicy::default()).await
+            },
+            async {
+                let (stream, _) = listener.accept().await.unwrap();
+                crate::noise_connection::Connection::new::<Ping>(stream, responder).await
+            }
+        );
+        let mut framed = framed.unwrap();
+        let (receiver, sender) = connection.unwrap();
+
+        framed.send(frame(1, 10)).await.unwrap();
+        let received: StandardSv2Frame<Ping<'static>> =
+            receiver.recv().await.unwrap().try_into().unwrap();
+        assert_eq!(nonce(received), 1);
+
+        let reply: StandardEitherFrame<Ping<'static>> = frame(2, 10).into();
+        sender.send(reply).await.unwrap();
+        assert_eq!(nonce(framed.next().await.unwrap().unwrap()), 2);
+    }
+
+    #[tokio::test]
+    async fn wrong_authority_key_fails_the_handshake() {
+        let (a, b) = tokio::io::duplex(1024);
+        let (_, responder) = roles();
+        let other_key = "9bETSCePTP78FSzHkRDjnqAh1rd3ZDKa9w39aU35hzrcLDvVKLS"
+            .parse::<Secp256k1PublicKey>()
+            .unwrap();
+        let initiator =
+            HandshakeRole::Initiator(Initiator::from_raw_k(other_key.into_bytes()).unwrap());
+        let (a, _) = tokio::join!(
+            noise_framed::<_, Ping>(a, initiator, RekeyPolicy::default()),
+            noise_framed::<_, Ping>(b, responder, RekeyPolicy::default())
+        );
+        assert!(matches!(a, Err(Error::CodecError(_))));
+    }
+}
@@ -0,0 +1,298 @@
+use codec_sv2::RekeyPolicy;
+use socket2::{SockRef, TcpKeepalive};
+use std::{sync::Arc, time::Duration};
+use tokio::{io::AsyncRead, io::AsyncReadExt, net::TcpStream};
+
+use crate::{
+    admission::{Permit, RateLimiter},
+    Error,
+};
+
+/// Limits applied by [`crate::noise_connection::Connection`] and
+/// [`crate::plain_connection::PlainConnection`] to the underlying TCP stream.
+///
+/// When a limit is hit the connection is closed: the handshake fails with an error, while an
+/// established connection closes its incoming channel, as it does when the peer disconnects.
+#[derive(Debug, Clone)]
+pub struct ConnectionConfig {
+    /// Time allowed to the peer to complete the Noise handshake.
+    pub handshake_timeout: Option<Duration>,
+    /// The connection is closed if waiting for the next frame, or for the rest of a frame, takes
+    /// longer than this. Sv2 has no ping message, so it must be larger than the longest expected
+    /// silence of the peer.
+    pub idle_timeout: Option<Duration>,
+    /// Idle time before the OS starts sending TCP keepalive probes, to detect half-open
+    /// connections.
+    pub keepalive_time: Option<Duration>,
+    /// Interval between TCP keepalive probes. The OS default is used if `None`.
+    pub keepalive_interval: Option<Duration>,
+    /// Maximum number of bytes following a frame header (the payload, plus the MACs on encrypted
+    /// connections). Larger frames close the connection before their body is buffered.
+    pub max_frame_size: Option<usize>,
+    /// Maximum number of incoming messages per second, with bursts of the same size.
+    pub max_messages_per_second: Option<u32>,
+    /// The [`Permit`] the connection was admitted with. It is held until the connection is
+    /// closed, and failed handshakes, oversized frames and message floods are reported to it.
+    pub permit: Option<Arc<Permit>>,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            handshake_timeout: Some(Duration::from_secs(10)),
+            idle_timeout: None,
+            keepalive_time: Some(Duration::from_secs(60)),
+            keepalive_interval: None,
+            max_frame_size: None,
+            max_messages_per_second: None,
+            permit: None,
+        }
+    }
+}
+
+impl ConnectionConfig {
+    /// No timeout, no keepalive and no frame size limit.
+    pub fn unlimited() -> Self {
+        Self {
+            handshake_timeout: None,
+            idle_timeout: None,
+            keepalive_time: None,
+            keepalive_interval: None,
+            max_frame_size: None,
+            max_messages_per_second: None,
+            permit: None,
+        }
+    }
+
+    pub(crate) fn set_keepalive(&self, stream: &TcpStream) -> Result<(), Error> {
+        if let Some(time) = self.keepalive_time {
+            let mut keepalive = TcpKeepalive::new().with_time(time);
+            if let Some(interval) = self.keepalive_interval {
+                keepalive = keepalive.with_interval(interval);
+            }
+            SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_frame_size(&self, size: usize) -> Result<(), Error> {
+        check_frame_size(size, self.max_frame_size)
+    }
+
+    pub(crate) fn message_rate_limiter(&self) -> Option<RateLimiter> {
+        self.max_messages_per_second.map(RateLimiter::new)
+    }
+
+    pub(crate) fn report_protocol_error(&self) {
+        if let Some(permit) = &self.permit {
+            permit.report_protocol_error();
+        }
+    }
+
+    pub(crate) async fn read_exact<R: AsyncRead + Unpin>(
+        &self,
+        reader: &mut R,
+        buf: &mut [u8],
+    ) -> Result<(), Error> {
+        match self.idle_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, reader.read_exact(buf))
+                .await
+                .map_err(|_| Error::IdleTimeout)??,
+            None => reader.read_exact(buf).await?,
+        };
+        Ok(())
+    }
+}
+
+/// The timeouts, keepalive and frame size limit of the connections of a role, as read from the
+/// role configuration. Durations are in seconds, 0 standing for `None` in the corresponding
+/// [`ConnectionConfig`] field.
+///
+/// The defaults are the ones of [`ConnectionConfig::default`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ConnectionLimitsConfig {
+    /// See [`ConnectionConfig::handshake_timeout`].
+    pub handshake_timeout_secs: u64,
+    /// See [`ConnectionConfig::idle_timeout`].
+    pub idle_timeout_secs: u64,
+    /// See [`ConnectionConfig::keepalive_time`].
+    pub keepalive_time_secs: u64,
+    /// See [`ConnectionConfig::keepalive_interval`].
+    pub keepalive_interval_secs: u64,
+    /// See [`ConnectionConfig::max_frame_size`].
+    pub max_frame_size: Option<usize>,
+}
+
+impl Default for ConnectionLimitsConfig {
+    fn default() -> Self {
+        Self {
+            handshake_timeout_secs: 10,
+            idle_timeout_secs: 0,
+            keepalive_time_secs: 60,
+            keepalive_interval_secs: 0,
+            max_frame_size: None,
+        }
+    }
+}
+
+impl From<ConnectionLimitsConfig> for ConnectionConfig {
+    fn from(config: ConnectionLimitsConfig) -> Self {
+        let secs = |secs: u64| Some(secs).filter(|secs| *secs > 0).map(Duration::from_secs);
+        ConnectionConfig {
+            handshake_timeout: secs(config.handshake_timeout_secs),
+            idle_timeout: secs(config.idle_timeout_secs),
+            keepalive_time: secs(config.keepalive_time_secs),
+            keepalive_interval: secs(config.keepalive_interval_secs),
+            max_frame_size: config.max_frame_size,
+            ..ConnectionConfig::unlimited()
+        }
+    }
+}
+
+/// When the Noise connections of a role are rekeyed, as read from the role configuration.
+///
+/// Both limits are unset by default, so that the connections are never rekeyed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RekeyConfig {
+    /// Maximum number of messages encrypted (or decrypted) with the same key.
+    pub max_messages: Option<u64>,
+    /// Maximum number of plaintext bytes encrypted (or decrypted) with the same key.
+    pub max_bytes: Option<u64>,
+}
+
+impl From<RekeyConfig> for RekeyPolicy {
+    fn from(config: RekeyConfig) -> Self {
+        RekeyPolicy {
+            max_messages: config.max_messages,
+            max_bytes: config.max_bytes,
+        }
+    }
+}
+
+pub(crate) fn check_frame_size(size: usize, max_frame_size: Option<usize>) -> Result<(), Error> {
+    match max_frame_size {
+        Some(max) if size > max => Err(Error::FrameTooLarge(size)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        admission::{AdmissionConfig, AdmissionControl},
+        noise_connection::Connection,
+        plain_connection::PlainConnection,
+        test_utils::{responder, tcp_pair},
+    };
+    use binary_sv2::{binary_codec_sv2, Deserialize, Serialize};
+    use codec_sv2::HandshakeRole;
+    use std::convert::TryInto;
+    use tokio::{io::AsyncWriteExt, time::timeout};
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    struct Ping {
+        nonce: u32,
+    }
+
+    fn config() -> ConnectionConfig {
+        ConnectionConfig {
+            handshake_timeout: Some(Duration::from_millis(50)),
+            idle_timeout: Some(Duration::from_millis(50)),
+            max_frame_size: Some(100),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn zero_limits_are_disabled() {
+        let config: ConnectionConfig = ConnectionLimitsConfig {
+            idle_timeout_secs: 30,
+            keepalive_time_secs: 0,
+            ..Default::default()
+        }
+        .into();
+        assert_eq!(config.handshake_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(config.idle_timeout, Some(Duration::from_secs(30)));
+        assert_eq!(config.keepalive_time, None);
+        assert_eq!(config.keepalive_interval, None);
+    }
+
+    #[tokio::test]
+    async fn silent_peer_fails_the_handshake() {
+        let (_client, server) = tcp_pair().await;
+        let responder = responder();
+        let result = Connection::new_with_config::<Ping>(
+            server,
+            HandshakeRole::Responder(responder),
+            RekeyPolicy::default(),
+            config(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::HandshakeTimeout)));
+    }
+
+    #[tokio::test]
+    async fn idle_peer_is_disconnected() {
+        let (_client, server) = tcp_pair().await;
+        let (receiver, _sender) = PlainConnection::new_with_config::<Ping>(server, config()).await;
+        let closed = timeout(Duration::from_secs(5), receiver.recv()).await;
+        assert!(closed.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn large_frame_is_rejected() {
+        let (mut client, server) = tcp_pair().await;
+        let (receiver, _sender) = PlainConnection::new_with_config::<Ping>(
+            server,
+            ConnectionConfig {
+                idle_timeout: None,
+                ..config()
+            },
+        )
+        .await;
+        // Header of a frame with a 1000 bytes payload, the payload is never sent.
+        client
+            .write_all(&[0, 0, 0xf0, 0xe8, 0x03, 0])
+            .await
+            .unwrap();
+        let closed = timeout(Duration::from_secs(5), receiver.recv()).await;
+        assert!(closed.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn message_flood_bans_the_peer() {
+        let control = AdmissionControl::new(AdmissionConfig {
+            max_messages_per_second: Some(2),
+            max_protocol_errors: Some(1),
+            ..Default::default()
+        });
+        let (mut client, server) = tcp_pair().await;
+        let ip = server.peer_addr().unwrap().ip();
+        let permit = control.admit(ip).unwrap();
+        let (receiver, _sender) = PlainConnection::new_with_config::<Ping>(
+            server,
+            permit.into_connection_config(ConnectionConfig::default()),
+        )
+        .await;
+        for _ in 0..3 {
+            client.write_all(&[0, 0, 0xf0, 0, 0, 0]).await.unwrap();
+        }
+        assert!(receiver.recv().await.is_ok());
+        assert!(receiver.recv().await.is_ok());
+        assert!(receiver.recv().await.is_err());
+        assert!(control.is_banned(ip));
+        // The permit is released by the reader task once it is done
+        timeout(Duration::from_secs(5), async {
+            while control.connections() > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
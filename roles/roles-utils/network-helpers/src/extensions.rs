@@ -0,0 +1,147 @@
+//! Extensions negotiation done by a downstream right after `SetupConnectionSuccess`.
+//!
+//! Upstreams that do not implement the extensions negotiation may never answer the
+//! `RequestExtensions`, so the reply is awaited for [`EXTENSIONS_NEGOTIATION_TIMEOUT`] at most:
+//! after that the connection goes on without extensions.
+
+use async_channel::{Receiver, Sender};
+use codec_sv2::{StandardEitherFrame, StandardSv2Frame};
+use const_sv2::EXTENSION_TYPE_EXTENSIONS_NEGOTIATION;
+use roles_logic_sv2::{
+    common_messages_sv2::RequestExtensions,
+    handlers::extensions::ParseExtensionsNegotiationFromUpstream,
+    parsers::{AnyMessage, ExtensionsNegotiation},
+    utils::Mutex,
+};
+use std::{convert::TryInto, sync::Arc, time::Duration};
+use tracing::warn;
+
+use crate::Error;
+
+/// Time allowed to the upstream to answer a `RequestExtensions`.
+pub const EXTENSIONS_NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends a `RequestExtensions` for `requested_extensions` and passes the reply to the
+/// [`ParseExtensionsNegotiationFromUpstream`] handlers of `node`.
+///
+/// If the upstream does not answer in time no handler is called and `Ok(())` is returned, `node`
+/// must then consider that no extension has been negotiated.
+pub async fn negotiate_extensions<Node: ParseExtensionsNegotiationFromUpstream>(
+    node: Arc<Mutex<Node>>,
+    sender: &Sender<StandardEitherFrame<AnyMessage<'static>>>,
+    receiver: &Receiver<StandardEitherFrame<AnyMessage<'static>>>,
+    requested_extensions: Vec<u16>,
+) -> Result<(), Error> {
+    let request_extensions = RequestExtensions {
+        request_id: 0,
+        requested_extensions: requested_extensions.into(),
+    };
+    let frame: StandardSv2Frame<AnyMessage<'static>> = AnyMessage::ExtensionsNegotiation(
+        ExtensionsNegotiation::RequestExtensions(request_extensions),
+    )
+    .try_into()?;
+    sender.send(frame.into()).await?;
+
+    let reply = match tokio::time::timeout(EXTENSIONS_NEGOTIATION_TIMEOUT, receiver.recv()).await {
+        Ok(reply) => reply?,
+        Err(_) => {
+            warn!("Upstream did not answer `RequestExtensions`, no extension negotiated");
+            return Ok(());
+        }
+    };
+    let mut reply: StandardSv2Frame<AnyMessage<'static>> =
+        reply.try_into().map_err(codec_sv2::Error::from)?;
+    let header = reply
+        .get_header()
+        .ok_or(codec_sv2::Error::from(framing_sv2::Error::ExpectedSv2Frame))?;
+    if header.ext_type() != EXTENSION_TYPE_EXTENSIONS_NEGOTIATION {
+        return Err(roles_logic_sv2::Error::UnexpectedExtensionMessage(
+            header.ext_type(),
+            header.msg_type(),
+        )
+        .into());
+    }
+    Node::handle_message_extensions_negotiation(node, header.msg_type(), reply.payload())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use const_sv2::EXTENSION_TYPE_WORKER_HASHRATE_TRACKING;
+    use roles_logic_sv2::{
+        common_messages_sv2::{RequestExtensionsError, RequestExtensionsSuccess},
+        handlers::extensions::SendTo,
+    };
+
+    #[derive(Default)]
+    struct Node {
+        supported_extensions: Option<Vec<u16>>,
+    }
+
+    impl ParseExtensionsNegotiationFromUpstream for Node {
+        fn handle_request_extensions_success(
+            &mut self,
+            m: RequestExtensionsSuccess,
+        ) -> Result<SendTo, roles_logic_sv2::Error> {
+            self.supported_extensions = Some(m.supported_extensions.into_inner());
+            Ok(SendTo::None(None))
+        }
+
+        fn handle_request_extensions_error(
+            &mut self,
+            _m: RequestExtensionsError,
+        ) -> Result<SendTo, roles_logic_sv2::Error> {
+            self.supported_extensions = Some(vec![]);
+            Ok(SendTo::None(None))
+        }
+    }
+
+    // Frames received from a connection have been deserialized, the payload of a frame built from
+    // a message can not be read
+    #[allow(clippy::useless_conversion)] // the buffer is a `Slice` with `with_buffer_pool`
+    fn serialized(message: AnyMessage<'static>) -> StandardSv2Frame<AnyMessage<'static>> {
+        let frame: StandardSv2Frame<AnyMessage<'static>> = message.try_into().unwrap();
+        let mut bytes = vec![0; frame.encoded_length()];
+        frame.serialize(&mut bytes).unwrap();
+        StandardSv2Frame::from_bytes(bytes.into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reply_is_passed_to_the_node() {
+        let (to_upstream, from_downstream) =
+            async_channel::unbounded::<StandardEitherFrame<AnyMessage<'static>>>();
+        let (to_downstream, from_upstream) = async_channel::unbounded();
+        let node = Arc::new(Mutex::new(Node::default()));
+        let upstream = tokio::spawn(async move {
+            let request: StandardSv2Frame<AnyMessage<'static>> =
+                from_downstream.recv().await.unwrap().try_into().unwrap();
+            assert_eq!(
+                request.get_header().unwrap().ext_type(),
+                EXTENSION_TYPE_EXTENSIONS_NEGOTIATION
+            );
+            let success = RequestExtensionsSuccess {
+                request_id: 0,
+                supported_extensions: vec![EXTENSION_TYPE_WORKER_HASHRATE_TRACKING].into(),
+            };
+            let reply = serialized(AnyMessage::ExtensionsNegotiation(
+                ExtensionsNegotiation::RequestExtensionsSuccess(success),
+            ));
+            to_downstream.send(reply.into()).await.unwrap();
+        });
+
+        negotiate_extensions(
+            node.clone(),
+            &to_upstream,
+            &from_upstream,
+            vec![EXTENSION_TYPE_WORKER_HASHRATE_TRACKING],
+        )
+        .await
+        .unwrap();
+        upstream.await.unwrap();
+        assert_eq!(
+            node.safe_lock(|n| n.supported_extensions.clone()).unwrap(),
+            Some(vec![EXTENSION_TYPE_WORKER_HASHRATE_TRACKING])
+        );
+    }
+}
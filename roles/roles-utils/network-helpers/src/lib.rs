@@ -1,18 +1,36 @@
 use binary_sv2::{Deserialize, GetSize, Serialize};
+pub mod admission;
+pub mod certificate_provider;
+#[cfg(feature = "tokio_codec")]
+pub mod codec;
+pub mod config;
+#[cfg(feature = "extensions")]
+pub mod extensions;
 pub mod noise_connection;
 pub mod plain_connection;
 #[cfg(feature = "sv1")]
 pub mod sv1_connection;
+// Not every helper is used with every feature
+#[cfg(test)]
+#[allow(dead_code)]
+mod test_utils;
+
+pub use config::{ConnectionConfig, ConnectionLimitsConfig, RekeyConfig};
 
 use async_channel::{Receiver, RecvError, SendError, Sender};
-use codec_sv2::{Error as CodecError, HandShakeFrame, HandshakeRole, StandardEitherFrame};
+use codec_sv2::{
+    Error as CodecError, HandShakeFrame, HandshakeRole, RekeyPolicy, StandardEitherFrame,
+};
 use const_sv2::{
     INITIATOR_EXPECTED_HANDSHAKE_MESSAGE_SIZE, RESPONDER_EXPECTED_HANDSHAKE_MESSAGE_SIZE,
 };
 use futures::lock::Mutex;
 use std::{
     convert::TryInto,
-    sync::{atomic::AtomicBool, Arc},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 #[derive(Debug)]
@@ -24,6 +42,21 @@ pub enum Error {
     // This means that a socket that was supposed to be opened have been closed, likley by the
     // peer
     SocketClosed,
+    // The authority keys used to sign the certificates are invalid or could not be loaded
+    InvalidAuthorityKeys(String),
+    // Reading from or writing to the socket failed
+    Io(std::io::Error),
+    // The peer did not complete the noise handshake in time
+    HandshakeTimeout,
+    // The peer did not send anything for longer than the idle timeout
+    IdleTimeout,
+    // The peer sent a frame larger than the maximum frame size
+    FrameTooLarge(usize),
+    // The peer sent more messages than allowed by the message rate limit
+    RateLimited,
+    // The reply to a `RequestExtensions` could not be handled
+    #[cfg(feature = "extensions")]
+    RolesLogic(roles_logic_sv2::Error),
 }
 
 impl From<CodecError> for Error {
@@ -31,6 +64,17 @@ impl From<CodecError> for Error {
         Error::CodecError(e)
     }
 }
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+#[cfg(feature = "extensions")]
+impl From<roles_logic_sv2::Error> for Error {
+    fn from(e: roles_logic_sv2::Error) -> Self {
+        Error::RolesLogic(e)
+    }
+}
 impl From<RecvError> for Error {
     fn from(_: RecvError) -> Self {
         Error::RecvError
@@ -43,7 +87,13 @@ impl<T> From<SendError<T>> for Error {
 }
 
 trait SetState {
-    async fn set_state(self_: Arc<Mutex<Self>>, state: codec_sv2::State);
+    /// Switches the connection to `state` once `handshake_written` tells that the handshake
+    /// messages queued so far were encoded with the previous state.
+    async fn set_state(
+        self_: Arc<Mutex<Self>>,
+        state: codec_sv2::State,
+        handshake_written: &AtomicBool,
+    );
 }
 
 async fn initialize_as_downstream<
@@ -53,6 +103,8 @@ async fn initialize_as_downstream<
 >(
     self_: Arc<Mutex<T>>,
     role: HandshakeRole,
+    rekey_policy: RekeyPolicy,
+    handshake_written: &AtomicBool,
     sender_outgoing: Sender<StandardEitherFrame<Message>>,
     receiver_incoming: Receiver<StandardEitherFrame<Message>>,
 ) -> Result<(), Error> {
@@ -73,18 +125,18 @@ async fn initialize_as_downstream<
         .map_err(|_| Error::HandshakeRemoteInvalidMessage)?;
 
     // Create and send thirth handshake message
-    let transport_mode = state.step_2(second_message)?;
+    let mut transport_mode = state.step_2(second_message)?;
+    transport_mode.set_rekey_policy(rekey_policy)?;
 
-    T::set_state(self_, transport_mode).await;
-    while !TRANSPORT_READY.load(std::sync::atomic::Ordering::SeqCst) {
-        std::hint::spin_loop()
-    }
+    T::set_state(self_, transport_mode, handshake_written).await;
     Ok(())
 }
 
 async fn initialize_as_upstream<'a, Message: Serialize + Deserialize<'a> + GetSize, T: SetState>(
     self_: Arc<Mutex<T>>,
     role: HandshakeRole,
+    rekey_policy: RekeyPolicy,
+    handshake_written: &AtomicBool,
     sender_outgoing: Sender<StandardEitherFrame<Message>>,
     receiver_incoming: Receiver<StandardEitherFrame<Message>>,
 ) -> Result<(), Error> {
@@ -102,20 +154,15 @@ async fn initialize_as_upstream<'a, Message: Serialize + Deserialize<'a> + GetSi
         .map_err(|_| Error::HandshakeRemoteInvalidMessage)?;
 
     // Create and send second handshake message
-    let (second_message, transport_mode) = state.step_1(first_message)?;
-    HANDSHAKE_READY.store(false, std::sync::atomic::Ordering::SeqCst);
+    let (second_message, mut transport_mode) = state.step_1(first_message)?;
+    transport_mode.set_rekey_policy(rekey_policy)?;
+    handshake_written.store(false, Ordering::SeqCst);
     sender_outgoing.send(second_message.into()).await?;
 
     // This sets the state to Handshake state - this prompts the task above to move the state
     // to transport mode so that the next incoming message will be decoded correctly
     // It is important to do this directly before sending the fourth message
-    T::set_state(self_, transport_mode).await;
-    while !TRANSPORT_READY.load(std::sync::atomic::Ordering::SeqCst) {
-        std::hint::spin_loop()
-    }
+    T::set_state(self_, transport_mode, handshake_written).await;
 
     Ok(())
 }
-
-static HANDSHAKE_READY: AtomicBool = AtomicBool::new(false);
-static TRANSPORT_READY: AtomicBool = AtomicBool::new(false);
@@ -1,15 +1,14 @@
-use crate::Error;
+use crate::{ConnectionConfig, Error};
 use async_channel::{unbounded, Receiver, Sender};
 use binary_sv2::{Deserialize, GetSize, Serialize};
-use codec_sv2::{HandshakeRole, StandardEitherFrame, StandardNoiseDecoder};
+use codec_sv2::{HandshakeRole, RekeyPolicy, StandardEitherFrame, StandardNoiseDecoder};
 use futures::lock::Mutex;
-use std::sync::Arc;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-    select, task,
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
 };
-use tracing::{debug, error};
+use tokio::{io::AsyncWriteExt, net::TcpStream, select, task, time::timeout};
+use tracing::{debug, error, warn};
 
 #[derive(Debug)]
 pub struct Connection {
@@ -17,12 +16,15 @@ pub struct Connection {
 }
 
 impl crate::SetState for Connection {
-    async fn set_state(self_: Arc<Mutex<Self>>, state: codec_sv2::State) {
+    async fn set_state(
+        self_: Arc<Mutex<Self>>,
+        state: codec_sv2::State,
+        handshake_written: &AtomicBool,
+    ) {
         loop {
-            if crate::HANDSHAKE_READY.load(std::sync::atomic::Ordering::SeqCst) {
+            if handshake_written.load(Ordering::SeqCst) {
                 if let Some(mut connection) = self_.try_lock() {
                     connection.state = state;
-                    crate::TRANSPORT_READY.store(true, std::sync::atomic::Ordering::Relaxed);
                     break;
                 };
             }
@@ -42,8 +44,50 @@ impl Connection {
             Sender<StandardEitherFrame<Message>>,
         ),
         Error,
+    > {
+        Self::new_with_rekey_policy(stream, role, RekeyPolicy::default()).await
+    }
+
+    /// Like [`Connection::new`], but the transport keys are rekeyed according to `rekey_policy`,
+    /// which must match the policy used by the peer.
+    pub async fn new_with_rekey_policy<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+    >(
+        stream: TcpStream,
+        role: HandshakeRole,
+        rekey_policy: RekeyPolicy,
+    ) -> Result<
+        (
+            Receiver<StandardEitherFrame<Message>>,
+            Sender<StandardEitherFrame<Message>>,
+        ),
+        Error,
+    > {
+        Self::new_with_config(stream, role, rekey_policy, ConnectionConfig::unlimited()).await
+    }
+
+    /// Like [`Connection::new_with_rekey_policy`], with the timeouts, keepalive and frame size
+    /// limit of `config`. The other constructors apply no limit.
+    pub async fn new_with_config<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+    >(
+        stream: TcpStream,
+        role: HandshakeRole,
+        rekey_policy: RekeyPolicy,
+        config: ConnectionConfig,
+    ) -> Result<
+        (
+            Receiver<StandardEitherFrame<Message>>,
+            Sender<StandardEitherFrame<Message>>,
+        ),
+        Error,
     > {
         let address = stream.peer_addr().map_err(|_| Error::SocketClosed)?;
+        if let Err(e) = config.set_keepalive(&stream) {
+            warn!("Failed to set TCP keepalive: {:?} - {}", e, &address);
+        }
 
         let (mut reader, mut writer) = stream.into_split();
 
@@ -62,30 +106,49 @@ impl Connection {
 
         let cloned1 = connection.clone();
         let cloned2 = connection.clone();
+        // Set by the writer once a frame is written, so that the handshake state is not replaced
+        // before the last handshake message is encoded with it
+        let handshake_written = Arc::new(AtomicBool::new(false));
+        let written = handshake_written.clone();
 
-        task::spawn(async move {
+        let reader_config = config.clone();
+        let reader_task = task::spawn(async move {
             select!(
               _ = tokio::signal::ctrl_c() => { },
               _ = async {
                 let mut decoder = StandardNoiseDecoder::<Message>::new();
+                let mut rate_limiter = reader_config.message_rate_limiter();
                 loop {
                   let writable = decoder.writable();
-                  match reader.read_exact(writable).await {
+                  match reader_config.read_exact(&mut reader, writable).await {
                     Ok(_) => {
                       let mut connection = cloned1.lock().await;
                       let decoded = decoder.next_frame(&mut connection.state);
                       drop(connection);
                       match decoded {
                         Ok(x) => {
+                          if rate_limiter.as_mut().is_some_and(|limiter| !limiter.try_acquire()) {
+                            error!("Shutting down noise stream reader! {:?} - {}", Error::RateLimited, &address);
+                            reader_config.report_protocol_error();
+                            sender_incoming.close();
+                            break;
+                          }
                           if sender_incoming.send(x).await.is_err() {
                             error!("Shutting down noise stream reader!");
                             break;
                           }
                         }
                         Err(e) => {
-                          if let codec_sv2::Error::MissingBytes(_) = e {
+                          if let codec_sv2::Error::MissingBytes(size) = e {
+                            if let Err(e) = reader_config.check_frame_size(size) {
+                              error!("Shutting down noise stream reader! {:?} - {}", e, &address);
+                              reader_config.report_protocol_error();
+                              sender_incoming.close();
+                              break;
+                            }
                           } else {
                             error!("Shutting down noise stream reader! {:#?}", e);
+                            reader_config.report_protocol_error();
                             sender_incoming.close();
                             break;
                           }
@@ -94,7 +157,7 @@ impl Connection {
                     }
                     Err(e) => {
                       error!(
-                        "Disconnected from client while reading : {} - {}",
+                        "Disconnected from client while reading : {:?} - {}",
                         e, &address
                       );
                       sender_incoming.close();
@@ -146,36 +209,92 @@ impl Connection {
                       break;
                     }
                   };
-                  crate::HANDSHAKE_READY.store(true, std::sync::atomic::Ordering::Relaxed);
+                  written.store(true, Ordering::SeqCst);
                 }
               } => {}
             );
         });
 
         // DO THE NOISE HANDSHAKE
-        match role {
-            HandshakeRole::Initiator(_) => {
-                debug!("Initializing as downstream for - {}", &address);
-                crate::initialize_as_downstream(
-                    connection.clone(),
-                    role,
-                    sender_outgoing.clone(),
-                    receiver_incoming.clone(),
-                )
-                .await?
-            }
-            HandshakeRole::Responder(_) => {
-                debug!("Initializing as upstream for - {}", &address);
-                crate::initialize_as_upstream(
-                    connection.clone(),
-                    role,
-                    sender_outgoing.clone(),
-                    receiver_incoming.clone(),
-                )
-                .await?
+        let handshake = async {
+            match role {
+                HandshakeRole::Initiator(_) => {
+                    debug!("Initializing as downstream for - {}", &address);
+                    crate::initialize_as_downstream(
+                        connection.clone(),
+                        role,
+                        rekey_policy,
+                        &handshake_written,
+                        sender_outgoing.clone(),
+                        receiver_incoming.clone(),
+                    )
+                    .await
+                }
+                HandshakeRole::Responder(_) => {
+                    debug!("Initializing as upstream for - {}", &address);
+                    crate::initialize_as_upstream(
+                        connection.clone(),
+                        role,
+                        rekey_policy,
+                        &handshake_written,
+                        sender_outgoing.clone(),
+                        receiver_incoming.clone(),
+                    )
+                    .await
+                }
             }
         };
+        let handshake = match config.handshake_timeout {
+            Some(handshake_timeout) => timeout(handshake_timeout, handshake)
+                .await
+                .unwrap_or(Err(Error::HandshakeTimeout)),
+            None => handshake.await,
+        };
+        if let Err(e) = handshake {
+            error!("Noise handshake failed: {:?} - {}", e, &address);
+            config.report_protocol_error();
+            // The writer stops when the outgoing sender is dropped, the reader might be waiting
+            // for a peer that never writes.
+            reader_task.abort();
+            return Err(e);
+        }
         debug!("Noise handshake complete - {}", &address);
         Ok((receiver_incoming, sender_outgoing))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{roles, tcp_pair};
+    use binary_sv2::binary_codec_sv2;
+    use codec_sv2::StandardSv2Frame;
+    use std::convert::TryInto;
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    struct Ping {
+        nonce: u32,
+    }
+
+    // The transport state of each connection must only wait for its own handshake messages
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_handshakes() {
+        let connections = (0..32_u32).map(|nonce| async move {
+            let (client, server) = tcp_pair().await;
+            let (initiator, responder) = roles();
+            let (client, server) = tokio::join!(
+                Connection::new::<Ping>(client, initiator),
+                Connection::new::<Ping>(server, responder)
+            );
+            let (_, sender) = client.unwrap();
+            let (receiver, _) = server.unwrap();
+            let frame = StandardSv2Frame::from_message(Ping { nonce }, 0, 0, false).unwrap();
+            sender.send(frame.into()).await.unwrap();
+            let mut frame: StandardSv2Frame<Ping> =
+                receiver.recv().await.unwrap().try_into().unwrap();
+            let ping: Ping = binary_sv2::from_bytes(frame.payload()).unwrap();
+            assert_eq!(ping.nonce, nonce);
+        });
+        futures::future::join_all(connections).await;
+    }
+}
@@ -2,14 +2,16 @@ use async_channel::{bounded, Receiver, Sender};
 use binary_sv2::{Deserialize, Serialize};
 use core::convert::TryInto;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::AsyncWriteExt,
     net::{TcpListener, TcpStream},
     task,
 };
 
 use binary_sv2::GetSize;
 use codec_sv2::{Error::MissingBytes, StandardDecoder, StandardEitherFrame};
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
+
+use crate::{ConnectionConfig, Error};
 
 #[derive(Debug)]
 pub struct PlainConnection {}
@@ -27,9 +29,27 @@ impl PlainConnection {
     ) -> (
         Receiver<StandardEitherFrame<Message>>,
         Sender<StandardEitherFrame<Message>>,
+    ) {
+        Self::new_with_config(stream, ConnectionConfig::unlimited()).await
+    }
+
+    /// Like [`PlainConnection::new`], with the idle timeout, keepalive and frame size limit of
+    /// `config`. [`PlainConnection::new`] applies no limit.
+    pub async fn new_with_config<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+    >(
+        stream: TcpStream,
+        config: ConnectionConfig,
+    ) -> (
+        Receiver<StandardEitherFrame<Message>>,
+        Sender<StandardEitherFrame<Message>>,
     ) {
         const NOISE_HANDSHAKE_SIZE_HINT: usize = 3363412;
 
+        if let Err(e) = config.set_keepalive(&stream) {
+            warn!("Failed to set TCP keepalive: {:?}", e);
+        }
         let (mut reader, mut writer) = stream.into_split();
 
         let (sender_incoming, receiver_incoming): (
@@ -44,13 +64,23 @@ impl PlainConnection {
         // RECEIVE AND PARSE INCOMING MESSAGES FROM TCP STREAM
         task::spawn(async move {
             let mut decoder = StandardDecoder::<Message>::new();
+            let mut rate_limiter = config.message_rate_limiter();
 
             loop {
                 let writable = decoder.writable();
-                match reader.read_exact(writable).await {
+                match config.read_exact(&mut reader, writable).await {
                     Ok(_) => {
                         match decoder.next_frame() {
                             Ok(frame) => {
+                                if rate_limiter
+                                    .as_mut()
+                                    .is_some_and(|limiter| !limiter.try_acquire())
+                                {
+                                    error!("Failed to read from stream: {:?}", Error::RateLimited);
+                                    config.report_protocol_error();
+                                    sender_incoming.close();
+                                    break;
+                                }
                                 if let Err(e) = sender_incoming.send(frame.into()).await {
                                     error!("Failed to send incoming message: {}", e);
                                     task::yield_now().await;
@@ -64,12 +94,18 @@ impl PlainConnection {
                                 if size == NOISE_HANDSHAKE_SIZE_HINT {
                                     error!("Got noise message on unencrypted connection - disconnecting");
                                     break;
+                                } else if let Err(e) = config.check_frame_size(size) {
+                                    error!("Failed to read from stream: {:?}", e);
+                                    config.report_protocol_error();
+                                    sender_incoming.close();
+                                    break;
                                 } else {
                                     trace!("MissingBytes({}) on incoming message - ignoring", size);
                                 }
                             }
                             Err(e) => {
                                 error!("Failed to read from stream: {}", e);
+                                config.report_protocol_error();
                                 sender_incoming.close();
                                 task::yield_now().await;
                                 break;
@@ -78,7 +114,7 @@ impl PlainConnection {
                     }
                     Err(e) => {
                         // Just fail and force to reinitialize everything
-                        error!("Failed to read from stream: {}", e);
+                        error!("Failed to read from stream: {:?}", e);
                         sender_incoming.close();
                         task::yield_now().await;
                         break;
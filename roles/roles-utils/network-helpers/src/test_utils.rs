@@ -0,0 +1,48 @@
+//! Authority keys, Noise roles and sockets shared by the tests and the benches.
+//!
+//! The benches include this file with `#[path]`, so it only refers to the dependencies of the
+//! crate, never to `crate::`.
+
+use codec_sv2::{HandshakeRole, Initiator, Responder};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+/// Authority public key of the test responders.
+pub const PUBLIC_KEY: &str = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72";
+/// Authority secret key matching [`PUBLIC_KEY`].
+pub const SECRET_KEY: &str = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n";
+
+/// The authority key pair of the test responders.
+pub fn keys() -> (Secp256k1PublicKey, Secp256k1SecretKey) {
+    (PUBLIC_KEY.parse().unwrap(), SECRET_KEY.parse().unwrap())
+}
+
+/// A responder whose certificates are signed with [`keys`].
+pub fn responder() -> Box<Responder> {
+    let (public_key, secret_key) = keys();
+    Responder::from_authority_kp(
+        &public_key.into_bytes(),
+        &secret_key.into_bytes(),
+        Duration::from_secs(3600),
+    )
+    .unwrap()
+}
+
+/// An initiator pinning [`PUBLIC_KEY`] and a [`responder`], so that their handshake succeeds.
+pub fn roles() -> (HandshakeRole, HandshakeRole) {
+    let (public_key, _) = keys();
+    let initiator = Initiator::from_raw_k(public_key.into_bytes()).unwrap();
+    (
+        HandshakeRole::Initiator(initiator),
+        HandshakeRole::Responder(responder()),
+    )
+}
+
+/// Both ends of a localhost TCP connection, the connecting one first.
+pub async fn tcp_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let (client, server) = tokio::join!(TcpStream::connect(address), listener.accept());
+    (client.unwrap(), server.unwrap().0)
+}
@@ -0,0 +1,56 @@
+use network_helpers_sv2::admission::AdmissionConfig;
+use serde::Deserialize;
+
+/// Settings of a [`crate::Sv1Server`]. Every field has a default, so a configuration file only
+/// needs to set the ones it changes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Sv1ServerConfig {
+    /// Address the server listens on, in this format ip:port.
+    pub listen_address: String,
+    /// Size of the extranonce1 assigned to each connection, between 1 and 8 bytes.
+    pub extranonce1_size: usize,
+    /// Size of the extranonce2 rolled by the miners.
+    pub extranonce2_size: usize,
+    /// Maximum length of a JSON-RPC line, longer lines close the connection.
+    pub max_line_length: usize,
+    /// Connections that do not send `mining.subscribe` within this delay are closed.
+    pub subscribe_timeout_secs: u64,
+    /// Version bits the miners are allowed to roll.
+    pub version_rolling_mask: u32,
+    /// How far in the future, from the time of the job, the ntime of a share can be rolled.
+    pub max_ntime_roll_secs: u32,
+    /// Share rate that the vardiff keeps on every connection.
+    pub shares_per_minute: f32,
+    /// Hashrate assumed for a new connection, used to set its first difficulty.
+    pub initial_hashrate: f32,
+    /// Minimum time between two difficulty changes.
+    pub retarget_interval_secs: u64,
+    /// Lowest difficulty the vardiff can set.
+    pub min_difficulty: f64,
+    /// Admission control of the incoming connections.
+    pub admission: AdmissionConfig,
+    /// Number of [`crate::Event`]s kept while the receiver lags, newer events are dropped when
+    /// they do not fit.
+    pub events_capacity: usize,
+}
+
+impl Default for Sv1ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen_address: "0.0.0.0:3333".to_string(),
+            extranonce1_size: 4,
+            extranonce2_size: 4,
+            max_line_length: 2_usize.pow(16),
+            subscribe_timeout_secs: 10,
+            version_rolling_mask: 0x1FFFE000,
+            max_ntime_roll_secs: 7200,
+            shares_per_minute: 10.0,
+            initial_hashrate: 10_000_000_000_000.0,
+            retarget_interval_secs: 60,
+            min_difficulty: 1.0,
+            admission: AdmissionConfig::default(),
+            events_capacity: 1024,
+        }
+    }
+}
@@ -0,0 +1,628 @@
+//! A single SV1 connection.
+//!
+//! Every connection runs in its own task, reading newline delimited JSON-RPC messages from the
+//! miner, forwarding the jobs of the server and retargeting the miner with the vardiff. The
+//! JSON-RPC requests are handled through [`IsServer`].
+
+use crate::{
+    config::Sv1ServerConfig,
+    extranonce::ExtranonceAllocator,
+    job::{difficulty_from_target, header_hash, target_from_difficulty, Job},
+    server::{send_event, Authorizer, Event},
+    share::{Share, ShareRejection, SolvedBlock},
+};
+use futures::StreamExt;
+use network_helpers_sv2::admission::Permit;
+use roles_logic_sv2::{
+    mining_sv2::Target,
+    utils::{hash_rate_from_target, Mutex},
+    vardiff::{Vardiff, VardiffConfig},
+};
+use std::{
+    cell::RefCell,
+    collections::{HashSet, VecDeque},
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{tcp::OwnedWriteHalf, TcpStream},
+    sync::watch,
+};
+use tokio_util::codec::{FramedRead, LinesCodec, LinesCodecError};
+use tracing::{debug, info, warn};
+use v1::{
+    client_to_server,
+    error::Error,
+    json_rpc::{self, JsonRpcError},
+    methods::Client2Server,
+    server_to_client,
+    utils::{Extranonce, HexBytes, HexU32Be},
+    IsServer,
+};
+
+/// How often the vardiff of the connection is checked, the engine enforce its own minimum
+/// interval between two retargets.
+const VARDIFF_TIMER_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Number of jobs kept to validate the shares, older jobs are considered not found.
+const MAX_RECENT_JOBS: usize = 16;
+
+// A job as it has been sent to the miner.
+struct SentJob {
+    job: Arc<Job>,
+    // Target of the connection when the job has been sent.
+    target: Target,
+}
+
+// job_id, extranonce2, ntime, nonce and version of a share, used to detect duplicates.
+type ShareKey = (String, Vec<u8>, u32, u32, u32);
+
+pub(crate) struct Connection {
+    id: u32,
+    config: Arc<Sv1ServerConfig>,
+    permit: Permit,
+    events: async_channel::Sender<Event>,
+    authorizer: Option<Authorizer>,
+    extranonces: Arc<Mutex<ExtranonceAllocator>>,
+    extranonce1: Vec<u8>,
+    version_rolling_mask: Option<HexU32Be>,
+    version_rolling_min_bit: Option<HexU32Be>,
+    authorized_names: Vec<String>,
+    subscribed: bool,
+    // Whether the first job has been sent.
+    mining: bool,
+    vardiff: Vardiff,
+    // A new difficulty must be sent to the miner.
+    difficulty_changed: bool,
+    current_job: Option<Arc<Job>>,
+    recent_jobs: VecDeque<SentJob>,
+    submitted: RefCell<HashSet<ShareKey>>,
+    // Outcome of the last `mining.submit`, set by `handle_submit`.
+    last_share: RefCell<Option<Result<Share, ShareRejection>>>,
+}
+
+impl Connection {
+    pub(crate) fn new(
+        id: u32,
+        config: Arc<Sv1ServerConfig>,
+        permit: Permit,
+        events: async_channel::Sender<Event>,
+        authorizer: Option<Authorizer>,
+        extranonces: Arc<Mutex<ExtranonceAllocator>>,
+        extranonce1: Vec<u8>,
+    ) -> Result<Self, crate::Error> {
+        let vardiff_config = VardiffConfig {
+            retarget_interval: Duration::from_secs(config.retarget_interval_secs),
+            max_target: Some(target_from_difficulty(config.min_difficulty)),
+            ..VardiffConfig::new(config.shares_per_minute)
+        };
+        let vardiff = Vardiff::new(vardiff_config, config.initial_hashrate, Instant::now())?;
+        Ok(Self {
+            id,
+            config,
+            permit,
+            events,
+            authorizer,
+            extranonces,
+            extranonce1,
+            version_rolling_mask: None,
+            version_rolling_min_bit: None,
+            authorized_names: vec![],
+            subscribed: false,
+            mining: false,
+            vardiff,
+            difficulty_changed: false,
+            current_job: None,
+            recent_jobs: VecDeque::new(),
+            submitted: RefCell::new(HashSet::new()),
+            last_share: RefCell::new(None),
+        })
+    }
+
+    /// Serves the miner until it disconnects, the connection is closed on I/O errors, on lines
+    /// longer than the configured limit, when the miner does not subscribe in time, or when it
+    /// goes over the message rate of the admission control.
+    pub(crate) async fn run(
+        mut self,
+        stream: TcpStream,
+        address: SocketAddr,
+        mut jobs: watch::Receiver<Option<Arc<Job>>>,
+    ) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = FramedRead::new(
+            reader,
+            LinesCodec::new_with_max_length(self.config.max_line_length),
+        );
+        let mut rate_limiter = self.permit.message_rate_limiter();
+        let mut vardiff_timer = tokio::time::interval(VARDIFF_TIMER_INTERVAL);
+        let subscribe_timeout =
+            tokio::time::sleep(Duration::from_secs(self.config.subscribe_timeout_secs));
+        tokio::pin!(subscribe_timeout);
+        self.current_job = jobs.borrow_and_update().clone();
+
+        loop {
+            let messages = tokio::select! {
+                line = lines.next() => match line {
+                    Some(Ok(line)) => {
+                        if let Some(rate_limiter) = rate_limiter.as_mut() {
+                            if !rate_limiter.try_acquire() {
+                                warn!("Connection {} from {} is sending too many messages", self.id, address);
+                                break;
+                            }
+                        }
+                        self.on_line(&line)
+                    }
+                    Some(Err(LinesCodecError::MaxLineLengthExceeded)) => {
+                        warn!("Connection {} from {} sent a line too long", self.id, address);
+                        self.permit.report_protocol_error();
+                        break;
+                    }
+                    Some(Err(LinesCodecError::Io(e))) => {
+                        debug!("Connection {} from {} closed: {}", self.id, address, e);
+                        break;
+                    }
+                    None => break,
+                },
+                changed = jobs.changed() => {
+                    if changed.is_err() {
+                        // the server has been dropped
+                        break;
+                    }
+                    match jobs.borrow_and_update().clone() {
+                        Some(job) => self.on_new_job(job),
+                        None => vec![],
+                    }
+                }
+                _ = vardiff_timer.tick() => {
+                    if let Some(target) = self.vardiff.on_timer(Instant::now()) {
+                        self.retarget(target);
+                    }
+                    self.pending_difficulty()
+                }
+                _ = &mut subscribe_timeout, if !self.subscribed => {
+                    warn!("Connection {} from {} did not subscribe in time", self.id, address);
+                    break;
+                }
+            };
+            if let Err(e) = Self::send(&mut writer, messages).await {
+                debug!("Connection {} from {} closed: {}", self.id, address, e);
+                break;
+            }
+        }
+
+        info!("Connection {} from {} closed", self.id, address);
+        if self
+            .extranonces
+            .safe_lock(|extranonces| extranonces.release(&self.extranonce1))
+            .is_err()
+        {
+            warn!("Extranonce allocator mutex poisoned");
+        }
+        send_event(
+            &self.events,
+            Event::Disconnected {
+                connection_id: self.id,
+            },
+        );
+    }
+
+    async fn send(
+        writer: &mut OwnedWriteHalf,
+        messages: Vec<json_rpc::Message>,
+    ) -> std::io::Result<()> {
+        for message in messages {
+            debug!("Sending to miner: {}", message);
+            let mut line = serde_json::to_string(&message)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            line.push('\n');
+            writer.write_all(line.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    // Handles a line sent by the miner, returns the messages to send back.
+    fn on_line(&mut self, line: &str) -> Vec<json_rpc::Message> {
+        let message: json_rpc::Message = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Connection {} sent an invalid message: {}", self.id, e);
+                self.permit.report_protocol_error();
+                return vec![];
+            }
+        };
+        debug!("Received from miner: {}", message);
+        let id = match &message {
+            json_rpc::Message::StandardRequest(request) => Some(request.id),
+            _ => None,
+        };
+        let mut messages = vec![];
+        let request = match Client2Server::try_from(message) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!(
+                    "Connection {} sent an unsupported message: {:?}",
+                    self.id, e
+                );
+                if let Some(id) = id {
+                    messages.push(Self::error_response(
+                        id,
+                        20,
+                        "Unsupported method".to_string(),
+                    ));
+                }
+                return messages;
+            }
+        };
+        let submit = match &request {
+            Client2Server::Submit(submit) => Some((submit.id, submit.user_name.clone())),
+            _ => None,
+        };
+
+        match self.handle_request(request) {
+            Ok(Some(response)) => match self.last_share.take() {
+                Some(Ok(share)) => {
+                    messages.push(response.into());
+                    self.on_share(share);
+                }
+                Some(Err(rejection)) => {
+                    messages.push(Self::error_response(
+                        response.id,
+                        rejection.code(),
+                        rejection.to_string(),
+                    ));
+                    self.on_rejected_share(submit.map(|(_, user_name)| user_name), rejection);
+                }
+                None => messages.push(response.into()),
+            },
+            Ok(None) => (),
+            Err(Error::InvalidSubmission) => {
+                let (id, user_name) = submit.expect("only mining.submit is an invalid submission");
+                let rejection = if self.is_authorized(&user_name) {
+                    ShareRejection::InvalidSubmission
+                } else {
+                    ShareRejection::Unauthorized
+                };
+                messages.push(Self::error_response(
+                    id,
+                    rejection.code(),
+                    rejection.to_string(),
+                ));
+                self.on_rejected_share(Some(user_name), rejection);
+            }
+            Err(e) => warn!("Connection {}: {}", self.id, e),
+        }
+
+        if !self.mining && self.subscribed && !self.authorized_names.is_empty() {
+            messages.extend(self.start_mining());
+        }
+        messages.extend(self.pending_difficulty());
+        messages
+    }
+
+    fn error_response(id: u64, code: i32, message: String) -> json_rpc::Message {
+        json_rpc::Response {
+            id,
+            error: Some(JsonRpcError {
+                code,
+                message,
+                data: None,
+            }),
+            result: serde_json::Value::Null,
+        }
+        .into()
+    }
+
+    fn on_share(&mut self, share: Share) {
+        if share.block.is_some() {
+            info!(
+                "Connection {} found a block on job {}",
+                self.id, share.job_id
+            );
+        }
+        if let Some(target) = self.vardiff.on_share(Instant::now()) {
+            self.retarget(target);
+        }
+        send_event(&self.events, Event::Share(share));
+    }
+
+    fn on_rejected_share(&self, user_name: Option<String>, rejection: ShareRejection) {
+        debug!("Connection {} share rejected: {}", self.id, rejection);
+        send_event(
+            &self.events,
+            Event::ShareRejected {
+                connection_id: self.id,
+                user_name: user_name.unwrap_or_default(),
+                rejection,
+            },
+        );
+    }
+
+    fn retarget(&mut self, target: Target) {
+        debug!(
+            "Vardiff: new difficulty {} for connection {}",
+            difficulty_from_target(&target),
+            self.id
+        );
+        self.difficulty_changed = true;
+    }
+
+    // Sends the difficulty, followed by the current job so that miners applying the difficulty
+    // only to new jobs get it right away.
+    fn pending_difficulty(&mut self) -> Vec<json_rpc::Message> {
+        if !self.mining || !self.difficulty_changed {
+            return vec![];
+        }
+        self.difficulty_changed = false;
+        let mut messages = vec![self.set_difficulty()];
+        if let Some(job) = self.current_job.clone() {
+            messages.push(self.send_job(job, false));
+        }
+        messages
+    }
+
+    fn set_difficulty(&self) -> json_rpc::Message {
+        server_to_client::SetDifficulty {
+            value: difficulty_from_target(self.vardiff.target()),
+        }
+        .into()
+    }
+
+    // The miner is subscribed and authorized, sends the difficulty and the current job.
+    fn start_mining(&mut self) -> Vec<json_rpc::Message> {
+        self.mining = true;
+        self.difficulty_changed = false;
+        let mut messages = vec![self.set_difficulty()];
+        if let Some(job) = self.current_job.clone() {
+            messages.push(self.send_job(job, true));
+        }
+        messages
+    }
+
+    fn on_new_job(&mut self, job: Arc<Job>) -> Vec<json_rpc::Message> {
+        let clean_jobs = job.clean_jobs
+            || self
+                .current_job
+                .as_ref()
+                .is_some_and(|current| current.prev_hash != job.prev_hash);
+        if clean_jobs {
+            self.recent_jobs.clear();
+            self.submitted.get_mut().clear();
+        }
+        self.current_job = Some(job.clone());
+        if !self.mining {
+            return vec![];
+        }
+        vec![self.send_job(job, clean_jobs)]
+    }
+
+    fn send_job(&mut self, job: Arc<Job>, clean_jobs: bool) -> json_rpc::Message {
+        let mut notify = job.to_notify();
+        notify.clean_jobs = clean_jobs;
+        self.recent_jobs.push_back(SentJob {
+            job,
+            target: self.vardiff.target().clone(),
+        });
+        if self.recent_jobs.len() > MAX_RECENT_JOBS {
+            self.recent_jobs.pop_front();
+            let recent_jobs = &self.recent_jobs;
+            self.submitted
+                .get_mut()
+                .retain(|(job_id, ..)| recent_jobs.iter().any(|sent| &sent.job.job_id == job_id));
+        }
+        notify.into()
+    }
+
+    fn check_share(&self, submit: &client_to_server::Submit) -> Result<Share, ShareRejection> {
+        let sent = self
+            .recent_jobs
+            .iter()
+            .rev()
+            .find(|sent| sent.job.job_id == submit.job_id)
+            .ok_or(ShareRejection::JobNotFound)?;
+        let job = &sent.job;
+
+        let time = submit.time.0;
+        if time < job.time || time - job.time > self.config.max_ntime_roll_secs {
+            return Err(ShareRejection::InvalidTime);
+        }
+        let version = match (&submit.version_bits, &self.version_rolling_mask) {
+            (Some(bits), Some(mask)) => (job.version & !mask.0) | (bits.0 & mask.0),
+            _ => job.version,
+        };
+        let extranonce2: Vec<u8> = submit.extra_nonce2.clone().into();
+        let key = (
+            job.job_id.clone(),
+            extranonce2.clone(),
+            time,
+            submit.nonce.0,
+            version,
+        );
+        if self.submitted.borrow().contains(&key) {
+            return Err(ShareRejection::Duplicate);
+        }
+
+        let extranonce = [&self.extranonce1[..], &extranonce2[..]].concat();
+        let header = job
+            .header(&extranonce, version, time, submit.nonce.0)
+            .ok_or(ShareRejection::InvalidCoinbase)?;
+        let hash = header_hash(&header);
+        // Shares of the current jobs are valid for the target they have been sent with, and for
+        // the current one, so that miners are not penalized for applying a new difficulty late
+        // or early.
+        let target = std::cmp::max(&sent.target, self.vardiff.target());
+        if &hash > target {
+            return Err(ShareRejection::LowDifficulty);
+        }
+        self.submitted.borrow_mut().insert(key);
+
+        let block = (hash <= job.block_target()).then(|| SolvedBlock {
+            header,
+            coinbase: job.coinbase(&extranonce),
+        });
+        Ok(Share {
+            connection_id: self.id,
+            user_name: submit.user_name.clone(),
+            job_id: job.job_id.clone(),
+            extranonce1: self.extranonce1.clone(),
+            extranonce2,
+            version,
+            time,
+            nonce: submit.nonce.0,
+            difficulty: difficulty_from_target(target),
+            block,
+        })
+    }
+}
+
+impl IsServer<'static> for Connection {
+    /// Negotiates the version rolling, the miner can only roll the bits allowed by the
+    /// configuration.
+    fn handle_configure(
+        &mut self,
+        request: &client_to_server::Configure,
+    ) -> (Option<server_to_client::VersionRollingParams>, Option<bool>) {
+        let mask = match request.version_rolling_mask() {
+            Some(mask) => mask.0 & self.config.version_rolling_mask,
+            None => {
+                self.version_rolling_mask = None;
+                return (None, Some(false));
+            }
+        };
+        let params = server_to_client::VersionRollingParams::new(
+            HexU32Be(mask),
+            self.version_rolling_min_bit.clone().unwrap_or(HexU32Be(0)),
+        )
+        .ok();
+        self.version_rolling_mask = params
+            .as_ref()
+            .map(|params| params.version_rolling_mask.clone());
+        (params, Some(false))
+    }
+
+    fn handle_subscribe(&self, request: &client_to_server::Subscribe) -> Vec<(String, String)> {
+        debug!(
+            "Connection {} subscribed with agent {}",
+            self.id, request.agent_signature
+        );
+        let subscription_id = format!("{:08x}", self.id);
+        vec![
+            ("mining.set_difficulty".to_string(), subscription_id.clone()),
+            ("mining.notify".to_string(), subscription_id),
+        ]
+    }
+
+    /// Workers are authorized by the authorizer of the server, all of them are when it is not
+    /// set.
+    fn handle_authorize(&self, request: &client_to_server::Authorize) -> bool {
+        let authorized = match &self.authorizer {
+            Some(authorizer) => authorizer(&request.name, &request.password),
+            None => true,
+        };
+        if authorized {
+            send_event(
+                &self.events,
+                Event::Authorized {
+                    connection_id: self.id,
+                    user_name: request.name.clone(),
+                },
+            );
+        }
+        authorized
+    }
+
+    /// Checks the share against the job it has been mined on, the outcome is picked up by
+    /// [`Connection::on_line`] to answer with the right error code.
+    fn handle_submit(&self, request: &client_to_server::Submit<'static>) -> bool {
+        let outcome = self.check_share(request);
+        let accepted = outcome.is_ok();
+        *self.last_share.borrow_mut() = Some(outcome);
+        accepted
+    }
+
+    fn handle_extranonce_subscribe(&self) {}
+
+    /// Sets the difficulty asked by the miner, if it is not below the minimum difficulty. The
+    /// vardiff adjusts it from there.
+    fn handle_suggest_difficulty(&mut self, request: &client_to_server::SuggestDifficulty) -> bool {
+        if request.value.is_nan() || request.value < self.config.min_difficulty {
+            return false;
+        }
+        let target = target_from_difficulty(request.value);
+        let hashrate =
+            hash_rate_from_target(target.clone().into(), self.config.shares_per_minute as f64)
+                .map(|hashrate| hashrate as f32)
+                .unwrap_or(self.config.initial_hashrate);
+        self.vardiff = Vardiff::with_target(
+            self.vardiff.config().clone(),
+            hashrate,
+            target,
+            Instant::now(),
+        );
+        self.difficulty_changed = true;
+        true
+    }
+
+    /// The jobs do not carry their transactions, so the list is always empty.
+    fn handle_get_transactions(
+        &self,
+        _request: &client_to_server::GetTransactions,
+    ) -> Vec<HexBytes> {
+        vec![]
+    }
+
+    fn is_authorized(&self, name: &str) -> bool {
+        self.authorized_names
+            .iter()
+            .any(|authorized| authorized == name)
+    }
+
+    fn authorize(&mut self, name: &str) {
+        if !self.is_authorized(name) {
+            self.authorized_names.push(name.to_string());
+        }
+    }
+
+    /// The extranonce1 is allocated when the connection is opened, the one requested by the
+    /// miner is ignored. This is called when the miner subscribes.
+    fn set_extranonce1(
+        &mut self,
+        _extranonce1: Option<Extranonce<'static>>,
+    ) -> Extranonce<'static> {
+        self.subscribed = true;
+        self.extranonce1()
+    }
+
+    fn extranonce1(&self) -> Extranonce<'static> {
+        // the size is checked by the allocator
+        self.extranonce1.clone().try_into().unwrap()
+    }
+
+    fn set_extranonce2_size(&mut self, _extra_nonce2_size: Option<usize>) -> usize {
+        self.config.extranonce2_size
+    }
+
+    fn extranonce2_size(&self) -> usize {
+        self.config.extranonce2_size
+    }
+
+    fn version_rolling_mask(&self) -> Option<HexU32Be> {
+        self.version_rolling_mask.clone()
+    }
+
+    fn set_version_rolling_mask(&mut self, mask: Option<HexU32Be>) {
+        self.version_rolling_mask = mask;
+    }
+
+    fn set_version_rolling_min_bit(&mut self, mask: Option<HexU32Be>) {
+        self.version_rolling_min_bit = mask;
+    }
+
+    fn notify(&mut self) -> Result<json_rpc::Message, Error<'_>> {
+        match &self.current_job {
+            Some(job) => Ok(job.to_notify().into()),
+            None => Err(Error::IncorrectClientStatus("no job yet".to_string())),
+        }
+    }
+}
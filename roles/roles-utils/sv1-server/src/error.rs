@@ -0,0 +1,37 @@
+use std::fmt;
+
+/// Errors returned by the [`crate::Sv1Server`].
+#[derive(Debug)]
+pub enum Error {
+    /// Errors on the listening socket.
+    Io(std::io::Error),
+    /// The configuration can not be used, e.g. an extranonce1 longer than 8 bytes.
+    InvalidConfig(String),
+    /// Errors from the `roles_logic_sv2` crate, e.g. while setting up the vardiff.
+    RolesSv2Logic(roles_logic_sv2::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Error::*;
+        match self {
+            Io(e) => write!(f, "I/O error: `{:?}`", e),
+            InvalidConfig(e) => write!(f, "Invalid configuration: {}", e),
+            RolesSv2Logic(e) => write!(f, "Roles SV2 Logic Error: `{:?}`", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<roles_logic_sv2::Error> for Error {
+    fn from(e: roles_logic_sv2::Error) -> Self {
+        Error::RolesSv2Logic(e)
+    }
+}
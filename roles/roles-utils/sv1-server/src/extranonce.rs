@@ -0,0 +1,107 @@
+//! Allocation of the extranonce1 of the connections.
+//!
+//! Every connection gets a unique extranonce1, so that two miners never hash the same coinbase.
+//! Extranonce1 values are allocated sequentially and given back when the connection is closed.
+
+use crate::error::Error;
+use std::collections::HashSet;
+
+/// Allocates unique extranonce1 values of a fixed size.
+#[derive(Debug)]
+pub struct ExtranonceAllocator {
+    size: usize,
+    next: u64,
+    in_use: HashSet<u64>,
+}
+
+impl ExtranonceAllocator {
+    /// Creates an allocator of extranonce1 values of `size` bytes, `size` must be between 1 and
+    /// 8.
+    pub fn new(size: usize) -> Result<Self, Error> {
+        if size == 0 || size > 8 {
+            return Err(Error::InvalidConfig(format!(
+                "extranonce1 size must be between 1 and 8 bytes, got {}",
+                size
+            )));
+        }
+        Ok(Self {
+            size,
+            next: 0,
+            in_use: HashSet::new(),
+        })
+    }
+
+    /// Size in bytes of the allocated values.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Number of distinct values, `None` when all the u64 are available.
+    fn capacity(&self) -> Option<u64> {
+        1_u64.checked_shl(8 * self.size as u32)
+    }
+
+    /// Returns an unused extranonce1, big-endian encoded, or `None` if they are all in use.
+    pub fn allocate(&mut self) -> Option<Vec<u8>> {
+        if let Some(capacity) = self.capacity() {
+            if self.in_use.len() as u64 >= capacity {
+                return None;
+            }
+        }
+        while self.in_use.contains(&self.next) {
+            self.advance();
+        }
+        let value = self.next;
+        self.in_use.insert(value);
+        self.advance();
+        Some(value.to_be_bytes()[8 - self.size..].to_vec())
+    }
+
+    /// Gives back an extranonce1 returned by [`ExtranonceAllocator::allocate`].
+    pub fn release(&mut self, extranonce1: &[u8]) {
+        if extranonce1.len() != self.size {
+            return;
+        }
+        let mut bytes = [0_u8; 8];
+        bytes[8 - self.size..].copy_from_slice(extranonce1);
+        self.in_use.remove(&u64::from_be_bytes(bytes));
+    }
+
+    fn advance(&mut self) {
+        self.next = match self.capacity() {
+            Some(capacity) => (self.next + 1) % capacity,
+            None => self.next.wrapping_add(1),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_unique_values() {
+        let mut allocator = ExtranonceAllocator::new(1).unwrap();
+        let mut values = HashSet::new();
+        for _ in 0..256 {
+            let value = allocator.allocate().unwrap();
+            assert_eq!(value.len(), 1);
+            assert!(values.insert(value));
+        }
+        assert!(allocator.allocate().is_none());
+
+        allocator.release(&[42]);
+        assert_eq!(allocator.allocate(), Some(vec![42]));
+        assert!(allocator.allocate().is_none());
+    }
+
+    #[test]
+    fn encodes_big_endian() {
+        let mut allocator = ExtranonceAllocator::new(4).unwrap();
+        assert_eq!(allocator.allocate(), Some(vec![0, 0, 0, 0]));
+        assert_eq!(allocator.allocate(), Some(vec![0, 0, 0, 1]));
+        assert!(ExtranonceAllocator::new(0).is_err());
+        assert!(ExtranonceAllocator::new(9).is_err());
+        assert!(ExtranonceAllocator::new(8).unwrap().allocate().is_some());
+    }
+}
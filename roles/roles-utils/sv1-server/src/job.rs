@@ -0,0 +1,230 @@
+//! Mining jobs and share validation.
+//!
+//! A [`Job`] is built by the user of the server, e.g. from a block template, and broadcast to the
+//! connections with [`crate::Sv1Server::new_job`]. The server keeps the recent jobs of every
+//! connection to rebuild the block header of the submitted shares and check their hash.
+
+use roles_logic_sv2::{mining_sv2::Target, utils::merkle_root_from_path};
+use std::convert::TryInto;
+use stratum_common::bitcoin::{
+    block::{Header, Version},
+    hashes::Hash,
+    BlockHash, CompactTarget, Target as BitcoinTarget, TxMerkleNode,
+};
+use v1::{
+    server_to_client,
+    utils::{HexU32Be, MerkleNode, PrevHash},
+};
+
+/// A mining job, sent to the miners with `mining.notify`.
+///
+/// The coinbase is split around the extranonce: the miners hash
+/// `coinbase_prefix | extranonce1 | extranonce2 | coinbase_suffix`, so the coinbase input script
+/// must leave room for the extranonce1 and extranonce2 configured on the server. The coinbase is
+/// serialized without the segwit marker and witness, as its txid is what goes in the merkle tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// Identifier of the job, must be unique among the recent jobs.
+    pub job_id: String,
+    /// Hash of the previous block, in the byte order of the block header.
+    pub prev_hash: [u8; 32],
+    /// Coinbase transaction up to the extranonce.
+    pub coinbase_prefix: Vec<u8>,
+    /// Coinbase transaction after the extranonce.
+    pub coinbase_suffix: Vec<u8>,
+    /// Hashes needed to compute the merkle root from the coinbase txid.
+    pub merkle_branch: Vec<[u8; 32]>,
+    /// Block version, the miners can roll the bits allowed by the version rolling mask.
+    pub version: u32,
+    /// Network target, in compact form.
+    pub bits: u32,
+    /// Block time, the miners can roll it forward.
+    pub time: u32,
+    /// When `true` the miners must drop the previous jobs, and their shares are rejected as stale.
+    pub clean_jobs: bool,
+}
+
+impl Job {
+    /// The `mining.notify` message of the job.
+    pub fn to_notify(&self) -> server_to_client::Notify<'static> {
+        server_to_client::Notify {
+            job_id: self.job_id.clone(),
+            prev_hash: PrevHash(self.prev_hash.into()),
+            coin_base1: self.coinbase_prefix.clone().into(),
+            coin_base2: self.coinbase_suffix.clone().into(),
+            merkle_branch: self
+                .merkle_branch
+                .iter()
+                .map(|node| MerkleNode((*node).into()))
+                .collect(),
+            version: HexU32Be(self.version),
+            bits: HexU32Be(self.bits),
+            time: HexU32Be(self.time),
+            clean_jobs: self.clean_jobs,
+        }
+    }
+
+    /// Full coinbase transaction for the given `extranonce`, the extranonce1 followed by the
+    /// extranonce2.
+    pub fn coinbase(&self, extranonce: &[u8]) -> Vec<u8> {
+        [
+            &self.coinbase_prefix[..],
+            extranonce,
+            &self.coinbase_suffix[..],
+        ]
+        .concat()
+    }
+
+    /// Header of the block mined by a share, `None` if the coinbase can not be deserialized.
+    pub fn header(&self, extranonce: &[u8], version: u32, time: u32, nonce: u32) -> Option<Header> {
+        let merkle_root: [u8; 32] = merkle_root_from_path(
+            &self.coinbase_prefix,
+            &self.coinbase_suffix,
+            extranonce,
+            &self.merkle_branch,
+        )?
+        .try_into()
+        .ok()?;
+        Some(Header {
+            version: Version::from_consensus(version as i32),
+            prev_blockhash: BlockHash::from_byte_array(self.prev_hash),
+            merkle_root: TxMerkleNode::from_byte_array(merkle_root),
+            time,
+            bits: CompactTarget::from_consensus(self.bits),
+            nonce,
+        })
+    }
+
+    /// Network target of the job, a share whose hash is below it solves a block.
+    pub fn block_target(&self) -> Target {
+        BitcoinTarget::from_compact(CompactTarget::from_consensus(self.bits))
+            .to_le_bytes()
+            .into()
+    }
+}
+
+/// Hash of a block header, as a [`Target`] so that it can be compared with the share targets.
+pub fn header_hash(header: &Header) -> Target {
+    header.block_hash().to_byte_array().into()
+}
+
+/// Converts a `mining.set_difficulty` difficulty in the target of the shares.
+pub fn target_from_difficulty(difficulty: f64) -> Target {
+    // a share with difficulty 1 has target 0xffff * 2^208
+    let mut value = 65535.0 * 2_f64.powi(208) / difficulty;
+    if !value.is_finite() || value >= 2_f64.powi(256) {
+        return [0xff; 32].into();
+    }
+    let mut target = [0_u8; 32];
+    for (i, byte) in target.iter_mut().enumerate().rev() {
+        let unit = 2_f64.powi(8 * i as i32);
+        let digit = (value / unit).floor().min(255.0);
+        *byte = digit as u8;
+        value -= digit * unit;
+    }
+    target.into()
+}
+
+/// Converts a share target in the difficulty sent with `mining.set_difficulty`.
+pub fn difficulty_from_target(target: &Target) -> f64 {
+    let target: binary_sv2::U256<'static> = target.clone().into();
+    let target: [u8; 32] = target.to_vec().try_into().expect("U256 is 32 bytes");
+    BitcoinTarget::from_le_bytes(target).difficulty_float()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use stratum_common::bitcoin::{
+        absolute::LockTime, consensus, transaction, Amount, OutPoint, ScriptBuf, Sequence,
+        Transaction, TxIn, TxOut, Witness,
+    };
+
+    // Coinbase with an 8 bytes input script, split around it.
+    fn coinbase_parts() -> (Vec<u8>, Vec<u8>, Transaction) {
+        let coinbase = Transaction {
+            version: transaction::Version::ONE,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::from_bytes(vec![0; 8]),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(5_000_000_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let bytes = consensus::serialize(&coinbase);
+        // version | input count | outpoint | script length
+        let prefix_len = 4 + 1 + 36 + 1;
+        (
+            bytes[..prefix_len].to_vec(),
+            bytes[prefix_len + 8..].to_vec(),
+            coinbase,
+        )
+    }
+
+    fn job() -> Job {
+        let (coinbase_prefix, coinbase_suffix, _) = coinbase_parts();
+        Job {
+            job_id: "1".to_string(),
+            prev_hash: [7; 32],
+            coinbase_prefix,
+            coinbase_suffix,
+            merkle_branch: vec![],
+            version: 0x20000000,
+            bits: 0x1d00ffff,
+            time: 1_700_000_000,
+            clean_jobs: true,
+        }
+    }
+
+    #[test]
+    fn converts_difficulty() {
+        let mut difficulty_1 = [0_u8; 32];
+        difficulty_1[26] = 0xff;
+        difficulty_1[27] = 0xff;
+        assert_eq!(target_from_difficulty(1.0), Target::from(difficulty_1));
+        assert_eq!(job().block_target(), Target::from(difficulty_1));
+
+        for difficulty in [0.001, 1.0, 512.0, 1e12] {
+            let converted = difficulty_from_target(&target_from_difficulty(difficulty));
+            assert!((converted - difficulty).abs() / difficulty < 1e-9);
+        }
+        assert_eq!(target_from_difficulty(0.0), Target::from([0xff; 32]));
+    }
+
+    #[test]
+    fn builds_share_header() {
+        let job = job();
+        let (_, _, coinbase) = coinbase_parts();
+        let header = job.header(&[0; 8], 0x20002000, job.time + 1, 42).unwrap();
+        assert_eq!(
+            header.merkle_root.to_byte_array(),
+            coinbase.compute_txid().to_byte_array()
+        );
+        assert_eq!(header.prev_blockhash.to_byte_array(), job.prev_hash);
+        assert_eq!(header.version.to_consensus(), 0x20002000);
+        assert_eq!((header.time, header.nonce), (job.time + 1, 42));
+        assert_eq!(job.coinbase(&[0; 8]), consensus::serialize(&coinbase));
+    }
+
+    #[test]
+    fn notify_round_trip() {
+        let job = job();
+        let message: v1::Message = job.to_notify().into();
+        let notification = match message {
+            v1::Message::Notification(notification) => notification,
+            _ => panic!("mining.notify must be a notification"),
+        };
+        let notify = server_to_client::Notify::try_from(notification).unwrap();
+        assert_eq!(notify.job_id, job.job_id);
+        assert_eq!(Vec::<u8>::from(notify.prev_hash), job.prev_hash.to_vec());
+        assert_eq!(Vec::<u8>::from(notify.coin_base1), job.coinbase_prefix);
+        assert_eq!(notify.bits, HexU32Be(job.bits));
+        assert!(notify.clean_jobs);
+    }
+}
@@ -0,0 +1,48 @@
+//! # SV1 Server
+//!
+//! A generic Stratum V1 pool server, built on top of [`v1::IsServer`]. It lets legacy SV1-only
+//! deployments run without an SV2 upstream: the user of the library builds the jobs, e.g. from
+//! the block templates of a node, and receives the validated shares and the solved blocks.
+//!
+//! The server takes care of:
+//! - accepting the connections, with the admission control of `network_helpers_sv2`;
+//! - the newline delimited JSON-RPC framing, with a maximum line length;
+//! - the subscriptions, with a unique extranonce1 for every connection;
+//! - the version rolling negotiation (`mining.configure`);
+//! - the difficulty of every connection, with the [vardiff](roles_logic_sv2::vardiff);
+//! - the validation of the shares against the jobs sent to the connection.
+//!
+//! ```no_run
+//! # async fn run(job: sv1_server::Job) -> Result<(), sv1_server::Error> {
+//! use sv1_server::{Event, Sv1Server, Sv1ServerConfig};
+//!
+//! let (server, events) = Sv1Server::new(Sv1ServerConfig::default())?;
+//! server.new_job(job);
+//! tokio::spawn({
+//!     let server = server.clone();
+//!     async move { server.run().await }
+//! });
+//! while let Ok(event) = events.recv().await {
+//!     if let Event::Share(share) = event {
+//!         if let Some(_block) = share.block {
+//!             // complete the block with the transactions of the job and submit it
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod config;
+mod connection;
+pub mod error;
+pub mod extranonce;
+pub mod job;
+pub mod server;
+pub mod share;
+
+pub use config::Sv1ServerConfig;
+pub use error::Error;
+pub use job::Job;
+pub use server::{Authorizer, Event, Sv1Server};
+pub use share::{Share, ShareRejection, SolvedBlock};
@@ -0,0 +1,187 @@
+use crate::{
+    config::Sv1ServerConfig, connection::Connection, error::Error, extranonce::ExtranonceAllocator,
+    job::Job, share::Share, share::ShareRejection,
+};
+use network_helpers_sv2::admission::AdmissionControl;
+use roles_logic_sv2::utils::Mutex;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{net::TcpListener, sync::watch};
+use tracing::{error, info, warn};
+
+/// Decides if a worker can mine on the server, from the user name and password sent with
+/// `mining.authorize`.
+pub type Authorizer = Arc<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+// Pause after a failed accept, errors like running out of file descriptors would otherwise spin
+// the loop
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// Events of the connections, received from the channel returned by [`Sv1Server::new`].
+#[derive(Debug, Clone)]
+pub enum Event {
+    Connected {
+        connection_id: u32,
+        address: SocketAddr,
+    },
+    Authorized {
+        connection_id: u32,
+        user_name: String,
+    },
+    /// A valid share, the ones solving a block carry it in [`Share::block`].
+    Share(Share),
+    ShareRejected {
+        connection_id: u32,
+        user_name: String,
+        rejection: ShareRejection,
+    },
+    Disconnected {
+        connection_id: u32,
+    },
+}
+
+/// A Stratum V1 pool server.
+///
+/// The server accepts the miners, assigns them a unique extranonce1 and a difficulty, sends them
+/// the jobs given to [`Sv1Server::new_job`] and validates their shares. Accepted shares, and the
+/// blocks they solve, are reported as [`Event`]s. Handles can be cloned, e.g. to push jobs from
+/// another task.
+#[derive(Clone)]
+pub struct Sv1Server {
+    config: Arc<Sv1ServerConfig>,
+    jobs: Arc<watch::Sender<Option<Arc<Job>>>>,
+    events: async_channel::Sender<Event>,
+    extranonces: Arc<Mutex<ExtranonceAllocator>>,
+    admission: Arc<AdmissionControl>,
+    authorizer: Option<Authorizer>,
+    next_connection_id: Arc<AtomicU32>,
+}
+
+impl Sv1Server {
+    /// Creates a server and the channel of its [`Event`]s. Events are dropped when the receiver
+    /// is dropped, or when it lags more than [`Sv1ServerConfig::events_capacity`] events behind.
+    pub fn new(config: Sv1ServerConfig) -> Result<(Self, async_channel::Receiver<Event>), Error> {
+        let extranonces = ExtranonceAllocator::new(config.extranonce1_size)?;
+        let (jobs, _) = watch::channel(None);
+        if config.events_capacity == 0 {
+            return Err(Error::InvalidConfig(
+                "events_capacity must be greater than 0".to_string(),
+            ));
+        }
+        let (events, events_receiver) = async_channel::bounded(config.events_capacity);
+        let server = Self {
+            admission: AdmissionControl::new(config.admission.clone()),
+            config: Arc::new(config),
+            jobs: Arc::new(jobs),
+            events,
+            extranonces: Arc::new(Mutex::new(extranonces)),
+            authorizer: None,
+            next_connection_id: Arc::new(AtomicU32::new(0)),
+        };
+        Ok((server, events_receiver))
+    }
+
+    /// Sets the function checking the workers on `mining.authorize`, by default every worker is
+    /// authorized.
+    pub fn with_authorizer(
+        mut self,
+        authorizer: impl Fn(&str, &str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.authorizer = Some(Arc::new(authorizer));
+        self
+    }
+
+    pub fn config(&self) -> &Sv1ServerConfig {
+        &self.config
+    }
+
+    /// Sends a job to every connection, and to the ones opened later. The connections see a
+    /// new previous hash as a job with `clean_jobs` set, even when it is not.
+    pub fn new_job(&self, job: Job) {
+        self.jobs.send_replace(Some(Arc::new(job)));
+    }
+
+    /// Last job given to [`Sv1Server::new_job`].
+    pub fn current_job(&self) -> Option<Job> {
+        self.jobs.borrow().as_ref().map(|job| job.as_ref().clone())
+    }
+
+    /// Listens on the configured address and serves the miners.
+    pub async fn run(&self) -> Result<(), Error> {
+        let listener = TcpListener::bind(&self.config.listen_address).await?;
+        info!("SV1 server listening on {}", self.config.listen_address);
+        self.serve(listener).await
+    }
+
+    /// Serves the miners connecting to `listener`, each connection runs in its own task. Failing
+    /// to accept or set up a connection is logged and does not stop the server.
+    pub async fn serve(&self, listener: TcpListener) -> Result<(), Error> {
+        loop {
+            let (stream, address) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!("Failed to accept a connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            };
+            let permit = match self.admission.admit(address.ip()) {
+                Ok(permit) => permit,
+                Err(e) => {
+                    warn!("Refusing connection from {}: {}", address, e);
+                    continue;
+                }
+            };
+            let extranonce1 = match self.extranonces.safe_lock(|e| e.allocate()) {
+                Ok(Some(extranonce1)) => extranonce1,
+                Ok(None) => {
+                    warn!("Refusing connection from {}: no extranonce1 left", address);
+                    continue;
+                }
+                Err(e) => {
+                    error!("Extranonce allocator mutex poisoned: {}", e);
+                    continue;
+                }
+            };
+            let connection_id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+            let connection = match Connection::new(
+                connection_id,
+                self.config.clone(),
+                permit,
+                self.events.clone(),
+                self.authorizer.clone(),
+                self.extranonces.clone(),
+                extranonce1.clone(),
+            ) {
+                Ok(connection) => connection,
+                Err(e) => {
+                    error!("Failed to set up the connection from {}: {}", address, e);
+                    let _ = self.extranonces.safe_lock(|e| e.release(&extranonce1));
+                    continue;
+                }
+            };
+            info!("New connection {} from {}", connection_id, address);
+            send_event(
+                &self.events,
+                Event::Connected {
+                    connection_id,
+                    address,
+                },
+            );
+            tokio::spawn(connection.run(stream, address, self.jobs.subscribe()));
+        }
+    }
+}
+
+/// Sends an event without waiting, the connections must not be slowed down by the receiver.
+pub(crate) fn send_event(events: &async_channel::Sender<Event>, event: Event) {
+    if let Err(async_channel::TrySendError::Full(event)) = events.try_send(event) {
+        warn!("Events channel full, dropping {:?}", event);
+    }
+}
@@ -0,0 +1,80 @@
+//! Outcome of the `mining.submit` requests.
+
+use std::fmt;
+use stratum_common::bitcoin::block::Header;
+
+/// A share accepted by the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    pub connection_id: u32,
+    /// Worker that submitted the share.
+    pub user_name: String,
+    pub job_id: String,
+    pub extranonce1: Vec<u8>,
+    pub extranonce2: Vec<u8>,
+    /// Block version, rolled bits included.
+    pub version: u32,
+    pub time: u32,
+    pub nonce: u32,
+    /// Difficulty the share is credited with, the one of the target it has been checked against.
+    pub difficulty: f64,
+    /// Set when the share also meets the network target.
+    pub block: Option<SolvedBlock>,
+}
+
+/// A block found by a miner. The caller completes it with the transactions of the job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolvedBlock {
+    pub header: Header,
+    /// Coinbase transaction, without the segwit marker and witness.
+    pub coinbase: Vec<u8>,
+}
+
+/// Reasons a share is rejected. The rejection is sent to the miner as a JSON-RPC error, with the
+/// error codes commonly used by SV1 pools.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareRejection {
+    /// The job is unknown, or it has been invalidated by a job with `clean_jobs` set.
+    JobNotFound,
+    /// The same share has already been submitted.
+    Duplicate,
+    /// The share hash does not meet the target of the connection.
+    LowDifficulty,
+    /// The worker has not been authorized on the connection.
+    Unauthorized,
+    /// The ntime is before the time of the job or rolled too far.
+    InvalidTime,
+    /// The extranonce2 size or the version bits do not match the ones negotiated with the miner.
+    InvalidSubmission,
+    /// The coinbase of the share can not be deserialized, the job is likely malformed.
+    InvalidCoinbase,
+}
+
+impl ShareRejection {
+    /// JSON-RPC error code sent to the miner.
+    pub fn code(&self) -> i32 {
+        match self {
+            ShareRejection::JobNotFound => 21,
+            ShareRejection::Duplicate => 22,
+            ShareRejection::LowDifficulty => 23,
+            ShareRejection::Unauthorized => 24,
+            ShareRejection::InvalidTime
+            | ShareRejection::InvalidSubmission
+            | ShareRejection::InvalidCoinbase => 20,
+        }
+    }
+}
+
+impl fmt::Display for ShareRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareRejection::JobNotFound => write!(f, "Job not found"),
+            ShareRejection::Duplicate => write!(f, "Duplicate share"),
+            ShareRejection::LowDifficulty => write!(f, "Low difficulty share"),
+            ShareRejection::Unauthorized => write!(f, "Unauthorized worker"),
+            ShareRejection::InvalidTime => write!(f, "Time out of range"),
+            ShareRejection::InvalidSubmission => write!(f, "Invalid extranonce2 or version bits"),
+            ShareRejection::InvalidCoinbase => write!(f, "Invalid coinbase"),
+        }
+    }
+}
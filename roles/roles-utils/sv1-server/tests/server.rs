@@ -0,0 +1,290 @@
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use stratum_common::bitcoin::{
+    absolute::LockTime, consensus, transaction, Amount, OutPoint, ScriptBuf, Sequence, Transaction,
+    TxIn, TxOut, Witness,
+};
+use sv1_server::{Event, Job, ShareRejection, Sv1Server, Sv1ServerConfig};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{tcp::OwnedReadHalf, tcp::OwnedWriteHalf, TcpListener, TcpStream},
+};
+
+fn job(job_id: &str, bits: u32) -> Job {
+    // extranonce1 and extranonce2 are 4 bytes each
+    let coinbase = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::from_bytes(vec![0; 8]),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(5_000_000_000),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    };
+    let coinbase = consensus::serialize(&coinbase);
+    let prefix_len = 4 + 1 + 36 + 1;
+    Job {
+        job_id: job_id.to_string(),
+        prev_hash: [1; 32],
+        coinbase_prefix: coinbase[..prefix_len].to_vec(),
+        coinbase_suffix: coinbase[prefix_len + 8..].to_vec(),
+        merkle_branch: vec![[2; 32]],
+        version: 0x20000000,
+        bits,
+        time: 1_700_000_000,
+        clean_jobs: true,
+    }
+}
+
+struct Miner {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Miner {
+    async fn connect(address: SocketAddr) -> Self {
+        let (reader, writer) = TcpStream::connect(address).await.unwrap().into_split();
+        Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+
+    async fn send(&mut self, message: Value) {
+        let line = format!("{}\n", message);
+        self.writer.write_all(line.as_bytes()).await.unwrap();
+    }
+
+    // Returns the next message matching `method`, or the response with `id`.
+    async fn recv(&mut self, method: Option<&str>, id: Option<u64>) -> Value {
+        loop {
+            let line =
+                tokio::time::timeout(std::time::Duration::from_secs(5), self.lines.next_line())
+                    .await
+                    .expect("timeout waiting for the server")
+                    .unwrap()
+                    .expect("connection closed");
+            let message: Value = serde_json::from_str(&line).unwrap();
+            if method.is_some() && message["method"].as_str() == method {
+                return message;
+            }
+            if id.is_some() && message["id"].as_u64() == id {
+                return message;
+            }
+        }
+    }
+
+    async fn submit(&mut self, id: u64, job_id: &str, nonce: &str) -> Value {
+        self.send(json!({
+            "id": id,
+            "method": "mining.submit",
+            "params": ["worker", job_id, "00000001", "6553f100", nonce],
+        }))
+        .await;
+        self.recv(None, Some(id)).await
+    }
+}
+
+async fn start(server: &Sv1Server) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn({
+        let server = server.clone();
+        async move { server.serve(listener).await }
+    });
+    address
+}
+
+async fn next_share(
+    events: &async_channel::Receiver<Event>,
+) -> Result<sv1_server::Share, ShareRejection> {
+    loop {
+        match events.recv().await.unwrap() {
+            Event::Share(share) => return Ok(share),
+            Event::ShareRejected { rejection, .. } => return Err(rejection),
+            _ => (),
+        }
+    }
+}
+
+#[tokio::test]
+async fn validates_shares() {
+    let config = Sv1ServerConfig {
+        // every hash meets the share target
+        min_difficulty: 1e-12,
+        initial_hashrate: 0.0,
+        ..Default::default()
+    };
+    let (server, events) = Sv1Server::new(config).unwrap();
+    let address = start(&server).await;
+    server.new_job(job("1", 0x1d00ffff));
+
+    let mut miner = Miner::connect(address).await;
+    miner
+        .send(json!({"id": 1, "method": "mining.subscribe", "params": ["test/1.0"]}))
+        .await;
+    let subscribed = miner.recv(None, Some(1)).await;
+    assert_eq!(subscribed["result"][1], "00000000");
+    assert_eq!(subscribed["result"][2], 4);
+
+    miner
+        .send(json!({"id": 2, "method": "mining.authorize", "params": ["worker", "x"]}))
+        .await;
+    assert_eq!(miner.recv(None, Some(2)).await["result"], true);
+    miner.recv(Some("mining.set_difficulty"), None).await;
+    let notify = miner.recv(Some("mining.notify"), None).await;
+    assert_eq!(notify["params"][0], "1");
+
+    assert_eq!(miner.submit(3, "1", "00000001").await["result"], true);
+    let share = next_share(&events).await.unwrap();
+    assert_eq!(share.user_name, "worker");
+    assert_eq!(share.extranonce2, vec![0, 0, 0, 1]);
+    assert!(share.block.is_none());
+
+    let duplicate = miner.submit(4, "1", "00000001").await;
+    assert_eq!(duplicate["error"]["code"], 22);
+    assert_eq!(next_share(&events).await, Err(ShareRejection::Duplicate));
+
+    let unknown = miner.submit(5, "2", "00000001").await;
+    assert_eq!(unknown["error"]["code"], 21);
+    assert_eq!(next_share(&events).await, Err(ShareRejection::JobNotFound));
+
+    // with the regtest target half of the shares solve a block
+    server.new_job(job("2", 0x207fffff));
+    let notify = miner.recv(Some("mining.notify"), None).await;
+    assert_eq!(notify["params"][8], true);
+    assert_eq!(miner.submit(6, "1", "00000002").await["error"]["code"], 21);
+    assert_eq!(next_share(&events).await, Err(ShareRejection::JobNotFound));
+    let mut nonce = 0;
+    let block = loop {
+        nonce += 1;
+        let submitted = miner
+            .submit(6 + nonce, "2", &format!("{:08x}", nonce))
+            .await;
+        assert_eq!(submitted["result"], true);
+        if let Some(block) = next_share(&events).await.unwrap().block {
+            break block;
+        }
+    };
+    assert_eq!(block.header.nonce, nonce as u32);
+    assert_eq!(
+        block.coinbase,
+        server
+            .current_job()
+            .unwrap()
+            .coinbase(&[0, 0, 0, 0, 0, 0, 0, 1])
+    );
+}
+
+#[tokio::test]
+async fn rejects_invalid_shares() {
+    let config = Sv1ServerConfig {
+        min_difficulty: 1e12,
+        initial_hashrate: 0.0,
+        ..Default::default()
+    };
+    let (server, events) = Sv1Server::new(config).unwrap();
+    let server = server.with_authorizer(|name, _| name == "worker");
+    let address = start(&server).await;
+    server.new_job(job("1", 0x1d00ffff));
+
+    let mut miner = Miner::connect(address).await;
+    miner
+        .send(json!({"id": 1, "method": "mining.configure", "params": [["version-rolling"], {"version-rolling.mask": "ffffffff", "version-rolling.min-bit-count": "00000002"}]}))
+        .await;
+    let configured = miner.recv(None, Some(1)).await;
+    assert_eq!(configured["result"]["version-rolling.mask"], "1fffe000");
+    miner
+        .send(json!({"id": 2, "method": "mining.subscribe", "params": []}))
+        .await;
+    miner.recv(None, Some(2)).await;
+    miner
+        .send(json!({"id": 3, "method": "mining.authorize", "params": ["intruder", "x"]}))
+        .await;
+    assert_eq!(miner.recv(None, Some(3)).await["result"], false);
+    let unauthorized = miner.submit(4, "1", "00000001").await;
+    assert_eq!(unauthorized["error"]["code"], 24);
+    assert_eq!(next_share(&events).await, Err(ShareRejection::Unauthorized));
+
+    miner
+        .send(json!({"id": 5, "method": "mining.authorize", "params": ["worker", "x"]}))
+        .await;
+    assert_eq!(miner.recv(None, Some(5)).await["result"], true);
+    let difficulty = miner.recv(Some("mining.set_difficulty"), None).await;
+    assert_eq!(difficulty["params"][0], 1e12);
+    miner.recv(Some("mining.notify"), None).await;
+
+    // the version rolling has been negotiated, the version bits are required
+    let no_version_bits = miner.submit(6, "1", "00000001").await;
+    assert_eq!(no_version_bits["error"]["code"], 20);
+    assert_eq!(
+        next_share(&events).await,
+        Err(ShareRejection::InvalidSubmission)
+    );
+    miner
+        .send(json!({
+            "id": 7,
+            "method": "mining.submit",
+            "params": ["worker", "1", "00000001", "6553f100", "00000001", "00002000"],
+        }))
+        .await;
+    let low_difficulty = miner.recv(None, Some(7)).await;
+    assert_eq!(low_difficulty["error"]["code"], 23);
+    assert_eq!(
+        next_share(&events).await,
+        Err(ShareRejection::LowDifficulty)
+    );
+
+    miner
+        .send(json!({
+            "id": 8,
+            "method": "mining.submit",
+            "params": ["worker", "1", "00000001", "00000000", "00000001", "00002000"],
+        }))
+        .await;
+    assert_eq!(miner.recv(None, Some(8)).await["error"]["code"], 20);
+    assert_eq!(next_share(&events).await, Err(ShareRejection::InvalidTime));
+
+    miner
+        .send(json!({"id": 9, "method": "mining.unknown", "params": []}))
+        .await;
+    assert_eq!(miner.recv(None, Some(9)).await["error"]["code"], 20);
+}
+
+#[tokio::test]
+async fn lagging_events_receiver_does_not_block_the_server() {
+    let config = Sv1ServerConfig {
+        events_capacity: 1,
+        ..Default::default()
+    };
+    let (server, events) = Sv1Server::new(config).unwrap();
+    let address = start(&server).await;
+
+    for id in 0..3 {
+        let mut miner = Miner::connect(address).await;
+        miner
+            .send(json!({"id": id, "method": "mining.subscribe", "params": []}))
+            .await;
+        miner.recv(None, Some(id)).await;
+    }
+    // only the first event is kept
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        Event::Connected { .. }
+    ));
+}
+
+#[test]
+fn events_capacity_must_not_be_zero() {
+    let config = Sv1ServerConfig {
+        events_capacity: 0,
+        ..Default::default()
+    };
+    assert!(Sv1Server::new(config).is_err());
+}
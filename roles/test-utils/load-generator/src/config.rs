@@ -0,0 +1,120 @@
+use clap::{Parser, ValueEnum};
+use key_utils::Secp256k1PublicKey;
+use std::time::Duration;
+
+/// Protocol spoken by the simulated devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Protocol {
+    /// SV1 devices, to load a Translator Proxy or any SV1 pool.
+    Sv1,
+    /// SV2 devices, to load a Pool or a Mining Proxy.
+    Sv2,
+}
+
+/// Channel opened by the simulated SV2 devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChannelKind {
+    /// Standard channel, the upstream builds the Merkle root of the jobs.
+    Standard,
+    /// Extended channel, the device rolls its extranonce part of the coinbase.
+    Extended,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+pub struct Config {
+    #[arg(
+        short,
+        long,
+        help = "Address of the upstream in this format ip:port or domain:port"
+    )]
+    pub address: String,
+    #[arg(long, value_enum, default_value = "sv2")]
+    pub protocol: Protocol,
+    #[arg(
+        long,
+        value_enum,
+        help = "Channel opened by the devices (SV2 only)",
+        default_value = "standard"
+    )]
+    pub channel: ChannelKind,
+    #[arg(
+        short,
+        long,
+        help = "Upstream pub key (SV2 only), when left empty the upstream certificate is not checked"
+    )]
+    pub pubkey: Option<Secp256k1PublicKey>,
+    #[arg(
+        short,
+        long,
+        help = "Number of simulated devices",
+        default_value = "100"
+    )]
+    pub devices: u32,
+    #[arg(
+        long,
+        help = "Hashrate of each device in h/s, shares are found as if the device was hashing at this rate",
+        default_value = "100000000000000"
+    )]
+    pub hashrate: f64,
+    #[arg(
+        long,
+        help = "User identity of the devices, the device number is appended to it",
+        default_value = "load-generator"
+    )]
+    pub user: String,
+    #[arg(
+        long,
+        help = "Seconds over which the devices are started, spreading the initial connections",
+        default_value = "10"
+    )]
+    pub ramp_up: u64,
+    #[arg(
+        long,
+        help = "Mean duration in seconds of a device session, each device disconnects after an exponentially distributed time and reconnects. No churn when left empty"
+    )]
+    pub mean_session: Option<u64>,
+    #[arg(
+        long,
+        help = "Milliseconds a device waits before reconnecting",
+        default_value = "1000"
+    )]
+    pub reconnect_delay: u64,
+    #[arg(
+        long,
+        help = "Number of nonces tried to make each share meet the target, 0 submits a random nonce",
+        default_value = "0"
+    )]
+    pub grind_limit: u32,
+    #[arg(
+        long,
+        help = "Seconds after which the test stops, runs until interrupted when left empty"
+    )]
+    pub duration: Option<u64>,
+    #[arg(
+        long,
+        help = "Seconds between two statistics reports",
+        default_value = "10"
+    )]
+    pub report_interval: u64,
+}
+
+impl Config {
+    /// Delay between the start of two consecutive devices.
+    pub fn start_interval(&self) -> Duration {
+        Duration::from_secs(self.ramp_up) / self.devices.max(1)
+    }
+
+    pub fn reconnect_delay(&self) -> Duration {
+        Duration::from_millis(self.reconnect_delay)
+    }
+
+    pub fn report_interval(&self) -> Duration {
+        Duration::from_secs(self.report_interval.max(1))
+    }
+
+    /// User identity of the device `id`.
+    pub fn user_identity(&self, id: u32) -> String {
+        format!("{}.{}", self.user, id)
+    }
+}
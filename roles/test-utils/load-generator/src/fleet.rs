@@ -0,0 +1,164 @@
+use crate::{
+    config::{Config, Protocol},
+    stats::{Counters, Stats},
+    sv1, sv2,
+};
+use rand::Rng;
+use std::{future::pending, sync::Arc, time::Duration};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{sleep, sleep_until, Instant},
+};
+use tracing::{debug, info};
+
+/// Time allowed to a device to connect, set up the connection and open its channel.
+pub const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How a device session ended.
+#[derive(Debug)]
+pub(crate) enum Outcome {
+    /// The device could not connect or complete its setup.
+    Failed(String),
+    /// The connection was closed by the upstream, or an error occurred after the setup.
+    Dropped(String),
+    /// The device closed the connection at the end of its session.
+    Churned,
+    /// The test is over.
+    Stopped,
+}
+
+/// A simulated device, running sessions one after the other until the test is over.
+#[derive(Debug, Clone)]
+pub(crate) struct Device {
+    pub id: u32,
+    pub config: Arc<Config>,
+    pub stats: Arc<Stats>,
+    pub shutdown: watch::Receiver<bool>,
+}
+
+impl Device {
+    async fn run(mut self) {
+        loop {
+            self.stats.connection_attempt();
+            let end = self.session_end();
+            let outcome = match self.config.protocol {
+                Protocol::Sv1 => sv1::run_session(&mut self, end).await,
+                Protocol::Sv2 => sv2::run_session(&mut self, end).await,
+            };
+            match outcome {
+                Outcome::Failed(e) => {
+                    debug!("Device {} failed to connect: {}", self.id, e);
+                    self.stats.connection_failure();
+                }
+                Outcome::Dropped(e) => {
+                    debug!("Device {} disconnected: {}", self.id, e);
+                    self.stats.disconnected(false);
+                }
+                Outcome::Churned => self.stats.disconnected(true),
+                Outcome::Stopped => return,
+            }
+            tokio::select! {
+                _ = sleep(self.config.reconnect_delay()) => (),
+                _ = self.shutdown.changed() => return,
+            }
+        }
+    }
+
+    // Sessions last an exponentially distributed time when churn is enabled
+    fn session_end(&self) -> Option<Instant> {
+        let mean = self.config.mean_session? as f64;
+        let u: f64 = rand::thread_rng().gen();
+        Some(Instant::now() + Duration::from_secs_f64(-(1.0 - u).ln() * mean))
+    }
+}
+
+/// Completes at `deadline`, never if it is `None`.
+pub(crate) async fn sleep_until_opt(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => pending().await,
+    }
+}
+
+/// Starts the devices described by `config`, and reports statistics until `config.duration`
+/// elapsed or the process is interrupted.
+pub async fn run(config: Config) -> Arc<Stats> {
+    let config = Arc::new(config);
+    let stats = Arc::new(Stats::default());
+    let (stop, shutdown) = watch::channel(false);
+    info!(
+        "Starting {} {:?} devices of {} h/s against {}",
+        config.devices, config.protocol, config.hashrate, config.address
+    );
+
+    let starter: JoinHandle<Vec<JoinHandle<()>>> = tokio::spawn({
+        let config = config.clone();
+        let stats = stats.clone();
+        let mut shutdown = shutdown.clone();
+        async move {
+            let mut devices = Vec::with_capacity(config.devices as usize);
+            for id in 0..config.devices {
+                let device = Device {
+                    id,
+                    config: config.clone(),
+                    stats: stats.clone(),
+                    shutdown: shutdown.clone(),
+                };
+                devices.push(tokio::spawn(device.run()));
+                tokio::select! {
+                    _ = sleep(config.start_interval()) => (),
+                    _ = shutdown.changed() => break,
+                }
+            }
+            devices
+        }
+    });
+
+    let end = config
+        .duration
+        .map(|duration| Instant::now() + Duration::from_secs(duration));
+    let mut previous = Counters::default();
+    let mut interval = tokio::time::interval(config.report_interval());
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                previous = report(&stats, &previous);
+            }
+            _ = sleep_until_opt(end) => break,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    info!("Stopping the devices");
+    let _ = stop.send(true);
+    if let Ok(devices) = starter.await {
+        for device in devices {
+            let _ = device.await;
+        }
+    }
+    report(&stats, &previous);
+    summary(&stats);
+    stats
+}
+
+// Logs the activity since `previous` and returns the current counters
+fn report(stats: &Stats, previous: &Counters) -> Counters {
+    let counters = stats.counters();
+    let (setup, shares) = stats.take_interval_latencies();
+    info!("{}", counters.since(previous));
+    info!("setup latency: {}", setup);
+    info!("share latency: {}", shares);
+    counters
+}
+
+fn summary(stats: &Stats) {
+    let (setup, shares) = stats.total_latencies();
+    info!("Total: {}", stats.counters());
+    info!("Total setup latency: {}", setup);
+    info!("Total share latency: {}", shares);
+    for (reason, count) in stats.reject_reasons() {
+        info!("Rejected shares with {}: {}", reason, count);
+    }
+}
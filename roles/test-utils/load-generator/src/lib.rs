@@ -0,0 +1,16 @@
+//! Load generator simulating a fleet of SV1 or SV2 mining devices in a single process.
+//!
+//! Devices do not hash: share arrivals are drawn from a Poisson process at the configured
+//! hashrate (see [`shares`]), so that thousands of devices can be simulated to capacity-test a
+//! Pool, a Translator Proxy or a Mining Proxy. Devices can also disconnect and reconnect
+//! periodically, and the acceptance and latency of connections and shares are reported in
+//! [`stats::Stats`].
+pub mod config;
+pub mod fleet;
+pub mod shares;
+pub mod stats;
+mod sv1;
+mod sv2;
+
+pub use config::{ChannelKind, Config, Protocol};
+pub use fleet::run;
@@ -0,0 +1,9 @@
+use clap::Parser;
+use load_generator::Config;
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+    let config = Config::parse();
+    load_generator::run(config).await;
+}
@@ -0,0 +1,125 @@
+//! Synthetic share arrivals.
+//!
+//! A device hashing at `h` h/s finds a share for a target `t` once every `2^256 / (h * t)`
+//! seconds on average, and the arrivals of shares are a Poisson process. The devices only wait
+//! for the next arrival instead of hashing, so a single process can simulate a whole fleet.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::time::Duration;
+use stratum_common::bitcoin::{blockdata::block::Header, hashes::Hash};
+
+/// Shares per second of a single device are capped to this value, so that a mistakenly easy
+/// target does not turn the load generator into a flood.
+pub const MAX_SHARES_PER_SECOND: f64 = 1_000.0;
+
+// 2^256
+const HASH_SPACE: f64 = 1.157_920_892_373_162e77;
+// Target of an SV1 difficulty of 1, 0xffff * 2^208
+const DIFFICULTY_1_TARGET: f64 = 2.695_953_529_101_131e67;
+
+/// Value of a 256 bits little endian number, like an SV2 target or a block hash.
+pub fn le_bytes_to_f64(bytes: &[u8]) -> f64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0.0, |acc, b| acc * 256.0 + *b as f64)
+}
+
+/// Target of an SV1 difficulty.
+pub fn difficulty_to_target(difficulty: f64) -> f64 {
+    if difficulty <= 0.0 {
+        HASH_SPACE
+    } else {
+        DIFFICULTY_1_TARGET / difficulty
+    }
+}
+
+/// Mean number of shares per second found by a device hashing at `hashrate` on `target`.
+pub fn shares_per_second(hashrate: f64, target: f64) -> f64 {
+    (hashrate * target / HASH_SPACE).clamp(0.0, MAX_SHARES_PER_SECOND)
+}
+
+/// Draws the time to the next share of a device.
+#[derive(Debug)]
+pub struct ShareClock {
+    hashrate: f64,
+    rate: f64,
+    rng: StdRng,
+}
+
+impl ShareClock {
+    pub fn new(hashrate: f64) -> Self {
+        Self {
+            hashrate,
+            rate: 0.0,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Updates the target the device is mining on.
+    pub fn set_target(&mut self, target: f64) {
+        self.rate = shares_per_second(self.hashrate, target);
+    }
+
+    /// Time to the next share, `None` as long as no target was set. Arrivals are memoryless, so
+    /// the delay can be drawn again whenever the target changes.
+    pub fn next_share(&mut self) -> Option<Duration> {
+        if self.rate <= 0.0 {
+            return None;
+        }
+        // Exponential distribution by inversion, 1 - u is in (0, 1]
+        let u: f64 = self.rng.gen();
+        Some(Duration::from_secs_f64(-(1.0 - u).ln() / self.rate))
+    }
+
+    /// Random nonce for a share that is not ground.
+    pub fn nonce(&mut self) -> u32 {
+        self.rng.gen()
+    }
+}
+
+/// Tries up to `limit` nonces, starting from the one of `header`, to find one whose hash meets
+/// `target`. Returns `true` if one was found, otherwise `header` keeps its initial nonce.
+pub fn grind(header: &mut Header, target: f64, limit: u32) -> bool {
+    let start = header.nonce;
+    for i in 0..limit {
+        header.nonce = start.wrapping_add(i);
+        if le_bytes_to_f64(&header.block_hash().to_byte_array()) <= target {
+            return true;
+        }
+    }
+    header.nonce = start;
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let mut max_target = [0xff; 32];
+        assert!((le_bytes_to_f64(&max_target) / HASH_SPACE - 1.0).abs() < 1e-9);
+        max_target[31] = 0;
+        assert!((le_bytes_to_f64(&max_target) * 256.0 / HASH_SPACE - 1.0).abs() < 1e-9);
+        assert!((difficulty_to_target(2.0) * 2.0 / DIFFICULTY_1_TARGET - 1.0).abs() < 1e-9);
+        // A difficulty 1 share takes 2^32 hashes
+        let rate = shares_per_second(2f64.powi(32), difficulty_to_target(1.0));
+        assert!((rate - 1.0).abs() < 1e-3);
+        assert_eq!(shares_per_second(1e18, HASH_SPACE), MAX_SHARES_PER_SECOND);
+    }
+
+    #[test]
+    fn share_arrivals_follow_the_hashrate() {
+        let mut clock = ShareClock::new(1e12);
+        assert!(clock.next_share().is_none());
+        // 10 shares per second
+        clock.set_target(HASH_SPACE / 1e11);
+        let samples = 20_000;
+        let total: f64 = (0..samples)
+            .map(|_| clock.next_share().unwrap().as_secs_f64())
+            .sum();
+        let mean = total / samples as f64;
+        assert!((mean - 0.1).abs() < 0.005, "mean interval {}", mean);
+    }
+}
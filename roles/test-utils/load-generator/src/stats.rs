@@ -0,0 +1,275 @@
+use roles_logic_sv2::utils::Mutex;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Counters and latencies shared by all the simulated devices.
+#[derive(Debug)]
+pub struct Stats {
+    connection_attempts: AtomicU64,
+    connection_failures: AtomicU64,
+    active_connections: AtomicU64,
+    disconnections: AtomicU64,
+    churned: AtomicU64,
+    shares_sent: AtomicU64,
+    shares_accepted: AtomicU64,
+    shares_rejected: AtomicU64,
+    reject_reasons: Mutex<HashMap<String, u64>>,
+    setup_latencies: Mutex<Latencies>,
+    share_latencies: Mutex<Latencies>,
+}
+
+/// Value of the counters of [`Stats`] at a given time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub connection_attempts: u64,
+    pub connection_failures: u64,
+    pub active_connections: u64,
+    pub disconnections: u64,
+    pub churned: u64,
+    pub shares_sent: u64,
+    pub shares_accepted: u64,
+    pub shares_rejected: u64,
+}
+
+impl Counters {
+    /// Counts since `previous`, the number of active connections is kept as is.
+    pub fn since(&self, previous: &Counters) -> Counters {
+        Counters {
+            connection_attempts: self.connection_attempts - previous.connection_attempts,
+            connection_failures: self.connection_failures - previous.connection_failures,
+            active_connections: self.active_connections,
+            disconnections: self.disconnections - previous.disconnections,
+            churned: self.churned - previous.churned,
+            shares_sent: self.shares_sent - previous.shares_sent,
+            shares_accepted: self.shares_accepted - previous.shares_accepted,
+            shares_rejected: self.shares_rejected - previous.shares_rejected,
+        }
+    }
+}
+
+impl fmt::Display for Counters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "connections: {} active, {} attempts, {} failed, {} dropped, {} churned | shares: {} sent, {} accepted, {} rejected",
+            self.active_connections,
+            self.connection_attempts,
+            self.connection_failures,
+            self.disconnections,
+            self.churned,
+            self.shares_sent,
+            self.shares_accepted,
+            self.shares_rejected
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Latencies {
+    interval: Vec<Duration>,
+    total: Vec<Duration>,
+}
+
+impl Latencies {
+    fn push(&mut self, latency: Duration) {
+        self.interval.push(latency);
+        self.total.push(latency);
+    }
+}
+
+/// Percentiles of a set of latencies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    pub fn new(latencies: &mut [Duration]) -> Self {
+        latencies.sort_unstable();
+        Self {
+            count: latencies.len(),
+            p50: percentile(latencies, 50),
+            p90: percentile(latencies, 90),
+            p99: percentile(latencies, 99),
+            max: latencies.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} samples, p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+            self.count, self.p50, self.p90, self.p99, self.max
+        )
+    }
+}
+
+// Nearest rank percentile of sorted latencies
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::default();
+    }
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            connection_attempts: AtomicU64::new(0),
+            connection_failures: AtomicU64::new(0),
+            active_connections: AtomicU64::new(0),
+            disconnections: AtomicU64::new(0),
+            churned: AtomicU64::new(0),
+            shares_sent: AtomicU64::new(0),
+            shares_accepted: AtomicU64::new(0),
+            shares_rejected: AtomicU64::new(0),
+            reject_reasons: Mutex::new(HashMap::new()),
+            setup_latencies: Mutex::new(Latencies::default()),
+            share_latencies: Mutex::new(Latencies::default()),
+        }
+    }
+}
+
+impl Stats {
+    pub fn connection_attempt(&self) {
+        self.connection_attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_failure(&self) {
+        self.connection_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A device finished its setup, `latency` is the time from the start of the connection to
+    /// the opening of the channel (SV2) or to the authorization (SV1).
+    pub fn connected(&self, latency: Duration) {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        self.setup_latencies.safe_lock(|l| l.push(latency)).unwrap();
+    }
+
+    /// A connected device was disconnected, by the upstream if `churned` is false.
+    pub fn disconnected(&self, churned: bool) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+        if churned {
+            self.churned.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.disconnections.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn share_sent(&self) {
+        self.shares_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn share_accepted(&self, latency: Duration) {
+        self.shares_accepted.fetch_add(1, Ordering::Relaxed);
+        self.share_latencies.safe_lock(|l| l.push(latency)).unwrap();
+    }
+
+    pub fn share_rejected(&self, latency: Duration, reason: &str) {
+        self.shares_rejected.fetch_add(1, Ordering::Relaxed);
+        self.share_latencies.safe_lock(|l| l.push(latency)).unwrap();
+        self.reject_reasons
+            .safe_lock(|r| *r.entry(reason.to_string()).or_insert(0) += 1)
+            .unwrap();
+    }
+
+    pub fn counters(&self) -> Counters {
+        Counters {
+            connection_attempts: self.connection_attempts.load(Ordering::Relaxed),
+            connection_failures: self.connection_failures.load(Ordering::Relaxed),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            disconnections: self.disconnections.load(Ordering::Relaxed),
+            churned: self.churned.load(Ordering::Relaxed),
+            shares_sent: self.shares_sent.load(Ordering::Relaxed),
+            shares_accepted: self.shares_accepted.load(Ordering::Relaxed),
+            shares_rejected: self.shares_rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Summaries of the setup and share latencies recorded since the previous call.
+    pub fn take_interval_latencies(&self) -> (LatencySummary, LatencySummary) {
+        let take = |latencies: &Mutex<Latencies>| {
+            let mut interval = latencies
+                .safe_lock(|l| std::mem::take(&mut l.interval))
+                .unwrap();
+            LatencySummary::new(&mut interval)
+        };
+        (take(&self.setup_latencies), take(&self.share_latencies))
+    }
+
+    /// Summaries of all the setup and share latencies recorded.
+    pub fn total_latencies(&self) -> (LatencySummary, LatencySummary) {
+        let total = |latencies: &Mutex<Latencies>| {
+            let mut total = latencies.safe_lock(|l| l.total.clone()).unwrap();
+            LatencySummary::new(&mut total)
+        };
+        (total(&self.setup_latencies), total(&self.share_latencies))
+    }
+
+    /// Number of rejected shares by reason, most frequent first.
+    pub fn reject_reasons(&self) -> Vec<(String, u64)> {
+        let mut reasons: Vec<(String, u64)> = self
+            .reject_reasons
+            .safe_lock(|r| r.clone().into_iter().collect())
+            .unwrap();
+        reasons.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        reasons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_percentiles() {
+        let mut latencies: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+        let summary = LatencySummary::new(&mut latencies);
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p90, Duration::from_millis(90));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+        assert_eq!(LatencySummary::new(&mut []), LatencySummary::default());
+    }
+
+    #[test]
+    fn interval_counters() {
+        let stats = Stats::default();
+        stats.connection_attempt();
+        stats.connected(Duration::from_millis(10));
+        stats.share_sent();
+        stats.share_sent();
+        stats.share_rejected(Duration::from_millis(1), "invalid-nonce");
+        let first = stats.counters();
+        stats.share_sent();
+        stats.share_accepted(Duration::from_millis(1));
+        stats.disconnected(true);
+        let interval = stats.counters().since(&first);
+        assert_eq!(interval.shares_sent, 1);
+        assert_eq!(interval.shares_accepted, 1);
+        assert_eq!(interval.shares_rejected, 0);
+        assert_eq!(interval.churned, 1);
+        assert_eq!(interval.active_connections, 0);
+        assert_eq!(
+            stats.reject_reasons(),
+            vec![("invalid-nonce".to_string(), 1)]
+        );
+        let (setup, shares) = stats.take_interval_latencies();
+        assert_eq!((setup.count, shares.count), (1, 2));
+        let (setup, shares) = stats.take_interval_latencies();
+        assert_eq!((setup.count, shares.count), (0, 0));
+        assert_eq!(stats.total_latencies().1.count, 2);
+    }
+}
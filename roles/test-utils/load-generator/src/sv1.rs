@@ -0,0 +1,279 @@
+use crate::{
+    fleet::{sleep_until_opt, Device, Outcome, SETUP_TIMEOUT},
+    shares::{difficulty_to_target, grind, ShareClock},
+};
+use roles_logic_sv2::utils::merkle_root_from_path;
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+};
+use stratum_common::bitcoin::{
+    blockdata::block::{Header, Version},
+    hash_types::{BlockHash, TxMerkleNode},
+    hashes::{sha256d::Hash as DHash, Hash},
+    CompactTarget,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    time::{timeout, Instant},
+};
+use tracing::debug;
+use v1::{
+    client_to_server, json_rpc, server_to_client,
+    utils::{Extranonce, HexU32Be},
+};
+
+const SUBSCRIBE_ID: u64 = 1;
+const AUTHORIZE_ID: u64 = 2;
+
+#[derive(Debug)]
+struct Job {
+    job_id: String,
+    prev_hash: [u8; 32],
+    coinbase_prefix: Vec<u8>,
+    coinbase_suffix: Vec<u8>,
+    merkle_path: Vec<Vec<u8>>,
+    version: u32,
+    nbits: u32,
+    ntime: u32,
+}
+
+/// State of the connection of a device.
+#[derive(Debug, Default)]
+struct Client {
+    extranonce1: Option<Vec<u8>>,
+    extranonce2_size: usize,
+    authorized: bool,
+    job: Option<Job>,
+    target: Option<f64>,
+    next_id: u64,
+    // Submitted shares waiting for an answer, by request id
+    pending: HashMap<u64, Instant>,
+}
+
+impl Client {
+    fn is_ready(&self) -> bool {
+        self.extranonce1.is_some() && self.authorized
+    }
+}
+
+/// Runs a session of `device`: connects, subscribes, authorizes and submits shares until `end`.
+pub(crate) async fn run_session(device: &mut Device, end: Option<Instant>) -> Outcome {
+    let start = Instant::now();
+    let mut shutdown = device.shutdown.clone();
+    let connection = tokio::select! {
+        connection = timeout(SETUP_TIMEOUT, connect(device)) => connection,
+        _ = shutdown.changed() => return Outcome::Stopped,
+    };
+    let (mut lines, mut writer, mut client) = match connection {
+        Ok(Ok(connection)) => connection,
+        Ok(Err(e)) => return Outcome::Failed(e),
+        Err(_) => return Outcome::Failed("setup timed out".to_string()),
+    };
+    device.stats.connected(start.elapsed());
+
+    let mut clock = ShareClock::new(device.config.hashrate);
+    let mut next_share: Option<Instant> = None;
+    loop {
+        if next_share.is_none() && client.job.is_some() {
+            next_share = clock.next_share().map(|delay| Instant::now() + delay);
+        }
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = match line {
+                    Ok(Some(line)) => line,
+                    Ok(None) => return Outcome::Dropped("connection closed".to_string()),
+                    Err(e) => return Outcome::Dropped(e.to_string()),
+                };
+                let previous_target = client.target;
+                if let Err(e) = handle_message(device, &mut client, &line) {
+                    return Outcome::Dropped(e);
+                }
+                if client.target != previous_target {
+                    clock.set_target(client.target.unwrap_or_default());
+                    next_share = None;
+                }
+            }
+            _ = sleep_until_opt(next_share) => {
+                next_share = None;
+                if let Err(e) = submit_share(device, &mut clock, &mut client, &mut writer).await {
+                    return Outcome::Dropped(e);
+                }
+            }
+            _ = sleep_until_opt(end) => return Outcome::Churned,
+            _ = device.shutdown.changed() => return Outcome::Stopped,
+        }
+    }
+}
+
+async fn connect(
+    device: &Device,
+) -> Result<(Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf, Client), String> {
+    let stream = TcpStream::connect(&device.config.address)
+        .await
+        .map_err(|e| e.to_string())?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let subscribe = client_to_server::Subscribe {
+        id: SUBSCRIBE_ID,
+        agent_signature: "load-generator".to_string(),
+        extranonce1: None,
+    };
+    send(
+        &mut writer,
+        subscribe.try_into().map_err(|e| format!("{:?}", e))?,
+    )
+    .await?;
+    let authorize = client_to_server::Authorize {
+        id: AUTHORIZE_ID,
+        name: device.config.user_identity(device.id),
+        password: "x".to_string(),
+    };
+    send(&mut writer, authorize.into()).await?;
+
+    let mut client = Client {
+        next_id: AUTHORIZE_ID + 1,
+        ..Default::default()
+    };
+    while !client.is_ready() {
+        let line = lines
+            .next_line()
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "connection closed".to_string())?;
+        handle_message(device, &mut client, &line)?;
+    }
+    Ok((lines, writer, client))
+}
+
+fn handle_message(device: &Device, client: &mut Client, line: &str) -> Result<(), String> {
+    let message: json_rpc::Message =
+        serde_json::from_str(line).map_err(|e| format!("invalid message {}: {}", line, e))?;
+    match message {
+        json_rpc::Message::Notification(notification) => match notification.method.as_str() {
+            "mining.notify" => {
+                let notify = server_to_client::Notify::try_from(notification)
+                    .map_err(|e| format!("invalid mining.notify: {:?}", e))?;
+                let prev_hash: Vec<u8> = notify.prev_hash.into();
+                client.job = Some(Job {
+                    job_id: notify.job_id,
+                    prev_hash: prev_hash
+                        .try_into()
+                        .map_err(|_| "invalid previous hash".to_string())?,
+                    coinbase_prefix: notify.coin_base1.into(),
+                    coinbase_suffix: notify.coin_base2.into(),
+                    merkle_path: notify.merkle_branch.into_iter().map(Into::into).collect(),
+                    version: notify.version.0,
+                    nbits: notify.bits.0,
+                    ntime: notify.time.0,
+                });
+            }
+            "mining.set_difficulty" => {
+                let difficulty = server_to_client::SetDifficulty::try_from(notification)
+                    .map_err(|e| format!("invalid mining.set_difficulty: {:?}", e))?;
+                client.target = Some(difficulty_to_target(difficulty.value));
+            }
+            method => debug!("Ignoring notification {}", method),
+        },
+        json_rpc::Message::OkResponse(response) | json_rpc::Message::ErrorResponse(response) => {
+            match response.id {
+                SUBSCRIBE_ID => {
+                    let subscribe = server_to_client::Subscribe::try_from(&response)
+                        .map_err(|e| format!("subscription failed: {:?}", e))?;
+                    client.extranonce1 = Some(subscribe.extra_nonce1.into());
+                    client.extranonce2_size = subscribe.extra_nonce2_size;
+                }
+                AUTHORIZE_ID => {
+                    if response.result.as_bool() != Some(true) {
+                        return Err(format!("authorization failed: {:?}", response.error));
+                    }
+                    client.authorized = true;
+                }
+                id => match client.pending.remove(&id) {
+                    Some(sent) => match (response.result.as_bool(), response.error) {
+                        (Some(true), _) => device.stats.share_accepted(sent.elapsed()),
+                        (_, Some(error)) => {
+                            device.stats.share_rejected(sent.elapsed(), &error.message)
+                        }
+                        (_, None) => device.stats.share_rejected(sent.elapsed(), "rejected"),
+                    },
+                    None => debug!("Ignoring response {}", id),
+                },
+            }
+        }
+        json_rpc::Message::StandardRequest(request) => {
+            debug!("Ignoring request {}", request.method)
+        }
+    }
+    Ok(())
+}
+
+async fn submit_share(
+    device: &Device,
+    clock: &mut ShareClock,
+    client: &mut Client,
+    writer: &mut OwnedWriteHalf,
+) -> Result<(), String> {
+    let (job, extranonce1) = match (&client.job, &client.extranonce1) {
+        (Some(job), Some(extranonce1)) => (job, extranonce1),
+        _ => return Ok(()),
+    };
+    let extranonce2 = vec![0; client.extranonce2_size];
+    let mut nonce = clock.nonce();
+    if device.config.grind_limit > 0 {
+        let mut extranonce = extranonce1.clone();
+        extranonce.extend_from_slice(&extranonce2);
+        let merkle_root: [u8; 32] = merkle_root_from_path(
+            &job.coinbase_prefix,
+            &job.coinbase_suffix,
+            &extranonce,
+            &job.merkle_path,
+        )
+        .and_then(|root| root.try_into().ok())
+        .ok_or_else(|| "invalid coinbase".to_string())?;
+        let mut header = Header {
+            version: Version::from_consensus(job.version as i32),
+            prev_blockhash: BlockHash::from_raw_hash(DHash::from_byte_array(job.prev_hash)),
+            merkle_root: TxMerkleNode::from_raw_hash(DHash::from_byte_array(merkle_root)),
+            time: job.ntime,
+            bits: CompactTarget::from_consensus(job.nbits),
+            nonce,
+        };
+        grind(
+            &mut header,
+            client.target.unwrap_or_default(),
+            device.config.grind_limit,
+        );
+        nonce = header.nonce;
+    }
+    let id = client.next_id;
+    client.next_id += 1;
+    let submit = client_to_server::Submit {
+        id,
+        user_name: device.config.user_identity(device.id),
+        job_id: job.job_id.clone(),
+        extra_nonce2: Extranonce::try_from(extranonce2).map_err(|e| format!("{:?}", e))?,
+        time: HexU32Be(job.ntime),
+        nonce: HexU32Be(nonce),
+        version_bits: None,
+    };
+    client.pending.insert(id, Instant::now());
+    send(writer, submit.into()).await?;
+    device.stats.share_sent();
+    Ok(())
+}
+
+async fn send(writer: &mut OwnedWriteHalf, message: json_rpc::Message) -> Result<(), String> {
+    let message = format!(
+        "{}\n",
+        serde_json::to_string(&message).map_err(|e| e.to_string())?
+    );
+    writer
+        .write_all(message.as_bytes())
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,456 @@
+use crate::{
+    config::ChannelKind,
+    fleet::{sleep_until_opt, Device, Outcome, SETUP_TIMEOUT},
+    shares::{grind, le_bytes_to_f64, ShareClock},
+};
+use async_channel::{Receiver, Sender};
+use codec_sv2::{HandshakeRole, Initiator, StandardEitherFrame, StandardSv2Frame};
+use network_helpers_sv2::noise_connection::Connection;
+use roles_logic_sv2::{
+    common_messages_sv2::{Protocol, SetupConnection},
+    mining_sv2::*,
+    parsers::{CommonMessages, Mining, MiningDeviceMessages},
+    utils::merkle_root_from_path,
+};
+use std::{
+    collections::VecDeque,
+    convert::{TryFrom, TryInto},
+};
+use stratum_common::bitcoin::{
+    blockdata::block::{Header, Version},
+    hash_types::{BlockHash, TxMerkleNode},
+    hashes::{sha256d::Hash as DHash, Hash},
+    CompactTarget,
+};
+use tokio::{
+    net::TcpStream,
+    time::{timeout, Instant},
+};
+use tracing::debug;
+
+type Message = MiningDeviceMessages<'static>;
+type StdFrame = StandardSv2Frame<Message>;
+type EitherFrame = StandardEitherFrame<Message>;
+
+// Flag of SetupConnection asking the upstream for standard jobs
+const REQUIRES_STANDARD_JOBS: u32 = 0b0000_0000_0000_0000_0000_0000_0000_0001;
+
+#[derive(Debug, Clone)]
+struct Job {
+    job_id: u32,
+    version: u32,
+    merkle: Merkle,
+}
+
+#[derive(Debug, Clone)]
+enum Merkle {
+    // Merkle root of a standard job
+    Root([u8; 32]),
+    // Coinbase and Merkle path of an extended job, the root depends on the extranonce
+    Path {
+        coinbase_tx_prefix: Vec<u8>,
+        coinbase_tx_suffix: Vec<u8>,
+        merkle_path: Vec<Vec<u8>>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct PrevHash {
+    prev_hash: [u8; 32],
+    ntime: u32,
+    nbits: u32,
+}
+
+/// State of the channel of a device.
+#[derive(Debug)]
+struct Channel {
+    kind: ChannelKind,
+    channel_id: u32,
+    // Extranonce prefix and size of the part rolled by the device, for extended channels
+    extranonce_prefix: Vec<u8>,
+    extranonce_size: usize,
+    job: Option<Job>,
+    future_jobs: Vec<Job>,
+    prev_hash: Option<PrevHash>,
+    target: f64,
+    sequence_number: u32,
+    // Submitted shares waiting for an answer, by sequence number
+    pending: VecDeque<(u32, Instant)>,
+}
+
+impl Channel {
+    fn new(kind: ChannelKind, channel_id: u32, target: f64) -> Self {
+        Self {
+            kind,
+            channel_id,
+            extranonce_prefix: Vec::new(),
+            extranonce_size: 0,
+            job: None,
+            future_jobs: Vec::new(),
+            prev_hash: None,
+            target,
+            sequence_number: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn add_job(&mut self, job: Job, future: bool) {
+        if future {
+            self.future_jobs.push(job);
+        } else {
+            self.job = Some(job);
+        }
+    }
+
+    fn can_mine(&self) -> bool {
+        self.job.is_some() && self.prev_hash.is_some()
+    }
+
+    // Extranonce of the next extended share, made of the sequence number so that every share
+    // has a different coinbase
+    fn next_extranonce(&self) -> Vec<u8> {
+        let mut extranonce = self.sequence_number.to_le_bytes().to_vec();
+        extranonce.resize(self.extranonce_size, 0);
+        extranonce
+    }
+}
+
+/// Runs a session of `device`: connects, opens a channel and submits shares until `end`.
+pub(crate) async fn run_session(device: &mut Device, end: Option<Instant>) -> Outcome {
+    let start = Instant::now();
+    let mut shutdown = device.shutdown.clone();
+    let setup = tokio::select! {
+        setup = timeout(SETUP_TIMEOUT, connect(device)) => setup,
+        _ = shutdown.changed() => return Outcome::Stopped,
+    };
+    let (receiver, sender, channel) = match setup {
+        Ok(Ok(setup)) => setup,
+        Ok(Err(e)) => return Outcome::Failed(e),
+        Err(_) => return Outcome::Failed("setup timed out".to_string()),
+    };
+    device.stats.connected(start.elapsed());
+    mine(device, receiver, sender, channel, end).await
+}
+
+async fn connect(
+    device: &Device,
+) -> Result<(Receiver<EitherFrame>, Sender<EitherFrame>, Channel), String> {
+    let config = &device.config;
+    let stream = TcpStream::connect(&config.address)
+        .await
+        .map_err(|e| e.to_string())?;
+    let address = stream.peer_addr().map_err(|e| e.to_string())?;
+    let initiator = Initiator::new(config.pubkey.map(|k| k.0));
+    let kind = config.channel;
+    let (receiver, sender) = Connection::new(stream, HandshakeRole::Initiator(initiator))
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+
+    let setup_connection = SetupConnection {
+        protocol: Protocol::MiningProtocol,
+        min_version: 2,
+        max_version: 2,
+        flags: match kind {
+            ChannelKind::Standard => REQUIRES_STANDARD_JOBS,
+            ChannelKind::Extended => 0,
+        },
+        endpoint_host: address.ip().to_string().into_bytes().try_into().unwrap(),
+        endpoint_port: address.port(),
+        vendor: String::from("load-generator").try_into().unwrap(),
+        hardware_version: String::new().try_into().unwrap(),
+        firmware: String::new().try_into().unwrap(),
+        device_id: device.id.to_string().try_into().unwrap(),
+    };
+    send(
+        &sender,
+        MiningDeviceMessages::Common(setup_connection.into()),
+    )
+    .await?;
+    let mut frame = recv(&receiver).await?;
+    let message_type = header_type(&frame)?;
+    match CommonMessages::try_from((message_type, frame.payload())) {
+        Ok(CommonMessages::SetupConnectionSuccess(_)) => (),
+        Ok(CommonMessages::SetupConnectionError(m)) => {
+            return Err(format!(
+                "SetupConnectionError: {}",
+                String::from_utf8_lossy(m.error_code.inner_as_ref())
+            ))
+        }
+        m => return Err(format!("unexpected message: {:?}", m)),
+    }
+
+    let user_identity = config
+        .user_identity(device.id)
+        .try_into()
+        .map_err(|_| "user identity is too long".to_string())?;
+    let open_channel = match kind {
+        ChannelKind::Standard => Mining::OpenStandardMiningChannel(OpenStandardMiningChannel {
+            request_id: device.id.into(),
+            user_identity,
+            nominal_hash_rate: config.hashrate as f32,
+            max_target: [0xff; 32].into(),
+        }),
+        ChannelKind::Extended => Mining::OpenExtendedMiningChannel(OpenExtendedMiningChannel {
+            request_id: device.id,
+            user_identity,
+            nominal_hash_rate: config.hashrate as f32,
+            max_target: [0xff; 32].into(),
+            min_extranonce_size: 0,
+        }),
+    };
+    send(&sender, MiningDeviceMessages::Mining(open_channel)).await?;
+    let channel = loop {
+        let mut frame = recv(&receiver).await?;
+        let message_type = header_type(&frame)?;
+        match Mining::try_from((message_type, frame.payload())) {
+            Ok(Mining::OpenStandardMiningChannelSuccess(m)) if kind == ChannelKind::Standard => {
+                break Channel::new(kind, m.channel_id, le_bytes_to_f64(m.target.inner_as_ref()));
+            }
+            Ok(Mining::OpenExtendedMiningChannelSuccess(m)) if kind == ChannelKind::Extended => {
+                let mut channel =
+                    Channel::new(kind, m.channel_id, le_bytes_to_f64(m.target.inner_as_ref()));
+                channel.extranonce_prefix = m.extranonce_prefix.inner_as_ref().to_vec();
+                channel.extranonce_size = m.extranonce_size as usize;
+                break channel;
+            }
+            Ok(Mining::OpenMiningChannelError(m)) => {
+                return Err(format!(
+                    "OpenMiningChannelError: {}",
+                    String::from_utf8_lossy(m.error_code.inner_as_ref())
+                ))
+            }
+            // Jobs can be sent before the success when the channel joins a group
+            Ok(_) | Err(_) => debug!("Ignoring message {} before channel opening", message_type),
+        }
+    };
+    Ok((receiver, sender, channel))
+}
+
+async fn mine(
+    device: &mut Device,
+    receiver: Receiver<EitherFrame>,
+    sender: Sender<EitherFrame>,
+    mut channel: Channel,
+    end: Option<Instant>,
+) -> Outcome {
+    let mut clock = ShareClock::new(device.config.hashrate);
+    clock.set_target(channel.target);
+    let mut next_share: Option<Instant> = None;
+    loop {
+        if next_share.is_none() && channel.can_mine() {
+            next_share = clock.next_share().map(|delay| Instant::now() + delay);
+        }
+        tokio::select! {
+            frame = receiver.recv() => {
+                let mut frame: StdFrame = match frame.map(|f| f.try_into()) {
+                    Ok(Ok(frame)) => frame,
+                    Ok(Err(_)) => return Outcome::Dropped("unexpected handshake frame".to_string()),
+                    Err(_) => return Outcome::Dropped("connection closed".to_string()),
+                };
+                let message_type = match header_type(&frame) {
+                    Ok(message_type) => message_type,
+                    Err(e) => return Outcome::Dropped(e),
+                };
+                let previous_target = channel.target;
+                if let Err(e) = handle_message(device, &mut channel, message_type, frame.payload()) {
+                    return Outcome::Dropped(e);
+                }
+                if channel.target != previous_target {
+                    clock.set_target(channel.target);
+                    next_share = None;
+                }
+            }
+            _ = sleep_until_opt(next_share) => {
+                next_share = None;
+                if let Err(e) = submit_share(device, &mut clock, &mut channel, &sender).await {
+                    return Outcome::Dropped(e);
+                }
+            }
+            _ = sleep_until_opt(end) => return Outcome::Churned,
+            _ = device.shutdown.changed() => return Outcome::Stopped,
+        }
+    }
+}
+
+fn handle_message(
+    device: &Device,
+    channel: &mut Channel,
+    message_type: u8,
+    payload: &mut [u8],
+) -> Result<(), String> {
+    let message = match Mining::try_from((message_type, payload)) {
+        Ok(message) => message,
+        Err(_) => {
+            debug!("Ignoring message {}", message_type);
+            return Ok(());
+        }
+    };
+    match message {
+        Mining::NewMiningJob(m) => {
+            let job = Job {
+                job_id: m.job_id,
+                version: m.version,
+                merkle: Merkle::Root(m.merkle_root.inner_as_ref().try_into().unwrap()),
+            };
+            channel.add_job(job, m.is_future());
+        }
+        Mining::NewExtendedMiningJob(m) => {
+            let job = Job {
+                job_id: m.job_id,
+                version: m.version,
+                merkle: Merkle::Path {
+                    coinbase_tx_prefix: m.coinbase_tx_prefix.inner_as_ref().to_vec(),
+                    coinbase_tx_suffix: m.coinbase_tx_suffix.inner_as_ref().to_vec(),
+                    merkle_path: m
+                        .merkle_path
+                        .inner_as_ref()
+                        .iter()
+                        .map(|hash| hash.to_vec())
+                        .collect(),
+                },
+            };
+            channel.add_job(job, m.is_future());
+        }
+        Mining::SetNewPrevHash(m) => {
+            if let Some(job) = channel
+                .future_jobs
+                .iter()
+                .find(|job| job.job_id == m.job_id)
+            {
+                channel.job = Some(job.clone());
+            }
+            channel.future_jobs.clear();
+            channel.prev_hash = Some(PrevHash {
+                prev_hash: m.prev_hash.inner_as_ref().try_into().unwrap(),
+                ntime: m.min_ntime,
+                nbits: m.nbits,
+            });
+        }
+        Mining::SetTarget(m) => {
+            channel.target = le_bytes_to_f64(m.maximum_target.inner_as_ref());
+        }
+        Mining::SubmitSharesSuccess(m) => {
+            while let Some((sequence_number, sent)) = channel.pending.front() {
+                if *sequence_number > m.last_sequence_number {
+                    break;
+                }
+                device.stats.share_accepted(sent.elapsed());
+                channel.pending.pop_front();
+            }
+        }
+        Mining::SubmitSharesError(m) => {
+            let reason = String::from_utf8_lossy(m.error_code.inner_as_ref()).to_string();
+            match channel
+                .pending
+                .iter()
+                .position(|(sequence_number, _)| *sequence_number == m.sequence_number)
+            {
+                Some(i) => {
+                    let (_, sent) = channel.pending.remove(i).unwrap();
+                    device.stats.share_rejected(sent.elapsed(), &reason);
+                }
+                None => debug!("SubmitSharesError for an unknown share: {}", reason),
+            }
+        }
+        Mining::CloseChannel(m) => {
+            return Err(format!(
+                "channel closed: {}",
+                String::from_utf8_lossy(m.reason_code.inner_as_ref())
+            ))
+        }
+        _ => debug!("Ignoring message {}", message_type),
+    }
+    Ok(())
+}
+
+async fn submit_share(
+    device: &Device,
+    clock: &mut ShareClock,
+    channel: &mut Channel,
+    sender: &Sender<EitherFrame>,
+) -> Result<(), String> {
+    let (job, prev_hash) = match (&channel.job, &channel.prev_hash) {
+        (Some(job), Some(prev_hash)) => (job, prev_hash),
+        _ => return Ok(()),
+    };
+    let extranonce = channel.next_extranonce();
+    let merkle_root = match &job.merkle {
+        Merkle::Root(merkle_root) => *merkle_root,
+        Merkle::Path {
+            coinbase_tx_prefix,
+            coinbase_tx_suffix,
+            merkle_path,
+        } => {
+            let full_extranonce = [&channel.extranonce_prefix[..], &extranonce[..]].concat();
+            merkle_root_from_path(
+                coinbase_tx_prefix,
+                coinbase_tx_suffix,
+                &full_extranonce,
+                merkle_path,
+            )
+            .and_then(|root| root.try_into().ok())
+            .ok_or_else(|| format!("invalid coinbase in job {}", job.job_id))?
+        }
+    };
+    let mut header = Header {
+        version: Version::from_consensus(job.version as i32),
+        prev_blockhash: BlockHash::from_raw_hash(DHash::from_byte_array(prev_hash.prev_hash)),
+        merkle_root: TxMerkleNode::from_raw_hash(DHash::from_byte_array(merkle_root)),
+        time: prev_hash.ntime,
+        bits: CompactTarget::from_consensus(prev_hash.nbits),
+        nonce: clock.nonce(),
+    };
+    grind(&mut header, channel.target, device.config.grind_limit);
+    let share = match channel.kind {
+        ChannelKind::Standard => Mining::SubmitSharesStandard(SubmitSharesStandard {
+            channel_id: channel.channel_id,
+            sequence_number: channel.sequence_number,
+            job_id: job.job_id,
+            nonce: header.nonce,
+            ntime: header.time,
+            version: job.version,
+        }),
+        ChannelKind::Extended => Mining::SubmitSharesExtended(SubmitSharesExtended {
+            channel_id: channel.channel_id,
+            sequence_number: channel.sequence_number,
+            job_id: job.job_id,
+            nonce: header.nonce,
+            ntime: header.time,
+            version: job.version,
+            extranonce: extranonce
+                .try_into()
+                .map_err(|_| "extranonce is too long".to_string())?,
+        }),
+    };
+    channel
+        .pending
+        .push_back((channel.sequence_number, Instant::now()));
+    channel.sequence_number = channel.sequence_number.wrapping_add(1);
+    send(sender, MiningDeviceMessages::Mining(share)).await?;
+    device.stats.share_sent();
+    Ok(())
+}
+
+async fn send(sender: &Sender<EitherFrame>, message: Message) -> Result<(), String> {
+    let frame: StdFrame = message.try_into().map_err(|e| format!("{:?}", e))?;
+    sender
+        .send(frame.into())
+        .await
+        .map_err(|_| "connection closed".to_string())
+}
+
+fn header_type(frame: &StdFrame) -> Result<u8, String> {
+    frame
+        .get_header()
+        .map(|header| header.msg_type())
+        .ok_or_else(|| "frame without header".to_string())
+}
+
+async fn recv(receiver: &Receiver<EitherFrame>) -> Result<StdFrame, String> {
+    receiver
+        .recv()
+        .await
+        .map_err(|_| "connection closed".to_string())?
+        .try_into()
+        .map_err(|_| "unexpected handshake frame".to_string())
+}
@@ -264,6 +264,7 @@ impl Client {
                     Self::parse_message(client.clone(), Ok(incoming)).await;
                 }
                 ClientStatus::Subscribed => {
+                    Self::send_extranonce_subscribe(client.clone()).await;
                     Self::send_authorize(client.clone()).await;
                     break;
                 }
@@ -339,6 +340,22 @@ impl Client {
             .unwrap();
     }
 
+    /// Tells the Upstream node that the extranonce can be changed with `mining.set_extranonce`,
+    /// the Upstream node does not respond to it.
+    pub async fn send_extranonce_subscribe(self_: Arc<Mutex<Self>>) {
+        let id = time::SystemTime::now()
+            .duration_since(time::SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let extranonce_subscribe = json_rpc::Message::StandardRequest(json_rpc::StandardRequest {
+            id,
+            method: "mining.extranonce.subscribe".to_string(),
+            params: serde_json::Value::Array(vec![]),
+        });
+        let sender = self_.safe_lock(|s| s.sender_outgoing.clone()).unwrap();
+        Self::send_message(sender, extranonce_subscribe).await;
+    }
+
     pub async fn send_authorize(self_: Arc<Mutex<Self>>) {
         let id = time::SystemTime::now()
             .duration_since(time::SystemTime::UNIX_EPOCH)
@@ -389,6 +406,25 @@ impl IsClient<'static> for Client {
         Ok(())
     }
 
+    fn handle_reconnect(
+        &mut self,
+        reconnect: server_to_client::Reconnect,
+    ) -> Result<(), Error<'static>> {
+        warn!(
+            "Upstream asked to reconnect to {:?}:{:?}, not supported",
+            reconnect.host, reconnect.port
+        );
+        Ok(())
+    }
+
+    fn handle_show_message(
+        &mut self,
+        show_message: server_to_client::ShowMessage,
+    ) -> Result<(), Error<'static>> {
+        info!("Message from upstream: {}", show_message.message);
+        Ok(())
+    }
+
     fn set_extranonce1(&mut self, extranonce1: Extranonce<'static>) {
         self.extranonce1 = Some(extranonce1);
     }
@@ -494,10 +530,13 @@ impl IsClient<'static> for Client {
         Ok(())
     }
 
+    /// The new extranonce is used from the next job on.
     fn handle_set_extranonce(
         &mut self,
-        _conf: &mut server_to_client::SetExtranonce,
+        conf: &mut server_to_client::SetExtranonce,
     ) -> Result<(), Error<'static>> {
+        self.extranonce1 = Some(conf.extra_nonce1.0.clone().into_static().into());
+        self.extranonce2_size = Some(conf.extra_nonce2_size);
         Ok(())
     }
 
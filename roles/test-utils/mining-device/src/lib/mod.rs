@@ -1,13 +1,12 @@
 #![allow(clippy::option_map_unit_fn)]
 use async_channel::{Receiver, Sender};
-use binary_sv2::u256_from_int;
 use codec_sv2::{Initiator, StandardEitherFrame, StandardSv2Frame};
 use key_utils::Secp256k1PublicKey;
 use network_helpers_sv2::noise_connection::Connection;
 use primitive_types::U256;
 use rand::{thread_rng, Rng};
 use roles_logic_sv2::{
-    common_messages_sv2::{Protocol, SetupConnection, SetupConnectionSuccess},
+    common_messages_sv2::{Protocol, Reconnect, SetupConnection, SetupConnectionSuccess},
     common_properties::{IsMiningUpstream, IsUpstream},
     errors::Error,
     handlers::{
@@ -15,11 +14,12 @@ use roles_logic_sv2::{
         mining::{ParseMiningMessagesFromUpstream, SendTo, SupportedChannelTypes},
     },
     mining_sv2::*,
-    parsers::{Mining, MiningDeviceMessages},
-    utils::{Id, Mutex},
+    parsers::{CommonMessages, Mining, MiningDeviceMessages},
+    utils::{merkle_root_from_path, Id, Mutex},
 };
 use std::{
-    net::{SocketAddr, ToSocketAddrs},
+    convert::{TryFrom, TryInto},
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -33,6 +33,76 @@ use stratum_common::bitcoin::{
 use tokio::net::TcpStream;
 use tracing::{debug, error, info};
 
+/// Kind of channel opened by the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ChannelKind {
+    /// Standard channel receiving standard jobs, the device only mines headers.
+    #[default]
+    Standard,
+    /// Standard channel that does not require standard jobs, the device mines the extended jobs
+    /// sent to the group channel it belongs to.
+    Group,
+    /// Extended channel, the device rolls its own part of the extranonce.
+    Extended,
+}
+
+impl ChannelKind {
+    fn setup_connection_flags(&self) -> u32 {
+        match self {
+            // REQUIRES_STANDARD_JOBS
+            ChannelKind::Standard => 0b0000_0000_0000_0000_0000_0000_0000_0001,
+            ChannelKind::Group | ChannelKind::Extended => 0,
+        }
+    }
+}
+
+/// Fault injected in the shares submitted by the device, to exercise the error paths of the
+/// upstream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Fault {
+    /// Submits the share for the last job of the previous block.
+    Stale,
+    /// Submits the share with a nonce that was not found.
+    BadNonce,
+    /// Submits the share for a job id that was never sent.
+    WrongJobId,
+    /// Submits the share twice.
+    Duplicate,
+}
+
+/// Options changing how the device mines and submits shares.
+#[derive(Debug, Clone, Default)]
+pub struct MiningOptions {
+    /// Number of mining threads splitting the nonce space, the available parallelism when
+    /// `None`.
+    pub threads: Option<usize>,
+    /// Rolls the version bits allowed by BIP320 once a thread exhausted its nonce range.
+    pub version_rolling: bool,
+    /// Rolls the ntime once a thread exhausted its nonce range, and the version bits if rolled.
+    pub ntime_rolling: bool,
+    /// Submits shares at this rate without hashing, so they are very unlikely to meet the target.
+    pub fake_shares_per_minute: Option<f32>,
+    /// Fault injected in the submitted shares.
+    pub fault: Option<Fault>,
+    /// The fault is injected in one share every `fault_every`, in every share when 0 or 1.
+    pub fault_every: u32,
+}
+
+impl MiningOptions {
+    fn threads(&self) -> u32 {
+        self.threads
+            .unwrap_or_else(|| available_parallelism().unwrap().get())
+            .max(1) as u32
+    }
+}
+
+/// Minimum size of the extranonce rolled by the device on extended channels.
+const MIN_EXTRANONCE_SIZE: u16 = 4;
+
+/// Bits of the version that can be rolled, as specified in BIP320.
+const VERSION_ROLLING_MASK: u32 = 0x1fff_e000;
+
+#[allow(clippy::too_many_arguments)]
 pub async fn connect(
     address: String,
     pub_key: Option<Secp256k1PublicKey>,
@@ -41,13 +111,68 @@ pub async fn connect(
     handicap: u32,
     nominal_hashrate_multiplier: Option<f32>,
     single_submit: bool,
+    channel_kind: ChannelKind,
+    options: MiningOptions,
 ) {
+    info!("Measuring CPU hashrate");
+    let measured_hashrate = measure_hashrate(5, handicap, options.threads()) as f32;
+    info!("Measured CPU hashrate is {}", measured_hashrate);
+    let nominal_hash_rate = match nominal_hashrate_multiplier {
+        Some(m) => measured_hashrate * m,
+        None => measured_hashrate,
+    };
+    let mut address = address;
+    // Address of the pool that sent the last `Reconnect`, used when the new one is unreachable
+    let mut previous: Option<String> = None;
+    loop {
+        let (receiver, sender, peer) = match connect_noise(&address, pub_key).await {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("Failed to connect to {}: {}", address, e);
+                match previous.take() {
+                    Some(previous) => {
+                        info!("Going back to {}", previous);
+                        address = previous;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+        };
+        let reconnect_to = Device::start(
+            receiver,
+            sender,
+            peer,
+            device_id.clone(),
+            user_id.clone(),
+            handicap,
+            nominal_hash_rate,
+            single_submit,
+            channel_kind,
+            options.clone(),
+        )
+        .await;
+        match reconnect_to {
+            Some(new_address) => {
+                info!("Reconnecting to {}", new_address);
+                previous = Some(std::mem::replace(&mut address, new_address));
+            }
+            None => break,
+        }
+    }
+}
+
+async fn connect_noise(
+    address: &str,
+    pub_key: Option<Secp256k1PublicKey>,
+) -> Result<(Receiver<EitherFrame>, Sender<EitherFrame>, SocketAddr), String> {
+    const INVALID_ADDRESS: &str =
+        "Invalid pool address, use one of this formats: ip:port, domain:port";
     let address = address
-        .clone()
         .to_socket_addrs()
-        .expect("Invalid pool address, use one of this formats: ip:port, domain:port")
+        .map_err(|e| format!("{}: {}", INVALID_ADDRESS, e))?
         .next()
-        .expect("Invalid pool address, use one of this formats: ip:port, domain:port");
+        .ok_or_else(|| INVALID_ADDRESS.to_string())?;
     info!("Connecting to pool at {}", address);
     let socket = loop {
         let pool = tokio::time::timeout(Duration::from_secs(5), TcpStream::connect(address)).await;
@@ -62,31 +187,35 @@ pub async fn connect(
                     tokio::time::sleep(Duration::from_secs(5)).await;
                 }
             },
-            Err(_) => {
-                error!("Pool is unresponsive, terminating");
-                std::process::exit(1);
-            }
+            Err(_) => return Err("Pool is unresponsive".to_string()),
         }
     };
     info!("Pool tcp connection established at {}", address);
-    let address = socket.peer_addr().unwrap();
+    let address = socket.peer_addr().map_err(|e| e.to_string())?;
     let initiator = Initiator::new(pub_key.map(|e| e.0));
     let (receiver, sender) =
         Connection::new(socket, codec_sv2::HandshakeRole::Initiator(initiator))
             .await
-            .unwrap();
+            .map_err(|e| format!("Noise handshake failed: {:?}", e))?;
     info!("Pool noise connection established at {}", address);
-    Device::start(
-        receiver,
-        sender,
-        address,
-        device_id,
-        user_id,
-        handicap,
-        nominal_hashrate_multiplier,
-        single_submit,
-    )
-    .await
+    Ok((receiver, sender, address))
+}
+
+// Address requested by a `Reconnect`, an empty host or a zero port mean the current ones
+fn reconnect_address(current: SocketAddr, m: &Reconnect) -> String {
+    let host = String::from_utf8_lossy(m.new_host.inner_as_ref()).to_string();
+    let port = match m.new_port {
+        0 => current.port(),
+        port => port,
+    };
+    if host.is_empty() {
+        SocketAddr::new(current.ip(), port).to_string()
+    } else {
+        match host.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, port).to_string(),
+            Err(_) => format!("{}:{}", host, port),
+        }
+    }
 }
 
 pub type Message = MiningDeviceMessages<'static>;
@@ -94,8 +223,6 @@ pub type StdFrame = StandardSv2Frame<Message>;
 pub type EitherFrame = StandardEitherFrame<Message>;
 
 struct SetupConnectionHandler {}
-use roles_logic_sv2::common_messages_sv2::Reconnect;
-use std::convert::TryInto;
 use stratum_common::bitcoin::block::Version;
 
 impl SetupConnectionHandler {
@@ -105,6 +232,7 @@ impl SetupConnectionHandler {
     fn get_setup_connection_message(
         address: SocketAddr,
         device_id: Option<String>,
+        flags: u32,
     ) -> SetupConnection<'static> {
         let endpoint_host = address.ip().to_string().into_bytes().try_into().unwrap();
         let vendor = String::new().try_into().unwrap();
@@ -119,7 +247,7 @@ impl SetupConnectionHandler {
             protocol: Protocol::MiningProtocol,
             min_version: 2,
             max_version: 2,
-            flags: 0b0000_0000_0000_0000_0000_0000_0000_0001,
+            flags,
             endpoint_host,
             endpoint_port: address.port(),
             vendor,
@@ -134,8 +262,9 @@ impl SetupConnectionHandler {
         sender: &mut Sender<EitherFrame>,
         device_id: Option<String>,
         address: SocketAddr,
+        flags: u32,
     ) {
-        let setup_connection = Self::get_setup_connection_message(address, device_id);
+        let setup_connection = Self::get_setup_connection_message(address, device_id, flags);
 
         let sv2_frame: StdFrame = MiningDeviceMessages::Common(setup_connection.into())
             .try_into()
@@ -194,6 +323,53 @@ struct NewWorkNotifier {
     sender: Sender<()>,
 }
 
+/// Job received on the channel, or on the group channel the channel belongs to.
+#[derive(Debug, Clone)]
+enum Job {
+    Standard(NewMiningJob<'static>),
+    Extended {
+        job: NewExtendedMiningJob<'static>,
+        // Extranonce prefix of the channel when the job was received
+        extranonce_prefix: Vec<u8>,
+    },
+}
+
+impl Job {
+    fn job_id(&self) -> u32 {
+        match self {
+            Job::Standard(job) => job.job_id,
+            Job::Extended { job, .. } => job.job_id,
+        }
+    }
+
+    fn is_future(&self) -> bool {
+        match self {
+            Job::Standard(job) => job.is_future(),
+            Job::Extended { job, .. } => job.is_future(),
+        }
+    }
+}
+
+/// Fields of the header given by a job, and the extranonce to submit with its shares.
+#[derive(Debug, Clone)]
+struct Work {
+    job_id: u32,
+    version: u32,
+    version_rolling_allowed: bool,
+    merkle_root: [u8; 32],
+    extranonce: Vec<u8>,
+}
+
+/// Share found by the mining threads.
+#[derive(Debug, Clone)]
+struct Share {
+    nonce: u32,
+    job_id: u32,
+    version: u32,
+    ntime: u32,
+    extranonce: Vec<u8>,
+}
+
 #[derive(Debug)]
 pub struct Device {
     #[allow(dead_code)]
@@ -202,38 +378,61 @@ pub struct Device {
     #[allow(dead_code)]
     channel_opened: bool,
     channel_id: Option<u32>,
+    channel_kind: ChannelKind,
+    group_channel_id: Option<u32>,
+    extranonce_prefix: Vec<u8>,
+    // Size of the part of the extranonce rolled by the device on extended channels
+    extranonce_size: usize,
+    extranonce_counter: u64,
+    // Set when the channel is closed, the device then disconnects
+    closed: bool,
+    fault: Option<Fault>,
+    fault_every: u32,
+    shares_until_fault: u32,
+    // Last job of the previous block, used to submit stale shares
+    stale_job_id: Option<u32>,
     miner: Arc<Mutex<Miner>>,
-    jobs: Vec<NewMiningJob<'static>>,
+    jobs: Vec<Job>,
     prev_hash: Option<SetNewPrevHash<'static>>,
     sequence_numbers: Id,
     notify_changes_to_mining_thread: NewWorkNotifier,
 }
 
 fn open_channel(
-    device_id: Option<String>,
-    nominal_hashrate_multiplier: Option<f32>,
-    handicap: u32,
-) -> OpenStandardMiningChannel<'static> {
-    let user_identity = device_id.unwrap_or_default().try_into().unwrap();
-    let id: u32 = 10;
-    info!("Measuring CPU hashrate");
-    let measured_hashrate = measure_hashrate(5, handicap) as f32;
-    info!("Measured CPU hashrate is {}", measured_hashrate);
-    let nominal_hash_rate = match nominal_hashrate_multiplier {
-        Some(m) => measured_hashrate * m,
-        None => measured_hashrate,
-    };
-
-    info!("MINING DEVICE: send open channel with request id {}", id);
-    OpenStandardMiningChannel {
-        request_id: id.into(),
-        user_identity,
-        nominal_hash_rate,
-        max_target: u256_from_int(567_u64),
+    request_id: u32,
+    user_id: Option<String>,
+    nominal_hash_rate: f32,
+    channel_kind: ChannelKind,
+) -> Mining<'static> {
+    let user_identity = user_id.unwrap_or_default().try_into().unwrap();
+    // The device accepts any target
+    let max_target = [0xff_u8; 32].into();
+    info!(
+        "MINING DEVICE: send open {:?} channel with request id {}",
+        channel_kind, request_id
+    );
+    match channel_kind {
+        ChannelKind::Standard | ChannelKind::Group => {
+            Mining::OpenStandardMiningChannel(OpenStandardMiningChannel {
+                request_id: request_id.into(),
+                user_identity,
+                nominal_hash_rate,
+                max_target,
+            })
+        }
+        ChannelKind::Extended => Mining::OpenExtendedMiningChannel(OpenExtendedMiningChannel {
+            request_id,
+            user_identity,
+            nominal_hash_rate,
+            max_target,
+            min_extranonce_size: MIN_EXTRANONCE_SIZE,
+        }),
     }
 }
 
 impl Device {
+    /// Sets up the connection, opens a channel and mines on it. Returns the address to reconnect
+    /// to when the upstream sends a `Reconnect`, `None` when the device disconnects.
     #[allow(clippy::too_many_arguments)]
     async fn start(
         mut receiver: Receiver<EitherFrame>,
@@ -242,9 +441,11 @@ impl Device {
         device_id: Option<String>,
         user_id: Option<String>,
         handicap: u32,
-        nominal_hashrate_multiplier: Option<f32>,
+        nominal_hash_rate: f32,
         single_submit: bool,
-    ) {
+        channel_kind: ChannelKind,
+        options: MiningOptions,
+    ) -> Option<String> {
         let setup_connection_handler = Arc::new(Mutex::new(SetupConnectionHandler::new()));
         SetupConnectionHandler::setup(
             setup_connection_handler,
@@ -252,10 +453,15 @@ impl Device {
             &mut sender,
             device_id,
             addr,
+            channel_kind.setup_connection_flags(),
         )
         .await;
         info!("Pool sv2 connection established at {}", addr);
-        let miner = Arc::new(Mutex::new(Miner::new(handicap)));
+        let miner = Arc::new(Mutex::new(Miner {
+            version_rolling: options.version_rolling,
+            ntime_rolling: options.ntime_rolling,
+            ..Miner::new(handicap)
+        }));
         let (notify_changes_to_mining_thread, update_miners) = async_channel::unbounded();
         let self_ = Self {
             channel_opened: false,
@@ -265,14 +471,28 @@ impl Device {
             jobs: Vec::new(),
             prev_hash: None,
             channel_id: None,
+            channel_kind,
+            group_channel_id: None,
+            extranonce_prefix: Vec::new(),
+            extranonce_size: 0,
+            extranonce_counter: 0,
+            closed: false,
+            fault: options.fault,
+            fault_every: options.fault_every.max(1),
+            shares_until_fault: options.fault_every.max(1),
+            stale_job_id: None,
             sequence_numbers: Id::new(),
             notify_changes_to_mining_thread: NewWorkNotifier {
-                should_send: true,
+                should_send: false,
                 sender: notify_changes_to_mining_thread,
             },
         };
-        let open_channel = MiningDeviceMessages::Mining(Mining::OpenStandardMiningChannel(
-            open_channel(user_id, nominal_hashrate_multiplier, handicap),
+        let request_id = thread_rng().gen();
+        let open_channel = MiningDeviceMessages::Mining(open_channel(
+            request_id,
+            user_id,
+            nominal_hash_rate,
+            channel_kind,
         ));
         let frame: StdFrame = open_channel.try_into().unwrap();
         self_.sender.send(frame.into()).await.unwrap();
@@ -281,38 +501,72 @@ impl Device {
 
         let (share_send, share_recv) = async_channel::unbounded();
 
-        start_mining_threads(update_miners, miner, share_send);
+        start_mining_threads(update_miners, miner, share_send, &options);
         tokio::task::spawn(async move {
-            let recv = share_recv.clone();
-            loop {
-                let (nonce, job_id, version, ntime) = recv.recv().await.unwrap();
-                Self::send_share(cloned.clone(), nonce, job_id, version, ntime).await;
+            while let Ok(share) = share_recv.recv().await {
+                Self::send_share(cloned.clone(), share).await;
                 if single_submit {
                     break;
                 }
             }
         });
 
-        loop {
-            let mut incoming: StdFrame = receiver.recv().await.unwrap().try_into().unwrap();
-            let message_type = incoming.get_header().unwrap().msg_type();
+        let reconnect_to = loop {
+            let mut incoming: StdFrame = match receiver.recv().await {
+                Ok(EitherFrame::Sv2(frame)) => frame,
+                Ok(EitherFrame::HandShake(_)) => {
+                    error!("Received an unexpected handshake frame");
+                    continue;
+                }
+                Err(_) => {
+                    error!("Connection with the pool at {} closed", addr);
+                    break None;
+                }
+            };
+            let message_type = match incoming.get_header() {
+                Some(header) => header.msg_type(),
+                None => {
+                    error!("Received a frame without header");
+                    continue;
+                }
+            };
             let payload = incoming.payload();
+            if message_type == const_sv2::MESSAGE_TYPE_RECONNECT {
+                match CommonMessages::try_from((message_type, payload)) {
+                    Ok(CommonMessages::Reconnect(m)) => {
+                        info!("Received Reconnect: {:?}", m);
+                        break Some(reconnect_address(addr, &m));
+                    }
+                    _ => {
+                        error!("Received an invalid Reconnect");
+                        continue;
+                    }
+                }
+            }
             let next =
-                Device::handle_message_mining(self_mutex.clone(), message_type, payload).unwrap();
-            let mut notify_changes_to_mining_thread = self_mutex
-                .safe_lock(|s| s.notify_changes_to_mining_thread.clone())
+                match Device::handle_message_mining(self_mutex.clone(), message_type, payload) {
+                    Ok(next) => next,
+                    Err(e) => {
+                        error!("Failed to handle message {}: {:?}", message_type, e);
+                        continue;
+                    }
+                };
+            let (notify_changes_to_mining_thread, closed) = self_mutex
+                .safe_lock(|s| {
+                    let notifier = s.notify_changes_to_mining_thread.clone();
+                    s.notify_changes_to_mining_thread.should_send = false;
+                    (notifier, s.closed)
+                })
                 .unwrap();
-            if notify_changes_to_mining_thread.should_send
-                && (message_type == const_sv2::MESSAGE_TYPE_NEW_MINING_JOB
-                    || message_type == const_sv2::MESSAGE_TYPE_SET_NEW_PREV_HASH
-                    || message_type == const_sv2::MESSAGE_TYPE_SET_TARGET)
-            {
+            if closed {
+                break None;
+            }
+            if notify_changes_to_mining_thread.should_send {
                 notify_changes_to_mining_thread
                     .sender
                     .send(())
                     .await
                     .unwrap();
-                notify_changes_to_mining_thread.should_send = false;
             };
             match next {
                 SendTo::RelayNewMessageToRemote(_, m) => {
@@ -321,30 +575,160 @@ impl Device {
                     sender.send(either_frame).await.unwrap();
                 }
                 SendTo::None(_) => (),
-                _ => panic!(),
+                other => error!("Unexpected result of the message handler: {:?}", other),
+            }
+        };
+        // Stops the mining threads, which in turn stops the task sending the shares
+        self_mutex
+            .safe_lock(|s| s.notify_changes_to_mining_thread.sender.close())
+            .unwrap();
+        sender.close();
+        reconnect_to
+    }
+
+    async fn send_share(self_mutex: Arc<Mutex<Self>>, mut share: Share) {
+        let (copies, sender) = self_mutex
+            .safe_lock(|s| (s.inject_fault(&mut share), s.sender.clone()))
+            .unwrap();
+        for _ in 0..copies {
+            let submit = self_mutex.safe_lock(|s| s.submit_shares(&share)).unwrap();
+            let frame: StdFrame = MiningDeviceMessages::Mining(submit).try_into().unwrap();
+            if sender.send(frame.into()).await.is_err() {
+                debug!("Share found after the connection was closed");
+                return;
             }
         }
     }
 
-    async fn send_share(
-        self_mutex: Arc<Mutex<Self>>,
-        nonce: u32,
-        job_id: u32,
-        version: u32,
-        ntime: u32,
-    ) {
-        let share =
-            MiningDeviceMessages::Mining(Mining::SubmitSharesStandard(SubmitSharesStandard {
-                channel_id: self_mutex.safe_lock(|s| s.channel_id.unwrap()).unwrap(),
-                sequence_number: self_mutex.safe_lock(|s| s.sequence_numbers.next()).unwrap(),
-                job_id,
-                nonce,
-                ntime,
-                version,
-            }));
-        let frame: StdFrame = share.try_into().unwrap();
-        let sender = self_mutex.safe_lock(|s| s.sender.clone()).unwrap();
-        sender.send(frame.into()).await.unwrap();
+    fn submit_shares(&mut self, share: &Share) -> Mining<'static> {
+        let channel_id = self.channel_id.unwrap();
+        let sequence_number = self.sequence_numbers.next();
+        match self.channel_kind {
+            ChannelKind::Standard | ChannelKind::Group => {
+                Mining::SubmitSharesStandard(SubmitSharesStandard {
+                    channel_id,
+                    sequence_number,
+                    job_id: share.job_id,
+                    nonce: share.nonce,
+                    ntime: share.ntime,
+                    version: share.version,
+                })
+            }
+            ChannelKind::Extended => Mining::SubmitSharesExtended(SubmitSharesExtended {
+                channel_id,
+                sequence_number,
+                job_id: share.job_id,
+                nonce: share.nonce,
+                ntime: share.ntime,
+                version: share.version,
+                extranonce: share.extranonce.clone().try_into().unwrap(),
+            }),
+        }
+    }
+
+    /// Alters `share` with the configured fault when it is due, returns the number of times the
+    /// share has to be submitted.
+    fn inject_fault(&mut self, share: &mut Share) -> usize {
+        self.shares_until_fault = self.shares_until_fault.saturating_sub(1);
+        let fault = match self.fault {
+            Some(fault) if self.shares_until_fault == 0 => fault,
+            _ => return 1,
+        };
+        self.shares_until_fault = self.fault_every;
+        info!(
+            "Injecting fault {:?} in share for job {}",
+            fault, share.job_id
+        );
+        match fault {
+            Fault::Stale => match self.stale_job_id {
+                Some(job_id) => share.job_id = job_id,
+                None => info!("No job of a previous block yet, the share is not stale"),
+            },
+            Fault::BadNonce => share.nonce = share.nonce.wrapping_add(1),
+            Fault::WrongJobId => share.job_id = share.job_id.wrapping_add(u32::MAX / 2),
+            Fault::Duplicate => return 2,
+        }
+        1
+    }
+
+    /// Next part of the extranonce rolled by the device, empty on standard channels.
+    fn next_extranonce(&mut self) -> Vec<u8> {
+        if self.channel_kind != ChannelKind::Extended {
+            return Vec::new();
+        }
+        let mut extranonce = self.extranonce_counter.to_le_bytes().to_vec();
+        extranonce.resize(self.extranonce_size, 0);
+        self.extranonce_counter = self.extranonce_counter.wrapping_add(1);
+        extranonce
+    }
+
+    fn work(&mut self, job: &Job) -> Option<Work> {
+        match job {
+            Job::Standard(job) => Some(Work {
+                job_id: job.job_id,
+                version: job.version,
+                // The BIP320 bits of standard jobs can always be rolled
+                version_rolling_allowed: true,
+                merkle_root: job.merkle_root.to_vec().try_into().unwrap(),
+                extranonce: Vec::new(),
+            }),
+            Job::Extended {
+                job,
+                extranonce_prefix,
+            } => {
+                let extranonce = self.next_extranonce();
+                let mut full_extranonce = extranonce_prefix.clone();
+                full_extranonce.extend_from_slice(&extranonce);
+                let merkle_root = merkle_root_from_path(
+                    job.coinbase_tx_prefix.inner_as_ref(),
+                    job.coinbase_tx_suffix.inner_as_ref(),
+                    &full_extranonce,
+                    &job.merkle_path.inner_as_ref(),
+                )?;
+                Some(Work {
+                    job_id: job.job_id,
+                    version: job.version,
+                    version_rolling_allowed: job.version_rolling_allowed,
+                    merkle_root: merkle_root.try_into().ok()?,
+                    extranonce,
+                })
+            }
+        }
+    }
+
+    // Starts mining on `job`
+    fn activate(&mut self, prev_hash: &SetNewPrevHash, job: &Job) {
+        match self.work(job) {
+            Some(work) => {
+                self.miner
+                    .safe_lock(|miner| miner.new_header(prev_hash, work))
+                    .unwrap();
+                self.notify_changes_to_mining_thread.should_send = true;
+            }
+            None => error!("Invalid coinbase in job {}", job.job_id()),
+        }
+    }
+
+    fn new_job(&mut self, job: Job) -> Result<(), Error> {
+        match (job.is_future(), self.prev_hash.clone()) {
+            (false, Some(p_h)) => {
+                self.activate(&p_h, &job);
+                self.jobs = vec![job];
+            }
+            (true, _) => {
+                // A future job replaces the one with the same id, so that a prev hash always
+                // matches a single job
+                self.jobs.retain(|j| j.job_id() != job.job_id());
+                self.jobs.push(job);
+            }
+            (false, None) => return Err(Error::JobIsNotFutureButPrevHashNotPresent),
+        }
+        Ok(())
+    }
+
+    // Whether the messages sent to `channel_id` are meant for the device
+    fn is_for_device(&self, channel_id: u32) -> bool {
+        Some(channel_id) == self.channel_id || Some(channel_id) == self.group_channel_id
     }
 }
 
@@ -378,6 +762,9 @@ impl IsMiningUpstream<()> for Device {
     fn add_hash_rate(&mut self, _to_add: u64) {
         todo!()
     }
+    fn remove_hash_rate(&mut self, _to_remove: u64) {
+        todo!()
+    }
     fn get_opened_channels(
         &mut self,
     ) -> &mut Vec<roles_logic_sv2::common_properties::UpstreamChannel> {
@@ -391,7 +778,11 @@ impl IsMiningUpstream<()> for Device {
 
 impl ParseMiningMessagesFromUpstream<()> for Device {
     fn get_channel_type(&self) -> SupportedChannelTypes {
-        SupportedChannelTypes::Standard
+        match self.channel_kind {
+            ChannelKind::Standard => SupportedChannelTypes::Standard,
+            ChannelKind::Group => SupportedChannelTypes::Group,
+            ChannelKind::Extended => SupportedChannelTypes::GroupAndExtended,
+        }
     }
 
     fn is_work_selection_enabled(&self) -> bool {
@@ -404,6 +795,8 @@ impl ParseMiningMessagesFromUpstream<()> for Device {
     ) -> Result<SendTo<()>, Error> {
         self.channel_opened = true;
         self.channel_id = Some(m.channel_id);
+        self.group_channel_id = Some(m.group_channel_id);
+        self.extranonce_prefix = m.extranonce_prefix.to_vec();
         let req_id = m.get_request_id_as_u32();
         info!(
             "MINING DEVICE: channel opened with: group id {}, channel id {}, request id {}",
@@ -418,31 +811,69 @@ impl ParseMiningMessagesFromUpstream<()> for Device {
 
     fn handle_open_extended_mining_channel_success(
         &mut self,
-        _: OpenExtendedMiningChannelSuccess,
+        m: OpenExtendedMiningChannelSuccess,
     ) -> Result<SendTo<()>, Error> {
-        unreachable!()
+        self.channel_opened = true;
+        self.channel_id = Some(m.channel_id);
+        self.extranonce_prefix = m.extranonce_prefix.to_vec();
+        self.extranonce_size = m.extranonce_size as usize;
+        info!(
+            "MINING DEVICE: extended channel opened with: channel id {}, request id {}, extranonce size {}",
+            m.channel_id, m.request_id, m.extranonce_size
+        );
+        self.miner
+            .safe_lock(|miner| miner.new_target(m.target.to_vec()))
+            .unwrap();
+        self.notify_changes_to_mining_thread.should_send = true;
+        Ok(SendTo::None(None))
     }
 
     fn handle_open_mining_channel_error(
         &mut self,
-        _: OpenMiningChannelError,
+        m: OpenMiningChannelError,
     ) -> Result<SendTo<()>, Error> {
-        todo!()
+        error!(
+            "Failed to open channel with request id {}: {}",
+            m.request_id,
+            std::str::from_utf8(m.error_code.as_ref()).unwrap_or("unknown error code")
+        );
+        self.closed = true;
+        Ok(SendTo::None(None))
     }
 
-    fn handle_update_channel_error(&mut self, _: UpdateChannelError) -> Result<SendTo<()>, Error> {
-        todo!()
+    fn handle_update_channel_error(&mut self, m: UpdateChannelError) -> Result<SendTo<()>, Error> {
+        error!(
+            "Received UpdateChannelError for channel id {} with error code {}",
+            m.channel_id,
+            std::str::from_utf8(m.error_code.as_ref()).unwrap_or("unknown error code")
+        );
+        Ok(SendTo::None(None))
     }
 
-    fn handle_close_channel(&mut self, _: CloseChannel) -> Result<SendTo<()>, Error> {
-        todo!()
+    fn handle_close_channel(&mut self, m: CloseChannel) -> Result<SendTo<()>, Error> {
+        info!(
+            "Received CloseChannel for channel id {} with reason {}",
+            m.channel_id,
+            std::str::from_utf8(m.reason_code.as_ref()).unwrap_or("unknown reason")
+        );
+        if self.is_for_device(m.channel_id) {
+            self.closed = true;
+        }
+        Ok(SendTo::None(None))
     }
 
     fn handle_set_extranonce_prefix(
         &mut self,
-        _: SetExtranoncePrefix,
+        m: SetExtranoncePrefix,
     ) -> Result<SendTo<()>, Error> {
-        todo!()
+        info!(
+            "Received SetExtranoncePrefix for channel id {}",
+            m.channel_id
+        );
+        debug!("SetExtranoncePrefix: {:?}", m);
+        // The new prefix is used for the jobs received from now on
+        self.extranonce_prefix = m.extranonce_prefix.to_vec();
+        Ok(SendTo::None(None))
     }
 
     fn handle_submit_shares_success(
@@ -470,27 +901,30 @@ impl ParseMiningMessagesFromUpstream<()> for Device {
             m.is_future()
         );
         debug!("NewMiningJob: {:?}", m);
-        match (m.is_future(), self.prev_hash.as_ref()) {
-            (false, Some(p_h)) => {
-                self.miner
-                    .safe_lock(|miner| miner.new_header(p_h, &m))
-                    .unwrap();
-                self.jobs = vec![m.as_static()];
-                self.notify_changes_to_mining_thread.should_send = true;
-            }
-            (true, _) => self.jobs.push(m.as_static()),
-            (false, None) => {
-                panic!()
-            }
-        }
+        self.new_job(Job::Standard(m.as_static()))?;
         Ok(SendTo::None(None))
     }
 
     fn handle_new_extended_mining_job(
         &mut self,
-        _: NewExtendedMiningJob,
+        m: NewExtendedMiningJob,
     ) -> Result<SendTo<()>, Error> {
-        todo!()
+        info!(
+            "Received new extended mining job for channel id: {} with job id: {} is future: {}",
+            m.channel_id,
+            m.job_id,
+            m.is_future()
+        );
+        debug!("NewExtendedMiningJob: {:?}", m);
+        if !self.is_for_device(m.channel_id) {
+            info!("Ignoring job for channel id {}", m.channel_id);
+            return Ok(SendTo::None(None));
+        }
+        self.new_job(Job::Extended {
+            job: m.as_static(),
+            extranonce_prefix: self.extranonce_prefix.clone(),
+        })?;
+        Ok(SendTo::None(None))
     }
 
     fn handle_set_new_prev_hash(&mut self, m: SetNewPrevHash) -> Result<SendTo<()>, Error> {
@@ -499,25 +933,18 @@ impl ParseMiningMessagesFromUpstream<()> for Device {
             m.channel_id, m.job_id
         );
         debug!("SetNewPrevHash: {:?}", m);
-        let jobs: Vec<&NewMiningJob<'static>> = self
+        let job = self
             .jobs
             .iter()
-            .filter(|j| j.job_id == m.job_id && j.is_future())
-            .collect();
-        match jobs.len() {
-            0 => {
-                self.prev_hash = Some(m.as_static());
-            }
-            1 => {
-                self.miner
-                    .safe_lock(|miner| miner.new_header(&m, jobs[0]))
-                    .unwrap();
-                self.jobs = vec![jobs[0].clone()];
-                self.prev_hash = Some(m.as_static());
-                self.notify_changes_to_mining_thread.should_send = true;
-            }
-            _ => panic!(),
+            .rev()
+            .find(|j| j.job_id() == m.job_id && j.is_future())
+            .cloned();
+        if let Some(job) = job {
+            self.stale_job_id = self.miner.safe_lock(|miner| miner.job_id).unwrap();
+            self.activate(&m, &job);
+            self.jobs = vec![job];
         }
+        self.prev_hash = Some(m.as_static());
         Ok(SendTo::None(None))
     }
 
@@ -545,8 +972,22 @@ impl ParseMiningMessagesFromUpstream<()> for Device {
         Ok(SendTo::None(None))
     }
 
-    fn handle_set_group_channel(&mut self, _m: SetGroupChannel) -> Result<SendTo<()>, Error> {
-        todo!()
+    fn handle_set_group_channel(&mut self, m: SetGroupChannel) -> Result<SendTo<()>, Error> {
+        info!(
+            "Received SetGroupChannel for group channel id {}",
+            m.group_channel_id
+        );
+        debug!("SetGroupChannel: {:?}", m);
+        let group_channel_id = m.group_channel_id;
+        let in_group = self
+            .channel_id
+            .is_some_and(|id| m.channel_ids.into_inner().contains(&id));
+        if in_group {
+            self.group_channel_id = Some(group_channel_id);
+        } else if self.group_channel_id == Some(group_channel_id) {
+            self.group_channel_id = None;
+        }
+        Ok(SendTo::None(None))
     }
 }
 
@@ -555,8 +996,11 @@ struct Miner {
     header: Option<Header>,
     target: Option<U256>,
     job_id: Option<u32>,
-    version: Option<u32>,
+    extranonce: Vec<u8>,
     handicap: u32,
+    version_rolling: bool,
+    version_rolling_allowed: bool,
+    ntime_rolling: bool,
 }
 
 impl Miner {
@@ -565,8 +1009,11 @@ impl Miner {
             target: None,
             header: None,
             job_id: None,
-            version: None,
+            extranonce: Vec::new(),
             handicap,
+            version_rolling: false,
+            version_rolling_allowed: false,
+            ntime_rolling: false,
         }
     }
 
@@ -580,17 +1027,17 @@ impl Miner {
         self.target = Some(U256::from_little_endian(target.as_slice()));
     }
 
-    fn new_header(&mut self, set_new_prev_hash: &SetNewPrevHash, new_job: &NewMiningJob) {
-        self.job_id = Some(new_job.job_id);
-        self.version = Some(new_job.version);
+    fn new_header(&mut self, set_new_prev_hash: &SetNewPrevHash, work: Work) {
+        self.job_id = Some(work.job_id);
+        self.version_rolling_allowed = work.version_rolling_allowed;
+        self.extranonce = work.extranonce;
         let prev_hash: [u8; 32] = set_new_prev_hash.prev_hash.to_vec().try_into().unwrap();
         let prev_hash = Hash::from_byte_array(prev_hash);
-        let merkle_root: [u8; 32] = new_job.merkle_root.to_vec().try_into().unwrap();
-        let merkle_root = Hash::from_byte_array(merkle_root);
+        let merkle_root = Hash::from_byte_array(work.merkle_root);
         // fields need to be added as BE and the are converted to LE in the background before
         // hashing
         let header = Header {
-            version: Version::from_consensus(new_job.version as i32),
+            version: Version::from_consensus(work.version as i32),
             prev_blockhash: BlockHash::from_raw_hash(prev_hash),
             merkle_root,
             time: std::time::SystemTime::now()
@@ -604,6 +1051,50 @@ impl Miner {
         };
         self.header = Some(header);
     }
+
+    // Called when a mining thread exhausted its nonce range
+    fn roll(&mut self) {
+        let version_rolling = self.version_rolling && self.version_rolling_allowed;
+        let ntime_rolling = self.ntime_rolling;
+        if let Some(header) = self.header.as_mut() {
+            if version_rolling {
+                let version = header.version.to_consensus() as u32;
+                // Increments the masked bits, carrying through the other ones
+                let rolled =
+                    (version | !VERSION_ROLLING_MASK).wrapping_add(1) & VERSION_ROLLING_MASK;
+                header.version =
+                    Version::from_consensus(((version & !VERSION_ROLLING_MASK) | rolled) as i32);
+                if rolled != 0 {
+                    return;
+                }
+            }
+            if ntime_rolling {
+                header.time = header.time.wrapping_add(1);
+            }
+        }
+    }
+
+    fn share(&self) -> Option<Share> {
+        let header = self.header.as_ref()?;
+        Some(Share {
+            nonce: header.nonce,
+            job_id: self.job_id?,
+            version: header.version.to_consensus() as u32,
+            ntime: header.time,
+            extranonce: self.extranonce.clone(),
+        })
+    }
+
+    // Share for the current nonce without hashing, the nonce is then incremented
+    fn fake_share(&mut self) -> Option<Share> {
+        self.target?;
+        let share = self.share()?;
+        self.header
+            .as_mut()
+            .map(|h| h.nonce = h.nonce.wrapping_add(1));
+        Some(share)
+    }
+
     pub fn next_share(&mut self) -> NextShareOutcome {
         if let Some(header) = self.header.as_ref() {
             let hash_ = header.block_hash();
@@ -652,7 +1143,7 @@ impl NextShareOutcome {
 }
 
 // returns hashrate based on how fast the device hashes over the given duration
-fn measure_hashrate(duration_secs: u64, handicap: u32) -> f64 {
+fn measure_hashrate(duration_secs: u64, handicap: u32, threads: u32) -> f64 {
     let mut rng = thread_rng();
     let prev_hash: [u8; 32] = generate_random_32_byte_array().to_vec().try_into().unwrap();
     let prev_hash = Hash::from_byte_array(prev_hash);
@@ -688,10 +1179,8 @@ fn measure_hashrate(duration_secs: u64, handicap: u32) -> f64 {
     let elapsed_secs = start_time.elapsed().as_secs_f64();
     let hashrate_single_thread = hashes as f64 / elapsed_secs;
 
-    // we just measured for a single thread, need to multiply by the available parallelism
-    let p = available_parallelism().unwrap().get();
-
-    hashrate_single_thread * p as f64
+    // we just measured for a single thread, need to multiply by the number of mining threads
+    hashrate_single_thread * threads as f64
 }
 fn generate_random_32_byte_array() -> [u8; 32] {
     let mut rng = thread_rng();
@@ -703,72 +1192,281 @@ fn generate_random_32_byte_array() -> [u8; 32] {
 fn start_mining_threads(
     have_new_job: Receiver<()>,
     miner: Arc<Mutex<Miner>>,
-    share_send: Sender<(u32, u32, u32, u32)>,
+    share_send: Sender<Share>,
+    options: &MiningOptions,
 ) {
+    if let Some(shares_per_minute) = options.fake_shares_per_minute {
+        tokio::task::spawn(fake_solve(
+            have_new_job,
+            miner,
+            share_send,
+            shares_per_minute,
+        ));
+        return;
+    }
+    let p = options.threads();
     tokio::task::spawn(async move {
         let mut killers: Vec<Arc<AtomicBool>> = vec![];
-        loop {
-            let p = available_parallelism().unwrap().get() as u32;
-            let unit = u32::MAX / p;
-            while have_new_job.recv().await.is_ok() {
-                while let Some(killer) = killers.pop() {
-                    killer.store(true, Ordering::Relaxed);
-                }
-                let miner = miner.safe_lock(|m| m.clone()).unwrap();
-                for i in 0..p {
-                    let mut miner = miner.clone();
-                    let share_send = share_send.clone();
-                    let killer = Arc::new(AtomicBool::new(false));
-                    miner.header.as_mut().map(|h| h.nonce = i * unit);
-                    killers.push(killer.clone());
-                    std::thread::spawn(move || {
-                        mine(miner, share_send, killer);
-                    });
-                }
+        let unit = u32::MAX / p;
+        while have_new_job.recv().await.is_ok() {
+            while let Some(killer) = killers.pop() {
+                killer.store(true, Ordering::Relaxed);
             }
+            let miner = miner.safe_lock(|m| m.clone()).unwrap();
+            for i in 0..p {
+                let mut miner = miner.clone();
+                let share_send = share_send.clone();
+                let killer = Arc::new(AtomicBool::new(false));
+                miner.header.as_mut().map(|h| h.nonce = i * unit);
+                killers.push(killer.clone());
+                std::thread::spawn(move || {
+                    mine(miner, share_send, killer, i * unit, unit);
+                });
+            }
+        }
+        // The device disconnected
+        while let Some(killer) = killers.pop() {
+            killer.store(true, Ordering::Relaxed);
         }
     });
 }
 
-fn mine(mut miner: Miner, share_send: Sender<(u32, u32, u32, u32)>, kill: Arc<AtomicBool>) {
-    if miner.handicap != 0 {
-        loop {
-            if kill.load(Ordering::Relaxed) {
-                break;
-            }
+// Mines the `nonces` nonces starting at `first_nonce`, rolling the header when they are exhausted
+fn mine(
+    mut miner: Miner,
+    share_send: Sender<Share>,
+    kill: Arc<AtomicBool>,
+    first_nonce: u32,
+    nonces: u32,
+) {
+    let mut hashed: u32 = 0;
+    loop {
+        if kill.load(Ordering::Relaxed) {
+            break;
+        }
+        if miner.handicap != 0 {
             std::thread::sleep(std::time::Duration::from_micros(miner.handicap.into()));
-            if miner.next_share().is_valid() {
-                let nonce = miner.header.unwrap().nonce;
-                let time = miner.header.unwrap().time;
-                let job_id = miner.job_id.unwrap();
-                let version = miner.version;
-                share_send
-                    .try_send((nonce, job_id, version.unwrap(), time))
-                    .unwrap();
+        }
+        if miner.next_share().is_valid() {
+            if let Some(share) = miner.share() {
+                if share_send.try_send(share).is_err() {
+                    break;
+                }
             }
+        }
+        hashed = hashed.wrapping_add(1);
+        if hashed == nonces {
+            hashed = 0;
+            miner.header.as_mut().map(|h| h.nonce = first_nonce);
+            miner.roll();
+        } else {
             miner
                 .header
                 .as_mut()
                 .map(|h| h.nonce = h.nonce.wrapping_add(1));
         }
-    } else {
-        loop {
-            if miner.next_share().is_valid() {
-                if kill.load(Ordering::Relaxed) {
+    }
+}
+
+// Submits shares at a fixed rate without hashing, until the device disconnects
+async fn fake_solve(
+    have_new_job: Receiver<()>,
+    miner: Arc<Mutex<Miner>>,
+    share_send: Sender<Share>,
+    shares_per_minute: f32,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs_f32(60.0 / shares_per_minute));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            new_job = have_new_job.recv() => {
+                if new_job.is_err() {
                     break;
                 }
-                let nonce = miner.header.unwrap().nonce;
-                let time = miner.header.unwrap().time;
-                let job_id = miner.job_id.unwrap();
-                let version = miner.version;
-                share_send
-                    .try_send((nonce, job_id, version.unwrap(), time))
-                    .unwrap();
             }
-            miner
-                .header
-                .as_mut()
-                .map(|h| h.nonce = h.nonce.wrapping_add(1));
+            _ = interval.tick() => {
+                if let Some(share) = miner.safe_lock(|m| m.fake_share()).unwrap() {
+                    info!("Submitting fake share with nonce: {}", share.nonce);
+                    if share_send.send(share).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binary_sv2::Sv2Option;
+
+    fn miner_with_version(version: u32, version_rolling: bool, ntime_rolling: bool) -> Miner {
+        let mut miner = Miner {
+            version_rolling,
+            version_rolling_allowed: true,
+            ntime_rolling,
+            ..Miner::new(0)
+        };
+        miner.header = Some(Header {
+            version: Version::from_consensus(version as i32),
+            prev_blockhash: BlockHash::from_raw_hash(Hash::from_byte_array([0; 32])),
+            merkle_root: Hash::from_byte_array([0; 32]),
+            time: 1000,
+            bits: CompactTarget::from_consensus(0),
+            nonce: 0,
+        });
+        miner
+    }
+
+    #[test]
+    fn roll_version_then_ntime() {
+        let mut miner = miner_with_version(0x2000_0000, true, true);
+        miner.roll();
+        let header = miner.header.unwrap();
+        assert_eq!(header.version.to_consensus() as u32, 0x2000_2000);
+        assert_eq!(header.time, 1000);
+
+        // Once the rollable bits are exhausted they wrap and the ntime is rolled
+        let mut miner = miner_with_version(0x3fff_e000, true, true);
+        miner.roll();
+        let header = miner.header.unwrap();
+        assert_eq!(header.version.to_consensus() as u32, 0x2000_0000);
+        assert_eq!(header.time, 1001);
+
+        let mut miner = miner_with_version(0x2000_0000, false, true);
+        miner.version_rolling_allowed = false;
+        miner.roll();
+        let header = miner.header.unwrap();
+        assert_eq!(header.version.to_consensus() as u32, 0x2000_0000);
+        assert_eq!(header.time, 1001);
+    }
+
+    fn device() -> Device {
+        let (sender, receiver) = async_channel::unbounded();
+        let (notifier, _) = async_channel::unbounded();
+        Device {
+            receiver,
+            sender,
+            channel_opened: true,
+            channel_id: Some(1),
+            channel_kind: ChannelKind::Standard,
+            group_channel_id: None,
+            extranonce_prefix: Vec::new(),
+            extranonce_size: 0,
+            extranonce_counter: 0,
+            closed: false,
+            fault: None,
+            fault_every: 1,
+            shares_until_fault: 1,
+            stale_job_id: None,
+            miner: Arc::new(Mutex::new(Miner::new(0))),
+            jobs: Vec::new(),
+            prev_hash: None,
+            sequence_numbers: Id::new(),
+            notify_changes_to_mining_thread: NewWorkNotifier {
+                should_send: false,
+                sender: notifier,
+            },
+        }
+    }
+
+    fn job(job_id: u32, version: u32, future: bool) -> NewMiningJob<'static> {
+        NewMiningJob {
+            channel_id: 1,
+            job_id,
+            min_ntime: Sv2Option::new((!future).then_some(1000)),
+            version,
+            merkle_root: [0; 32].into(),
+        }
+    }
+
+    fn prev_hash(job_id: u32) -> SetNewPrevHash<'static> {
+        SetNewPrevHash {
+            channel_id: 1,
+            job_id,
+            prev_hash: [0; 32].into(),
+            min_ntime: 1000,
+            nbits: 0,
         }
     }
+
+    fn reconnect(host: &str, port: u16) -> Reconnect<'static> {
+        Reconnect {
+            new_host: host.to_string().try_into().unwrap(),
+            new_port: port,
+        }
+    }
+
+    #[test]
+    fn reconnect_address_defaults_to_the_current_one() {
+        let current: SocketAddr = "10.0.0.1:34255".parse().unwrap();
+        assert_eq!(
+            reconnect_address(current, &reconnect("", 0)),
+            "10.0.0.1:34255"
+        );
+        assert_eq!(
+            reconnect_address(current, &reconnect("", 4444)),
+            "10.0.0.1:4444"
+        );
+        assert_eq!(
+            reconnect_address(current, &reconnect("10.0.0.2", 0)),
+            "10.0.0.2:34255"
+        );
+        assert_eq!(
+            reconnect_address(current, &reconnect("::1", 4444)),
+            "[::1]:4444"
+        );
+        assert_eq!(
+            reconnect_address(current, &reconnect("pool.example.com", 4444)),
+            "pool.example.com:4444"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_address_is_an_error() {
+        assert!(connect_noise("not an address", None).await.is_err());
+        assert!(connect_noise("127.0.0.1", None).await.is_err());
+    }
+
+    #[test]
+    fn job_without_prev_hash_is_an_error() {
+        let mut device = device();
+        assert!(matches!(
+            device.handle_new_mining_job(job(1, 0x2000_0000, false)),
+            Err(Error::JobIsNotFutureButPrevHashNotPresent)
+        ));
+        assert!(device.jobs.is_empty());
+    }
+
+    #[test]
+    fn prev_hash_activates_the_last_future_job_with_its_id() {
+        let mut device = device();
+        device
+            .handle_new_mining_job(job(1, 0x2000_0000, true))
+            .unwrap();
+        device
+            .handle_new_mining_job(job(1, 0x2000_4000, true))
+            .unwrap();
+        device
+            .handle_new_mining_job(job(2, 0x2000_0000, true))
+            .unwrap();
+        assert_eq!(device.jobs.len(), 2);
+
+        device.handle_set_new_prev_hash(prev_hash(1)).unwrap();
+        assert_eq!(device.jobs.len(), 1);
+        let header = device
+            .miner
+            .safe_lock(|miner| miner.header.unwrap())
+            .unwrap();
+        assert_eq!(header.version.to_consensus() as u32, 0x2000_4000);
+
+        // A prev hash of an unknown job is kept for the next jobs
+        device.handle_set_new_prev_hash(prev_hash(3)).unwrap();
+        assert_eq!(device.prev_hash.as_ref().unwrap().job_id, 3);
+        device
+            .handle_new_mining_job(job(4, 0x2000_0000, false))
+            .unwrap();
+        assert_eq!(device.jobs.len(), 1);
+    }
 }
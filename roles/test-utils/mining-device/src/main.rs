@@ -47,6 +47,53 @@ struct Args {
          \nIf empty, the CPU miner will simply advertise its real capacity."
     )]
     nominal_hashrate_multiplier: Option<f32>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Kind of channel opened with the upstream",
+        default_value = "standard"
+    )]
+    channel: lib::ChannelKind,
+    #[arg(
+        long,
+        help = "Number of mining threads splitting the nonce space, defaults to the available parallelism"
+    )]
+    threads: Option<usize>,
+    #[arg(
+        long,
+        help = "Roll the version bits allowed by BIP320 once a thread exhausted its nonce range"
+    )]
+    version_rolling: bool,
+    #[arg(
+        long,
+        help = "Roll the ntime once a thread exhausted its nonce range (and the version bits, if rolled)"
+    )]
+    ntime_rolling: bool,
+    #[arg(
+        long,
+        value_parser = parse_rate,
+        help = "Do not hash, submit shares at this rate instead. The shares are very unlikely to meet the target"
+    )]
+    fake_shares_per_minute: Option<f32>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Fault injected in the submitted shares, to exercise the error paths of the upstream"
+    )]
+    fault: Option<lib::Fault>,
+    #[arg(
+        long,
+        help = "Inject the fault in one share every N shares",
+        default_value = "1"
+    )]
+    fault_every: u32,
+}
+
+fn parse_rate(rate: &str) -> Result<f32, String> {
+    match rate.parse::<f32>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("{} is not a positive number", rate)),
+    }
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -62,6 +109,15 @@ async fn main() {
         args.handicap,
         args.nominal_hashrate_multiplier,
         false,
+        args.channel,
+        lib::MiningOptions {
+            threads: args.threads,
+            version_rolling: args.version_rolling,
+            ntime_rolling: args.ntime_rolling,
+            fake_shares_per_minute: args.fake_shares_per_minute,
+            fault: args.fault,
+            fault_every: args.fault_every,
+        },
     )
     .await;
 }
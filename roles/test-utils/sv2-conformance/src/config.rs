@@ -0,0 +1,37 @@
+use clap::Parser;
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+pub struct Config {
+    #[arg(
+        short,
+        long,
+        help = "Address of the role under test in this format ip:port or domain:port"
+    )]
+    pub address: String,
+    #[arg(
+        short,
+        long,
+        help = "Authority public key of the role under test, when left empty its certificate is not checked"
+    )]
+    pub pubkey: Option<Secp256k1PublicKey>,
+    #[arg(
+        short,
+        long,
+        help = "Authority secret key matching the public key, used to play the upstream in the scripts with side = \"upstream\""
+    )]
+    pub secret_key: Option<Secp256k1SecretKey>,
+    #[arg(
+        short,
+        long,
+        help = "Only run the scripts written for this role, e.g. pool"
+    )]
+    pub role: Option<String>,
+    #[arg(
+        required = true,
+        help = "Scripts to run, directories are searched recursively for .toml and .json scripts"
+    )]
+    pub scripts: Vec<PathBuf>,
+}
@@ -0,0 +1,67 @@
+//! Declarative conformance tests for SV2 roles.
+//!
+//! Each test is a [`script::Script`] describing the messages to send to the role under test and
+//! the messages it is expected to answer with. The runner connects to the role like any SV2
+//! downstream would, so the same scripts can be run against this project's roles and against
+//! external implementations. A suite of cases checking the behavior required by the specification
+//! is shipped in the `cases` directory, one directory per role.
+pub mod config;
+pub mod matcher;
+pub mod runner;
+pub mod script;
+
+pub use config::Config;
+pub use runner::{run_script, Failure, Target};
+pub use script::{Script, Side};
+
+/// Runs every script found in `config.scripts` and prints a line per script. Returns whether all
+/// of them passed.
+pub async fn run(config: Config) -> bool {
+    let target = Target {
+        address: config.address.clone(),
+        authority_pubkey: config.pubkey,
+        authority_secret_key: config.secret_key,
+    };
+    let (mut passed, mut failed) = (0, 0);
+    for path in &config.scripts {
+        let paths = match script::find_scripts(path) {
+            Ok(paths) => paths,
+            Err(e) => {
+                println!("ERROR {}", e);
+                failed += 1;
+                continue;
+            }
+        };
+        for path in paths {
+            let script = match Script::load(&path) {
+                Ok(script) => script,
+                Err(e) => {
+                    println!("ERROR {}: {}", path.display(), e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            if config.role.is_some() && script.role != config.role {
+                continue;
+            }
+            match run_script(&script, &target).await {
+                Ok(()) => {
+                    println!("PASS  {} ({})", script.name, path.display());
+                    passed += 1;
+                }
+                Err(Failure { step, reason }) => {
+                    println!(
+                        "FAIL  {} ({}) step {}: {}",
+                        script.name,
+                        path.display(),
+                        step,
+                        reason
+                    );
+                    failed += 1;
+                }
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    failed == 0
+}
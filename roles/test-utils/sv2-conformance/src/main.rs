@@ -0,0 +1,14 @@
+use clap::Parser;
+use sv2_conformance::Config;
+
+#[tokio::main]
+async fn main() {
+    // stdout is reserved to the results
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    let config = Config::parse();
+    if !sv2_conformance::run(config).await {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,218 @@
+//! Field matchers and variables.
+//!
+//! The `fields` of an expectation map a field name to a matcher. Nested fields and sequence items
+//! are reached with a dotted path, e.g. `tx_ids.0`. A matcher is either a plain value, that the
+//! field must be equal to, or a table of conditions that must all hold:
+//!
+//! | condition | holds when |
+//! |-----------|------------|
+//! | `eq = v`, `ne = v` | the field is (not) equal to `v` |
+//! | `gt`, `ge`, `lt`, `le` | the field is a number greater, greater or equal, ... |
+//! | `one_of = [..]` | the field is equal to one of the values |
+//! | `len = n` | the field is a string of `n` characters or a sequence of `n` items |
+//! | `save = "name"` | always, and stores the field in the variable `name` |
+//!
+//! Byte arrays are hex strings, so a 32 bytes hash has a `len` of 64.
+//!
+//! A string `"$name"`, in a matcher or anywhere in a sent message, is replaced by the value of the
+//! variable `name`. This is how a script reuses ids assigned by the role, like a channel id.
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Values saved by the matchers of a script.
+#[derive(Debug, Clone, Default)]
+pub struct Variables(BTreeMap<String, Value>);
+
+impl Variables {
+    /// Returns `value` with every `"$name"` string replaced by the variable `name`.
+    pub fn substitute(&self, value: &Value) -> Result<Value, String> {
+        Ok(match value {
+            Value::String(s) => match s.strip_prefix('$') {
+                Some(name) => self
+                    .0
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| format!("undefined variable ${}", name))?,
+                None => value.clone(),
+            },
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.substitute(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), self.substitute(v)?)))
+                    .collect::<Result<Map<_, _>, String>>()?,
+            ),
+            _ => value.clone(),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+}
+
+/// Checks the `fields` of `message` against `matchers`, saving variables along the way.
+pub fn check_fields(
+    message: &Value,
+    matchers: &BTreeMap<String, Value>,
+    variables: &mut Variables,
+) -> Result<(), String> {
+    for (path, matcher) in matchers {
+        let field = lookup(message, path).ok_or_else(|| format!("no field {}", path))?;
+        check(field, matcher, variables).map_err(|e| format!("field {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Object(fields) => fields.get(key),
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+fn check(field: &Value, matcher: &Value, variables: &mut Variables) -> Result<(), String> {
+    let conditions = match matcher {
+        Value::Object(conditions) => conditions,
+        _ => return expect_equal(field, &variables.substitute(matcher)?),
+    };
+    for (condition, operand) in conditions {
+        if condition == "save" {
+            let name = operand.as_str().ok_or("save expects a variable name")?;
+            variables.0.insert(name.to_string(), field.clone());
+            continue;
+        }
+        let operand = variables.substitute(operand)?;
+        match condition.as_str() {
+            "eq" => expect_equal(field, &operand)?,
+            "ne" => {
+                if equal(field, &operand) {
+                    return Err(format!("expected anything but {}", operand));
+                }
+            }
+            "gt" | "ge" | "lt" | "le" => {
+                let (a, b) = match (field.as_f64(), operand.as_f64()) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => return Err(format!("{} is not a number", field)),
+                };
+                let holds = match condition.as_str() {
+                    "gt" => a > b,
+                    "ge" => a >= b,
+                    "lt" => a < b,
+                    _ => a <= b,
+                };
+                if !holds {
+                    return Err(format!("expected {} {}, got {}", condition, operand, field));
+                }
+            }
+            "one_of" => {
+                let values = operand.as_array().ok_or("one_of expects a list")?;
+                if !values.iter().any(|v| equal(field, v)) {
+                    return Err(format!("expected one of {}, got {}", operand, field));
+                }
+            }
+            "len" => {
+                let len = match field {
+                    Value::String(s) => s.chars().count(),
+                    Value::Array(items) => items.len(),
+                    _ => return Err(format!("{} has no length", field)),
+                };
+                if operand.as_u64() != Some(len as u64) {
+                    return Err(format!("expected length {}, got {}", operand, len));
+                }
+            }
+            _ => return Err(format!("unknown condition {}", condition)),
+        }
+    }
+    Ok(())
+}
+
+fn expect_equal(field: &Value, expected: &Value) -> Result<(), String> {
+    if equal(field, expected) {
+        Ok(())
+    } else {
+        Err(format!("expected {}, got {}", expected, field))
+    }
+}
+
+// Numbers are compared by value, so that `2`, `2.0` and a `f32` field holding 2 are equal.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn matchers(value: Value) -> BTreeMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn conditions() {
+        let message = json!({
+            "channel_id": 3,
+            "extranonce_size": 8,
+            "target": "ffff",
+            "error_code": "invalid-channel-id",
+            "tx_ids": [{"a": 1}, {"a": 2}],
+        });
+        let mut variables = Variables::default();
+        let ok = matchers(json!({
+            "channel_id": {"ge": 1, "lt": 4, "ne": 2, "save": "channel"},
+            "extranonce_size": {"one_of": [4, 8.0]},
+            "target": {"len": 4},
+            "error_code": "invalid-channel-id",
+            "tx_ids.1.a": 2,
+        }));
+        check_fields(&message, &ok, &mut variables).unwrap();
+        assert_eq!(variables.get("channel"), Some(&json!(3)));
+
+        for bad in [
+            json!({"channel_id": 4}),
+            json!({"channel_id": {"gt": 3}}),
+            json!({"target": {"len": 2}}),
+            json!({"error_code": {"one_of": ["stale-share"]}}),
+            json!({"missing": 1}),
+            json!({"tx_ids.2.a": 1}),
+            json!({"channel_id": {"unknown": 1}}),
+        ] {
+            assert!(check_fields(&message, &matchers(bad), &mut variables).is_err());
+        }
+    }
+
+    #[test]
+    fn variables() {
+        let mut variables = Variables::default();
+        let message = json!({"channel_id": 7});
+        check_fields(
+            &message,
+            &matchers(json!({"channel_id": {"save": "channel"}})),
+            &mut variables,
+        )
+        .unwrap();
+        check_fields(
+            &message,
+            &matchers(json!({"channel_id": "$channel"})),
+            &mut variables,
+        )
+        .unwrap();
+        let sent = json!({"Mining": {"UpdateChannel": {"channel_id": "$channel", "id": "$"}}});
+        assert!(variables.substitute(&sent).is_err());
+        let sent = json!({"Mining": {"UpdateChannel": {"channel_id": "$channel"}}});
+        assert_eq!(
+            variables.substitute(&sent).unwrap(),
+            json!({"Mining": {"UpdateChannel": {"channel_id": 7}}})
+        );
+    }
+}
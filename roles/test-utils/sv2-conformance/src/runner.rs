@@ -0,0 +1,319 @@
+//! Runs [`Script`]s against a role under test.
+use crate::{
+    matcher::{check_fields, Variables},
+    script::{Action, Script, Side, Step},
+};
+use async_channel::{Receiver, Sender};
+use codec_sv2::{framing_sv2::framing::Frame, HandshakeRole, Initiator, Responder, Sv2Frame};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+use network_helpers_sv2::noise_connection::Connection;
+use roles_logic_sv2::parsers::{AnyMessage, IsSv2Message};
+use serde_json::Value;
+use std::{
+    convert::TryInto,
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+type EitherFrame = codec_sv2::StandardEitherFrame<AnyMessage<'static>>;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time given to the role under test to connect when the runner plays the upstream.
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Validity of the certificates sent by the runner when it plays the upstream.
+const CERT_VALIDITY: Duration = Duration::from_secs(3600);
+
+/// The role the scripts are run against.
+#[derive(Debug, Clone)]
+pub struct Target {
+    /// Address of the role, or the address the runner listens on for [`Side::Upstream`] scripts.
+    pub address: String,
+    /// Authority public key of the role, its certificate is not checked when `None`. For
+    /// [`Side::Upstream`] scripts, the public key of the runner.
+    pub authority_pubkey: Option<Secp256k1PublicKey>,
+    /// Authority secret key of the runner, only needed by [`Side::Upstream`] scripts.
+    pub authority_secret_key: Option<Secp256k1SecretKey>,
+}
+
+/// Why a script failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// 1-based index of the failed step, 0 when the connection could not be established.
+    pub step: usize,
+    pub reason: String,
+}
+
+/// Runs `script` on a new connection to `target`, or on the first connection from `target` for
+/// [`Side::Upstream`] scripts.
+pub async fn run_script(script: &Script, target: &Target) -> Result<(), Failure> {
+    let connection = match script.side {
+        Side::Downstream => connect(target).await,
+        Side::Upstream => accept(target).await,
+    };
+    let (receiver, sender) = connection.map_err(|reason| Failure { step: 0, reason })?;
+    let mut variables = Variables::default();
+    let result = async {
+        for (i, step) in script.steps.iter().enumerate() {
+            run_step(script, step, &receiver, &sender, &mut variables)
+                .await
+                .map_err(|reason| Failure {
+                    step: i + 1,
+                    reason,
+                })?;
+        }
+        Ok(())
+    }
+    .await;
+    sender.close();
+    result
+}
+
+async fn connect(target: &Target) -> Result<(Receiver<EitherFrame>, Sender<EitherFrame>), String> {
+    // The role may still be starting, so refused connections are retried
+    let start = Instant::now();
+    let stream = loop {
+        match timeout(CONNECT_TIMEOUT, TcpStream::connect(&target.address)).await {
+            Ok(Ok(stream)) => break stream,
+            Ok(Err(e)) if start.elapsed() >= CONNECT_TIMEOUT => {
+                return Err(format!("failed to connect to {}: {}", target.address, e))
+            }
+            Ok(Err(_)) => tokio::time::sleep(Duration::from_millis(100)).await,
+            Err(_) => return Err(format!("timeout connecting to {}", target.address)),
+        }
+    };
+    let initiator = match target.authority_pubkey {
+        Some(key) => Initiator::from_raw_k(key.into_bytes()),
+        None => Initiator::without_pk(),
+    }
+    .map_err(|e| format!("invalid authority public key: {:?}", e))?;
+    timeout(
+        CONNECT_TIMEOUT,
+        Connection::new::<AnyMessage<'static>>(stream, HandshakeRole::Initiator(initiator)),
+    )
+    .await
+    .map_err(|_| "timeout during the noise handshake".to_string())?
+    .map_err(|e| format!("noise handshake failed: {:?}", e))
+}
+
+async fn accept(target: &Target) -> Result<(Receiver<EitherFrame>, Sender<EitherFrame>), String> {
+    let (public_key, secret_key) = match (target.authority_pubkey, target.authority_secret_key) {
+        (Some(public_key), Some(secret_key)) => (public_key, secret_key),
+        _ => return Err("upstream scripts need the authority public and secret keys".to_string()),
+    };
+    let responder = Responder::from_authority_kp(
+        &public_key.into_bytes(),
+        &secret_key.into_bytes(),
+        CERT_VALIDITY,
+    )
+    .map_err(|e| format!("invalid authority key pair: {:?}", e))?;
+    let listener = TcpListener::bind(&target.address)
+        .await
+        .map_err(|e| format!("failed to listen on {}: {}", target.address, e))?;
+    let (stream, _) = timeout(ACCEPT_TIMEOUT, listener.accept())
+        .await
+        .map_err(|_| format!("timeout waiting for a connection on {}", target.address))?
+        .map_err(|e| format!("failed to accept a connection: {}", e))?;
+    timeout(
+        CONNECT_TIMEOUT,
+        Connection::new::<AnyMessage<'static>>(stream, HandshakeRole::Responder(responder)),
+    )
+    .await
+    .map_err(|_| "timeout during the noise handshake".to_string())?
+    .map_err(|e| format!("noise handshake failed: {:?}", e))
+}
+
+async fn run_step(
+    script: &Script,
+    step: &Step,
+    receiver: &Receiver<EitherFrame>,
+    sender: &Sender<EitherFrame>,
+    variables: &mut Variables,
+) -> Result<(), String> {
+    let deadline = Instant::now() + Duration::from_millis(step.timeout_ms());
+    match step.action()? {
+        Action::Send(message) => {
+            let message = variables.substitute(message)?;
+            let message: AnyMessage<'static> = serde_json::from_value(message)
+                .map_err(|e| format!("invalid message to send: {}", e))?;
+            let frame = Sv2Frame::from_message(
+                message.clone(),
+                message.message_type(),
+                message.extension_type(),
+                message.channel_bit(),
+            )
+            .ok_or("message too big for a frame")?;
+            sender
+                .send(frame.into())
+                .await
+                .map_err(|_| "connection closed by the role".to_string())
+        }
+        Action::Expect(expected) => loop {
+            let (name, fields) = match receive(receiver, deadline).await? {
+                Some(message) => message,
+                None => return Err(format!("connection closed, expected {}", expected)),
+            };
+            if name == expected {
+                return check_fields(&fields, &step.fields, variables)
+                    .map_err(|e| format!("{}: {}", name, e));
+            }
+            if !step.skip_others && !script.ignore.contains(&name) {
+                return Err(format!("expected {}, got {} {}", expected, name, fields));
+            }
+        },
+        Action::ExpectClose => loop {
+            match receive(receiver, deadline).await? {
+                None => return Ok(()),
+                Some((name, _)) if script.ignore.contains(&name) => continue,
+                Some((name, fields)) => {
+                    return Err(format!(
+                        "expected the connection to be closed, got {} {}",
+                        name, fields
+                    ))
+                }
+            }
+        },
+        Action::Wait(ms) => {
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok(())
+        }
+    }
+}
+
+// Returns the name and the fields of the next message, or `None` when the connection is closed.
+async fn receive(
+    receiver: &Receiver<EitherFrame>,
+    deadline: Instant,
+) -> Result<Option<(String, Value)>, String> {
+    let wait = deadline.saturating_duration_since(Instant::now());
+    let frame = match timeout(wait, receiver.recv()).await {
+        Ok(Ok(frame)) => frame,
+        Ok(Err(_)) => return Ok(None),
+        Err(_) => return Err("timeout".to_string()),
+    };
+    let mut frame = match frame {
+        Frame::Sv2(frame) => frame,
+        Frame::HandShake(_) => return Err("unexpected handshake frame".to_string()),
+    };
+    let header = frame.get_header().ok_or("frame without header")?;
+    let mut payload = frame.payload().to_vec();
+    let message: AnyMessage<'_> = (header.ext_type(), header.msg_type(), payload.as_mut_slice())
+        .try_into()
+        .map_err(|e| {
+            format!(
+                "undecodable message of type 0x{:02x}: {:?}",
+                header.msg_type(),
+                e
+            )
+        })?;
+    let value = serde_json::to_value(&message).map_err(|e| e.to_string())?;
+    // `{"Subprotocol": {"Message": {fields..}}}`
+    let (name, fields) = value
+        .as_object()
+        .and_then(|o| o.values().next())
+        .and_then(|v| v.as_object())
+        .and_then(|o| o.iter().next())
+        .map(|(name, fields)| (name.clone(), fields.clone()))
+        .ok_or_else(|| format!("unexpected message representation {}", value))?;
+    Ok(Some((name, fields)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roles_logic_sv2::{
+        common_messages_sv2::{Protocol, SetupConnection},
+        parsers::CommonMessages,
+    };
+
+    const PUBLIC_KEY: &str = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72";
+    const SECRET_KEY: &str = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n";
+
+    fn setup_connection() -> AnyMessage<'static> {
+        AnyMessage::Common(CommonMessages::SetupConnection(SetupConnection {
+            protocol: Protocol::MiningProtocol,
+            min_version: 2,
+            max_version: 2,
+            flags: 0,
+            endpoint_host: "127.0.0.1".to_string().try_into().unwrap(),
+            endpoint_port: 0,
+            vendor: "".to_string().try_into().unwrap(),
+            hardware_version: "".to_string().try_into().unwrap(),
+            firmware: "".to_string().try_into().unwrap(),
+            device_id: "".to_string().try_into().unwrap(),
+        }))
+    }
+
+    #[tokio::test]
+    async fn upstream_scripts_wait_for_the_role() {
+        let script: Script = toml::from_str(
+            r#"
+            name = "upstream"
+            side = "upstream"
+            [[steps]]
+            expect = "SetupConnection"
+            fields = { protocol = "MiningProtocol", max_version = 2 }
+            [[steps]]
+            send = { Common = { SetupConnectionSuccess = { used_version = 2, flags = 0 } } }
+            "#,
+        )
+        .unwrap();
+        let address = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let target = Target {
+            address: address.clone(),
+            authority_pubkey: Some(PUBLIC_KEY.parse().unwrap()),
+            authority_secret_key: Some(SECRET_KEY.parse().unwrap()),
+        };
+        let runner = tokio::spawn(async move { run_script(&script, &target).await });
+
+        // The role under test connects to the runner
+        let role = Target {
+            address,
+            authority_pubkey: Some(PUBLIC_KEY.parse().unwrap()),
+            authority_secret_key: None,
+        };
+        let (receiver, sender) = connect(&role).await.unwrap();
+        let message = setup_connection();
+        let frame = Sv2Frame::from_message(
+            message.clone(),
+            message.message_type(),
+            message.extension_type(),
+            message.channel_bit(),
+        )
+        .unwrap();
+        sender.send(frame.into()).await.unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (name, _) = receive(&receiver, deadline).await.unwrap().unwrap();
+        assert_eq!(name, "SetupConnectionSuccess");
+        assert_eq!(runner.await.unwrap(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn upstream_scripts_need_the_key_pair() {
+        let script: Script = toml::from_str(
+            r#"
+            name = "upstream"
+            side = "upstream"
+            [[steps]]
+            wait_ms = 1
+            "#,
+        )
+        .unwrap();
+        let target = Target {
+            address: "127.0.0.1:0".to_string(),
+            authority_pubkey: Some(PUBLIC_KEY.parse().unwrap()),
+            authority_secret_key: None,
+        };
+        let failure = run_script(&script, &target).await.unwrap_err();
+        assert_eq!(failure.step, 0);
+    }
+}
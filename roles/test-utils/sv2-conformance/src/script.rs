@@ -0,0 +1,215 @@
+//! Conformance scripts.
+//!
+//! A script is a TOML (or JSON) file describing a conversation with the role under test:
+//!
+//! ```toml
+//! name = "OpenStandardMiningChannel is answered with a success"
+//! role = "pool"
+//! # messages the role may send at any time, skipped while waiting for an expected message
+//! ignore = ["SetTarget"]
+//!
+//! [[steps]]
+//! send = { Common = { SetupConnection = { protocol = "MiningProtocol", min_version = 2, max_version = 2, flags = 1, endpoint_host = "", endpoint_port = 0, vendor = "", hardware_version = "", firmware = "", device_id = "" } } }
+//!
+//! [[steps]]
+//! expect = "SetupConnectionSuccess"
+//! fields = { used_version = 2 }
+//!
+//! [[steps]]
+//! send = { Mining = { OpenStandardMiningChannel = { request_id = 1, user_identity = "conformance", nominal_hash_rate = 1e12, max_target = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff" } } }
+//!
+//! [[steps]]
+//! expect = "OpenStandardMiningChannelSuccess"
+//! fields = { request_id = 1, channel_id = { save = "channel" } }
+//! ```
+//!
+//! Messages are written with the serde representation of
+//! [`roles_logic_sv2::parsers::AnyMessage`], and expectations use the name of the message
+//! variant. See [`crate::matcher`] for the field matchers and variables.
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Timeout used by expectations that do not set one.
+pub const DEFAULT_TIMEOUT_MS: u64 = 5_000;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Role the script is written for, e.g. `pool`, only informative.
+    #[serde(default)]
+    pub role: Option<String>,
+    /// Side of the connection played by the runner.
+    #[serde(default)]
+    pub side: Side,
+    /// Names of the messages skipped while waiting for an expected message.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    pub steps: Vec<Step>,
+}
+
+/// Side of the connection played by the runner, the role under test plays the other one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    /// The runner connects to the role under test.
+    #[default]
+    Downstream,
+    /// The runner listens for the role under test to connect, e.g. to check a proxy.
+    Upstream,
+}
+
+/// A single step of a script, exactly one action among `send`, `expect`, `expect_close` and
+/// `wait_ms` must be set.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// Message sent to the role.
+    pub send: Option<Value>,
+    /// Name of the next message expected from the role.
+    pub expect: Option<String>,
+    /// Matchers applied to the fields of the expected message.
+    #[serde(default)]
+    pub fields: BTreeMap<String, Value>,
+    /// Skip any other message until the expected one is received, instead of failing.
+    #[serde(default)]
+    pub skip_others: bool,
+    /// Expect the role to close the connection.
+    #[serde(default)]
+    pub expect_close: bool,
+    /// Milliseconds to wait for an expected message or for the connection to be closed.
+    pub timeout_ms: Option<u64>,
+    /// Milliseconds to pause before the next step.
+    pub wait_ms: Option<u64>,
+}
+
+/// The action of a [`Step`], once validated.
+#[derive(Debug, Clone, Copy)]
+pub enum Action<'a> {
+    Send(&'a Value),
+    Expect(&'a str),
+    ExpectClose,
+    Wait(u64),
+}
+
+impl Step {
+    pub fn action(&self) -> Result<Action<'_>, String> {
+        let mut actions = Vec::new();
+        if let Some(message) = &self.send {
+            actions.push(Action::Send(message));
+        }
+        if let Some(name) = &self.expect {
+            actions.push(Action::Expect(name));
+        }
+        if self.expect_close {
+            actions.push(Action::ExpectClose);
+        }
+        if let Some(ms) = self.wait_ms {
+            actions.push(Action::Wait(ms));
+        }
+        match actions.as_slice() {
+            [action] => Ok(*action),
+            [] => Err("step without action".to_string()),
+            _ => Err("step with more than one action".to_string()),
+        }
+    }
+
+    pub fn timeout_ms(&self) -> u64 {
+        self.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS)
+    }
+}
+
+impl Script {
+    /// Reads a script, as JSON if the extension is `.json` and as TOML otherwise.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let script: Self = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&content).map_err(|e| e.to_string())?
+        } else {
+            toml::from_str(&content).map_err(|e| e.to_string())?
+        };
+        for (i, step) in script.steps.iter().enumerate() {
+            step.action()
+                .map_err(|e| format!("step {}: {}", i + 1, e))?;
+        }
+        Ok(script)
+    }
+}
+
+/// Lists the scripts found in `path`, recursively and sorted by path when it is a directory.
+pub fn find_scripts(path: &Path) -> Result<Vec<std::path::PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut scripts = Vec::new();
+    let entries = fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?.path();
+        if entry.is_dir() {
+            scripts.extend(find_scripts(&entry)?);
+        } else if entry
+            .extension()
+            .is_some_and(|e| e == "toml" || e == "json")
+        {
+            scripts.push(entry);
+        }
+    }
+    scripts.sort();
+    Ok(scripts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roles_logic_sv2::parsers::AnyMessage;
+
+    // Variables are only known while running, any number stands for them here.
+    fn fill_variables(value: &Value) -> Value {
+        match value {
+            Value::String(s) if s.starts_with('$') => Value::from(1),
+            Value::Array(items) => Value::Array(items.iter().map(fill_variables).collect()),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), fill_variables(v)))
+                    .collect(),
+            ),
+            _ => value.clone(),
+        }
+    }
+
+    #[test]
+    fn shipped_cases_are_valid() {
+        let cases = Path::new(env!("CARGO_MANIFEST_DIR")).join("cases");
+        let scripts = find_scripts(&cases).unwrap();
+        assert!(!scripts.is_empty());
+        for path in scripts {
+            let script = Script::load(&path).unwrap_or_else(|e| panic!("{:?}: {}", path, e));
+            assert!(script.role.is_some(), "{:?} has no role", path);
+            for step in &script.steps {
+                if let Action::Send(message) = step.action().unwrap() {
+                    serde_json::from_value::<AnyMessage<'static>>(fill_variables(message))
+                        .unwrap_or_else(|e| panic!("{:?}: {}", path, e));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn steps_have_one_action() {
+        let script: Script = toml::from_str(
+            r#"
+            name = "invalid"
+            [[steps]]
+            expect = "SetupConnectionSuccess"
+            wait_ms = 10
+            "#,
+        )
+        .unwrap();
+        assert!(script.steps[0].action().is_err());
+        assert!(Step::default().action().is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use crate::filter::Filter;
+use clap::{Parser, ValueEnum};
+use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
+
+/// How inspected messages are written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One JSON object per line, to be processed with `jq` or similar tools.
+    Json,
+    /// One human readable line per message.
+    Text,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+pub struct Config {
+    #[arg(
+        short,
+        long,
+        help = "Address the downstream connects to, in this format ip:port",
+        default_value = "0.0.0.0:34255"
+    )]
+    pub listen: String,
+    #[arg(
+        short,
+        long,
+        help = "Address of the upstream in this format ip:port or domain:port"
+    )]
+    pub upstream: String,
+    #[arg(
+        long,
+        help = "Authority public key presented to the downstream, the downstream must be configured to trust it"
+    )]
+    pub authority_public_key: Secp256k1PublicKey,
+    #[arg(
+        long,
+        help = "Authority secret key used to sign the inspector certificate"
+    )]
+    pub authority_secret_key: Secp256k1SecretKey,
+    #[arg(
+        long,
+        help = "Validity in seconds of the certificate presented to the downstream",
+        default_value = "3600"
+    )]
+    pub cert_validity_sec: u64,
+    #[arg(
+        long,
+        help = "Upstream authority public key, when left empty the upstream certificate is not checked"
+    )]
+    pub upstream_authority_pubkey: Option<Secp256k1PublicKey>,
+    #[arg(
+        short,
+        long = "filter",
+        help = "Only log the messages matching this expression, e.g. \"type=SubmitSharesExtended channel=1,2 dir=up\". Can be repeated, a message is logged if it matches any of them"
+    )]
+    pub filters: Vec<Filter>,
+    #[arg(long, value_enum, default_value = "json")]
+    pub format: OutputFormat,
+}
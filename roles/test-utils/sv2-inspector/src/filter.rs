@@ -0,0 +1,169 @@
+//! Filter expressions selecting which messages are logged.
+//!
+//! An expression is a whitespace separated list of terms, all of which must match:
+//! - `type=<types>`: message type, by name (e.g. `NewMiningJob`) or number (`0x15` or `21`)
+//! - `channel=<ids>`: channel id, messages without a `channel_id` field never match
+//! - `dir=<up|down>`: direction, `up` being from the downstream to the upstream
+//!
+//! Each term takes a comma separated list of values and matches if any of them does. Using `!=`
+//! instead of `=` negates the term, e.g. `type!=SubmitSharesStandard,SubmitSharesSuccess`.
+use crate::inspect::{Direction, Inspected};
+use roles_logic_sv2::parsers::message_type_to_name;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Term {
+    negated: bool,
+    condition: Condition,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Condition {
+    MessageType(Vec<u8>),
+    Channel(Vec<u32>),
+    Direction(Direction),
+}
+
+impl Filter {
+    pub fn matches(&self, message: &Inspected) -> bool {
+        self.terms.iter().all(|term| {
+            let matches = match &term.condition {
+                Condition::MessageType(types) => types.contains(&message.message_type),
+                Condition::Channel(ids) => message
+                    .channel_id()
+                    .is_some_and(|channel_id| ids.contains(&channel_id)),
+                Condition::Direction(direction) => *direction == message.direction,
+            };
+            matches != term.negated
+        })
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let terms = s
+            .split_whitespace()
+            .map(parse_term)
+            .collect::<Result<Vec<_>, _>>()?;
+        if terms.is_empty() {
+            return Err("Empty filter expression".to_string());
+        }
+        Ok(Self { terms })
+    }
+}
+
+fn parse_term(term: &str) -> Result<Term, String> {
+    let (key, values, negated) = match term.split_once("!=") {
+        Some((key, values)) => (key, values, true),
+        None => match term.split_once('=') {
+            Some((key, values)) => (key, values, false),
+            None => return Err(format!("Invalid filter term {}, expected key=values", term)),
+        },
+    };
+    let values = values.split(',').filter(|v| !v.is_empty());
+    let condition = match key {
+        "type" => {
+            let mut types = Vec::new();
+            for value in values {
+                types.extend(parse_message_type(value)?);
+            }
+            Condition::MessageType(types)
+        }
+        "channel" => Condition::Channel(
+            values
+                .map(|v| v.parse().map_err(|_| format!("Invalid channel id {}", v)))
+                .collect::<Result<_, _>>()?,
+        ),
+        "dir" => match values.collect::<Vec<_>>().as_slice() {
+            ["up"] => Condition::Direction(Direction::Up),
+            ["down"] => Condition::Direction(Direction::Down),
+            _ => {
+                return Err(format!(
+                    "Invalid direction in {}, expected up or down",
+                    term
+                ))
+            }
+        },
+        _ => return Err(format!("Unknown filter key {}", key)),
+    };
+    Ok(Term { negated, condition })
+}
+
+// Message names are not unique across subprotocols, e.g. `SubmitSolution`, so a name can stand
+// for more than one message type.
+fn parse_message_type(value: &str) -> Result<Vec<u8>, String> {
+    let number = match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+    if let Some(number) = number {
+        return Ok(vec![number]);
+    }
+    let types: Vec<u8> = (0..=u8::MAX)
+        .filter(|t| message_type_to_name(*t).eq_ignore_ascii_case(value))
+        .collect();
+    if types.is_empty() {
+        Err(format!("Unknown message type {}", value))
+    } else {
+        Ok(types)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn inspected(direction: Direction, message_type: u8, channel_id: Option<u32>) -> Inspected {
+        let fields = match channel_id {
+            Some(channel_id) => json!({ "channel_id": channel_id }),
+            None => json!({}),
+        };
+        Inspected {
+            timestamp_ms: 0,
+            connection: 0,
+            direction,
+            extension_type: 0,
+            message_type,
+            message: Ok(json!({ "Mining": { "Message": fields } })),
+        }
+    }
+
+    #[test]
+    fn matches_all_terms() {
+        let filter: Filter = "type=NewMiningJob,0x1f channel=2 dir=down".parse().unwrap();
+        assert!(filter.matches(&inspected(Direction::Down, 0x15, Some(2))));
+        assert!(filter.matches(&inspected(Direction::Down, 0x1f, Some(2))));
+        assert!(!filter.matches(&inspected(Direction::Up, 0x15, Some(2))));
+        assert!(!filter.matches(&inspected(Direction::Down, 0x15, Some(3))));
+        assert!(!filter.matches(&inspected(Direction::Down, 0x15, None)));
+        assert!(!filter.matches(&inspected(Direction::Down, 0x16, Some(2))));
+    }
+
+    #[test]
+    fn negated_terms() {
+        let filter: Filter = "type!=submitsolution channel!=1".parse().unwrap();
+        assert!(!filter.matches(&inspected(Direction::Up, 0x60, None)));
+        assert!(!filter.matches(&inspected(Direction::Up, 0x76, None)));
+        assert!(!filter.matches(&inspected(Direction::Up, 0x1a, Some(1))));
+        assert!(filter.matches(&inspected(Direction::Up, 0x1a, Some(2))));
+        assert!(filter.matches(&inspected(Direction::Up, 0x00, None)));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!("".parse::<Filter>().is_err());
+        assert!("type".parse::<Filter>().is_err());
+        assert!("type=NotAMessage".parse::<Filter>().is_err());
+        assert!("channel=abc".parse::<Filter>().is_err());
+        assert!("dir=sideways".parse::<Filter>().is_err());
+        assert!("foo=1".parse::<Filter>().is_err());
+    }
+}
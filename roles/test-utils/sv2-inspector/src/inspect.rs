@@ -0,0 +1,127 @@
+//! Decoding and formatting of the frames seen by the inspector.
+use roles_logic_sv2::parsers::{message_type_to_name, AnyMessage};
+use serde_json::{json, Value};
+use std::{
+    convert::TryInto,
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Direction in which a message travels through the inspector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the downstream to the upstream.
+    Up,
+    /// From the upstream to the downstream.
+    Down,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Up => write!(f, "up"),
+            Direction::Down => write!(f, "down"),
+        }
+    }
+}
+
+/// A decoded frame, along with the connection it belongs to.
+#[derive(Debug, Clone)]
+pub struct Inspected {
+    pub timestamp_ms: u128,
+    pub connection: u64,
+    pub direction: Direction,
+    pub extension_type: u16,
+    pub message_type: u8,
+    /// The message serialized with serde, or the raw payload when it could not be decoded.
+    pub message: Result<Value, Vec<u8>>,
+}
+
+impl Inspected {
+    /// Decodes the payload of a frame received on `connection`.
+    pub fn decode(
+        connection: u64,
+        direction: Direction,
+        extension_type: u16,
+        message_type: u8,
+        payload: &[u8],
+    ) -> Self {
+        let mut buffer = payload.to_vec();
+        let message: Result<AnyMessage<'_>, _> =
+            (extension_type, message_type, buffer.as_mut_slice()).try_into();
+        let message = match message {
+            Ok(message) => serde_json::to_value(&message).map_err(|_| payload.to_vec()),
+            Err(_) => Err(payload.to_vec()),
+        };
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            connection,
+            direction,
+            extension_type,
+            message_type,
+            message,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        message_type_to_name(self.message_type)
+    }
+
+    /// Channel the message refers to, if the message has a `channel_id` field.
+    pub fn channel_id(&self) -> Option<u32> {
+        // Messages are serialized as `{"Subprotocol": {"Message": {fields..}}}`
+        let mut value = self.message.as_ref().ok()?;
+        for _ in 0..2 {
+            value = value.as_object()?.values().next()?;
+        }
+        value
+            .get("channel_id")?
+            .as_u64()
+            .and_then(|id| id.try_into().ok())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut object = json!({
+            "timestamp_ms": self.timestamp_ms as u64,
+            "connection": self.connection,
+            "direction": self.direction.to_string(),
+            "extension_type": self.extension_type,
+            "message_type": self.message_type,
+            "name": self.name(),
+        });
+        match &self.message {
+            Ok(message) => object["message"] = message.clone(),
+            Err(payload) => object["payload"] = Value::String(to_hex(payload)),
+        }
+        object
+    }
+}
+
+impl fmt::Display for Inspected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrow = match self.direction {
+            Direction::Up => "⬆",
+            Direction::Down => "⬇",
+        };
+        write!(
+            f,
+            "{} #{} {} {} (0x{:02x})",
+            self.timestamp_ms,
+            self.connection,
+            arrow,
+            self.name(),
+            self.message_type
+        )?;
+        match &self.message {
+            Ok(message) => write!(f, " {}", message),
+            Err(payload) => write!(f, " undecodable payload {}", to_hex(payload)),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,14 @@
+//! SV2 protocol inspector.
+//!
+//! A man-in-the-middle proxy sitting between any two SV2 roles. It terminates the noise encryption
+//! on both sides, acting as the upstream towards the downstream (with the configured authority
+//! keys) and as a downstream towards the upstream, and forwards every frame unchanged. Each frame
+//! is decoded into an [`roles_logic_sv2::parsers::AnyMessage`] and written to stdout, as JSON or
+//! as text, optionally narrowed down with [`filter::Filter`] expressions.
+pub mod config;
+pub mod filter;
+pub mod inspect;
+mod proxy;
+
+pub use config::{Config, OutputFormat};
+pub use proxy::run;
@@ -0,0 +1,12 @@
+use clap::Parser;
+use sv2_inspector::Config;
+
+#[tokio::main]
+async fn main() {
+    // stdout is reserved to the inspected messages
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    let config = Config::parse();
+    sv2_inspector::run(config).await;
+}
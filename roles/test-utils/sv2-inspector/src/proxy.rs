@@ -0,0 +1,127 @@
+//! Transparent noise proxy decoding every frame it forwards.
+use crate::{
+    config::{Config, OutputFormat},
+    inspect::{Direction, Inspected},
+};
+use async_channel::{Receiver, Sender};
+use codec_sv2::{framing_sv2::framing::Frame, HandshakeRole, Initiator, Responder};
+use network_helpers_sv2::noise_connection::Connection;
+use roles_logic_sv2::parsers::AnyMessage;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    select,
+};
+use tracing::{error, info, warn};
+
+type EitherFrame = codec_sv2::StandardEitherFrame<AnyMessage<'static>>;
+
+/// Accepts downstream connections on the configured address and proxies each of them to the
+/// upstream, until the process is interrupted.
+pub async fn run(config: Config) {
+    let config = Arc::new(config);
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .expect("Impossible to listen on given address");
+    info!(
+        "Inspecting connections to {} on {}",
+        config.upstream, config.listen
+    );
+    let mut next_connection = 0;
+    loop {
+        let (stream, address) = select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!("Failed to accept downstream connection: {}", e);
+                    continue;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let connection = next_connection;
+        next_connection += 1;
+        info!("#{} Downstream connected from {}", connection, address);
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = proxy(config, connection, stream).await {
+                warn!("#{} {}", connection, e);
+            }
+            info!("#{} Connection closed", connection);
+        });
+    }
+}
+
+async fn proxy(config: Arc<Config>, connection: u64, downstream: TcpStream) -> Result<(), String> {
+    let responder = Responder::from_authority_kp(
+        &config.authority_public_key.into_bytes(),
+        &config.authority_secret_key.into_bytes(),
+        Duration::from_secs(config.cert_validity_sec),
+    )
+    .map_err(|e| format!("Invalid authority keys: {:?}", e))?;
+    let (receiver_from_downstream, sender_to_downstream) =
+        Connection::new::<AnyMessage<'static>>(downstream, HandshakeRole::Responder(responder))
+            .await
+            .map_err(|e| format!("Downstream handshake failed: {:?}", e))?;
+
+    let upstream = TcpStream::connect(&config.upstream)
+        .await
+        .map_err(|e| format!("Failed to connect to upstream {}: {}", config.upstream, e))?;
+    let initiator = match config.upstream_authority_pubkey {
+        Some(key) => Initiator::from_raw_k(key.into_bytes()),
+        None => Initiator::without_pk(),
+    }
+    .map_err(|e| format!("Invalid upstream authority key: {:?}", e))?;
+    let (receiver_from_upstream, sender_to_upstream) =
+        Connection::new::<AnyMessage<'static>>(upstream, HandshakeRole::Initiator(initiator))
+            .await
+            .map_err(|e| format!("Upstream handshake failed: {:?}", e))?;
+
+    select! {
+        _ = forward(&config, connection, Direction::Up, receiver_from_downstream, sender_to_upstream.clone()) => {},
+        _ = forward(&config, connection, Direction::Down, receiver_from_upstream, sender_to_downstream.clone()) => {},
+    }
+    sender_to_upstream.close();
+    sender_to_downstream.close();
+    Ok(())
+}
+
+async fn forward(
+    config: &Config,
+    connection: u64,
+    direction: Direction,
+    receiver: Receiver<EitherFrame>,
+    sender: Sender<EitherFrame>,
+) {
+    while let Ok(mut frame) = receiver.recv().await {
+        match &mut frame {
+            Frame::Sv2(sv2_frame) => match sv2_frame.get_header() {
+                Some(header) => {
+                    let inspected = Inspected::decode(
+                        connection,
+                        direction,
+                        header.ext_type(),
+                        header.msg_type(),
+                        sv2_frame.payload(),
+                    );
+                    log(config, &inspected);
+                }
+                None => warn!("#{} Received frame without header", connection),
+            },
+            Frame::HandShake(_) => warn!("#{} Received unexpected handshake frame", connection),
+        }
+        if sender.send(frame).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn log(config: &Config, inspected: &Inspected) {
+    if !config.filters.is_empty() && !config.filters.iter().any(|f| f.matches(inspected)) {
+        return;
+    }
+    match config.format {
+        OutputFormat::Json => println!("{}", inspected.to_json()),
+        OutputFormat::Text => println!("{}", inspected),
+    }
+}
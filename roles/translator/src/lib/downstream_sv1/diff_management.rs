@@ -309,7 +309,9 @@ impl Downstream {
 
 #[cfg(test)]
 mod test {
-    use crate::proxy_config::{DownstreamDifficultyConfig, UpstreamDifficultyConfig};
+    use crate::proxy_config::{
+        DownstreamDifficultyConfig, UpstreamChannelMode, UpstreamDifficultyConfig,
+    };
     use async_channel::unbounded;
     use binary_sv2::U256;
     use rand::{thread_rng, Rng};
@@ -427,7 +429,7 @@ mod test {
             channel_diff_update_interval: 60,
             channel_nominal_hashrate: 0.0,
             timestamp_of_last_update: 0,
-            should_aggregate: false,
+            channel_mode: UpstreamChannelMode::Aggregate,
         };
         let (tx_sv1_submit, _rx_sv1_submit) = unbounded();
         let (tx_outgoing, _rx_outgoing) = unbounded();
@@ -1,16 +1,18 @@
 use crate::{
     downstream_sv1,
     error::ProxyResult,
-    proxy_config::{DownstreamDifficultyConfig, UpstreamDifficultyConfig},
+    proxy::{bridge::worker_prefix, Bridge, OpenSv1Downstream},
+    proxy_config::{DownstreamDifficultyConfig, UpstreamChannelMode, UpstreamDifficultyConfig},
     status,
+    upstream_sv2::Upstream,
 };
 use async_channel::{bounded, Receiver, Sender};
 use error_handling::handle_result;
 use futures::{FutureExt, StreamExt};
+use network_helpers_sv2::admission::{AdmissionConfig, AdmissionControl, Permit};
 use tokio::{
     io::{AsyncWriteExt, BufReader},
     net::{TcpListener, TcpStream},
-    sync::broadcast,
     task::AbortHandle,
 };
 
@@ -25,7 +27,13 @@ use crate::error::Error;
 use futures::select;
 use tokio_util::codec::{FramedRead, LinesCodec};
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use tracing::{debug, info, warn};
 use v1::{
     client_to_server::{self, Submit},
@@ -59,6 +67,9 @@ pub struct Downstream {
     /// True if this is the first job received from `Upstream`.
     first_job_received: bool,
     extranonce2_len: usize,
+    /// True if the Downstream sent a `mining.extranonce.subscribe`, so that it can be moved to
+    /// another Upstream channel with a `mining.set_extranonce`.
+    extranonce_subscribed: AtomicBool,
     pub(super) difficulty_mgmt: DownstreamDifficultyConfig,
     pub(super) upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
 }
@@ -89,6 +100,7 @@ impl Downstream {
             tx_outgoing,
             first_job_received,
             extranonce2_len,
+            extranonce_subscribed: AtomicBool::new(false),
             difficulty_mgmt,
             upstream_difficulty_config,
         }
@@ -97,35 +109,37 @@ impl Downstream {
     #[allow(clippy::too_many_arguments)]
     pub async fn new_downstream(
         stream: TcpStream,
-        connection_id: u32,
+        opened: OpenSv1Downstream,
         tx_sv1_bridge: Sender<DownstreamMessages>,
-        mut rx_sv1_notify: broadcast::Receiver<server_to_client::Notify<'static>>,
         tx_status: status::Sender,
-        extranonce1: Vec<u8>,
-        last_notify: Option<server_to_client::Notify<'static>>,
-        extranonce2_len: usize,
         host: String,
         difficulty_config: DownstreamDifficultyConfig,
-        upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
+        bridge: Arc<Mutex<Bridge>>,
+        upstream: Arc<Mutex<Upstream>>,
+        channel_mode: UpstreamChannelMode,
         task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        permit: Permit,
     ) {
         // Reads and writes from Downstream SV1 Mining Device Client
         let (socket_reader, mut socket_writer) = stream.into_split();
         let (tx_outgoing, receiver_outgoing) = bounded(10);
+        let mut rx_sv1_notify = opened.rx_sv1_notify;
+        let mut last_notify = opened.last_notify;
 
         let downstream = Arc::new(Mutex::new(Downstream {
-            connection_id,
+            connection_id: opened.channel_id,
             authorized_names: vec![],
-            extranonce1,
+            extranonce1: opened.extranonce,
             //extranonce1: extranonce1.to_vec(),
             version_rolling_mask: None,
             version_rolling_min_bit: None,
             tx_sv1_bridge,
             tx_outgoing,
             first_job_received: false,
-            extranonce2_len,
+            extranonce2_len: opened.extranonce2_len as usize,
+            extranonce_subscribed: AtomicBool::new(false),
             difficulty_mgmt: difficulty_config,
-            upstream_difficulty_config,
+            upstream_difficulty_config: opened.difficulty_config,
         }));
         let self_ = downstream.clone();
 
@@ -153,6 +167,8 @@ impl Downstream {
             let reader = BufReader::new(socket_reader);
             let mut messages =
                 FramedRead::new(reader, LinesCodec::new_with_max_length(MAX_LINE_LENGTH));
+            // The connection slot of the admission control is released when the reader is done
+            let mut rate_limiter = permit.message_rate_limiter();
             loop {
                 // Read message from SV1 Mining Device Client socket
                 // On message receive, parse to `json_rpc:Message` and send to Upstream
@@ -162,6 +178,10 @@ impl Downstream {
                     res = messages.next().fuse() => {
                         match res {
                             Some(Ok(incoming)) => {
+                                if rate_limiter.as_mut().is_some_and(|limiter| !limiter.try_acquire()) {
+                                    permit.report_protocol_error();
+                                    handle_result!(tx_status_reader, Err(Error::Sv1MessageRateExceeded));
+                                }
                                 debug!("Receiving from Mining Device {}: {:?}", &host_, &incoming);
                                 let incoming: json_rpc::Message = handle_result!(tx_status_reader, serde_json::from_str(&incoming));
                                 // Handle what to do with message
@@ -178,6 +198,7 @@ impl Downstream {
                                 handle_result!(tx_status_reader, res);
                             }
                             Some(Err(_)) => {
+                                permit.report_protocol_error();
                                 handle_result!(tx_status_reader, Err(Error::Sv1MessageTooLong));
                             }
                             None => {
@@ -264,7 +285,33 @@ impl Downstream {
                         break;
                     }
                 };
-                if is_a && !first_sent && last_notify.is_some() {
+                if is_a && !first_sent {
+                    // Unless the channel mode is `Aggregate`, the miner joins the Upstream channel of
+                    // its worker before getting its first job
+                    if channel_mode != UpstreamChannelMode::Aggregate {
+                        let moved = handle_result!(
+                            tx_status_notify,
+                            Self::join_upstream_channel(
+                                downstream.clone(),
+                                bridge.clone(),
+                                upstream.clone(),
+                                channel_mode,
+                            )
+                            .await
+                        );
+                        if let Some(moved) = moved {
+                            rx_sv1_notify = moved.rx_sv1_notify;
+                            last_notify = moved.last_notify;
+                        }
+                    }
+                    // A newly opened Upstream channel has no job yet
+                    let sv1_mining_notify_msg = match last_notify.take() {
+                        Some(notify) => notify,
+                        None => select! {
+                            res = rx_sv1_notify.recv().fuse() => handle_result!(tx_status_notify, res),
+                            _ = rx_shutdown.recv().fuse() => break,
+                        },
+                    };
                     let target = handle_result!(
                         tx_status_notify,
                         Self::hash_rate_to_target(downstream.clone())
@@ -282,8 +329,6 @@ impl Downstream {
                         Downstream::send_message_downstream(downstream.clone(), message).await
                     );
 
-                    let sv1_mining_notify_msg = last_notify.clone().unwrap();
-
                     let message: json_rpc::Message = sv1_mining_notify_msg.into();
                     handle_result!(
                         tx_status_notify,
@@ -326,7 +371,10 @@ impl Downstream {
                     tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                 }
             }
-            let _ = Self::remove_miner_hashrate_from_channel(self_);
+            let _ = Self::remove_miner_hashrate_from_channel(self_.clone());
+            if let Err(e) = Self::leave_upstream_channel(self_, bridge, upstream).await {
+                warn!("Downstream: failed to leave its Upstream channel: {:?}", e);
+            }
             kill(&tx_shutdown).await;
             warn!(
                 "Downstream: Shutting down sv1 downstream job notifier for {}",
@@ -344,23 +392,35 @@ impl Downstream {
     pub fn accept_connections(
         downstream_addr: SocketAddr,
         tx_sv1_submit: Sender<DownstreamMessages>,
-        tx_mining_notify: broadcast::Sender<server_to_client::Notify<'static>>,
         tx_status: status::Sender,
-        bridge: Arc<Mutex<crate::proxy::Bridge>>,
+        bridge: Arc<Mutex<Bridge>>,
         downstream_difficulty_config: DownstreamDifficultyConfig,
-        upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
+        upstream: Arc<Mutex<Upstream>>,
+        channel_mode: UpstreamChannelMode,
         task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        admission: AdmissionConfig,
     ) {
         let accept_connections = tokio::task::spawn({
             let task_collector = task_collector.clone();
             async move {
                 let listener = TcpListener::bind(downstream_addr).await.unwrap();
+                let admission = AdmissionControl::new(admission);
 
-                while let Ok((stream, _)) = listener.accept().await {
+                while let Ok((stream, address)) = listener.accept().await {
+                    let permit = match admission.admit(address.ip()) {
+                        Ok(permit) => permit,
+                        Err(e) => {
+                            warn!("Refusing connection from {}: {}", address, e);
+                            continue;
+                        }
+                    };
                     let expected_hash_rate =
                         downstream_difficulty_config.min_individual_miner_hashrate;
+                    // Every miner starts on the channel opened when connecting to the Upstream
                     let open_sv1_downstream = bridge
-                        .safe_lock(|s| s.on_new_sv1_connection(expected_hash_rate))
+                        .safe_lock(|b| {
+                            b.on_new_sv1_connection(b.default_channel_id(), expected_hash_rate)
+                        })
                         .unwrap();
 
                     let host = stream.peer_addr().unwrap().to_string();
@@ -370,17 +430,16 @@ impl Downstream {
                             info!("PROXY SERVER - ACCEPTING FROM DOWNSTREAM: {}", host);
                             Downstream::new_downstream(
                                 stream,
-                                opened.channel_id,
+                                opened,
                                 tx_sv1_submit.clone(),
-                                tx_mining_notify.subscribe(),
                                 tx_status.listener_to_connection(),
-                                opened.extranonce,
-                                opened.last_notify,
-                                opened.extranonce2_len as usize,
                                 host,
                                 downstream_difficulty_config.clone(),
-                                upstream_difficulty_config.clone(),
+                                bridge.clone(),
+                                upstream.clone(),
+                                channel_mode,
                                 task_collector.clone(),
+                                permit,
                             )
                             .await;
                         }
@@ -402,6 +461,111 @@ impl Downstream {
         });
     }
 
+    /// Moves an authorized Downstream to an Upstream channel of its own in the `PerMiner` channel
+    /// mode, or to the Upstream channel of the worker name prefix of its first worker in the
+    /// `Hybrid` channel mode. A new Upstream channel is opened with the worker name, or prefix, as
+    /// user identity. Returns the Downstream channel opened on the Upstream channel, if the
+    /// Downstream was moved.
+    #[allow(clippy::result_large_err)]
+    async fn join_upstream_channel(
+        self_: Arc<Mutex<Self>>,
+        bridge: Arc<Mutex<Bridge>>,
+        upstream: Arc<Mutex<Upstream>>,
+        channel_mode: UpstreamChannelMode,
+    ) -> ProxyResult<'static, Option<OpenSv1Downstream>> {
+        let (connection_id, worker_name, can_update_extranonce, hash_rate) = self_
+            .safe_lock(|d| {
+                (
+                    d.connection_id,
+                    d.authorized_names.first().cloned().unwrap_or_default(),
+                    d.extranonce_subscribed.load(Ordering::SeqCst),
+                    d.difficulty_mgmt.min_individual_miner_hashrate,
+                )
+            })
+            .map_err(|_e| Error::PoisonLock)?;
+        if !can_update_extranonce {
+            warn!(
+                "Worker {} did not subscribe to extranonce updates, it keeps mining on the default Upstream channel",
+                worker_name
+            );
+            return Ok(None);
+        }
+        let worker_group = match channel_mode {
+            UpstreamChannelMode::Hybrid => Some(worker_prefix(&worker_name)),
+            _ => None,
+        };
+        let group_channel_id = match worker_group {
+            Some(worker_group) => bridge
+                .safe_lock(|b| b.worker_group_channel(worker_group))
+                .map_err(|_e| Error::PoisonLock)?,
+            None => None,
+        };
+        let moved = match group_channel_id {
+            Some(upstream_channel_id) => bridge
+                .safe_lock(|b| b.move_sv1_downstream(connection_id, upstream_channel_id, hash_rate))
+                .map_err(|_e| Error::PoisonLock)?,
+            None => {
+                let user_identity = worker_group.unwrap_or(&worker_name);
+                let opened =
+                    Upstream::open_channel(upstream.clone(), user_identity, hash_rate).await?;
+                let moved = bridge
+                    .safe_lock(|b| {
+                        b.on_new_upstream_channel(&opened, worker_group)?;
+                        b.move_sv1_downstream(connection_id, opened.channel_id, hash_rate)
+                    })
+                    .map_err(|_e| Error::PoisonLock)?;
+                let channel_id = opened.channel_id;
+                // The Upstream handles the first jobs of the channel once it is dropped
+                drop(opened);
+                if moved.is_err() {
+                    bridge
+                        .safe_lock(|b| b.on_upstream_channel_closed(channel_id))
+                        .map_err(|_e| Error::PoisonLock)?;
+                    Upstream::close_channel(upstream.clone(), channel_id).await?;
+                }
+                moved
+            }
+        };
+        let (moved, to_close) = moved?;
+        if let Some(channel_id) = to_close {
+            Upstream::close_channel(upstream, channel_id).await?;
+        }
+        info!(
+            "Downstream {} joins the Upstream channel of {}",
+            connection_id,
+            worker_group.unwrap_or(&worker_name)
+        );
+        let extranonce1: Extranonce<'static> = moved.extranonce.clone().try_into()?;
+        let message = self_
+            .safe_lock(|d| {
+                d.connection_id = moved.channel_id;
+                d.upstream_difficulty_config = moved.difficulty_config.clone();
+                d.update_extranonce(extranonce1, moved.extranonce2_len as usize)
+            })
+            .map_err(|_e| Error::PoisonLock)??;
+        Self::send_message_downstream(self_, message).await?;
+        Ok(Some(moved))
+    }
+
+    /// Removes the channel of a disconnected Downstream from the `Bridge`, and closes its Upstream
+    /// channel if no other Downstream mines on it.
+    async fn leave_upstream_channel(
+        self_: Arc<Mutex<Self>>,
+        bridge: Arc<Mutex<Bridge>>,
+        upstream: Arc<Mutex<Upstream>>,
+    ) -> ProxyResult<'static, ()> {
+        let connection_id = self_
+            .safe_lock(|d| d.connection_id)
+            .map_err(|_e| Error::PoisonLock)?;
+        let to_close = bridge
+            .safe_lock(|b| b.on_sv1_disconnection(connection_id))
+            .map_err(|_e| Error::PoisonLock)?;
+        if let Some(channel_id) = to_close {
+            Upstream::close_channel(upstream, channel_id).await?;
+        }
+        Ok(())
+    }
+
     /// As SV1 messages come in, determines if the message response needs to be translated to SV2
     /// and sent to the `Upstream`, or if a direct response can be sent back by the `Translator`
     /// (SV1 and SV2 protocol messages are NOT 1-to-1).
@@ -545,7 +709,15 @@ impl IsServer<'static> for Downstream {
     }
 
     /// Indicates to the server that the client supports the mining.set_extranonce method.
-    fn handle_extranonce_subscribe(&self) {}
+    fn handle_extranonce_subscribe(&self) {
+        self.extranonce_subscribed.store(true, Ordering::SeqCst);
+    }
+
+    /// The share difficulty is managed by the translator vardiff, so the suggestion is refused.
+    fn handle_suggest_difficulty(&mut self, request: &client_to_server::SuggestDifficulty) -> bool {
+        debug!("Down: Ignoring mining.suggest_difficulty: {:?}", request);
+        false
+    }
 
     /// Checks if a Downstream role is authorized.
     fn is_authorized(&self, name: &str) -> bool {
@@ -559,10 +731,10 @@ impl IsServer<'static> for Downstream {
 
     /// Sets the `extranonce1` field sent in the SV1 `mining.notify` message to the value specified
     /// by the SV2 `OpenExtendedMiningChannelSuccess` message sent from the Upstream role.
-    fn set_extranonce1(
-        &mut self,
-        _extranonce1: Option<Extranonce<'static>>,
-    ) -> Extranonce<'static> {
+    fn set_extranonce1(&mut self, extranonce1: Option<Extranonce<'static>>) -> Extranonce<'static> {
+        if let Some(extranonce1) = extranonce1 {
+            self.extranonce1 = extranonce1.into();
+        }
         self.extranonce1.clone().try_into().unwrap()
     }
 
@@ -573,7 +745,10 @@ impl IsServer<'static> for Downstream {
 
     /// Sets the `extranonce2_size` field sent in the SV1 `mining.notify` message to the value
     /// specified by the SV2 `OpenExtendedMiningChannelSuccess` message sent from the Upstream role.
-    fn set_extranonce2_size(&mut self, _extra_nonce2_size: Option<usize>) -> usize {
+    fn set_extranonce2_size(&mut self, extra_nonce2_size: Option<usize>) -> usize {
+        if let Some(extra_nonce2_size) = extra_nonce2_size {
+            self.extranonce2_len = extra_nonce2_size;
+        }
         self.extranonce2_len
     }
 
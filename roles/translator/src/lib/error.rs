@@ -1,6 +1,6 @@
 use ext_config::ConfigError;
 use roles_logic_sv2::{
-    mining_sv2::{ExtendedExtranonce, NewExtendedMiningJob, SetCustomMiningJob},
+    mining_sv2::{CloseChannel, ExtendedExtranonce, NewExtendedMiningJob, SetCustomMiningJob},
     parsers::Mining,
 };
 use std::{fmt, sync::PoisonError};
@@ -11,10 +11,14 @@ pub type ProxyResult<'a, T> = core::result::Result<T, Error<'a>>;
 #[derive(Debug)]
 pub enum ChannelSendError<'a> {
     SubmitSharesExtended(
-        async_channel::SendError<roles_logic_sv2::mining_sv2::SubmitSharesExtended<'a>>,
+        async_channel::SendError<(
+            roles_logic_sv2::mining_sv2::SubmitSharesExtended<'a>,
+            String,
+        )>,
     ),
     SetNewPrevHash(async_channel::SendError<roles_logic_sv2::mining_sv2::SetNewPrevHash<'a>>),
     NewExtendedMiningJob(async_channel::SendError<NewExtendedMiningJob<'a>>),
+    CloseChannel(async_channel::SendError<CloseChannel<'a>>),
     Notify(tokio::sync::broadcast::error::SendError<Notify<'a>>),
     V1Message(async_channel::SendError<v1::Message>),
     General(String),
@@ -51,6 +55,8 @@ pub enum Error<'a> {
     InvalidExtranonce(String),
     /// Errors on bad `String` to `int` conversion.
     ParseInt(std::num::ParseIntError),
+    /// Errors from `network_helpers_sv2` crate.
+    NetworkHelpers(network_helpers_sv2::Error),
     /// Errors from `roles_logic_sv2` crate.
     RolesSv2Logic(roles_logic_sv2::errors::Error),
     UpstreamIncoming(roles_logic_sv2::errors::Error),
@@ -73,6 +79,7 @@ pub enum Error<'a> {
     #[allow(clippy::enum_variant_names)]
     TargetError(roles_logic_sv2::errors::Error),
     Sv1MessageTooLong,
+    Sv1MessageRateExceeded,
 }
 
 impl fmt::Display for Error<'_> {
@@ -88,6 +95,7 @@ impl fmt::Display for Error<'_> {
             InvalidExtranonce(ref e) => write!(f, "Invalid Extranonce error: `{:?}", e),
             Io(ref e) => write!(f, "I/O error: `{:?}", e),
             ParseInt(ref e) => write!(f, "Bad convert from `String` to `int`: `{:?}`", e),
+            NetworkHelpers(ref e) => write!(f, "Network helpers error: `{:?}`", e),
             RolesSv2Logic(ref e) => write!(f, "Roles SV2 Logic Error: `{:?}`", e),
             V1Protocol(ref e) => write!(f, "V1 Protocol Error: `{:?}`", e),
             SubprotocolMining(ref e) => write!(f, "Subprotocol Mining Error: `{:?}`", e),
@@ -110,6 +118,12 @@ impl fmt::Display for Error<'_> {
             Sv1MessageTooLong => {
                 write!(f, "Received an sv1 message that is longer than max len")
             }
+            Sv1MessageRateExceeded => {
+                write!(
+                    f,
+                    "Received more sv1 messages than allowed by the rate limit"
+                )
+            }
         }
     }
 }
@@ -144,6 +158,12 @@ impl From<std::num::ParseIntError> for Error<'_> {
     }
 }
 
+impl From<network_helpers_sv2::Error> for Error<'_> {
+    fn from(e: network_helpers_sv2::Error) -> Self {
+        Error::NetworkHelpers(e)
+    }
+}
+
 impl From<roles_logic_sv2::errors::Error> for Error<'_> {
     fn from(e: roles_logic_sv2::errors::Error) -> Self {
         Error::RolesSv2Logic(e)
@@ -188,11 +208,19 @@ impl<T> From<PoisonError<T>> for Error<'_> {
 }
 
 // *** CHANNEL SENDER ERRORS ***
-impl<'a> From<async_channel::SendError<roles_logic_sv2::mining_sv2::SubmitSharesExtended<'a>>>
-    for Error<'a>
+impl<'a>
+    From<
+        async_channel::SendError<(
+            roles_logic_sv2::mining_sv2::SubmitSharesExtended<'a>,
+            String,
+        )>,
+    > for Error<'a>
 {
     fn from(
-        e: async_channel::SendError<roles_logic_sv2::mining_sv2::SubmitSharesExtended<'a>>,
+        e: async_channel::SendError<(
+            roles_logic_sv2::mining_sv2::SubmitSharesExtended<'a>,
+            String,
+        )>,
     ) -> Self {
         Error::ChannelErrorSender(ChannelSendError::SubmitSharesExtended(e))
     }
@@ -230,6 +258,12 @@ impl<'a> From<async_channel::SendError<NewExtendedMiningJob<'a>>> for Error<'a>
     }
 }
 
+impl<'a> From<async_channel::SendError<CloseChannel<'a>>> for Error<'a> {
+    fn from(e: async_channel::SendError<CloseChannel<'a>>) -> Self {
+        Error::ChannelErrorSender(ChannelSendError::CloseChannel(e))
+    }
+}
+
 impl<'a> From<async_channel::SendError<SetCustomMiningJob<'a>>> for Error<'a> {
     fn from(e: async_channel::SendError<SetCustomMiningJob<'a>>) -> Self {
         Error::ChannelErrorSender(ChannelSendError::SetCustomMiningJob(e))
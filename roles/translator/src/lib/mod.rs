@@ -174,6 +174,10 @@ impl TranslatorSv2 {
         // `Bridge` (Sender<SetNewPrevHash<'static>>, Receiver<SetNewPrevHash<'static>>)
         let (tx_sv2_set_new_prev_hash, rx_sv2_set_new_prev_hash) = bounded(10);
 
+        // Sender/Receiver to send a SV2 `CloseChannel` message from the `Upstream` to the `Bridge`
+        // (Sender<CloseChannel<'static>>, Receiver<CloseChannel<'static>>)
+        let (tx_sv2_close_channel, rx_sv2_close_channel) = bounded(10);
+
         // Format `Upstream` connection address
         let upstream_addr = SocketAddr::new(
             IpAddr::from_str(&proxy_config.upstream_address)
@@ -186,16 +190,19 @@ impl TranslatorSv2 {
         // Instantiate a new `Upstream` (SV2 Pool)
         let upstream = match upstream_sv2::Upstream::new(
             upstream_addr,
-            proxy_config.upstream_authority_pubkey,
+            proxy_config.upstream_authority_pubkeys.clone(),
             rx_sv2_submit_shares_ext,
             tx_sv2_set_new_prev_hash,
             tx_sv2_new_ext_mining_job,
+            tx_sv2_close_channel,
             proxy_config.min_extranonce2_size,
             tx_sv2_extranonce,
             status::Sender::Upstream(tx_status.clone()),
             target.clone(),
             diff_config.clone(),
             task_collector_upstream,
+            proxy_config.worker_hashrate_tracking,
+            proxy_config.rekey_policy.into(),
         )
         .await
         {
@@ -257,11 +264,13 @@ impl TranslatorSv2 {
                 tx_sv2_submit_shares_ext,
                 rx_sv2_set_new_prev_hash,
                 rx_sv2_new_ext_mining_job,
+                rx_sv2_close_channel,
                 tx_sv1_notify.clone(),
                 status::Sender::Bridge(tx_status.clone()),
                 extended_extranonce,
                 target,
                 up_id,
+                diff_config,
                 task_collector_bridge,
             );
             proxy::Bridge::start(b.clone());
@@ -277,12 +286,13 @@ impl TranslatorSv2 {
             downstream_sv1::Downstream::accept_connections(
                 downstream_addr,
                 tx_sv1_bridge,
-                tx_sv1_notify,
                 status::Sender::DownstreamListener(tx_status.clone()),
                 b,
                 proxy_config.downstream_difficulty_config,
-                diff_config,
+                upstream,
+                proxy_config.upstream_difficulty_config.channel_mode,
                 task_collector_downstream,
+                proxy_config.admission,
             );
         }); // End of init task
         let _ =
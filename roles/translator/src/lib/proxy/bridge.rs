@@ -4,7 +4,9 @@ use super::super::{
         Error::{self, PoisonLock},
         ProxyResult,
     },
+    proxy_config::UpstreamDifficultyConfig,
     status,
+    upstream_sv2::OpenedUpstreamChannel,
 };
 use async_channel::{Receiver, Sender};
 use error_handling::handle_result;
@@ -13,13 +15,14 @@ use roles_logic_sv2::{
         ExtendedChannelKind, OnNewShare, ProxyExtendedChannelFactory, Share,
     },
     mining_sv2::{
-        ExtendedExtranonce, NewExtendedMiningJob, SetNewPrevHash, SubmitSharesExtended, Target,
+        CloseChannel, ExtendedExtranonce, NewExtendedMiningJob, SetNewPrevHash,
+        SubmitSharesExtended, Target,
     },
     parsers::Mining,
     utils::{GroupId, Mutex},
     Error as RolesLogicError,
 };
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use tokio::{sync::broadcast, task::AbortHandle};
 use tracing::{debug, error, info, warn};
 use v1::{client_to_server::Submit, server_to_client, utils::HexU32Be};
@@ -28,13 +31,16 @@ use v1::{client_to_server::Submit, server_to_client, utils::HexU32Be};
 /// translation:
 /// 1. SV1 `mining.submit` -> SV2 `SubmitSharesExtended`
 /// 2. SV2 `SetNewPrevHash` + `NewExtendedMiningJob` -> SV1 `mining.notify`
+///
+/// Every extended channel opened with the Upstream is translated on its own, for the SV1
+/// Downstreams mining on it.
 #[derive(Debug)]
 pub struct Bridge {
     /// Receives a SV1 `mining.submit` message from the Downstream role.
     rx_sv1_downstream: Receiver<DownstreamMessages>,
     /// Sends SV2 `SubmitSharesExtended` messages translated from SV1 `mining.submit` messages to
-    /// the `Upstream`.
-    tx_sv2_submit_shares_ext: Sender<SubmitSharesExtended<'static>>,
+    /// the `Upstream`, together with the name of the SV1 worker that submitted the share.
+    tx_sv2_submit_shares_ext: Sender<(SubmitSharesExtended<'static>, String)>,
     /// Receives a SV2 `SetNewPrevHash` message from the `Upstream` to be translated (along with a
     /// SV2 `NewExtendedMiningJob` message) to a SV1 `mining.submit` for the `Downstream`.
     rx_sv2_set_new_prev_hash: Receiver<SetNewPrevHash<'static>>,
@@ -42,12 +48,36 @@ pub struct Bridge {
     /// with a SV2 `SetNewPrevHash` message) to a SV1 `mining.submit` to be sent to the
     /// `Downstream`.
     rx_sv2_new_ext_mining_job: Receiver<NewExtendedMiningJob<'static>>,
-    /// Sends SV1 `mining.notify` message (translated from the SV2 `SetNewPrevHash` and
-    /// `NewExtendedMiningJob` messages stored in the `NextMiningNotify`) to the `Downstream`.
-    tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
+    /// Receives the SV2 `CloseChannel` messages sent by the Upstream for the channels opened with
+    /// `Upstream::open_channel`.
+    rx_sv2_close_channel: Receiver<CloseChannel<'static>>,
     /// Allows the bridge the ability to communicate back to the main thread any status updates
     /// that would interest the main thread for error handling
     tx_status: status::Sender,
+    /// Assigns the ids of the Downstream channels of every channel factory, so that a Downstream
+    /// channel id is unique across the Upstream channels.
+    ids: Arc<Mutex<GroupId>>,
+    /// Id of the Upstream channel opened when the proxy connected to the Upstream.
+    default_channel_id: u32,
+    /// Translation state of every Upstream channel, by Upstream channel id.
+    channels: HashMap<u32, UpstreamChannel>,
+    /// Upstream channel id of every Downstream channel.
+    downstream_channels: HashMap<u32, u32>,
+    /// Upstream channel shared by the miners with the same worker name prefix, used in the
+    /// `Hybrid` channel mode.
+    worker_groups: HashMap<String, u32>,
+    task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+}
+
+/// Translation state of an extended channel opened with the Upstream.
+#[derive(Debug)]
+pub(super) struct UpstreamChannel {
+    /// Sends SV1 `mining.notify` message (translated from the SV2 `SetNewPrevHash` and
+    /// `NewExtendedMiningJob` messages stored in the `NextMiningNotify`) to the `Downstream`s
+    /// mining on the channel.
+    tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
+    /// Keeps `tx_sv1_notify` open while no `Downstream` mines on the channel.
+    _rx_sv1_notify: broadcast::Receiver<server_to_client::Notify<'static>>,
     /// Stores the most recent SV1 `mining.notify` values to be sent to the `Downstream` upon
     /// receiving a new SV2 `SetNewPrevHash` and `NewExtendedMiningJob` messages **before** any
     /// Downstream role connects to the proxy.
@@ -59,12 +89,152 @@ pub struct Bridge {
     /// a Downstream role connects and receives the first notify values, this member field is no
     /// longer used.
     last_notify: Option<server_to_client::Notify<'static>>,
-    pub(self) channel_factory: ProxyExtendedChannelFactory,
+    pub(super) channel_factory: ProxyExtendedChannelFactory,
     future_jobs: Vec<NewExtendedMiningJob<'static>>,
     last_p_hash: Option<SetNewPrevHash<'static>>,
     target: Arc<Mutex<Vec<u8>>>,
     last_job_id: u32,
-    task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+    /// Nominal hashrate of the channel, the `Downstream`s mining on it add their hashrates.
+    difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
+}
+
+impl UpstreamChannel {
+    fn new(
+        ids: Arc<Mutex<GroupId>>,
+        extranonces: ExtendedExtranonce,
+        upstream_target: Target,
+        target: Arc<Mutex<Vec<u8>>>,
+        up_id: u32,
+        tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
+        difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
+    ) -> Self {
+        let share_per_min = 1.0;
+        Self {
+            _rx_sv1_notify: tx_sv1_notify.subscribe(),
+            tx_sv1_notify,
+            last_notify: None,
+            channel_factory: ProxyExtendedChannelFactory::new(
+                ids,
+                extranonces,
+                None,
+                share_per_min,
+                ExtendedChannelKind::Proxy { upstream_target },
+                None,
+                up_id,
+            ),
+            future_jobs: vec![],
+            last_p_hash: None,
+            target,
+            last_job_id: 0,
+            difficulty_config,
+        }
+    }
+
+    /// Translates a SV1 `mining.submit` message to a SV2 `SubmitSharesExtended` message.
+    #[allow(clippy::result_large_err)]
+    pub(super) fn translate_submit(
+        &self,
+        channel_id: u32,
+        sv1_submit: Submit,
+        version_rolling_mask: Option<HexU32Be>,
+    ) -> ProxyResult<'static, SubmitSharesExtended<'static>> {
+        let last_version = self
+            .channel_factory
+            .last_valid_job_version()
+            .ok_or(Error::RolesSv2Logic(RolesLogicError::NoValidJob))?;
+        let version = match (sv1_submit.version_bits, version_rolling_mask) {
+            // regarding version masking see https://github.com/slushpool/stratumprotocol/blob/master/stratum-extensions.mediawiki#changes-in-request-miningsubmit
+            (Some(vb), Some(mask)) => (last_version & !mask.0) | (vb.0 & mask.0),
+            (None, None) => last_version,
+            _ => return Err(Error::V1Protocol(v1::error::Error::InvalidSubmission)),
+        };
+        let mining_device_extranonce: Vec<u8> = sv1_submit.extra_nonce2.into();
+        let extranonce2 = mining_device_extranonce;
+        Ok(SubmitSharesExtended {
+            channel_id,
+            // I put 0 below cause sequence_number is not what should be TODO
+            sequence_number: 0,
+            job_id: sv1_submit.job_id.parse::<u32>()?,
+            nonce: sv1_submit.nonce.0,
+            ntime: sv1_submit.time.0,
+            version,
+            extranonce: extranonce2.try_into()?,
+        })
+    }
+
+    /// Activates the future job of the `SetNewPrevHash` and sends its `mining.notify` to the
+    /// `Downstream`s.
+    #[allow(clippy::result_large_err)]
+    fn on_new_prev_hash(
+        &mut self,
+        sv2_set_new_prev_hash: SetNewPrevHash<'static>,
+    ) -> ProxyResult<'static, ()> {
+        self.last_p_hash = Some(sv2_set_new_prev_hash.clone());
+        self.channel_factory
+            .on_new_prev_hash(sv2_set_new_prev_hash.clone())?;
+
+        let mut future_jobs = std::mem::take(&mut self.future_jobs);
+        while let Some(job) = future_jobs.pop() {
+            if job.job_id == sv2_set_new_prev_hash.job_id {
+                let j_id = job.job_id;
+                // Create the mining.notify to be sent to the Downstream.
+                let notify = crate::proxy::next_mining_notify::create_notify(
+                    sv2_set_new_prev_hash,
+                    job,
+                    true,
+                );
+
+                // Get the sender to send the mining.notify to the Downstream
+                self.tx_sv1_notify.send(notify.clone())?;
+                self.last_notify = Some(notify);
+                self.last_job_id = j_id;
+                return Ok(());
+            }
+        }
+        debug!("No future jobs for {:?}", sv2_set_new_prev_hash);
+        Ok(())
+    }
+
+    /// Stores a future job until its `SetNewPrevHash`, or sends the `mining.notify` of a job for
+    /// the current prev hash to the `Downstream`s.
+    #[allow(clippy::result_large_err)]
+    fn on_new_extended_mining_job(
+        &mut self,
+        sv2_new_extended_mining_job: NewExtendedMiningJob<'static>,
+    ) -> ProxyResult<'static, ()> {
+        // convert to non segwit jobs so we dont have to depend if miner's support segwit or not
+        self.channel_factory
+            .on_new_extended_mining_job(sv2_new_extended_mining_job.as_static().clone())?;
+
+        // If future_job=true, this job is meant for a future SetNewPrevHash that the proxy
+        // has yet to receive. Insert this new job into the job_mapper .
+        if sv2_new_extended_mining_job.is_future() {
+            self.future_jobs.push(sv2_new_extended_mining_job);
+            Ok(())
+
+        // If future_job=false, this job is meant for the current SetNewPrevHash.
+        } else {
+            // last_p_hash is an Option<SetNewPrevHash> so we need to map to the correct error type
+            // to be handled
+            let last_p_hash = self.last_p_hash.clone().ok_or(Error::RolesSv2Logic(
+                RolesLogicError::JobIsNotFutureButPrevHashNotPresent,
+            ))?;
+
+            let j_id = sv2_new_extended_mining_job.job_id;
+            // Create the mining.notify to be sent to the Downstream.
+            // clean_jobs must be false because it's not a NewPrevHash template
+            let notify = crate::proxy::next_mining_notify::create_notify(
+                last_p_hash,
+                sv2_new_extended_mining_job,
+                false,
+            );
+            // Get the sender to send the mining.notify to the Downstream
+            self.tx_sv1_notify.send(notify.clone())?;
+            self.last_notify = Some(notify);
+            self.last_job_id = j_id;
+            Ok(())
+        }
+    }
 }
 
 impl Bridge {
@@ -72,67 +242,128 @@ impl Bridge {
     /// Instantiate a new `Bridge`.
     pub fn new(
         rx_sv1_downstream: Receiver<DownstreamMessages>,
-        tx_sv2_submit_shares_ext: Sender<SubmitSharesExtended<'static>>,
+        tx_sv2_submit_shares_ext: Sender<(SubmitSharesExtended<'static>, String)>,
         rx_sv2_set_new_prev_hash: Receiver<SetNewPrevHash<'static>>,
         rx_sv2_new_ext_mining_job: Receiver<NewExtendedMiningJob<'static>>,
+        rx_sv2_close_channel: Receiver<CloseChannel<'static>>,
         tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
         tx_status: status::Sender,
         extranonces: ExtendedExtranonce,
         target: Arc<Mutex<Vec<u8>>>,
         up_id: u32,
+        difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
         task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
     ) -> Arc<Mutex<Self>> {
         let ids = Arc::new(Mutex::new(GroupId::new()));
-        let share_per_min = 1.0;
         let upstream_target: [u8; 32] =
             target.safe_lock(|t| t.clone()).unwrap().try_into().unwrap();
         let upstream_target: Target = upstream_target.into();
+        let channel = UpstreamChannel::new(
+            ids.clone(),
+            extranonces,
+            upstream_target,
+            target,
+            up_id,
+            tx_sv1_notify,
+            difficulty_config,
+        );
         Arc::new(Mutex::new(Self {
             rx_sv1_downstream,
             tx_sv2_submit_shares_ext,
             rx_sv2_set_new_prev_hash,
             rx_sv2_new_ext_mining_job,
-            tx_sv1_notify,
+            rx_sv2_close_channel,
             tx_status,
-            last_notify: None,
-            channel_factory: ProxyExtendedChannelFactory::new(
-                ids,
-                extranonces,
-                None,
-                share_per_min,
-                ExtendedChannelKind::Proxy { upstream_target },
-                None,
-                up_id,
-            ),
-            future_jobs: vec![],
-            last_p_hash: None,
-            target,
-            last_job_id: 0,
+            ids,
+            default_channel_id: up_id,
+            channels: HashMap::from([(up_id, channel)]),
+            downstream_channels: HashMap::new(),
+            worker_groups: HashMap::new(),
             task_collector,
         }))
     }
 
+    /// Id of the Upstream channel opened when the proxy connected to the Upstream.
+    pub fn default_channel_id(&self) -> u32 {
+        self.default_channel_id
+    }
+
+    /// Starts translating the jobs of an Upstream channel opened with `Upstream::open_channel`. In
+    /// the `Hybrid` channel mode the channel is shared by the miners of `worker_group`.
+    #[allow(clippy::result_large_err)]
+    pub fn on_new_upstream_channel(
+        &mut self,
+        opened: &OpenedUpstreamChannel,
+        worker_group: Option<&str>,
+    ) -> ProxyResult<'static, ()> {
+        let upstream_target: [u8; 32] = opened
+            .target
+            .safe_lock(|t| t.clone())
+            .map_err(|_| PoisonLock)?
+            .try_into()?;
+        let (tx_sv1_notify, _) = broadcast::channel(10);
+        let channel = UpstreamChannel::new(
+            self.ids.clone(),
+            opened.extranonces.clone(),
+            upstream_target.into(),
+            opened.target.clone(),
+            opened.channel_id,
+            tx_sv1_notify,
+            opened.difficulty_config.clone(),
+        );
+        self.channels.insert(opened.channel_id, channel);
+        if let Some(worker_group) = worker_group {
+            // Two miners of a new group may open a channel at the same time, the next miners join
+            // the first one
+            self.worker_groups
+                .entry(worker_group.to_string())
+                .or_insert(opened.channel_id);
+        }
+        Ok(())
+    }
+
+    /// Upstream channel shared by the miners of `worker_group`, in the `Hybrid` channel mode.
+    pub fn worker_group_channel(&self, worker_group: &str) -> Option<u32> {
+        self.worker_groups.get(worker_group).copied()
+    }
+
+    /// Opens a Downstream channel on the Upstream channel `upstream_channel_id`.
     #[allow(clippy::result_large_err)]
     pub fn on_new_sv1_connection(
         &mut self,
+        upstream_channel_id: u32,
         hash_rate: f32,
     ) -> ProxyResult<'static, OpenSv1Downstream> {
-        match self.channel_factory.new_extended_channel(0, hash_rate, 0) {
+        let channel = self.channels.get_mut(&upstream_channel_id).ok_or_else(|| {
+            Error::SubprotocolMining(format!(
+                "Bridge: unknown upstream channel {}",
+                upstream_channel_id
+            ))
+        })?;
+        match channel
+            .channel_factory
+            .new_extended_channel(0, hash_rate, 0)
+        {
             Ok(messages) => {
                 for message in messages {
                     match message {
                         Mining::OpenExtendedMiningChannelSuccess(success) => {
                             let extranonce = success.extranonce_prefix.to_vec();
                             let extranonce2_len = success.extranonce_size;
-                            self.target
+                            channel
+                                .target
                                 .safe_lock(|t| *t = success.target.to_vec())
                                 .map_err(|_e| PoisonLock)?;
+                            self.downstream_channels
+                                .insert(success.channel_id, upstream_channel_id);
                             return Ok(OpenSv1Downstream {
                                 channel_id: success.channel_id,
-                                last_notify: self.last_notify.clone(),
+                                last_notify: channel.last_notify.clone(),
                                 extranonce,
-                                target: self.target.clone(),
+                                target: channel.target.clone(),
                                 extranonce2_len,
+                                rx_sv1_notify: channel.tx_sv1_notify.subscribe(),
+                                difficulty_config: channel.difficulty_config.clone(),
                             });
                         }
                         Mining::OpenMiningChannelError(_) => todo!(),
@@ -153,14 +384,97 @@ impl Bridge {
         ))
     }
 
+    /// Forgets a disconnected Downstream channel, its extranonce is reused by the next ones.
+    /// Returns the id of its Upstream channel when no Downstream mines on it anymore and it has to
+    /// be closed, the channel opened when the proxy connected to the Upstream is never closed.
+    pub fn on_sv1_disconnection(&mut self, downstream_channel_id: u32) -> Option<u32> {
+        let upstream_channel_id = self.downstream_channels.remove(&downstream_channel_id)?;
+        let channel = self.channels.get_mut(&upstream_channel_id)?;
+        channel
+            .channel_factory
+            .remove_channel(downstream_channel_id);
+        let in_use = self
+            .downstream_channels
+            .values()
+            .any(|id| *id == upstream_channel_id);
+        if in_use || upstream_channel_id == self.default_channel_id {
+            return None;
+        }
+        self.channels.remove(&upstream_channel_id);
+        self.worker_groups
+            .retain(|_, channel_id| *channel_id != upstream_channel_id);
+        Some(upstream_channel_id)
+    }
+
+    /// Moves an authorized Downstream to the Upstream channel `upstream_channel_id`, in the
+    /// `PerMiner` and `Hybrid` channel modes. Returns the Downstream channel opened on it, and the
+    /// Upstream channel to close if the Downstream was the last one mining on its previous one.
+    #[allow(clippy::result_large_err)]
+    pub fn move_sv1_downstream(
+        &mut self,
+        downstream_channel_id: u32,
+        upstream_channel_id: u32,
+        hash_rate: f32,
+    ) -> ProxyResult<'static, (OpenSv1Downstream, Option<u32>)> {
+        let opened = self.on_new_sv1_connection(upstream_channel_id, hash_rate)?;
+        let to_close = self.on_sv1_disconnection(downstream_channel_id);
+        Ok((opened, to_close))
+    }
+
+    /// Forgets an Upstream channel closed by the Upstream. Its Downstreams are disconnected once
+    /// their `mining.notify` sender is dropped.
+    pub fn on_upstream_channel_closed(&mut self, upstream_channel_id: u32) {
+        if upstream_channel_id == self.default_channel_id
+            || self.channels.remove(&upstream_channel_id).is_none()
+        {
+            return;
+        }
+        info!("Upstream closed channel {}", upstream_channel_id);
+        self.downstream_channels
+            .retain(|_, channel_id| *channel_id != upstream_channel_id);
+        self.worker_groups
+            .retain(|_, channel_id| *channel_id != upstream_channel_id);
+    }
+
     /// Starts the tasks that receive SV1 and SV2 messages to be translated and sent to their
     /// respective roles.
     pub fn start(self_: Arc<Mutex<Self>>) {
         Self::handle_new_prev_hash(self_.clone());
         Self::handle_new_extended_mining_job(self_.clone());
+        Self::handle_close_channel(self_.clone());
         Self::handle_downstream_messages(self_);
     }
 
+    /// Receives the SV2 `CloseChannel` messages of the Upstream and forgets the closed channels.
+    fn handle_close_channel(self_: Arc<Mutex<Self>>) {
+        let (rx_sv2_close_channel, tx_status, task_collector) = self_
+            .safe_lock(|s| {
+                (
+                    s.rx_sv2_close_channel.clone(),
+                    s.tx_status.clone(),
+                    s.task_collector.clone(),
+                )
+            })
+            .unwrap();
+        let handle_close_channel = tokio::task::spawn(async move {
+            loop {
+                let close_channel = handle_result!(tx_status, rx_sv2_close_channel.recv().await);
+                handle_result!(
+                    tx_status,
+                    self_
+                        .safe_lock(|b| b.on_upstream_channel_closed(close_channel.channel_id))
+                        .map_err(|_| PoisonLock)
+                );
+            }
+        });
+        let _ = task_collector.safe_lock(|a| {
+            a.push((
+                handle_close_channel.abort_handle(),
+                "handle_close_channel".to_string(),
+            ))
+        });
+    }
+
     /// Receives a `DownstreamMessages` message from the `Downstream`, handles based on the
     /// variant received.
     fn handle_downstream_messages(self_: Arc<Mutex<Self>>) {
@@ -204,44 +518,67 @@ impl Bridge {
     ) -> ProxyResult<'static, ()> {
         self_
             .safe_lock(|b| {
-                b.channel_factory
-                    .update_target_for_channel(new_target.channel_id, new_target.new_target);
+                let upstream_channel_id = b.downstream_channels.get(&new_target.channel_id);
+                if let Some(channel) = upstream_channel_id.and_then(|id| b.channels.get_mut(id)) {
+                    channel
+                        .channel_factory
+                        .update_target_for_channel(new_target.channel_id, new_target.new_target);
+                }
             })
             .map_err(|_| PoisonLock)?;
         Ok(())
     }
     /// receives a `SubmitShareWithChannelId` and validates the shares and sends to `Upstream` if
     /// the share meets the upstream target
+    #[allow(clippy::result_large_err)]
     async fn handle_submit_shares(
         self_: Arc<Mutex<Self>>,
         share: SubmitShareWithChannelId,
     ) -> ProxyResult<'static, ()> {
-        let (tx_sv2_submit_shares_ext, target_mutex, tx_status) = self_
+        let (tx_sv2_submit_shares_ext, upstream_channel, tx_status) = self_
             .safe_lock(|s| {
+                let upstream_channel = s
+                    .downstream_channels
+                    .get(&share.channel_id)
+                    .and_then(|id| s.channels.get(id).map(|c| (*id, c.target.clone())));
                 (
                     s.tx_sv2_submit_shares_ext.clone(),
-                    s.target.clone(),
+                    upstream_channel,
                     s.tx_status.clone(),
                 )
             })
             .map_err(|_| PoisonLock)?;
+        // The Downstream may have been moved to another channel since it sent the share
+        let Some((upstream_channel_id, target_mutex)) = upstream_channel else {
+            warn!("Share from unknown channel {}", share.channel_id);
+            return Ok(());
+        };
         let upstream_target: [u8; 32] = target_mutex
             .safe_lock(|t| t.clone())
             .map_err(|_| PoisonLock)?
             .try_into()?;
         let mut upstream_target: Target = upstream_target.into();
-        self_
-            .safe_lock(|s| s.channel_factory.set_target(&mut upstream_target))
-            .map_err(|_| PoisonLock)?;
 
-        let sv2_submit = self_
+        let user_name = share.share.user_name.clone();
+        let res = self_
             .safe_lock(|s| {
-                s.translate_submit(share.channel_id, share.share, share.version_rolling_mask)
+                // Infallible, the channel was found above under the same lock
+                let channel = s
+                    .channels
+                    .get_mut(&upstream_channel_id)
+                    .ok_or(Error::RolesSv2Logic(RolesLogicError::NotFoundChannelId))?;
+                channel.channel_factory.set_target(&mut upstream_target);
+                let sv2_submit = channel.translate_submit(
+                    share.channel_id,
+                    share.share,
+                    share.version_rolling_mask,
+                )?;
+                Ok(channel
+                    .channel_factory
+                    .on_submit_shares_extended(sv2_submit))
             })
-            .map_err(|_| PoisonLock)??;
-        let res = self_
-            .safe_lock(|s| s.channel_factory.on_submit_shares_extended(sv2_submit))
-            .map_err(|_| PoisonLock);
+            .map_err(|_| PoisonLock)
+            .and_then(|res: ProxyResult<'static, _>| res);
 
         match res {
             Ok(Ok(OnNewShare::SendErrorDownstream(e))) => {
@@ -253,8 +590,9 @@ impl Bridge {
             Ok(Ok(OnNewShare::SendSubmitShareUpstream((share, _)))) => {
                 info!("SHARE MEETS UPSTREAM TARGET");
                 match share {
-                    Share::Extended(share) => {
-                        tx_sv2_submit_shares_ext.send(share).await?;
+                    Share::Extended(mut share) => {
+                        share.channel_id = upstream_channel_id;
+                        tx_sv2_submit_shares_ext.send((share, user_name)).await?;
                     }
                     // We are in an extended channel shares are extended
                     Share::Standard(_) => unreachable!(),
@@ -268,53 +606,22 @@ impl Bridge {
             // Proxy do not have JD capabilities
             Ok(Ok(OnNewShare::ShareMeetBitcoinTarget(..))) => unreachable!(),
             Ok(Err(e)) => error!("Error: {:?}", e),
-            Err(e) => {
+            Err(PoisonLock) => {
                 let _ = tx_status
                     .send(status::Status {
-                        state: status::State::BridgeShutdown(e),
+                        state: status::State::BridgeShutdown(PoisonLock),
                     })
                     .await;
             }
+            Err(e) => return Err(e),
         }
         Ok(())
     }
 
-    /// Translates a SV1 `mining.submit` message to a SV2 `SubmitSharesExtended` message.
     #[allow(clippy::result_large_err)]
-    fn translate_submit(
-        &self,
-        channel_id: u32,
-        sv1_submit: Submit,
-        version_rolling_mask: Option<HexU32Be>,
-    ) -> ProxyResult<'static, SubmitSharesExtended<'static>> {
-        let last_version = self
-            .channel_factory
-            .last_valid_job_version()
-            .ok_or(Error::RolesSv2Logic(RolesLogicError::NoValidJob))?;
-        let version = match (sv1_submit.version_bits, version_rolling_mask) {
-            // regarding version masking see https://github.com/slushpool/stratumprotocol/blob/master/stratum-extensions.mediawiki#changes-in-request-miningsubmit
-            (Some(vb), Some(mask)) => (last_version & !mask.0) | (vb.0 & mask.0),
-            (None, None) => last_version,
-            _ => return Err(Error::V1Protocol(v1::error::Error::InvalidSubmission)),
-        };
-        let mining_device_extranonce: Vec<u8> = sv1_submit.extra_nonce2.into();
-        let extranonce2 = mining_device_extranonce;
-        Ok(SubmitSharesExtended {
-            channel_id,
-            // I put 0 below cause sequence_number is not what should be TODO
-            sequence_number: 0,
-            job_id: sv1_submit.job_id.parse::<u32>()?,
-            nonce: sv1_submit.nonce.0,
-            ntime: sv1_submit.time.0,
-            version,
-            extranonce: extranonce2.try_into()?,
-        })
-    }
-
     async fn handle_new_prev_hash_(
         self_: Arc<Mutex<Self>>,
         sv2_set_new_prev_hash: SetNewPrevHash<'static>,
-        tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
     ) -> Result<(), Error<'static>> {
         while !crate::upstream_sv2::upstream::IS_NEW_JOB_HANDLED
             .load(std::sync::atomic::Ordering::SeqCst)
@@ -322,52 +629,19 @@ impl Bridge {
             tokio::task::yield_now().await;
         }
         self_
-            .safe_lock(|s| s.last_p_hash = Some(sv2_set_new_prev_hash.clone()))
-            .map_err(|_| PoisonLock)?;
-
-        let on_new_prev_hash_res = self_
-            .safe_lock(|s| {
-                s.channel_factory
-                    .on_new_prev_hash(sv2_set_new_prev_hash.clone())
-            })
-            .map_err(|_| PoisonLock)?;
-        on_new_prev_hash_res?;
-
-        let mut future_jobs = self_
-            .safe_lock(|s| {
-                let future_jobs = s.future_jobs.clone();
-                s.future_jobs = vec![];
-                future_jobs
-            })
-            .map_err(|_| PoisonLock)?;
-
-        let mut match_a_future_job = false;
-        while let Some(job) = future_jobs.pop() {
-            if job.job_id == sv2_set_new_prev_hash.job_id {
-                let j_id = job.job_id;
-                // Create the mining.notify to be sent to the Downstream.
-                let notify = crate::proxy::next_mining_notify::create_notify(
-                    sv2_set_new_prev_hash.clone(),
-                    job,
-                    true,
-                );
-
-                // Get the sender to send the mining.notify to the Downstream
-                tx_sv1_notify.send(notify.clone())?;
-                match_a_future_job = true;
-                self_
-                    .safe_lock(|s| {
-                        s.last_notify = Some(notify);
-                        s.last_job_id = j_id;
-                    })
-                    .map_err(|_| PoisonLock)?;
-                break;
-            }
-        }
-        if !match_a_future_job {
-            debug!("No future jobs for {:?}", sv2_set_new_prev_hash);
-        }
-        Ok(())
+            .safe_lock(
+                |s| match s.channels.get_mut(&sv2_set_new_prev_hash.channel_id) {
+                    Some(channel) => channel.on_new_prev_hash(sv2_set_new_prev_hash),
+                    None => {
+                        debug!(
+                            "SetNewPrevHash for closed channel {}",
+                            sv2_set_new_prev_hash.channel_id
+                        );
+                        Ok(())
+                    }
+                },
+            )
+            .map_err(|_| PoisonLock)?
     }
 
     /// Receives a SV2 `SetNewPrevHash` message from the `Upstream` and creates a SV1
@@ -379,14 +653,8 @@ impl Bridge {
     fn handle_new_prev_hash(self_: Arc<Mutex<Self>>) {
         let task_collector_handle_new_prev_hash =
             self_.safe_lock(|b| b.task_collector.clone()).unwrap();
-        let (tx_sv1_notify, rx_sv2_set_new_prev_hash, tx_status) = self_
-            .safe_lock(|s| {
-                (
-                    s.tx_sv1_notify.clone(),
-                    s.rx_sv2_set_new_prev_hash.clone(),
-                    s.tx_status.clone(),
-                )
-            })
+        let (rx_sv2_set_new_prev_hash, tx_status) = self_
+            .safe_lock(|s| (s.rx_sv2_set_new_prev_hash.clone(), s.tx_status.clone()))
             .unwrap();
         debug!("Starting handle_new_prev_hash task");
         let handle_new_prev_hash = tokio::task::spawn(async move {
@@ -400,12 +668,7 @@ impl Bridge {
                 );
                 handle_result!(
                     tx_status.clone(),
-                    Self::handle_new_prev_hash_(
-                        self_.clone(),
-                        sv2_set_new_prev_hash,
-                        tx_sv1_notify.clone(),
-                    )
-                    .await
+                    Self::handle_new_prev_hash_(self_.clone(), sv2_set_new_prev_hash).await
                 )
             }
         });
@@ -417,57 +680,27 @@ impl Bridge {
         });
     }
 
+    #[allow(clippy::result_large_err)]
     async fn handle_new_extended_mining_job_(
         self_: Arc<Mutex<Self>>,
         sv2_new_extended_mining_job: NewExtendedMiningJob<'static>,
-        tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
     ) -> Result<(), Error<'static>> {
-        // convert to non segwit jobs so we dont have to depend if miner's support segwit or not
         self_
-            .safe_lock(|s| {
-                s.channel_factory
-                    .on_new_extended_mining_job(sv2_new_extended_mining_job.as_static().clone())
-            })
-            .map_err(|_| PoisonLock)??;
-
-        // If future_job=true, this job is meant for a future SetNewPrevHash that the proxy
-        // has yet to receive. Insert this new job into the job_mapper .
-        if sv2_new_extended_mining_job.is_future() {
-            self_
-                .safe_lock(|s| s.future_jobs.push(sv2_new_extended_mining_job.clone()))
-                .map_err(|_| PoisonLock)?;
-            Ok(())
-
-        // If future_job=false, this job is meant for the current SetNewPrevHash.
-        } else {
-            let last_p_hash_option = self_
-                .safe_lock(|s| s.last_p_hash.clone())
-                .map_err(|_| PoisonLock)?;
-
-            // last_p_hash is an Option<SetNewPrevHash> so we need to map to the correct error type
-            // to be handled
-            let last_p_hash = last_p_hash_option.ok_or(Error::RolesSv2Logic(
-                RolesLogicError::JobIsNotFutureButPrevHashNotPresent,
-            ))?;
-
-            let j_id = sv2_new_extended_mining_job.job_id;
-            // Create the mining.notify to be sent to the Downstream.
-            // clean_jobs must be false because it's not a NewPrevHash template
-            let notify = crate::proxy::next_mining_notify::create_notify(
-                last_p_hash,
-                sv2_new_extended_mining_job.clone(),
-                false,
-            );
-            // Get the sender to send the mining.notify to the Downstream
-            tx_sv1_notify.send(notify.clone())?;
-            self_
-                .safe_lock(|s| {
-                    s.last_notify = Some(notify);
-                    s.last_job_id = j_id;
-                })
-                .map_err(|_| PoisonLock)?;
-            Ok(())
-        }
+            .safe_lock(
+                |s| match s.channels.get_mut(&sv2_new_extended_mining_job.channel_id) {
+                    Some(channel) => {
+                        channel.on_new_extended_mining_job(sv2_new_extended_mining_job)
+                    }
+                    None => {
+                        debug!(
+                            "NewExtendedMiningJob for closed channel {}",
+                            sv2_new_extended_mining_job.channel_id
+                        );
+                        Ok(())
+                    }
+                },
+            )
+            .map_err(|_| PoisonLock)?
     }
 
     /// Receives a SV2 `NewExtendedMiningJob` message from the `Upstream`. If `future_job=true`,
@@ -481,14 +714,8 @@ impl Bridge {
     fn handle_new_extended_mining_job(self_: Arc<Mutex<Self>>) {
         let task_collector_new_extended_mining_job =
             self_.safe_lock(|b| b.task_collector.clone()).unwrap();
-        let (tx_sv1_notify, rx_sv2_new_ext_mining_job, tx_status) = self_
-            .safe_lock(|s| {
-                (
-                    s.tx_sv1_notify.clone(),
-                    s.rx_sv2_new_ext_mining_job.clone(),
-                    s.tx_status.clone(),
-                )
-            })
+        let (rx_sv2_new_ext_mining_job, tx_status) = self_
+            .safe_lock(|s| (s.rx_sv2_new_ext_mining_job.clone(), s.tx_status.clone()))
             .unwrap();
         debug!("Starting handle_new_extended_mining_job task");
         let handle_new_extended_mining_job = tokio::task::spawn(async move {
@@ -507,7 +734,6 @@ impl Bridge {
                     Self::handle_new_extended_mining_job_(
                         self_.clone(),
                         sv2_new_extended_mining_job,
-                        tx_sv1_notify.clone(),
                     )
                     .await
                 );
@@ -523,17 +749,31 @@ impl Bridge {
         });
     }
 }
+
+/// Part of the worker name identifying the miners that share an Upstream channel in the `Hybrid`
+/// channel mode, e.g. `account` for `account.worker1`.
+pub fn worker_prefix(worker_name: &str) -> &str {
+    worker_name
+        .split_once('.')
+        .map_or(worker_name, |(prefix, _)| prefix)
+}
+
 pub struct OpenSv1Downstream {
     pub channel_id: u32,
     pub last_notify: Option<server_to_client::Notify<'static>>,
     pub extranonce: Vec<u8>,
     pub target: Arc<Mutex<Vec<u8>>>,
     pub extranonce2_len: u16,
+    /// Receives the `mining.notify` of the Upstream channel of the Downstream.
+    pub rx_sv1_notify: broadcast::Receiver<server_to_client::Notify<'static>>,
+    /// Nominal hashrate of the Upstream channel of the Downstream.
+    pub difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::proxy_config::UpstreamChannelMode;
     use async_channel::bounded;
     use stratum_common::bitcoin::{absolute::LockTime, consensus, transaction::Version};
 
@@ -543,7 +783,7 @@ mod test {
         #[allow(dead_code)]
         pub struct BridgeInterface {
             pub tx_sv1_submit: Sender<DownstreamMessages>,
-            pub rx_sv2_submit_shares_ext: Receiver<SubmitSharesExtended<'static>>,
+            pub rx_sv2_submit_shares_ext: Receiver<(SubmitSharesExtended<'static>, String)>,
             pub tx_sv2_set_new_prev_hash: Sender<SetNewPrevHash<'static>>,
             pub tx_sv2_new_ext_mining_job: Sender<NewExtendedMiningJob<'static>>,
             pub rx_sv1_notify: broadcast::Receiver<server_to_client::Notify<'static>>,
@@ -556,6 +796,7 @@ mod test {
             let (tx_sv2_submit_shares_ext, rx_sv2_submit_shares_ext) = bounded(1);
             let (tx_sv2_set_new_prev_hash, rx_sv2_set_new_prev_hash) = bounded(1);
             let (tx_sv2_new_ext_mining_job, rx_sv2_new_ext_mining_job) = bounded(1);
+            let (_tx_sv2_close_channel, rx_sv2_close_channel) = bounded(1);
             let (tx_sv1_notify, rx_sv1_notify) = broadcast::channel(1);
             let (tx_status, _rx_status) = bounded(1);
             let upstream_target = vec![
@@ -576,11 +817,18 @@ mod test {
                 tx_sv2_submit_shares_ext,
                 rx_sv2_set_new_prev_hash,
                 rx_sv2_new_ext_mining_job,
+                rx_sv2_close_channel,
                 tx_sv1_notify,
                 status::Sender::Bridge(tx_status),
                 extranonces,
                 Arc::new(Mutex::new(upstream_target)),
                 1,
+                Arc::new(Mutex::new(UpstreamDifficultyConfig::new(
+                    60,
+                    0.0,
+                    0,
+                    UpstreamChannelMode::Aggregate,
+                ))),
                 task_collector,
             );
             (b, interface)
@@ -612,6 +860,7 @@ mod test {
         bridge
             .safe_lock(|bridge| {
                 let channel_id = 1;
+                let channel = bridge.channels.get_mut(&channel_id).unwrap();
                 let out_id = bitcoin::hashes::sha256d::Hash::from_slice(&[
                     0_u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
                     0, 0, 0, 0, 0, 0, 0,
@@ -634,7 +883,7 @@ mod test {
                     output: vec![],
                 };
                 let tx = consensus::serialize(&tx);
-                let _down = bridge
+                let _down = channel
                     .channel_factory
                     .add_standard_channel(0, 10_000_000_000.0, true, 1)
                     .unwrap();
@@ -649,7 +898,7 @@ mod test {
                     min_ntime: 989898,
                     nbits: 9,
                 };
-                bridge.channel_factory.on_new_prev_hash(prev_hash).unwrap();
+                channel.channel_factory.on_new_prev_hash(prev_hash).unwrap();
                 let now = std::time::SystemTime::now()
                     .duration_since(std::time::UNIX_EPOCH)
                     .unwrap()
@@ -664,14 +913,14 @@ mod test {
                     coinbase_tx_prefix: tx[0..42].to_vec().try_into().unwrap(),
                     coinbase_tx_suffix: tx[58..].to_vec().try_into().unwrap(),
                 };
-                bridge
+                channel
                     .channel_factory
                     .on_new_extended_mining_job(new_mining_job.clone())
                     .unwrap();
 
                 // pass sv1_submit into Bridge::translate_submit
                 let sv1_submit = test_utils::create_sv1_submit(0);
-                let sv2_message = bridge
+                let sv2_message = channel
                     .translate_submit(channel_id, sv1_submit, None)
                     .unwrap();
                 // assert sv2 message equals sv1 with version bits added
@@ -682,4 +931,38 @@ mod test {
             })
             .unwrap();
     }
+
+    #[test]
+    fn test_downstream_channels_on_default_channel() {
+        assert_eq!(worker_prefix("account.worker1"), "account");
+        assert_eq!(worker_prefix("account"), "account");
+
+        let extranonces = ExtendedExtranonce::new(0..6, 6..8, 8..16, None)
+            .expect("Failed to create ExtendedExtranonce with valid ranges");
+        let (bridge, _) = test_utils::create_bridge(extranonces);
+        bridge
+            .safe_lock(|bridge| {
+                let first = bridge.on_new_sv1_connection(1, 10_000.0).unwrap();
+                let second = bridge.on_new_sv1_connection(1, 10_000.0).unwrap();
+                assert_ne!(first.channel_id, second.channel_id);
+                assert_ne!(first.extranonce, second.extranonce);
+
+                // The channel opened when connecting to the Upstream is never closed
+                assert_eq!(bridge.on_sv1_disconnection(first.channel_id), None);
+                assert_eq!(bridge.on_sv1_disconnection(first.channel_id), None);
+                bridge.on_upstream_channel_closed(1);
+                assert_eq!(bridge.worker_group_channel("account"), None);
+
+                // The extranonce of a disconnected Downstream is reused
+                let third = bridge.on_new_sv1_connection(1, 10_000.0).unwrap();
+                assert_eq!(third.extranonce, first.extranonce);
+                let (moved, to_close) = bridge
+                    .move_sv1_downstream(second.channel_id, 1, 10_000.0)
+                    .unwrap();
+                assert_eq!(to_close, None);
+                assert!(!bridge.downstream_channels.contains_key(&second.channel_id));
+                assert_eq!(bridge.downstream_channels.get(&moved.channel_id), Some(&1));
+            })
+            .unwrap();
+    }
 }
@@ -1,3 +1,3 @@
 pub mod bridge;
 pub mod next_mining_notify;
-pub use bridge::Bridge;
+pub use bridge::{Bridge, OpenSv1Downstream};
@@ -1,11 +1,18 @@
 use key_utils::Secp256k1PublicKey;
-use serde::Deserialize;
+use network_helpers_sv2::{admission::AdmissionConfig, RekeyConfig};
+use serde::{de::Error as _, Deserialize, Deserializer};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ProxyConfig {
     pub upstream_address: String,
     pub upstream_port: u16,
-    pub upstream_authority_pubkey: Secp256k1PublicKey,
+    /// Authority public keys accepted for the Upstream certificate. Several keys can be pinned
+    /// while the Upstream rotates its authority key.
+    #[serde(
+        alias = "upstream_authority_pubkey",
+        deserialize_with = "config_helpers::one_or_many"
+    )]
+    pub upstream_authority_pubkeys: Vec<Secp256k1PublicKey>,
     pub downstream_address: String,
     pub downstream_port: u16,
     pub max_supported_version: u16,
@@ -13,12 +20,22 @@ pub struct ProxyConfig {
     pub min_extranonce2_size: u16,
     pub downstream_difficulty_config: DownstreamDifficultyConfig,
     pub upstream_difficulty_config: UpstreamDifficultyConfig,
+    /// Request the worker-specific hashrate tracking extension to the Upstream, sending the SV1
+    /// worker name of every share in a `user_identity` TLV field.
+    #[serde(default = "bool::default")]
+    pub worker_hashrate_tracking: bool,
+    /// Limits applied to the SV1 downstream connections.
+    #[serde(default)]
+    pub admission: AdmissionConfig,
+    /// When the upstream connection is rekeyed, never by default.
+    #[serde(default)]
+    pub rekey_policy: RekeyConfig,
 }
 
 pub struct UpstreamConfig {
     address: String,
     port: u16,
-    authority_pubkey: Secp256k1PublicKey,
+    authority_pubkeys: Vec<Secp256k1PublicKey>,
     difficulty_config: UpstreamDifficultyConfig,
 }
 
@@ -26,13 +43,13 @@ impl UpstreamConfig {
     pub fn new(
         address: String,
         port: u16,
-        authority_pubkey: Secp256k1PublicKey,
+        authority_pubkeys: Vec<Secp256k1PublicKey>,
         difficulty_config: UpstreamDifficultyConfig,
     ) -> Self {
         Self {
             address,
             port,
-            authority_pubkey,
+            authority_pubkeys,
             difficulty_config,
         }
     }
@@ -65,7 +82,7 @@ impl ProxyConfig {
         Self {
             upstream_address: upstream.address,
             upstream_port: upstream.port,
-            upstream_authority_pubkey: upstream.authority_pubkey,
+            upstream_authority_pubkeys: upstream.authority_pubkeys,
             downstream_address: downstream.address,
             downstream_port: downstream.port,
             max_supported_version,
@@ -73,6 +90,9 @@ impl ProxyConfig {
             min_extranonce2_size,
             downstream_difficulty_config: downstream.difficulty_config,
             upstream_difficulty_config: upstream.difficulty_config,
+            worker_hashrate_tracking: false,
+            admission: AdmissionConfig::default(),
+            rekey_policy: RekeyConfig::default(),
         }
     }
 }
@@ -115,8 +135,13 @@ pub struct UpstreamDifficultyConfig {
     pub channel_nominal_hashrate: f32,
     #[serde(default = "u64::default")]
     pub timestamp_of_last_update: u64,
-    #[serde(default = "bool::default")]
-    pub should_aggregate: bool,
+    /// How the SV1 miners are spread over the extended channels opened with the Upstream.
+    #[serde(
+        default,
+        alias = "should_aggregate",
+        deserialize_with = "deserialize_channel_mode"
+    )]
+    pub channel_mode: UpstreamChannelMode,
 }
 
 impl UpstreamDifficultyConfig {
@@ -124,13 +149,129 @@ impl UpstreamDifficultyConfig {
         channel_diff_update_interval: u32,
         channel_nominal_hashrate: f32,
         timestamp_of_last_update: u64,
-        should_aggregate: bool,
+        channel_mode: UpstreamChannelMode,
     ) -> Self {
         Self {
             channel_diff_update_interval,
             channel_nominal_hashrate,
             timestamp_of_last_update,
-            should_aggregate,
+            channel_mode,
         }
     }
 }
+
+/// Mapping of the SV1 miners on the SV2 extended channels opened with the Upstream.
+///
+/// Every extended channel is updated with the sum of the hashrates of its miners, so the more
+/// channels are opened, the finer the hashrate the Upstream sees.
+///
+/// Outside of the `Aggregate` mode, a miner starts on the aggregate channel and its own channel
+/// is opened once it is authorized, with its worker name (or prefix) as user identity. The miner
+/// is then moved there with a `mining.set_extranonce`, so it has to send
+/// `mining.extranonce.subscribe`, otherwise it stays on the aggregate channel.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamChannelMode {
+    /// All the miners share the channel opened when the proxy connects to the Upstream.
+    #[default]
+    Aggregate,
+    /// Every miner connection gets its own channel, closed when the miner disconnects.
+    PerMiner,
+    /// Miners whose worker names share the same prefix, the part before the first `.`, share a
+    /// channel.
+    Hybrid,
+}
+
+/// Reads `channel_mode`, or the `should_aggregate` boolean it replaced: `true` is the `Aggregate`
+/// mode and `false` the `PerMiner` mode.
+fn deserialize_channel_mode<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<UpstreamChannelMode, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ChannelMode {
+        Mode(UpstreamChannelMode),
+        ShouldAggregate(bool),
+    }
+    match ChannelMode::deserialize(deserializer) {
+        Ok(ChannelMode::Mode(mode)) => Ok(mode),
+        Ok(ChannelMode::ShouldAggregate(true)) => Ok(UpstreamChannelMode::Aggregate),
+        Ok(ChannelMode::ShouldAggregate(false)) => Ok(UpstreamChannelMode::PerMiner),
+        Err(_) => Err(D::Error::custom(
+            "invalid channel_mode, expected \"aggregate\", \"per_miner\" or \"hybrid\"",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ext_config::{Config, File, FileFormat};
+
+    const EXAMPLE: &str = "config-examples/tproxy-config-hosted-pool-example.toml";
+
+    fn load(overrides: &str) -> ProxyConfig {
+        let example = std::fs::read_to_string(EXAMPLE).unwrap();
+        let example: String = example
+            .lines()
+            .filter(|line| !line.starts_with("upstream_authority_pubkeys"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Config::builder()
+            .add_source(File::from_str(
+                &format!("{}\n{}", overrides, example),
+                FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn upstream_authority_pubkeys_accept_a_list() {
+        let config = load(
+            r#"upstream_authority_pubkeys = ["9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72", "9bwHCYnjhbHm4AS3pWg9MtAH83mzWohoJJJDELYBqZhDNqszDLc"]"#,
+        );
+        assert_eq!(config.upstream_authority_pubkeys.len(), 2);
+    }
+
+    #[test]
+    fn upstream_authority_pubkey_is_still_accepted() {
+        let config = load(
+            r#"upstream_authority_pubkey = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72""#,
+        );
+        assert_eq!(config.upstream_authority_pubkeys.len(), 1);
+    }
+
+    fn load_channel_mode(channel_mode: &str) -> UpstreamChannelMode {
+        let example = std::fs::read_to_string(EXAMPLE)
+            .unwrap()
+            .replace("# channel_mode = \"aggregate\"", channel_mode);
+        Config::builder()
+            .add_source(File::from_str(&example, FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize::<ProxyConfig>()
+            .unwrap()
+            .upstream_difficulty_config
+            .channel_mode
+    }
+
+    #[test]
+    fn channel_mode_replaces_should_aggregate() {
+        assert_eq!(load_channel_mode(""), UpstreamChannelMode::Aggregate);
+        assert_eq!(
+            load_channel_mode(r#"channel_mode = "hybrid""#),
+            UpstreamChannelMode::Hybrid
+        );
+        assert_eq!(
+            load_channel_mode("should_aggregate = true"),
+            UpstreamChannelMode::Aggregate
+        );
+        assert_eq!(
+            load_channel_mode("should_aggregate = false"),
+            UpstreamChannelMode::PerMiner
+        );
+    }
+}
@@ -141,6 +141,10 @@ pub async fn handle_error(
         Error::Io(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
         // Errors on bad `String` to `int` conversion.
         Error::ParseInt(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
+        // Errors from `network_helpers_sv2` crate.
+        Error::NetworkHelpers(_) => {
+            send_status(sender, e, error_handling::ErrorBranch::Break).await
+        }
         // Errors from `roles_logic_sv2` crate.
         Error::RolesSv2Logic(_) => send_status(sender, e, error_handling::ErrorBranch::Break).await,
         Error::UpstreamIncoming(_) => {
@@ -180,7 +184,7 @@ pub async fn handle_error(
         Error::TargetError(_) => {
             send_status(sender, e, error_handling::ErrorBranch::Continue).await
         }
-        Error::Sv1MessageTooLong => {
+        Error::Sv1MessageTooLong | Error::Sv1MessageRateExceeded => {
             send_status(sender, e, error_handling::ErrorBranch::Break).await
         }
     }
@@ -11,49 +11,72 @@ use roles_logic_sv2::{
 use std::{sync::Arc, time::Duration};
 
 impl Upstream {
-    /// this function checks if the elapsed time since the last update has surpassed the config
+    /// this function checks if the elapsed time since the last update has surpassed the config,
+    /// and sends an `UpdateChannel` for every channel whose nominal hashrate changed
     pub(super) async fn try_update_hashrate(self_: Arc<Mutex<Self>>) -> ProxyResult<'static, ()> {
-        let (channel_id_option, diff_mgmt, tx_frame, last_sent_hashrate) = self_
+        let (channel_id_option, channels, diff_mgmt, tx_frame) = self_
             .safe_lock(|u| {
+                let channels: Vec<_> = u
+                    .channels
+                    .iter()
+                    .map(|(channel_id, channel)| {
+                        (
+                            *channel_id,
+                            channel.difficulty_config.clone(),
+                            channel.last_sent_hashrate,
+                        )
+                    })
+                    .collect();
                 (
                     u.channel_id,
+                    channels,
                     u.difficulty_config.clone(),
                     u.connection.sender.clone(),
-                    u.last_sent_hashrate,
                 )
             })
             .map_err(|_e| PoisonLock)?;
 
-        let channel_id = channel_id_option.ok_or(super::super::error::Error::RolesSv2Logic(
+        channel_id_option.ok_or(super::super::error::Error::RolesSv2Logic(
             RolesLogicError::NotFoundChannelId,
         ))?;
 
-        let (timeout, new_hashrate) = diff_mgmt
-            .safe_lock(|d| (d.channel_diff_update_interval, d.channel_nominal_hashrate))
+        let timeout = diff_mgmt
+            .safe_lock(|d| d.channel_diff_update_interval)
             .map_err(|_e| PoisonLock)?;
 
-        let has_changed = Some(new_hashrate) != last_sent_hashrate;
-
-        if has_changed {
-            // Send UpdateChannel only if hashrate actually changed
-            let update_channel = UpdateChannel {
-                channel_id,
-                nominal_hash_rate: new_hashrate,
-                maximum_target: u256_from_int(u64::MAX),
-            };
-            let message = Message::Mining(Mining::UpdateChannel(update_channel));
-            let either_frame: StdFrame = message.try_into()?;
-            let frame: EitherFrame = either_frame.into();
-
-            tx_frame.send(frame).await.map_err(|e| {
-                super::super::error::Error::ChannelErrorSender(
-                    super::super::error::ChannelSendError::General(e.to_string()),
-                )
-            })?;
-
-            self_
-                .safe_lock(|u| u.last_sent_hashrate = Some(new_hashrate))
+        for (channel_id, channel_diff_mgmt, last_sent_hashrate) in channels {
+            let new_hashrate = channel_diff_mgmt
+                .safe_lock(|d| d.channel_nominal_hashrate)
                 .map_err(|_e| PoisonLock)?;
+
+            let has_changed = Some(new_hashrate) != last_sent_hashrate;
+
+            if has_changed {
+                // Send UpdateChannel only if hashrate actually changed
+                let update_channel = UpdateChannel {
+                    channel_id,
+                    nominal_hash_rate: new_hashrate,
+                    maximum_target: u256_from_int(u64::MAX),
+                };
+                let message = Message::Mining(Mining::UpdateChannel(update_channel));
+                let either_frame: StdFrame = message.try_into()?;
+                let frame: EitherFrame = either_frame.into();
+
+                tx_frame.send(frame).await.map_err(|e| {
+                    super::super::error::Error::ChannelErrorSender(
+                        super::super::error::ChannelSendError::General(e.to_string()),
+                    )
+                })?;
+
+                self_
+                    .safe_lock(|u| {
+                        // The channel may have been closed in the meantime
+                        if let Some(channel) = u.channels.get_mut(&channel_id) {
+                            channel.last_sent_hashrate = Some(new_hashrate);
+                        }
+                    })
+                    .map_err(|_e| PoisonLock)?;
+            }
         }
 
         // Always sleep, regardless of update
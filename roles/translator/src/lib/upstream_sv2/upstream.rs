@@ -8,36 +8,41 @@ use crate::{
     status,
     upstream_sv2::{EitherFrame, Message, StdFrame, UpstreamConnection},
 };
-use async_channel::{Receiver, Sender};
+use async_channel::{bounded, Receiver, Sender};
 use binary_sv2::u256_from_int;
-use codec_sv2::{HandshakeRole, Initiator};
+use codec_sv2::{HandshakeRole, Initiator, RekeyPolicy};
+use const_sv2::EXTENSION_TYPE_WORKER_HASHRATE_TRACKING;
 use error_handling::handle_result;
 use key_utils::Secp256k1PublicKey;
-use network_helpers_sv2::noise_connection::Connection;
+use network_helpers_sv2::{extensions::negotiate_extensions, noise_connection::Connection};
 use roles_logic_sv2::{
-    common_messages_sv2::{Protocol, SetupConnection},
+    common_messages_sv2::{
+        Protocol, RequestExtensionsError, RequestExtensionsSuccess, SetupConnection,
+    },
     common_properties::{IsMiningUpstream, IsUpstream},
     handlers::{
         common::{ParseCommonMessagesFromUpstream, SendTo as SendToCommon},
+        extensions::{ParseExtensionsNegotiationFromUpstream, SendTo as SendToExtensions},
         mining::{ParseMiningMessagesFromUpstream, SendTo},
     },
     mining_sv2::{
-        ExtendedExtranonce, Extranonce, NewExtendedMiningJob, OpenExtendedMiningChannel,
-        SetNewPrevHash, SubmitSharesExtended,
+        CloseChannel, ExtendedExtranonce, Extranonce, NewExtendedMiningJob,
+        OpenExtendedMiningChannel, SetNewPrevHash, SubmitSharesExtended, Tlv,
     },
-    parsers::Mining,
+    parsers::{frame_with_tlvs, Mining},
     utils::Mutex,
     Error as RolesLogicError,
     Error::NoUpstreamsConnected,
 };
 use std::{
+    collections::{HashMap, HashSet},
     net::SocketAddr,
     sync::{atomic::AtomicBool, Arc},
 };
 use tokio::{
     net::TcpStream,
     task::AbortHandle,
-    time::{sleep, Duration},
+    time::{sleep, timeout, Duration},
 };
 use tracing::{debug, error, info, warn};
 
@@ -48,6 +53,11 @@ use roles_logic_sv2::{
 use stratum_common::bitcoin::BlockHash;
 
 pub static IS_NEW_JOB_HANDLED: AtomicBool = AtomicBool::new(true);
+
+/// Time given to the SV2 Upstream role to answer an `OpenExtendedMiningChannel` sent by
+/// `Upstream::open_channel`.
+const OPEN_CHANNEL_TIMEOUT_SECS: u64 = 10;
+
 /// Represents the currently active `prevhash` of the mining job being worked on OR being submitted
 /// from the Downstream role.
 #[derive(Debug, Clone)]
@@ -59,28 +69,69 @@ struct PrevHash {
     nbits: u32,
 }
 
+/// State of an extended channel opened with the SV2 Upstream role.
+#[derive(Debug, Clone)]
+pub(super) struct UpstreamChannel {
+    /// Identifier of the last job of the channel, as provided by the `NewExtendedMiningJob`
+    /// message.
+    job_id: Option<u32>,
+    /// Target of the channel, set by the `OpenExtendedMiningChannelSuccess` message and then
+    /// updated by the `SetTarget` messages.
+    target: Arc<Mutex<Vec<u8>>>,
+    /// Nominal hashrate of the channel, the sum of the hashrates of the miners mining on it.
+    pub(super) difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
+    /// Tracks the most recently sent nominal hashrate to prevent unnecessary updates.
+    pub(super) last_sent_hashrate: Option<f32>,
+}
+
+/// Extended channel opened with [`Upstream::open_channel`].
+#[derive(Debug)]
+pub struct OpenedUpstreamChannel {
+    pub channel_id: u32,
+    /// Extranonce space of the channel, split between the miners mining on it.
+    pub extranonces: ExtendedExtranonce,
+    pub target: Arc<Mutex<Vec<u8>>>,
+    pub difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
+    /// The `Upstream` does not handle the next messages of the SV2 Upstream role until this is
+    /// dropped, so that the first jobs of the channel are handled once the channel is known by
+    /// the `Bridge`.
+    _registered: Sender<()>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Upstream {
     /// Newly assigned identifier of the channel, stable for the whole lifetime of the connection,
     /// e.g. it is used for broadcasting new jobs by the `NewExtendedMiningJob` message.
     pub(super) channel_id: Option<u32>,
-    /// Identifier of the job as provided by the `NewExtendedMiningJob` message.
-    job_id: Option<u32>,
+    /// Every extended channel opened with the SV2 Upstream role, including the one opened by
+    /// `connect`.
+    pub(super) channels: HashMap<u32, UpstreamChannel>,
+    /// Channels requested with `open_channel` and not opened yet, by request id, with the
+    /// requested nominal hashrate.
+    pending_channels: HashMap<u32, (f32, Sender<OpenedUpstreamChannel>)>,
+    /// Requests that `open_channel` stopped waiting for, the channel is closed if the SV2 Upstream
+    /// role opens it anyway.
+    timed_out_requests: HashSet<u32>,
+    /// Request id of the last `OpenExtendedMiningChannel` message, 0 is the one sent by `connect`.
+    last_request_id: u32,
     /// Identifier of the job as provided by the ` SetCustomMiningJobSucces` message
     last_job_id: Option<u32>,
     /// Bytes used as implicit first part of `extranonce`.
     extranonce_prefix: Option<Vec<u8>>,
     /// Represents a connection to a SV2 Upstream role.
     pub(super) connection: UpstreamConnection,
-    /// Receives SV2 `SubmitSharesExtended` messages translated from SV1 `mining.submit` messages.
-    /// Translated by and sent from the `Bridge`.
-    rx_sv2_submit_shares_ext: Receiver<SubmitSharesExtended<'static>>,
+    /// Receives SV2 `SubmitSharesExtended` messages translated from SV1 `mining.submit` messages,
+    /// together with the name of the SV1 worker. Translated by and sent from the `Bridge`.
+    rx_sv2_submit_shares_ext: Receiver<(SubmitSharesExtended<'static>, String)>,
     /// Sends SV2 `SetNewPrevHash` messages to be translated (along with SV2 `NewExtendedMiningJob`
     /// messages) into SV1 `mining.notify` messages. Received and translated by the `Bridge`.
     tx_sv2_set_new_prev_hash: Sender<SetNewPrevHash<'static>>,
     /// Sends SV2 `NewExtendedMiningJob` messages to be translated (along with SV2 `SetNewPrevHash`
     /// messages) into SV1 `mining.notify` messages. Received and translated by the `Bridge`.
     tx_sv2_new_ext_mining_job: Sender<NewExtendedMiningJob<'static>>,
+    /// Sends the SV2 `CloseChannel` messages of the channels opened with `open_channel` to the
+    /// `Bridge`, which disconnects the Downstreams mining on them.
+    tx_sv2_close_channel: Sender<CloseChannel<'static>>,
     /// Sends the extranonce1 and the channel id received in the SV2
     /// `OpenExtendedMiningChannelSuccess` message to be used by the `Downstream` and sent to
     /// the Downstream role in a SV2 `mining.subscribe` response message. Passed to the
@@ -94,8 +145,6 @@ pub struct Upstream {
     /// messages. Passed to the `Downstream` on connection creation and sent to the Downstream role
     /// via the SV1 `mining.set_difficulty` message.
     target: Arc<Mutex<Vec<u8>>>,
-    /// Tracks the most recently sent nominal hashrate to prevent unnecessary updates.
-    pub last_sent_hashrate: Option<f32>,
     /// Minimum `extranonce2` size. Initially requested in the `proxy-config.toml`, and ultimately
     /// set by the SV2 Upstream via the SV2 `OpenExtendedMiningChannelSuccess` message.
     pub min_extranonce_size: u16,
@@ -106,6 +155,12 @@ pub struct Upstream {
     // than the configured percentage
     pub(super) difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
     task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+    /// Set from the `proxy-config.toml`, true if the worker-specific hashrate tracking extension
+    /// has to be requested on every connection.
+    worker_hashrate_tracking: bool,
+    /// True if the SV2 Upstream accepted the worker-specific hashrate tracking extension, in which
+    /// case every share carries the SV1 worker name.
+    worker_hashrate_tracking_accepted: bool,
 }
 
 impl PartialEq for Upstream {
@@ -123,16 +178,19 @@ impl Upstream {
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
         address: SocketAddr,
-        authority_public_key: Secp256k1PublicKey,
-        rx_sv2_submit_shares_ext: Receiver<SubmitSharesExtended<'static>>,
+        authority_public_keys: Vec<Secp256k1PublicKey>,
+        rx_sv2_submit_shares_ext: Receiver<(SubmitSharesExtended<'static>, String)>,
         tx_sv2_set_new_prev_hash: Sender<SetNewPrevHash<'static>>,
         tx_sv2_new_ext_mining_job: Sender<NewExtendedMiningJob<'static>>,
+        tx_sv2_close_channel: Sender<CloseChannel<'static>>,
         min_extranonce_size: u16,
         tx_sv2_extranonce: Sender<(ExtendedExtranonce, u32)>,
         tx_status: status::Sender,
         target: Arc<Mutex<Vec<u8>>>,
         difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
         task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        worker_hashrate_tracking: bool,
+        rekey_policy: RekeyPolicy,
     ) -> ProxyResult<'static, Arc<Mutex<Self>>> {
         // Connect to the SV2 Upstream role retry connection every 5 seconds.
         let socket = loop {
@@ -149,8 +207,11 @@ impl Upstream {
             }
         };
 
-        let pub_key: Secp256k1PublicKey = authority_public_key;
-        let initiator = Initiator::from_raw_k(pub_key.into_bytes())?;
+        let pub_keys: Vec<[u8; 32]> = authority_public_keys
+            .iter()
+            .map(|key| key.into_bytes())
+            .collect();
+        let initiator = Initiator::from_raw_ks(&pub_keys)?;
 
         info!(
             "PROXY SERVER - ACCEPTING FROM UPSTREAM: {}",
@@ -158,9 +219,13 @@ impl Upstream {
         );
 
         // Channel to send and receive messages to the SV2 Upstream role
-        let (receiver, sender) = Connection::new(socket, HandshakeRole::Initiator(initiator))
-            .await
-            .unwrap();
+        let (receiver, sender) = Connection::new_with_rekey_policy(
+            socket,
+            HandshakeRole::Initiator(initiator),
+            rekey_policy,
+        )
+        .await
+        .unwrap();
         // Initialize `UpstreamConnection` with channel for SV2 Upstream role communication and
         // channel for downstream Translator Proxy communication
         let connection = UpstreamConnection { receiver, sender };
@@ -171,8 +236,12 @@ impl Upstream {
             extranonce_prefix: None,
             tx_sv2_set_new_prev_hash,
             tx_sv2_new_ext_mining_job,
+            tx_sv2_close_channel,
             channel_id: None,
-            job_id: None,
+            channels: HashMap::new(),
+            pending_channels: HashMap::new(),
+            timed_out_requests: HashSet::new(),
+            last_request_id: 0,
             last_job_id: None,
             min_extranonce_size,
             upstream_extranonce1_size: 16, /* 16 is the default since that is the only value the
@@ -180,9 +249,10 @@ impl Upstream {
             tx_sv2_extranonce,
             tx_status,
             target,
-            last_sent_hashrate: None,
             difficulty_config,
             task_collector,
+            worker_hashrate_tracking,
+            worker_hashrate_tracking_accepted: false,
         })))
     }
 
@@ -233,6 +303,8 @@ impl Upstream {
             payload,
         )?;
 
+        Self::negotiate_extensions(self_.clone(), &connection).await?;
+
         // Send open channel request before returning
         let nominal_hash_rate = self_
             .safe_lock(|u| {
@@ -241,20 +313,19 @@ impl Upstream {
                     .map_err(|_e| PoisonLock)
             })
             .map_err(|_e| PoisonLock)??;
-        let user_identity = "ABC".to_string().try_into()?;
 
         // Get the min_extranonce_size from the instance
         let min_extranonce_size = self_
             .safe_lock(|u| u.min_extranonce_size)
             .map_err(|_e| PoisonLock)?;
 
-        let open_channel = Mining::OpenExtendedMiningChannel(OpenExtendedMiningChannel {
-            request_id: 0, // TODO
-            user_identity, // TODO
+        let user_identity = "ABC"; // TODO
+        let open_channel = Self::open_extended_mining_channel(
+            0,
+            user_identity,
             nominal_hash_rate,
-            max_target: u256_from_int(u64::MAX), // TODO
             min_extranonce_size,
-        });
+        )?;
 
         // reset channel hashrate so downstreams can manage from now on out
         self_
@@ -271,6 +342,170 @@ impl Upstream {
         Ok(())
     }
 
+    /// Opens one more extended channel with the SV2 Upstream role, for the miners that do not
+    /// mine on the channel opened by `connect`. `user_identity` is the SV1 worker name, or worker
+    /// name prefix, of the miners. Returns once the SV2 Upstream role opened it.
+    pub async fn open_channel(
+        self_: Arc<Mutex<Self>>,
+        user_identity: &str,
+        nominal_hash_rate: f32,
+    ) -> ProxyResult<'static, OpenedUpstreamChannel> {
+        let (tx_opened, rx_opened) = bounded(1);
+        let (request_id, min_extranonce_size, tx_frame) = self_
+            .safe_lock(|u| {
+                u.last_request_id += 1;
+                u.pending_channels
+                    .insert(u.last_request_id, (nominal_hash_rate, tx_opened));
+                (
+                    u.last_request_id,
+                    u.min_extranonce_size,
+                    u.connection.sender.clone(),
+                )
+            })
+            .map_err(|_e| PoisonLock)?;
+
+        let open_channel = Self::open_extended_mining_channel(
+            request_id,
+            user_identity,
+            nominal_hash_rate,
+            min_extranonce_size,
+        )?;
+        let frame: StdFrame = Message::Mining(open_channel).try_into()?;
+        let frame: EitherFrame = frame.into();
+        tx_frame.send(frame).await.map_err(|e| {
+            super::super::error::Error::ChannelErrorSender(
+                super::super::error::ChannelSendError::General(e.to_string()),
+            )
+        })?;
+
+        match timeout(
+            Duration::from_secs(OPEN_CHANNEL_TIMEOUT_SECS),
+            rx_opened.recv(),
+        )
+        .await
+        {
+            Ok(Ok(opened)) => Ok(opened),
+            // The sender is dropped when the SV2 Upstream role answers with an
+            // `OpenMiningChannelError`
+            Ok(Err(_)) => Err(super::super::error::Error::SubprotocolMining(format!(
+                "Upstream refused to open the extended channel of request {}",
+                request_id
+            ))),
+            Err(_) => {
+                self_
+                    .safe_lock(|u| {
+                        u.pending_channels.remove(&request_id);
+                        u.timed_out_requests.insert(request_id);
+                    })
+                    .map_err(|_e| PoisonLock)?;
+                Err(super::super::error::Error::SubprotocolMining(format!(
+                    "Upstream did not open the extended channel of request {} in time",
+                    request_id
+                )))
+            }
+        }
+    }
+
+    /// Closes an extended channel opened with `open_channel`, once no miner mines on it anymore.
+    pub async fn close_channel(
+        self_: Arc<Mutex<Self>>,
+        channel_id: u32,
+    ) -> ProxyResult<'static, ()> {
+        let tx_frame = self_
+            .safe_lock(|u| {
+                u.channels.remove(&channel_id);
+                u.connection.sender.clone()
+            })
+            .map_err(|_e| PoisonLock)?;
+        info!("Closing Upstream channel {}", channel_id);
+        let close_channel = Mining::CloseChannel(CloseChannel {
+            channel_id,
+            reason_code: "no-downstream-left".to_string().try_into()?,
+        });
+        let frame: StdFrame = Message::Mining(close_channel).try_into()?;
+        let frame: EitherFrame = frame.into();
+        tx_frame.send(frame).await.map_err(|e| {
+            super::super::error::Error::ChannelErrorSender(
+                super::super::error::ChannelSendError::General(e.to_string()),
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Hands a channel opened with `open_channel` to its requester, then waits for the requester
+    /// to register it in the `Bridge`.
+    async fn on_channel_opened(
+        self_: Arc<Mutex<Self>>,
+        channel_id: u32,
+        extranonces: ExtendedExtranonce,
+        channel: UpstreamChannel,
+        tx_opened: Sender<OpenedUpstreamChannel>,
+    ) -> ProxyResult<'static, ()> {
+        let (registered, rx_registered) = bounded(1);
+        let opened = OpenedUpstreamChannel {
+            channel_id,
+            extranonces,
+            target: channel.target,
+            difficulty_config: channel.difficulty_config,
+            _registered: registered,
+        };
+        match tx_opened.send(opened).await {
+            Ok(()) => {
+                // Nothing is ever sent, `recv` returns when the requester drops `_registered`
+                let _ = rx_registered.recv().await;
+                Ok(())
+            }
+            // The requester gave up waiting, so no miner is going to mine on the channel
+            Err(_) => Self::close_channel(self_, channel_id).await,
+        }
+    }
+
+    /// Creates the `OpenExtendedMiningChannel` message used to open every extended channel with
+    /// the SV2 Upstream role.
+    #[allow(clippy::result_large_err)]
+    fn open_extended_mining_channel(
+        request_id: u32,
+        user_identity: &str,
+        nominal_hash_rate: f32,
+        min_extranonce_size: u16,
+    ) -> ProxyResult<'static, Mining<'static>> {
+        Ok(Mining::OpenExtendedMiningChannel(
+            OpenExtendedMiningChannel {
+                request_id,
+                user_identity: user_identity.to_string().try_into()?,
+                nominal_hash_rate,
+                max_target: u256_from_int(u64::MAX), // TODO
+                min_extranonce_size,
+            },
+        ))
+    }
+
+    /// Requests the worker-specific hashrate tracking extension to the SV2 Upstream role, if
+    /// enabled in the config, and waits for either a `RequestExtensionsSuccess` or a
+    /// `RequestExtensionsError`.
+    async fn negotiate_extensions(
+        self_: Arc<Mutex<Self>>,
+        connection: &UpstreamConnection,
+    ) -> ProxyResult<'static, ()> {
+        let worker_hashrate_tracking = self_
+            .safe_lock(|s| {
+                s.worker_hashrate_tracking_accepted = false;
+                s.worker_hashrate_tracking
+            })
+            .map_err(|_e| PoisonLock)?;
+        if !worker_hashrate_tracking {
+            return Ok(());
+        }
+        negotiate_extensions(
+            self_,
+            &connection.sender,
+            &connection.receiver,
+            vec![EXTENSION_TYPE_WORKER_HASHRATE_TRACKING],
+        )
+        .await?;
+        Ok(())
+    }
+
     /// Parses the incoming SV2 message from the Upstream role and routes the message to the
     /// appropriate handler.
     #[allow(clippy::result_large_err)]
@@ -284,6 +519,7 @@ impl Upstream {
             tx_sv2_extranonce,
             tx_sv2_new_ext_mining_job,
             tx_sv2_set_new_prev_hash,
+            tx_sv2_close_channel,
             recv,
             tx_status,
         ) = clone
@@ -293,6 +529,7 @@ impl Upstream {
                     s.tx_sv2_extranonce.clone(),
                     s.tx_sv2_new_ext_mining_job.clone(),
                     s.tx_sv2_set_new_prev_hash.clone(),
+                    s.tx_sv2_close_channel.clone(),
                     s.connection.receiver.clone(),
                     s.tx_status.clone(),
                 )
@@ -392,16 +629,42 @@ impl Upstream {
                                     extranonce_prefix.clone(), range_0.clone(), range_1.clone(), range_2.clone(),
                                 ).map_err(|err| InvalidExtranonce(format!("Impossible to create a valid extended extranonce from {:?} {:?} {:?} {:?}: {:?}",
                                     extranonce_prefix, range_0, range_1, range_2, err))));
-                                handle_result!(
-                                    tx_status,
-                                    tx_sv2_extranonce.send((extended, m.channel_id)).await
-                                );
+                                // Channels opened with `open_channel` go to their requester, the
+                                // one opened by `connect` initializes the bridge
+                                let requested = self_
+                                    .safe_lock(|u| {
+                                        let tx_opened = u
+                                            .pending_channels
+                                            .remove(&m.request_id)
+                                            .map(|(_, tx_opened)| tx_opened);
+                                        tx_opened.zip(u.channels.get(&m.channel_id).cloned())
+                                    })
+                                    .map_err(|_e| PoisonLock);
+                                match handle_result!(tx_status, requested) {
+                                    Some((tx_opened, channel)) => handle_result!(
+                                        tx_status,
+                                        Self::on_channel_opened(
+                                            self_.clone(),
+                                            m.channel_id,
+                                            extended,
+                                            channel,
+                                            tx_opened,
+                                        )
+                                        .await
+                                    ),
+                                    None => handle_result!(
+                                        tx_status,
+                                        tx_sv2_extranonce.send((extended, m.channel_id)).await
+                                    ),
+                                }
                             }
                             Mining::NewExtendedMiningJob(m) => {
                                 let job_id = m.job_id;
                                 let res = self_
                                     .safe_lock(|s| {
-                                        let _ = s.job_id.insert(job_id);
+                                        if let Some(channel) = s.channels.get_mut(&m.channel_id) {
+                                            channel.job_id = Some(job_id);
+                                        }
                                     })
                                     .map_err(|_e| PoisonLock);
                                 handle_result!(tx_status, res);
@@ -410,12 +673,36 @@ impl Upstream {
                             Mining::SetNewPrevHash(m) => {
                                 handle_result!(tx_status, tx_sv2_set_new_prev_hash.send(m).await);
                             }
-                            Mining::CloseChannel(_m) => {
-                                error!("Received Mining::CloseChannel msg from upstream!");
-                                handle_result!(tx_status, Err(NoUpstreamsConnected));
+                            Mining::CloseChannel(m) => {
+                                // The proxy can not run without the channel opened by `connect`,
+                                // the miners of the other channels just reconnect
+                                let is_default_channel = self_
+                                    .safe_lock(|u| u.channel_id == Some(m.channel_id))
+                                    .map_err(|_e| PoisonLock);
+                                if handle_result!(tx_status, is_default_channel) {
+                                    error!("Received Mining::CloseChannel msg from upstream!");
+                                    handle_result!(tx_status, Err(NoUpstreamsConnected));
+                                }
+                                handle_result!(tx_status, tx_sv2_close_channel.send(m).await);
                             }
-                            Mining::OpenMiningChannelError(_)
-                            | Mining::UpdateChannelError(_)
+                            Mining::OpenMiningChannelError(e) => {
+                                // Dropping the sender tells `open_channel` that the channel was
+                                // refused, only the channel opened by `connect` is needed to run
+                                let requested = self_
+                                    .safe_lock(|u| {
+                                        u.pending_channels.remove(&e.request_id).is_some()
+                                            || u.timed_out_requests.remove(&e.request_id)
+                                    })
+                                    .map_err(|_e| PoisonLock);
+                                if !handle_result!(tx_status, requested) {
+                                    error!("parse_incoming SV2 protocol error Message");
+                                    handle_result!(
+                                        tx_status,
+                                        Err(Mining::OpenMiningChannelError(e))
+                                    );
+                                }
+                            }
+                            Mining::UpdateChannelError(_)
                             | Mining::SubmitSharesError(_)
                             | Mining::SetCustomMiningJobError(_) => {
                                 error!("parse_incoming SV2 protocol error Message");
@@ -457,6 +744,7 @@ impl Upstream {
     #[allow(clippy::result_large_err)]
     fn get_job_id(
         self_: &Arc<Mutex<Self>>,
+        channel_id: u32,
     ) -> Result<Result<u32, super::super::error::Error<'static>>, super::super::error::Error<'static>>
     {
         self_
@@ -467,9 +755,12 @@ impl Upstream {
                             RolesLogicError::NoValidTranslatorJob,
                         ))
                 } else {
-                    s.job_id.ok_or(super::super::error::Error::RolesSv2Logic(
-                        RolesLogicError::NoValidJob,
-                    ))
+                    s.channels
+                        .get(&channel_id)
+                        .and_then(|channel| channel.job_id)
+                        .ok_or(super::super::error::Error::RolesSv2Logic(
+                            RolesLogicError::NoValidJob,
+                        ))
                 }
             })
             .map_err(|_e| PoisonLock)
@@ -491,27 +782,28 @@ impl Upstream {
 
         let handle_submit = tokio::task::spawn(async move {
             loop {
-                let mut sv2_submit: SubmitSharesExtended =
+                let (mut sv2_submit, user_name): (SubmitSharesExtended, String) =
                     handle_result!(tx_status, receiver.recv().await);
 
-                let channel_id = self_
-                    .safe_lock(|s| {
-                        s.channel_id
-                            .ok_or(super::super::error::Error::RolesSv2Logic(
-                                RolesLogicError::NotFoundChannelId,
-                            ))
-                    })
-                    .map_err(|_e| PoisonLock);
-                sv2_submit.channel_id =
-                    handle_result!(tx_status, handle_result!(tx_status, channel_id));
-                let job_id = Self::get_job_id(&self_);
+                // The `Bridge` sets the channel the share was mined on
+                let job_id = Self::get_job_id(&self_, sv2_submit.channel_id);
                 sv2_submit.job_id = handle_result!(tx_status, handle_result!(tx_status, job_id));
 
                 let message = Message::Mining(
                     roles_logic_sv2::parsers::Mining::SubmitSharesExtended(sv2_submit),
                 );
 
-                let frame: StdFrame = handle_result!(tx_status, message.try_into());
+                let worker_hashrate_tracking = handle_result!(
+                    tx_status,
+                    self_
+                        .safe_lock(|s| s.worker_hashrate_tracking_accepted)
+                        .map_err(|_e| PoisonLock)
+                );
+                let tlvs = match worker_hashrate_tracking {
+                    true => Self::user_identity_tlv(&user_name),
+                    false => vec![],
+                };
+                let frame: StdFrame = handle_result!(tx_status, frame_with_tlvs(message, &tlvs));
                 // Doesnt actually send because of Braiins Pool issue that needs to be fixed
 
                 let frame: EitherFrame = frame.into();
@@ -531,6 +823,20 @@ impl Upstream {
         Ok(())
     }
 
+    /// Builds the `user_identity` TLV field of the worker-specific hashrate tracking extension.
+    fn user_identity_tlv(user_name: &str) -> Vec<Tlv> {
+        match Tlv::user_identity(user_name) {
+            Some(tlv) => vec![tlv],
+            None => {
+                warn!(
+                    "SV1 worker name {} is longer than 32 bytes, the share is sent without it",
+                    user_name
+                );
+                vec![]
+            }
+        }
+    }
+
     fn _is_contained_in_upstream_target(&self, _share: SubmitSharesExtended) -> bool {
         todo!()
     }
@@ -599,6 +905,10 @@ impl IsMiningUpstream<Downstream> for Upstream {
         todo!()
     }
 
+    fn remove_hash_rate(&mut self, _to_remove: u64) {
+        todo!()
+    }
+
     fn get_opened_channels(
         &mut self,
     ) -> &mut Vec<roles_logic_sv2::common_properties::UpstreamChannel> {
@@ -641,6 +951,37 @@ impl ParseCommonMessagesFromUpstream for Upstream {
     }
 }
 
+impl ParseExtensionsNegotiationFromUpstream for Upstream {
+    fn handle_request_extensions_success(
+        &mut self,
+        m: RequestExtensionsSuccess,
+    ) -> Result<SendToExtensions, RolesLogicError> {
+        let supported_extensions = m.supported_extensions.into_inner();
+        info!(
+            "Received `RequestExtensionsSuccess`: supported_extensions={:?}",
+            supported_extensions
+        );
+        self.worker_hashrate_tracking_accepted =
+            supported_extensions.contains(&EXTENSION_TYPE_WORKER_HASHRATE_TRACKING);
+        if !self.worker_hashrate_tracking_accepted {
+            warn!("Upstream does not support worker-specific hashrate tracking");
+        }
+        Ok(SendToExtensions::None(None))
+    }
+
+    fn handle_request_extensions_error(
+        &mut self,
+        m: RequestExtensionsError,
+    ) -> Result<SendToExtensions, RolesLogicError> {
+        warn!(
+            "Received `RequestExtensionsError`: unsupported_extensions={:?}, required_extensions={:?}",
+            m.unsupported_extensions, m.required_extensions
+        );
+        self.worker_hashrate_tracking_accepted = false;
+        Ok(SendToExtensions::None(None))
+    }
+}
+
 /// Connection-wide SV2 Upstream role messages parser implemented by a downstream ("downstream"
 /// here is relative to the SV2 Upstream role and is represented by this `Upstream` struct).
 impl ParseMiningMessagesFromUpstream<Downstream> for Upstream {
@@ -679,6 +1020,17 @@ impl ParseMiningMessagesFromUpstream<Downstream> for Upstream {
             m.request_id, m.channel_id
         );
         debug!("OpenStandardMiningChannelSuccess: {:?}", m);
+        if self.timed_out_requests.remove(&m.request_id) {
+            warn!(
+                "Channel {} of request {} opened after `open_channel` timed out, closing it",
+                m.channel_id, m.request_id
+            );
+            let close_channel = CloseChannel {
+                channel_id: m.channel_id,
+                reason_code: "open-channel-timeout".to_string().try_into()?,
+            };
+            return Ok(SendTo::Respond(Mining::CloseChannel(close_channel)));
+        }
         let tproxy_e1_len = super::super::utils::proxy_extranonce1_len(
             m.extranonce_size as usize,
             self.min_extranonce_size.into(),
@@ -689,13 +1041,42 @@ impl ParseMiningMessagesFromUpstream<Downstream> for Upstream {
                 m.extranonce_size,
             ));
         }
-        self.target
-            .safe_lock(|t| *t = m.target.to_vec())
-            .map_err(|e| RolesLogicError::PoisonLock(e.to_string()))?;
-
         info!("Up: Successfully Opened Extended Mining Channel");
-        self.channel_id = Some(m.channel_id);
-        self.extranonce_prefix = Some(m.extranonce_prefix.to_vec());
+        let channel = match self.pending_channels.get(&m.request_id) {
+            // A channel opened with `open_channel`, its miners add their hashrates once they
+            // start mining, as it is done for the channel opened by `connect`
+            Some((nominal_hash_rate, _)) => {
+                let difficulty_config = self
+                    .difficulty_config
+                    .safe_lock(|c| UpstreamDifficultyConfig {
+                        channel_nominal_hashrate: 0.0,
+                        ..c.clone()
+                    })
+                    .map_err(|e| RolesLogicError::PoisonLock(e.to_string()))?;
+                UpstreamChannel {
+                    job_id: None,
+                    target: Arc::new(Mutex::new(m.target.to_vec())),
+                    difficulty_config: Arc::new(Mutex::new(difficulty_config)),
+                    last_sent_hashrate: Some(*nominal_hash_rate),
+                }
+            }
+            // Only the channel opened by `connect` is not requested with `open_channel`
+            None if m.request_id == 0 => {
+                self.target
+                    .safe_lock(|t| *t = m.target.to_vec())
+                    .map_err(|e| RolesLogicError::PoisonLock(e.to_string()))?;
+                self.channel_id = Some(m.channel_id);
+                self.extranonce_prefix = Some(m.extranonce_prefix.to_vec());
+                UpstreamChannel {
+                    job_id: None,
+                    target: self.target.clone(),
+                    difficulty_config: self.difficulty_config.clone(),
+                    last_sent_hashrate: None,
+                }
+            }
+            None => return Err(RolesLogicError::UnknownRequestId(m.request_id)),
+        };
+        self.channels.insert(m.channel_id, channel);
         let m = Mining::OpenExtendedMiningChannelSuccess(m.into_static());
         Ok(SendTo::None(Some(m)))
     }
@@ -728,12 +1109,14 @@ impl ParseMiningMessagesFromUpstream<Downstream> for Upstream {
         ))))
     }
 
-    /// Handles the SV2 `CloseChannel` message (TODO).
+    /// Handles the SV2 `CloseChannel` message, the channel is forgotten and its Downstreams are
+    /// disconnected by the `Bridge`.
     fn handle_close_channel(
         &mut self,
         m: roles_logic_sv2::mining_sv2::CloseChannel,
     ) -> Result<SendTo<Downstream>, RolesLogicError> {
         info!("Received CloseChannel for channel id: {}", m.channel_id);
+        self.channels.remove(&m.channel_id);
         Ok(SendTo::None(Some(Mining::CloseChannel(m.as_static()))))
     }
 
@@ -857,7 +1240,11 @@ impl ParseMiningMessagesFromUpstream<Downstream> for Upstream {
         info!("Received SetTarget for channel id: {}", m.channel_id);
         debug!("SetTarget: {:?}", m);
         let m = m.into_static();
-        self.target
+        let target = match self.channels.get(&m.channel_id) {
+            Some(channel) => &channel.target,
+            None => &self.target,
+        };
+        target
             .safe_lock(|t| *t = m.maximum_target.to_vec())
             .map_err(|e| RolesLogicError::PoisonLock(e.to_string()))?;
         Ok(SendTo::None(None))
@@ -12,6 +12,7 @@ tarpaulin
 crates=(
   "mining-proxy"
   "pool"
+  "test-utils/load-generator"
   "test-utils/mining-device"
   "test-utils/mining-device-sv1"
   "translator"
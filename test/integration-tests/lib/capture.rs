@@ -0,0 +1,611 @@
+//! Record-and-replay support for the sniffers.
+//!
+//! Every frame that passes through a [`crate::sniffer::Sniffer`] (or a `SnifferSV1`) is recorded
+//! into a [`Capture`], together with the time it was received and its direction. A capture can be
+//! saved into a portable, line oriented text file:
+//!
+//! ```text
+//! # sv2-capture v1 <identifier> <unix start time in micros>
+//! <micros since start> up sv2 <extension type> <message type> <payload as hex>
+//! <micros since start> down sv1 <json message>
+//! ```
+//!
+//! The [`Replayer`] reads such a file back and plays one side of the conversation against a role
+//! under test, returning a [`ReplayReport`] that diffs what the role answered against what was
+//! recorded. This can be used to write golden-file tests, or to reproduce a trace taken from a
+//! production deployment and attached to a bug report.
+use crate::sniffer::{MessageDirection, Sniffer};
+use async_channel::{Receiver, Sender};
+use codec_sv2::{framing_sv2::framing::Frame, StandardEitherFrame, Sv2Frame};
+use corepc_node::serde_json::{self, Value};
+use roles_logic_sv2::{
+    parsers::{message_type_to_name, AnyMessage},
+    utils::Mutex,
+};
+use std::{
+    convert::TryInto,
+    fmt, fs, io,
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{net::TcpStream, time::timeout};
+
+const CAPTURE_HEADER: &str = "# sv2-capture v1";
+const CHANNEL_MSG_BIT: u16 = 0b1000_0000_0000_0000;
+
+/// A frame as it was seen on the wire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CapturedFrame {
+    /// A decrypted SV2 frame. `extension_type` is the raw header field, channel bit included.
+    Sv2 {
+        extension_type: u16,
+        message_type: u8,
+        payload: Vec<u8>,
+    },
+    /// A SV1 message, serialized as a single line of json.
+    Sv1(String),
+}
+
+impl CapturedFrame {
+    /// Builds a [`CapturedFrame`] out of a SV2 frame, without consuming it.
+    pub fn from_sv2_frame(frame: &mut StandardEitherFrame<AnyMessage<'static>>) -> Option<Self> {
+        match frame {
+            Frame::Sv2(frame) => {
+                let header = frame.get_header()?;
+                Some(CapturedFrame::Sv2 {
+                    extension_type: header.ext_type(),
+                    message_type: header.msg_type(),
+                    payload: frame.payload().to_vec(),
+                })
+            }
+            Frame::HandShake(_) => None,
+        }
+    }
+
+    /// Decodes the captured payload back into a SV2 frame that can be sent to a role.
+    pub fn to_sv2_frame(&self) -> Option<StandardEitherFrame<AnyMessage<'static>>> {
+        match self {
+            CapturedFrame::Sv2 {
+                extension_type,
+                message_type,
+                payload,
+            } => {
+                let mut payload = payload.clone();
+                let message: AnyMessage<'_> =
+                    (*extension_type, *message_type, payload.as_mut_slice())
+                        .try_into()
+                        .ok()?;
+                let frame = Sv2Frame::from_message(
+                    Sniffer::into_static(message),
+                    *message_type,
+                    extension_type & !CHANNEL_MSG_BIT,
+                    extension_type & CHANNEL_MSG_BIT != 0,
+                )?;
+                Some(Frame::Sv2(frame))
+            }
+            CapturedFrame::Sv1(_) => None,
+        }
+    }
+
+    /// A short name for the frame: the message name for SV2 and the method name (or `response`)
+    /// for SV1. Used to diff traces whose fields are not deterministic.
+    pub fn kind(&self) -> String {
+        match self {
+            CapturedFrame::Sv2 { message_type, .. } => {
+                message_type_to_name(*message_type).to_string()
+            }
+            CapturedFrame::Sv1(json) => {
+                let message: Option<Value> = serde_json::from_str(json).ok();
+                match message
+                    .as_ref()
+                    .and_then(|message| message.get("method"))
+                    .and_then(Value::as_str)
+                {
+                    Some(method) => method.to_string(),
+                    None => "response".to_string(),
+                }
+            }
+        }
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            CapturedFrame::Sv2 {
+                extension_type,
+                message_type,
+                payload,
+            } => format!(
+                "sv2 {:04x} {:02x} {}",
+                extension_type,
+                message_type,
+                to_hex(payload)
+            ),
+            CapturedFrame::Sv1(json) => format!("sv1 {}", json),
+        }
+    }
+
+    fn decode(s: &str) -> Option<Self> {
+        let (protocol, rest) = s.split_once(' ')?;
+        match protocol {
+            "sv2" => {
+                let mut fields = rest.splitn(3, ' ');
+                let extension_type = u16::from_str_radix(fields.next()?, 16).ok()?;
+                let message_type = u8::from_str_radix(fields.next()?, 16).ok()?;
+                let payload = from_hex(fields.next().unwrap_or(""))?;
+                Some(CapturedFrame::Sv2 {
+                    extension_type,
+                    message_type,
+                    payload,
+                })
+            }
+            "sv1" => Some(CapturedFrame::Sv1(rest.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CapturedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapturedFrame::Sv2 { payload, .. } => {
+                write!(f, "{} ({})", self.kind(), to_hex(payload))
+            }
+            CapturedFrame::Sv1(json) => write!(f, "{}", json),
+        }
+    }
+}
+
+/// A single recorded frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    /// Time elapsed between the start of the capture and the reception of the frame.
+    pub timestamp: Duration,
+    pub direction: MessageDirection,
+    pub frame: CapturedFrame,
+}
+
+impl CaptureRecord {
+    fn encode(&self) -> String {
+        let direction = match self.direction {
+            MessageDirection::ToUpstream => "up",
+            MessageDirection::ToDownstream => "down",
+        };
+        format!(
+            "{} {} {}",
+            self.timestamp.as_micros(),
+            direction,
+            self.frame.encode()
+        )
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, ' ');
+        let timestamp = Duration::from_micros(fields.next()?.parse().ok()?);
+        let direction = match fields.next()? {
+            "up" => MessageDirection::ToUpstream,
+            "down" => MessageDirection::ToDownstream,
+            _ => return None,
+        };
+        let frame = CapturedFrame::decode(fields.next()?)?;
+        Some(Self {
+            timestamp,
+            direction,
+            frame,
+        })
+    }
+}
+
+/// Shared recorder of all the frames seen by a sniffer.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    identifier: String,
+    started_at: SystemTime,
+    start: Instant,
+    records: Arc<Mutex<Vec<CaptureRecord>>>,
+}
+
+impl Capture {
+    pub fn new(identifier: String) -> Self {
+        Self {
+            identifier,
+            started_at: SystemTime::now(),
+            start: Instant::now(),
+            records: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Records a frame received now, travelling in `direction`.
+    pub fn record(&self, direction: MessageDirection, frame: CapturedFrame) {
+        let record = CaptureRecord {
+            timestamp: self.start.elapsed(),
+            direction,
+            frame,
+        };
+        self.records
+            .safe_lock(|records| records.push(record))
+            .unwrap();
+    }
+
+    /// Returns a copy of everything recorded so far.
+    pub fn records(&self) -> Vec<CaptureRecord> {
+        self.records.safe_lock(|records| records.clone()).unwrap()
+    }
+
+    /// Writes the capture into `path`, see the module documentation for the format.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let started_at = self
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        let mut content = format!("{} {} {}\n", CAPTURE_HEADER, self.identifier, started_at);
+        for record in self.records() {
+            content.push_str(&record.encode());
+            content.push('\n');
+        }
+        fs::write(path, content)
+    }
+
+    /// Reads the records of a capture file written by [`Capture::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<CaptureRecord>> {
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines();
+        if !lines
+            .next()
+            .is_some_and(|header| header.starts_with(CAPTURE_HEADER))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing capture header",
+            ));
+        }
+        lines
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                CaptureRecord::decode(line).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid capture record: {}", line),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// A difference between a recorded frame and the frame received during a replay.
+///
+/// `expected` is `None` when the role sent more frames than recorded, `actual` is `None` when it
+/// sent less.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub index: usize,
+    pub expected: Option<CapturedFrame>,
+    pub actual: Option<CapturedFrame>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |frame: &Option<CapturedFrame>| match frame {
+            Some(frame) => frame.to_string(),
+            None => "<nothing>".to_string(),
+        };
+        write!(
+            f,
+            "#{}: expected {}, got {}",
+            self.index,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+/// Outcome of a replay: the frames recorded from the peer of the role under test and the frames
+/// the role actually sent back.
+#[derive(Debug, Clone)]
+pub struct ReplayReport {
+    pub expected: Vec<CapturedFrame>,
+    pub actual: Vec<CapturedFrame>,
+}
+
+impl ReplayReport {
+    /// Compares frames byte by byte, suited for deterministic traces.
+    pub fn mismatches(&self) -> Vec<Mismatch> {
+        self.diff(|expected, actual| expected == actual)
+    }
+
+    /// Only compares the kind of the frames (see [`CapturedFrame::kind`]), suited for traces
+    /// carrying timestamps, random ids or templates that change from run to run.
+    pub fn kind_mismatches(&self) -> Vec<Mismatch> {
+        self.diff(|expected, actual| expected.kind() == actual.kind())
+    }
+
+    fn diff(&self, same: impl Fn(&CapturedFrame, &CapturedFrame) -> bool) -> Vec<Mismatch> {
+        let len = self.expected.len().max(self.actual.len());
+        (0..len)
+            .filter_map(|index| {
+                let expected = self.expected.get(index);
+                let actual = self.actual.get(index);
+                match (expected, actual) {
+                    (Some(e), Some(a)) if same(e, a) => None,
+                    _ => Some(Mismatch {
+                        index,
+                        expected: expected.cloned(),
+                        actual: actual.cloned(),
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Plays a capture back against a role under test.
+///
+/// The replayer takes the place of one of the two recorded peers and sends the frames that peer
+/// sent, in order. Before sending a frame it waits until the role under test has answered with as
+/// many frames as the recorded counterpart had sent at that point, so that request/response flows
+/// are kept in lockstep. It gives up waiting after `timeout`.
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    records: Vec<CaptureRecord>,
+    timeout: Duration,
+}
+
+impl Replayer {
+    pub fn new(records: Vec<CaptureRecord>) -> Self {
+        Self {
+            records,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Creates a replayer out of a file written by [`Capture::save`].
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(Capture::load(path)?))
+    }
+
+    /// Sets how long to wait for each expected frame from the role under test.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Acts as the recorded downstream: connects to the SV2 role listening on `upstream` and sends
+    /// it the frames recorded in the [`MessageDirection::ToUpstream`] direction.
+    pub async fn replay_to_upstream(&self, upstream: SocketAddr) -> ReplayReport {
+        let stream = self.connect(upstream).await;
+        let (receiver, sender) = Sniffer::create_upstream(stream)
+            .await
+            .expect("Failed to create upstream");
+        self.replay_sv2(receiver, sender, MessageDirection::ToUpstream)
+            .await
+    }
+
+    /// Acts as the recorded upstream: waits for the SV2 role under test to connect on `listening`
+    /// and sends it the frames recorded in the [`MessageDirection::ToDownstream`] direction.
+    ///
+    /// The noise handshake uses the same keys as the [`crate::sniffer::Sniffer`].
+    pub async fn replay_to_downstream(&self, listening: SocketAddr) -> ReplayReport {
+        let stream = Sniffer::wait_for_client(listening).await;
+        let (receiver, sender) = Sniffer::create_downstream(stream)
+            .await
+            .expect("Failed to create downstream");
+        self.replay_sv2(receiver, sender, MessageDirection::ToDownstream)
+            .await
+    }
+
+    /// Acts as the recorded SV1 downstream: connects to the SV1 role listening on `upstream` and
+    /// sends it the messages recorded in the [`MessageDirection::ToUpstream`] direction.
+    #[cfg(feature = "sv1")]
+    pub async fn replay_sv1_to_upstream(&self, upstream: SocketAddr) -> ReplayReport {
+        use network_helpers_sv2::sv1_connection::ConnectionSV1;
+        let stream = self.connect(upstream).await;
+        let connection = ConnectionSV1::new(stream).await;
+        let (to_replayer, from_role) = async_channel::unbounded();
+        let (to_role, from_replayer) = async_channel::unbounded::<CapturedFrame>();
+        let receiver = connection.receiver();
+        tokio::spawn(async move {
+            while let Ok(msg) = receiver.recv().await {
+                let json = serde_json::to_string(&msg).expect("Invalid SV1 message");
+                if to_replayer.send(CapturedFrame::Sv1(json)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let sender = connection.sender();
+        tokio::spawn(async move {
+            while let Ok(CapturedFrame::Sv1(json)) = from_replayer.recv().await {
+                let msg: sv1_api::Message =
+                    serde_json::from_str(&json).expect("Invalid SV1 message");
+                if sender.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        });
+        self.replay(from_role, to_role, MessageDirection::ToUpstream)
+            .await
+    }
+
+    // Roles only listen once their own upstream is up, so the connection is retried until
+    // `timeout`
+    async fn connect(&self, address: SocketAddr) -> TcpStream {
+        let start = Instant::now();
+        loop {
+            match TcpStream::connect(address).await {
+                Ok(stream) => break stream,
+                Err(e) if start.elapsed() > self.timeout => {
+                    panic!("Failed to connect to the role under test: {}", e)
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+
+    async fn replay_sv2(
+        &self,
+        receiver: Receiver<StandardEitherFrame<AnyMessage<'static>>>,
+        sender: Sender<StandardEitherFrame<AnyMessage<'static>>>,
+        direction: MessageDirection,
+    ) -> ReplayReport {
+        let (to_replayer, from_role) = async_channel::unbounded();
+        let (to_role, from_replayer) = async_channel::unbounded::<CapturedFrame>();
+        tokio::spawn(async move {
+            while let Ok(mut frame) = receiver.recv().await {
+                let Some(frame) = CapturedFrame::from_sv2_frame(&mut frame) else {
+                    continue;
+                };
+                if to_replayer.send(frame).await.is_err() {
+                    break;
+                }
+            }
+        });
+        tokio::spawn(async move {
+            while let Ok(frame) = from_replayer.recv().await {
+                let frame = frame
+                    .to_sv2_frame()
+                    .expect("Capture contains an invalid SV2 frame");
+                if sender.send(frame).await.is_err() {
+                    break;
+                }
+            }
+        });
+        self.replay(from_role, to_role, direction).await
+    }
+
+    async fn replay(
+        &self,
+        from_role: Receiver<CapturedFrame>,
+        to_role: Sender<CapturedFrame>,
+        direction: MessageDirection,
+    ) -> ReplayReport {
+        let mut expected = Vec::new();
+        let mut actual = Vec::new();
+        for record in &self.records {
+            if record.direction != direction {
+                expected.push(record.frame.clone());
+                continue;
+            }
+            Self::receive_until(&from_role, &mut actual, expected.len(), self.timeout).await;
+            if to_role.send(record.frame.clone()).await.is_err() {
+                tracing::warn!("Role under test closed the connection during the replay");
+                break;
+            }
+        }
+        Self::receive_until(&from_role, &mut actual, expected.len(), self.timeout).await;
+        while let Ok(frame) = from_role.try_recv() {
+            actual.push(frame);
+        }
+        ReplayReport { expected, actual }
+    }
+
+    async fn receive_until(
+        from_role: &Receiver<CapturedFrame>,
+        received: &mut Vec<CapturedFrame>,
+        count: usize,
+        wait: Duration,
+    ) {
+        while received.len() < count {
+            match timeout(wait, from_role.recv()).await {
+                Ok(Ok(frame)) => received.push(frame),
+                _ => break,
+            }
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => u8::from_str_radix(std::str::from_utf8(&[*high, *low]).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_round_trip() {
+        let records = [
+            CaptureRecord {
+                timestamp: Duration::from_micros(0),
+                direction: MessageDirection::ToUpstream,
+                frame: CapturedFrame::Sv2 {
+                    extension_type: 0x8000,
+                    message_type: 0x15,
+                    payload: vec![0x00, 0x0f, 0xa0, 0xff],
+                },
+            },
+            CaptureRecord {
+                timestamp: Duration::from_micros(1250),
+                direction: MessageDirection::ToDownstream,
+                frame: CapturedFrame::Sv2 {
+                    extension_type: 0,
+                    message_type: 0x01,
+                    payload: Vec::new(),
+                },
+            },
+            CaptureRecord {
+                timestamp: Duration::from_secs(3),
+                direction: MessageDirection::ToDownstream,
+                frame: CapturedFrame::Sv1(
+                    r#"{"id":null,"method":"mining.set_difficulty","params":[1.0]}"#.to_string(),
+                ),
+            },
+        ];
+        for record in &records {
+            assert_eq!(
+                CaptureRecord::decode(&record.encode()).as_ref(),
+                Some(record)
+            );
+        }
+        assert_eq!(
+            records[0].encode(),
+            "0 up sv2 8000 15 000fa0ff",
+            "the file format changed"
+        );
+    }
+
+    #[test]
+    fn invalid_records_are_rejected() {
+        assert!(CaptureRecord::decode("").is_none());
+        assert!(CaptureRecord::decode("0 sideways sv1 {}").is_none());
+        assert!(CaptureRecord::decode("0 up sv3 {}").is_none());
+        assert!(CaptureRecord::decode("x up sv1 {}").is_none());
+        assert!(CaptureRecord::decode("0 up sv2 10000 00 ").is_none());
+        assert!(CaptureRecord::decode("0 up sv2 0000 00 abc").is_none());
+        assert!(CaptureRecord::decode("0 up sv2 0000 00 zz").is_none());
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(from_hex(&to_hex(&bytes)), Some(bytes));
+        assert_eq!(from_hex(""), Some(Vec::new()));
+        assert_eq!(from_hex("0A"), Some(vec![0x0a]));
+        assert_eq!(from_hex("0"), None);
+    }
+
+    #[test]
+    fn sv1_kind() {
+        let kind = |json: &str| CapturedFrame::Sv1(json.to_string()).kind();
+        assert_eq!(
+            kind(r#"{"id":1,"method":"mining.subscribe","params":[]}"#),
+            "mining.subscribe"
+        );
+        assert_eq!(
+            kind(r#"{ "params": [], "method" : "mining.notify", "id": null }"#),
+            "mining.notify"
+        );
+        assert_eq!(kind(r#"{"id":1,"error":null,"result":true}"#), "response");
+    }
+}
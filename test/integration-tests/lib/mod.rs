@@ -15,6 +15,7 @@ use std::{
 use translator_sv2::TranslatorSv2;
 use utils::get_available_address;
 
+pub mod capture;
 pub mod sniffer;
 #[cfg(feature = "sv1")]
 pub mod sv1_sniffer;
@@ -131,7 +132,7 @@ pub fn start_jdc(
         .iter()
         .map(|(pool_addr, jds_addr)| {
             Upstream::new(
-                authority_pubkey,
+                vec![authority_pubkey],
                 pool_addr.to_string(),
                 jds_addr.to_string(),
             )
@@ -212,6 +213,16 @@ pub fn start_jds(tp_rpc_connection: &ConnectParams) -> (JobDeclaratorServer, Soc
 }
 
 pub fn start_sv2_translator(upstream: SocketAddr) -> (TranslatorSv2, SocketAddr) {
+    start_sv2_translator_with_channel_mode(
+        upstream,
+        translator_sv2::proxy_config::UpstreamChannelMode::Aggregate,
+    )
+}
+
+pub fn start_sv2_translator_with_channel_mode(
+    upstream: SocketAddr,
+    channel_mode: translator_sv2::proxy_config::UpstreamChannelMode,
+) -> (TranslatorSv2, SocketAddr) {
     let upstream_address = upstream.ip().to_string();
     let upstream_port = upstream.port();
     let upstream_authority_pubkey = Secp256k1PublicKey::try_from(
@@ -234,12 +245,12 @@ pub fn start_sv2_translator(upstream: SocketAddr) -> (TranslatorSv2, SocketAddr)
         channel_diff_update_interval,
         channel_nominal_hashrate,
         0,
-        false,
+        channel_mode,
     );
     let upstream_conf = translator_sv2::proxy_config::UpstreamConfig::new(
         upstream_address,
         upstream_port,
-        upstream_authority_pubkey,
+        vec![upstream_authority_pubkey],
         upstream_difficulty_config,
     );
     let downstream_conf = translator_sv2::proxy_config::DownstreamConfig::new(
@@ -330,13 +341,17 @@ pub fn start_mining_device_sv2(
             handicap,
             nominal_hashrate_multiplier,
             single_submit,
+            mining_device::ChannelKind::Standard,
+            mining_device::MiningOptions::default(),
         )
         .await;
     });
 }
 
-pub fn start_mining_sv2_proxy(upstreams: &[SocketAddr]) -> SocketAddr {
-    use mining_proxy_sv2::{ChannelKind, UpstreamMiningValues};
+pub async fn start_mining_sv2_proxy(
+    upstreams: &[SocketAddr],
+) -> (mining_proxy_sv2::MiningProxy, SocketAddr) {
+    use mining_proxy_sv2::{ChannelKind, MiningProxy, UpstreamMiningValues};
     let upstreams = upstreams
         .iter()
         .map(|upstream| UpstreamMiningValues {
@@ -347,6 +362,9 @@ pub fn start_mining_sv2_proxy(upstreams: &[SocketAddr]) -> SocketAddr {
             )
             .unwrap(),
             channel_kind: ChannelKind::Extended,
+            weight: None,
+            priority: None,
+            user_identity_prefixes: vec![],
         })
         .collect();
     let mining_proxy_listening_address = get_available_address();
@@ -359,11 +377,108 @@ pub fn start_mining_sv2_proxy(upstreams: &[SocketAddr]) -> SocketAddr {
         downstream_share_per_minute: 1.0,
         expected_total_downstream_hr: 10_000.0,
         reconnect: true,
+        upstream_selection_strategy: Default::default(),
+        rekey_policy: Default::default(),
     };
-    tokio::spawn(async move {
-        mining_proxy_sv2::start_mining_proxy(config).await;
+    let mining_proxy = MiningProxy::new(config);
+    assert!(mining_proxy.start().await.is_ok());
+    (mining_proxy, mining_proxy_listening_address)
+}
+
+/// Starts a SV1 pool with a single job, at a difficulty low enough for the CPU miners. The
+/// accepted shares are received from the events channel.
+pub async fn start_sv1_pool() -> (
+    sv1_server::Sv1Server,
+    async_channel::Receiver<sv1_server::Event>,
+    SocketAddr,
+) {
+    use stratum_common::bitcoin::{
+        absolute::LockTime, consensus, transaction, Amount, OutPoint, ScriptBuf, Sequence,
+        Transaction, TxIn, TxOut, Witness,
+    };
+    use sv1_server::{Job, Sv1Server, Sv1ServerConfig};
+    let listening_address = get_available_address();
+    let config = Sv1ServerConfig {
+        listen_address: listening_address.to_string(),
+        initial_hashrate: 100_000.0,
+        min_difficulty: 0.000_001,
+        ..Default::default()
+    };
+    let (server, events) = Sv1Server::new(config).expect("failed");
+    // The extranonce1 and the extranonce2, 4 bytes each, go in the coinbase script
+    let coinbase = Transaction {
+        version: transaction::Version::ONE,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::from_bytes(vec![0; 8]),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(5_000_000_000),
+            script_pubkey: ScriptBuf::new(),
+        }],
+    };
+    let coinbase = consensus::serialize(&coinbase);
+    let prefix_len = 4 + 1 + 36 + 1;
+    server.new_job(Job {
+        job_id: "1".to_string(),
+        prev_hash: [1; 32],
+        coinbase_prefix: coinbase[..prefix_len].to_vec(),
+        coinbase_suffix: coinbase[prefix_len + 8..].to_vec(),
+        merkle_branch: vec![],
+        version: 0x20000000,
+        bits: 0x1d00ffff,
+        // The mining device rolls the ntime from the current time
+        time: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("failed")
+            .as_secs() as u32,
+        clean_jobs: true,
+    });
+    let listener = tokio::net::TcpListener::bind(listening_address)
+        .await
+        .expect("failed");
+    tokio::spawn({
+        let server = server.clone();
+        async move { server.serve(listener).await }
+    });
+    (server, events, listening_address)
+}
+
+pub fn start_reverse_translator(
+    upstream: SocketAddr,
+) -> (reverse_translator_sv2::ReverseTranslatorSv2, SocketAddr) {
+    use reverse_translator_sv2::{config::ReverseTranslatorConfig, ReverseTranslatorSv2};
+    let listening_address = get_available_address();
+    let config = ReverseTranslatorConfig {
+        upstream_address: upstream.to_string(),
+        upstream_user: "username.worker".to_string(),
+        upstream_password: "x".to_string(),
+        version_rolling_mask: Some(0x1FFFE000),
+        extranonce_prefix_size: 1,
+        listen_address: listening_address.to_string(),
+        authority_public_key: Secp256k1PublicKey::try_from(
+            "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72".to_string(),
+        )
+        .expect("failed"),
+        authority_secret_key: Secp256k1SecretKey::try_from(
+            "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n".to_string(),
+        )
+        .expect("failed"),
+        cert_validity_sec: 3600,
+        shares_per_minute: SHARES_PER_MINUTE,
+        admission: Default::default(),
+        rekey_policy: Default::default(),
+        connection_limits: Default::default(),
+    };
+    let reverse_translator = ReverseTranslatorSv2::new(config);
+    tokio::spawn({
+        let reverse_translator = reverse_translator.clone();
+        async move { reverse_translator.start().await }
     });
-    mining_proxy_listening_address
+    (reverse_translator, listening_address)
 }
 
 #[cfg(feature = "sv1")]
@@ -1,3 +1,4 @@
+use crate::capture::{Capture, CapturedFrame};
 use async_channel::{Receiver, Sender};
 use codec_sv2::{
     framing_sv2::framing::Frame, HandshakeRole, Initiator, Responder, StandardEitherFrame, Sv2Frame,
@@ -49,6 +50,10 @@ enum SnifferError {
 ///
 /// In order to replace or ignore the messages sent between the roles, [`InterceptAction`] can be
 /// used in [`Sniffer::new`].
+///
+/// Every frame received from either side, before any [`InterceptAction`] is applied, is also
+/// recorded into a [`Capture`] that can be saved with [`Sniffer::save_capture`] and played back
+/// with [`crate::capture::Replayer`].
 #[derive(Debug, Clone)]
 pub struct Sniffer {
     identifier: String,
@@ -58,6 +63,7 @@ pub struct Sniffer {
     messages_from_upstream: MessagesAggregator,
     check_on_drop: bool,
     action: Option<InterceptAction>,
+    capture: Capture,
 }
 
 impl Sniffer {
@@ -71,6 +77,7 @@ impl Sniffer {
         action: Option<InterceptAction>,
     ) -> Self {
         Self {
+            capture: Capture::new(identifier.clone()),
             identifier,
             listening_address,
             upstream_address,
@@ -92,6 +99,7 @@ impl Sniffer {
         let messages_from_upstream = self.messages_from_upstream.clone();
         let action = self.action.clone();
         let identifier = self.identifier.clone();
+        let capture = self.capture.clone();
         tokio::spawn(async move {
             let (downstream_receiver, downstream_sender) =
                 Self::create_downstream(Self::wait_for_client(listening_address).await)
@@ -106,8 +114,8 @@ impl Sniffer {
             .expect("Failed to create upstream");
             select! {
                 _ = tokio::signal::ctrl_c() => { },
-                _ = Self::recv_from_down_send_to_up(downstream_receiver, upstream_sender, messages_from_downstream, action.clone(), &identifier) => { },
-                _ = Self::recv_from_up_send_to_down(upstream_receiver, downstream_sender, messages_from_upstream, action, &identifier) => { },
+                _ = Self::recv_from_down_send_to_up(downstream_receiver, upstream_sender, messages_from_downstream, action.clone(), capture.clone(), &identifier) => { },
+                _ = Self::recv_from_up_send_to_down(upstream_receiver, downstream_sender, messages_from_upstream, action, capture, &identifier) => { },
             };
         });
     }
@@ -134,6 +142,16 @@ impl Sniffer {
         self.messages_from_upstream.next_message()
     }
 
+    /// Returns the recorder holding every frame seen by the sniffer so far.
+    pub fn capture(&self) -> &Capture {
+        &self.capture
+    }
+
+    /// Saves every frame seen by the sniffer so far into a capture file at `path`.
+    pub fn save_capture<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        self.capture.save(path)
+    }
+
     /// Waits until a message of the specified type is received into the `message_direction`
     /// corresponding queue.
     pub async fn wait_for_message_type(
@@ -202,7 +220,7 @@ impl Sniffer {
         }
     }
 
-    async fn create_downstream(
+    pub(crate) async fn create_downstream(
         stream: TcpStream,
     ) -> Option<(Receiver<MessageFrame>, Sender<MessageFrame>)> {
         let pub_key = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72"
@@ -231,7 +249,7 @@ impl Sniffer {
         }
     }
 
-    async fn create_upstream(
+    pub(crate) async fn create_upstream(
         stream: TcpStream,
     ) -> Option<(Receiver<MessageFrame>, Sender<MessageFrame>)> {
         let initiator = Initiator::without_pk().expect("This fn call can not fail");
@@ -253,10 +271,14 @@ impl Sniffer {
         send: Sender<MessageFrame>,
         downstream_messages: MessagesAggregator,
         action: Option<InterceptAction>,
+        capture: Capture,
         identifier: &str,
     ) -> Result<(), SnifferError> {
         while let Ok(mut frame) = recv.recv().await {
             let (msg_type, msg) = Self::message_from_frame(&mut frame);
+            if let Some(captured) = CapturedFrame::from_sv2_frame(&mut frame) {
+                capture.record(MessageDirection::ToUpstream, captured);
+            }
             let action = action.as_ref().and_then(|action| {
                 action.find_matching_action(msg_type, MessageDirection::ToUpstream)
             });
@@ -317,10 +339,14 @@ impl Sniffer {
         send: Sender<MessageFrame>,
         upstream_messages: MessagesAggregator,
         action: Option<InterceptAction>,
+        capture: Capture,
         identifier: &str,
     ) -> Result<(), SnifferError> {
         while let Ok(mut frame) = recv.recv().await {
             let (msg_type, msg) = Self::message_from_frame(&mut frame);
+            if let Some(captured) = CapturedFrame::from_sv2_frame(&mut frame) {
+                capture.record(MessageDirection::ToDownstream, captured);
+            }
 
             let action = action.as_ref().and_then(|action| {
                 action.find_matching_action(msg_type, MessageDirection::ToDownstream)
@@ -383,9 +409,10 @@ impl Sniffer {
             Frame::Sv2(frame) => {
                 if let Some(header) = frame.get_header() {
                     let message_type = header.msg_type();
+                    let extension_type = header.ext_type();
                     let mut payload = frame.payload().to_vec();
                     let message: Result<AnyMessage<'_>, _> =
-                        (message_type, payload.as_mut_slice()).try_into();
+                        (extension_type, message_type, payload.as_mut_slice()).try_into();
                     match message {
                         Ok(message) => {
                             let message = Self::into_static(message);
@@ -410,7 +437,7 @@ impl Sniffer {
         }
     }
 
-    fn into_static(m: AnyMessage<'_>) -> AnyMessage<'static> {
+    pub(crate) fn into_static(m: AnyMessage<'_>) -> AnyMessage<'static> {
         match m {
             AnyMessage::Mining(m) => AnyMessage::Mining(m.into_static()),
             AnyMessage::Common(m) => match m {
@@ -489,10 +516,14 @@ impl Sniffer {
                     TemplateDistribution::SubmitSolution(m.into_static()),
                 ),
             },
+            AnyMessage::ExtensionsNegotiation(m) => {
+                AnyMessage::ExtensionsNegotiation(m.into_static())
+            }
+            AnyMessage::Extension(m) => AnyMessage::Extension(m),
         }
     }
 
-    async fn wait_for_client(listen_socket: SocketAddr) -> TcpStream {
+    pub(crate) async fn wait_for_client(listen_socket: SocketAddr) -> TcpStream {
         let listener = TcpListener::bind(listen_socket)
             .await
             .expect("Impossible to listen on given address");
@@ -8,7 +8,10 @@ use tokio::{
     sync::Mutex,
 };
 
-use crate::MessageDirection;
+use crate::{
+    capture::{Capture, CapturedFrame},
+    MessageDirection,
+};
 
 #[derive(Debug, PartialEq)]
 enum SnifferError {
@@ -21,12 +24,16 @@ enum SnifferError {
 /// This struct acts as a middleman between two SV1 roles. It forwards messages from one role to
 /// the other and vice versa. It also provides methods to wait for specific messages to be received
 /// from the downstream or upstream role.
+///
+/// Every message is also recorded into a [`Capture`] that can be saved with
+/// [`SnifferSV1::save_capture`].
 #[derive(Debug, Clone)]
 pub struct SnifferSV1 {
     listening_address: SocketAddr,
     upstream_address: SocketAddr,
     messages_from_downstream: MessagesAggregatorSV1,
     messages_from_upstream: MessagesAggregatorSV1,
+    capture: Capture,
 }
 
 impl SnifferSV1 {
@@ -41,6 +48,7 @@ impl SnifferSV1 {
             upstream_address,
             messages_from_downstream: MessagesAggregatorSV1::new(),
             messages_from_upstream: MessagesAggregatorSV1::new(),
+            capture: Capture::new("sv1".to_string()),
         }
     }
 
@@ -50,6 +58,7 @@ impl SnifferSV1 {
         let listening_address = self.listening_address.clone();
         let messages_from_downstream = self.messages_from_downstream.clone();
         let messages_from_upstream = self.messages_from_upstream.clone();
+        let capture = self.capture.clone();
         tokio::spawn(async move {
             let listener = TcpListener::bind(listening_address)
                 .await
@@ -74,12 +83,14 @@ impl SnifferSV1 {
                 _ = Self::recv_from_down_send_to_up_sv1(
                     downstream_to_sniffer_connection.receiver(),
                     sniffer_to_upstream_connection.sender(),
-                    messages_from_downstream
+                    messages_from_downstream,
+                    capture.clone()
                 ) => { },
                 _ = Self::recv_from_up_send_to_down_sv1(
                     sniffer_to_upstream_connection.receiver(),
                     downstream_to_sniffer_connection.sender(),
-                    messages_from_upstream
+                    messages_from_upstream,
+                    capture
                 ) => { },
             };
         });
@@ -118,12 +129,31 @@ impl SnifferSV1 {
         );
     }
 
+    /// Returns the recorder holding every message seen by the sniffer so far.
+    pub fn capture(&self) -> &Capture {
+        &self.capture
+    }
+
+    /// Saves every message seen by the sniffer so far into a capture file at `path`.
+    pub fn save_capture<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        self.capture.save(path)
+    }
+
+    fn record(capture: &Capture, direction: MessageDirection, msg: &sv1_api::Message) {
+        match corepc_node::serde_json::to_string(msg) {
+            Ok(json) => capture.record(direction, CapturedFrame::Sv1(json)),
+            Err(e) => tracing::warn!("🔍 Sv1 Sniffer | Failed to capture {}: {}", msg, e),
+        }
+    }
+
     async fn recv_from_up_send_to_down_sv1(
         recv: Receiver<sv1_api::Message>,
         send: Sender<sv1_api::Message>,
         upstream_messages: MessagesAggregatorSV1,
+        capture: Capture,
     ) -> Result<(), SnifferError> {
         while let Ok(msg) = recv.recv().await {
+            Self::record(&capture, MessageDirection::ToDownstream, &msg);
             send.send(msg.clone())
                 .await
                 .map_err(|_| SnifferError::DownstreamClosed)?;
@@ -137,8 +167,10 @@ impl SnifferSV1 {
         recv: Receiver<sv1_api::Message>,
         send: Sender<sv1_api::Message>,
         downstream_messages: MessagesAggregatorSV1,
+        capture: Capture,
     ) -> Result<(), SnifferError> {
         while let Ok(msg) = recv.recv().await {
+            Self::record(&capture, MessageDirection::ToUpstream, &msg);
             send.send(msg.clone())
                 .await
                 .map_err(|_| SnifferError::UpstreamClosed)?;
@@ -0,0 +1,102 @@
+// This file runs the sv2-conformance cases against the roles of this repository.
+use integration_tests_sv2::*;
+use key_utils::Secp256k1PublicKey;
+use std::{convert::TryInto, net::SocketAddr, path::Path, str::FromStr};
+use sv2_conformance::{run_script, script::find_scripts, Failure, Script, Target};
+use tokio::net::TcpListener;
+
+const AUTHORITY_PUBLIC_KEY: &str = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72";
+const AUTHORITY_SECRET_KEY: &str = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n";
+
+fn target(address: SocketAddr) -> Target {
+    Target {
+        address: address.to_string(),
+        authority_pubkey: Some(Secp256k1PublicKey::from_str(AUTHORITY_PUBLIC_KEY).unwrap()),
+        authority_secret_key: None,
+    }
+}
+
+fn cases(role: &str) -> Vec<Script> {
+    let cases = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../roles/test-utils/sv2-conformance/cases")
+        .join(role);
+    let scripts: Vec<Script> = find_scripts(&cases)
+        .unwrap()
+        .iter()
+        .map(|path| Script::load(path).unwrap())
+        .collect();
+    assert!(!scripts.is_empty(), "no cases for {}", role);
+    scripts
+}
+
+async fn run_cases(role: &str, target: &Target) {
+    for script in cases(role) {
+        if let Err(Failure { step, reason }) = run_script(&script, target).await {
+            panic!("{}: step {}: {}", script.name, step, reason);
+        }
+    }
+}
+
+// Runs the pool cases against the pool.
+//
+// **Flow:**
+// `sv2-conformance -> Pool -> TP`
+#[tokio::test]
+async fn pool_conformance() {
+    start_tracing();
+    let (_tp, tp_addr) = start_template_provider(None);
+    let (_pool, pool_addr) = start_pool(Some(tp_addr)).await;
+    run_cases("pool", &target(pool_addr)).await;
+}
+
+// Runs the mining proxy cases against a mining proxy connected to the pool.
+//
+// **Flow:**
+// `sv2-conformance -> Mining Proxy -> Pool -> TP`
+#[tokio::test]
+async fn mining_proxy_conformance() {
+    start_tracing();
+    let (_tp, tp_addr) = start_template_provider(None);
+    let (_pool, pool_addr) = start_pool(Some(tp_addr)).await;
+    let (_mining_proxy, mining_proxy_addr) = start_mining_sv2_proxy(&[pool_addr]).await;
+    run_cases("mining-proxy", &target(mining_proxy_addr)).await;
+}
+
+// Runs the JDC cases against a JDC declaring its jobs to the JDS.
+//
+// **Flow:**
+// `sv2-conformance -> JDC -> Pool/JDS -> TP`
+#[tokio::test]
+async fn jd_client_conformance() {
+    start_tracing();
+    let (tp, tp_addr) = start_template_provider(None);
+    let (_pool, pool_addr) = start_pool(Some(tp_addr)).await;
+    let (_jds, jds_addr) = start_jds(tp.rpc_info());
+    let (_jdc, jdc_addr) = start_jdc(&[(pool_addr, jds_addr)], tp_addr);
+    run_cases("jd-client", &target(jdc_addr)).await;
+}
+
+// Runs the translator cases, the conformance runner plays the pool the translator connects to.
+//
+// **Flow:**
+// `Translator -> sv2-conformance`
+#[tokio::test]
+async fn translator_conformance() {
+    start_tracing();
+    let address = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let target = Target {
+        authority_secret_key: Some(AUTHORITY_SECRET_KEY.parse().unwrap()),
+        ..target(address)
+    };
+    // Every case runs on its own connection, the translator only connects once
+    let [script]: [Script; 1] = cases("translator").try_into().unwrap();
+    let runner = tokio::spawn(async move { run_script(&script, &target).await });
+    let (_translator, _) = start_sv2_translator(address);
+    if let Err(Failure { step, reason }) = runner.await.unwrap() {
+        panic!("step {}: {}", step, reason);
+    }
+}
@@ -0,0 +1,76 @@
+use const_sv2::{
+    MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH, MESSAGE_TYPE_NEW_MINING_JOB,
+    MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL, MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS,
+    MESSAGE_TYPE_SETUP_CONNECTION, MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS,
+    MESSAGE_TYPE_SUBMIT_SHARES_STANDARD, MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS,
+};
+use integration_tests_sv2::*;
+use sniffer::MessageDirection;
+use std::time::Duration;
+
+// This test starts a SV1 pool, a Reverse Translator, a Sniffer and a SV2 Mining Device. It checks
+// the messages exchanged between the Mining Device and the Reverse Translator, then that the SV1
+// pool accepts a share found by the Mining Device.
+#[tokio::test]
+async fn reverse_translator_submits_shares_to_sv1_pool() {
+    start_tracing();
+    let (_sv1_pool, events, sv1_pool_addr) = start_sv1_pool().await;
+    let (_reverse_translator, reverse_translator_addr) = start_reverse_translator(sv1_pool_addr);
+    let (sniffer, sniffer_addr) =
+        start_sniffer("A".to_string(), reverse_translator_addr, false, None);
+    let _sv2_mining_device =
+        start_mining_device_sv2(sniffer_addr, None, None, None, 1, None, false);
+    sniffer
+        .wait_for_message_type(MessageDirection::ToUpstream, MESSAGE_TYPE_SETUP_CONNECTION)
+        .await;
+    sniffer
+        .wait_for_message_type(
+            MessageDirection::ToDownstream,
+            MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS,
+        )
+        .await;
+    sniffer
+        .wait_for_message_type(
+            MessageDirection::ToUpstream,
+            MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL,
+        )
+        .await;
+    sniffer
+        .wait_for_message_type(
+            MessageDirection::ToDownstream,
+            MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS,
+        )
+        .await;
+    sniffer
+        .wait_for_message_type(MessageDirection::ToDownstream, MESSAGE_TYPE_NEW_MINING_JOB)
+        .await;
+    sniffer
+        .wait_for_message_type(
+            MessageDirection::ToDownstream,
+            MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH,
+        )
+        .await;
+    sniffer
+        .wait_for_message_type(
+            MessageDirection::ToUpstream,
+            MESSAGE_TYPE_SUBMIT_SHARES_STANDARD,
+        )
+        .await;
+    sniffer
+        .wait_for_message_type(
+            MessageDirection::ToDownstream,
+            MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS,
+        )
+        .await;
+
+    let share = tokio::time::timeout(Duration::from_secs(60), async {
+        loop {
+            if let sv1_server::Event::Share(share) = events.recv().await.unwrap() {
+                break share;
+            }
+        }
+    })
+    .await
+    .expect("no share accepted by the SV1 pool");
+    assert_eq!(share.user_name, "username.worker");
+}
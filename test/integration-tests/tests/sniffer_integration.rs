@@ -3,7 +3,11 @@ use const_sv2::{
     MESSAGE_TYPE_SETUP_CONNECTION, MESSAGE_TYPE_SETUP_CONNECTION_ERROR,
     MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS, MESSAGE_TYPE_SET_NEW_PREV_HASH,
 };
-use integration_tests_sv2::{sniffer::IgnoreMessage, *};
+use integration_tests_sv2::{
+    capture::{Capture, CapturedFrame, Replayer},
+    sniffer::IgnoreMessage,
+    *,
+};
 use roles_logic_sv2::{
     common_messages_sv2::{Protocol, SetupConnection, SetupConnectionError},
     parsers::{AnyMessage, CommonMessages},
@@ -170,3 +174,64 @@ async fn test_sniffer_blocks_message() {
     // Assert that `sniffer_c` does not receive any messages, confirming `sniffer_b`'s block works.
     assert!(sniffer_c.next_message_from_upstream().is_none());
 }
+
+// Verifies that a capture recorded by [`Sniffer`] can be saved, loaded back and replayed against
+// a fresh Template Provider, which is expected to answer exactly as recorded.
+//
+// **Flow:**
+// `TP -> sniffer -> Pool`, then `capture file -> Replayer -> TP`
+#[tokio::test]
+async fn test_sniffer_record_and_replay() {
+    start_tracing();
+    let (_tp, tp_addr) = start_template_provider(None);
+    let (sniffer, sniffer_addr) = start_sniffer("".to_string(), tp_addr, false, None);
+    let _ = start_pool(Some(sniffer_addr)).await;
+    sniffer
+        .wait_for_message_type(
+            MessageDirection::ToDownstream,
+            MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS,
+        )
+        .await;
+    let capture_path = std::env::temp_dir().join("test_sniffer_record_and_replay.sv2");
+    sniffer.save_capture(&capture_path).unwrap();
+    // Only keep the connection setup, templates are not deterministic across TP instances.
+    let mut records = Capture::load(&capture_path).unwrap();
+    let setup_connection_success = records
+        .iter()
+        .position(|record| {
+            matches!(
+                record.frame,
+                CapturedFrame::Sv2 {
+                    message_type: MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS,
+                    ..
+                }
+            )
+        })
+        .unwrap();
+    records.truncate(setup_connection_success + 1);
+    let (_tp, tp_addr) = start_template_provider(None);
+    let report = Replayer::new(records).replay_to_upstream(tp_addr).await;
+    assert!(report.mismatches().is_empty(), "{:?}", report.mismatches());
+}
+
+// Replays the golden capture of a Template Distribution connection setup against a Template
+// Provider. Only the kind of the answers is compared, the flags depend on the TP version.
+//
+// **Flow:**
+// `capture file -> Replayer -> TP`
+#[tokio::test]
+async fn test_replay_golden_capture() {
+    start_tracing();
+    let (_tp, tp_addr) = start_template_provider(None);
+    let capture_path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/captures/tp_setup_connection.sv2"
+    );
+    let replayer = Replayer::from_file(capture_path).unwrap();
+    let report = replayer.replay_to_upstream(tp_addr).await;
+    assert!(
+        report.kind_mismatches().is_empty(),
+        "{:?}",
+        report.kind_mismatches()
+    );
+}
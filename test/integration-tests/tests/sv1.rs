@@ -1,5 +1,5 @@
 #![cfg(feature = "sv1")]
-use integration_tests_sv2::*;
+use integration_tests_sv2::{capture::Replayer, *};
 use sniffer::MessageDirection;
 
 #[tokio::test]
@@ -34,3 +34,27 @@ async fn test_basic_sv1() {
         .wait_for_message(&["mining.notify"], MessageDirection::ToDownstream)
         .await;
 }
+
+// Replays the golden capture of a SV1 miner against the translator, which is expected to answer
+// the subscribe and the authorize and then send the difficulty and the first job.
+//
+// **Flow:**
+// `capture file -> Replayer -> Translator -> Pool -> TP`
+#[tokio::test]
+async fn test_replay_sv1_golden_capture() {
+    start_tracing();
+    let (_tp, tp_addr) = start_template_provider(None);
+    let (_pool, pool_addr) = start_pool(Some(tp_addr)).await;
+    let (_, tproxy_addr) = start_sv2_translator(pool_addr);
+    let capture_path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/captures/translator_subscribe_authorize.sv1"
+    );
+    let replayer = Replayer::from_file(capture_path).unwrap();
+    let report = replayer.replay_sv1_to_upstream(tproxy_addr).await;
+    assert!(
+        report.kind_mismatches().is_empty(),
+        "{:?}",
+        report.kind_mismatches()
+    );
+}
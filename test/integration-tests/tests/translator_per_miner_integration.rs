@@ -0,0 +1,77 @@
+// This file contains integration tests for the `per_miner` channel mode of the `TranslatorSv2`.
+use const_sv2::{
+    MESSAGE_TYPE_CLOSE_CHANNEL, MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL,
+    MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL_SUCCES,
+};
+use integration_tests_sv2::{sniffer::*, *};
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+use translator_sv2::proxy_config::UpstreamChannelMode;
+
+// This test runs an sv2 translator in the `per_miner` channel mode between two sv1 miners and a
+// pool. The connection between the translator and the pool is intercepted by a sniffer. The test
+// checks that, besides the channel opened when the translator connects, an extended channel is
+// opened for each authorized miner, and that the channel of a miner is closed when it disconnects.
+#[tokio::test]
+async fn translator_opens_and_closes_a_channel_per_miner() {
+    start_tracing();
+    let (_tp, tp_addr) = start_template_provider(None);
+    let (_pool, pool_addr) = start_pool(Some(tp_addr)).await;
+    let (pool_translator_sniffer, pool_translator_sniffer_addr) =
+        start_sniffer("0".to_string(), pool_addr, false, None);
+    let (_, tproxy_addr) = start_sv2_translator_with_channel_mode(
+        pool_translator_sniffer_addr,
+        UpstreamChannelMode::PerMiner,
+    );
+    pool_translator_sniffer
+        .wait_for_message_type_and_clean_queue(
+            MessageDirection::ToDownstream,
+            MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL_SUCCES,
+        )
+        .await;
+    pool_translator_sniffer
+        .wait_for_message_type_and_clean_queue(
+            MessageDirection::ToUpstream,
+            MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL,
+        )
+        .await;
+
+    let _mining_device = start_mining_device_sv1(tproxy_addr, false, None);
+    pool_translator_sniffer
+        .wait_for_message_type_and_clean_queue(
+            MessageDirection::ToUpstream,
+            MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL,
+        )
+        .await;
+
+    // A bare sv1 miner, so that the test can disconnect it
+    let mut miner = TcpStream::connect(tproxy_addr).await.unwrap();
+    miner
+        .write_all(
+            concat!(
+                r#"{"id":1,"method":"mining.subscribe","params":["miner"]}"#,
+                "\n",
+                r#"{"id":2,"method":"mining.extranonce.subscribe","params":[]}"#,
+                "\n",
+                r#"{"id":3,"method":"mining.authorize","params":["miner.1","x"]}"#,
+                "\n",
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    pool_translator_sniffer
+        .wait_for_message_type_and_clean_queue(
+            MessageDirection::ToUpstream,
+            MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL,
+        )
+        .await;
+    assert!(!pool_translator_sniffer.includes_message_type(
+        MessageDirection::ToUpstream,
+        MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL
+    ));
+
+    drop(miner);
+    pool_translator_sniffer
+        .wait_for_message_type(MessageDirection::ToUpstream, MESSAGE_TYPE_CLOSE_CHANNEL)
+        .await;
+}
@@ -10,9 +10,7 @@ use clap::{App, Arg};
 use codec_sv2::{HandshakeRole, Initiator, Responder, StandardEitherFrame, StandardSv2Frame};
 use std::time::Duration;
 
-use network_helpers::{
-    noise_connection_tokio::Connection, plain_connection_tokio::PlainConnection,
-};
+use network_helpers_sv2::{noise_connection::Connection, plain_connection::PlainConnection};
 
 use key_utils::{Secp256k1PublicKey, Secp256k1SecretKey};
 use roles_logic_sv2::{
@@ -72,7 +70,7 @@ async fn setup_driver(
         let k: Secp256k1PublicKey = AUTHORITY_PUBLIC_K.to_string().try_into().unwrap();
         let initiator = Initiator::from_raw_k(k.into_bytes()).unwrap();
 
-        (_, server_sender, _, _) =
+        (_server_receiver, server_sender) =
             Connection::new(server_stream, HandshakeRole::Initiator(initiator))
                 .await
                 .unwrap();
@@ -137,8 +135,8 @@ async fn handle_messages(
 
         let binary: EitherFrame = frame.into();
 
-        if server.is_some() {
-            server.as_ref().unwrap().send(binary).await.unwrap();
+        if let Some(server) = &server {
+            server.send(binary).await.unwrap();
         } else {
             messages_received += 1;
             //println!("last server: {} got msg {}", name, messages_received);
@@ -175,9 +173,10 @@ async fn create_proxy(
             Duration::from_secs(3600),
         )
         .unwrap();
-        (cli_receiver, _, _, _) = Connection::new(cli_stream, HandshakeRole::Responder(responder))
-            .await
-            .unwrap();
+        (cli_receiver, _cli_sender) =
+            Connection::new(cli_stream, HandshakeRole::Responder(responder))
+                .await
+                .unwrap();
     } else {
         (cli_receiver, _cli_sender) = PlainConnection::new(cli_stream).await;
     }
@@ -193,7 +192,7 @@ async fn create_proxy(
 
         if encrypt {
             let initiator = Initiator::from_raw_k(k_pub.into_bytes()).unwrap();
-            (_, server_sender, _, _) =
+            (_server_receiver, server_sender) =
                 Connection::new(server_stream, HandshakeRole::Initiator(initiator))
                     .await
                     .unwrap();